@@ -0,0 +1,14 @@
+fn main() {
+    // Messages of `proto/ingest.proto` for the `protobuf` feature, and the gRPC
+    // service code for the `grpc` feature.
+    #[cfg(feature = "protobuf")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("vendored protoc");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::configure()
+            .build_server(cfg!(feature = "grpc"))
+            .build_client(cfg!(feature = "grpc"))
+            .compile_protos(&["proto/ingest.proto"], &["proto"])
+            .expect("failed to compile proto/ingest.proto");
+    }
+}
@@ -0,0 +1,291 @@
+//! Admin API for managing the persistent API keys ([`crate::api_key_store`]).
+//!
+//! Serves, on its own listener (`[admin_api]`):
+//! - `GET /v1/api_keys`: every key, without tokens
+//! - `POST /v1/api_keys`: create a key (`name`, `roles`, optional `tenant`, `expires_at`)
+//! - `POST /v1/api_keys/{id}/rotate`: new token; the old one works for `grace_secs`
+//! - `DELETE /v1/api_keys/{id}`: revoke a key, effective immediately
+//!
+//! Every request needs `Authorization: Bearer <token>`: the `auth_bearer_token`
+//! or an API key with the `admin` role. Tokens are returned once, by create and
+//! rotate. Each change is recorded in the store's audit log under the name of
+//! the key that made it.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+    body::Bytes,
+    extract::{rejection::JsonRejection, Path, State},
+    http::{HeaderMap, StatusCode},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    api_key_store::{ApiKeyStore, NewKey, StoreError, StoredKey},
+    auth::{authorize_key, bearer_token},
+    config::{AdminApiConfig, Role},
+    sources::http_error::{parse_record, parse_ts_field, ApiError, FieldError},
+};
+
+#[derive(Clone)]
+struct ApiState {
+    store: Arc<ApiKeyStore>,
+    auth_bearer_token: Option<String>,
+    rotation_grace: Duration,
+}
+
+#[derive(Deserialize)]
+struct CreateRequest {
+    name: String,
+    roles: Vec<Role>,
+    #[serde(default)]
+    tenant: Option<String>,
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+struct RotateRequest {
+    #[serde(default)]
+    grace_secs: Option<u64>,
+    #[serde(default)]
+    expires_at: Option<String>,
+}
+
+#[derive(Serialize)]
+struct KeyView {
+    id: String,
+    name: String,
+    roles: Vec<Role>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tenant: Option<String>,
+    /// `active`, `expired` or `revoked`.
+    status: &'static str,
+    #[serde(with = "time::serde::rfc3339")]
+    created_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    rotated_at: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    revoked_at: Option<OffsetDateTime>,
+    /// Only on create and rotate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    token: Option<String>,
+}
+
+impl KeyView {
+    fn new(key: StoredKey, token: Option<String>) -> Self {
+        let status = match key.revoked_at {
+            Some(_) => "revoked",
+            None if !key.is_active(OffsetDateTime::now_utc()) => "expired",
+            None => "active",
+        };
+        Self {
+            id: key.id,
+            name: key.name,
+            roles: key.roles,
+            tenant: key.tenant,
+            status,
+            created_at: key.created_at,
+            expires_at: key.expires_at,
+            rotated_at: key.rotated_at,
+            revoked_at: key.revoked_at,
+            token,
+        }
+    }
+}
+
+impl From<StoreError> for ApiError {
+    fn from(e: StoreError) -> Self {
+        match e {
+            StoreError::Invalid(_) => ApiError::new(StatusCode::BAD_REQUEST, e.to_string()),
+            StoreError::NotFound(_) => ApiError::new(StatusCode::NOT_FOUND, e.to_string()),
+            StoreError::Revoked(_) => ApiError::new(StatusCode::CONFLICT, e.to_string()),
+            StoreError::Io(_) => {
+                metrics::counter!("admin_api_store_errors_total").increment(1);
+                tracing::error!(error = %e, "api key store update failed");
+                ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "key store update failed")
+            }
+        }
+    }
+}
+
+/// Authorize an admin request; returns who made it, for the audit log.
+fn admin(state: &ApiState, headers: &HeaderMap, endpoint: &'static str) -> Result<String, ApiError> {
+    metrics::counter!("admin_api_requests_total", "endpoint" => endpoint).increment(1);
+    let key = authorize_key(
+        bearer_token(headers),
+        &state.auth_bearer_token,
+        Role::Admin,
+        "admin_api_unauthorized_total",
+    )?;
+    Ok(key.map_or_else(|| "auth_bearer_token".to_string(), |k| k.name.clone()))
+}
+
+fn grace_secs(secs: u64) -> Duration {
+    Duration::seconds(i64::try_from(secs).unwrap_or(i64::MAX))
+}
+
+fn parse_expiry(value: &Option<String>) -> Result<Option<OffsetDateTime>, ApiError> {
+    value
+        .as_deref()
+        .map(|v| parse_ts_field("expires_at", v))
+        .transpose()
+        .map_err(|e| ApiError::invalid(vec![e]))
+}
+
+async fn list(State(state): State<ApiState>, headers: HeaderMap) -> Result<Json<Vec<KeyView>>, ApiError> {
+    admin(&state, &headers, "list")?;
+    let keys = state.store.list().await;
+    Ok(Json(keys.into_iter().map(|k| KeyView::new(k, None)).collect()))
+}
+
+async fn create(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    payload: Result<Json<CreateRequest>, JsonRejection>,
+) -> Result<(StatusCode, Json<KeyView>), ApiError> {
+    let actor = admin(&state, &headers, "create")?;
+    let Json(req) = payload?;
+    let new = NewKey {
+        expires_at: parse_expiry(&req.expires_at)?,
+        name: req.name,
+        roles: req.roles,
+        tenant: req.tenant,
+    };
+    let (key, token) = state.store.create(new, &actor).await?;
+    Ok((StatusCode::CREATED, Json(KeyView::new(key, Some(token)))))
+}
+
+/// The body is optional: without one the configured grace applies.
+async fn rotate(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    body: Bytes,
+) -> Result<Json<KeyView>, ApiError> {
+    let actor = admin(&state, &headers, "rotate")?;
+    let req: RotateRequest = match std::str::from_utf8(&body).map(str::trim) {
+        Ok("") => RotateRequest::default(),
+        Ok(json) => parse_record(json).map_err(|e| ApiError::invalid(vec![e]))?,
+        Err(_) => return Err(ApiError::invalid(vec![FieldError::record("body is not UTF-8")])),
+    };
+    let grace = req.grace_secs.map_or(state.rotation_grace, grace_secs);
+    let (key, token) = state
+        .store
+        .rotate(&id, grace, parse_expiry(&req.expires_at)?, &actor)
+        .await?;
+    Ok(Json(KeyView::new(key, Some(token))))
+}
+
+async fn revoke(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<KeyView>, ApiError> {
+    let actor = admin(&state, &headers, "revoke")?;
+    let key = state.store.revoke(&id, &actor).await?;
+    Ok(Json(KeyView::new(key, None)))
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/v1/api_keys", get(list).post(create))
+        .route("/v1/api_keys/:id", axum::routing::delete(revoke))
+        .route("/v1/api_keys/:id/rotate", post(rotate))
+        .with_state(state)
+}
+
+/// Bind `cfg.bind_addr` and serve the API in the background; returns the bound address.
+pub async fn serve(cfg: &AdminApiConfig, store: Arc<ApiKeyStore>) -> anyhow::Result<SocketAddr> {
+    let state = ApiState {
+        store,
+        auth_bearer_token: cfg.auth_bearer_token.clone(),
+        rotation_grace: grace_secs(cfg.rotation_grace_secs),
+    };
+
+    // Fail-fast: if we can't bind, return an error to the caller.
+    let listener = tokio::net::TcpListener::bind(&cfg.bind_addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to bind admin API on {}: {e}", cfg.bind_addr))?;
+    let addr = listener.local_addr()?;
+    let app = router(state);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app.into_make_service()).await {
+            tracing::error!(error = %e, "admin API server error");
+        }
+    });
+    tracing::info!(%addr, "admin API listening");
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ApiKeyStoreConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    async fn request(addr: SocketAddr, method: &str, path: &str, auth: &str, body: &str) -> String {
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let request = format!(
+            "{method} {path} HTTP/1.1\r\nHost: test\r\n{auth}Content-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn creates_and_revokes_keys_with_the_admin_token() {
+        let dir = std::env::temp_dir().join(format!("admin-api-{}", uuid::Uuid::now_v7()));
+        tokio::fs::create_dir_all(&dir).await.unwrap();
+        let store_cfg = ApiKeyStoreConfig {
+            path: dir.join("keys.json").display().to_string(),
+            audit_log_path: None,
+        };
+        // Not installed: the test must not change what other tests' endpoints accept.
+        let store = ApiKeyStore::load(&store_cfg, &[], |_| {}).await.unwrap();
+        let cfg: AdminApiConfig = toml::from_str("bind_addr = \"127.0.0.1:0\"\nauth_bearer_token = \"root\"").unwrap();
+        let addr = serve(&cfg, Arc::new(store)).await.unwrap();
+        let root = "Authorization: Bearer root\r\n";
+
+        let response = request(addr, "GET", "/v1/api_keys", "", "").await;
+        assert!(response.starts_with("HTTP/1.1 401"), "{response}");
+
+        let body = r#"{"name":"grafana","roles":["read"],"expires_at":"2999-01-01T00:00:00Z"}"#;
+        let response = request(addr, "POST", "/v1/api_keys", root, body).await;
+        assert!(response.starts_with("HTTP/1.1 201"), "{response}");
+        assert!(response.contains(r#""token":"uak_"#), "{response}");
+        let created: serde_json::Value = serde_json::from_str(response.split("\r\n\r\n").nth(1).unwrap()).unwrap();
+        let id = created["id"].as_str().unwrap();
+
+        let body = r#"{"name":"vendor","roles":["read"],"tenant":"vendor-a"}"#;
+        let response = request(addr, "POST", "/v1/api_keys", root, body).await;
+        assert!(response.starts_with("HTTP/1.1 400"), "{response}");
+
+        let response = request(addr, "DELETE", &format!("/v1/api_keys/{id}"), root, "").await;
+        assert!(response.contains(r#""status":"revoked""#), "{response}");
+        let response = request(addr, "POST", &format!("/v1/api_keys/{id}/rotate"), root, "").await;
+        assert!(response.starts_with("HTTP/1.1 409"), "{response}");
+
+        let response = request(addr, "GET", "/v1/api_keys", root, "").await;
+        assert!(
+            response.contains(r#""name":"grafana""#) && !response.contains("token"),
+            "{response}"
+        );
+
+        let audit = tokio::fs::read_to_string(store_cfg.audit_log_path()).await.unwrap();
+        assert!(
+            audit.lines().all(|l| l.contains(r#""actor":"auth_bearer_token""#)),
+            "{audit}"
+        );
+
+        tokio::fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
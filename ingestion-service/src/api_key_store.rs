@@ -0,0 +1,417 @@
+//! Persistent API keys, created, rotated and revoked through the admin API
+//! instead of redeploying `[[api_keys]]`.
+//!
+//! Keys live in a JSON file (`[api_key_store] path`) holding only token
+//! hashes. Every change replaces the file atomically, re-installs the
+//! credentials the endpoints check ([`auth::install`]) and appends an entry to
+//! an NDJSON audit log. A token is shown once, when its key is created or
+//! rotated. Revoked keys stay in the file for the record.
+
+use std::{io, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use time::{Duration, OffsetDateTime};
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use uuid::Uuid;
+
+use crate::{
+    auth::{self, check_roles, token_hash, ApiKey, ApiKeys},
+    config::{ApiKeyConfig, ApiKeyStoreConfig, Role},
+};
+
+/// A token replaced by a rotation, still accepted until `expires_at`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct PreviousToken {
+    hash: String,
+    #[serde(with = "time::serde::rfc3339")]
+    expires_at: OffsetDateTime,
+}
+
+/// A key as persisted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoredKey {
+    pub id: String,
+    pub name: String,
+    pub roles: Vec<Role>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub rotated_at: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub revoked_at: Option<OffsetDateTime>,
+    token_hash: String,
+    #[serde(default)]
+    previous_token: Option<PreviousToken>,
+}
+
+impl StoredKey {
+    /// Whether the key still grants anything at `now`.
+    pub fn is_active(&self, now: OffsetDateTime) -> bool {
+        self.revoked_at.is_none() && self.expires_at.is_none_or(|at| now < at)
+    }
+
+    fn api_key(&self, expires_at: Option<OffsetDateTime>) -> Arc<ApiKey> {
+        Arc::new(ApiKey::new(
+            self.name.clone(),
+            self.roles.clone(),
+            self.tenant.clone(),
+            expires_at,
+        ))
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct KeyFile {
+    keys: Vec<StoredKey>,
+}
+
+/// A key to create.
+#[derive(Debug, Clone)]
+pub struct NewKey {
+    pub name: String,
+    pub roles: Vec<Role>,
+    pub tenant: Option<String>,
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StoreError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("unknown key '{0}'")]
+    NotFound(String),
+    #[error("key '{0}' is revoked")]
+    Revoked(String),
+    #[error("key store: {0}")]
+    Io(#[from] io::Error),
+}
+
+#[derive(Serialize)]
+struct AuditEntry<'a> {
+    #[serde(with = "time::serde::rfc3339")]
+    at: OffsetDateTime,
+    /// Name of the admin key, or `auth_bearer_token`.
+    actor: &'a str,
+    action: &'a str,
+    key_id: &'a str,
+    key_name: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none", with = "time::serde::rfc3339::option")]
+    expires_at: Option<OffsetDateTime>,
+}
+
+/// New, unguessable token.
+fn new_token() -> String {
+    format!("uak_{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple())
+}
+
+pub struct ApiKeyStore {
+    path: PathBuf,
+    audit_path: PathBuf,
+    static_keys: Vec<ApiKeyConfig>,
+    keys: Mutex<Vec<StoredKey>>,
+    /// Where the live key set goes; [`auth::install`] outside tests.
+    publish: fn(ApiKeys),
+}
+
+impl ApiKeyStore {
+    /// Load the store (empty if the file doesn't exist yet) and install its
+    /// keys next to `static_keys`.
+    pub async fn open(cfg: &ApiKeyStoreConfig, static_keys: &[ApiKeyConfig]) -> anyhow::Result<Self> {
+        Self::load(cfg, static_keys, auth::install).await
+    }
+
+    pub(crate) async fn load(
+        cfg: &ApiKeyStoreConfig,
+        static_keys: &[ApiKeyConfig],
+        publish: fn(ApiKeys),
+    ) -> anyhow::Result<Self> {
+        let path = PathBuf::from(&cfg.path);
+        let file: KeyFile = match fs::read(&path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map_err(|e| anyhow::anyhow!("invalid api key store {}: {e}", path.display()))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => KeyFile::default(),
+            Err(e) => return Err(anyhow::anyhow!("failed to read api key store {}: {e}", path.display())),
+        };
+        let store = Self {
+            path,
+            audit_path: PathBuf::from(cfg.audit_log_path()),
+            static_keys: static_keys.to_vec(),
+            keys: Mutex::new(Vec::new()),
+            publish,
+        };
+        (store.publish)(store.credentials(&file.keys)?);
+        tracing::info!(keys = file.keys.len(), path = %store.path.display(), "api key store loaded");
+        *store.keys.lock().await = file.keys;
+        Ok(store)
+    }
+
+    pub async fn list(&self) -> Vec<StoredKey> {
+        self.keys.lock().await.clone()
+    }
+
+    /// Create a key; returns it with its token.
+    pub async fn create(&self, new: NewKey, actor: &str) -> Result<(StoredKey, String), StoreError> {
+        check_roles(&new.roles, new.tenant.as_deref()).map_err(|e| StoreError::Invalid(e.to_string()))?;
+        if new.name.is_empty() {
+            return Err(StoreError::Invalid("name may not be empty".to_string()));
+        }
+        let now = OffsetDateTime::now_utc();
+        if new.expires_at.is_some_and(|at| at <= now) {
+            return Err(StoreError::Invalid("expires_at must be in the future".to_string()));
+        }
+
+        let mut keys = self.keys.lock().await;
+        let taken = keys.iter().any(|k| k.is_active(now) && k.name == new.name)
+            || self.static_keys.iter().any(|k| k.name == new.name);
+        if taken {
+            return Err(StoreError::Invalid(format!(
+                "a key named '{}' already exists",
+                new.name
+            )));
+        }
+
+        let token = new_token();
+        let key = StoredKey {
+            id: Uuid::now_v7().to_string(),
+            name: new.name,
+            roles: new.roles,
+            tenant: new.tenant,
+            created_at: now,
+            expires_at: new.expires_at,
+            rotated_at: None,
+            revoked_at: None,
+            token_hash: token_hash(&token),
+            previous_token: None,
+        };
+        let mut updated = keys.clone();
+        updated.push(key.clone());
+        self.commit(&mut keys, updated).await?;
+        self.audit(actor, "create", &key).await;
+        Ok((key, token))
+    }
+
+    /// Give a key a new token. The old one keeps working for `grace`; a new
+    /// `expires_at` replaces the key's expiry when given.
+    pub async fn rotate(
+        &self,
+        id: &str,
+        grace: Duration,
+        expires_at: Option<OffsetDateTime>,
+        actor: &str,
+    ) -> Result<(StoredKey, String), StoreError> {
+        let now = OffsetDateTime::now_utc();
+        if expires_at.is_some_and(|at| at <= now) {
+            return Err(StoreError::Invalid("expires_at must be in the future".to_string()));
+        }
+
+        let mut keys = self.keys.lock().await;
+        let mut updated = keys.clone();
+        let key = find(&mut updated, id)?;
+        let token = new_token();
+        let old_hash = std::mem::replace(&mut key.token_hash, token_hash(&token));
+        let old_expiry = key.expires_at;
+        key.previous_token = (grace > Duration::ZERO).then(|| PreviousToken {
+            hash: old_hash,
+            expires_at: old_expiry.map_or(now + grace, |at| at.min(now + grace)),
+        });
+        key.rotated_at = Some(now);
+        if expires_at.is_some() {
+            key.expires_at = expires_at;
+        }
+        let key = key.clone();
+        self.commit(&mut keys, updated).await?;
+        self.audit(actor, "rotate", &key).await;
+        Ok((key, token))
+    }
+
+    /// Revoke a key and any token it was rotated from, effective immediately.
+    pub async fn revoke(&self, id: &str, actor: &str) -> Result<StoredKey, StoreError> {
+        let mut keys = self.keys.lock().await;
+        let mut updated = keys.clone();
+        let key = find(&mut updated, id)?;
+        key.revoked_at = Some(OffsetDateTime::now_utc());
+        key.previous_token = None;
+        let key = key.clone();
+        self.commit(&mut keys, updated).await?;
+        self.audit(actor, "revoke", &key).await;
+        Ok(key)
+    }
+
+    /// Credentials of the static keys plus the stored keys not revoked. Endpoints
+    /// stay closed even when none is left.
+    fn credentials(&self, keys: &[StoredKey]) -> anyhow::Result<ApiKeys> {
+        let now = OffsetDateTime::now_utc();
+        let mut credentials = ApiKeys::from_config(&self.static_keys)?.closed();
+        for key in keys.iter().filter(|k| k.revoked_at.is_none()) {
+            credentials.insert(key.token_hash.clone(), key.api_key(key.expires_at))?;
+            if let Some(previous) = key.previous_token.as_ref().filter(|p| now < p.expires_at) {
+                credentials.insert(previous.hash.clone(), key.api_key(Some(previous.expires_at)))?;
+            }
+        }
+        Ok(credentials)
+    }
+
+    /// Persist `updated`, then make it the live key set.
+    async fn commit(&self, keys: &mut Vec<StoredKey>, updated: Vec<StoredKey>) -> Result<(), StoreError> {
+        let credentials = self
+            .credentials(&updated)
+            .map_err(|e| StoreError::Invalid(e.to_string()))?;
+        let file = KeyFile { keys: updated };
+
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        let mut out = fs::File::create(&tmp).await?;
+        out.write_all(&serde_json::to_vec_pretty(&file).map_err(io::Error::other)?)
+            .await?;
+        out.sync_all().await?;
+        drop(out);
+        fs::rename(&tmp, &self.path).await?;
+
+        (self.publish)(credentials);
+        *keys = file.keys;
+        Ok(())
+    }
+
+    /// Append an audit entry. The change already took effect, so a failure is
+    /// logged rather than returned.
+    async fn audit(&self, actor: &str, action: &str, key: &StoredKey) {
+        let entry = AuditEntry {
+            at: OffsetDateTime::now_utc(),
+            actor,
+            action,
+            key_id: &key.id,
+            key_name: &key.name,
+            expires_at: key.expires_at,
+        };
+        tracing::info!(actor, action, key_id = %key.id, key_name = %key.name, "api key changed");
+
+        let mut line = serde_json::to_vec(&entry).expect("audit entry serializes");
+        line.push(b'\n');
+        let appended = async {
+            let mut file = fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&self.audit_path)
+                .await?;
+            file.write_all(&line).await?;
+            file.sync_data().await
+        };
+        if let Err(e) = appended.await {
+            metrics::counter!("api_key_audit_errors_total").increment(1);
+            tracing::error!(error = %e, path = %self.audit_path.display(), "failed to append api key audit entry");
+        }
+    }
+}
+
+fn find<'a>(keys: &'a mut [StoredKey], id: &str) -> Result<&'a mut StoredKey, StoreError> {
+    let key = keys
+        .iter_mut()
+        .find(|k| k.id == id)
+        .ok_or_else(|| StoreError::NotFound(id.to_string()))?;
+    if key.revoked_at.is_some() {
+        return Err(StoreError::Revoked(id.to_string()));
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn config(dir: &std::path::Path) -> ApiKeyStoreConfig {
+        ApiKeyStoreConfig {
+            path: dir.join("keys.json").display().to_string(),
+            audit_log_path: None,
+        }
+    }
+
+    fn new_key(name: &str, roles: Vec<Role>) -> NewKey {
+        NewKey {
+            name: name.to_string(),
+            roles,
+            tenant: None,
+            expires_at: None,
+        }
+    }
+
+    /// Check `token` against the store's current credentials.
+    async fn check(store: &ApiKeyStore, token: &str) -> Result<Option<Arc<ApiKey>>, StatusCode> {
+        let keys = store.keys.lock().await;
+        store.credentials(&keys).unwrap().check(Some(token), None, Role::Read)
+    }
+
+    #[tokio::test]
+    async fn keys_survive_restarts_and_rotate_with_grace() {
+        let dir = std::env::temp_dir().join(format!("api-key-store-{}", Uuid::now_v7()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let cfg = config(&dir);
+
+        let store = ApiKeyStore::load(&cfg, &[], |_| {}).await.unwrap();
+        let (key, token) = store.create(new_key("grafana", vec![Role::Read]), "ops").await.unwrap();
+        assert!(check(&store, &token).await.unwrap().is_some());
+        assert!(store.create(new_key("grafana", vec![Role::Read]), "ops").await.is_err());
+
+        // Rotated: both tokens work during the grace period, only the new one without.
+        let (_, rotated) = store.rotate(&key.id, Duration::hours(1), None, "ops").await.unwrap();
+        assert!(check(&store, &token).await.unwrap().is_some());
+        let (_, rotated_again) = store.rotate(&key.id, Duration::ZERO, None, "ops").await.unwrap();
+        assert_eq!(check(&store, &rotated).await, Err(StatusCode::UNAUTHORIZED));
+
+        // Only hashes are written, and a reopened store knows the key.
+        let saved = fs::read_to_string(&cfg.path).await.unwrap();
+        assert!(!saved.contains(&rotated_again));
+        let reopened = ApiKeyStore::load(&cfg, &[], |_| {}).await.unwrap();
+        assert!(check(&reopened, &rotated_again).await.unwrap().is_some());
+
+        reopened.revoke(&key.id, "ops").await.unwrap();
+        assert_eq!(check(&reopened, &rotated_again).await, Err(StatusCode::UNAUTHORIZED));
+        assert!(matches!(
+            reopened.rotate(&key.id, Duration::ZERO, None, "ops").await,
+            Err(StoreError::Revoked(_))
+        ));
+
+        let audit = fs::read_to_string(cfg.audit_log_path()).await.unwrap();
+        let actions: Vec<String> = audit
+            .lines()
+            .map(|l| serde_json::from_str::<serde_json::Value>(l).unwrap()["action"].to_string())
+            .collect();
+        assert_eq!(actions, ["\"create\"", "\"rotate\"", "\"rotate\"", "\"revoke\""]);
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_keys() {
+        let dir = std::env::temp_dir().join(format!("api-key-store-{}", Uuid::now_v7()));
+        fs::create_dir_all(&dir).await.unwrap();
+        let store = ApiKeyStore::load(&config(&dir), &[], |_| {}).await.unwrap();
+
+        let mut tenant_admin = new_key("vendor-a", vec![Role::Admin]);
+        tenant_admin.tenant = Some("vendor-a".to_string());
+        assert!(matches!(
+            store.create(tenant_admin, "ops").await,
+            Err(StoreError::Invalid(_))
+        ));
+
+        let mut expired = new_key("old", vec![Role::Read]);
+        expired.expires_at = Some(OffsetDateTime::now_utc() - Duration::hours(1));
+        assert!(matches!(
+            store.create(expired, "ops").await,
+            Err(StoreError::Invalid(_))
+        ));
+
+        assert!(matches!(
+            store.revoke("missing", "ops").await,
+            Err(StoreError::NotFound(_))
+        ));
+        assert!(store.list().await.is_empty());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,503 @@
+//! Arrow IPC export endpoints for data science clients.
+//!
+//! Built with the `arrow` feature and served on its own listener
+//! (`[arrow_export]`). Results of the rust-client analytics queries are
+//! returned as Arrow IPC streams (`application/vnd.apache.arrow.stream`), which
+//! pyarrow and the R arrow package read straight into data frames:
+//! - `GET /v1/arrow/meter_usage?meter_id=...`: interval readings of a meter
+//! - `GET /v1/arrow/resample?series=...&id=...`: one resampled series
+//! - `GET /v1/arrow/aligned?series=<kind>:<id>,...`: several series on one time grid
+//!
+//! Every request needs `Authorization: Bearer <token>` (the `auth_bearer_token`
+//! or an API key with the `read` role) and an RFC 3339 `start` / `end` range
+//! (`end` exclusive).
+
+use std::{net::SocketAddr, sync::Arc};
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray, TimestampMicrosecondArray};
+use arrow_ipc::writer::StreamWriter;
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef, TimeUnit};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use rust_client::{
+    db::{
+        aligned_series, load_profile, resample_profile, Aggregation, AlignJoin, AlignedRow, AlignedSeries, Fill,
+        Granularity, ProfileSeries, ResampleSpec, SamplePoint,
+    },
+    domain::MeterUsage,
+};
+use serde::Deserialize;
+use sqlx::PgPool;
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    auth::{self, authorize},
+    config::{ArrowExportConfig, Role},
+    sources::http_error::{parse_ts_field, ApiError, FieldError},
+};
+
+const CONTENT_TYPE: &str = "application/vnd.apache.arrow.stream";
+/// Rows per record batch of a stream.
+const BATCH_ROWS: usize = 65_536;
+
+#[derive(Clone)]
+struct ApiState {
+    pool: PgPool,
+    auth_bearer_token: Option<String>,
+    max_range: Duration,
+}
+
+fn invalid(field: &str, reason: impl Into<String>) -> ApiError {
+    ApiError::new(StatusCode::BAD_REQUEST, "invalid query").with_details(vec![FieldError::field(field, reason)])
+}
+
+fn query_failed(e: anyhow::Error) -> ApiError {
+    metrics::counter!("arrow_export_query_errors_total").increment(1);
+    tracing::error!(error = %e, "Arrow export query failed");
+    ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "query failed")
+}
+
+/// The validated `[start, end)` of a request.
+fn parse_range(start: &str, end: &str, max_range: Duration) -> Result<(OffsetDateTime, OffsetDateTime), ApiError> {
+    let bad = |e: FieldError| ApiError::new(StatusCode::BAD_REQUEST, "invalid query").with_details(vec![e]);
+    let start = parse_ts_field("start", start).map_err(bad)?;
+    let end = parse_ts_field("end", end).map_err(bad)?;
+    if end <= start {
+        return Err(invalid("end", "must be after start"));
+    }
+    if end - start > max_range {
+        return Err(invalid(
+            "end",
+            format!("range is limited to {} days", max_range.whole_days()),
+        ));
+    }
+    Ok((start, end))
+}
+
+fn parse_granularity(granularity: &str) -> Result<Granularity, ApiError> {
+    granularity
+        .parse()
+        .map_err(|e: anyhow::Error| invalid("granularity", e.to_string()))
+}
+
+/// Authorize and validate the range of a request.
+fn prepare(
+    state: &ApiState,
+    headers: &HeaderMap,
+    endpoint: &'static str,
+    start: &str,
+    end: &str,
+) -> Result<(OffsetDateTime, OffsetDateTime), ApiError> {
+    metrics::counter!("arrow_export_requests_total", "endpoint" => endpoint).increment(1);
+    authorize(headers, &state.auth_bearer_token, Role::Read, "arrow_export_unauthorized_total")?;
+    parse_range(start, end, state.max_range)
+}
+
+fn timestamp_field(name: &str) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+        false,
+    )
+}
+
+fn ts_column(ts: impl Iterator<Item = OffsetDateTime>) -> ArrayRef {
+    let micros = ts.map(|ts| (ts.unix_timestamp_nanos() / 1_000) as i64);
+    Arc::new(TimestampMicrosecondArray::from_iter_values(micros).with_timezone("UTC"))
+}
+
+/// Encode `rows` as an IPC stream of record batches of at most `BATCH_ROWS` rows.
+fn encode<T>(schema: SchemaRef, rows: &[T], columns: impl Fn(&[T]) -> Vec<ArrayRef>) -> Result<Vec<u8>, ArrowError> {
+    let mut writer = StreamWriter::try_new(Vec::new(), &schema)?;
+    for chunk in rows.chunks(BATCH_ROWS) {
+        writer.write(&RecordBatch::try_new(schema.clone(), columns(chunk))?)?;
+    }
+    writer.finish()?;
+    writer.into_inner()
+}
+
+fn encode_meter_usage(rows: &[MeterUsage]) -> Result<Vec<u8>, ArrowError> {
+    let schema = Arc::new(Schema::new(vec![
+        timestamp_field("ts"),
+        Field::new("meter_id", DataType::Utf8, false),
+        Field::new("premise_id", DataType::Utf8, true),
+        Field::new("kwh", DataType::Float64, false),
+        Field::new("kvarh", DataType::Float64, true),
+        Field::new("kva_demand", DataType::Float64, true),
+        Field::new("quality_flag", DataType::Utf8, true),
+        Field::new("source_system", DataType::Utf8, true),
+    ]));
+    encode(schema, rows, |chunk| {
+        vec![
+            ts_column(chunk.iter().map(|r| r.ts)),
+            Arc::new(StringArray::from_iter_values(chunk.iter().map(|r| &r.meter_id))),
+            Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.premise_id.as_deref()))),
+            Arc::new(Float64Array::from_iter_values(chunk.iter().map(|r| r.kwh))),
+            Arc::new(Float64Array::from_iter(chunk.iter().map(|r| r.kvarh))),
+            Arc::new(Float64Array::from_iter(chunk.iter().map(|r| r.kva_demand))),
+            Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.quality_flag.as_deref()))),
+            Arc::new(StringArray::from_iter(chunk.iter().map(|r| r.source_system.as_deref()))),
+        ]
+    })
+}
+
+fn encode_samples(points: &[SamplePoint]) -> Result<Vec<u8>, ArrowError> {
+    let schema = Arc::new(Schema::new(vec![
+        timestamp_field("ts"),
+        Field::new("value", DataType::Float64, true),
+    ]));
+    encode(schema, points, |chunk| {
+        vec![
+            ts_column(chunk.iter().map(|p| p.ts)),
+            Arc::new(Float64Array::from_iter(chunk.iter().map(|p| p.value))),
+        ]
+    })
+}
+
+/// One `ts` column and a value column per series, named by `names`.
+fn encode_aligned(names: &[&str], rows: &[AlignedRow]) -> Result<Vec<u8>, ArrowError> {
+    let mut fields = vec![timestamp_field("ts")];
+    fields.extend(names.iter().map(|name| Field::new(*name, DataType::Float64, true)));
+    encode(Arc::new(Schema::new(fields)), rows, |chunk| {
+        let mut columns = vec![ts_column(chunk.iter().map(|r| r.ts))];
+        columns.extend((0..names.len()).map(|i| {
+            Arc::new(Float64Array::from_iter(
+                chunk.iter().map(|r| r.values.get(i).copied().flatten()),
+            )) as ArrayRef
+        }));
+        columns
+    })
+}
+
+fn arrow_response(encoded: Result<Vec<u8>, ArrowError>) -> Result<Response, ApiError> {
+    let body = encoded.map_err(|e| {
+        tracing::error!(error = %e, "Arrow IPC encoding failed");
+        ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "encoding failed")
+    })?;
+    Ok(([(header::CONTENT_TYPE, CONTENT_TYPE)], body).into_response())
+}
+
+#[derive(Deserialize)]
+struct MeterUsageQuery {
+    meter_id: String,
+    start: String,
+    end: String,
+}
+
+async fn meter_usage(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(q): Query<MeterUsageQuery>,
+) -> Result<Response, ApiError> {
+    let (start, end) = prepare(&state, &headers, "meter_usage", &q.start, &q.end)?;
+    let rows = load_profile(&state.pool, &q.meter_id, start, end)
+        .await
+        .map_err(query_failed)?;
+    arrow_response(encode_meter_usage(&rows))
+}
+
+fn default_granularity() -> String {
+    "1h".to_string()
+}
+
+#[derive(Deserialize)]
+struct ResampleQuery {
+    /// `meter_kwh`, `meter_kvarh`, `meter_kva_demand` or `generation_mw`.
+    series: String,
+    /// Meter or plant ID.
+    id: String,
+    #[serde(default)]
+    unit_id: Option<String>,
+    start: String,
+    end: String,
+    #[serde(default = "default_granularity")]
+    granularity: String,
+    #[serde(default)]
+    aggregation: Option<String>,
+    #[serde(default)]
+    fill: Option<String>,
+}
+
+/// The series and spec of a resample request; energy series are summed and
+/// power series averaged unless `aggregation` says otherwise.
+fn parse_resample(q: &ResampleQuery) -> Result<(ProfileSeries<'_>, ResampleSpec), ApiError> {
+    let series = match q.series.as_str() {
+        "meter_kwh" => ProfileSeries::MeterKwh(&q.id),
+        "meter_kvarh" => ProfileSeries::MeterKvarh(&q.id),
+        "meter_kva_demand" => ProfileSeries::MeterKvaDemand(&q.id),
+        "generation_mw" => ProfileSeries::GenerationMw {
+            plant_id: &q.id,
+            unit_id: q.unit_id.as_deref(),
+        },
+        other => {
+            return Err(invalid(
+                "series",
+                format!("unknown series '{other}', expected meter_kwh, meter_kvarh, meter_kva_demand or generation_mw"),
+            ))
+        }
+    };
+    let aggregation = match q.aggregation.as_deref() {
+        None => match series {
+            ProfileSeries::MeterKwh(_) | ProfileSeries::MeterKvarh(_) => Aggregation::Sum,
+            ProfileSeries::MeterKvaDemand(_) | ProfileSeries::GenerationMw { .. } => Aggregation::Avg,
+        },
+        Some("sum") => Aggregation::Sum,
+        Some("avg") => Aggregation::Avg,
+        Some("min") => Aggregation::Min,
+        Some("max") => Aggregation::Max,
+        Some(other) => {
+            return Err(invalid(
+                "aggregation",
+                format!("unknown aggregation '{other}', expected sum, avg, min or max"),
+            ))
+        }
+    };
+    let fill = match q.fill.as_deref() {
+        None | Some("none") => Fill::None,
+        Some("null") => Fill::Null,
+        Some("prev") => Fill::Prev,
+        Some("linear") => Fill::Linear,
+        Some(other) => {
+            return Err(invalid(
+                "fill",
+                format!("unknown fill '{other}', expected none, null, prev or linear"),
+            ))
+        }
+    };
+    let spec = ResampleSpec {
+        granularity: parse_granularity(&q.granularity)?,
+        aggregation,
+        fill,
+    };
+    Ok((series, spec))
+}
+
+async fn resample(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(q): Query<ResampleQuery>,
+) -> Result<Response, ApiError> {
+    let (start, end) = prepare(&state, &headers, "resample", &q.start, &q.end)?;
+    let (series, spec) = parse_resample(&q)?;
+    let points = resample_profile(&state.pool, series, start, end, spec)
+        .await
+        .map_err(query_failed)?;
+    arrow_response(encode_samples(&points))
+}
+
+#[derive(Deserialize)]
+struct AlignedQuery {
+    /// Comma-separated `<kind>:<id>` series; each becomes a column of that name.
+    series: String,
+    start: String,
+    end: String,
+    #[serde(default = "default_granularity")]
+    granularity: String,
+    #[serde(default)]
+    join: Option<String>,
+}
+
+/// The `<kind>:<id>` series of an aligned request, with their column names.
+fn parse_aligned_series(spec: &str) -> Result<Vec<(&str, AlignedSeries<'_>)>, ApiError> {
+    spec.split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|name| {
+            let series = match name.split_once(':') {
+                Some(("feeder_load_kwh", id)) => AlignedSeries::FeederLoadKwh(id),
+                Some(("feeder_generation_mw", id)) => AlignedSeries::FeederGenerationMw(id),
+                Some(("weather_temperature_c", id)) => AlignedSeries::WeatherTemperatureC(id),
+                _ => {
+                    return Err(invalid(
+                        "series",
+                        format!(
+                            "invalid series '{name}', expected feeder_load_kwh:<id>, feeder_generation_mw:<id> or weather_temperature_c:<id>"
+                        ),
+                    ))
+                }
+            };
+            Ok((name, series))
+        })
+        .collect()
+}
+
+async fn aligned(
+    State(state): State<ApiState>,
+    headers: HeaderMap,
+    Query(q): Query<AlignedQuery>,
+) -> Result<Response, ApiError> {
+    let (start, end) = prepare(&state, &headers, "aligned", &q.start, &q.end)?;
+    let parsed = parse_aligned_series(&q.series)?;
+    if parsed.is_empty() {
+        return Err(invalid("series", "at least one series is required"));
+    }
+    let join = match q.join.as_deref() {
+        None | Some("asof") => AlignJoin::Asof,
+        Some("lt") => AlignJoin::Lt,
+        Some(other) => return Err(invalid("join", format!("unknown join '{other}', expected asof or lt"))),
+    };
+    let granularity = parse_granularity(&q.granularity)?;
+    let (names, series): (Vec<_>, Vec<_>) = parsed.into_iter().unzip();
+    let rows = aligned_series(&state.pool, &series, join, start, end, granularity)
+        .await
+        .map_err(query_failed)?;
+    arrow_response(encode_aligned(&names, &rows))
+}
+
+fn router(state: ApiState) -> Router {
+    Router::new()
+        .route("/v1/arrow/meter_usage", get(meter_usage))
+        .route("/v1/arrow/resample", get(resample))
+        .route("/v1/arrow/aligned", get(aligned))
+        .with_state(state)
+}
+
+/// Bind `cfg.bind_addr` and serve the exports in the background; returns the bound address.
+pub async fn serve(cfg: &ArrowExportConfig, pool: PgPool) -> anyhow::Result<SocketAddr> {
+    auth::ensure_protected("[arrow_export]", &cfg.auth_bearer_token)?;
+    let state = ApiState {
+        pool,
+        auth_bearer_token: cfg.auth_bearer_token.clone(),
+        max_range: Duration::days(cfg.max_range_days.into()),
+    };
+
+    // Fail-fast: if we can't bind, return an error to the caller.
+    let listener = tokio::net::TcpListener::bind(&cfg.bind_addr)
+        .await
+        .map_err(|e| anyhow::anyhow!("failed to bind Arrow export on {}: {e}", cfg.bind_addr))?;
+    let addr = listener.local_addr()?;
+    let app = router(state);
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app.into_make_service()).await {
+            tracing::error!(error = %e, "Arrow export server error");
+        }
+    });
+    tracing::info!(%addr, "Arrow export listening");
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::{
+        cast::AsArray,
+        types::{Float64Type, TimestampMicrosecondType},
+        Array,
+    };
+    use arrow_ipc::reader::StreamReader;
+    use rust_client::domain::PhaseChannels;
+    use time::macros::datetime;
+
+    fn read(bytes: Vec<u8>) -> Vec<RecordBatch> {
+        StreamReader::try_new(std::io::Cursor::new(bytes), None)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn meter_usage_and_aligned_rows_read_back_from_the_stream() {
+        let usage = |ts, kwh, quality_flag: Option<&str>| MeterUsage {
+            ts,
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh,
+            kvarh: Some(0.1),
+            kva_demand: None,
+            quality_flag: quality_flag.map(str::to_string),
+            source_system: Some("mdm".to_string()),
+            event_id: None,
+            phases: PhaseChannels::default(),
+        };
+        let rows = [
+            usage(datetime!(2024-06-01 00:00 UTC), 0.25, None),
+            usage(datetime!(2024-06-01 00:15 UTC), 0.5, Some("estimated")),
+        ];
+        let batches = read(encode_meter_usage(&rows).unwrap());
+        assert_eq!(batches.len(), 1);
+        let batch = &batches[0];
+        assert_eq!(batch.num_rows(), 2);
+        let ts = batch.column(0).as_primitive::<TimestampMicrosecondType>();
+        assert_eq!(
+            ts.value(1),
+            datetime!(2024-06-01 00:15 UTC).unix_timestamp() * 1_000_000
+        );
+        assert_eq!(ts.timezone(), Some("UTC"));
+        assert_eq!(
+            batch
+                .column_by_name("kwh")
+                .unwrap()
+                .as_primitive::<Float64Type>()
+                .value(1),
+            0.5
+        );
+        let quality = batch.column_by_name("quality_flag").unwrap().as_string::<i32>();
+        assert!(quality.is_null(0));
+        assert_eq!(quality.value(1), "estimated");
+
+        let aligned = [
+            AlignedRow {
+                ts: datetime!(2024-06-01 00:00 UTC),
+                values: vec![Some(10.0), None],
+            },
+            AlignedRow {
+                ts: datetime!(2024-06-01 01:00 UTC),
+                values: vec![Some(12.0), Some(21.5)],
+            },
+        ];
+        let batches = read(encode_aligned(&["feeder_load_kwh:F-1", "weather_temperature_c:KJFK"], &aligned).unwrap());
+        let temperature = batches[0]
+            .column_by_name("weather_temperature_c:KJFK")
+            .unwrap()
+            .as_primitive::<Float64Type>();
+        assert!(temperature.is_null(0));
+        assert_eq!(temperature.value(1), 21.5);
+
+        // An empty result is still a valid stream with the schema.
+        assert!(read(encode_samples(&[]).unwrap()).is_empty());
+    }
+
+    #[test]
+    fn parses_series_and_defaults_aggregation_by_quantity() {
+        let series = parse_aligned_series("feeder_load_kwh:F-1, weather_temperature_c:KJFK").unwrap();
+        assert_eq!(
+            series.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            ["feeder_load_kwh:F-1", "weather_temperature_c:KJFK"]
+        );
+        assert!(matches!(series[0].1, AlignedSeries::FeederLoadKwh("F-1")));
+        let err = parse_aligned_series("feeder_load_kwh:F-1,F-2").unwrap_err();
+        assert!(err.details[0].reason.contains("invalid series 'F-2'"));
+
+        let query = |series: &str, aggregation: Option<&str>| ResampleQuery {
+            series: series.to_string(),
+            id: "m-1".to_string(),
+            unit_id: None,
+            start: String::new(),
+            end: String::new(),
+            granularity: default_granularity(),
+            aggregation: aggregation.map(str::to_string),
+            fill: None,
+        };
+        assert_eq!(
+            parse_resample(&query("meter_kwh", None)).unwrap().1.aggregation,
+            Aggregation::Sum
+        );
+        assert_eq!(
+            parse_resample(&query("generation_mw", None)).unwrap().1.aggregation,
+            Aggregation::Avg
+        );
+        assert_eq!(
+            parse_resample(&query("meter_kwh", Some("max"))).unwrap().1.aggregation,
+            Aggregation::Max
+        );
+        assert!(parse_resample(&query("meter_volts", None)).is_err());
+
+        let err = parse_range("2024-01-01T00:00:00Z", "2025-06-01T00:00:00Z", Duration::days(366)).unwrap_err();
+        assert!(err.details[0].reason.contains("366 days"));
+    }
+}
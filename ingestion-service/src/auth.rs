@@ -0,0 +1,380 @@
+//! Role-based access control for the ingest, reference and read endpoints.
+//!
+//! Every `[[api_keys]]` entry is a bearer token holding a set of [`Role`]s and,
+//! optionally, a tenant. Each endpoint names the role it needs: a key without
+//! it gets 403, an unknown credential 401. An endpoint's own
+//! `auth_bearer_token` keeps granting that endpoint alone, so existing clients
+//! work unchanged; once any key is configured (or a key store is in use),
+//! endpoints without a token of their own are no longer open.
+//!
+//! Tenant keys only reach endpoints that can enforce the tenant (meter usage
+//! ingest, by `source_system`); everywhere else they are refused.
+//!
+//! With `[jwt]`, tokens issued by the identity provider are accepted as well
+//! (see [`TokenValidator`]) and pass the same role checks.
+//!
+//! Keys come from the config and, when `[api_key_store]` is set, from the
+//! [`crate::api_key_store`], which re-installs them on every change. Only
+//! token hashes are kept in memory.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use axum::http::{HeaderMap, StatusCode};
+use time::OffsetDateTime;
+
+use crate::config::{ApiKeyConfig, Role};
+
+/// A credential, without its token.
+#[derive(Debug, PartialEq)]
+pub struct ApiKey {
+    pub name: String,
+    roles: Vec<Role>,
+    pub tenant: Option<String>,
+    /// Rejected as unknown from then on.
+    pub expires_at: Option<OffsetDateTime>,
+}
+
+impl ApiKey {
+    pub fn new(name: String, roles: Vec<Role>, tenant: Option<String>, expires_at: Option<OffsetDateTime>) -> Self {
+        Self {
+            name,
+            roles,
+            tenant,
+            expires_at,
+        }
+    }
+
+    pub fn has_role(&self, role: Role) -> bool {
+        self.roles.contains(&role) || self.roles.contains(&Role::Admin)
+    }
+}
+
+/// Why a key's roles and tenant don't go together, if they don't.
+pub fn check_roles(roles: &[Role], tenant: Option<&str>) -> Result<(), &'static str> {
+    if roles.is_empty() {
+        return Err("a key needs at least one role");
+    }
+    match tenant {
+        Some("") => Err("tenant may not be empty"),
+        Some(_) if roles.iter().any(|r| *r != Role::Ingest) => {
+            Err("tenant-scoped keys may only hold the ingest role")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Hash under which a token is looked up; tokens themselves are never kept.
+pub fn token_hash(token: &str) -> String {
+    blake3::hash(token.as_bytes()).to_hex().to_string()
+}
+
+/// Credentials by token hash.
+#[derive(Debug, Default)]
+pub struct ApiKeys {
+    by_token_hash: BTreeMap<String, Arc<ApiKey>>,
+    /// Require credentials even while no key exists (a key store is in use).
+    closed: bool,
+}
+
+static NO_KEYS: ApiKeys = ApiKeys {
+    by_token_hash: BTreeMap::new(),
+    closed: false,
+};
+static INSTALLED: RwLock<Option<ApiKeys>> = RwLock::new(None);
+
+impl ApiKeys {
+    pub fn from_config(keys: &[ApiKeyConfig]) -> anyhow::Result<Self> {
+        let mut out = Self::default();
+        let mut names = HashSet::new();
+        for key in keys {
+            anyhow::ensure!(!key.name.is_empty(), "[[api_keys]] entries need a name");
+            anyhow::ensure!(names.insert(key.name.as_str()), "api key {:?} is configured twice", key.name);
+            anyhow::ensure!(!key.token.is_empty(), "api key {:?} has an empty token", key.name);
+            check_roles(&key.roles, key.tenant.as_deref()).map_err(|e| anyhow::anyhow!("api key {:?}: {e}", key.name))?;
+            let api_key = ApiKey::new(key.name.clone(), key.roles.clone(), key.tenant.clone(), None);
+            out.insert(token_hash(&key.token), Arc::new(api_key))?;
+        }
+        Ok(out)
+    }
+
+    /// Add a key under the hash of its token.
+    pub fn insert(&mut self, token_hash: String, key: Arc<ApiKey>) -> anyhow::Result<()> {
+        let name = key.name.clone();
+        anyhow::ensure!(
+            self.by_token_hash.insert(token_hash, key).is_none(),
+            "api key {name:?} reuses another key's token"
+        );
+        Ok(())
+    }
+
+    /// Keep endpoints without a token of their own closed even with no keys.
+    pub fn closed(mut self) -> Self {
+        self.closed = true;
+        self
+    }
+
+    /// Whether endpoints without a token of their own need a key.
+    pub fn requires_credentials(&self) -> bool {
+        self.closed || !self.by_token_hash.is_empty()
+    }
+
+    /// Check a presented bearer token against an endpoint needing `role`,
+    /// whose own token (if any) is `endpoint_token`.
+    ///
+    /// Returns the matching key, or `None` when the endpoint token matched or
+    /// the endpoint is open.
+    pub fn check(
+        &self,
+        given: Option<&str>,
+        endpoint_token: Option<&str>,
+        role: Role,
+    ) -> Result<Option<Arc<ApiKey>>, StatusCode> {
+        self.check_with(given, endpoint_token, role, None)
+    }
+
+    /// Like [`Self::check`], but also accept tokens `validator` vouches for.
+    pub fn check_with(
+        &self,
+        given: Option<&str>,
+        endpoint_token: Option<&str>,
+        role: Role,
+        validator: Option<&dyn TokenValidator>,
+    ) -> Result<Option<Arc<ApiKey>>, StatusCode> {
+        if let Some(given) = given {
+            if endpoint_token == Some(given) {
+                return Ok(None);
+            }
+            let live = |k: &Arc<ApiKey>| k.expires_at.is_none_or(|at| OffsetDateTime::now_utc() < at);
+            let key = self
+                .by_token_hash
+                .get(&token_hash(given))
+                .cloned()
+                .or_else(|| validator.and_then(|v| v.validate(given)))
+                .filter(live);
+            if let Some(key) = key {
+                if !key.has_role(role) {
+                    tracing::debug!(key = %key.name, role = role.as_str(), "api key lacks role");
+                    return Err(StatusCode::FORBIDDEN);
+                }
+                return Ok(Some(key));
+            }
+        }
+        if endpoint_token.is_none() && !self.requires_credentials() && validator.is_none() {
+            return Ok(None);
+        }
+        Err(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// Vouches for bearer tokens that are not API keys, e.g. JWTs from an identity provider.
+pub trait TokenValidator: Send + Sync {
+    /// The credential `token` stands for, or `None` if it is not valid.
+    fn validate(&self, token: &str) -> Option<Arc<ApiKey>>;
+}
+
+static VALIDATOR: OnceLock<Arc<dyn TokenValidator>> = OnceLock::new();
+
+/// Accept tokens `validator` vouches for on every endpoint, next to the keys.
+pub fn install_validator(validator: Arc<dyn TokenValidator>) -> anyhow::Result<()> {
+    VALIDATOR
+        .set(validator)
+        .map_err(|_| anyhow::anyhow!("a token validator is already installed"))
+}
+
+/// Check a presented token against the installed keys and validator.
+pub(crate) fn resolve(
+    given: Option<&str>,
+    endpoint_token: Option<&str>,
+    role: Role,
+) -> Result<Option<Arc<ApiKey>>, StatusCode> {
+    let validator = VALIDATOR.get().map(|v| v.as_ref());
+    with_installed(|keys| keys.check_with(given, endpoint_token, role, validator))
+}
+
+/// Make `keys` the credentials every endpoint checks, replacing any installed before.
+pub fn install(keys: ApiKeys) {
+    *INSTALLED.write().unwrap_or_else(|e| e.into_inner()) = Some(keys);
+}
+
+/// Run `f` with the installed credentials; none until [`install`] runs.
+fn with_installed<R>(f: impl FnOnce(&ApiKeys) -> R) -> R {
+    let installed = INSTALLED.read().unwrap_or_else(|e| e.into_inner());
+    f(installed.as_ref().unwrap_or(&NO_KEYS))
+}
+
+/// Refuse to start an endpoint that would accept anyone: it needs either its
+/// own token or configured keys.
+pub fn ensure_protected(section: &str, token: &Option<String>) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        token.is_some() || VALIDATOR.get().is_some() || with_installed(ApiKeys::requires_credentials),
+        "{section} needs an auth_bearer_token or API keys with the read role"
+    );
+    Ok(())
+}
+
+/// The token of an `Authorization: Bearer <token>` header.
+pub(crate) fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(axum::http::header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Check a presented token against an endpoint needing `role`, counting
+/// refusals. Returns the matching key, if a key matched.
+pub(crate) fn authorize_key(
+    given: Option<&str>,
+    token: &Option<String>,
+    role: Role,
+    metric_name: &'static str,
+) -> Result<Option<Arc<ApiKey>>, StatusCode> {
+    match resolve(given, token.as_deref(), role) {
+        Ok(key) => Ok(key),
+        Err(StatusCode::UNAUTHORIZED) => {
+            metrics::counter!(metric_name).increment(1);
+            Err(StatusCode::UNAUTHORIZED)
+        }
+        Err(status) => {
+            metrics::counter!("api_key_forbidden_total", "role" => role.as_str()).increment(1);
+            Err(status)
+        }
+    }
+}
+
+/// Like [`authorize`], but admit tenant keys too and return their tenant,
+/// which the caller must enforce.
+pub(crate) fn authorize_bearer(
+    given: Option<&str>,
+    token: &Option<String>,
+    role: Role,
+    metric_name: &'static str,
+) -> Result<Option<String>, StatusCode> {
+    Ok(authorize_key(given, token, role, metric_name)?.and_then(|k| k.tenant.clone()))
+}
+
+/// Authorize a request needing `role` against `token` and the configured
+/// keys. Tenant keys are refused: the endpoint has no tenant to scope to.
+pub(crate) fn authorize(
+    headers: &HeaderMap,
+    token: &Option<String>,
+    role: Role,
+    metric_name: &'static str,
+) -> Result<(), StatusCode> {
+    unscoped(authorize_bearer(bearer_token(headers), token, role, metric_name)?, role)
+}
+
+/// Refuse a tenant returned by [`authorize_bearer`] on an endpoint that cannot scope to it.
+pub(crate) fn unscoped(tenant: Option<String>, role: Role) -> Result<(), StatusCode> {
+    match tenant {
+        None => Ok(()),
+        Some(_) => {
+            metrics::counter!("api_key_forbidden_total", "role" => role.as_str()).increment(1);
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, token: &str, roles: Vec<Role>, tenant: Option<&str>) -> ApiKeyConfig {
+        ApiKeyConfig {
+            name: name.to_string(),
+            token: token.to_string(),
+            roles,
+            tenant: tenant.map(str::to_string),
+        }
+    }
+
+    fn keys() -> ApiKeys {
+        ApiKeys::from_config(&[
+            key("loader", "ingest-secret", vec![Role::Ingest], None),
+            key("ops", "admin-secret", vec![Role::Admin], None),
+            key("vendor-a", "tenant-secret", vec![Role::Ingest], Some("vendor-a")),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn keys_need_the_endpoint_role() {
+        let keys = keys();
+
+        let loader = keys.check(Some("ingest-secret"), None, Role::Ingest).unwrap().unwrap();
+        assert_eq!(loader.name, "loader");
+        assert_eq!(keys.check(Some("ingest-secret"), None, Role::Read), Err(StatusCode::FORBIDDEN));
+        assert_eq!(keys.check(Some("ingest-secret"), None, Role::Admin), Err(StatusCode::FORBIDDEN));
+
+        // Admin implies every role.
+        assert!(keys.check(Some("admin-secret"), None, Role::Read).unwrap().is_some());
+
+        let tenant = keys.check(Some("tenant-secret"), None, Role::Ingest).unwrap().unwrap();
+        assert_eq!(tenant.tenant.as_deref(), Some("vendor-a"));
+
+        assert_eq!(keys.check(Some("wrong"), None, Role::Ingest), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(keys.check(None, None, Role::Ingest), Err(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn endpoint_token_still_grants_its_endpoint() {
+        let keys = keys();
+        assert_eq!(keys.check(Some("legacy"), Some("legacy"), Role::Admin), Ok(None));
+        assert_eq!(keys.check(Some("wrong"), Some("legacy"), Role::Admin), Err(StatusCode::UNAUTHORIZED));
+
+        // Without keys or a token the endpoint stays open, as before.
+        assert_eq!(ApiKeys::default().check(None, None, Role::Ingest), Ok(None));
+        assert_eq!(ApiKeys::default().check(None, Some("legacy"), Role::Ingest), Err(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn expired_keys_are_unknown() {
+        let mut keys = ApiKeys::default();
+        let expires = |at| Arc::new(ApiKey::new("k".to_string(), vec![Role::Read], None, Some(at)));
+        let now = OffsetDateTime::now_utc();
+        keys.insert(token_hash("old"), expires(now - time::Duration::seconds(1))).unwrap();
+        keys.insert(token_hash("new"), expires(now + time::Duration::hours(1))).unwrap();
+
+        assert_eq!(keys.check(Some("old"), None, Role::Read), Err(StatusCode::UNAUTHORIZED));
+        assert!(keys.check(Some("new"), None, Role::Read).unwrap().is_some());
+    }
+
+    struct Vouch;
+
+    impl TokenValidator for Vouch {
+        fn validate(&self, token: &str) -> Option<Arc<ApiKey>> {
+            let roles = match token {
+                "idp-reader" => vec![Role::Read],
+                _ => return None,
+            };
+            Some(Arc::new(ApiKey::new("jwt:alice".to_string(), roles, None, None)))
+        }
+    }
+
+    #[test]
+    fn validated_tokens_pass_the_same_role_checks() {
+        let keys = keys();
+        let reader = keys.check_with(Some("idp-reader"), None, Role::Read, Some(&Vouch)).unwrap();
+        assert_eq!(reader.unwrap().name, "jwt:alice");
+        assert_eq!(keys.check_with(Some("idp-reader"), None, Role::Admin, Some(&Vouch)), Err(StatusCode::FORBIDDEN));
+        assert!(keys.check_with(Some("ingest-secret"), None, Role::Ingest, Some(&Vouch)).is_ok());
+
+        // A validator closes endpoints that had neither keys nor a token.
+        let none = ApiKeys::default();
+        assert_eq!(none.check_with(None, None, Role::Read, Some(&Vouch)), Err(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn rejects_ambiguous_or_overreaching_keys() {
+        let dup_token = [key("a", "t", vec![Role::Read], None), key("b", "t", vec![Role::Read], None)];
+        assert!(ApiKeys::from_config(&dup_token).is_err());
+
+        let no_roles = [key("a", "t", vec![], None)];
+        assert!(ApiKeys::from_config(&no_roles).is_err());
+
+        let tenant_reader = [key("a", "t", vec![Role::Ingest, Role::Read], Some("vendor-a"))];
+        assert!(ApiKeys::from_config(&tenant_reader).is_err());
+    }
+}
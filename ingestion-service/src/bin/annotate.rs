@@ -0,0 +1,80 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::annotations::{Annotation, AnnotationWriter},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{format_description::well_known::Rfc3339, Duration, OffsetDateTime};
+
+/// Write an operational event to `ops_annotations` for Grafana dashboards.
+///
+/// Usage:
+///   annotate <kind> <text> [--tag TAG]... [--at RFC3339] [--end RFC3339 | --duration-mins N] [--source NAME]
+///
+/// E.g. from a deploy script: `annotate deploy "ingestion-service 1.4.2" --tag prod`,
+/// or for a maintenance window: `annotate maintenance "QuestDB upgrade" --duration-mins 30`.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let (annotation, source) = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new().max_connections(1).connect(&cfg.questdb.uri).await?;
+
+    // See `sql/schema/04_ingest_quality.sql` for the table.
+    AnnotationWriter::new(pool, source).write(&annotation).await?;
+
+    tracing::info!(kind = %annotation.kind, text = %annotation.text, "annotation written");
+
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Annotation, String)> {
+    let usage = "usage: annotate <kind> <text> [--tag TAG]... [--at RFC3339] [--end RFC3339 | --duration-mins N] [--source NAME]";
+    let mut positional = Vec::new();
+    let mut tags = Vec::new();
+    let mut at = None;
+    let mut end = None;
+    let mut duration_mins: Option<i64> = None;
+    let mut source = "annotate".to_string();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--tag" => tags.push(value()?),
+            "--at" => at = Some(OffsetDateTime::parse(&value()?, &Rfc3339)?),
+            "--end" => end = Some(OffsetDateTime::parse(&value()?, &Rfc3339)?),
+            "--duration-mins" => duration_mins = Some(value()?.parse()?),
+            "--source" => source = value()?,
+            other if other.starts_with("--") => bail!("unknown argument '{other}'"),
+            _ => positional.push(arg),
+        }
+    }
+    let [kind, text] = <[String; 2]>::try_from(positional).map_err(|_| anyhow!(usage))?;
+
+    let mut annotation = Annotation::new(kind, text);
+    if let Some(at) = at {
+        annotation = annotation.with_ts(at);
+    }
+    let end = match (end, duration_mins) {
+        (Some(_), Some(_)) => bail!("--end and --duration-mins are exclusive"),
+        (Some(end), None) => Some(end),
+        (None, Some(mins)) => Some(annotation.ts + Duration::minutes(mins)),
+        (None, None) => None,
+    };
+    if let Some(end) = end {
+        if end < annotation.ts {
+            bail!("the annotation ends before it starts");
+        }
+        annotation = annotation.with_end(end);
+    }
+    for tag in tags {
+        annotation = annotation.with_tag(tag);
+    }
+
+    Ok((annotation, source))
+}
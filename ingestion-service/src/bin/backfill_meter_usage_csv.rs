@@ -1,29 +1,55 @@
 use anyhow::{bail, Result};
 use ingestion_service::{
     config::AppConfig,
+    jobs::{
+        annotations::{Annotation, AnnotationWriter},
+        backfill_verify::{self, VerifyStatus},
+    },
     observability,
-    pipeline::Pipeline,
+    pipeline::{Pipeline, Source},
     sinks::QuestDbSink,
-    sources::MeterUsageCsvFileSource,
+    sources::{
+        count_existing_meter_usage, Checkpoint, CheckpointedSource, MeterUsageCsvFileSource, OnConflict,
+        SkipExistingMeterUsageSource,
+    },
     transform,
 };
 use rust_client::domain::MeterUsage;
 use sqlx::postgres::PgPoolOptions;
-use std::{env, sync::Arc, time::Duration};
+use std::{
+    env,
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
 
 /// Backfill `meter_usage` table from a CSV file.
 ///
 /// Usage:
-///   backfill_meter_usage_csv <path_to_csv>
+///   backfill_meter_usage_csv <path_to_csv> [--on-conflict append|skip|overwrite|fail | --exactly-once] [--no-verify]
+///
+/// `--on-conflict` decides what happens to rows whose `(ts, meter_id)` already
+/// exists: `append` them (default), `skip` them (`--skip-existing` is the same),
+/// `overwrite` them (needs `(ts, meter_id)` dedup) or `fail` before loading anything.
+/// With `--exactly-once`, the load is checkpointed and relies on `event_id` dedup
+/// (see the README, "Exactly-once backfills").
+/// Afterwards the file is verified against `meter_usage` and the result recorded
+/// in `backfill_runs`, unless `--no-verify` is given.
 #[tokio::main]
 async fn main() -> Result<()> {
     observability::init_tracing();
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        bail!("usage: backfill_meter_usage_csv <csv_file_path>");
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let on_conflict = OnConflict::take_from_args(&mut args)?;
+    let exactly_once = args.iter().any(|a| a == "--exactly-once");
+    let verify = !args.iter().any(|a| a == "--no-verify");
+    let Some(file_path) = args.iter().find(|a| !a.starts_with("--")) else {
+        bail!("usage: backfill_meter_usage_csv <csv_file_path> [--on-conflict append|skip|overwrite|fail | --exactly-once] [--no-verify]");
+    };
+
+    if on_conflict != OnConflict::Append && exactly_once {
+        bail!("--on-conflict and --exactly-once are exclusive; exactly-once relies on event_id dedup instead");
     }
-    let file_path = &args[1];
 
     // Load configuration (INGESTION_CONFIG can point to a backfill-specific file).
     let cfg = AppConfig::load()?;
@@ -34,20 +60,93 @@ async fn main() -> Result<()> {
         .connect(&cfg.questdb.uri)
         .await?;
 
-    let mu_cfg = &cfg.meter_usage;
+    let annotations = AnnotationWriter::new(pool.clone(), "backfill_meter_usage_csv");
+    let start = Annotation::new("backfill", format!("backfill {file_path}")).with_tag("meter_usage");
+    annotations
+        .around(start, async {
+            let mu_cfg = &cfg.meter_usage;
+
+            let sink = QuestDbSink::new(
+                pool.clone(),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+            )
+            .with_event_id(mu_cfg.sink.event_id);
+
+            let source = MeterUsageCsvFileSource::new(file_path);
+
+            if exactly_once {
+                sink.store().require_event_id_dedup("meter_usage").await?;
+                let checkpoint = Checkpoint::for_file(Path::new(file_path));
+                let source = CheckpointedSource::new(source, file_path, checkpoint.clone())
+                    .with_save_every(mu_cfg.sink.batch_size as u64);
+                let progress = source.progress();
+                let result = run(source, sink).await;
+                // Save the final checkpoint even if the load failed, so a re-run resumes there.
+                let records = progress.settled().await?;
+                result?;
+                tracing::info!(
+                    records,
+                    checkpoint = %checkpoint.path().display(),
+                    "exactly-once backfill complete"
+                );
+            } else if on_conflict == OnConflict::Skip {
+                let source = SkipExistingMeterUsageSource::new(source, pool.clone(), mu_cfg.sink.batch_size);
+                let skipped = source.skipped();
+                run(source, sink).await?;
+                tracing::info!(
+                    skipped_existing = skipped.load(Ordering::Relaxed),
+                    "backfill complete; rows already present were skipped"
+                );
+            } else {
+                match on_conflict {
+                    OnConflict::Overwrite => sink.store().require_meter_id_dedup("meter_usage").await?,
+                    OnConflict::Fail => {
+                        let existing =
+                            count_existing_meter_usage(&MeterUsageCsvFileSource::new(file_path), &pool, mu_cfg.sink.batch_size).await?;
+                        if existing > 0 {
+                            bail!("{existing} rows of {file_path} are already in meter_usage; nothing was loaded");
+                        }
+                    }
+                    OnConflict::Append | OnConflict::Skip => {}
+                }
+                run(source, sink).await?;
+            }
 
-    let sink = QuestDbSink::new(
-        pool,
-        mu_cfg.sink.batch_size,
-        mu_cfg.sink.max_retries,
-        Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
-    );
+            if verify {
+                let mode = if exactly_once { "exactly_once" } else { on_conflict.mode() };
+                let v = backfill_verify::verify_and_record(&pool, file_path, mode, &MeterUsageCsvFileSource::new(file_path))
+                    .await?;
+                tracing::info!(
+                    file = %file_path,
+                    expected_rows = v.expected_rows,
+                    landed_rows = v.landed.rows,
+                    status = %v.status(),
+                    "backfill verified"
+                );
+                if v.status() != VerifyStatus::Ok {
+                    bail!(
+                        "backfill verification failed ({}): expected {} rows, {} landed; see backfill_runs",
+                        v.status(),
+                        v.expected_rows,
+                        v.landed.rows
+                    );
+                }
+            }
 
-    let source = MeterUsageCsvFileSource::new(file_path);
+            anyhow::Ok(())
+        })
+        .await
+}
 
+async fn run<S>(source: S, sink: QuestDbSink) -> Result<()>
+where
+    S: Source<MeterUsage> + 'static,
+{
     let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
         source,
-        transforms: vec![Arc::new(transform::MeterUsageValidation::default())],
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
         sink,
     };
 
@@ -0,0 +1,204 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        annotations::{Annotation, AnnotationWriter},
+        backfill_verify::{self, VerifyStatus},
+    },
+    observability,
+    pipeline::{Pipeline, Source},
+    sinks::QuestDbSink,
+    sources::{
+        bulk_file::BulkFormat, count_existing_meter_usage, Checkpoint, CheckpointedSource, OnConflict,
+        SkipExistingMeterUsageSource, ZipArchiveSource,
+    },
+    transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{
+    env,
+    path::Path,
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+
+/// Backfill `meter_usage` table from the files in a `.zip` archive, as one run.
+///
+/// Usage:
+///   backfill_meter_usage_zip <path_to_zip> [--members GLOB] [--format csv|dat|ndjson]
+///       [--on-conflict append|skip|overwrite|fail | --exactly-once] [--no-verify]
+///
+/// Members matching `--members` (default `*`) are read in name order and parsed
+/// by suffix, or all as `--format`.
+/// `--on-conflict` decides what happens to rows whose `(ts, meter_id)` already
+/// exists: `append` them (default), `skip` them (`--skip-existing` is the same),
+/// `overwrite` them (needs `(ts, meter_id)` dedup) or `fail` before loading anything.
+/// With `--exactly-once`, the load is checkpointed and relies on `event_id` dedup
+/// (see the README, "Exactly-once backfills").
+/// Afterwards the file is verified against `meter_usage` and the result recorded
+/// in `backfill_runs`, unless `--no-verify` is given.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let mut args: Vec<String> = env::args().skip(1).collect();
+    let on_conflict = OnConflict::take_from_args(&mut args)?;
+    let args = parse_args(args.into_iter())?;
+    let (exactly_once, verify) = (args.exactly_once, args.verify);
+    let file_path = &args.path;
+    let zip_source = || ZipArchiveSource::new(file_path, args.members.clone()).with_format(args.format);
+
+    if on_conflict != OnConflict::Append && exactly_once {
+        bail!("--on-conflict and --exactly-once are exclusive; exactly-once relies on event_id dedup instead");
+    }
+
+    // Load configuration (INGESTION_CONFIG can point to a backfill-specific file).
+    let cfg = AppConfig::load()?;
+
+    // Create QuestDB pool
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let annotations = AnnotationWriter::new(pool.clone(), "backfill_meter_usage_zip");
+    let start = Annotation::new("backfill", format!("backfill {file_path}")).with_tag("meter_usage");
+    annotations
+        .around(start, async {
+            let mu_cfg = &cfg.meter_usage;
+
+            let sink = QuestDbSink::new(
+                pool.clone(),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+            )
+            .with_event_id(mu_cfg.sink.event_id);
+
+            let source = zip_source();
+            tracing::info!(archive = %file_path, members = ?source.member_names()?, "backfilling zip members");
+
+            if exactly_once {
+                sink.store().require_event_id_dedup("meter_usage").await?;
+                let checkpoint = Checkpoint::for_file(Path::new(file_path));
+                let source = CheckpointedSource::new(source, file_path, checkpoint.clone())
+                    .with_save_every(mu_cfg.sink.batch_size as u64);
+                let progress = source.progress();
+                let result = run(source, sink).await;
+                // Save the final checkpoint even if the load failed, so a re-run resumes there.
+                let records = progress.settled().await?;
+                result?;
+                tracing::info!(
+                    records,
+                    checkpoint = %checkpoint.path().display(),
+                    "exactly-once backfill complete"
+                );
+            } else if on_conflict == OnConflict::Skip {
+                let source = SkipExistingMeterUsageSource::new(source, pool.clone(), mu_cfg.sink.batch_size);
+                let skipped = source.skipped();
+                run(source, sink).await?;
+                tracing::info!(
+                    skipped_existing = skipped.load(Ordering::Relaxed),
+                    "backfill complete; rows already present were skipped"
+                );
+            } else {
+                match on_conflict {
+                    OnConflict::Overwrite => sink.store().require_meter_id_dedup("meter_usage").await?,
+                    OnConflict::Fail => {
+                        let existing = count_existing_meter_usage(&zip_source(), &pool, mu_cfg.sink.batch_size).await?;
+                        if existing > 0 {
+                            bail!("{existing} rows of {file_path} are already in meter_usage; nothing was loaded");
+                        }
+                    }
+                    OnConflict::Append | OnConflict::Skip => {}
+                }
+                run(source, sink).await?;
+            }
+
+            if verify {
+                let mode = if exactly_once { "exactly_once" } else { on_conflict.mode() };
+                let v = backfill_verify::verify_and_record(&pool, file_path, mode, &zip_source())
+                    .await?;
+                tracing::info!(
+                    file = %file_path,
+                    expected_rows = v.expected_rows,
+                    landed_rows = v.landed.rows,
+                    status = %v.status(),
+                    "backfill verified"
+                );
+                if v.status() != VerifyStatus::Ok {
+                    bail!(
+                        "backfill verification failed ({}): expected {} rows, {} landed; see backfill_runs",
+                        v.status(),
+                        v.expected_rows,
+                        v.landed.rows
+                    );
+                }
+            }
+
+            anyhow::Ok(())
+        })
+        .await
+}
+
+async fn run<S>(source: S, sink: QuestDbSink) -> Result<()>
+where
+    S: Source<MeterUsage> + 'static,
+{
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink,
+    };
+
+    pipeline.run().await?;
+
+    Ok(())
+}
+
+struct Args {
+    path: String,
+    members: String,
+    format: Option<BulkFormat>,
+    exactly_once: bool,
+    verify: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut path = None;
+    let mut parsed = Args {
+        path: String::new(),
+        members: "*".to_string(),
+        format: None,
+        exactly_once: false,
+        verify: true,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--members" => parsed.members = value()?,
+            "--format" => {
+                parsed.format = Some(match value()?.as_str() {
+                    "csv" => BulkFormat::Csv,
+                    "dat" => BulkFormat::Dat,
+                    "ndjson" | "jsonl" => BulkFormat::Ndjson,
+                    other => bail!("unknown --format '{other}' (expected csv|dat|ndjson)"),
+                })
+            }
+            "--exactly-once" => parsed.exactly_once = true,
+            "--no-verify" => parsed.verify = false,
+            other if other.starts_with("--") => bail!("unknown argument '{other}'"),
+            _ => path = Some(arg),
+        }
+    }
+
+    parsed.path = path.ok_or_else(|| {
+        anyhow!(
+            "usage: backfill_meter_usage_zip <zip_file_path> [--members GLOB] [--format csv|dat|ndjson] \
+             [--on-conflict append|skip|overwrite|fail | --exactly-once] [--no-verify]"
+        )
+    })?;
+    Ok(parsed)
+}
@@ -0,0 +1,111 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        clock_drift::{self, DEFAULT_BASELINE_DAYS, DEFAULT_LOOKBACK_DAYS, DEFAULT_THRESHOLD_SECS},
+        job_runs,
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Estimate per-`source_system` clock offset and drift into `source_clock_drift`.
+///
+/// Usage:
+///   clock_drift [--date YYYY-MM-DD] [--threshold-secs N] [--baseline-days N] [--lookback-days N]
+///
+/// Defaults to yesterday (UTC). Run once a day after `ingest_source_stats`; each
+/// day is compared with the stored offsets of the previous `--baseline-days`.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/04_ingest_quality.sql` for the tables used by the job.
+    let (results, written) = job_runs::tracked(&pool, "clock_drift", async {
+        let results =
+            clock_drift::compute(&pool, args.day, args.lookback_days, args.baseline_days, args.threshold_secs).await?;
+        let written = clock_drift::store(&pool, args.day, &results).await?;
+        Ok::<_, sqlx::Error>((results, written))
+    })
+    .await?;
+    for (obs, assessment) in &results {
+        metrics::gauge!("source_clock_offset_seconds", "source_system" => obs.source_system.clone()).set(obs.offset_secs);
+        if assessment.alert {
+            metrics::counter!("source_clock_drift_alerts_total", "source_system" => obs.source_system.clone()).increment(1);
+            tracing::warn!(
+                source_system = %obs.source_system,
+                offset_secs = obs.offset_secs,
+                baseline_offset_secs = assessment.baseline_offset_secs,
+                drift_secs = assessment.drift_secs,
+                drift_rate_secs_per_day = assessment.drift_rate_secs_per_day,
+                future_records = obs.future_records,
+                "source clock drift exceeds threshold"
+            );
+        }
+    }
+
+    tracing::info!(
+        day = %args.day,
+        sources = results.len(),
+        alerts = results.iter().filter(|(_, a)| a.alert).count(),
+        written_rows = written,
+        "source_clock_drift computed"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+    threshold_secs: f64,
+    baseline_days: i64,
+    lookback_days: i64,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: (OffsetDateTime::now_utc() - Duration::days(1)).date(),
+        threshold_secs: DEFAULT_THRESHOLD_SECS,
+        baseline_days: DEFAULT_BASELINE_DAYS,
+        lookback_days: DEFAULT_LOOKBACK_DAYS,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--threshold-secs" => {
+                parsed.threshold_secs = value()?.parse()?;
+                if parsed.threshold_secs <= 0.0 {
+                    bail!("--threshold-secs must be positive");
+                }
+            }
+            "--baseline-days" => {
+                parsed.baseline_days = value()?.parse()?;
+                if parsed.baseline_days < 1 {
+                    bail!("--baseline-days must be at least 1");
+                }
+            }
+            "--lookback-days" => {
+                parsed.lookback_days = value()?.parse()?;
+                if parsed.lookback_days < 1 {
+                    bail!("--lookback-days must be at least 1");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
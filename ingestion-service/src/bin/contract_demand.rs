@@ -0,0 +1,84 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{contract_demand, job_runs},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Flag key accounts whose measured demand exceeded their contract demand.
+///
+/// Usage:
+///   contract_demand [--date YYYY-MM-DD]
+///
+/// Defaults to yesterday (UTC). Evaluates the month up to the end of the day
+/// into `contract_demand_exceedances` (runs of intervals above contract) and
+/// `contract_demand_monthly`; re-running replaces earlier results.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/05_demand_response.sql` for the tables used by the job.
+    let (evaluation, written) = job_runs::tracked(&pool, "contract_demand", async {
+        let evaluation = contract_demand::evaluate(&pool, args.day).await?;
+        let written = contract_demand::store(&pool, OffsetDateTime::now_utc(), &evaluation).await?;
+        Ok::<_, sqlx::Error>((evaluation, written))
+    })
+    .await?;
+
+    let day_start = args.day.midnight().assume_utc();
+    for e in evaluation.exceedances.iter().filter(|e| e.end > day_start) {
+        metrics::counter!("contract_demand_exceedances_total", "customer_id" => e.customer_id.clone()).increment(1);
+        tracing::warn!(
+            customer_id = %e.customer_id,
+            start = %e.start,
+            duration_mins = e.duration().whole_minutes(),
+            unit = %e.unit,
+            contract_demand = e.contract_demand,
+            max_demand = e.max_demand,
+            max_excess_pct = e.max_excess_pct(),
+            "contract demand exceeded"
+        );
+    }
+
+    tracing::info!(
+        day = %args.day,
+        accounts_exceeded = evaluation.monthly.len(),
+        exceedances = evaluation.exceedances.len(),
+        written_rows = written,
+        "contract demand evaluated"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: (OffsetDateTime::now_utc() - Duration::days(1)).date(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
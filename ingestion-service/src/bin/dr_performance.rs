@@ -0,0 +1,93 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        dr_performance::{self, DEFAULT_BASELINE_DAYS},
+        job_runs,
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Evaluate demand response events into `dr_event_performance`.
+///
+/// Usage:
+///   dr_performance [--from YYYY-MM-DD] [--to YYYY-MM-DD] [--baseline-days N]
+///
+/// Evaluates events that started in `[from, to)` (default: the last 7 days) and
+/// have already ended.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/05_demand_response.sql` for the tables used by the job.
+    let written = job_runs::tracked(
+        &pool,
+        "dr_performance",
+        dr_performance::run(&pool, args.from, args.to, args.baseline_days),
+    )
+    .await?;
+
+    tracing::info!(
+        from = %args.from,
+        to = %args.to,
+        baseline_days = args.baseline_days,
+        written_rows = written,
+        "dr_event_performance computed"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    baseline_days: u32,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let parse_day = |s: &str| -> Result<OffsetDateTime> {
+        Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))?
+            .midnight()
+            .assume_utc())
+    };
+
+    let now = OffsetDateTime::now_utc();
+    let mut parsed = Args {
+        from: now - Duration::days(7),
+        to: now,
+        baseline_days: DEFAULT_BASELINE_DAYS,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--from" => parsed.from = parse_day(&value()?)?,
+            "--to" => parsed.to = parse_day(&value()?)?,
+            "--baseline-days" => {
+                parsed.baseline_days = value()?.parse()?;
+                if parsed.baseline_days == 0 {
+                    bail!("--baseline-days must be at least 1");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    if parsed.from >= parsed.to {
+        bail!("--from must be before --to");
+    }
+
+    Ok(parsed)
+}
@@ -1,13 +1,38 @@
-use anyhow::Result;
-use ingestion_service::{config::AppConfig, observability};
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        feeder_balance::{self, FeederBalanceOptions},
+        job_runs, loss_uncertainty,
+    },
+    observability,
+};
 use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime};
 
-const LOSS_ALERT_THRESHOLD: f64 = 0.02; // > 2% triggers alert
-
+/// Recompute `feeder_energy_balance`, or a what-if balance under an
+/// alternative meter-to-feeder mapping.
+///
+/// Usage:
+///   feeder_balance [--min-completeness <0..1>] [--incomplete mark|skip]
+///   feeder_balance --scenario NAME --from DATE --to DATE [--min-completeness <0..1>] [--incomplete mark|skip]
+///
+/// Intervals where fewer than `--min-completeness` of the feeder's mapped meters
+/// reported are either marked incomplete (loss values nulled, no alert) or skipped.
+/// With `--scenario`, meters are mapped by that scenario of
+/// `meter_feeder_map_scenarios` and the results of `[from, to)` are written to
+/// `feeder_energy_balance_scenarios`; production tables are left untouched.
+///
+/// With a `[loss_uncertainty]` section, a full recompute is followed by the
+/// Monte Carlo pass that writes `feeder_loss_uncertainty`.
 #[tokio::main]
 async fn main() -> Result<()> {
     observability::init_tracing();
 
+    let args = parse_args(env::args().skip(1))?;
+    let opts = args.opts;
+
     let cfg = AppConfig::load()?;
 
     let pool = PgPoolOptions::new()
@@ -16,129 +41,101 @@ async fn main() -> Result<()> {
         .await?;
 
     // Schema is expected to be applied out-of-band via `sql/schema/*.sql`.
-    // See `sql/schema/03_mapping_tables.sql` for the tables referenced below.
-
-    // For now, recompute the entire feeder_energy_balance table from scratch.
-    sqlx::query("TRUNCATE TABLE feeder_energy_balance;")
-        .execute(&pool)
+    // See `sql/schema/03_mapping_tables.sql` for the tables referenced by the job.
+    if let Some(scenario) = &args.scenario {
+        let (Some(from), Some(to)) = (args.from, args.to) else {
+            bail!("--scenario needs --from and --to");
+        };
+        let run = job_runs::tracked(
+            &pool,
+            "feeder_balance_scenario",
+            feeder_balance::run_scenario(&pool, &opts, scenario, from, to, OffsetDateTime::now_utc()),
+        )
         .await?;
+        tracing::info!(
+            scenario = %scenario,
+            computed_at = %run.computed_at,
+            inserted_rows = run.rows,
+            "scenario feeder balance computed"
+        );
+        return Ok(());
+    }
+    if args.from.is_some() || args.to.is_some() {
+        bail!("--from and --to only apply to --scenario runs");
+    }
 
-    // Insert feeder-level balance with alert flag when |loss_pct| > threshold.
-    let insert_sql = r#"
-        INSERT INTO feeder_energy_balance
-        SELECT
-            g.ts,
-            g.feeder_id,
-            g.feeder_kwh_gen,
-            COALESCE(d.feeder_kwh_demand, 0)                                       AS feeder_kwh_demand,
-            g.feeder_kwh_gen - COALESCE(d.feeder_kwh_demand, 0)                   AS loss_kwh,
-            CASE WHEN g.feeder_kwh_gen = 0 THEN NULL
-                 ELSE (g.feeder_kwh_gen - COALESCE(d.feeder_kwh_demand, 0)) / g.feeder_kwh_gen
-            END                                                                   AS loss_pct,
-            COALESCE(c.meter_coverage_pct, 1.0)                                   AS meter_coverage_pct,
-            CASE
-                WHEN c.meter_coverage_pct IS NULL THEN 1.0
-                ELSE c.meter_coverage_pct
-            END                                                                   AS data_quality_score,
-            CASE
-                WHEN g.feeder_kwh_gen = 0 THEN 'unknown'
-                WHEN c.meter_coverage_pct IS NOT NULL AND c.meter_coverage_pct < 0.9 THEN 'data'
-                WHEN t.topology_events > 0 THEN 'topology'
-                WHEN th.theft_events > 0 AND (c.meter_coverage_pct IS NULL OR c.meter_coverage_pct >= 0.9) THEN 'theft'
-                WHEN g.feeder_kwh_gen > 0
-                     AND ABS((g.feeder_kwh_gen - COALESCE(d.feeder_kwh_demand, 0)) / g.feeder_kwh_gen) <= 0.05
-                     THEN 'physics'
-                ELSE 'unknown'
-            END                                                                   AS cause_hint,
-            CASE
-                WHEN g.feeder_kwh_gen = 0 THEN FALSE
-                WHEN ABS((g.feeder_kwh_gen - COALESCE(d.feeder_kwh_demand, 0)) / g.feeder_kwh_gen) > $1
-                    THEN TRUE
-                ELSE FALSE
-            END                                                                   AS alert
-        FROM (
-            SELECT
-                go.ts,
-                pfm.feeder_id,
-                SUM(go.mw) * 0.25 AS feeder_kwh_gen            -- assume 15-min intervals
-            FROM generation_output go
-            JOIN plant_feeder_map pfm
-              ON pfm.plant_id = go.plant_id
-             AND (pfm.unit_id IS NULL OR pfm.unit_id = go.unit_id)
-             AND pfm.from_ts <= go.ts
-             AND pfm.to_ts   >  go.ts
-            GROUP BY go.ts, pfm.feeder_id
-        ) g
-        LEFT JOIN (
-            SELECT
-                mu.ts,
-                mfm.feeder_id,
-                SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS feeder_kwh_demand
-            FROM meter_usage mu
-            JOIN meter_feeder_map mfm
-              ON mfm.meter_id = mu.meter_id
-             AND mfm.from_ts <= mu.ts
-             AND mfm.to_ts   >  mu.ts
-            LEFT JOIN meter_scale_map msm
-              ON msm.meter_id = mu.meter_id
-             AND msm.from_ts <= mu.ts
-             AND msm.to_ts   >  mu.ts
-            GROUP BY mu.ts, mfm.feeder_id
-        ) d
-          ON d.ts = g.ts
-         AND d.feeder_id = g.feeder_id
-        LEFT JOIN (
-            SELECT
-                mfm.feeder_id,
-                mu.ts,
-                COUNT(DISTINCT mu.meter_id) * 1.0 / NULLIF(COUNT(DISTINCT mfm.meter_id), 0) AS meter_coverage_pct
-            FROM meter_feeder_map mfm
-            LEFT JOIN meter_usage mu
-              ON mu.meter_id = mfm.meter_id
-             AND mu.ts      >= mfm.from_ts
-             AND mu.ts      <  mfm.to_ts
-            GROUP BY mfm.feeder_id, mu.ts
-        ) c
-          ON c.ts = g.ts
-         AND c.feeder_id = g.feeder_id
-        LEFT JOIN (
-            SELECT
-                feeder_id,
-                ts,
-                COUNT(*) AS topology_events
-            FROM topology_events
-            GROUP BY feeder_id, ts
-        ) t
-          ON t.ts = g.ts
-         AND t.feeder_id = g.feeder_id
-        LEFT JOIN (
-            SELECT
-                mfm.feeder_id,
-                me.ts,
-                COUNT(*) AS theft_events
-            FROM meter_events me
-            JOIN meter_feeder_map mfm
-              ON mfm.meter_id = me.meter_id
-             AND mfm.from_ts <= me.ts
-             AND mfm.to_ts   >  me.ts
-            WHERE me.event_type IN ('tamper', 'reverse_run', 'magnetic', 'theft_suspect')
-            GROUP BY mfm.feeder_id, me.ts
-        ) th
-          ON th.ts = g.ts
-         AND th.feeder_id = g.feeder_id;
-        "#;
+    let inserted = job_runs::tracked(&pool, "feeder_balance", feeder_balance::recompute(&pool, &opts)).await?;
 
-    let result = sqlx::query(insert_sql)
-        .bind(LOSS_ALERT_THRESHOLD)
-        .execute(&pool)
-        .await?;
-
-    let inserted = result.rows_affected();
     tracing::info!(
         inserted_rows = inserted,
-        loss_alert_threshold = LOSS_ALERT_THRESHOLD,
+        loss_alert_threshold = opts.loss_alert_threshold,
+        min_completeness = opts.min_completeness,
+        incomplete_policy = ?opts.incomplete_policy,
         "feeder_energy_balance recomputed"
     );
 
+    if let Some(uncertainty) = &cfg.loss_uncertainty {
+        let inserted = job_runs::tracked(
+            &pool,
+            "feeder_loss_uncertainty",
+            loss_uncertainty::recompute(&pool, uncertainty, &opts),
+        )
+        .await?;
+        tracing::info!(
+            inserted_rows = inserted,
+            samples = uncertainty.samples,
+            confidence = uncertainty.confidence,
+            "feeder_loss_uncertainty recomputed"
+        );
+    }
+
     Ok(())
-}
\ No newline at end of file
+}
+
+struct Args {
+    opts: FeederBalanceOptions,
+    scenario: Option<String>,
+    from: Option<OffsetDateTime>,
+    to: Option<OffsetDateTime>,
+}
+
+fn parse_ts(s: &str) -> Result<OffsetDateTime> {
+    if let Ok(ts) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(ts);
+    }
+    Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{s}' is neither YYYY-MM-DD nor an RFC 3339 timestamp"))?
+        .midnight()
+        .assume_utc())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut opts = FeederBalanceOptions::default();
+    let mut scenario = None;
+    let mut from = None;
+    let mut to = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--min-completeness" => {
+                opts.min_completeness = value()?.parse()?;
+                if !(0.0..=1.0).contains(&opts.min_completeness) {
+                    bail!("--min-completeness must be within [0, 1]");
+                }
+            }
+            "--incomplete" => opts.incomplete_policy = value()?.parse().map_err(|e: String| anyhow!(e))?,
+            "--scenario" => scenario = Some(value()?),
+            "--from" => from = Some(parse_ts(&value()?)?),
+            "--to" => to = Some(parse_ts(&value()?)?),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(Args {
+        opts,
+        scenario,
+        from,
+        to,
+    })
+}
@@ -0,0 +1,81 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        forecast_accuracy::{self, DEFAULT_DAYS},
+        job_runs,
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Score `load_forecast` against actual feeder demand into `forecast_accuracy`.
+///
+/// Usage:
+///   forecast_accuracy [--date YYYY-MM-DD] [--days N]
+///
+/// Re-scores the `--days` days up to and including `--date` (default today,
+/// UTC), so intervals whose actuals arrived since the previous run are counted.
+/// Run after `feeder_balance`, e.g. hourly.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/08_forecasting.sql` for the tables used by the job.
+    let first = args.day - Duration::days(args.days - 1);
+    let written = job_runs::tracked(&pool, "forecast_accuracy", async {
+        let mut written = 0;
+        for offset in 0..args.days {
+            let day = first + Duration::days(offset);
+            let results = forecast_accuracy::score(&forecast_accuracy::pairs(&pool, day).await?);
+            let rows = forecast_accuracy::store(&pool, day, &results).await?;
+            tracing::info!(%day, groups = results.len(), written_rows = rows, "forecast day scored");
+            written += rows;
+        }
+        Ok::<_, sqlx::Error>(written)
+    })
+    .await?;
+
+    tracing::info!(from = %first, to = %args.day, written_rows = written, "forecast_accuracy computed");
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+    days: i64,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: OffsetDateTime::now_utc().date(),
+        days: DEFAULT_DAYS,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--days" => {
+                parsed.days = value()?.parse()?;
+                if parsed.days < 1 {
+                    bail!("--days must be at least 1");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    metrics_server,
+    observability,
+    pipeline::Pipeline,
+    sinks::QuestDbSink,
+    sources::DirectoryWatchSource,
+    transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{sync::Arc, time::Duration};
+
+/// Continuously ingest `meter_usage` files dropped into the folder under `[directory_source]`.
+///
+/// NDJSON (`.ndjson`, `.jsonl`), CSV (`.csv`) and DAT (`.dat`) files in the
+/// backfill formats are loaded through the pgwire sink using the
+/// `[meter_usage.sink]` settings and then moved to the done (or quarantine)
+/// folder, so the job can be restarted at any time.
+///
+/// Usage:
+///   ingest_dir
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let cfg = AppConfig::load()?;
+    let Some(dir_cfg) = cfg.directory_source.clone() else {
+        bail!("missing [directory_source] section in config");
+    };
+
+    if let Some(metrics_cfg) = &cfg.metrics {
+        metrics_server::init(metrics_cfg)?;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mu_cfg = &cfg.meter_usage;
+    let sink = QuestDbSink::new(
+        pool,
+        mu_cfg.sink.batch_size,
+        mu_cfg.sink.max_retries,
+        Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+    )
+    .with_event_id(mu_cfg.sink.event_id);
+
+    let source = DirectoryWatchSource::from_config(&dir_cfg)?;
+    tracing::info!(dir = %dir_cfg.dir.display(), "watching drop folder for meter usage files");
+
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink,
+    };
+    pipeline.run().await?;
+
+    Ok(())
+}
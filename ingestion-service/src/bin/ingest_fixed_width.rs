@@ -0,0 +1,52 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig, observability, pipeline::Pipeline, sinks::QuestDbSink, sources::FixedWidthFileSource, transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{env, sync::Arc, time::Duration};
+
+/// Load a fixed-width text file (e.g. a mainframe billing extract) into
+/// `meter_usage`.
+///
+/// The column layout comes from `[meter_usage.fixed_width]`. Rows go through
+/// the pgwire sink using the `[meter_usage.sink]` settings.
+///
+/// Usage:
+///   ingest_fixed_width <path_to_file>
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(file_path) = args.iter().find(|a| !a.starts_with("--")) else {
+        bail!("usage: ingest_fixed_width <file_path>");
+    };
+
+    let cfg = AppConfig::load()?;
+    let mu_cfg = &cfg.meter_usage;
+    let layout = mu_cfg
+        .fixed_width
+        .as_ref()
+        .ok_or_else(|| anyhow!("no [meter_usage.fixed_width] section in the configuration"))?;
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source: FixedWidthFileSource::new(file_path, layout),
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink: QuestDbSink::new(
+            pool,
+            mu_cfg.sink.batch_size,
+            mu_cfg.sink.max_retries,
+            Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+        )
+        .with_event_id(mu_cfg.sink.event_id),
+    };
+    pipeline.run().await?;
+    tracing::info!(file = %file_path, "fixed-width file loaded");
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig, observability, pipeline::Pipeline, sinks::QuestDbSink, sources::GreenButtonSource, transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{env, sync::Arc, time::Duration};
+
+/// Load a Green Button (ESPI Atom/XML) export into `meter_usage`.
+///
+/// Records are keyed by the file's usage point ids, or by `--meter-id` for a
+/// file holding a single usage point. Rows go through the pgwire sink using
+/// the `[meter_usage.sink]` settings.
+///
+/// Usage:
+///   ingest_green_button <path_to_xml> [--meter-id ID]
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mut source = GreenButtonSource::new(&args.path);
+    if let Some(meter_id) = &args.meter_id {
+        source = source.with_meter_id(meter_id);
+    }
+
+    let mu_cfg = &cfg.meter_usage;
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink: QuestDbSink::new(
+            pool,
+            mu_cfg.sink.batch_size,
+            mu_cfg.sink.max_retries,
+            Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+        )
+        .with_event_id(mu_cfg.sink.event_id),
+    };
+    pipeline.run().await?;
+    tracing::info!(file = %args.path, "Green Button file loaded");
+
+    Ok(())
+}
+
+struct Args {
+    path: String,
+    meter_id: Option<String>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut path = None;
+    let mut meter_id = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--meter-id" => meter_id = Some(value()?),
+            other if other.starts_with("--") => bail!("unknown argument '{other}'"),
+            _ => path = Some(arg),
+        }
+    }
+
+    let path = path.ok_or_else(|| anyhow!("usage: ingest_green_button <xml_file_path> [--meter-id ID]"))?;
+    Ok(Args { path, meter_id })
+}
@@ -0,0 +1,48 @@
+use anyhow::{bail, Result};
+use ingestion_service::{
+    config::AppConfig, observability, pipeline::Pipeline, sinks::QuestDbSink, sources::ItronMdmFileSource, transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{env, sync::Arc, time::Duration};
+
+/// Load an Itron IEE/MDM interval export into `meter_usage`.
+///
+/// Unit of measure and status codes beyond the built-in ones are set in
+/// `[meter_usage.itron_mdm]`. Rows go through the pgwire sink using the
+/// `[meter_usage.sink]` settings.
+///
+/// Usage:
+///   ingest_itron_mdm <path_to_export>
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(file_path) = args.iter().find(|a| !a.starts_with("--")) else {
+        bail!("usage: ingest_itron_mdm <export_file_path>");
+    };
+
+    let cfg = AppConfig::load()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mu_cfg = &cfg.meter_usage;
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source: ItronMdmFileSource::new(file_path, &mu_cfg.itron_mdm.clone().unwrap_or_default()),
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink: QuestDbSink::new(
+            pool,
+            mu_cfg.sink.batch_size,
+            mu_cfg.sink.max_retries,
+            Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+        )
+        .with_event_id(mu_cfg.sink.event_id),
+    };
+    pipeline.run().await?;
+    tracing::info!(file = %file_path, "Itron MDM export loaded");
+
+    Ok(())
+}
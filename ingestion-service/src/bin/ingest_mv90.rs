@@ -0,0 +1,48 @@
+use anyhow::{bail, Result};
+use ingestion_service::{
+    config::AppConfig, observability, pipeline::Pipeline, sinks::QuestDbSink, sources::Mv90FileSource, transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{env, sync::Arc, time::Duration};
+
+/// Load an MV-90 HHF interval file into `meter_usage`.
+///
+/// Channels are mapped with `[meter_usage.mv90]` (channel 1 as kWh and
+/// channel 2 as kVArh if unset). Rows go through the pgwire sink using the
+/// `[meter_usage.sink]` settings.
+///
+/// Usage:
+///   ingest_mv90 <path_to_hhf>
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some(file_path) = args.iter().find(|a| !a.starts_with("--")) else {
+        bail!("usage: ingest_mv90 <hhf_file_path>");
+    };
+
+    let cfg = AppConfig::load()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mu_cfg = &cfg.meter_usage;
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source: Mv90FileSource::new(file_path, &mu_cfg.mv90.clone().unwrap_or_default()),
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink: QuestDbSink::new(
+            pool,
+            mu_cfg.sink.batch_size,
+            mu_cfg.sink.max_retries,
+            Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+        )
+        .with_event_id(mu_cfg.sink.event_id),
+    };
+    pipeline.run().await?;
+    tracing::info!(file = %file_path, "MV-90 file loaded");
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use anyhow::{bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    metrics_server,
+    observability,
+    pipeline::Pipeline,
+    sinks::QuestDbSink,
+    sources::S3FileSource,
+    transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{sync::Arc, time::Duration};
+
+/// Continuously ingest `meter_usage` bulk files dropped into S3 under `[s3_source]`.
+///
+/// NDJSON (`.ndjson`, `.jsonl`), CSV (`.csv`) and DAT (`.dat`) objects in the backfill
+/// formats are loaded through the pgwire sink using the `[meter_usage.sink]`
+/// settings. Processed keys are tracked in `s3_ingested_objects`, so the job can
+/// be restarted at any time.
+///
+/// Usage:
+///   ingest_s3
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let cfg = AppConfig::load()?;
+    let Some(s3_cfg) = cfg.s3_source.clone() else {
+        bail!("missing [s3_source] section in config");
+    };
+
+    if let Some(metrics_cfg) = &cfg.metrics {
+        metrics_server::init(metrics_cfg)?;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mu_cfg = &cfg.meter_usage;
+    let sink = QuestDbSink::new(
+        pool.clone(),
+        mu_cfg.sink.batch_size,
+        mu_cfg.sink.max_retries,
+        Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+    )
+    .with_event_id(mu_cfg.sink.event_id);
+
+    // See `sql/schema/04_ingest_quality.sql` for `s3_ingested_objects`.
+    let source = S3FileSource::from_config(&s3_cfg, pool)?;
+    tracing::info!(bucket = %s3_cfg.bucket, prefix = ?s3_cfg.prefix, "polling S3 for meter usage files");
+
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink,
+    };
+    pipeline.run().await?;
+
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use anyhow::{bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    metrics_server,
+    observability,
+    pipeline::Pipeline,
+    sinks::QuestDbSink,
+    sources::SftpDirectorySource,
+    transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{sync::Arc, time::Duration};
+
+/// Continuously ingest vendor `meter_usage` files from the SFTP directory under `[sftp_source]`.
+///
+/// `.csv` and pipe-separated `.dat` files with the CSV backfill columns, and
+/// NDJSON backfill files, are loaded through the pgwire sink using the `[meter_usage.sink]` settings and
+/// then moved to `archive_dir` on the server, so the job can be restarted at
+/// any time.
+///
+/// Usage:
+///   ingest_sftp
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let cfg = AppConfig::load()?;
+    let Some(sftp_cfg) = cfg.sftp_source.clone() else {
+        bail!("missing [sftp_source] section in config");
+    };
+
+    if let Some(metrics_cfg) = &cfg.metrics {
+        metrics_server::init(metrics_cfg)?;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mu_cfg = &cfg.meter_usage;
+    let sink = QuestDbSink::new(
+        pool,
+        mu_cfg.sink.batch_size,
+        mu_cfg.sink.max_retries,
+        Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+    )
+    .with_event_id(mu_cfg.sink.event_id);
+
+    let source = SftpDirectorySource::from_config(&sftp_cfg)?;
+    tracing::info!(host = %sftp_cfg.host, dir = %sftp_cfg.remote_dir, "polling SFTP for meter usage files");
+
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(transform::MeterUsageValidation)],
+        sink,
+    };
+    pipeline.run().await?;
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        ingest_source_stats::{self, DEFAULT_LOOKBACK_DAYS},
+        job_runs,
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Compute per-`source_system` ingestion statistics into `ingest_source_stats`.
+///
+/// Usage:
+///   ingest_source_stats [--date YYYY-MM-DD] [--lookback-days N]
+///
+/// Defaults to yesterday (UTC). Intended to run once a day; re-running a day
+/// replaces its rows.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let (day, lookback_days) = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/04_ingest_quality.sql` for the tables used by the job.
+    let (stats, written) = job_runs::tracked(&pool, "ingest_source_stats", async {
+        let stats = ingest_source_stats::compute(&pool, day, lookback_days).await?;
+        let written = ingest_source_stats::store(&pool, day, &stats).await?;
+        Ok::<_, sqlx::Error>((stats, written))
+    })
+    .await?;
+
+    tracing::info!(%day, sources = stats.len(), written_rows = written, "ingest_source_stats computed");
+
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Date, i64)> {
+    let mut day = (OffsetDateTime::now_utc() - Duration::days(1)).date();
+    let mut lookback_days = DEFAULT_LOOKBACK_DAYS;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--lookback-days" => {
+                lookback_days = value()?.parse()?;
+                if lookback_days < 1 {
+                    bail!("--lookback-days must be at least 1");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok((day, lookback_days))
+}
@@ -0,0 +1,99 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        job_runs,
+        multiplier_changes::{self, DetectionOptions},
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Flag steps in meter usage that look like unrecorded CT/PT multiplier changes,
+/// into `meter_multiplier_candidates`.
+///
+/// Usage:
+///   multiplier_changes [--date YYYY-MM-DD] [--lookback-days N] [--window-days N] [--tolerance X]
+///
+/// Searches the `--lookback-days` up to `--date`, by default yesterday (UTC).
+/// Run once a day; a change already flagged keeps its row.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let (candidates, written) = job_runs::tracked(&pool, "multiplier_changes", async {
+        let candidates = multiplier_changes::compute(&pool, args.day, &args.opts).await?;
+        let written = multiplier_changes::store(&pool, &candidates, OffsetDateTime::now_utc()).await?;
+        Ok::<_, sqlx::Error>((candidates, written))
+    })
+    .await?;
+    for c in &candidates {
+        metrics::counter!("meter_multiplier_candidates_total").increment(1);
+        tracing::warn!(
+            meter_id = %c.meter_id,
+            change_day = %c.change_day,
+            before_kwh = c.before_kwh,
+            after_kwh = c.after_kwh,
+            inferred_ratio = c.inferred_ratio,
+            "usage step consistent with a multiplier change"
+        );
+    }
+
+    tracing::info!(
+        day = %args.day,
+        candidates = candidates.len(),
+        written_rows = written,
+        "meter multiplier changes searched"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+    opts: DetectionOptions,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: (OffsetDateTime::now_utc() - Duration::days(1)).date(),
+        opts: DetectionOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--lookback-days" => parsed.opts.lookback_days = value()?.parse()?,
+            "--window-days" => {
+                parsed.opts.window_days = value()?.parse()?;
+                if parsed.opts.window_days < 3 {
+                    bail!("--window-days must be at least 3");
+                }
+            }
+            "--tolerance" => {
+                parsed.opts.ratio_tolerance = value()?.parse()?;
+                if !(0.0..0.5).contains(&parsed.opts.ratio_tolerance) {
+                    bail!("--tolerance must be in [0, 0.5)");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+    if parsed.opts.lookback_days < 2 * parsed.opts.window_days as i64 {
+        bail!("--lookback-days must cover two --window-days");
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,77 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, ops_report},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::{env, path::PathBuf};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Render the daily operations report and deliver it as configured in `[report]`.
+///
+/// Usage:
+///   ops_report [--date YYYY-MM-DD] [--out-dir DIR] [--print]
+///
+/// Defaults to yesterday (UTC). `--out-dir` overrides `report.out_dir`; without
+/// any destination configured (or with `--print`) the CSV is written to stdout.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let mut report_cfg = cfg.report.unwrap_or_default();
+    if args.out_dir.is_some() {
+        report_cfg.out_dir = args.out_dir;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let report = job_runs::tracked(&pool, "ops_report", async {
+        // See `sql/schema/04_ingest_quality.sql` for the tables read by the job.
+        let report = ops_report::compute(&pool, args.day, OffsetDateTime::now_utc(), &report_cfg).await?;
+        ops_report::deliver(&report_cfg, &report).await?;
+        anyhow::Ok(report)
+    })
+    .await?;
+
+    let nowhere = report_cfg.out_dir.is_none() && report_cfg.webhook_url.is_none() && report_cfg.smtp.is_none();
+    if args.print || nowhere {
+        print!("{}", ops_report::render_csv(&report));
+    }
+
+    tracing::info!(day = %args.day, issues = report.issues(), alerts = report.alerts.len(), "ops report generated");
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+    out_dir: Option<PathBuf>,
+    print: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: (OffsetDateTime::now_utc() - Duration::days(1)).date(),
+        out_dir: None,
+        print: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--out-dir" => parsed.out_dir = Some(PathBuf::from(value()?)),
+            "--print" => parsed.print = true,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
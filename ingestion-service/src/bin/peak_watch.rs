@@ -0,0 +1,91 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        job_runs,
+        peak_watch::{self, PeakWatchOptions},
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// Flag system and feeder demand tracking toward a new monthly or annual peak.
+///
+/// Usage:
+///   peak_watch [--at RFC3339] [--threshold <0..1>] [--window-intervals N]
+///
+/// Meant to run every interval (e.g. from cron every 15 minutes). New alerts are
+/// appended to `peak_alerts` and logged; an alert already raised today is not
+/// raised again.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/05_demand_response.sql` for the tables used by the job.
+    let new = job_runs::tracked(&pool, "peak_watch", async {
+        let alerts = peak_watch::evaluate(&pool, args.at, &args.opts).await?;
+        peak_watch::record_new(&pool, args.at, alerts).await
+    })
+    .await?;
+    for a in &new {
+        tracing::warn!(
+            scope = %a.scope,
+            scope_id = %a.scope_id,
+            level = %a.level,
+            status = %a.status,
+            max_kw = a.check.max_kw,
+            projected_kw = a.check.projected_kw,
+            peak_kw = a.check.peak_kw,
+            "demand peak alert"
+        );
+    }
+
+    tracing::info!(at = %args.at, new_alerts = new.len(), "peak watch evaluated");
+
+    Ok(())
+}
+
+struct Args {
+    at: OffsetDateTime,
+    opts: PeakWatchOptions,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        at: OffsetDateTime::now_utc(),
+        opts: PeakWatchOptions::default(),
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--at" => parsed.at = OffsetDateTime::parse(&value()?, &Rfc3339)?,
+            "--threshold" => {
+                parsed.opts.threshold = value()?.parse()?;
+                if !(0.0..=1.0).contains(&parsed.opts.threshold) {
+                    bail!("--threshold must be within [0, 1]");
+                }
+            }
+            "--window-intervals" => {
+                parsed.opts.window_intervals = value()?.parse()?;
+                if parsed.opts.window_intervals == 0 {
+                    bail!("--window-intervals must be at least 1");
+                }
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,64 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, premise_usage},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Recompute `premise_usage`: interval usage per premise, with the meters of
+/// multi-meter premises combined per `premise_meter_config`.
+///
+/// Usage:
+///   premise_usage [--from DATE --to DATE]
+///
+/// Covers the days `[from, to)`, by default yesterday (UTC). Run it after the
+/// day's meter data is in, before segment and billing analytics; `reaggregate`
+/// recomputes it after corrections.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let (from, to) = match parse_args(env::args().skip(1))? {
+        (None, None) => {
+            let today = OffsetDateTime::now_utc().date().midnight().assume_utc();
+            (today - Duration::DAY, today)
+        }
+        (Some(from), Some(to)) if from < to => (from, to),
+        (Some(_), Some(_)) => bail!("--from must be before --to"),
+        _ => bail!("use both --from and --to, or neither"),
+    };
+
+    let cfg = AppConfig::load()?;
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let inserted = job_runs::tracked(&pool, "premise_usage", premise_usage::recompute_range(&pool, from, to)).await?;
+    tracing::info!(%from, %to, inserted_rows = inserted, "premise_usage recomputed");
+
+    Ok(())
+}
+
+fn parse_date(s: &str) -> Result<OffsetDateTime> {
+    Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{s}' is not a YYYY-MM-DD date"))?
+        .midnight()
+        .assume_utc())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Option<OffsetDateTime>, Option<OffsetDateTime>)> {
+    let (mut from, mut to) = (None, None);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--from" => from = Some(parse_date(&value()?)?),
+            "--to" => to = Some(parse_date(&value()?)?),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+    Ok((from, to))
+}
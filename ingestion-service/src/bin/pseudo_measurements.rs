@@ -0,0 +1,74 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, pseudo_measurements},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Write pseudo-measurements of the loads in `unmetered_loads` to
+/// `meter_usage`, configured in `[pseudo_measurements]`.
+///
+/// Usage:
+///   pseudo_measurements [--from DATE --to DATE]
+///
+/// Covers the days `[from, to)`, by default yesterday (UTC). Re-running a day
+/// replaces its readings. Run it before `feeder_balance`, which reports the
+/// readings as `feeder_kwh_unmetered`.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let (from, to) = match parse_args(env::args().skip(1))? {
+        (None, None) => {
+            let today = OffsetDateTime::now_utc().date().midnight().assume_utc();
+            (today - Duration::DAY, today)
+        }
+        (Some(from), Some(to)) if from < to => (from, to),
+        (Some(_), Some(_)) => bail!("--from must be before --to"),
+        _ => bail!("use both --from and --to, or neither"),
+    };
+
+    let cfg = AppConfig::load()?;
+    let pseudo_cfg = cfg
+        .pseudo_measurements
+        .as_ref()
+        .ok_or_else(|| anyhow!("no [pseudo_measurements] section in the configuration"))?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let written = job_runs::tracked(
+        &pool,
+        "pseudo_measurements",
+        pseudo_measurements::run(&pool, pseudo_cfg, from, to),
+    )
+    .await?;
+    tracing::info!(%from, %to, readings = written, "pseudo-measurements written");
+
+    Ok(())
+}
+
+fn parse_date(s: &str) -> Result<OffsetDateTime> {
+    Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{s}' is not a YYYY-MM-DD date"))?
+        .midnight()
+        .assume_utc())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<(Option<OffsetDateTime>, Option<OffsetDateTime>)> {
+    let (mut from, mut to) = (None, None);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--from" => from = Some(parse_date(&value()?)?),
+            "--to" => to = Some(parse_date(&value()?)?),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+    Ok((from, to))
+}
@@ -0,0 +1,151 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        dr_performance::DEFAULT_BASELINE_DAYS,
+        feeder_balance::FeederBalanceOptions,
+        job_runs,
+        reaggregate::{self, CorrectionScope, ReaggregateOptions},
+        rollups::{self, RollupMode},
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime};
+
+/// Recompute the derived tables after corrections to `meter_usage` or `generation_output`.
+///
+/// Usage:
+///   reaggregate --from <date|RFC3339> --to <date|RFC3339> [--meter <id>]... [--feeder <id>]...
+///               [--manual] [--baseline-days N] [--min-completeness <0..1>]
+///               [--incomplete mark|skip] [--dry-run]
+///
+/// `--meter` and `--feeder` name what was corrected; they select the stale tables
+/// (all of them if neither is given). Rollups and `feeder_energy_balance` are
+/// rebuilt over whole partitions, then `dr_event_performance` is re-evaluated.
+/// `--dry-run` prints the plan without touching the database.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+    let steps = reaggregate::plan(&args.scope, args.baseline_days)?;
+    if args.dry_run {
+        for step in &steps {
+            println!("{step}");
+        }
+        return Ok(());
+    }
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let version = rollups::detect_server_version(&pool).await;
+    let rollup_mode = if args.force_manual {
+        RollupMode::ManualTable
+    } else {
+        RollupMode::for_version(version)
+    };
+    tracing::info!(server_version = ?version, mode = ?rollup_mode, "rollup mode selected");
+
+    let opts = ReaggregateOptions {
+        rollup_mode,
+        feeder_balance: args.feeder_balance,
+        baseline_days: args.baseline_days,
+        loss_uncertainty: cfg.loss_uncertainty.clone(),
+    };
+    job_runs::tracked(&pool, "reaggregate", reaggregate::execute(&pool, &steps, &opts)).await?;
+
+    tracing::info!(
+        from = %args.scope.from,
+        to = %args.scope.to,
+        meters = args.scope.meter_ids.len(),
+        feeders = args.scope.feeder_ids.len(),
+        steps = steps.len(),
+        "derived tables recomputed"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    scope: CorrectionScope,
+    force_manual: bool,
+    baseline_days: u32,
+    feeder_balance: FeederBalanceOptions,
+    dry_run: bool,
+}
+
+fn parse_ts(s: &str) -> Result<OffsetDateTime> {
+    if let Ok(ts) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(ts);
+    }
+    Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{s}' is neither YYYY-MM-DD nor an RFC 3339 timestamp"))?
+        .midnight()
+        .assume_utc())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut from = None;
+    let mut to = None;
+    let mut meter_ids = Vec::new();
+    let mut feeder_ids = Vec::new();
+    let mut force_manual = false;
+    let mut baseline_days = DEFAULT_BASELINE_DAYS;
+    let mut feeder_balance = FeederBalanceOptions::default();
+    let mut dry_run = false;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--from" => from = Some(parse_ts(&value()?)?),
+            "--to" => to = Some(parse_ts(&value()?)?),
+            "--meter" => meter_ids.push(value()?),
+            "--feeder" => feeder_ids.push(value()?),
+            "--manual" => force_manual = true,
+            "--baseline-days" => {
+                baseline_days = value()?.parse()?;
+                if baseline_days == 0 {
+                    bail!("--baseline-days must be at least 1");
+                }
+            }
+            "--min-completeness" => {
+                feeder_balance.min_completeness = value()?.parse()?;
+                if !(0.0..=1.0).contains(&feeder_balance.min_completeness) {
+                    bail!("--min-completeness must be within [0, 1]");
+                }
+            }
+            "--incomplete" => {
+                feeder_balance.incomplete_policy = value()?.parse().map_err(|e: String| anyhow!(e))?
+            }
+            "--dry-run" => dry_run = true,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    let (Some(from), Some(to)) = (from, to) else {
+        bail!("--from and --to are required");
+    };
+    if from >= to {
+        bail!("--from must be before --to");
+    }
+
+    Ok(Args {
+        scope: CorrectionScope {
+            from,
+            to,
+            meter_ids,
+            feeder_ids,
+        },
+        force_manual,
+        baseline_days,
+        feeder_balance,
+        dry_run,
+    })
+}
@@ -0,0 +1,109 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, regulatory_export},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::{env, path::PathBuf};
+use time::{Month, OffsetDateTime};
+
+/// Write the monthly regulatory reports configured in `[regulatory_export]`.
+///
+/// Usage:
+///   regulatory_export [--month YYYY-MM] [--report NAME] [--out-dir DIR]
+///
+/// Defaults to the previous month (UTC) and every configured report. Sales come
+/// from the `meter_usage_1d` rollup, generation from `generation_output_1h`;
+/// re-running replaces the month's files.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let export_cfg = cfg
+        .regulatory_export
+        .ok_or_else(|| anyhow!("no [regulatory_export] section in the configuration"))?;
+    let out_dir = args.out_dir.unwrap_or(export_cfg.out_dir);
+    let reports: Vec<_> = export_cfg
+        .reports
+        .iter()
+        .filter(|r| args.report.as_ref().is_none_or(|name| &r.name == name))
+        .collect();
+    if reports.is_empty() {
+        bail!(
+            "no report named '{}' in [regulatory_export]",
+            args.report.unwrap_or_default()
+        );
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let written = job_runs::tracked(&pool, "regulatory_export", async {
+        let agg = regulatory_export::aggregates(&pool, args.year, args.month).await?;
+        // Render every report before writing any, so a mapping error leaves no partial filing set.
+        let rendered = reports
+            .iter()
+            .map(|r| {
+                let body = regulatory_export::render(r, &agg).map_err(|e| anyhow!(e))?;
+                Ok((regulatory_export::file_name(r, args.year, args.month), body))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let mut written = Vec::new();
+        for (name, body) in rendered {
+            written.push(regulatory_export::write_report(&out_dir, &name, &body).await?);
+        }
+        anyhow::Ok(written)
+    })
+    .await?;
+
+    for path in &written {
+        tracing::info!(year = args.year, month = %args.month, file = %path.display(), "regulatory report written");
+    }
+
+    Ok(())
+}
+
+struct Args {
+    year: i32,
+    month: Month,
+    report: Option<String>,
+    out_dir: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let today = OffsetDateTime::now_utc().date();
+    let mut parsed = Args {
+        year: if today.month() == Month::January {
+            today.year() - 1
+        } else {
+            today.year()
+        },
+        month: today.month().previous(),
+        report: None,
+        out_dir: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--month" => {
+                let v = value()?;
+                let (year, month) = v.split_once('-').ok_or_else(|| anyhow!("--month must be YYYY-MM"))?;
+                parsed.year = year.parse().with_context(|| format!("invalid year in --month {v}"))?;
+                let month: u8 = month.parse().with_context(|| format!("invalid month in --month {v}"))?;
+                parsed.month = Month::try_from(month).with_context(|| format!("invalid month in --month {v}"))?;
+            }
+            "--report" => parsed.report = Some(value()?),
+            "--out-dir" => parsed.out_dir = Some(PathBuf::from(value()?)),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
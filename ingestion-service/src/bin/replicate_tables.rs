@@ -0,0 +1,126 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::{AppConfig, ReplicationConfig},
+    jobs::rollups::detect_server_version,
+    metrics_server,
+    observability,
+    pipeline::Pipeline,
+    sinks::questdb_ilp::{IlpEncode, IlpProtocolVersion, QuestDbIlpParallelSink, ShardKey},
+    sources::{
+        questdb_replication::{committed_watermark, ReplicatedTable, REPLICATED_EVENT_IDS},
+        QuestDbReplicationSource,
+    },
+};
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use std::{net::SocketAddr, time::Duration};
+use tokio::task::JoinSet;
+
+/// Incrementally copy tables from the primary QuestDB (`questdb.uri`) to a
+/// DR/reporting QuestDB configured under `[replication]`.
+///
+/// Each table resumes from its committed watermark in `replication_watermarks`
+/// on the target, which only advances past rows every sink shard wrote, so
+/// the job can be restarted at any time. Rows are re-written via ILP with the
+/// `event_id` read from the primary (`[replication.sink] event_id` is not
+/// used), so an overlapping restart stays dedupable.
+///
+/// Usage:
+///   replicate_tables
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let cfg = AppConfig::load()?;
+    let Some(repl_cfg) = cfg.replication.clone() else {
+        bail!("missing [replication] section in config");
+    };
+
+    if let Some(metrics_cfg) = &cfg.metrics {
+        metrics_server::init(metrics_cfg)?;
+    }
+
+    let primary = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+    let target = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&repl_cfg.target_uri)
+        .await?;
+
+    let target_ilp: SocketAddr = repl_cfg
+        .target_ilp_tcp_addr
+        .parse()
+        .map_err(|e| anyhow!("invalid replication.target_ilp_tcp_addr: {e}"))?;
+
+    let mut jobs = JoinSet::new();
+    for table in &repl_cfg.tables {
+        match table.as_str() {
+            "meter_usage" => jobs.spawn(replicate::<MeterUsage>(
+                primary.clone(),
+                target.clone(),
+                target_ilp,
+                repl_cfg.clone(),
+            )),
+            "generation_output" => jobs.spawn(replicate::<GenerationOutput>(
+                primary.clone(),
+                target.clone(),
+                target_ilp,
+                repl_cfg.clone(),
+            )),
+            other => bail!("unsupported replication table '{other}'"),
+        };
+    }
+
+    // Tables only stop replicating on errors; exit on the first one so the job
+    // is restarted from the committed watermarks.
+    while let Some(job) = jobs.join_next().await {
+        job??;
+    }
+
+    Ok(())
+}
+
+async fn replicate<T>(primary: PgPool, target: PgPool, target_ilp: SocketAddr, cfg: ReplicationConfig) -> Result<()>
+where
+    T: ReplicatedTable + IlpEncode + ShardKey + Sync,
+{
+    let start_after = committed_watermark::<T>(&target).await?;
+    tracing::info!(table = T::TABLE, start_after = ?start_after, "starting table replication");
+
+    let source = QuestDbReplicationSource::<T>::new(
+        primary,
+        start_after,
+        Duration::from_secs(cfg.window_secs),
+        Duration::from_secs(cfg.max_lateness_secs),
+        Duration::from_millis(cfg.poll_interval_ms),
+    )
+    .with_committed_watermarks(target.clone());
+
+    let sink = QuestDbIlpParallelSink::<T>::new(
+        target_ilp,
+        cfg.sink.batch_size,
+        cfg.sink.max_retries,
+        Duration::from_millis(cfg.sink.retry_backoff_ms),
+        Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        cfg.sink.workers,
+    )
+    .with_ordering(cfg.sink.ordering)
+    .with_protocol(IlpProtocolVersion::resolve(
+        cfg.sink.ilp_protocol,
+        detect_server_version(&target).await,
+    ))
+    .with_event_id(REPLICATED_EVENT_IDS);
+
+    let pipeline: Pipeline<_, T, _> = Pipeline {
+        source,
+        transforms: vec![],
+        sink,
+    };
+
+    pipeline.run().await?;
+
+    // The source only ends after rows were lost, once the committed watermark can't advance.
+    bail!("replication of {} stopped; restart to resume from the committed watermark", T::TABLE)
+}
@@ -0,0 +1,90 @@
+use anyhow::{anyhow, bail, Context, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, research_export},
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::{env, path::PathBuf};
+use time::{Month, OffsetDateTime};
+
+/// Write the de-identified meter usage dataset of a month, configured in
+/// `[research_export]`, as Parquet.
+///
+/// Usage:
+///   research_export [--month YYYY-MM] [--out-dir DIR]
+///
+/// Defaults to the previous month (UTC); run it monthly from cron. Re-running
+/// replaces the month's file.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let export_cfg = cfg
+        .research_export
+        .ok_or_else(|| anyhow!("no [research_export] section in the configuration"))?;
+    let out_dir = args.out_dir.unwrap_or_else(|| export_cfg.out_dir.clone());
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let summary = job_runs::tracked(
+        &pool,
+        "research_export",
+        research_export::export(&pool, &export_cfg, args.year, args.month, &out_dir),
+    )
+    .await?;
+
+    tracing::info!(
+        year = args.year,
+        month = %args.month,
+        file = %summary.path.display(),
+        rows = summary.rows,
+        suppressed_groups = summary.suppressed_groups,
+        suppressed_meters = summary.suppressed_meters,
+        "research dataset written"
+    );
+
+    Ok(())
+}
+
+struct Args {
+    year: i32,
+    month: Month,
+    out_dir: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let today = OffsetDateTime::now_utc().date();
+    let mut parsed = Args {
+        year: if today.month() == Month::January {
+            today.year() - 1
+        } else {
+            today.year()
+        },
+        month: today.month().previous(),
+        out_dir: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--month" => {
+                let v = value()?;
+                let (year, month) = v.split_once('-').ok_or_else(|| anyhow!("--month must be YYYY-MM"))?;
+                parsed.year = year.parse().with_context(|| format!("invalid year in --month {v}"))?;
+                let month: u8 = month.parse().with_context(|| format!("invalid month in --month {v}"))?;
+                parsed.month = Month::try_from(month).with_context(|| format!("invalid month in --month {v}"))?;
+            }
+            "--out-dir" => parsed.out_dir = Some(PathBuf::from(value()?)),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,124 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::annotations::{Annotation, AnnotationWriter},
+    observability,
+    pipeline::Pipeline,
+    sinks::QuestDbSink,
+    sources::S3ArchiveSource,
+    transform,
+};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPoolOptions;
+use std::{env, sync::Arc, time::Duration};
+use time::{format_description::well_known::Rfc3339, macros::format_description, Date, OffsetDateTime};
+
+/// Re-ingest archived `meter_usage` records of a time range from `[archive]`
+/// object storage, e.g. for the raw intervals of an old billing dispute.
+///
+/// Usage:
+///   restore_archive --from <date|RFC 3339> --to <date|RFC 3339> [--prefix <prefix>] [--allow-duplicates]
+///
+/// Records with `ts` in `[from, to)` are written through the pgwire sink with
+/// the `[meter_usage.sink]` settings. Unless `--allow-duplicates` is given,
+/// `meter_usage` must deduplicate on `event_id`, so records still in the table
+/// and re-runs don't add rows.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let Some(archive_cfg) = cfg.archive.clone() else {
+        bail!("missing [archive] section in config");
+    };
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let mut source = S3ArchiveSource::from_config(&archive_cfg, args.from, args.to)?;
+    if let Some(prefix) = &args.prefix {
+        source = source.with_prefix(prefix);
+    }
+
+    let annotations = AnnotationWriter::new(pool.clone(), "restore_archive");
+    let start =
+        Annotation::new("restore", format!("restore meter_usage {} to {}", args.from, args.to)).with_tag("meter_usage");
+    annotations
+        .around(start, async {
+            let mu_cfg = &cfg.meter_usage;
+            let sink = QuestDbSink::new(
+                pool.clone(),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+            )
+            .with_event_id(mu_cfg.sink.event_id);
+            if !args.allow_duplicates {
+                sink.store().require_event_id_dedup("meter_usage").await?;
+            }
+
+            tracing::info!(
+                bucket = %archive_cfg.bucket,
+                prefix = ?args.prefix.as_ref().or(archive_cfg.prefix.as_ref()),
+                from = %args.from,
+                to = %args.to,
+                "restoring meter usage from archive"
+            );
+            let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+                source,
+                transforms: vec![Arc::new(transform::MeterUsageValidation)],
+                sink,
+            };
+            pipeline.run().await?;
+
+            anyhow::Ok(())
+        })
+        .await
+}
+
+struct Args {
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    prefix: Option<String>,
+    allow_duplicates: bool,
+}
+
+/// A UTC day (its midnight) or an RFC 3339 timestamp.
+fn parse_time(value: &str) -> Result<OffsetDateTime> {
+    if let Ok(day) = Date::parse(value, format_description!("[year]-[month]-[day]")) {
+        return Ok(day.midnight().assume_utc());
+    }
+    OffsetDateTime::parse(value, &Rfc3339).map_err(|e| anyhow!("invalid time '{value}': {e}"))
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let (mut from, mut to, mut prefix, mut allow_duplicates) = (None, None, None, false);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--from" => from = Some(parse_time(&value()?)?),
+            "--to" => to = Some(parse_time(&value()?)?),
+            "--prefix" => prefix = Some(value()?),
+            "--allow-duplicates" => allow_duplicates = true,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+    let (Some(from), Some(to)) = (from, to) else {
+        bail!("usage: restore_archive --from <date|RFC 3339> --to <date|RFC 3339> [--prefix <prefix>] [--allow-duplicates]");
+    };
+    if from >= to {
+        bail!("--from must be before --to");
+    }
+
+    Ok(Args {
+        from,
+        to,
+        prefix,
+        allow_duplicates,
+    })
+}
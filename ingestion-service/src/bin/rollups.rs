@@ -0,0 +1,66 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        job_runs,
+        rollups::{self, RollupMode},
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+
+/// Create or refresh the rollup tables (`meter_usage_1h`, `meter_usage_1d`, `generation_output_1h`).
+///
+/// On QuestDB versions with materialized views the rollups are views;
+/// otherwise they are plain tables rebuilt by `refresh`.
+///
+/// Usage:
+///   rollups apply [--manual]
+///   rollups refresh [--table <base_table>] [--manual]
+///
+/// `--manual` forces the plain-table fallback regardless of server version.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let mut args = env::args().skip(1);
+    let Some(command) = args.next() else {
+        bail!("usage: rollups <apply|refresh> [--table <base_table>] [--manual]");
+    };
+
+    let mut force_manual = false;
+    let mut base_table: Option<String> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--manual" => force_manual = true,
+            "--table" => base_table = Some(args.next().ok_or_else(|| anyhow!("missing value for --table"))?),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let version = rollups::detect_server_version(&pool).await;
+    let mode = if force_manual {
+        RollupMode::ManualTable
+    } else {
+        RollupMode::for_version(version)
+    };
+    tracing::info!(server_version = ?version, mode = ?mode, "rollup mode selected");
+
+    match command.as_str() {
+        "apply" => rollups::apply(&pool, mode).await?,
+        "refresh" => {
+            job_runs::tracked(&pool, "rollups_refresh", rollups::refresh(&pool, mode, base_table.as_deref())).await?
+        }
+        other => bail!("unknown command '{other}' (expected apply|refresh)"),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        job_runs,
+        settlement::{self, SettlementPeriod},
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{Month, OffsetDateTime};
+
+/// Snapshot a closed billing month into the `settled_*` tables.
+///
+/// Usage:
+///   settlement_freeze [--month YYYY-MM]
+///
+/// Defaults to the previous month (UTC). A month is frozen once per table;
+/// re-running only completes tables an interrupted run didn't get to.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let now = OffsetDateTime::now_utc();
+    let period = parse_args(env::args().skip(1), now)?;
+
+    let cfg = AppConfig::load()?;
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    // See `sql/schema/07_settlement.sql` for the tables used by the job.
+    for frozen in job_runs::tracked(&pool, "settlement_freeze", settlement::freeze(&pool, period, now)).await? {
+        tracing::info!(
+            table = frozen.table,
+            period_start = %period.start,
+            frozen_at = %frozen.frozen_at,
+            rows = frozen.rows,
+            "settlement snapshot"
+        );
+    }
+
+    Ok(())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>, now: OffsetDateTime) -> Result<SettlementPeriod> {
+    let mut period = SettlementPeriod::previous_month(now);
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--month" => {
+                let value = value()?;
+                let (year, month) = value
+                    .split_once('-')
+                    .ok_or_else(|| anyhow!("--month must be YYYY-MM, got '{value}'"))?;
+                let month = Month::try_from(month.parse::<u8>()?)?;
+                period = SettlementPeriod::month(year.parse()?, month).map_err(|e| anyhow!(e))?;
+            }
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(period)
+}
@@ -0,0 +1,41 @@
+use anyhow::{bail, Result};
+use ingestion_service::{config::AppConfig, jobs::ndjson_shipper::NdjsonShipper, metrics_server, observability};
+use std::env;
+
+/// Forward NDJSON files spooled by `file` sinks to the central HTTP ingest
+/// endpoints configured under `[shipper]`.
+///
+/// Usage:
+///   ship_ndjson [--once]
+///
+/// Runs until stopped, checking the spool directory every `poll_interval_secs`.
+/// With `--once`, ships what is there and exits non-zero if anything failed.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let once = match env::args().nth(1).as_deref() {
+        None => false,
+        Some("--once") => true,
+        Some(other) => bail!("unknown argument '{other}'"),
+    };
+
+    let cfg = AppConfig::load()?;
+    let Some(shipper_cfg) = cfg.shipper.clone() else {
+        bail!("missing [shipper] section in config");
+    };
+
+    if let Some(metrics_cfg) = &cfg.metrics {
+        metrics_server::init(metrics_cfg)?;
+    }
+
+    let shipper = NdjsonShipper::new(shipper_cfg);
+    if once {
+        let files = shipper.ship_pending().await?;
+        tracing::info!(files, "shipped spooled NDJSON files");
+    } else {
+        shipper.run().await;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{job_runs, state_estimation},
+    metrics_server, observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::env;
+use time::{format_description::well_known::Rfc3339, macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Write feeder head and per-node load snapshots for the distribution state
+/// estimator, configured in `[state_estimation_export]`.
+///
+/// Usage:
+///   state_estimation_export [--once]
+///   state_estimation_export --from DATE --to DATE
+///
+/// Runs until stopped, writing a snapshot every `cadence_minutes`,
+/// `lag_minutes` after its window ends. With `--once`, writes the latest
+/// window that is old enough and exits. With `--from`/`--to`, rewrites the
+/// snapshots of the windows within `[from, to)`.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    let export_cfg = cfg
+        .state_estimation_export
+        .clone()
+        .ok_or_else(|| anyhow!("no [state_estimation_export] section in the configuration"))?;
+    state_estimation::validate(&export_cfg).map_err(|e| anyhow!("[state_estimation_export]: {e}"))?;
+    let cadence = Duration::minutes(i64::from(export_cfg.cadence_minutes));
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    match (args.once, args.from, args.to) {
+        (false, None, None) => {
+            if let Some(metrics_cfg) = &cfg.metrics {
+                metrics_server::init(metrics_cfg)?;
+            }
+            state_estimation::run(&pool, &export_cfg).await;
+        }
+        (true, None, None) => {
+            let lag = Duration::minutes(i64::from(export_cfg.lag_minutes));
+            let end = state_estimation::latest_window_end(OffsetDateTime::now_utc(), cadence, lag);
+            let path = job_runs::tracked(
+                &pool,
+                "state_estimation_export",
+                state_estimation::export(&pool, &export_cfg, end),
+            )
+            .await?;
+            tracing::info!(snapshot = %end, file = %path.display(), "state estimation input written");
+        }
+        (false, Some(from), Some(to)) => {
+            if from >= to {
+                bail!("--from must be before --to");
+            }
+            // Windows are aligned to the cadence; start with the first one starting at or after `from`.
+            let mut start = state_estimation::latest_window_end(from, cadence, Duration::ZERO);
+            if start < from {
+                start += cadence;
+            }
+            let mut end = start + cadence;
+            let mut written = 0;
+            while end <= to {
+                state_estimation::export(&pool, &export_cfg, end).await?;
+                written += 1;
+                end += cadence;
+            }
+            tracing::info!(snapshots = written, "state estimation inputs rewritten");
+        }
+        _ => bail!("use either --once or both --from and --to"),
+    }
+
+    Ok(())
+}
+
+struct Args {
+    once: bool,
+    from: Option<OffsetDateTime>,
+    to: Option<OffsetDateTime>,
+}
+
+fn parse_ts(s: &str) -> Result<OffsetDateTime> {
+    if let Ok(ts) = OffsetDateTime::parse(s, &Rfc3339) {
+        return Ok(ts);
+    }
+    Ok(Date::parse(s, format_description!("[year]-[month]-[day]"))
+        .map_err(|_| anyhow!("'{s}' is neither YYYY-MM-DD nor an RFC 3339 timestamp"))?
+        .midnight()
+        .assume_utc())
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        once: false,
+        from: None,
+        to: None,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--once" => parsed.once = true,
+            "--from" => parsed.from = Some(parse_ts(&value()?)?),
+            "--to" => parsed.to = Some(parse_ts(&value()?)?),
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,137 @@
+use anyhow::{anyhow, bail, Result};
+use ingestion_service::{
+    config::AppConfig,
+    jobs::{
+        dr_performance::DEFAULT_BASELINE_DAYS,
+        feeder_balance::FeederBalanceOptions,
+        job_runs,
+        reaggregate::{self, ReaggregateOptions},
+        rollups::{self, RollupMode},
+        triggers,
+    },
+    observability,
+};
+use sqlx::postgres::PgPoolOptions;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    env,
+};
+use time::{macros::format_description, Date, Duration, OffsetDateTime};
+
+/// Recompute derived tables for days whose meter data became complete (`[[triggers]]`).
+///
+/// Usage:
+///   triggers [--date YYYY-MM-DD] [--dry-run]
+///
+/// Checks `--date` (default yesterday, UTC) and the `lookback_days` before it.
+/// The per-feeder completeness of days a trigger still waits for is recorded in
+/// `feeder_completeness`; once every feeder of such a day reaches the trigger's
+/// `min_feeder_completeness`, the trigger's table and the tables built on it are
+/// recomputed for that day. Each trigger fires once per day (see `job_triggers`).
+/// `--dry-run` prints the steps that would run. Run it from cron every few minutes.
+#[tokio::main]
+async fn main() -> Result<()> {
+    observability::init_tracing();
+
+    let args = parse_args(env::args().skip(1))?;
+
+    let cfg = AppConfig::load()?;
+    if cfg.triggers.is_empty() {
+        bail!("no [[triggers]] configured");
+    }
+    for trigger in &cfg.triggers {
+        triggers::plan(&trigger.table, args.day, DEFAULT_BASELINE_DAYS)
+            .map_err(|e| anyhow!("trigger '{}': {e}", trigger.name))?;
+    }
+
+    let pool = PgPoolOptions::new()
+        .max_connections(cfg.questdb.max_connections)
+        .connect(&cfg.questdb.uri)
+        .await?;
+
+    let opts = ReaggregateOptions {
+        rollup_mode: RollupMode::for_version(rollups::detect_server_version(&pool).await),
+        feeder_balance: FeederBalanceOptions::default(),
+        baseline_days: DEFAULT_BASELINE_DAYS,
+        loss_uncertainty: cfg.loss_uncertainty.clone(),
+    };
+
+    // See `sql/schema/04_ingest_quality.sql` for the tables used by the job.
+    let fired = job_runs::tracked(&pool, "triggers", async {
+        let mut completeness = HashMap::new();
+        let mut fired = 0;
+        for trigger in &cfg.triggers {
+            let first = args.day - Duration::days(i64::from(trigger.lookback_days));
+            let done = triggers::fired_days(&pool, &trigger.name, first).await?;
+
+            for offset in 0..=i64::from(trigger.lookback_days) {
+                let day = first + Duration::days(offset);
+                if done.contains(&day) {
+                    continue;
+                }
+
+                let rows = match completeness.entry(day) {
+                    Entry::Occupied(e) => e.into_mut(),
+                    Entry::Vacant(e) => {
+                        let rows = triggers::feeder_completeness(&pool, day).await?;
+                        if !args.dry_run {
+                            triggers::store_completeness(&pool, day, &rows).await?;
+                        }
+                        e.insert(rows)
+                    }
+                };
+                let Some(lowest) = triggers::ready(rows, trigger.min_feeder_completeness) else {
+                    continue;
+                };
+
+                let steps = triggers::plan(&trigger.table, day, opts.baseline_days)?;
+                if args.dry_run {
+                    for step in &steps {
+                        println!("{} {day}: {step}", trigger.name);
+                    }
+                    continue;
+                }
+                reaggregate::execute(&pool, &steps, &opts).await?;
+                triggers::record_fired(&pool, &trigger.name, day, lowest, steps.len()).await?;
+                metrics::counter!("job_triggers_fired_total", "trigger" => trigger.name.clone()).increment(1);
+                tracing::info!(
+                    trigger = %trigger.name,
+                    day = %day,
+                    min_completeness = lowest,
+                    steps = steps.len(),
+                    "trigger fired"
+                );
+                fired += 1;
+            }
+        }
+        anyhow::Ok(fired)
+    })
+    .await?;
+
+    tracing::info!(day = %args.day, triggers = cfg.triggers.len(), fired, "triggers evaluated");
+
+    Ok(())
+}
+
+struct Args {
+    day: Date,
+    dry_run: bool,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Args> {
+    let mut parsed = Args {
+        day: (OffsetDateTime::now_utc() - Duration::days(1)).date(),
+        dry_run: false,
+    };
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| anyhow!("missing value for {arg}"));
+        match arg.as_str() {
+            "--date" => parsed.day = Date::parse(&value()?, format_description!("[year]-[month]-[day]"))?,
+            "--dry-run" => parsed.dry_run = true,
+            other => bail!("unknown argument '{other}'"),
+        }
+    }
+
+    Ok(parsed)
+}
@@ -0,0 +1,132 @@
+use std::{fmt::Display, future::Future};
+
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+/// An operational event for Grafana dashboards, written to `ops_annotations`.
+///
+/// A point in time, or a region when `time_end` is set. `kind` is a short
+/// category (`deploy`, `backfill`, `maintenance`, `pipeline_pause`, ...) and is
+/// also added to the tags, so dashboards can filter on it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Annotation {
+    pub ts: OffsetDateTime,
+    pub time_end: Option<OffsetDateTime>,
+    pub kind: String,
+    pub text: String,
+    pub tags: Vec<String>,
+}
+
+impl Annotation {
+    pub fn new(kind: impl Into<String>, text: impl Into<String>) -> Self {
+        Self {
+            ts: OffsetDateTime::now_utc(),
+            time_end: None,
+            kind: kind.into(),
+            text: text.into(),
+            tags: Vec::new(),
+        }
+    }
+
+    pub fn with_ts(mut self, ts: OffsetDateTime) -> Self {
+        self.ts = ts;
+        self
+    }
+
+    /// Make this a region ending at `time_end`.
+    pub fn with_end(mut self, time_end: OffsetDateTime) -> Self {
+        self.time_end = Some(time_end);
+        self
+    }
+
+    pub fn with_tag(mut self, tag: impl Into<String>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Tags as Grafana reads them from a `tags` column: comma-separated, kind first, no duplicates.
+    pub fn tags_column(&self) -> String {
+        let mut tags: Vec<&str> = vec![&self.kind];
+        for tag in &self.tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        tags.iter().map(|t| t.replace(',', " ")).collect::<Vec<_>>().join(",")
+    }
+}
+
+/// Writes [`Annotation`]s over pgwire, recording `source` (the writing binary or host) with each.
+#[derive(Clone)]
+pub struct AnnotationWriter {
+    pool: PgPool,
+    source: String,
+}
+
+impl AnnotationWriter {
+    pub fn new(pool: PgPool, source: impl Into<String>) -> Self {
+        Self {
+            pool,
+            source: source.into(),
+        }
+    }
+
+    pub async fn write(&self, a: &Annotation) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT INTO ops_annotations (ts, time_end, kind, text, tags, source) VALUES ($1, $2, $3, $4, $5, $6)",
+        )
+        .bind(a.ts)
+        .bind(a.time_end)
+        .bind(&a.kind)
+        .bind(&a.text)
+        .bind(a.tags_column())
+        .bind(&self.source)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Write `a`, logging instead of returning a failure: annotations must not
+    /// fail the operation they describe.
+    pub async fn write_logged(&self, a: &Annotation) {
+        if let Err(e) = self.write(a).await {
+            tracing::warn!(kind = %a.kind, error = %e, "failed to write ops annotation");
+        }
+    }
+
+    /// Annotate the start of `fut`, then the whole run as a region once it
+    /// finishes, with "finished" or "failed: <error>" appended to the text.
+    pub async fn around<T, E: Display>(&self, start: Annotation, fut: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+        let started = start.ts;
+        let mut finish = start.clone();
+        self.write_logged(&start.with_tag("start")).await;
+
+        let result = fut.await;
+
+        finish.text = match &result {
+            Ok(_) => format!("{} finished", finish.text),
+            Err(e) => format!("{} failed: {e}", finish.text),
+        };
+        let outcome = if result.is_ok() { "ok" } else { "failed" };
+        self.write_logged(&finish.with_ts(started).with_end(OffsetDateTime::now_utc()).with_tag(outcome))
+            .await;
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_column_leads_with_the_kind() {
+        let a = Annotation::new("backfill", "backfill usage.csv")
+            .with_tag("meter_usage")
+            .with_tag(" backfill ")
+            .with_tag("")
+            .with_tag("a,b");
+        assert_eq!(a.tags_column(), "backfill,meter_usage,a b");
+        assert_eq!(a.time_end, None);
+    }
+}
@@ -0,0 +1,249 @@
+use std::{collections::HashMap, fmt};
+
+use futures::{StreamExt, TryStreamExt};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use crate::{
+    pipeline::{PipelineError, Source, Transform},
+    transform::MeterUsageValidation,
+};
+
+/// Order-independent checksum contribution of one row: `(ts, meter_id, kwh)`.
+///
+/// `ts` is taken at microsecond precision, as stored by QuestDB.
+pub fn row_checksum(ts: OffsetDateTime, meter_id: &str, kwh: f64) -> u64 {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&((ts.unix_timestamp_nanos() / 1000) as i64).to_le_bytes());
+    hasher.update(meter_id.as_bytes());
+    hasher.update(&[0]);
+    hasher.update(&kwh.to_bits().to_le_bytes());
+    let hash = hasher.finalize();
+    u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("8 bytes"))
+}
+
+/// Rows a backfill file should have produced: its valid records, one per
+/// `(ts, meter_id)` (the last one wins, as with upsert dedup).
+#[derive(Debug, Default)]
+pub struct FileDigest {
+    rows: HashMap<(i64, String), f64>,
+    from: Option<OffsetDateTime>,
+    to: Option<OffsetDateTime>,
+}
+
+impl FileDigest {
+    pub fn add(&mut self, m: &MeterUsage) {
+        let micros = (m.ts.unix_timestamp_nanos() / 1000) as i64;
+        self.rows.insert((micros, m.meter_id.clone()), m.kwh);
+        self.from = Some(self.from.map_or(m.ts, |f| f.min(m.ts)));
+        self.to = Some(self.to.map_or(m.ts, |t| t.max(m.ts)));
+    }
+
+    pub fn rows(&self) -> u64 {
+        self.rows.len() as u64
+    }
+
+    pub fn checksum(&self) -> u64 {
+        self.rows.iter().fold(0u64, |sum, ((micros, meter_id), kwh)| {
+            let ts = OffsetDateTime::from_unix_timestamp_nanos(i128::from(*micros) * 1000).expect("valid timestamp");
+            sum.wrapping_add(row_checksum(ts, meter_id, *kwh))
+        })
+    }
+
+    /// Time range covered by the file, inclusive.
+    pub fn range(&self) -> Option<(OffsetDateTime, OffsetDateTime)> {
+        self.from.zip(self.to)
+    }
+
+    fn contains(&self, ts: OffsetDateTime, meter_id: &str) -> bool {
+        let micros = (ts.unix_timestamp_nanos() / 1000) as i64;
+        self.rows.contains_key(&(micros, meter_id.to_string()))
+    }
+}
+
+/// Read `source` again and digest the records that pass validation.
+pub async fn digest_source<S: Source<MeterUsage>>(source: &S) -> Result<FileDigest, PipelineError> {
+    let mut digest = FileDigest::default();
+    let mut stream = source.stream().await;
+    while let Some(env) = stream.next().await {
+        if let Ok(env) = MeterUsageValidation.apply(env?).await {
+            digest.add(&env.payload);
+        }
+    }
+    Ok(digest)
+}
+
+/// What landed in `meter_usage` for the rows of a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LandedDigest {
+    pub rows: u64,
+    pub checksum: u64,
+}
+
+/// Count and checksum the `meter_usage` rows within the file's time range
+/// whose `(ts, meter_id)` appears in the file. Rows of other files in the same
+/// range are ignored; duplicates of the file's rows are counted.
+pub async fn landed(pool: &PgPool, digest: &FileDigest) -> Result<LandedDigest, sqlx::Error> {
+    let Some((from, to)) = digest.range() else {
+        return Ok(LandedDigest::default());
+    };
+    let mut rows = sqlx::query_as::<_, (OffsetDateTime, String, Option<f64>)>(
+        "SELECT ts, meter_id, kwh FROM meter_usage WHERE ts >= $1 AND ts <= $2",
+    )
+    .bind(from)
+    .bind(to)
+    .fetch(pool);
+
+    let mut out = LandedDigest::default();
+    while let Some((ts, meter_id, kwh)) = rows.try_next().await? {
+        if digest.contains(ts, &meter_id) {
+            out.rows += 1;
+            out.checksum = out
+                .checksum
+                .wrapping_add(row_checksum(ts, &meter_id, kwh.unwrap_or(f64::NAN)));
+        }
+    }
+    Ok(out)
+}
+
+/// Result of comparing a file with what landed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyStatus {
+    Ok,
+    /// Fewer rows landed than the file holds.
+    Missing,
+    /// More rows landed than the file holds (the table doesn't deduplicate them).
+    Duplicated,
+    /// Row counts match but values differ.
+    Mismatch,
+}
+
+impl fmt::Display for VerifyStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Ok => "ok",
+            Self::Missing => "missing",
+            Self::Duplicated => "duplicated",
+            Self::Mismatch => "mismatch",
+        })
+    }
+}
+
+/// Verification of one backfill file, as recorded in `backfill_runs`.
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub file: String,
+    /// Backfill mode (`plain`, `skip_existing`, `overwrite`, `fail_on_conflict`, `exactly_once`).
+    pub mode: &'static str,
+    pub expected_rows: u64,
+    pub expected_checksum: u64,
+    pub landed: LandedDigest,
+    pub range: Option<(OffsetDateTime, OffsetDateTime)>,
+}
+
+impl Verification {
+    pub fn status(&self) -> VerifyStatus {
+        match self.landed.rows.cmp(&self.expected_rows) {
+            std::cmp::Ordering::Less => VerifyStatus::Missing,
+            std::cmp::Ordering::Greater => VerifyStatus::Duplicated,
+            std::cmp::Ordering::Equal if self.landed.checksum != self.expected_checksum => VerifyStatus::Mismatch,
+            std::cmp::Ordering::Equal => VerifyStatus::Ok,
+        }
+    }
+}
+
+/// Digest `source` (the file just loaded), compare it with `meter_usage` and
+/// record the result in `backfill_runs`.
+pub async fn verify_and_record<S: Source<MeterUsage>>(
+    pool: &PgPool,
+    file: &str,
+    mode: &'static str,
+    source: &S,
+) -> anyhow::Result<Verification> {
+    let digest = digest_source(source).await?;
+    let verification = Verification {
+        file: file.to_string(),
+        mode,
+        expected_rows: digest.rows(),
+        expected_checksum: digest.checksum(),
+        landed: landed(pool, &digest).await?,
+        range: digest.range(),
+    };
+    record(pool, &verification).await?;
+    metrics::counter!("backfill_verifications_total", "status" => verification.status().to_string()).increment(1);
+    Ok(verification)
+}
+
+/// Append a verification to `backfill_runs`. Checksums are stored as their
+/// two's-complement `LONG`.
+pub async fn record(pool: &PgPool, v: &Verification) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO backfill_runs (ts, file, mode, range_from, range_to, expected_rows, landed_rows, \
+         expected_checksum, landed_checksum, status) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+    )
+    .bind(OffsetDateTime::now_utc())
+    .bind(&v.file)
+    .bind(v.mode)
+    .bind(v.range.map(|(from, _)| from))
+    .bind(v.range.map(|(_, to)| to))
+    .bind(v.expected_rows as i64)
+    .bind(v.landed.rows as i64)
+    .bind(v.expected_checksum as i64)
+    .bind(v.landed.checksum as i64)
+    .bind(v.status().to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn usage(ts: OffsetDateTime, meter_id: &str, kwh: f64) -> MeterUsage {
+        MeterUsage {
+            ts,
+            meter_id: meter_id.to_string(),
+            premise_id: None,
+            kwh,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: None,
+            event_id: None,
+            phases: Default::default(),
+        }
+    }
+
+    #[test]
+    fn digest_matches_rows_landed_in_any_order() {
+        let t1 = datetime!(2024-01-01 00:15:00.000001 UTC);
+        let t2 = datetime!(2024-01-01 00:30:00 UTC);
+        let mut digest = FileDigest::default();
+        digest.add(&usage(t2, "m-1", 2.0));
+        digest.add(&usage(t1, "m-1", 9.0));
+        digest.add(&usage(t1, "m-1", 1.0)); // replaces the earlier line, like upsert dedup
+
+        assert_eq!(digest.rows(), 2);
+        assert_eq!(digest.range(), Some((t1, t2)));
+        assert_eq!(
+            digest.checksum(),
+            row_checksum(t1, "m-1", 1.0).wrapping_add(row_checksum(t2, "m-1", 2.0))
+        );
+
+        let verification = |rows, checksum| Verification {
+            file: "usage.ndjson".to_string(),
+            mode: "plain",
+            expected_rows: digest.rows(),
+            expected_checksum: digest.checksum(),
+            landed: LandedDigest { rows, checksum },
+            range: digest.range(),
+        };
+        assert_eq!(verification(2, digest.checksum()).status(), VerifyStatus::Ok);
+        assert_eq!(verification(2, row_checksum(t1, "m-1", 9.0)).status(), VerifyStatus::Mismatch);
+        assert_eq!(verification(1, 0).status(), VerifyStatus::Missing);
+        assert_eq!(verification(3, 0).status(), VerifyStatus::Duplicated);
+    }
+}
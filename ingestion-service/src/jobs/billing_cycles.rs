@@ -0,0 +1,309 @@
+use std::collections::BTreeMap;
+
+use rust_client::db::{meter_exchanges_for_premise, stitch_meter_segments, MeterSegment};
+use sqlx::postgres::PgPool;
+use time::{Duration, OffsetDateTime};
+
+/// Calendar margin loaded around a requested range, so the periods
+/// overlapping its ends are complete.
+const CALENDAR_MARGIN_DAYS: i64 = 62;
+
+/// A billing period (read window) of a cycle: from one scheduled read to the
+/// next, `[start, end)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BillingPeriod {
+    pub cycle_id: String,
+    /// Set when the route has its own read schedule within the cycle.
+    pub route_id: Option<String>,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+}
+
+impl BillingPeriod {
+    pub fn length(&self) -> Duration {
+        self.end - self.start
+    }
+}
+
+/// Scheduled read dates per billing cycle and route, from `billing_cycle_reads`.
+///
+/// A route's own schedule takes precedence over its cycle's; routes without
+/// one are read on the cycle's dates.
+#[derive(Debug, Clone, Default)]
+pub struct BillingCalendar {
+    reads: BTreeMap<(String, Option<String>), Vec<OffsetDateTime>>,
+}
+
+impl BillingCalendar {
+    /// Build from `(read_date, cycle_id, route_id)` rows in any order.
+    pub fn from_reads(rows: impl IntoIterator<Item = (OffsetDateTime, String, Option<String>)>) -> Self {
+        let mut reads: BTreeMap<(String, Option<String>), Vec<OffsetDateTime>> = BTreeMap::new();
+        for (read_date, cycle_id, route_id) in rows {
+            reads.entry((cycle_id, route_id)).or_default().push(read_date);
+        }
+        for dates in reads.values_mut() {
+            dates.sort();
+            dates.dedup();
+        }
+        Self { reads }
+    }
+
+    /// Load the scheduled reads around `[from, to)`.
+    pub async fn load(pool: &PgPool, from: OffsetDateTime, to: OffsetDateTime) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(OffsetDateTime, String, Option<String>)> = sqlx::query_as(
+            "SELECT read_date, cycle_id, route_id FROM billing_cycle_reads WHERE read_date >= $1 AND read_date < $2",
+        )
+        .bind(from - Duration::days(CALENDAR_MARGIN_DAYS))
+        .bind(to + Duration::days(CALENDAR_MARGIN_DAYS))
+        .fetch_all(pool)
+        .await?;
+        Ok(Self::from_reads(rows))
+    }
+
+    /// The read dates for `route_id` within `cycle_id`, with the route they
+    /// belong to (`None` for the cycle's own schedule).
+    fn schedule(&self, cycle_id: &str, route_id: Option<&str>) -> Option<(Option<&str>, &[OffsetDateTime])> {
+        let own = route_id.and_then(|r| self.reads.get_key_value(&(cycle_id.to_string(), Some(r.to_string()))));
+        own.or_else(|| self.reads.get_key_value(&(cycle_id.to_string(), None)))
+            .map(|((_, route), dates)| (route.as_deref(), dates.as_slice()))
+    }
+
+    /// The period of `cycle_id` / `route_id` containing `ts`, if the calendar covers it.
+    pub fn period_containing(
+        &self,
+        cycle_id: &str,
+        route_id: Option<&str>,
+        ts: OffsetDateTime,
+    ) -> Option<BillingPeriod> {
+        let (route, dates) = self.schedule(cycle_id, route_id)?;
+        let next = dates.partition_point(|d| *d <= ts);
+        (next > 0 && next < dates.len()).then(|| BillingPeriod {
+            cycle_id: cycle_id.to_string(),
+            route_id: route.map(str::to_string),
+            start: dates[next - 1],
+            end: dates[next],
+        })
+    }
+
+    /// The periods of `cycle_id` / `route_id` that close within `[from, to)`,
+    /// i.e. the bills due for that range.
+    pub fn periods_closing(
+        &self,
+        cycle_id: &str,
+        route_id: Option<&str>,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+    ) -> Vec<BillingPeriod> {
+        let Some((route, dates)) = self.schedule(cycle_id, route_id) else {
+            return Vec::new();
+        };
+        dates
+            .windows(2)
+            .filter(|w| w[1] >= from && w[1] < to)
+            .map(|w| BillingPeriod {
+                cycle_id: cycle_id.to_string(),
+                route_id: route.map(str::to_string),
+                start: w[0],
+                end: w[1],
+            })
+            .collect()
+    }
+}
+
+/// A meter's part of a billing period.
+///
+/// Usage is summed over `[from, to)`; `share` is the fraction of the period
+/// the meter was installed, for prorating fixed and demand charges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProratedMeter {
+    pub meter_id: String,
+    pub from: OffsetDateTime,
+    pub to: OffsetDateTime,
+    pub share: f64,
+}
+
+/// Split `period` over the meters that served the premise during it.
+///
+/// `segments` come from [`stitch_meter_segments`]; a premise without
+/// exchanges passes a single unbounded segment. Shares of a fully covered
+/// period add up to 1.
+pub fn prorate(period: &BillingPeriod, segments: &[MeterSegment]) -> Vec<ProratedMeter> {
+    let length = period.length().as_seconds_f64();
+    segments
+        .iter()
+        .filter_map(|seg| {
+            let from = seg.from.map_or(period.start, |f| f.max(period.start));
+            let to = seg.to.map_or(period.end, |t| t.min(period.end));
+            (from < to).then(|| ProratedMeter {
+                meter_id: seg.meter_id.clone(),
+                from,
+                to,
+                share: if length > 0.0 {
+                    (to - from).as_seconds_f64() / length
+                } else {
+                    0.0
+                },
+            })
+        })
+        .collect()
+}
+
+/// Usage of one meter within a billing period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeriodUsage {
+    pub meter: ProratedMeter,
+    pub kwh: f64,
+    pub max_kva_demand: Option<f64>,
+}
+
+/// A premise's usage for `period`, per meter, stitched across meter exchanges.
+///
+/// `fallback_meter_id` is used for premises without recorded exchanges.
+pub async fn premise_period_usage(
+    pool: &PgPool,
+    premise_id: &str,
+    fallback_meter_id: &str,
+    period: &BillingPeriod,
+) -> anyhow::Result<Vec<PeriodUsage>> {
+    let exchanges = meter_exchanges_for_premise(pool, premise_id).await?;
+    let mut segments = stitch_meter_segments(&exchanges);
+    if segments.is_empty() {
+        segments.push(MeterSegment {
+            meter_id: fallback_meter_id.to_string(),
+            from: None,
+            to: None,
+        });
+    }
+
+    let mut out = Vec::new();
+    for meter in prorate(period, &segments) {
+        let (kwh, max_kva_demand): (Option<f64>, Option<f64>) = sqlx::query_as(
+            "SELECT sum(kwh), max(kva_demand) FROM meter_usage WHERE meter_id = $1 AND ts >= $2 AND ts < $3",
+        )
+        .bind(&meter.meter_id)
+        .bind(meter.from)
+        .bind(meter.to)
+        .fetch_one(pool)
+        .await?;
+        out.push(PeriodUsage {
+            meter,
+            kwh: kwh.unwrap_or(0.0),
+            max_kva_demand,
+        });
+    }
+    Ok(out)
+}
+
+/// Billing cycle and route of a premise at `ts`, from `premise_billing_cycles`.
+pub async fn premise_cycle(
+    pool: &PgPool,
+    premise_id: &str,
+    ts: OffsetDateTime,
+) -> Result<Option<(String, Option<String>)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT cycle_id, route_id FROM premise_billing_cycles \
+         WHERE premise_id = $1 AND effective_from <= $2 \
+         ORDER BY effective_from DESC LIMIT 1",
+    )
+    .bind(premise_id)
+    .bind(ts)
+    .fetch_optional(pool)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn calendar() -> BillingCalendar {
+        BillingCalendar::from_reads([
+            (datetime!(2024-02-07 00:00 UTC), "c07".to_string(), None),
+            (datetime!(2024-03-08 00:00 UTC), "c07".to_string(), None),
+            (datetime!(2024-01-08 00:00 UTC), "c07".to_string(), None),
+            (
+                datetime!(2024-02-09 00:00 UTC),
+                "c07".to_string(),
+                Some("r-12".to_string()),
+            ),
+            (
+                datetime!(2024-03-11 00:00 UTC),
+                "c07".to_string(),
+                Some("r-12".to_string()),
+            ),
+        ])
+    }
+
+    #[test]
+    fn finds_periods_by_cycle_and_route() {
+        let cal = calendar();
+        let p = cal
+            .period_containing("c07", Some("r-1"), datetime!(2024-02-20 12:00 UTC))
+            .unwrap();
+        assert_eq!(
+            (p.start, p.end, p.route_id),
+            (datetime!(2024-02-07 00:00 UTC), datetime!(2024-03-08 00:00 UTC), None)
+        );
+
+        // A route with its own schedule.
+        let p = cal
+            .period_containing("c07", Some("r-12"), datetime!(2024-02-20 12:00 UTC))
+            .unwrap();
+        assert_eq!(p.route_id.as_deref(), Some("r-12"));
+        assert_eq!(p.length(), Duration::days(31));
+
+        // Outside the calendar, or an unknown cycle.
+        assert_eq!(
+            cal.period_containing("c07", None, datetime!(2024-03-09 00:00 UTC)),
+            None
+        );
+        assert_eq!(
+            cal.period_containing("c99", None, datetime!(2024-02-20 00:00 UTC)),
+            None
+        );
+
+        let closing = cal.periods_closing(
+            "c07",
+            None,
+            datetime!(2024-02-01 00:00 UTC),
+            datetime!(2024-03-01 00:00 UTC),
+        );
+        assert_eq!(closing.len(), 1);
+        assert_eq!(closing[0].end, datetime!(2024-02-07 00:00 UTC));
+    }
+
+    #[test]
+    fn prorates_a_period_across_a_meter_exchange() {
+        let period = calendar()
+            .period_containing("c07", None, datetime!(2024-02-20 00:00 UTC))
+            .unwrap();
+        let segments = [
+            MeterSegment {
+                meter_id: "m-old".to_string(),
+                from: None,
+                to: Some(datetime!(2024-02-17 00:00 UTC)),
+            },
+            MeterSegment {
+                meter_id: "m-new".to_string(),
+                from: Some(datetime!(2024-02-17 00:00 UTC)),
+                to: None,
+            },
+        ];
+        let parts = prorate(&period, &segments);
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            (parts[0].from, parts[0].to),
+            (period.start, datetime!(2024-02-17 00:00 UTC))
+        );
+        assert!((parts[0].share - 10.0 / 30.0).abs() < 1e-12);
+        assert!((parts[1].share - 20.0 / 30.0).abs() < 1e-12);
+
+        // A meter retired before the period doesn't take part.
+        let earlier = [MeterSegment {
+            meter_id: "m-gone".to_string(),
+            from: None,
+            to: Some(datetime!(2024-01-01 00:00 UTC)),
+        }];
+        assert!(prorate(&period, &earlier).is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+/// Default tolerated deviation of a source's clock offset from its baseline.
+pub const DEFAULT_THRESHOLD_SECS: f64 = 300.0;
+
+/// Default number of earlier days the baseline offset is taken from.
+pub const DEFAULT_BASELINE_DAYS: i64 = 14;
+
+/// Records more than this far ahead of their arrival always alert: their device clock is ahead.
+pub const FUTURE_TOLERANCE_SECS: f64 = 60.0;
+
+/// Default window (before the end of the day) searched for rows that arrived on the day.
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 35;
+
+/// Clock offset of one `source_system` for one day of arrivals.
+///
+/// The offset estimate is the smallest `ingested_at - ts` (seconds): the
+/// freshest record shows the source's clock error plus the minimum transport
+/// delay. A clock running behind raises it, a clock running ahead lowers it,
+/// possibly below zero (records from the future).
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ClockObservation {
+    pub source_system: String,
+    pub samples: i64,
+    pub offset_secs: f64,
+    pub avg_lateness_secs: Option<f64>,
+    /// Records whose `ts` is after their `ingested_at`.
+    pub future_records: i64,
+}
+
+/// Bind parameters: `$1` day start, `$2` day end, `$3` lookback start (on `ts`).
+const OBSERVATION_SQL: &str = r#"
+SELECT
+    source_system,
+    count() AS samples,
+    min(lateness) AS offset_secs,
+    avg(lateness) AS avg_lateness_secs,
+    sum(CASE WHEN lateness < 0 THEN 1 ELSE 0 END) AS future_records
+FROM (
+    SELECT
+        coalesce(source_system, 'unknown') AS source_system,
+        (cast(ingested_at AS LONG) - cast(ts AS LONG)) / 1000000.0 AS lateness
+    FROM meter_usage
+    WHERE ts >= $3 AND ts < $2
+      AND ingested_at >= $1 AND ingested_at < $2
+)
+GROUP BY source_system
+ORDER BY source_system
+"#;
+
+/// Observe the clock offset of every source for meter usage that arrived on `day` (UTC).
+pub async fn observe(pool: &PgPool, day: Date, lookback_days: i64) -> Result<Vec<ClockObservation>, sqlx::Error> {
+    let start = day.midnight().assume_utc();
+    let end = start + Duration::days(1);
+    sqlx::query_as::<_, ClockObservation>(OBSERVATION_SQL)
+        .bind(start)
+        .bind(end)
+        .bind(end - Duration::days(lookback_days))
+        .fetch_all(pool)
+        .await
+}
+
+/// A day's offset compared with the source's history.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriftAssessment {
+    /// Median offset of the baseline days; `None` without history.
+    pub baseline_offset_secs: Option<f64>,
+    /// Offset minus baseline.
+    pub drift_secs: Option<f64>,
+    /// Least-squares slope of the offset over the baseline days and today (seconds per day).
+    pub drift_rate_secs_per_day: Option<f64>,
+    pub alert: bool,
+}
+
+/// Compare `offset_secs` with the `history` of `(days before today, offset)` of the same source.
+///
+/// Alerts when the offset moved more than `threshold_secs` away from the
+/// baseline, or when records arrive more than [`FUTURE_TOLERANCE_SECS`] before
+/// their own timestamp.
+pub fn assess(offset_secs: f64, history: &[(i64, f64)], threshold_secs: f64) -> DriftAssessment {
+    let mut offsets: Vec<f64> = history.iter().map(|(_, o)| *o).collect();
+    offsets.sort_by(f64::total_cmp);
+    let baseline = match offsets.len() {
+        0 => None,
+        n if n % 2 == 1 => Some(offsets[n / 2]),
+        n => Some((offsets[n / 2 - 1] + offsets[n / 2]) / 2.0),
+    };
+    let drift = baseline.map(|b| offset_secs - b);
+
+    let points: Vec<(f64, f64)> = history
+        .iter()
+        .map(|(days_ago, o)| (-(*days_ago as f64), *o))
+        .chain(std::iter::once((0.0, offset_secs)))
+        .collect();
+    let n = points.len() as f64;
+    let mean_x = points.iter().map(|(x, _)| x).sum::<f64>() / n;
+    let mean_y = points.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let var_x: f64 = points.iter().map(|(x, _)| (x - mean_x).powi(2)).sum();
+    let rate = (var_x > 0.0).then(|| {
+        points.iter().map(|(x, y)| (x - mean_x) * (y - mean_y)).sum::<f64>() / var_x
+    });
+
+    DriftAssessment {
+        baseline_offset_secs: baseline,
+        drift_secs: drift,
+        drift_rate_secs_per_day: rate,
+        alert: drift.is_some_and(|d| d.abs() > threshold_secs) || offset_secs < -FUTURE_TOLERANCE_SECS,
+    }
+}
+
+/// Offsets stored for `[from, to)`, as `(day, source_system, offset_secs)`.
+pub async fn history(
+    pool: &PgPool,
+    from: Date,
+    to: Date,
+) -> Result<Vec<(OffsetDateTime, String, f64)>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT day, source_system, offset_secs FROM source_clock_drift \
+         WHERE day >= $1 AND day < $2 AND offset_secs IS NOT NULL",
+    )
+    .bind(from.midnight().assume_utc())
+    .bind(to.midnight().assume_utc())
+    .fetch_all(pool)
+    .await
+}
+
+/// Observe `day`, assess each source against its previous `baseline_days` and
+/// return the observations with their assessments.
+pub async fn compute(
+    pool: &PgPool,
+    day: Date,
+    lookback_days: i64,
+    baseline_days: i64,
+    threshold_secs: f64,
+) -> Result<Vec<(ClockObservation, DriftAssessment)>, sqlx::Error> {
+    let observations = observe(pool, day, lookback_days).await?;
+    let history = history(pool, day - Duration::days(baseline_days), day).await?;
+
+    Ok(observations
+        .into_iter()
+        .map(|obs| {
+            let source_history: Vec<(i64, f64)> = history
+                .iter()
+                .filter(|(_, source, _)| *source == obs.source_system)
+                .map(|(d, _, offset)| ((day - d.date()).whole_days(), *offset))
+                .collect();
+            let assessment = assess(obs.offset_secs, &source_history, threshold_secs);
+            (obs, assessment)
+        })
+        .collect())
+}
+
+/// Write one day's results to `source_clock_drift`.
+///
+/// The table deduplicates on `(day, source_system)`, so re-running a day replaces its rows.
+pub async fn store(
+    pool: &PgPool,
+    day: Date,
+    results: &[(ClockObservation, DriftAssessment)],
+) -> Result<u64, sqlx::Error> {
+    if results.is_empty() {
+        return Ok(0);
+    }
+
+    let day_ts: OffsetDateTime = day.midnight().assume_utc();
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO source_clock_drift (day, source_system, samples, offset_secs, avg_lateness_secs, \
+         future_records, baseline_offset_secs, drift_secs, drift_rate_secs_per_day, alert) ",
+    );
+    builder.push_values(results, |mut b, (obs, a)| {
+        b.push_bind(day_ts)
+            .push_bind(&obs.source_system)
+            .push_bind(obs.samples)
+            .push_bind(obs.offset_secs)
+            .push_bind(obs.avg_lateness_secs)
+            .push_bind(obs.future_records)
+            .push_bind(a.baseline_offset_secs)
+            .push_bind(a.drift_secs)
+            .push_bind(a.drift_rate_secs_per_day)
+            .push_bind(a.alert);
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alerts_on_offsets_drifting_from_the_baseline() {
+        // A head-end losing a minute a day: its records look ever later.
+        let history: Vec<(i64, f64)> = (1..=14).map(|d| (d, 30.0 + 60.0 * (14 - d) as f64)).collect();
+        let today = assess(30.0 + 60.0 * 14.0, &history, DEFAULT_THRESHOLD_SECS);
+        assert_eq!(today.baseline_offset_secs, Some(30.0 + 60.0 * 6.5));
+        assert!((today.drift_rate_secs_per_day.unwrap() - 60.0).abs() < 1e-9);
+        assert!(today.alert);
+
+        // A steady source with jitter doesn't alert.
+        let steady: Vec<(i64, f64)> = (1..=7).map(|d| (d, 5.0 + (d % 3) as f64)).collect();
+        let ok = assess(6.0, &steady, DEFAULT_THRESHOLD_SECS);
+        assert_eq!(ok.drift_secs, Some(0.0));
+        assert!(!ok.alert);
+
+        // Without history there is no baseline, but records from the future still alert.
+        let first = assess(-120.0, &[], DEFAULT_THRESHOLD_SECS);
+        assert_eq!(first.baseline_offset_secs, None);
+        assert_eq!(first.drift_rate_secs_per_day, None);
+        assert!(first.alert);
+    }
+}
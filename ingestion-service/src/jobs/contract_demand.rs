@@ -0,0 +1,484 @@
+use std::{collections::BTreeMap, fmt, str::FromStr};
+
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+/// Meter intervals are 15 minutes, so interval kWh times 4 is average kW.
+const KW_PER_INTERVAL_KWH: f64 = 4.0;
+
+/// Length of a meter interval.
+pub const INTERVAL: Duration = Duration::minutes(15);
+
+/// Quantity a contract limits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DemandUnit {
+    /// Average kW per interval, from interval kWh (scaled by `meter_scale_map`).
+    Kw,
+    /// Apparent demand, from the meters' `kva_demand`.
+    Kva,
+}
+
+impl fmt::Display for DemandUnit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Kw => "kw",
+            Self::Kva => "kva",
+        })
+    }
+}
+
+impl FromStr for DemandUnit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "kw" => Ok(Self::Kw),
+            "kva" => Ok(Self::Kva),
+            other => Err(format!("unknown demand unit '{other}' (expected kw or kva)")),
+        }
+    }
+}
+
+/// A version of an account's contract, from `contract_demand`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contract {
+    pub effective_from: OffsetDateTime,
+    pub contract_demand: f64,
+    pub unit: DemandUnit,
+}
+
+/// Contract versions of each account, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct Contracts(BTreeMap<String, Vec<Contract>>);
+
+impl Contracts {
+    pub fn new(rows: impl IntoIterator<Item = (String, Contract)>) -> Self {
+        let mut by_account: BTreeMap<String, Vec<Contract>> = BTreeMap::new();
+        for (customer_id, contract) in rows {
+            by_account.entry(customer_id).or_default().push(contract);
+        }
+        for versions in by_account.values_mut() {
+            versions.sort_by_key(|c| c.effective_from);
+        }
+        Self(by_account)
+    }
+
+    /// Contract versions in effect before `to`.
+    pub async fn load(pool: &PgPool, to: OffsetDateTime) -> Result<Self, sqlx::Error> {
+        let rows: Vec<(OffsetDateTime, String, f64, Option<String>)> = sqlx::query_as(
+            "SELECT effective_from, customer_id, contract_demand, demand_unit FROM contract_demand \
+             WHERE effective_from < $1",
+        )
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+
+        let mut contracts = Vec::with_capacity(rows.len());
+        for (effective_from, customer_id, contract_demand, unit) in rows {
+            let unit = match unit.as_deref().map(DemandUnit::from_str).transpose() {
+                Ok(unit) => unit.unwrap_or(DemandUnit::Kw),
+                Err(e) => {
+                    tracing::warn!(customer_id = %customer_id, error = %e, "skipping contract_demand row");
+                    continue;
+                }
+            };
+            contracts.push((
+                customer_id,
+                Contract {
+                    effective_from,
+                    contract_demand,
+                    unit,
+                },
+            ));
+        }
+        Ok(Self::new(contracts))
+    }
+
+    pub fn accounts(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    /// The contract of `customer_id` in effect at `ts`.
+    pub fn at(&self, customer_id: &str, ts: OffsetDateTime) -> Option<&Contract> {
+        self.0.get(customer_id)?.iter().rev().find(|c| c.effective_from <= ts)
+    }
+}
+
+/// Measured demand of one account in one interval.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IntervalDemand {
+    pub ts: OffsetDateTime,
+    pub kw: f64,
+    /// Sum of the meters' `kva_demand`; `None` if none reported it.
+    pub kva: Option<f64>,
+}
+
+impl IntervalDemand {
+    fn measured(&self, unit: DemandUnit) -> Option<f64> {
+        match unit {
+            DemandUnit::Kw => Some(self.kw),
+            DemandUnit::Kva => self.kva,
+        }
+    }
+}
+
+/// A run of consecutive intervals above contract, as recorded in `contract_demand_exceedances`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Exceedance {
+    pub customer_id: String,
+    /// Start of the first interval above contract.
+    pub start: OffsetDateTime,
+    /// End of the last interval above contract.
+    pub end: OffsetDateTime,
+    pub intervals: i64,
+    pub unit: DemandUnit,
+    pub contract_demand: f64,
+    pub max_demand: f64,
+    /// Largest overshoot, in the contract's unit.
+    pub max_excess: f64,
+    /// Overshoot integrated over the run (kWh for kW contracts, kVAh for kVA).
+    pub excess_energy: f64,
+}
+
+impl Exceedance {
+    pub fn duration(&self) -> Duration {
+        self.end - self.start
+    }
+
+    /// Largest overshoot as a percentage of the contract.
+    pub fn max_excess_pct(&self) -> f64 {
+        if self.contract_demand > 0.0 {
+            100.0 * self.max_excess / self.contract_demand
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Group the intervals of `customer_id` above its contract into runs.
+///
+/// `intervals` may be in any order; a missing interval or a change of
+/// contract ends a run. Intervals without a contract in effect are skipped.
+pub fn exceedances(customer_id: &str, intervals: &[IntervalDemand], contracts: &Contracts) -> Vec<Exceedance> {
+    let mut sorted = intervals.to_vec();
+    sorted.sort_by_key(|i| i.ts);
+
+    let hours = INTERVAL.as_seconds_f64() / 3600.0;
+    let mut runs: Vec<Exceedance> = Vec::new();
+    let mut open = false;
+    for interval in sorted {
+        let Some(contract) = contracts.at(customer_id, interval.ts) else {
+            open = false;
+            continue;
+        };
+        let Some(demand) = interval
+            .measured(contract.unit)
+            .filter(|d| *d > contract.contract_demand)
+        else {
+            open = false;
+            continue;
+        };
+        let excess = demand - contract.contract_demand;
+
+        match runs.last_mut() {
+            Some(run)
+                if open
+                    && run.end == interval.ts
+                    && run.contract_demand == contract.contract_demand
+                    && run.unit == contract.unit =>
+            {
+                run.end = interval.ts + INTERVAL;
+                run.intervals += 1;
+                run.max_demand = run.max_demand.max(demand);
+                run.max_excess = run.max_excess.max(excess);
+                run.excess_energy += excess * hours;
+            }
+            _ => runs.push(Exceedance {
+                customer_id: customer_id.to_string(),
+                start: interval.ts,
+                end: interval.ts + INTERVAL,
+                intervals: 1,
+                unit: contract.unit,
+                contract_demand: contract.contract_demand,
+                max_demand: demand,
+                max_excess: excess,
+                excess_energy: excess * hours,
+            }),
+        }
+        open = true;
+    }
+    runs
+}
+
+/// An account's month against its contract, as recorded in `contract_demand_monthly`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MonthlyExceedance {
+    pub month: Date,
+    pub customer_id: String,
+    pub unit: DemandUnit,
+    /// Contract at the month's highest overshoot.
+    pub contract_demand: f64,
+    pub max_demand: f64,
+    pub max_excess: f64,
+    pub exceeded_intervals: i64,
+    pub exceedances: i64,
+    pub excess_energy: f64,
+}
+
+impl MonthlyExceedance {
+    pub fn exceeded_duration(&self) -> Duration {
+        INTERVAL * self.exceeded_intervals as i32
+    }
+}
+
+/// Roll the runs of one account and month up; `None` without any.
+pub fn monthly(month: Date, runs: &[Exceedance]) -> Option<MonthlyExceedance> {
+    let worst = runs.iter().max_by(|a, b| a.max_excess.total_cmp(&b.max_excess))?;
+    Some(MonthlyExceedance {
+        month,
+        customer_id: worst.customer_id.clone(),
+        unit: worst.unit,
+        contract_demand: worst.contract_demand,
+        max_demand: worst.max_demand,
+        max_excess: worst.max_excess,
+        exceeded_intervals: runs.iter().map(|r| r.intervals).sum(),
+        exceedances: runs.len() as i64,
+        excess_energy: runs.iter().map(|r| r.excess_energy).sum(),
+    })
+}
+
+/// Interval demand of the meters assigned to each account, as
+/// `(ts, customer_id, kw, kva)`.
+///
+/// Meters are attributed to the account of the `meters` version in effect at
+/// each interval. Bind parameters: `$1` start, `$2` end, `$3` account ids.
+pub fn demand_sql() -> String {
+    format!(
+        r#"
+        SELECT
+            mu.ts,
+            m.customer_id,
+            SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) * {KW_PER_INTERVAL_KWH} AS kw,
+            SUM(mu.kva_demand) AS kva
+        FROM meter_usage mu
+        ASOF JOIN meters m ON (meter_id)
+        LEFT JOIN meter_scale_map msm
+          ON msm.meter_id = mu.meter_id
+         AND msm.from_ts <= mu.ts
+         AND msm.to_ts   >  mu.ts
+        WHERE mu.ts >= $1
+          AND mu.ts <  $2
+          AND m.deleted = false
+          AND m.customer_id = ANY($3)
+        GROUP BY mu.ts, m.customer_id
+        "#
+    )
+}
+
+/// Exceedance runs and monthly roll-ups of one evaluation.
+#[derive(Debug, Clone, Default)]
+pub struct Evaluation {
+    pub exceedances: Vec<Exceedance>,
+    pub monthly: Vec<MonthlyExceedance>,
+}
+
+/// Evaluate the month of `day` up to the end of `day` for every account with a contract.
+///
+/// The whole month to date is evaluated, so runs crossing midnight are
+/// recorded once, with their full length.
+pub async fn evaluate(pool: &PgPool, day: Date) -> Result<Evaluation, sqlx::Error> {
+    let month = day.replace_day(1).expect("first of the month is valid");
+    let from = month.midnight().assume_utc();
+    let to = day.midnight().assume_utc() + Duration::days(1);
+
+    let contracts = Contracts::load(pool, to).await?;
+    let accounts = contracts.accounts();
+    if accounts.is_empty() {
+        return Ok(Evaluation::default());
+    }
+
+    let rows: Vec<(OffsetDateTime, String, f64, Option<f64>)> = sqlx::query_as(&demand_sql())
+        .bind(from)
+        .bind(to)
+        .bind(&accounts)
+        .fetch_all(pool)
+        .await?;
+    let mut by_account: BTreeMap<String, Vec<IntervalDemand>> = BTreeMap::new();
+    for (ts, customer_id, kw, kva) in rows {
+        by_account
+            .entry(customer_id)
+            .or_default()
+            .push(IntervalDemand { ts, kw, kva });
+    }
+
+    let mut evaluation = Evaluation::default();
+    for (customer_id, intervals) in by_account {
+        let runs = exceedances(&customer_id, &intervals, &contracts);
+        evaluation.monthly.extend(monthly(month, &runs));
+        evaluation.exceedances.extend(runs);
+    }
+    Ok(evaluation)
+}
+
+/// Upsert the evaluation into `contract_demand_exceedances` and `contract_demand_monthly`.
+///
+/// Both tables deduplicate on their keys, so re-evaluating a month replaces
+/// its rows. A run still growing at the end of the evaluated range is
+/// rewritten with its new end by the next evaluation.
+pub async fn store(pool: &PgPool, now: OffsetDateTime, evaluation: &Evaluation) -> Result<u64, sqlx::Error> {
+    let mut written = 0;
+    if !evaluation.exceedances.is_empty() {
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO contract_demand_exceedances (ts, customer_id, end_ts, duration_mins, intervals, \
+             demand_unit, contract_demand, max_demand, max_excess, max_excess_pct, excess_energy, evaluated_at) ",
+        );
+        builder.push_values(&evaluation.exceedances, |mut b, e| {
+            b.push_bind(e.start)
+                .push_bind(&e.customer_id)
+                .push_bind(e.end)
+                .push_bind(e.duration().whole_minutes())
+                .push_bind(e.intervals)
+                .push_bind(e.unit.to_string())
+                .push_bind(e.contract_demand)
+                .push_bind(e.max_demand)
+                .push_bind(e.max_excess)
+                .push_bind(e.max_excess_pct())
+                .push_bind(e.excess_energy)
+                .push_bind(now);
+        });
+        written += builder.build().execute(pool).await?.rows_affected();
+    }
+
+    if !evaluation.monthly.is_empty() {
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO contract_demand_monthly (month, customer_id, demand_unit, contract_demand, max_demand, \
+             max_excess, exceeded_intervals, exceeded_mins, exceedances, excess_energy, evaluated_at) ",
+        );
+        builder.push_values(&evaluation.monthly, |mut b, m| {
+            b.push_bind(m.month.midnight().assume_utc())
+                .push_bind(&m.customer_id)
+                .push_bind(m.unit.to_string())
+                .push_bind(m.contract_demand)
+                .push_bind(m.max_demand)
+                .push_bind(m.max_excess)
+                .push_bind(m.exceeded_intervals)
+                .push_bind(m.exceeded_duration().whole_minutes())
+                .push_bind(m.exceedances)
+                .push_bind(m.excess_energy)
+                .push_bind(now);
+        });
+        written += builder.build().execute(pool).await?.rows_affected();
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    fn contracts() -> Contracts {
+        Contracts::new([
+            (
+                "acct-1".to_string(),
+                Contract {
+                    effective_from: datetime!(2024-01-01 00:00 UTC),
+                    contract_demand: 500.0,
+                    unit: DemandUnit::Kw,
+                },
+            ),
+            (
+                "acct-1".to_string(),
+                Contract {
+                    effective_from: datetime!(2024-07-15 13:00 UTC),
+                    contract_demand: 600.0,
+                    unit: DemandUnit::Kw,
+                },
+            ),
+        ])
+    }
+
+    fn kw(ts: OffsetDateTime, kw: f64) -> IntervalDemand {
+        IntervalDemand { ts, kw, kva: None }
+    }
+
+    #[test]
+    fn groups_consecutive_intervals_above_contract() {
+        let t0 = datetime!(2024-07-15 12:00 UTC);
+        let at = |i: i64| t0 + INTERVAL * i as i32;
+        let intervals = [
+            kw(at(2), 550.0),
+            kw(at(0), 520.0),
+            kw(at(1), 540.0),
+            kw(at(3), 480.0),
+            // Above the old contract, but the new one is in effect from 13:00.
+            kw(at(4), 580.0),
+            kw(at(5), 650.0),
+            // A gap ends the run.
+            kw(at(7), 700.0),
+        ];
+
+        let runs = exceedances("acct-1", &intervals, &contracts());
+        assert_eq!(runs.len(), 3);
+        assert_eq!((runs[0].start, runs[0].end, runs[0].intervals), (at(0), at(3), 3));
+        assert_eq!((runs[0].max_demand, runs[0].max_excess), (550.0, 50.0));
+        assert_eq!(runs[0].duration(), Duration::minutes(45));
+        // (20 + 40 + 50) kW over quarter hours.
+        assert!((runs[0].excess_energy - 27.5).abs() < 1e-9);
+        assert_eq!(
+            (runs[1].start, runs[1].contract_demand, runs[1].max_excess),
+            (at(5), 600.0, 50.0)
+        );
+        assert_eq!(runs[2].start, at(7));
+        assert!((runs[2].max_excess_pct() - 100.0 / 6.0).abs() < 1e-9);
+
+        let month = monthly(date!(2024 - 07 - 01), &runs).unwrap();
+        assert_eq!((month.exceedances, month.exceeded_intervals), (3, 5));
+        assert_eq!(month.exceeded_duration(), Duration::minutes(75));
+        assert_eq!(
+            (month.max_demand, month.max_excess, month.contract_demand),
+            (700.0, 100.0, 600.0)
+        );
+
+        // No contract, no exceedance.
+        assert!(exceedances("acct-2", &intervals, &contracts()).is_empty());
+        assert_eq!(monthly(date!(2024 - 07 - 01), &[]), None);
+    }
+
+    #[test]
+    fn kva_contracts_use_reported_demand() {
+        let contracts = Contracts::new([(
+            "acct-1".to_string(),
+            Contract {
+                effective_from: datetime!(2024-01-01 00:00 UTC),
+                contract_demand: 500.0,
+                unit: "KVA".parse().unwrap(),
+            },
+        )]);
+        let t0 = datetime!(2024-07-15 12:00 UTC);
+        let intervals = [
+            IntervalDemand {
+                ts: t0,
+                kw: 900.0,
+                kva: Some(450.0),
+            },
+            IntervalDemand {
+                ts: t0 + INTERVAL,
+                kw: 400.0,
+                kva: Some(510.0),
+            },
+            // Without a kVA reading the interval can't be judged.
+            IntervalDemand {
+                ts: t0 + INTERVAL * 2,
+                kw: 900.0,
+                kva: None,
+            },
+        ];
+        let runs = exceedances("acct-1", &intervals, &contracts);
+        assert_eq!(runs.len(), 1);
+        assert_eq!(
+            (runs[0].start, runs[0].unit, runs[0].max_demand),
+            (t0 + INTERVAL, DemandUnit::Kva, 510.0)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+
+use rust_client::{db::dr_events_between, domain::DrEvent};
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Duration, OffsetDateTime};
+
+/// Default number of prior days averaged into the baseline.
+pub const DEFAULT_BASELINE_DAYS: u32 = 10;
+
+/// Load reduction delivered by one nominated meter during a DR event.
+///
+/// The baseline is the average consumption over the same clock window on the
+/// `baseline_days` preceding days; days without any data for the meter are
+/// left out (`baseline_days_used`). Earlier DR event days are not excluded.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrMeterPerformance {
+    pub event_id: String,
+    pub program: String,
+    pub start: OffsetDateTime,
+    pub meter_id: String,
+    pub actual_kwh: f64,
+    pub baseline_kwh: Option<f64>,
+    pub reduction_kwh: Option<f64>,
+    pub baseline_days_used: i32,
+}
+
+/// A `meter_usage` sample used for the evaluation.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UsageSample {
+    pub meter_id: String,
+    pub ts: OffsetDateTime,
+    pub kwh: f64,
+}
+
+/// Evaluate an event from usage covering `[start - baseline_days, end)`.
+///
+/// Returns one row per nominated meter, in nomination order.
+pub fn evaluate(event: &DrEvent, usage: &[UsageSample], baseline_days: u32) -> Vec<DrMeterPerformance> {
+    event
+        .meter_ids
+        .iter()
+        .map(|meter_id| {
+            let mut actual_kwh = 0.0;
+            // Prior day (1..=baseline_days) -> kWh in the shifted window.
+            let mut baseline_by_day: BTreeMap<u32, f64> = BTreeMap::new();
+
+            for s in usage.iter().filter(|s| &s.meter_id == meter_id) {
+                if s.ts >= event.start && s.ts < event.end {
+                    actual_kwh += s.kwh;
+                    continue;
+                }
+                for day in 1..=baseline_days {
+                    let shift = Duration::days(i64::from(day));
+                    if s.ts >= event.start - shift && s.ts < event.end - shift {
+                        *baseline_by_day.entry(day).or_default() += s.kwh;
+                    }
+                }
+            }
+
+            let baseline_kwh = (!baseline_by_day.is_empty())
+                .then(|| baseline_by_day.values().sum::<f64>() / baseline_by_day.len() as f64);
+
+            DrMeterPerformance {
+                event_id: event.event_id.clone(),
+                program: event.program.clone(),
+                start: event.start,
+                meter_id: meter_id.clone(),
+                actual_kwh,
+                baseline_kwh,
+                reduction_kwh: baseline_kwh.map(|b| b - actual_kwh),
+                baseline_days_used: baseline_by_day.len() as i32,
+            }
+        })
+        .collect()
+}
+
+async fn fetch_usage(
+    pool: &PgPool,
+    event: &DrEvent,
+    baseline_days: u32,
+) -> Result<Vec<UsageSample>, sqlx::Error> {
+    sqlx::query_as::<_, UsageSample>(
+        r#"
+        SELECT meter_id, ts, kwh
+        FROM meter_usage
+        WHERE ts >= $1
+          AND ts <  $2
+          AND meter_id = ANY($3)
+        "#,
+    )
+    .bind(event.start - Duration::days(i64::from(baseline_days)))
+    .bind(event.end)
+    .bind(&event.meter_ids)
+    .fetch_all(pool)
+    .await
+}
+
+async fn store(pool: &PgPool, rows: &[DrMeterPerformance]) -> Result<u64, sqlx::Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO dr_event_performance (ts, event_id, program, meter_id, actual_kwh, baseline_kwh, reduction_kwh, baseline_days_used) ",
+    );
+    builder.push_values(rows, |mut b, r| {
+        b.push_bind(r.start)
+            .push_bind(&r.event_id)
+            .push_bind(&r.program)
+            .push_bind(&r.meter_id)
+            .push_bind(r.actual_kwh)
+            .push_bind(r.baseline_kwh)
+            .push_bind(r.reduction_kwh)
+            .push_bind(r.baseline_days_used);
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+/// Evaluate all events that started in `[from, to)` and have already ended.
+///
+/// `dr_event_performance` deduplicates on `(ts, event_id, meter_id)`, so
+/// re-running a range replaces earlier results.
+pub async fn run(pool: &PgPool, from: OffsetDateTime, to: OffsetDateTime, baseline_days: u32) -> anyhow::Result<u64> {
+    let now = OffsetDateTime::now_utc();
+    let mut written = 0;
+
+    for event in dr_events_between(pool, from, to).await? {
+        if event.end > now {
+            tracing::debug!(event_id = %event.event_id, "DR event not finished yet; skipping");
+            continue;
+        }
+
+        let usage = fetch_usage(pool, &event, baseline_days).await?;
+        written += store(pool, &evaluate(&event, &usage, baseline_days)).await?;
+        metrics::counter!("dr_events_evaluated_total").increment(1);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn sample(meter_id: &str, ts: OffsetDateTime, kwh: f64) -> UsageSample {
+        UsageSample {
+            meter_id: meter_id.to_string(),
+            ts,
+            kwh,
+        }
+    }
+
+    #[test]
+    fn reduction_is_baseline_average_minus_event_usage() {
+        let event = DrEvent {
+            event_id: "e-1".to_string(),
+            program: "peak-saver".to_string(),
+            start: datetime!(2024-07-10 17:00:00 UTC),
+            end: datetime!(2024-07-10 19:00:00 UTC),
+            meter_ids: vec!["m-1".to_string(), "m-2".to_string()],
+        };
+
+        let usage = vec![
+            // Event window.
+            sample("m-1", datetime!(2024-07-10 17:00:00 UTC), 1.0),
+            sample("m-1", datetime!(2024-07-10 18:00:00 UTC), 1.0),
+            // Same window one and two days earlier.
+            sample("m-1", datetime!(2024-07-09 17:00:00 UTC), 2.0),
+            sample("m-1", datetime!(2024-07-09 18:00:00 UTC), 2.0),
+            sample("m-1", datetime!(2024-07-08 17:30:00 UTC), 6.0),
+            // Outside any window.
+            sample("m-1", datetime!(2024-07-09 19:00:00 UTC), 100.0),
+            sample("m-2", datetime!(2024-07-10 17:00:00 UTC), 3.0),
+        ];
+
+        let perf = evaluate(&event, &usage, 10);
+
+        assert_eq!(perf[0].actual_kwh, 2.0);
+        assert_eq!(perf[0].baseline_kwh, Some(5.0));
+        assert_eq!(perf[0].reduction_kwh, Some(3.0));
+        assert_eq!(perf[0].baseline_days_used, 2);
+
+        assert_eq!(perf[1].actual_kwh, 3.0);
+        assert_eq!(perf[1].baseline_kwh, None);
+        assert_eq!(perf[1].reduction_kwh, None);
+    }
+}
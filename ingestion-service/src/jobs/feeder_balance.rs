@@ -0,0 +1,524 @@
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use super::{
+    partitions::{drop_partitions_sql, sql_ts, PartitionBy},
+    pseudo_measurements::PSEUDO_QUALITY_FLAG,
+};
+
+/// Partitioning of `feeder_energy_balance` (see `sql/schema/03_mapping_tables.sql`).
+pub const PARTITION_BY: PartitionBy = PartitionBy::Month;
+
+/// Default loss alert threshold: |loss_pct| > 2% triggers an alert.
+pub const DEFAULT_LOSS_ALERT_THRESHOLD: f64 = 0.02;
+
+/// Default minimum fraction of mapped meters that must report in an interval.
+pub const DEFAULT_MIN_COMPLETENESS: f64 = 0.9;
+
+/// What to do with intervals whose meter data completeness is below the threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncompletePolicy {
+    /// Keep the row, but null out loss values, clear the alert and mark it incomplete.
+    Mark,
+    /// Do not write a balance row for the interval at all.
+    Skip,
+}
+
+impl std::str::FromStr for IncompletePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mark" => Ok(Self::Mark),
+            "skip" => Ok(Self::Skip),
+            other => Err(format!("unknown incomplete policy '{other}' (expected mark|skip)")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FeederBalanceOptions {
+    pub loss_alert_threshold: f64,
+    pub min_completeness: f64,
+    pub incomplete_policy: IncompletePolicy,
+}
+
+impl Default for FeederBalanceOptions {
+    fn default() -> Self {
+        Self {
+            loss_alert_threshold: DEFAULT_LOSS_ALERT_THRESHOLD,
+            min_completeness: DEFAULT_MIN_COMPLETENESS,
+            incomplete_policy: IncompletePolicy::Mark,
+        }
+    }
+}
+
+/// Build the `INSERT INTO feeder_energy_balance` statement.
+///
+/// Bind parameters: `$1` loss alert threshold, `$2` minimum completeness.
+///
+/// Completeness is the fraction of meters mapped to the feeder at `ts` that
+/// reported usage for that interval. Intervals without any mapped meters are
+/// treated as complete.
+///
+/// The gaps are counted inline because no per-interval gap output is stored:
+/// `feeder_completeness` (see [`super::triggers::feeder_completeness`]) is
+/// per day. Both match a `meter_usage` row to the mappings with
+/// `from_ts <= ts < to_ts`, so over a day with generation in every interval
+/// the mapped and reporting meters counted here add up to that table's
+/// expected and reported intervals.
+pub fn balance_insert_sql(policy: IncompletePolicy) -> String {
+    insert_sql(policy, false, false)
+}
+
+/// Like [`balance_insert_sql`], limited to intervals in `[$3, $4)`.
+pub fn balance_insert_range_sql(policy: IncompletePolicy) -> String {
+    insert_sql(policy, true, false)
+}
+
+/// Like [`balance_insert_range_sql`], with the meters mapped to feeders by
+/// scenario `$5` of `meter_feeder_map_scenarios` instead of `meter_feeder_map`,
+/// into `feeder_energy_balance_scenarios` with `computed_at` `$6`.
+pub fn scenario_insert_sql(policy: IncompletePolicy) -> String {
+    insert_sql(policy, true, true)
+}
+
+fn insert_sql(policy: IncompletePolicy, ranged: bool, scenario: bool) -> String {
+    let range_filter = if ranged { "WHERE go.ts >= $3 AND go.ts < $4" } else { "" };
+    let (target, scenario_columns, meter_feeder_map) = if scenario {
+        (
+            "feeder_energy_balance_scenarios",
+            ",\n            $5 AS scenario,\n            $6 AS computed_at",
+            "(SELECT meter_id, feeder_id, from_ts, to_ts FROM meter_feeder_map_scenarios WHERE scenario = $5)",
+        )
+    } else {
+        ("feeder_energy_balance", "", "meter_feeder_map")
+    };
+
+    let complete_expr = "(c.completeness IS NULL OR c.completeness >= $2)";
+    // Metered demand plus the pseudo-measured unmetered loads.
+    let demand = "(COALESCE(d.feeder_kwh_demand, 0) + COALESCE(u.feeder_kwh_unmetered, 0))";
+
+    let (loss_kwh, loss_pct, alert_guard) = match policy {
+        IncompletePolicy::Mark => (
+            format!("CASE WHEN {complete_expr} THEN g.feeder_kwh_gen - {demand} ELSE NULL END"),
+            format!(
+                "CASE WHEN NOT {complete_expr} OR g.feeder_kwh_gen = 0 THEN NULL
+                 ELSE (g.feeder_kwh_gen - {demand}) / g.feeder_kwh_gen
+            END"
+            ),
+            format!("WHEN NOT {complete_expr} THEN FALSE"),
+        ),
+        IncompletePolicy::Skip => (
+            format!("g.feeder_kwh_gen - {demand}"),
+            format!(
+                "CASE WHEN g.feeder_kwh_gen = 0 THEN NULL
+                 ELSE (g.feeder_kwh_gen - {demand}) / g.feeder_kwh_gen
+            END"
+            ),
+            String::new(),
+        ),
+    };
+
+    let where_clause = match policy {
+        IncompletePolicy::Mark => String::new(),
+        IncompletePolicy::Skip => format!("WHERE {complete_expr}"),
+    };
+
+    format!(
+        r#"
+        INSERT INTO {target}
+        SELECT
+            g.ts,
+            g.feeder_id,
+            g.feeder_kwh_gen,
+            {demand}                                                              AS feeder_kwh_demand,
+            {loss_kwh}                                                            AS loss_kwh,
+            {loss_pct}                                                            AS loss_pct,
+            COALESCE(c.completeness, 1.0)                                         AS meter_coverage_pct,
+            COALESCE(c.completeness, 1.0)                                         AS data_quality_score,
+            CASE
+                WHEN g.feeder_kwh_gen = 0 THEN 'unknown'
+                WHEN NOT {complete_expr} THEN 'data'
+                WHEN t.topology_events > 0 THEN 'topology'
+                WHEN th.theft_events > 0 THEN 'theft'
+                WHEN g.feeder_kwh_gen > 0
+                     AND ABS((g.feeder_kwh_gen - {demand}) / g.feeder_kwh_gen) <= 0.05
+                     THEN 'physics'
+                ELSE 'unknown'
+            END                                                                   AS cause_hint,
+            CASE
+                WHEN g.feeder_kwh_gen = 0 THEN FALSE
+                {alert_guard}
+                WHEN ABS((g.feeder_kwh_gen - {demand}) / g.feeder_kwh_gen) > $1
+                    THEN TRUE
+                ELSE FALSE
+            END                                                                   AS alert,
+            {complete_expr}                                                       AS complete{scenario_columns},
+            COALESCE(u.feeder_kwh_unmetered, 0)                                   AS feeder_kwh_unmetered
+        FROM (
+            SELECT
+                go.ts,
+                pfm.feeder_id,
+                SUM(go.mw) * 0.25 AS feeder_kwh_gen            -- assume 15-min intervals
+            FROM generation_output go
+            JOIN plant_feeder_map pfm
+              ON pfm.plant_id = go.plant_id
+             AND (pfm.unit_id IS NULL OR pfm.unit_id = go.unit_id)
+             AND pfm.from_ts <= go.ts
+             AND pfm.to_ts   >  go.ts
+            {range_filter}
+            GROUP BY go.ts, pfm.feeder_id
+        ) g
+        LEFT JOIN (
+            SELECT
+                mu.ts,
+                mfm.feeder_id,
+                SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS feeder_kwh_demand
+            FROM meter_usage mu
+            JOIN {meter_feeder_map} mfm
+              ON mfm.meter_id = mu.meter_id
+             AND mfm.from_ts <= mu.ts
+             AND mfm.to_ts   >  mu.ts
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            GROUP BY mu.ts, mfm.feeder_id
+        ) d
+          ON d.ts = g.ts
+         AND d.feeder_id = g.feeder_id
+        LEFT JOIN (
+            SELECT
+                mu.ts,
+                ul.feeder_id,
+                SUM(mu.kwh) AS feeder_kwh_unmetered
+            FROM meter_usage mu
+            JOIN unmetered_loads ul
+              ON ul.load_id = mu.meter_id
+             AND ul.from_ts <= mu.ts
+             AND ul.to_ts   >  mu.ts
+            WHERE mu.quality_flag = '{PSEUDO_QUALITY_FLAG}'
+            GROUP BY mu.ts, ul.feeder_id
+        ) u
+          ON u.ts = g.ts
+         AND u.feeder_id = g.feeder_id
+        LEFT JOIN (
+            SELECT
+                mapped.ts,
+                mapped.feeder_id,
+                COALESCE(reporting.reporting_meters, 0) * 1.0 / NULLIF(mapped.mapped_meters, 0) AS completeness
+            FROM (
+                SELECT
+                    gts.ts,
+                    mfm.feeder_id,
+                    COUNT(DISTINCT mfm.meter_id) AS mapped_meters
+                FROM (SELECT DISTINCT ts FROM generation_output) gts
+                JOIN {meter_feeder_map} mfm
+                  ON mfm.from_ts <= gts.ts
+                 AND mfm.to_ts   >  gts.ts
+                GROUP BY gts.ts, mfm.feeder_id
+            ) mapped
+            LEFT JOIN (
+                SELECT
+                    mu.ts,
+                    mfm.feeder_id,
+                    COUNT(DISTINCT mu.meter_id) AS reporting_meters
+                FROM meter_usage mu
+                JOIN {meter_feeder_map} mfm
+                  ON mfm.meter_id = mu.meter_id
+                 AND mfm.from_ts <= mu.ts
+                 AND mfm.to_ts   >  mu.ts
+                GROUP BY mu.ts, mfm.feeder_id
+            ) reporting
+              ON reporting.ts = mapped.ts
+             AND reporting.feeder_id = mapped.feeder_id
+        ) c
+          ON c.ts = g.ts
+         AND c.feeder_id = g.feeder_id
+        LEFT JOIN (
+            SELECT
+                feeder_id,
+                ts,
+                COUNT(*) AS topology_events
+            FROM topology_events
+            GROUP BY feeder_id, ts
+        ) t
+          ON t.ts = g.ts
+         AND t.feeder_id = g.feeder_id
+        LEFT JOIN (
+            SELECT
+                mfm.feeder_id,
+                me.ts,
+                COUNT(*) AS theft_events
+            FROM meter_events me
+            JOIN {meter_feeder_map} mfm
+              ON mfm.meter_id = me.meter_id
+             AND mfm.from_ts <= me.ts
+             AND mfm.to_ts   >  me.ts
+            WHERE me.event_type IN ('tamper', 'reverse_run', 'magnetic', 'theft_suspect')
+            GROUP BY mfm.feeder_id, me.ts
+        ) th
+          ON th.ts = g.ts
+         AND th.feeder_id = g.feeder_id
+        {where_clause};
+        "#
+    )
+}
+
+/// Recompute the entire `feeder_energy_balance` table from scratch.
+///
+/// Returns the number of rows inserted.
+pub async fn recompute(pool: &PgPool, opts: &FeederBalanceOptions) -> Result<u64, sqlx::Error> {
+    sqlx::query("TRUNCATE TABLE feeder_energy_balance;")
+        .execute(pool)
+        .await?;
+
+    let sql = balance_insert_sql(opts.incomplete_policy);
+    let result = sqlx::query(&sql)
+        .bind(opts.loss_alert_threshold)
+        .bind(opts.min_completeness)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Recompute `feeder_energy_balance` for `[from, to)`, widened to whole partitions.
+///
+/// QuestDB can't delete individual rows, so the partitions overlapping the
+/// range are dropped and rebuilt for every feeder. Returns the rows inserted.
+pub async fn recompute_range(
+    pool: &PgPool,
+    opts: &FeederBalanceOptions,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<u64, sqlx::Error> {
+    let (from, to) = PARTITION_BY.widen(from, to);
+    sqlx::query(&drop_partitions_sql("feeder_energy_balance", from, to))
+        .execute(pool)
+        .await?;
+
+    let result = sqlx::query(&balance_insert_range_sql(opts.incomplete_policy))
+        .bind(opts.loss_alert_threshold)
+        .bind(opts.min_completeness)
+        .bind(from)
+        .bind(to)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Outcome of a what-if run.
+#[derive(Debug, Clone, Copy)]
+pub struct ScenarioRun {
+    /// Labels the run's rows in `feeder_energy_balance_scenarios`.
+    pub computed_at: OffsetDateTime,
+    pub rows: u64,
+}
+
+/// Compute the feeder balance of `[from, to)` under an alternative
+/// meter-to-feeder mapping, scenario `scenario` of `meter_feeder_map_scenarios`.
+///
+/// Production tables are only read. Results go to
+/// `feeder_energy_balance_scenarios`, labeled with the scenario and the run's
+/// `computed_at`; the run is recorded in `feeder_scenario_runs` once all rows
+/// are written, so readers take the latest recorded run of a scenario and
+/// rows of an interrupted run are never picked up. Like settlement snapshots,
+/// scenario tables are not part of the derived-table registry.
+pub async fn run_scenario(
+    pool: &PgPool,
+    opts: &FeederBalanceOptions,
+    scenario: &str,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    now: OffsetDateTime,
+) -> anyhow::Result<ScenarioRun> {
+    if from >= to {
+        anyhow::bail!("empty window: {} is not before {}", sql_ts(from), sql_ts(to));
+    }
+    let mapped: i64 = sqlx::query_scalar("SELECT count() FROM meter_feeder_map_scenarios WHERE scenario = $1")
+        .bind(scenario)
+        .fetch_one(pool)
+        .await?;
+    if mapped == 0 {
+        anyhow::bail!("scenario '{scenario}' has no rows in meter_feeder_map_scenarios");
+    }
+    // QuestDB timestamps have microsecond precision.
+    let computed_at = now.replace_nanosecond(now.nanosecond() / 1000 * 1000)?;
+
+    let result = sqlx::query(&scenario_insert_sql(opts.incomplete_policy))
+        .bind(opts.loss_alert_threshold)
+        .bind(opts.min_completeness)
+        .bind(from)
+        .bind(to)
+        .bind(scenario)
+        .bind(computed_at)
+        .execute(pool)
+        .await?;
+    let rows = result.rows_affected();
+    sqlx::query(
+        "INSERT INTO feeder_scenario_runs (computed_at, scenario, from_ts, to_ts, rows) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(computed_at)
+    .bind(scenario)
+    .bind(from)
+    .bind(to)
+    .bind(rows as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(ScenarioRun { computed_at, rows })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skip_policy_filters_incomplete_intervals() {
+        let sql = balance_insert_sql(IncompletePolicy::Skip);
+        assert!(sql.contains("WHERE (c.completeness IS NULL OR c.completeness >= $2)"));
+    }
+
+    #[test]
+    fn mark_policy_keeps_rows_and_nulls_loss() {
+        let sql = balance_insert_sql(IncompletePolicy::Mark);
+        assert!(!sql.contains("WHERE (c.completeness"));
+        assert!(sql.contains("ELSE NULL END"));
+        assert!(sql.contains("AS complete"));
+    }
+
+    #[test]
+    fn range_sql_filters_generation_intervals() {
+        let sql = balance_insert_range_sql(IncompletePolicy::Mark);
+        assert!(sql.contains("WHERE go.ts >= $3 AND go.ts < $4"));
+        assert!(!balance_insert_sql(IncompletePolicy::Mark).contains("$3"));
+    }
+
+    #[test]
+    fn scenario_sql_reads_the_scenario_mapping_only() {
+        let sql = scenario_insert_sql(IncompletePolicy::Mark);
+        assert!(sql.contains("INSERT INTO feeder_energy_balance_scenarios"));
+        assert!(sql.contains("$5 AS scenario"));
+        assert!(sql.contains("$6 AS computed_at"));
+        assert!(sql.contains("WHERE go.ts >= $3 AND go.ts < $4"));
+        assert!(!sql.contains("JOIN meter_feeder_map mfm"));
+        assert_eq!(sql.matches("FROM meter_feeder_map_scenarios WHERE scenario = $5").count(), 4);
+        assert!(!balance_insert_sql(IncompletePolicy::Mark).contains("scenario"));
+    }
+
+    #[test]
+    fn unmetered_loads_count_as_demand() {
+        for sql in [
+            balance_insert_sql(IncompletePolicy::Mark),
+            balance_insert_sql(IncompletePolicy::Skip),
+            scenario_insert_sql(IncompletePolicy::Mark),
+        ] {
+            assert!(sql.contains("WHERE mu.quality_flag = 'pseudo'"));
+            assert!(sql.contains("JOIN unmetered_loads ul"));
+            assert!(!sql.contains("g.feeder_kwh_gen - COALESCE(d.feeder_kwh_demand, 0)"));
+            assert!(sql.contains("AS feeder_kwh_unmetered"));
+        }
+    }
+
+    /// Balance rows `(ts, loss_pct, alert, complete, cause_hint)` of one
+    /// feeder with ten mapped meters, generating 10 kWh per interval, where
+    /// `reporting[i]` meters report 1 kWh at the i-th interval.
+    ///
+    /// Runs the generated statement on in-memory SQLite, whose SQL covers
+    /// everything the statement uses.
+    async fn balance_rows(
+        policy: IncompletePolicy,
+        min_completeness: f64,
+        reporting: &[usize],
+    ) -> Vec<(String, Option<f64>, bool, bool, String)> {
+        use sqlx::{Connection, SqliteConnection};
+
+        let mut db = SqliteConnection::connect("sqlite::memory:").await.unwrap();
+        for ddl in [
+            "CREATE TABLE generation_output (ts TEXT, plant_id TEXT, unit_id TEXT, mw REAL)",
+            "CREATE TABLE plant_feeder_map (plant_id TEXT, unit_id TEXT, feeder_id TEXT, from_ts TEXT, to_ts TEXT)",
+            "CREATE TABLE meter_usage (ts TEXT, meter_id TEXT, kwh REAL, quality_flag TEXT)",
+            "CREATE TABLE meter_feeder_map (meter_id TEXT, feeder_id TEXT, from_ts TEXT, to_ts TEXT)",
+            "CREATE TABLE meter_scale_map (meter_id TEXT, kwh_multiplier REAL, from_ts TEXT, to_ts TEXT)",
+            "CREATE TABLE unmetered_loads (load_id TEXT, feeder_id TEXT, from_ts TEXT, to_ts TEXT)",
+            "CREATE TABLE topology_events (feeder_id TEXT, ts TEXT)",
+            "CREATE TABLE meter_events (meter_id TEXT, ts TEXT, event_type TEXT)",
+            "CREATE TABLE feeder_energy_balance (ts TEXT, feeder_id TEXT, feeder_kwh_gen REAL, \
+             feeder_kwh_demand REAL, loss_kwh REAL, loss_pct REAL, meter_coverage_pct REAL, \
+             data_quality_score REAL, cause_hint TEXT, alert BOOLEAN, complete BOOLEAN, feeder_kwh_unmetered REAL)",
+            "INSERT INTO plant_feeder_map VALUES ('p-1', NULL, 'f-1', '2024-01-01T00:00Z', '2025-01-01T00:00Z')",
+        ] {
+            sqlx::query(ddl).execute(&mut db).await.unwrap();
+        }
+        for meter in 0..10 {
+            sqlx::query("INSERT INTO meter_feeder_map VALUES ($1, 'f-1', '2024-01-01T00:00Z', '2025-01-01T00:00Z')")
+                .bind(format!("m-{meter}"))
+                .execute(&mut db)
+                .await
+                .unwrap();
+        }
+        for (interval, &meters) in reporting.iter().enumerate() {
+            let ts = format!("2024-03-10T00:{:02}Z", interval * 15);
+            sqlx::query("INSERT INTO generation_output VALUES ($1, 'p-1', 'u-1', 40.0)")
+                .bind(&ts)
+                .execute(&mut db)
+                .await
+                .unwrap();
+            for meter in 0..meters {
+                sqlx::query("INSERT INTO meter_usage VALUES ($1, $2, 1.0, NULL)")
+                    .bind(&ts)
+                    .bind(format!("m-{meter}"))
+                    .execute(&mut db)
+                    .await
+                    .unwrap();
+            }
+        }
+
+        sqlx::query(&balance_insert_sql(policy))
+            .bind(DEFAULT_LOSS_ALERT_THRESHOLD)
+            .bind(min_completeness)
+            .execute(&mut db)
+            .await
+            .unwrap();
+        sqlx::query_as("SELECT ts, loss_pct, alert, complete, cause_hint FROM feeder_energy_balance ORDER BY ts")
+            .fetch_all(&mut db)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn intervals_below_the_completeness_threshold_are_marked_or_skipped() {
+        // 9 of 10 meters report at the threshold, 8 of 10 just below it.
+        let reporting = [9, 8];
+
+        let marked = balance_rows(IncompletePolicy::Mark, 0.9, &reporting).await;
+        assert_eq!(marked.len(), 2);
+        let (ts, loss_pct, alert, complete, _) = &marked[0];
+        assert_eq!(ts, "2024-03-10T00:00Z");
+        assert!((loss_pct.unwrap() - 0.1).abs() < 1e-9);
+        assert!(*alert && *complete);
+        assert_eq!(
+            marked[1],
+            ("2024-03-10T00:15Z".to_string(), None, false, false, "data".to_string())
+        );
+
+        let skipped = balance_rows(IncompletePolicy::Skip, 0.9, &reporting).await;
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0], marked[0]);
+
+        // Lowering the threshold below 0.8 lets the second interval through.
+        let lowered = balance_rows(IncompletePolicy::Skip, 0.75, &reporting).await;
+        assert_eq!(lowered.len(), 2);
+        assert!(lowered[1].3);
+    }
+
+    #[test]
+    fn incomplete_policy_parses() {
+        assert_eq!("mark".parse::<IncompletePolicy>().unwrap(), IncompletePolicy::Mark);
+        assert_eq!("skip".parse::<IncompletePolicy>().unwrap(), IncompletePolicy::Skip);
+        assert!("drop".parse::<IncompletePolicy>().is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::BTreeMap;
+
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+/// Default number of days, up to and including the scored day, re-scored by each run.
+pub const DEFAULT_DAYS: i64 = 7;
+
+/// One forecast interval of a feeder with the actual demand that arrived for it.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct ForecastPair {
+    pub feeder_id: String,
+    pub model: String,
+    pub ts: OffsetDateTime,
+    pub issued_at: OffsetDateTime,
+    pub forecast_kwh: f64,
+    pub actual_kwh: f64,
+}
+
+/// Bind parameters: `$1` day start, `$2` day end.
+///
+/// Only complete balance intervals count as actuals, so a day is scored on
+/// more intervals as late meter data arrives. Forecasts issued after their
+/// interval aren't forecasts and are left out.
+const PAIRS_SQL: &str = r#"
+SELECT
+    f.feeder_id AS feeder_id,
+    f.model AS model,
+    f.ts AS ts,
+    f.issued_at AS issued_at,
+    f.kwh AS forecast_kwh,
+    b.feeder_kwh_demand AS actual_kwh
+FROM load_forecast f
+JOIN feeder_energy_balance b ON b.feeder_id = f.feeder_id AND b.ts = f.ts
+WHERE f.ts >= $1 AND f.ts < $2
+  AND b.ts >= $1 AND b.ts < $2
+  AND b.complete = true
+  AND b.feeder_kwh_demand IS NOT NULL
+  AND f.kwh IS NOT NULL
+  AND f.issued_at <= f.ts
+"#;
+
+/// Forecasts for `day` (UTC) paired with the feeder's actual demand.
+pub async fn pairs(pool: &PgPool, day: Date) -> Result<Vec<ForecastPair>, sqlx::Error> {
+    let start = day.midnight().assume_utc();
+    sqlx::query_as::<_, ForecastPair>(PAIRS_SQL)
+        .bind(start)
+        .bind(start + Duration::days(1))
+        .fetch_all(pool)
+        .await
+}
+
+/// Accuracy of one model's forecasts for one feeder at one horizon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForecastAccuracy {
+    pub feeder_id: String,
+    pub model: String,
+    /// Whole hours between `issued_at` and the forecast interval.
+    pub horizon_hours: i32,
+    pub samples: i64,
+    pub forecast_kwh: f64,
+    pub actual_kwh: f64,
+    /// Mean absolute percentage error over the intervals with positive actual
+    /// demand; `None` when there are none.
+    pub mape_pct: Option<f64>,
+    /// Mean of forecast minus actual: positive when the model over-forecasts.
+    pub bias_kwh: f64,
+    /// Total forecast minus total actual, relative to the total actual.
+    pub bias_pct: Option<f64>,
+}
+
+/// Score `pairs` per feeder, model and horizon, ordered by those keys.
+pub fn score(pairs: &[ForecastPair]) -> Vec<ForecastAccuracy> {
+    #[derive(Default)]
+    struct Totals {
+        samples: i64,
+        forecast: f64,
+        actual: f64,
+        ape_sum: f64,
+        ape_samples: i64,
+    }
+
+    let mut groups: BTreeMap<(&str, &str, i32), Totals> = BTreeMap::new();
+    for p in pairs {
+        let horizon = (p.ts - p.issued_at).whole_hours() as i32;
+        let t = groups.entry((&p.feeder_id, &p.model, horizon)).or_default();
+        t.samples += 1;
+        t.forecast += p.forecast_kwh;
+        t.actual += p.actual_kwh;
+        if p.actual_kwh > 0.0 {
+            t.ape_sum += (p.forecast_kwh - p.actual_kwh).abs() / p.actual_kwh;
+            t.ape_samples += 1;
+        }
+    }
+
+    groups
+        .into_iter()
+        .map(|((feeder_id, model, horizon_hours), t)| ForecastAccuracy {
+            feeder_id: feeder_id.to_string(),
+            model: model.to_string(),
+            horizon_hours,
+            samples: t.samples,
+            forecast_kwh: t.forecast,
+            actual_kwh: t.actual,
+            mape_pct: (t.ape_samples > 0).then(|| 100.0 * t.ape_sum / t.ape_samples as f64),
+            bias_kwh: (t.forecast - t.actual) / t.samples as f64,
+            bias_pct: (t.actual > 0.0).then(|| 100.0 * (t.forecast - t.actual) / t.actual),
+        })
+        .collect()
+}
+
+/// Write one day's scores to `forecast_accuracy`.
+///
+/// The table deduplicates on `(day, feeder_id, model, horizon_hours)`, so
+/// re-scoring a day replaces its rows.
+pub async fn store(pool: &PgPool, day: Date, results: &[ForecastAccuracy]) -> Result<u64, sqlx::Error> {
+    if results.is_empty() {
+        return Ok(0);
+    }
+
+    let day_ts: OffsetDateTime = day.midnight().assume_utc();
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO forecast_accuracy (day, feeder_id, model, horizon_hours, samples, forecast_kwh, \
+         actual_kwh, mape_pct, bias_kwh, bias_pct) ",
+    );
+    builder.push_values(results, |mut b, r| {
+        b.push_bind(day_ts)
+            .push_bind(&r.feeder_id)
+            .push_bind(&r.model)
+            .push_bind(r.horizon_hours)
+            .push_bind(r.samples)
+            .push_bind(r.forecast_kwh)
+            .push_bind(r.actual_kwh)
+            .push_bind(r.mape_pct)
+            .push_bind(r.bias_kwh)
+            .push_bind(r.bias_pct);
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+/// Score every UTC day overlapping `[from, to)` and return the rows written.
+pub async fn recompute_range(pool: &PgPool, from: OffsetDateTime, to: OffsetDateTime) -> Result<u64, sqlx::Error> {
+    let mut written = 0;
+    let mut day = from.date();
+    while day.midnight().assume_utc() < to {
+        written += store(pool, day, &score(&pairs(pool, day).await?)).await?;
+        day = day.next_day().expect("date in range");
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn pair(feeder_id: &str, hours_ahead: i64, forecast_kwh: f64, actual_kwh: f64) -> ForecastPair {
+        let ts = datetime!(2024-06-01 12:00 UTC);
+        ForecastPair {
+            feeder_id: feeder_id.to_string(),
+            model: "gbm".to_string(),
+            ts,
+            issued_at: ts - Duration::minutes(hours_ahead * 60 + 15),
+            forecast_kwh,
+            actual_kwh,
+        }
+    }
+
+    #[test]
+    fn scores_each_feeder_and_horizon_separately() {
+        let results = score(&[
+            pair("f-1", 1, 110.0, 100.0),
+            pair("f-1", 1, 90.0, 120.0),
+            pair("f-1", 24, 100.0, 100.0),
+            // No actual demand: part of the bias, not of the MAPE.
+            pair("f-2", 1, 5.0, 0.0),
+        ]);
+
+        let keys: Vec<_> = results
+            .iter()
+            .map(|r| (r.feeder_id.as_str(), r.horizon_hours))
+            .collect();
+        assert_eq!(keys, [("f-1", 1), ("f-1", 24), ("f-2", 1)]);
+
+        let hour_ahead = &results[0];
+        assert_eq!(hour_ahead.samples, 2);
+        assert!((hour_ahead.mape_pct.unwrap() - 17.5).abs() < 1e-9);
+        assert_eq!(hour_ahead.bias_kwh, -10.0);
+        assert!((hour_ahead.bias_pct.unwrap() + 100.0 * 20.0 / 220.0).abs() < 1e-9);
+
+        assert_eq!(results[1].mape_pct, Some(0.0));
+        assert_eq!(results[2].mape_pct, None);
+        assert_eq!(results[2].bias_kwh, 5.0);
+        assert_eq!(results[2].bias_pct, None);
+    }
+}
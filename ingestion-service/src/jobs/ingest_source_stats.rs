@@ -0,0 +1,246 @@
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+/// Default window (before the end of the day) searched for rows that arrived
+/// on the day. Rows delivered later than this after their `ts` are not counted.
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 35;
+
+/// Per-`source_system` ingestion statistics for one day of arrivals.
+///
+/// Lateness is `ingested_at - ts` in seconds, bucketed for the distribution.
+/// `reject_rate` is `rejected / (records + rejected)`. `duplicate_rate` is the
+/// share of records received that repeated a recent `event_id`, from
+/// `ingest_duplicates`; unset when duplicates aren't counted.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct SourceStats {
+    pub source_system: String,
+    pub records: i64,
+    #[sqlx(default)]
+    pub rejected: i64,
+    #[sqlx(default)]
+    pub reject_rate: Option<f64>,
+    #[sqlx(default)]
+    pub duplicates: i64,
+    #[sqlx(default)]
+    pub duplicate_rate: Option<f64>,
+    pub distinct_meters: i64,
+    pub avg_lateness_secs: Option<f64>,
+    pub max_lateness_secs: Option<f64>,
+    pub late_within_1h: i64,
+    pub late_1h_to_24h: i64,
+    pub late_24h_to_72h: i64,
+    pub late_over_72h: i64,
+}
+
+impl SourceStats {
+    fn rejected_only(source_system: String, rejected: i64) -> Self {
+        Self {
+            source_system,
+            records: 0,
+            rejected,
+            reject_rate: None,
+            duplicates: 0,
+            duplicate_rate: None,
+            distinct_meters: 0,
+            avg_lateness_secs: None,
+            max_lateness_secs: None,
+            late_within_1h: 0,
+            late_1h_to_24h: 0,
+            late_24h_to_72h: 0,
+            late_over_72h: 0,
+        }
+    }
+}
+
+/// Bind parameters: `$1` day start, `$2` day end, `$3` lookback start (on `ts`).
+const ACCEPTED_SQL: &str = r#"
+SELECT
+    source_system,
+    count() AS records,
+    count_distinct(meter_id) AS distinct_meters,
+    avg(lateness) AS avg_lateness_secs,
+    max(lateness) AS max_lateness_secs,
+    sum(CASE WHEN lateness <= 3600 THEN 1 ELSE 0 END) AS late_within_1h,
+    sum(CASE WHEN lateness > 3600 AND lateness <= 86400 THEN 1 ELSE 0 END) AS late_1h_to_24h,
+    sum(CASE WHEN lateness > 86400 AND lateness <= 259200 THEN 1 ELSE 0 END) AS late_24h_to_72h,
+    sum(CASE WHEN lateness > 259200 THEN 1 ELSE 0 END) AS late_over_72h
+FROM (
+    SELECT
+        coalesce(source_system, 'unknown') AS source_system,
+        meter_id,
+        (cast(ingested_at AS LONG) - cast(ts AS LONG)) / 1000000.0 AS lateness
+    FROM meter_usage
+    WHERE ts >= $3 AND ts < $2
+      AND ingested_at >= $1 AND ingested_at < $2
+)
+GROUP BY source_system
+"#;
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const REJECTED_SQL: &str = r#"
+SELECT coalesce(source_system, 'unknown') AS source_system, count() AS rejected
+FROM ingest_rejects
+WHERE table_name = 'meter_usage'
+  AND ts >= $1 AND ts < $2
+GROUP BY source_system
+"#;
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const DUPLICATES_SQL: &str = r#"
+SELECT coalesce(source_system, 'unknown') AS source_system, sum(records) AS received, sum(duplicates) AS duplicates
+FROM ingest_duplicates
+WHERE table_name = 'meter_usage'
+  AND ts >= $1 AND ts < $2
+GROUP BY source_system
+"#;
+
+/// Combine accepted-row stats with reject counts, filling in reject rates.
+///
+/// Sources with only rejects still get a row. Output is sorted by `source_system`.
+pub fn merge_rejects(mut stats: Vec<SourceStats>, rejected: Vec<(String, i64)>) -> Vec<SourceStats> {
+    for (source_system, count) in rejected {
+        match stats.iter_mut().find(|s| s.source_system == source_system) {
+            Some(s) => s.rejected = count,
+            None => stats.push(SourceStats::rejected_only(source_system, count)),
+        }
+    }
+
+    for s in &mut stats {
+        let total = s.records + s.rejected;
+        s.reject_rate = (total > 0).then(|| s.rejected as f64 / total as f64);
+    }
+
+    stats.sort_by(|a, b| a.source_system.cmp(&b.source_system));
+    stats
+}
+
+/// Fill in duplicate counts and rates from `(source_system, received, duplicates)`.
+///
+/// Sources whose records were all duplicates still get a row. Output is sorted
+/// by `source_system`.
+pub fn merge_duplicates(mut stats: Vec<SourceStats>, duplicates: Vec<(String, i64, i64)>) -> Vec<SourceStats> {
+    for (source_system, received, count) in duplicates {
+        let s = match stats.iter().position(|s| s.source_system == source_system) {
+            Some(i) => &mut stats[i],
+            None => {
+                stats.push(SourceStats::rejected_only(source_system, 0));
+                stats.last_mut().expect("just pushed")
+            }
+        };
+        s.duplicates = count;
+        s.duplicate_rate = (received > 0).then(|| count as f64 / received as f64);
+    }
+
+    stats.sort_by(|a, b| a.source_system.cmp(&b.source_system));
+    stats
+}
+
+/// Compute statistics for meter usage that arrived on `day` (UTC).
+pub async fn compute(pool: &PgPool, day: Date, lookback_days: i64) -> Result<Vec<SourceStats>, sqlx::Error> {
+    let start = day.midnight().assume_utc();
+    let end = start + Duration::days(1);
+    let lookback_start = end - Duration::days(lookback_days);
+
+    let accepted = sqlx::query_as::<_, SourceStats>(ACCEPTED_SQL)
+        .bind(start)
+        .bind(end)
+        .bind(lookback_start)
+        .fetch_all(pool)
+        .await?;
+
+    let rejected = sqlx::query_as::<_, (String, i64)>(REJECTED_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    let duplicates = sqlx::query_as::<_, (String, i64, i64)>(DUPLICATES_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(merge_duplicates(merge_rejects(accepted, rejected), duplicates))
+}
+
+/// Write one day's statistics to `ingest_source_stats`.
+///
+/// The table deduplicates on `(day, source_system)`, so re-running a day
+/// replaces its rows.
+pub async fn store(pool: &PgPool, day: Date, stats: &[SourceStats]) -> Result<u64, sqlx::Error> {
+    if stats.is_empty() {
+        return Ok(0);
+    }
+
+    let day_ts: OffsetDateTime = day.midnight().assume_utc();
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO ingest_source_stats (day, source_system, records, rejected, reject_rate, duplicates, \
+         duplicate_rate, distinct_meters, avg_lateness_secs, max_lateness_secs, late_within_1h, late_1h_to_24h, \
+         late_24h_to_72h, late_over_72h) ",
+    );
+    builder.push_values(stats, |mut b, s| {
+        b.push_bind(day_ts)
+            .push_bind(&s.source_system)
+            .push_bind(s.records)
+            .push_bind(s.rejected)
+            .push_bind(s.reject_rate)
+            .push_bind(s.duplicates)
+            .push_bind(s.duplicate_rate)
+            .push_bind(s.distinct_meters)
+            .push_bind(s.avg_lateness_secs)
+            .push_bind(s.max_lateness_secs)
+            .push_bind(s.late_within_1h)
+            .push_bind(s.late_1h_to_24h)
+            .push_bind(s.late_24h_to_72h)
+            .push_bind(s.late_over_72h);
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accepted(source_system: &str, records: i64) -> SourceStats {
+        SourceStats {
+            records,
+            ..SourceStats::rejected_only(source_system.to_string(), 0)
+        }
+    }
+
+    #[test]
+    fn merge_fills_reject_rates_and_keeps_reject_only_sources() {
+        let stats = merge_rejects(
+            vec![accepted("vendor-b", 90), accepted("vendor-a", 100)],
+            vec![("vendor-b".to_string(), 10), ("vendor-c".to_string(), 5)],
+        );
+
+        let names: Vec<_> = stats.iter().map(|s| s.source_system.as_str()).collect();
+        assert_eq!(names, ["vendor-a", "vendor-b", "vendor-c"]);
+
+        assert_eq!(stats[0].reject_rate, Some(0.0));
+        assert_eq!(stats[1].rejected, 10);
+        assert_eq!(stats[1].reject_rate, Some(0.1));
+        assert_eq!(stats[2].records, 0);
+        assert_eq!(stats[2].reject_rate, Some(1.0));
+    }
+
+    #[test]
+    fn merge_fills_duplicate_rates_and_keeps_duplicate_only_sources() {
+        let stats = merge_duplicates(
+            vec![accepted("vendor-a", 100), accepted("vendor-b", 90)],
+            vec![("vendor-b".to_string(), 120, 30), ("vendor-0".to_string(), 8, 8)],
+        );
+
+        let rates: Vec<_> = stats
+            .iter()
+            .map(|s| (s.source_system.as_str(), s.duplicates, s.duplicate_rate))
+            .collect();
+        assert_eq!(
+            rates,
+            [("vendor-0", 8, Some(1.0)), ("vendor-a", 0, None), ("vendor-b", 30, Some(0.25))]
+        );
+    }
+}
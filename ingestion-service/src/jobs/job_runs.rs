@@ -0,0 +1,38 @@
+use std::{fmt::Display, future::Future, time::Instant};
+
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+/// Append one run to `job_runs`. `error` is `None` for a successful run.
+pub async fn record(
+    pool: &PgPool,
+    job: &str,
+    started_at: OffsetDateTime,
+    duration_secs: f64,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO job_runs (ts, job, duration_secs, status, error) VALUES ($1, $2, $3, $4, $5)")
+        .bind(started_at)
+        .bind(job)
+        .bind(duration_secs)
+        .bind(if error.is_some() { "failed" } else { "ok" })
+        .bind(error)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Run `fut` and record its runtime and outcome as a run of `job`.
+///
+/// Failing to record the run is logged, not returned: the job's own result wins.
+pub async fn tracked<T, E: Display>(pool: &PgPool, job: &str, fut: impl Future<Output = Result<T, E>>) -> Result<T, E> {
+    let started_at = OffsetDateTime::now_utc();
+    let started = Instant::now();
+    let result = fut.await;
+
+    let error = result.as_ref().err().map(|e| e.to_string());
+    if let Err(e) = record(pool, job, started_at, started.elapsed().as_secs_f64(), error.as_deref()).await {
+        tracing::warn!(job, error = %e, "failed to record job run");
+    }
+    result
+}
@@ -0,0 +1,403 @@
+use std::collections::HashMap;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_distr::StandardNormal;
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::OffsetDateTime;
+
+use super::{
+    feeder_balance::FeederBalanceOptions,
+    partitions::{drop_partitions_sql, PartitionBy},
+};
+use crate::config::LossUncertaintyConfig;
+
+/// Partitioning of `feeder_loss_uncertainty` (see `sql/schema/03_mapping_tables.sql`).
+pub const PARTITION_BY: PartitionBy = PartitionBy::Month;
+
+/// Rows per `INSERT` statement.
+const INSERT_CHUNK: usize = 1000;
+
+/// Balance rows with a loss value, as `(ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_pct)`.
+/// Bind parameters: `$1` start, `$2` end.
+const BALANCE_SQL: &str = "SELECT ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_pct \
+     FROM feeder_energy_balance WHERE ts >= $1 AND ts < $2 AND loss_pct IS NOT NULL";
+
+/// Sum of squared unit energies per feeder interval, as `(ts, feeder_id, kwh_sq)`;
+/// joined like the generation side of `feeder_balance`. Bind parameters: `$1` start, `$2` end.
+const GENERATION_SQL: &str = r#"
+    SELECT ts, feeder_id, SUM(kwh * kwh) AS kwh_sq
+    FROM (
+        SELECT go.ts, pfm.feeder_id, go.mw * 0.25 AS kwh      -- assume 15-min intervals
+        FROM generation_output go
+        JOIN plant_feeder_map pfm
+          ON pfm.plant_id = go.plant_id
+         AND (pfm.unit_id IS NULL OR pfm.unit_id = go.unit_id)
+         AND pfm.from_ts <= go.ts
+         AND pfm.to_ts   >  go.ts
+        WHERE go.ts >= $1 AND go.ts < $2
+    )
+    GROUP BY ts, feeder_id
+"#;
+
+/// Sum of squared meter energies per feeder interval and meter type, as
+/// `(ts, feeder_id, meter_type, kwh_sq)`; joined like the demand side of
+/// `feeder_balance`, with the `meters` version in effect at `ts`.
+/// Bind parameters: `$1` start, `$2` end.
+const DEMAND_SQL: &str = r#"
+    SELECT ts, feeder_id, meter_type, SUM(kwh * kwh) AS kwh_sq
+    FROM (
+        SELECT mu.ts, mfm.feeder_id, m.meter_type, mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) AS kwh
+        FROM meter_usage mu
+        ASOF JOIN meters m ON (meter_id)
+        JOIN meter_feeder_map mfm
+          ON mfm.meter_id = mu.meter_id
+         AND mfm.from_ts <= mu.ts
+         AND mfm.to_ts   >  mu.ts
+        LEFT JOIN meter_scale_map msm
+          ON msm.meter_id = mu.meter_id
+         AND msm.from_ts <= mu.ts
+         AND msm.to_ts   >  mu.ts
+        WHERE mu.ts >= $1 AND mu.ts < $2
+    )
+    GROUP BY ts, feeder_id, meter_type
+"#;
+
+/// Relative error distributions of generation and customer metering.
+///
+/// A meter within its accuracy class is taken to be equally likely anywhere
+/// in ±class%, independently of other meters, so its standard uncertainty is
+/// class / √3 (GUM type B, rectangular). The error of a feeder's total is the
+/// sum of its meters' errors, drawn from the normal distribution with that
+/// sum's variance.
+#[derive(Debug, Clone)]
+pub struct ErrorModel {
+    samples: u32,
+    confidence: f64,
+    seed: u64,
+    generation: f64,
+    default_meter: f64,
+    meter_types: HashMap<String, f64>,
+}
+
+fn standard_uncertainty(name: &str, class: f64) -> Result<f64, String> {
+    if !class.is_finite() || class < 0.0 {
+        return Err(format!(
+            "{name}: accuracy class must be a non-negative percentage, got {class}"
+        ));
+    }
+    Ok(class / 100.0 / 3f64.sqrt())
+}
+
+impl ErrorModel {
+    pub fn new(cfg: &LossUncertaintyConfig) -> Result<Self, String> {
+        if cfg.samples < 2 {
+            return Err(format!("samples must be at least 2, got {}", cfg.samples));
+        }
+        if !(cfg.confidence > 0.0 && cfg.confidence < 1.0) {
+            return Err(format!("confidence must be within (0, 1), got {}", cfg.confidence));
+        }
+        Ok(Self {
+            samples: cfg.samples,
+            confidence: cfg.confidence,
+            seed: cfg.seed,
+            generation: standard_uncertainty("generation_class", cfg.generation_class)?,
+            default_meter: standard_uncertainty("default_meter_class", cfg.default_meter_class)?,
+            meter_types: cfg
+                .meter_classes
+                .iter()
+                .map(|(meter_type, class)| Ok((meter_type.clone(), standard_uncertainty(meter_type, *class)?)))
+                .collect::<Result<_, String>>()?,
+        })
+    }
+
+    fn meter(&self, meter_type: Option<&str>) -> f64 {
+        meter_type
+            .and_then(|t| self.meter_types.get(t))
+            .copied()
+            .unwrap_or(self.default_meter)
+    }
+
+    /// Draws of one feeder interval; the same for the same feeder, interval and seed.
+    fn rng(&self, ts: OffsetDateTime, feeder_id: &str) -> StdRng {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&self.seed.to_le_bytes());
+        hasher.update(&ts.unix_timestamp_nanos().to_le_bytes());
+        hasher.update(feeder_id.as_bytes());
+        StdRng::from_seed(*hasher.finalize().as_bytes())
+    }
+}
+
+/// Energies of one feeder interval, with the variances of their metering errors (kWh²).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BalanceInputs {
+    pub kwh_gen: f64,
+    pub kwh_demand: f64,
+    pub gen_variance: f64,
+    pub demand_variance: f64,
+}
+
+/// Distribution of an interval's `loss_pct` under metering error.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LossInterval {
+    /// Bounds of the central `confidence` interval of the draws.
+    pub low: f64,
+    pub high: f64,
+    pub stddev: f64,
+    /// Share of draws with |loss_pct| above the alert threshold.
+    pub alert_probability: f64,
+    /// The whole interval lies beyond the alert threshold.
+    pub alert: bool,
+}
+
+/// Propagate metering error through `loss_pct = (gen - demand) / gen`.
+///
+/// `inputs.kwh_gen` must be positive.
+pub fn simulate(
+    inputs: &BalanceInputs,
+    model: &ErrorModel,
+    loss_alert_threshold: f64,
+    rng: &mut impl Rng,
+) -> LossInterval {
+    let gen_sd = inputs.gen_variance.sqrt();
+    let demand_sd = inputs.demand_variance.sqrt();
+    let mut draws: Vec<f64> = (0..model.samples)
+        .map(|_| {
+            let gen = inputs.kwh_gen + gen_sd * rng.sample::<f64, _>(StandardNormal);
+            let demand = inputs.kwh_demand + demand_sd * rng.sample::<f64, _>(StandardNormal);
+            (gen - demand) / gen
+        })
+        .collect();
+    draws.sort_by(f64::total_cmp);
+
+    let n = draws.len() as f64;
+    let quantile = |q: f64| draws[((n - 1.0) * q).round() as usize];
+    let tail = (1.0 - model.confidence) / 2.0;
+    let (low, high) = (quantile(tail), quantile(1.0 - tail));
+    let mean = draws.iter().sum::<f64>() / n;
+    let stddev = (draws.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt();
+    let alerts = draws.iter().filter(|d| d.abs() > loss_alert_threshold).count();
+
+    LossInterval {
+        low,
+        high,
+        stddev,
+        alert_probability: alerts as f64 / n,
+        alert: low > loss_alert_threshold || high < -loss_alert_threshold,
+    }
+}
+
+struct Row {
+    ts: OffsetDateTime,
+    feeder_id: String,
+    loss_pct: f64,
+    interval: LossInterval,
+}
+
+/// Recompute the whole `feeder_loss_uncertainty` table from `feeder_energy_balance`.
+///
+/// Returns the number of rows inserted.
+pub async fn recompute(pool: &PgPool, cfg: &LossUncertaintyConfig, opts: &FeederBalanceOptions) -> anyhow::Result<u64> {
+    let model = ErrorModel::new(cfg).map_err(anyhow::Error::msg)?;
+    sqlx::query("TRUNCATE TABLE feeder_loss_uncertainty;")
+        .execute(pool)
+        .await?;
+
+    let (first, last): (Option<OffsetDateTime>, Option<OffsetDateTime>) =
+        sqlx::query_as("SELECT min(ts), max(ts) FROM feeder_energy_balance")
+            .fetch_one(pool)
+            .await?;
+    let (Some(first), Some(last)) = (first, last) else {
+        return Ok(0);
+    };
+    let (from, to) = PARTITION_BY.widen(first, last + time::Duration::microseconds(1));
+    run_partitions(pool, &model, opts, from, to).await
+}
+
+/// Recompute `feeder_loss_uncertainty` for `[from, to)`, widened to whole partitions.
+///
+/// Like [`feeder_balance::recompute_range`](super::feeder_balance::recompute_range),
+/// the partitions overlapping the range are dropped and rebuilt. Returns the rows inserted.
+pub async fn recompute_range(
+    pool: &PgPool,
+    cfg: &LossUncertaintyConfig,
+    opts: &FeederBalanceOptions,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    let model = ErrorModel::new(cfg).map_err(anyhow::Error::msg)?;
+    let (from, to) = PARTITION_BY.widen(from, to);
+    sqlx::query(&drop_partitions_sql("feeder_loss_uncertainty", from, to))
+        .execute(pool)
+        .await?;
+    run_partitions(pool, &model, opts, from, to).await
+}
+
+/// Simulate `[from, to)` one partition at a time, so only a month of inputs is held in memory.
+async fn run_partitions(
+    pool: &PgPool,
+    model: &ErrorModel,
+    opts: &FeederBalanceOptions,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let mut start = from;
+    while start < to {
+        let end = PARTITION_BY.next(start).min(to);
+        let rows = simulate_range(pool, model, opts, start, end).await?;
+        for chunk in rows.chunks(INSERT_CHUNK) {
+            let mut builder = QueryBuilder::<Postgres>::new(
+                "INSERT INTO feeder_loss_uncertainty (ts, feeder_id, loss_pct, loss_pct_low, loss_pct_high, \
+                 loss_pct_stddev, alert_probability, alert, confidence, samples) ",
+            );
+            builder.push_values(chunk, |mut b, row| {
+                b.push_bind(row.ts)
+                    .push_bind(&row.feeder_id)
+                    .push_bind(row.loss_pct)
+                    .push_bind(row.interval.low)
+                    .push_bind(row.interval.high)
+                    .push_bind(row.interval.stddev)
+                    .push_bind(row.interval.alert_probability)
+                    .push_bind(row.interval.alert)
+                    .push_bind(model.confidence)
+                    .push_bind(model.samples as i32);
+            });
+            builder.build().execute(pool).await?;
+        }
+        written += rows.len() as u64;
+        start = end;
+    }
+    Ok(written)
+}
+
+async fn simulate_range(
+    pool: &PgPool,
+    model: &ErrorModel,
+    opts: &FeederBalanceOptions,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<Row>, sqlx::Error> {
+    let balance: Vec<(OffsetDateTime, String, f64, f64, f64)> =
+        sqlx::query_as(BALANCE_SQL).bind(from).bind(to).fetch_all(pool).await?;
+    let mut inputs: HashMap<(OffsetDateTime, String), (f64, BalanceInputs)> = balance
+        .into_iter()
+        .map(|(ts, feeder_id, kwh_gen, kwh_demand, loss_pct)| {
+            let inputs = BalanceInputs {
+                kwh_gen,
+                kwh_demand,
+                ..Default::default()
+            };
+            ((ts, feeder_id), (loss_pct, inputs))
+        })
+        .collect();
+
+    let generation: Vec<(OffsetDateTime, String, f64)> = sqlx::query_as(GENERATION_SQL)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    for (ts, feeder_id, kwh_sq) in generation {
+        if let Some((_, i)) = inputs.get_mut(&(ts, feeder_id)) {
+            i.gen_variance += kwh_sq * model.generation.powi(2);
+        }
+    }
+
+    let demand: Vec<(OffsetDateTime, String, Option<String>, f64)> =
+        sqlx::query_as(DEMAND_SQL).bind(from).bind(to).fetch_all(pool).await?;
+    for (ts, feeder_id, meter_type, kwh_sq) in demand {
+        if let Some((_, i)) = inputs.get_mut(&(ts, feeder_id)) {
+            i.demand_variance += kwh_sq * model.meter(meter_type.as_deref()).powi(2);
+        }
+    }
+
+    Ok(inputs
+        .into_iter()
+        .filter(|(_, (_, i))| i.kwh_gen > 0.0)
+        .map(|((ts, feeder_id), (loss_pct, i))| {
+            let mut rng = model.rng(ts, &feeder_id);
+            Row {
+                interval: simulate(&i, model, opts.loss_alert_threshold, &mut rng),
+                ts,
+                feeder_id,
+                loss_pct,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn model(toml: &str) -> ErrorModel {
+        ErrorModel::new(&toml::from_str(toml).unwrap()).unwrap()
+    }
+
+    /// A feeder with 1000 kWh of class 0.5 generation metering and 500 class 1 meters.
+    fn feeder(model: &ErrorModel, loss: f64) -> BalanceInputs {
+        let kwh_demand = 1000.0 * (1.0 - loss);
+        let per_meter = kwh_demand / 500.0;
+        BalanceInputs {
+            kwh_gen: 1000.0,
+            kwh_demand,
+            gen_variance: (1000.0 * model.generation).powi(2),
+            demand_variance: 500.0 * (per_meter * model.meter(None)).powi(2),
+        }
+    }
+
+    #[test]
+    fn small_losses_within_metering_error_do_not_alert() {
+        let model = model("");
+        let mut rng = model.rng(datetime!(2024-06-01 00:15 UTC), "F12");
+        for loss in [0.023, 0.025] {
+            let interval = simulate(&feeder(&model, loss), &model, 0.02, &mut rng);
+            assert!(interval.low < 0.02 && interval.high > loss, "{loss}: {interval:?}");
+            assert!(!interval.alert);
+            assert!(interval.alert_probability > 0.5 && interval.alert_probability < 1.0);
+            // Generation metering dominates: 1000 kWh * 0.5% / √3.
+            assert!((interval.stddev - 0.0029).abs() < 0.0003, "{interval:?}");
+        }
+
+        let interval = simulate(&feeder(&model, 0.04), &model, 0.02, &mut rng);
+        assert!(interval.alert && interval.low > 0.02, "{interval:?}");
+        let interval = simulate(&feeder(&model, -0.04), &model, 0.02, &mut rng);
+        assert!(interval.alert && interval.high < -0.02, "{interval:?}");
+    }
+
+    #[test]
+    fn exact_metering_collapses_the_interval() {
+        let model = model("generation_class = 0.0\ndefault_meter_class = 0.0");
+        let mut rng = model.rng(datetime!(2024-06-01 00:15 UTC), "F12");
+        let interval = simulate(&feeder(&model, 0.023), &model, 0.02, &mut rng);
+        assert!((interval.low - 0.023).abs() < 1e-12 && (interval.high - 0.023).abs() < 1e-12);
+        assert!(interval.alert);
+        assert_eq!(interval.alert_probability, 1.0);
+    }
+
+    #[test]
+    fn draws_depend_on_feeder_interval_and_seed_only() {
+        let model_a = model("meter_classes = { ct_3ph = 0.5 }");
+        let inputs = feeder(&model_a, 0.02);
+        let ts = datetime!(2024-06-01 00:15 UTC);
+        let run = |m: &ErrorModel, feeder_id: &str| simulate(&inputs, m, 0.02, &mut m.rng(ts, feeder_id));
+        assert_eq!(run(&model_a, "F12"), run(&model_a, "F12"));
+        assert_ne!(run(&model_a, "F12"), run(&model_a, "F14"));
+        assert_ne!(run(&model_a, "F12"), run(&model("seed = 7"), "F12"));
+
+        assert!((model_a.meter(Some("ct_3ph")) - 0.005 / 3f64.sqrt()).abs() < 1e-15);
+        assert_eq!(model_a.meter(Some("smart_1ph")), model_a.meter(None));
+    }
+
+    #[test]
+    fn rejects_invalid_settings() {
+        for toml in [
+            "samples = 1",
+            "confidence = 1.0",
+            "generation_class = -0.2",
+            "meter_classes = { x = nan }",
+        ] {
+            assert!(ErrorModel::new(&toml::from_str(toml).unwrap()).is_err(), "{toml}");
+        }
+    }
+}
@@ -0,0 +1,26 @@
+pub mod annotations;
+pub mod backfill_verify;
+pub mod billing_cycles;
+pub mod clock_drift;
+pub mod contract_demand;
+pub mod dr_performance;
+pub mod feeder_balance;
+pub mod forecast_accuracy;
+pub mod ingest_source_stats;
+pub mod job_runs;
+pub mod loss_uncertainty;
+pub mod multiplier_changes;
+pub mod ndjson_shipper;
+pub mod ops_report;
+pub mod partitions;
+pub mod peak_watch;
+pub mod premise_usage;
+pub mod pseudo_measurements;
+pub mod reaggregate;
+pub mod registry;
+pub mod regulatory_export;
+pub mod research_export;
+pub mod rollups;
+pub mod settlement;
+pub mod state_estimation;
+pub mod triggers;
@@ -0,0 +1,247 @@
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+/// Default number of days of usage searched for a step.
+pub const DEFAULT_LOOKBACK_DAYS: i64 = 56;
+
+/// Default number of days with usage compared on each side of a step.
+pub const DEFAULT_WINDOW_DAYS: usize = 14;
+
+/// Default tolerated relative distance of a step from an integer ratio.
+pub const DEFAULT_RATIO_TOLERANCE: f64 = 0.05;
+
+/// Largest multiplier ratio considered; larger steps are more likely vacancies or data errors.
+pub const MAX_RATIO: u32 = 10;
+
+/// Days whose median usage is below this (kWh) carry no usable signal.
+const MIN_MEDIAN_KWH: f64 = 0.5;
+
+/// Largest median absolute deviation, relative to the median, of either side
+/// of a step: usage that varies more than this hides any step.
+const MAX_DISPERSION: f64 = 0.35;
+
+/// Daily usage as billed, i.e. scaled by the recorded `meter_scale_map`
+/// multiplier: a recorded multiplier change leaves no step, an unrecorded one
+/// does. Bind parameters: `$1` start, `$2` end.
+const DAILY_USAGE_SQL: &str = r#"
+SELECT meter_id, day, sum(kwh) AS kwh
+FROM (
+    SELECT
+        mu.meter_id,
+        timestamp_floor('d', mu.ts) AS day,
+        mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) AS kwh
+    FROM meter_usage mu
+    LEFT JOIN meter_scale_map msm
+      ON msm.meter_id = mu.meter_id
+     AND msm.from_ts <= mu.ts
+     AND msm.to_ts   >  mu.ts
+    WHERE mu.ts >= $1 AND mu.ts < $2
+)
+GROUP BY meter_id, day
+ORDER BY meter_id, day
+"#;
+
+/// Search settings, see the `DEFAULT_*` constants.
+#[derive(Debug, Clone, Copy)]
+pub struct DetectionOptions {
+    pub lookback_days: i64,
+    pub window_days: usize,
+    pub ratio_tolerance: f64,
+}
+
+impl Default for DetectionOptions {
+    fn default() -> Self {
+        Self {
+            lookback_days: DEFAULT_LOOKBACK_DAYS,
+            window_days: DEFAULT_WINDOW_DAYS,
+            ratio_tolerance: DEFAULT_RATIO_TOLERANCE,
+        }
+    }
+}
+
+/// A step in a meter's daily usage that looks like an unrecorded CT/PT
+/// multiplier change.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MultiplierCandidate {
+    pub meter_id: String,
+    /// First day at the new level.
+    pub change_day: Date,
+    /// Median daily kWh of the window before and after the change.
+    pub before_kwh: f64,
+    pub after_kwh: f64,
+    /// `after_kwh / before_kwh`.
+    pub observed_ratio: f64,
+    /// The integer ratio (or its inverse for a drop) the step is attributed to;
+    /// usage from `change_day` on is off by this factor.
+    pub inferred_ratio: f64,
+}
+
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(f64::total_cmp);
+    let n = values.len();
+    if n % 2 == 1 {
+        values[n / 2]
+    } else {
+        (values[n / 2 - 1] + values[n / 2]) / 2.0
+    }
+}
+
+/// Median and relative median absolute deviation of `values`.
+fn level(values: &[f64]) -> (f64, f64) {
+    let mut sorted = values.to_vec();
+    let m = median(&mut sorted);
+    let mut deviations: Vec<f64> = values.iter().map(|v| (v - m).abs()).collect();
+    (m, median(&mut deviations) / m)
+}
+
+/// The sharpest step in `days` (daily kWh in day order) and whether it fits a
+/// multiplier change.
+///
+/// Each day with `window_days` days of usage on both sides is a possible
+/// change day; the step is placed at the one with the largest ratio between
+/// the means of the two windows, which a day on the wrong side always lowers.
+/// It is a candidate when both windows are steady and the ratio of their
+/// medians (or its inverse) is within `ratio_tolerance` of an integer from 2 to
+/// [`MAX_RATIO`].
+pub fn detect(meter_id: &str, days: &[(Date, f64)], opts: &DetectionOptions) -> Option<MultiplierCandidate> {
+    let w = opts.window_days.max(1);
+    if days.len() < 2 * w {
+        return None;
+    }
+    let values: Vec<f64> = days.iter().map(|(_, kwh)| *kwh).collect();
+    let mean = |window: &[f64]| window.iter().sum::<f64>() / window.len() as f64;
+
+    let split = (w..=values.len() - w)
+        .map(|k| (k, mean(&values[k - w..k]), mean(&values[k..k + w])))
+        .filter(|(_, before, after)| *before > 0.0 && *after > 0.0)
+        .max_by(|(_, b1, a1), (_, b2, a2)| (a1 / b1).ln().abs().total_cmp(&(a2 / b2).ln().abs()))?
+        .0;
+    let (before, before_dispersion) = level(&values[split - w..split]);
+    let (after, after_dispersion) = level(&values[split..split + w]);
+    if before < MIN_MEDIAN_KWH || after < MIN_MEDIAN_KWH {
+        return None;
+    }
+    if before_dispersion > MAX_DISPERSION || after_dispersion > MAX_DISPERSION {
+        return None;
+    }
+
+    let observed_ratio = after / before;
+    let step = observed_ratio.max(1.0 / observed_ratio);
+    let n = step.round();
+    if !(2.0..=f64::from(MAX_RATIO)).contains(&n) || (step - n).abs() > opts.ratio_tolerance * n {
+        return None;
+    }
+    Some(MultiplierCandidate {
+        meter_id: meter_id.to_string(),
+        change_day: days[split].0,
+        before_kwh: before,
+        after_kwh: after,
+        observed_ratio,
+        inferred_ratio: if observed_ratio > 1.0 { n } else { 1.0 / n },
+    })
+}
+
+/// Search the usage of every meter in the `lookback_days` before `day` (UTC, inclusive).
+pub async fn compute(
+    pool: &PgPool,
+    day: Date,
+    opts: &DetectionOptions,
+) -> Result<Vec<MultiplierCandidate>, sqlx::Error> {
+    let end = day
+        .next_day()
+        .expect("a day before the end of time")
+        .midnight()
+        .assume_utc();
+    let rows: Vec<(String, OffsetDateTime, f64)> = sqlx::query_as(DAILY_USAGE_SQL)
+        .bind(end - Duration::days(opts.lookback_days))
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    let mut candidates = Vec::new();
+    for meter_rows in rows.chunk_by(|a, b| a.0 == b.0) {
+        let days: Vec<(Date, f64)> = meter_rows.iter().map(|(_, d, kwh)| (d.date(), *kwh)).collect();
+        candidates.extend(detect(&meter_rows[0].0, &days, opts));
+    }
+    Ok(candidates)
+}
+
+/// Write candidates to `meter_multiplier_candidates`.
+///
+/// The table deduplicates on `(change_day, meter_id)`, so a step found again
+/// by later runs keeps one row, with the latest `detected_at`.
+pub async fn store(
+    pool: &PgPool,
+    candidates: &[MultiplierCandidate],
+    detected_at: OffsetDateTime,
+) -> Result<u64, sqlx::Error> {
+    if candidates.is_empty() {
+        return Ok(0);
+    }
+
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO meter_multiplier_candidates (change_day, meter_id, before_kwh, after_kwh, observed_ratio, \
+         inferred_ratio, detected_at) ",
+    );
+    builder.push_values(candidates, |mut b, c| {
+        b.push_bind(c.change_day.midnight().assume_utc())
+            .push_bind(&c.meter_id)
+            .push_bind(c.before_kwh)
+            .push_bind(c.after_kwh)
+            .push_bind(c.observed_ratio)
+            .push_bind(c.inferred_ratio)
+            .push_bind(detected_at);
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::date;
+
+    /// 40 days of usage wobbling around `base`, times `factor` from day 25 on.
+    fn series(base: f64, factor: f64) -> Vec<(Date, f64)> {
+        (0..40)
+            .map(|i| {
+                let wobble = 1.0 + 0.1 * ((i * 7 % 5) as f64 - 2.0) / 2.0;
+                let kwh = base * wobble * if i >= 25 { factor } else { 1.0 };
+                (date!(2024 - 03 - 01) + Duration::days(i), kwh)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn finds_integer_steps_and_their_day() {
+        let opts = DetectionOptions::default();
+        let up = detect("M-1", &series(30.0, 2.0), &opts).unwrap();
+        assert_eq!(up.change_day, date!(2024 - 03 - 26));
+        assert_eq!(up.inferred_ratio, 2.0);
+        assert!((up.observed_ratio - 2.0).abs() < 0.05);
+
+        // A CT replaced by one of 4x the ratio without updating the multiplier: usage drops to a quarter.
+        let down = detect("M-2", &series(30.0, 0.25), &opts).unwrap();
+        assert_eq!(down.inferred_ratio, 0.25);
+        assert_eq!(down.change_day, date!(2024 - 03 - 26));
+    }
+
+    #[test]
+    fn ignores_other_steps_noise_and_short_series() {
+        let opts = DetectionOptions::default();
+        // A new tenant using 60% more is not a multiplier change.
+        assert_eq!(detect("M-3", &series(30.0, 1.6), &opts), None);
+        assert_eq!(detect("M-4", &series(30.0, 1.0), &opts), None);
+        // Vacant premise: no signal.
+        assert_eq!(detect("M-5", &series(0.1, 3.0), &opts), None);
+        assert_eq!(detect("M-6", &series(30.0, 2.0)[..20], &opts), None);
+
+        let erratic: Vec<(Date, f64)> = series(30.0, 2.0)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (d, kwh))| (d, if i % 2 == 0 { kwh * 3.0 } else { kwh }))
+            .collect();
+        assert_eq!(detect("M-7", &erratic, &opts), None);
+    }
+}
@@ -0,0 +1,231 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{header, Method, Request};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use tokio::fs;
+
+use crate::{
+    config::{Compression, ShipperConfig},
+    sinks::file_ndjson::{NdjsonRecord, READY_SUFFIX},
+    sources::admission::PRIORITY_HEADER,
+};
+
+/// Split spooled NDJSON into request bodies of whole lines, each at most
+/// `max_records` lines and (unless a single line is larger) `max_bytes` bytes.
+///
+/// A trailing line without `\n` (left by an interrupted write) is not returned;
+/// the second value reports whether one was dropped.
+pub fn chunk_lines(data: &[u8], max_records: usize, max_bytes: usize) -> (Vec<&[u8]>, bool) {
+    let complete = data.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+    let truncated = complete < data.len();
+
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut records = 0;
+    let mut pos = 0;
+    while pos < complete {
+        let end = pos + data[pos..complete].iter().position(|&b| b == b'\n').expect("ends with newline") + 1;
+        if records > 0 && (records >= max_records.max(1) || end - start > max_bytes) {
+            chunks.push(&data[start..pos]);
+            start = pos;
+            records = 0;
+        }
+        records += 1;
+        pos = end;
+    }
+    if start < complete {
+        chunks.push(&data[start..complete]);
+    }
+    (chunks, truncated)
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    use std::io::Write;
+
+    let mut gz = flate2::write::GzEncoder::new(Vec::with_capacity(data.len() / 4), flate2::Compression::default());
+    gz.write_all(data)?;
+    gz.finish()
+}
+
+/// Forwards NDJSON files spooled by `file` sinks to the central HTTP sources.
+///
+/// Files are shipped oldest first, as `bulk` priority so they don't crowd out
+/// real-time traffic, and deleted (or archived) once every chunk was accepted.
+/// A failed file is retried from the start on the next pass; records carry the
+/// same content and `event_id`, so re-sent chunks dedup downstream. Uploads can
+/// be gzip-compressed and capped with `max_bytes_per_sec` (measured on the wire).
+pub struct NdjsonShipper {
+    cfg: ShipperConfig,
+    client: Client<HttpConnector, Full<Bytes>>,
+}
+
+impl NdjsonShipper {
+    pub fn new(cfg: ShipperConfig) -> Self {
+        Self {
+            cfg,
+            client: Client::builder(TokioExecutor::new()).build_http(),
+        }
+    }
+
+    fn endpoint(&self, file_name: &str) -> Option<String> {
+        let (base, kind) = if file_name.starts_with(&format!("{}-", MeterUsage::KIND)) {
+            (self.cfg.meter_usage_url.as_ref()?, MeterUsage::KIND)
+        } else if file_name.starts_with(&format!("{}-", GenerationOutput::KIND)) {
+            (self.cfg.generation_output_url.as_ref()?, GenerationOutput::KIND)
+        } else {
+            return None;
+        };
+        Some(format!("{}/ingest/{kind}/ndjson", base.trim_end_matches('/')))
+    }
+
+    /// Ship every ready file once. Returns the number of files shipped.
+    pub async fn ship_pending(&self) -> anyhow::Result<usize> {
+        let mut ready = Vec::new();
+        let mut entries = fs::read_dir(&self.cfg.dir)
+            .await
+            .with_context(|| format!("reading {}", self.cfg.dir.display()))?;
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if !name.ends_with(READY_SUFFIX) {
+                continue;
+            }
+            if let Some(url) = self.endpoint(&name) {
+                ready.push((name, entry.path(), url));
+            }
+        }
+        // File names embed zero-padded creation millis, so this is oldest first per kind.
+        ready.sort();
+
+        let mut shipped = 0;
+        for (name, path, url) in ready {
+            self.ship_file(&path, &url).await.with_context(|| format!("shipping {name}"))?;
+            self.retire(&path, &name).await?;
+            metrics::counter!("ndjson_shipper_files_total").increment(1);
+            shipped += 1;
+        }
+        Ok(shipped)
+    }
+
+    /// Ship pending files every `poll_interval_secs`, forever.
+    ///
+    /// After a failed pass (e.g. the central service is unreachable) the wait
+    /// doubles up to `max_retry_backoff_secs`, and resets after a successful one.
+    pub async fn run(&self) {
+        let interval = Duration::from_secs(self.cfg.poll_interval_secs.max(1));
+        let max_backoff = Duration::from_secs(self.cfg.max_retry_backoff_secs).max(interval);
+        let mut wait = interval;
+        loop {
+            match self.ship_pending().await {
+                Ok(files) => {
+                    if files > 0 {
+                        tracing::info!(files, "shipped spooled NDJSON files");
+                    }
+                    wait = interval;
+                }
+                Err(e) => {
+                    metrics::counter!("ndjson_shipper_errors_total").increment(1);
+                    wait = (wait * 2).min(max_backoff);
+                    tracing::warn!(
+                        error = format!("{e:#}"),
+                        retry_in_secs = wait.as_secs(),
+                        "NDJSON shipping failed, retrying later"
+                    );
+                }
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    async fn ship_file(&self, path: &Path, url: &str) -> anyhow::Result<()> {
+        let data = fs::read(path).await?;
+        let (chunks, truncated) = chunk_lines(&data, self.cfg.max_request_records, self.cfg.max_request_bytes);
+        if truncated {
+            tracing::warn!(file = %path.display(), "dropping incomplete last line of spooled file");
+        }
+
+        for chunk in chunks {
+            let mut req = Request::builder()
+                .method(Method::POST)
+                .uri(url)
+                .header(header::CONTENT_TYPE, "application/x-ndjson")
+                .header(PRIORITY_HEADER, "bulk");
+            if let Some(token) = &self.cfg.auth_bearer_token {
+                req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let body = match self.cfg.compression {
+                Compression::None => Bytes::copy_from_slice(chunk),
+                Compression::Gzip => {
+                    req = req.header(header::CONTENT_ENCODING, "gzip");
+                    Bytes::from(gzip(chunk)?)
+                }
+            };
+            let wire_bytes = body.len();
+            let req = req.body(Full::new(body))?;
+            let started = Instant::now();
+
+            let resp = self.client.request(req).await?;
+            let status = resp.status();
+            if !status.is_success() {
+                let body = resp.into_body().collect().await.map(|b| b.to_bytes()).unwrap_or_default();
+                bail!("{url} returned {status}: {}", String::from_utf8_lossy(&body).trim());
+            }
+            metrics::counter!("ndjson_shipper_bytes_total").increment(chunk.len() as u64);
+            metrics::counter!("ndjson_shipper_wire_bytes_total").increment(wire_bytes as u64);
+
+            if let Some(rate) = self.cfg.max_bytes_per_sec.filter(|r| *r > 0) {
+                // Pace whole requests so the average upload rate stays within the cap.
+                let budget = Duration::from_secs_f64(wire_bytes as f64 / rate as f64);
+                if let Some(rest) = budget.checked_sub(started.elapsed()) {
+                    tokio::time::sleep(rest).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn retire(&self, path: &Path, name: &str) -> anyhow::Result<()> {
+        match &self.cfg.archive_dir {
+            Some(archive) => {
+                fs::create_dir_all(archive).await?;
+                let target: PathBuf = archive.join(name);
+                fs::rename(path, &target)
+                    .await
+                    .with_context(|| format!("archiving to {}", target.display()))?;
+            }
+            None => fs::remove_file(path).await?,
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_whole_lines_within_limits() {
+        let data = b"{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n{\"a\":4}\n{\"a\"";
+
+        let (chunks, truncated) = chunk_lines(data, 3, usize::MAX);
+        assert!(truncated);
+        assert_eq!(chunks, [&b"{\"a\":1}\n{\"a\":2}\n{\"a\":3}\n"[..], &b"{\"a\":4}\n"[..]]);
+
+        // 8 bytes per line, so at most two lines per 20-byte body.
+        let (chunks, _) = chunk_lines(&data[..32], 100, 20);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.len() == 16));
+
+        let (chunks, truncated) = chunk_lines(b"", 10, 10);
+        assert!(chunks.is_empty() && !truncated);
+    }
+}
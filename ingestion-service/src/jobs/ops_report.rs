@@ -0,0 +1,692 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context};
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{header, Method, Request};
+use hyper_util::{client::legacy::Client, rt::TokioExecutor};
+use serde::{Serialize, Serializer};
+use sqlx::postgres::PgPool;
+use time::{Date, Duration, OffsetDateTime};
+
+use crate::{
+    config::{ReportConfig, ReportFormat, SmtpConfig},
+    jobs::ingest_source_stats::DEFAULT_LOOKBACK_DAYS,
+};
+
+/// Tables reported on, one per ingest pipeline.
+pub const PIPELINES: [&str; 2] = ["meter_usage", "generation_output"];
+
+/// Records landed and rejected for one pipeline on the reported day.
+///
+/// `meter_usage` counts rows by `ingested_at`; `generation_output` has no
+/// arrival time and counts rows by `ts`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PipelineVolume {
+    pub pipeline: String,
+    pub records: i64,
+    pub rejected: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct RejectCount {
+    pub table_name: String,
+    pub reason: String,
+    pub rejected: i64,
+}
+
+/// Newest record of a table when the report was generated.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Freshness {
+    pub table_name: String,
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub latest_ts: Option<OffsetDateTime>,
+    pub lag_secs: Option<f64>,
+    pub stale: bool,
+}
+
+/// Alerts of one kind and rule raised on the reported day.
+///
+/// `kind` is `stream` (`stream_alerts`), `peak` (`peak_alerts`), `clock_drift`
+/// (`source_clock_drift`) or `feeder_loss` (`feeder_energy_balance`);
+/// `distinct_ids` counts the meters, plants, feeders or sources involved.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AlertGroup {
+    pub kind: String,
+    pub name: String,
+    pub alerts: i64,
+    pub distinct_ids: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, sqlx::FromRow)]
+pub struct JobRunSummary {
+    pub job: String,
+    pub runs: i64,
+    pub failures: i64,
+    pub total_secs: f64,
+    pub max_secs: f64,
+    pub last_status: String,
+}
+
+/// Daily operations summary.
+///
+/// Volumes, rejects and alerts cover the reported day (UTC); freshness and
+/// job runs the 24 hours before `generated_at`, so a morning report for
+/// yesterday includes the night's jobs.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpsReport {
+    #[serde(serialize_with = "serialize_day")]
+    pub day: Date,
+    #[serde(with = "time::serde::rfc3339")]
+    pub generated_at: OffsetDateTime,
+    pub pipelines: Vec<PipelineVolume>,
+    pub rejects: Vec<RejectCount>,
+    pub freshness: Vec<Freshness>,
+    pub alerts: Vec<AlertGroup>,
+    pub job_runs: Vec<JobRunSummary>,
+}
+
+fn serialize_day<S: Serializer>(day: &Date, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_str(day)
+}
+
+impl OpsReport {
+    /// Stale tables and jobs whose last run failed.
+    pub fn issues(&self) -> usize {
+        self.freshness.iter().filter(|f| f.stale).count()
+            + self.job_runs.iter().filter(|j| j.last_status != "ok").count()
+    }
+
+    pub fn subject(&self) -> String {
+        match self.issues() {
+            0 => format!("Ingestion report {}", self.day),
+            n => format!("Ingestion report {} ({n} issues)", self.day),
+        }
+    }
+}
+
+/// Bind parameters: `$1` day start, `$2` day end, `$3` lookback start (on `ts`).
+const METER_USAGE_RECORDS_SQL: &str = r#"
+SELECT count() FROM meter_usage
+WHERE ts >= $3 AND ts < $2
+  AND ingested_at >= $1 AND ingested_at < $2
+"#;
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const GENERATION_OUTPUT_RECORDS_SQL: &str = "SELECT count() FROM generation_output WHERE ts >= $1 AND ts < $2";
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const REJECTS_PER_TABLE_SQL: &str = r#"
+SELECT coalesce(table_name, 'unknown') AS table_name, count() AS rejected
+FROM ingest_rejects
+WHERE ts >= $1 AND ts < $2
+GROUP BY table_name
+"#;
+
+/// The 20 most frequent reject reasons. Bind parameters: `$1` day start, `$2` day end.
+const REJECT_REASONS_SQL: &str = r#"
+SELECT coalesce(table_name, 'unknown') AS table_name, coalesce(reason, '') AS reason, count() AS rejected
+FROM ingest_rejects
+WHERE ts >= $1 AND ts < $2
+GROUP BY table_name, reason
+ORDER BY rejected DESC
+LIMIT 20
+"#;
+
+/// Alert counts as `(a, b, alerts, distinct_ids)` per group. Bind parameters: `$1` day start, `$2` day end.
+const STREAM_ALERTS_SQL: &str = r#"
+SELECT rule, table_name, count() AS alerts, count_distinct(alert_id) AS distinct_ids
+FROM stream_alerts
+WHERE ts >= $1 AND ts < $2
+GROUP BY rule, table_name
+"#;
+
+const PEAK_ALERTS_SQL: &str = r#"
+SELECT level, status, count() AS alerts, count_distinct(scope_id) AS distinct_ids
+FROM peak_alerts
+WHERE ts >= $1 AND ts < $2
+GROUP BY level, status
+"#;
+
+/// `(alerts, distinct_ids)`. Bind parameters: `$1` day start, `$2` day end.
+const CLOCK_DRIFT_ALERTS_SQL: &str = r#"
+SELECT count() AS alerts, count_distinct(source_system) AS distinct_ids
+FROM source_clock_drift
+WHERE day >= $1 AND day < $2 AND alert
+"#;
+
+const FEEDER_LOSS_ALERTS_SQL: &str = r#"
+SELECT count() AS alerts, count_distinct(feeder_id) AS distinct_ids
+FROM feeder_energy_balance
+WHERE ts >= $1 AND ts < $2 AND alert
+"#;
+
+/// Bind parameters: `$1` window start, `$2` window end.
+const JOB_RUNS_SQL: &str = r#"
+SELECT
+    job,
+    count() AS runs,
+    sum(CASE WHEN status = 'failed' THEN 1 ELSE 0 END) AS failures,
+    sum(duration_secs) AS total_secs,
+    max(duration_secs) AS max_secs,
+    last(status) AS last_status
+FROM job_runs
+WHERE ts >= $1 AND ts < $2
+GROUP BY job
+ORDER BY job
+"#;
+
+/// The `limit` most frequent alert groups, dropping empty ones.
+pub fn top_alerts(mut groups: Vec<AlertGroup>, limit: usize) -> Vec<AlertGroup> {
+    groups.retain(|g| g.alerts > 0);
+    groups.sort_by(|a, b| {
+        b.alerts
+            .cmp(&a.alerts)
+            .then_with(|| (&a.kind, &a.name).cmp(&(&b.kind, &b.name)))
+    });
+    groups.truncate(limit);
+    groups
+}
+
+/// Gather the report for `day` (UTC), as of `now`.
+pub async fn compute(
+    pool: &PgPool,
+    day: Date,
+    now: OffsetDateTime,
+    cfg: &ReportConfig,
+) -> Result<OpsReport, sqlx::Error> {
+    let start = day.midnight().assume_utc();
+    let end = start + Duration::days(1);
+
+    let rejected: Vec<(String, i64)> = sqlx::query_as(REJECTS_PER_TABLE_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+    let mut pipelines = Vec::new();
+    for pipeline in PIPELINES {
+        let records: i64 = match pipeline {
+            "meter_usage" => sqlx::query_scalar(METER_USAGE_RECORDS_SQL)
+                .bind(start)
+                .bind(end)
+                .bind(end - Duration::days(DEFAULT_LOOKBACK_DAYS)),
+            _ => sqlx::query_scalar(GENERATION_OUTPUT_RECORDS_SQL).bind(start).bind(end),
+        }
+        .fetch_one(pool)
+        .await?;
+        pipelines.push(PipelineVolume {
+            pipeline: pipeline.to_string(),
+            records,
+            rejected: rejected.iter().find(|(t, _)| t == pipeline).map_or(0, |(_, n)| *n),
+        });
+    }
+
+    let rejects = sqlx::query_as::<_, RejectCount>(REJECT_REASONS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    let mut freshness = Vec::new();
+    for table in PIPELINES {
+        let latest_ts: Option<OffsetDateTime> = sqlx::query_scalar(&format!("SELECT max(ts) FROM {table}"))
+            .fetch_one(pool)
+            .await?;
+        let lag_secs = latest_ts.map(|ts| (now - ts).as_seconds_f64());
+        freshness.push(Freshness {
+            table_name: table.to_string(),
+            latest_ts,
+            lag_secs,
+            stale: lag_secs.is_none_or(|lag| lag > cfg.stale_after_secs as f64),
+        });
+    }
+
+    let group = |kind: &str, name: String, (alerts, distinct_ids): (i64, i64)| AlertGroup {
+        kind: kind.to_string(),
+        name,
+        alerts,
+        distinct_ids,
+    };
+    let mut alerts = Vec::new();
+    let stream: Vec<(String, String, i64, i64)> = sqlx::query_as(STREAM_ALERTS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+    alerts.extend(
+        stream
+            .into_iter()
+            .map(|(rule, table, n, ids)| group("stream", format!("{rule} ({table})"), (n, ids))),
+    );
+    let peak: Vec<(String, String, i64, i64)> = sqlx::query_as(PEAK_ALERTS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+    alerts.extend(
+        peak.into_iter()
+            .map(|(level, status, n, ids)| group("peak", format!("{level} {status}"), (n, ids))),
+    );
+    let drift = sqlx::query_as(CLOCK_DRIFT_ALERTS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_one(pool)
+        .await?;
+    alerts.push(group("clock_drift", "drift beyond threshold".to_string(), drift));
+    let losses = sqlx::query_as(FEEDER_LOSS_ALERTS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_one(pool)
+        .await?;
+    alerts.push(group("feeder_loss", "losses above threshold".to_string(), losses));
+
+    let job_runs = sqlx::query_as::<_, JobRunSummary>(JOB_RUNS_SQL)
+        .bind(now - Duration::days(1))
+        .bind(now)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(OpsReport {
+        day,
+        generated_at: now,
+        pipelines,
+        rejects,
+        freshness,
+        alerts: top_alerts(alerts, cfg.top_alerts),
+        job_runs,
+    })
+}
+
+fn secs(v: Option<f64>) -> String {
+    v.map(|v| format!("{v:.1}")).unwrap_or_default()
+}
+
+/// The report as CSV rows of `section,subject,detail,metric,value`.
+pub fn render_csv(report: &OpsReport) -> String {
+    let mut w = csv::Writer::from_writer(Vec::new());
+    let mut row = |section: &str, subject: &str, detail: &str, metric: &str, value: String| {
+        w.write_record([section, subject, detail, metric, &value])
+            .expect("writing to memory");
+    };
+
+    row("section", "subject", "detail", "metric", "value".to_string());
+    for p in &report.pipelines {
+        row("ingested", &p.pipeline, "", "records", p.records.to_string());
+        row("ingested", &p.pipeline, "", "rejected", p.rejected.to_string());
+    }
+    for r in &report.rejects {
+        row("rejects", &r.table_name, &r.reason, "rejected", r.rejected.to_string());
+    }
+    for f in &report.freshness {
+        let latest = f.latest_ts.map(|ts| ts.to_string()).unwrap_or_default();
+        row("freshness", &f.table_name, &latest, "lag_secs", secs(f.lag_secs));
+        row("freshness", &f.table_name, &latest, "stale", f.stale.to_string());
+    }
+    for a in &report.alerts {
+        row("alerts", &a.kind, &a.name, "alerts", a.alerts.to_string());
+        row("alerts", &a.kind, &a.name, "distinct_ids", a.distinct_ids.to_string());
+    }
+    for j in &report.job_runs {
+        row("jobs", &j.job, &j.last_status, "runs", j.runs.to_string());
+        row("jobs", &j.job, &j.last_status, "failures", j.failures.to_string());
+        row("jobs", &j.job, &j.last_status, "total_secs", secs(Some(j.total_secs)));
+        row("jobs", &j.job, &j.last_status, "max_secs", secs(Some(j.max_secs)));
+    }
+
+    String::from_utf8(w.into_inner().expect("writing to memory")).expect("CSV of UTF-8 fields")
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+fn html_table(out: &mut String, title: &str, header: &[&str], rows: Vec<(bool, Vec<String>)>) {
+    out.push_str(&format!("<h2>{}</h2>\n", escape_html(title)));
+    if rows.is_empty() {
+        out.push_str("<p>None.</p>\n");
+        return;
+    }
+    out.push_str("<table>\n<tr>");
+    for h in header {
+        out.push_str(&format!("<th>{}</th>", escape_html(h)));
+    }
+    out.push_str("</tr>\n");
+    for (bad, cells) in rows {
+        out.push_str(if bad { "<tr class=\"bad\">" } else { "<tr>" });
+        for c in cells {
+            out.push_str(&format!("<td>{}</td>", escape_html(&c)));
+        }
+        out.push_str("</tr>\n");
+    }
+    out.push_str("</table>\n");
+}
+
+/// The report as a self-contained HTML page (stale tables and failed jobs highlighted).
+pub fn render_html(report: &OpsReport) -> String {
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>\
+         body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+         th, td {{ border: 1px solid #ccc; padding: 2px 8px; text-align: left; }} .bad {{ background: #fdd; }}\
+         </style>\n</head>\n<body>\n<h1>{title}</h1>\n<p>Generated {generated_at}.</p>\n",
+        title = escape_html(&report.subject()),
+        generated_at = report.generated_at,
+    );
+
+    html_table(
+        &mut out,
+        "Records ingested",
+        &["Pipeline", "Records", "Rejected"],
+        report
+            .pipelines
+            .iter()
+            .map(|p| {
+                (
+                    false,
+                    vec![p.pipeline.clone(), p.records.to_string(), p.rejected.to_string()],
+                )
+            })
+            .collect(),
+    );
+    html_table(
+        &mut out,
+        "Rejects by reason",
+        &["Table", "Reason", "Rejected"],
+        report
+            .rejects
+            .iter()
+            .map(|r| {
+                (
+                    false,
+                    vec![r.table_name.clone(), r.reason.clone(), r.rejected.to_string()],
+                )
+            })
+            .collect(),
+    );
+    html_table(
+        &mut out,
+        "Data freshness",
+        &["Table", "Latest record", "Lag (s)"],
+        report
+            .freshness
+            .iter()
+            .map(|f| {
+                let latest = f
+                    .latest_ts
+                    .map(|ts| ts.to_string())
+                    .unwrap_or_else(|| "no data".to_string());
+                (f.stale, vec![f.table_name.clone(), latest, secs(f.lag_secs)])
+            })
+            .collect(),
+    );
+    html_table(
+        &mut out,
+        "Top alerts",
+        &["Kind", "Alert", "Count", "Distinct ids"],
+        report
+            .alerts
+            .iter()
+            .map(|a| {
+                (
+                    false,
+                    vec![
+                        a.kind.clone(),
+                        a.name.clone(),
+                        a.alerts.to_string(),
+                        a.distinct_ids.to_string(),
+                    ],
+                )
+            })
+            .collect(),
+    );
+    html_table(
+        &mut out,
+        "Job runs (last 24h)",
+        &["Job", "Runs", "Failures", "Total (s)", "Longest (s)", "Last status"],
+        report
+            .job_runs
+            .iter()
+            .map(|j| {
+                (
+                    j.last_status != "ok",
+                    vec![
+                        j.job.clone(),
+                        j.runs.to_string(),
+                        j.failures.to_string(),
+                        secs(Some(j.total_secs)),
+                        secs(Some(j.max_secs)),
+                        j.last_status.clone(),
+                    ],
+                )
+            })
+            .collect(),
+    );
+
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Write `ops-report-<day>.csv` and `.html` to `dir`.
+pub async fn write_files(dir: &Path, report: &OpsReport) -> anyhow::Result<Vec<PathBuf>> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("creating {}", dir.display()))?;
+    let mut written = Vec::new();
+    for (ext, body) in [("csv", render_csv(report)), ("html", render_html(report))] {
+        let path = dir.join(format!("ops-report-{}.{ext}", report.day));
+        tokio::fs::write(&path, body)
+            .await
+            .with_context(|| format!("writing {}", path.display()))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+/// POST the report to `url` in `format`.
+pub async fn post_webhook(
+    url: &str,
+    format: ReportFormat,
+    auth_bearer_token: Option<&str>,
+    report: &OpsReport,
+) -> anyhow::Result<()> {
+    let (content_type, body) = match format {
+        ReportFormat::Json => ("application/json", serde_json::to_vec(report)?),
+        ReportFormat::Csv => ("text/csv; charset=utf-8", render_csv(report).into_bytes()),
+        ReportFormat::Html => ("text/html; charset=utf-8", render_html(report).into_bytes()),
+    };
+
+    let mut req = Request::builder()
+        .method(Method::POST)
+        .uri(url)
+        .header(header::CONTENT_TYPE, content_type);
+    if let Some(token) = auth_bearer_token {
+        req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+    }
+    let req = req.body(Full::new(Bytes::from(body)))?;
+
+    let client = Client::builder(TokioExecutor::new()).build_http();
+    let resp = client.request(req).await?;
+    let status = resp.status();
+    if !status.is_success() {
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map(|b| b.to_bytes())
+            .unwrap_or_default();
+        bail!("{url} returned {status}: {}", String::from_utf8_lossy(&body).trim());
+    }
+    Ok(())
+}
+
+/// Email the report: HTML body with the CSV attached.
+#[cfg(feature = "smtp")]
+pub async fn send_email(cfg: &SmtpConfig, report: &OpsReport) -> anyhow::Result<()> {
+    use lettre::{
+        message::{header::ContentType, Attachment, MultiPart, SinglePart},
+        transport::smtp::authentication::Credentials,
+        AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    };
+
+    if cfg.to.is_empty() {
+        bail!("report.smtp.to has no recipients");
+    }
+    let mut message = Message::builder().from(cfg.from.parse()?).subject(report.subject());
+    for to in &cfg.to {
+        message = message.to(to.parse().with_context(|| format!("invalid recipient '{to}'"))?);
+    }
+    let email = message.multipart(
+        MultiPart::mixed()
+            .singlepart(SinglePart::html(render_html(report)))
+            .singlepart(
+                Attachment::new(format!("ops-report-{}.csv", report.day))
+                    .body(render_csv(report), ContentType::parse("text/csv")?),
+            ),
+    )?;
+
+    let mut transport = if cfg.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&cfg.host)?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&cfg.host)
+    };
+    if let Some(port) = cfg.port {
+        transport = transport.port(port);
+    }
+    if let (Some(user), Some(password)) = (&cfg.username, &cfg.password) {
+        transport = transport.credentials(Credentials::new(user.clone(), password.clone()));
+    }
+    transport.build().send(email).await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "smtp"))]
+pub async fn send_email(_cfg: &SmtpConfig, _report: &OpsReport) -> anyhow::Result<()> {
+    bail!("report.smtp is configured but the service was built without the `smtp` feature")
+}
+
+/// Deliver the report to every configured destination.
+///
+/// A failing destination doesn't keep the report from the others; the first
+/// error is returned once all were tried.
+pub async fn deliver(cfg: &ReportConfig, report: &OpsReport) -> anyhow::Result<()> {
+    let mut first_err = None;
+    let mut fail = |target: &str, e: anyhow::Error| {
+        tracing::warn!(target, error = format!("{e:#}"), "ops report delivery failed");
+        first_err.get_or_insert(e.context(format!("delivering ops report to {target}")));
+    };
+
+    if let Some(dir) = &cfg.out_dir {
+        match write_files(dir, report).await {
+            Ok(paths) => tracing::info!(files = ?paths, "ops report written"),
+            Err(e) => fail("out_dir", e),
+        }
+    }
+    if let Some(url) = &cfg.webhook_url {
+        match post_webhook(
+            url,
+            cfg.webhook_format,
+            cfg.webhook_auth_bearer_token.as_deref(),
+            report,
+        )
+        .await
+        {
+            Ok(()) => tracing::info!(url, "ops report posted"),
+            Err(e) => fail("webhook", e),
+        }
+    }
+    if let Some(smtp) = &cfg.smtp {
+        match send_email(smtp, report).await {
+            Ok(()) => tracing::info!(recipients = smtp.to.len(), "ops report emailed"),
+            Err(e) => fail("smtp", e),
+        }
+    }
+
+    first_err.map_or(Ok(()), Err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    fn report() -> OpsReport {
+        OpsReport {
+            day: date!(2026 - 10 - 17),
+            generated_at: datetime!(2026-10-18 07:00 UTC),
+            pipelines: vec![PipelineVolume {
+                pipeline: "meter_usage".to_string(),
+                records: 1200,
+                rejected: 3,
+            }],
+            rejects: vec![RejectCount {
+                table_name: "meter_usage".to_string(),
+                reason: "kwh < 0, \"negative\"".to_string(),
+                rejected: 3,
+            }],
+            freshness: vec![Freshness {
+                table_name: "generation_output".to_string(),
+                latest_ts: Some(datetime!(2026-10-18 04:00 UTC)),
+                lag_secs: Some(10800.0),
+                stale: true,
+            }],
+            alerts: Vec::new(),
+            job_runs: vec![JobRunSummary {
+                job: "clock_drift".to_string(),
+                runs: 1,
+                failures: 0,
+                total_secs: 4.5,
+                max_secs: 4.5,
+                last_status: "ok".to_string(),
+            }],
+        }
+    }
+
+    #[test]
+    fn renders_csv_and_html() {
+        let report = report();
+        assert_eq!(report.subject(), "Ingestion report 2026-10-17 (1 issues)");
+
+        let csv = render_csv(&report);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "section,subject,detail,metric,value");
+        assert!(lines.contains(&"ingested,meter_usage,,records,1200"));
+        assert!(lines.contains(&"rejects,meter_usage,\"kwh < 0, \"\"negative\"\"\",rejected,3"));
+        assert!(lines.contains(&"jobs,clock_drift,ok,total_secs,4.5"));
+
+        let html = render_html(&report);
+        assert!(html.contains("<td>kwh &lt; 0, &quot;negative&quot;</td>"));
+        assert!(html.contains("<tr class=\"bad\"><td>generation_output</td>"));
+        assert!(html.contains("<h2>Top alerts</h2>\n<p>None.</p>"));
+    }
+
+    #[test]
+    fn keeps_the_most_frequent_alert_groups() {
+        let group = |kind: &str, name: &str, alerts| AlertGroup {
+            kind: kind.to_string(),
+            name: name.to_string(),
+            alerts,
+            distinct_ids: 1,
+        };
+        let top = top_alerts(
+            vec![
+                group("stream", "kwh_spike (meter_usage)", 40),
+                group("clock_drift", "drift beyond threshold", 0),
+                group("peak", "system new_peak", 2),
+                group("feeder_loss", "losses above threshold", 40),
+            ],
+            2,
+        );
+        let names: Vec<&str> = top.iter().map(|g| g.name.as_str()).collect();
+        assert_eq!(names, ["losses above threshold", "kwh_spike (meter_usage)"]);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fmt;
+
+use time::{format_description::well_known::Rfc3339, Date, Month, OffsetDateTime, Time, UtcOffset};
+
+/// Partition unit of a QuestDB table (`PARTITION BY ...`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionBy {
+    Day,
+    Month,
+    Year,
+}
+
+impl fmt::Display for PartitionBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Day => "DAY",
+            Self::Month => "MONTH",
+            Self::Year => "YEAR",
+        })
+    }
+}
+
+impl PartitionBy {
+    /// Start of the partition containing `ts` (UTC).
+    pub fn floor(self, ts: OffsetDateTime) -> OffsetDateTime {
+        let date = ts.to_offset(UtcOffset::UTC).date();
+        let start = match self {
+            Self::Day => date,
+            Self::Month => Date::from_calendar_date(date.year(), date.month(), 1).expect("first of month"),
+            Self::Year => Date::from_calendar_date(date.year(), Month::January, 1).expect("first of year"),
+        };
+        start.with_time(Time::MIDNIGHT).assume_utc()
+    }
+
+    /// Start of the partition after the one containing `ts`.
+    pub fn next(self, ts: OffsetDateTime) -> OffsetDateTime {
+        let start = self.floor(ts);
+        match self {
+            Self::Day => start + time::Duration::days(1),
+            Self::Month => {
+                let (year, month) = match start.month() {
+                    Month::December => (start.year() + 1, Month::January),
+                    m => (start.year(), m.next()),
+                };
+                start.replace_date(Date::from_calendar_date(year, month, 1).expect("first of month"))
+            }
+            Self::Year => start.replace_date(Date::from_calendar_date(start.year() + 1, Month::January, 1).expect("first of year")),
+        }
+    }
+
+    /// Widen `[from, to)` to whole partitions.
+    pub fn widen(self, from: OffsetDateTime, to: OffsetDateTime) -> (OffsetDateTime, OffsetDateTime) {
+        let end = if self.floor(to) == to { to } else { self.next(to) };
+        (self.floor(from), end)
+    }
+}
+
+/// Timestamp literal for statements that can't take bind parameters.
+pub fn sql_ts(ts: OffsetDateTime) -> String {
+    ts.to_offset(UtcOffset::UTC)
+        .format(&Rfc3339)
+        .expect("RFC 3339 timestamps within year 0..=9999")
+}
+
+/// Drop the partitions of `table` within `[from, to)`; both should be partition boundaries.
+pub fn drop_partitions_sql(table: &str, from: OffsetDateTime, to: OffsetDateTime) -> String {
+    format!(
+        "ALTER TABLE {table} DROP PARTITION WHERE ts >= '{}' AND ts < '{}';",
+        sql_ts(from),
+        sql_ts(to)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn widens_ranges_to_partition_boundaries() {
+        let from = datetime!(2024-01-15 10:30:00 UTC);
+        let to = datetime!(2024-12-03 00:00:00 +02:00);
+
+        assert_eq!(
+            PartitionBy::Day.widen(from, to),
+            (datetime!(2024-01-15 00:00:00 UTC), datetime!(2024-12-03 00:00:00 UTC))
+        );
+        assert_eq!(
+            PartitionBy::Month.widen(from, to),
+            (datetime!(2024-01-01 00:00:00 UTC), datetime!(2025-01-01 00:00:00 UTC))
+        );
+        assert_eq!(
+            PartitionBy::Year.widen(from, datetime!(2025-01-01 00:00:00 UTC)),
+            (datetime!(2024-01-01 00:00:00 UTC), datetime!(2025-01-01 00:00:00 UTC))
+        );
+    }
+}
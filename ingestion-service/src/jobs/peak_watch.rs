@@ -0,0 +1,350 @@
+use std::{collections::HashSet, fmt};
+
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, Month, OffsetDateTime};
+
+/// Default fraction of the historical peak at which demand counts as tracking toward it.
+pub const DEFAULT_THRESHOLD: f64 = 0.95;
+
+/// Default number of recent intervals averaged for the current demand (one hour).
+pub const DEFAULT_WINDOW_INTERVALS: usize = 4;
+
+/// Meter intervals are 15 minutes, so interval kWh times 4 is average kW.
+const KW_PER_INTERVAL_KWH: f64 = 4.0;
+
+/// Demand series a peak is tracked for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    /// All meters.
+    System,
+    /// Meters mapped to a feeder at the interval.
+    Feeder,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::System => "system",
+            Self::Feeder => "feeder",
+        })
+    }
+}
+
+/// Period whose peak is compared with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeakLevel {
+    Monthly,
+    Annual,
+}
+
+impl PeakLevel {
+    /// Start of the period containing `day`.
+    pub fn period_start(self, day: Date) -> OffsetDateTime {
+        let month = match self {
+            Self::Monthly => day.month(),
+            Self::Annual => Month::January,
+        };
+        Date::from_calendar_date(day.year(), month, 1)
+            .expect("first of the month is valid")
+            .midnight()
+            .assume_utc()
+    }
+}
+
+impl fmt::Display for PeakLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Monthly => "monthly",
+            Self::Annual => "annual",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PeakStatus {
+    /// Recent demand, projected one window ahead, reached the threshold of the peak.
+    Tracking,
+    /// An interval today already exceeded the peak.
+    NewPeak,
+}
+
+impl fmt::Display for PeakStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Tracking => "tracking",
+            Self::NewPeak => "new_peak",
+        })
+    }
+}
+
+/// Average kW per interval of `meter_usage` (scaled by `meter_scale_map`), as
+/// `(ts, scope_id, kw)`; `scope_id` is `system` or the feeder id.
+///
+/// Bind parameters: `$1` start, `$2` end.
+pub fn demand_sql(scope: Scope) -> String {
+    let (select_id, feeder_join, group_by) = match scope {
+        Scope::System => ("'system'", "", "mu.ts"),
+        Scope::Feeder => (
+            "mfm.feeder_id",
+            "JOIN meter_feeder_map mfm
+              ON mfm.meter_id = mu.meter_id
+             AND mfm.from_ts <= mu.ts
+             AND mfm.to_ts   >  mu.ts",
+            "mu.ts, mfm.feeder_id",
+        ),
+    };
+    format!(
+        r#"
+        SELECT
+            mu.ts,
+            {select_id} AS scope_id,
+            SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) * {KW_PER_INTERVAL_KWH} AS kw
+        FROM meter_usage mu
+        {feeder_join}
+        LEFT JOIN meter_scale_map msm
+          ON msm.meter_id = mu.meter_id
+         AND msm.from_ts <= mu.ts
+         AND msm.to_ts   >  mu.ts
+        WHERE mu.ts >= $1 AND mu.ts < $2
+        GROUP BY {group_by}
+        "#
+    )
+}
+
+/// Today's demand of one series compared with a historical peak.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeakCheck {
+    /// Highest interval demand today (kW).
+    pub max_kw: f64,
+    /// Average of the last window of intervals (kW).
+    pub recent_kw: f64,
+    /// `recent_kw` plus its rise over the previous window, if rising (kW).
+    pub projected_kw: f64,
+    pub peak_kw: f64,
+    pub status: Option<PeakStatus>,
+}
+
+/// Compare today's `intervals` (`(ts, kw)`, any order) with `peak_kw`.
+///
+/// Returns `None` without intervals or without a peak to compare with.
+pub fn check(intervals: &[(OffsetDateTime, f64)], peak_kw: Option<f64>, threshold: f64, window: usize) -> Option<PeakCheck> {
+    let peak_kw = peak_kw.filter(|p| *p > 0.0)?;
+    let mut sorted = intervals.to_vec();
+    sorted.sort_by_key(|(ts, _)| *ts);
+    let kws: Vec<f64> = sorted.into_iter().map(|(_, kw)| kw).collect();
+    let max_kw = kws.iter().copied().reduce(f64::max)?;
+
+    let window = window.max(1);
+    let avg = |xs: &[f64]| xs.iter().sum::<f64>() / xs.len() as f64;
+    let split = kws.len().saturating_sub(window);
+    let recent_kw = avg(&kws[split..]);
+    let previous = &kws[split.saturating_sub(window)..split];
+    let rise = if previous.is_empty() { 0.0 } else { (recent_kw - avg(previous)).max(0.0) };
+    let projected_kw = recent_kw + rise;
+
+    let status = if max_kw > peak_kw {
+        Some(PeakStatus::NewPeak)
+    } else if projected_kw >= threshold * peak_kw {
+        Some(PeakStatus::Tracking)
+    } else {
+        None
+    };
+    Some(PeakCheck {
+        max_kw,
+        recent_kw,
+        projected_kw,
+        peak_kw,
+        status,
+    })
+}
+
+/// A peak alert, as recorded in `peak_alerts`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeakAlert {
+    pub day: Date,
+    pub scope: Scope,
+    pub scope_id: String,
+    pub level: PeakLevel,
+    pub status: PeakStatus,
+    pub check: PeakCheck,
+}
+
+impl PeakAlert {
+    fn key(&self) -> (String, String, String, String) {
+        (
+            self.scope.to_string(),
+            self.scope_id.clone(),
+            self.level.to_string(),
+            self.status.to_string(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PeakWatchOptions {
+    pub threshold: f64,
+    pub window_intervals: usize,
+}
+
+impl Default for PeakWatchOptions {
+    fn default() -> Self {
+        Self {
+            threshold: DEFAULT_THRESHOLD,
+            window_intervals: DEFAULT_WINDOW_INTERVALS,
+        }
+    }
+}
+
+async fn demand(
+    pool: &PgPool,
+    scope: Scope,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<(OffsetDateTime, String, f64)>, sqlx::Error> {
+    sqlx::query_as(&demand_sql(scope)).bind(from).bind(to).fetch_all(pool).await
+}
+
+async fn peaks(
+    pool: &PgPool,
+    scope: Scope,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<Vec<(String, f64)>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT scope_id, max(kw) FROM ({}) GROUP BY scope_id",
+        demand_sql(scope)
+    ))
+    .bind(from)
+    .bind(to)
+    .fetch_all(pool)
+    .await
+}
+
+/// Check system and feeder demand of the day containing `now` (UTC) against
+/// the monthly and annual peaks of the days before it.
+pub async fn evaluate(pool: &PgPool, now: OffsetDateTime, opts: &PeakWatchOptions) -> Result<Vec<PeakAlert>, sqlx::Error> {
+    let day = now.date();
+    let day_start = day.midnight().assume_utc();
+    let mut alerts = Vec::new();
+
+    for scope in [Scope::System, Scope::Feeder] {
+        let today = demand(pool, scope, day_start, now).await?;
+        for level in [PeakLevel::Monthly, PeakLevel::Annual] {
+            let peaks = peaks(pool, scope, level.period_start(day), day_start).await?;
+            for (scope_id, peak_kw) in peaks {
+                let intervals: Vec<(OffsetDateTime, f64)> = today
+                    .iter()
+                    .filter(|(_, id, _)| *id == scope_id)
+                    .map(|(ts, _, kw)| (*ts, *kw))
+                    .collect();
+                let Some(check) = check(&intervals, Some(peak_kw), opts.threshold, opts.window_intervals) else {
+                    continue;
+                };
+                if let Some(status) = check.status {
+                    alerts.push(PeakAlert {
+                        day,
+                        scope,
+                        scope_id,
+                        level,
+                        status,
+                        check,
+                    });
+                }
+            }
+        }
+    }
+    Ok(alerts)
+}
+
+/// Record the alerts not yet raised for their day in `peak_alerts`, and return them.
+///
+/// Each (scope, level, status) alerts at most once a day, so the job can run
+/// every interval without repeating notifications.
+pub async fn record_new(pool: &PgPool, now: OffsetDateTime, alerts: Vec<PeakAlert>) -> Result<Vec<PeakAlert>, sqlx::Error> {
+    let Some(day) = alerts.first().map(|a| a.day) else {
+        return Ok(alerts);
+    };
+    let day_ts = day.midnight().assume_utc();
+    let raised: HashSet<(String, String, String, String)> = sqlx::query_as(
+        "SELECT scope, scope_id, level, status FROM peak_alerts WHERE ts >= $1 AND ts < $2 AND day = $1",
+    )
+    .bind(day_ts)
+    .bind(day_ts + Duration::days(1))
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .collect();
+
+    let new: Vec<PeakAlert> = alerts.into_iter().filter(|a| !raised.contains(&a.key())).collect();
+    if new.is_empty() {
+        return Ok(new);
+    }
+
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO peak_alerts (ts, day, scope, scope_id, level, status, max_kw, recent_kw, projected_kw, peak_kw) ",
+    );
+    builder.push_values(&new, |mut b, a| {
+        b.push_bind(now)
+            .push_bind(day_ts)
+            .push_bind(a.scope.to_string())
+            .push_bind(&a.scope_id)
+            .push_bind(a.level.to_string())
+            .push_bind(a.status.to_string())
+            .push_bind(a.check.max_kw)
+            .push_bind(a.check.recent_kw)
+            .push_bind(a.check.projected_kw)
+            .push_bind(a.check.peak_kw);
+    });
+    builder.build().execute(pool).await?;
+
+    for a in &new {
+        metrics::counter!(
+            "peak_alerts_total",
+            "scope" => a.scope.to_string(),
+            "level" => a.level.to_string(),
+            "status" => a.status.to_string()
+        )
+        .increment(1);
+    }
+    Ok(new)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    #[test]
+    fn flags_demand_rising_toward_the_peak() {
+        let t0 = datetime!(2024-07-15 12:00 UTC);
+        let at = |i: i64, kw: f64| (t0 + Duration::minutes(15 * i), kw);
+
+        // An hour at 800 kW, then an hour at 900 kW: projected 1000 kW against a 1040 kW peak.
+        let rising: Vec<_> = (0..8).map(|i| at(i, if i < 4 { 800.0 } else { 900.0 })).collect();
+        let c = check(&rising, Some(1040.0), DEFAULT_THRESHOLD, DEFAULT_WINDOW_INTERVALS).unwrap();
+        assert_eq!((c.recent_kw, c.projected_kw), (900.0, 1000.0));
+        assert_eq!(c.status, Some(PeakStatus::Tracking));
+
+        // Flat demand at the same level stays below the threshold.
+        let flat: Vec<_> = (0..8).map(|i| at(i, 900.0)).collect();
+        let c = check(&flat, Some(1040.0), DEFAULT_THRESHOLD, DEFAULT_WINDOW_INTERVALS).unwrap();
+        assert_eq!(c.status, None);
+
+        // Any interval above the peak sets a new one.
+        let mut spike = flat.clone();
+        spike.push(at(8, 1100.0));
+        let c = check(&spike, Some(1040.0), DEFAULT_THRESHOLD, DEFAULT_WINDOW_INTERVALS).unwrap();
+        assert_eq!(c.status, Some(PeakStatus::NewPeak));
+
+        // Nothing to compare with early in the period or before any data arrived.
+        assert_eq!(check(&flat, None, DEFAULT_THRESHOLD, DEFAULT_WINDOW_INTERVALS), None);
+        assert_eq!(check(&[], Some(1040.0), DEFAULT_THRESHOLD, DEFAULT_WINDOW_INTERVALS), None);
+    }
+
+    #[test]
+    fn periods_start_at_month_and_year() {
+        let day = date!(2024-07-15);
+        assert_eq!(PeakLevel::Monthly.period_start(day), datetime!(2024-07-01 00:00 UTC));
+        assert_eq!(PeakLevel::Annual.period_start(day), datetime!(2024-01-01 00:00 UTC));
+    }
+}
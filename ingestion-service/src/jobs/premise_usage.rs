@@ -0,0 +1,124 @@
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use super::partitions::{drop_partitions_sql, PartitionBy};
+
+/// Partitioning of `premise_usage` (see `sql/schema/03_mapping_tables.sql`).
+pub const PARTITION_BY: PartitionBy = PartitionBy::Day;
+
+/// Build the `INSERT INTO premise_usage` statement for intervals in `[$1, $2)`.
+///
+/// Premises in `premise_meter_config` add up their `main` meters; `sub` meters
+/// sit behind a main meter, so their energy is already included and is only
+/// reported as `sub_kwh`. An interval is complete when every main meter in
+/// effect reported. Meters of other premises count as main meters of their
+/// `meter_usage.premise_id`; once a premise is configured, its unconfigured
+/// meters are left out. Readings are scaled by `meter_scale_map`.
+pub fn premise_usage_insert_sql() -> &'static str {
+    r#"
+    INSERT INTO premise_usage
+    SELECT ts, premise_id, kwh, kvarh, sub_kwh, main_meters, reporting_meters, complete
+    FROM (
+        SELECT
+            r.ts,
+            r.premise_id,
+            r.kwh,
+            r.kvarh,
+            r.sub_kwh,
+            COALESCE(m.main_meters, 0)                                   AS main_meters,
+            r.reporting_meters,
+            m.main_meters IS NOT NULL AND r.reporting_meters >= m.main_meters AS complete
+        FROM (
+            SELECT
+                mu.ts,
+                pmc.premise_id,
+                SUM(CASE WHEN pmc.role = 'main' THEN mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) END)     AS kwh,
+                SUM(CASE WHEN pmc.role = 'main' THEN mu.kvarh * COALESCE(msm.kvarh_multiplier, 1.0) END) AS kvarh,
+                SUM(CASE WHEN pmc.role = 'sub' THEN mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) END)      AS sub_kwh,
+                COUNT(DISTINCT CASE WHEN pmc.role = 'main' THEN mu.meter_id END)                          AS reporting_meters
+            FROM meter_usage mu
+            JOIN premise_meter_config pmc
+              ON pmc.meter_id = mu.meter_id
+             AND pmc.from_ts <= mu.ts
+             AND pmc.to_ts   >  mu.ts
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mu.ts >= $1 AND mu.ts < $2
+            GROUP BY mu.ts, pmc.premise_id
+        ) r
+        LEFT JOIN (
+            SELECT
+                t.ts,
+                pmc.premise_id,
+                COUNT(DISTINCT pmc.meter_id) AS main_meters
+            FROM (SELECT DISTINCT ts FROM meter_usage WHERE ts >= $1 AND ts < $2) t
+            JOIN premise_meter_config pmc
+              ON pmc.from_ts <= t.ts
+             AND pmc.to_ts   >  t.ts
+            WHERE pmc.role = 'main'
+            GROUP BY t.ts, pmc.premise_id
+        ) m
+          ON m.ts = r.ts
+         AND m.premise_id = r.premise_id
+
+        UNION ALL
+
+        SELECT
+            mu.ts,
+            mu.premise_id,
+            SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0))     AS kwh,
+            SUM(mu.kvarh * COALESCE(msm.kvarh_multiplier, 1.0)) AS kvarh,
+            NULL                                                AS sub_kwh,
+            COUNT(DISTINCT mu.meter_id)                         AS main_meters,
+            COUNT(DISTINCT mu.meter_id)                         AS reporting_meters,
+            TRUE                                                AS complete
+        FROM meter_usage mu
+        LEFT JOIN meter_scale_map msm
+          ON msm.meter_id = mu.meter_id
+         AND msm.from_ts <= mu.ts
+         AND msm.to_ts   >  mu.ts
+        WHERE mu.ts >= $1 AND mu.ts < $2
+          AND mu.premise_id IS NOT NULL
+          AND mu.premise_id NOT IN (SELECT DISTINCT premise_id FROM premise_meter_config)
+        GROUP BY mu.ts, mu.premise_id
+    )
+    ORDER BY ts;
+    "#
+}
+
+/// Recompute `premise_usage` for `[from, to)`, widened to whole partitions.
+///
+/// The partitions overlapping the range are dropped and rebuilt for every
+/// premise. Returns the rows inserted.
+pub async fn recompute_range(pool: &PgPool, from: OffsetDateTime, to: OffsetDateTime) -> Result<u64, sqlx::Error> {
+    let (from, to) = PARTITION_BY.widen(from, to);
+    sqlx::query(&drop_partitions_sql("premise_usage", from, to))
+        .execute(pool)
+        .await?;
+
+    let result = sqlx::query(premise_usage_insert_sql())
+        .bind(from)
+        .bind(to)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_premises_sum_main_meters_only() {
+        let sql = premise_usage_insert_sql();
+        assert!(sql.contains("SUM(CASE WHEN pmc.role = 'main' THEN mu.kwh"));
+        assert!(sql.contains("SUM(CASE WHEN pmc.role = 'sub' THEN mu.kwh"));
+        assert!(sql.contains("WHERE pmc.role = 'main'"));
+        // Unconfigured premises don't mix with configured ones.
+        assert!(sql.contains("mu.premise_id NOT IN (SELECT DISTINCT premise_id FROM premise_meter_config)"));
+        assert_eq!(sql.matches("WHERE mu.ts >= $1 AND mu.ts < $2").count(), 2);
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::{BTreeSet, HashMap};
+
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPool;
+use time::{Duration, OffsetDateTime, UtcOffset, Weekday};
+
+use crate::{
+    config::{EventIdStrategy, PseudoMeasurementConfig, UnmeteredLoadProfile},
+    pipeline::Envelope,
+    sinks::{QuestDbPgwireStore, TimeSeriesStore},
+};
+
+/// `meter_usage.quality_flag` of pseudo-measurements; `feeder_balance` reports
+/// their energy as `feeder_kwh_unmetered`.
+pub const PSEUDO_QUALITY_FLAG: &str = "pseudo";
+
+/// `meter_usage.source_system` of pseudo-measurements.
+pub const PSEUDO_SOURCE_SYSTEM: &str = "pseudo_measurement";
+
+const INSERT_BATCH: usize = 1000;
+
+/// Unmetered loads in effect at some point of `[$1, $2)`.
+const LOADS_SQL: &str = r#"
+    SELECT load_id, profile, rated_kw, station_id, from_ts, to_ts
+    FROM unmetered_loads
+    WHERE from_ts < $2 AND to_ts > $1
+"#;
+
+/// Hourly mean temperature per weather station over `[$1, $2)`.
+const TEMPERATURE_SQL: &str = r#"
+    SELECT station_id, timestamp_floor('h', ts) AS hour, avg(temperature_c) AS temperature_c
+    FROM weather_observations
+    WHERE ts >= $1 AND ts < $2
+    GROUP BY station_id, hour
+"#;
+
+/// One row of `unmetered_loads`: a streetlight circuit, traffic signal or other
+/// unmetered service, and the profile its consumption follows.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UnmeteredLoad {
+    pub load_id: String,
+    pub profile: String,
+    pub rated_kw: f64,
+    /// Weather station of the profile's temperature sensitivity.
+    pub station_id: Option<String>,
+    pub from_ts: OffsetDateTime,
+    pub to_ts: OffsetDateTime,
+}
+
+/// Hourly mean temperature by `(station_id, hour)`.
+pub type Temperatures = HashMap<(String, OffsetDateTime), f64>;
+
+/// Check the interval and profiles of `cfg`.
+pub fn validate(cfg: &PseudoMeasurementConfig) -> Result<(), String> {
+    if cfg.interval_minutes == 0 || 1440 % cfg.interval_minutes != 0 {
+        return Err(format!(
+            "interval_minutes must divide a day (1440 minutes), got {}",
+            cfg.interval_minutes
+        ));
+    }
+    UtcOffset::from_whole_seconds(cfg.utc_offset_minutes * 60)
+        .map_err(|e| format!("invalid utc_offset_minutes {}: {e}", cfg.utc_offset_minutes))?;
+    for (name, profile) in &cfg.profiles {
+        let factors = |key: &str, values: Option<&Vec<f64>>, len: usize| match values {
+            Some(v) if v.len() != len => Err(format!(
+                "profiles.{name}.{key}: expected {len} factors, got {}",
+                v.len()
+            )),
+            Some(v) if v.iter().any(|f| !f.is_finite() || *f < 0.0) => {
+                Err(format!("profiles.{name}.{key}: factors must be non-negative"))
+            }
+            _ => Ok(()),
+        };
+        factors("hours", Some(&profile.hours), 24)?;
+        factors("weekend_hours", profile.weekend_hours.as_ref(), 24)?;
+        factors("months", profile.months.as_ref(), 12)?;
+    }
+    Ok(())
+}
+
+/// Share of `rated_kw` drawn from `local` on, at `temperature_c` if known.
+pub fn load_factor(profile: &UnmeteredLoadProfile, local: OffsetDateTime, temperature_c: Option<f64>) -> f64 {
+    let weekend = matches!(local.weekday(), Weekday::Saturday | Weekday::Sunday);
+    let hours = match &profile.weekend_hours {
+        Some(weekend_hours) if weekend => weekend_hours,
+        _ => &profile.hours,
+    };
+    let mut factor = hours[usize::from(local.hour())];
+    if let Some(months) = &profile.months {
+        factor *= months[usize::from(u8::from(local.month())) - 1];
+    }
+    if let Some(t) = temperature_c {
+        let heating = (profile.balance_point_c - t).max(0.0) * profile.heating_per_c;
+        let cooling = (t - profile.balance_point_c).max(0.0) * profile.cooling_per_c;
+        factor *= 1.0 + heating + cooling;
+    }
+    factor.max(0.0)
+}
+
+/// Pseudo-measurements of a window, and what kept some from being made.
+#[derive(Debug, Default)]
+pub struct Generated {
+    pub readings: Vec<MeterUsage>,
+    /// Profiles of `unmetered_loads` rows that aren't configured; their loads are left out.
+    pub unknown_profiles: BTreeSet<String>,
+    /// Readings of temperature-sensitive loads made without a temperature.
+    pub missing_weather: u64,
+}
+
+/// Readings of every load for each interval of `[from, to)` it is in effect
+/// at the interval start; `from` is expected to be aligned to the interval.
+pub fn generate(
+    cfg: &PseudoMeasurementConfig,
+    loads: &[UnmeteredLoad],
+    temperatures: &Temperatures,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Generated {
+    let interval = Duration::minutes(i64::from(cfg.interval_minutes));
+    let hours = interval.as_seconds_f64() / 3600.0;
+    let offset = UtcOffset::from_whole_seconds(cfg.utc_offset_minutes * 60).unwrap_or(UtcOffset::UTC);
+    let mut generated = Generated::default();
+
+    for load in loads {
+        let Some(profile) = cfg.profiles.get(&load.profile) else {
+            generated.unknown_profiles.insert(load.profile.clone());
+            continue;
+        };
+        let weather_sensitive = profile.heating_per_c != 0.0 || profile.cooling_per_c != 0.0;
+        let mut ts = from;
+        while ts < to {
+            if load.from_ts <= ts && ts < load.to_ts {
+                let hour = ts.replace_minute(0).and_then(|t| t.replace_second(0)).unwrap_or(ts);
+                let temperature = load
+                    .station_id
+                    .as_ref()
+                    .and_then(|station| temperatures.get(&(station.clone(), hour)).copied());
+                if weather_sensitive && temperature.is_none() {
+                    generated.missing_weather += 1;
+                }
+                let factor = load_factor(profile, ts.to_offset(offset), temperature);
+                generated.readings.push(MeterUsage {
+                    ts,
+                    meter_id: load.load_id.clone(),
+                    premise_id: None,
+                    kwh: load.rated_kw * factor * hours,
+                    kvarh: None,
+                    kva_demand: None,
+                    quality_flag: Some(PSEUDO_QUALITY_FLAG.to_string()),
+                    source_system: Some(PSEUDO_SOURCE_SYSTEM.to_string()),
+                    event_id: None,
+                    phases: Default::default(),
+                });
+            }
+            ts += interval;
+        }
+    }
+    generated
+}
+
+/// Write the pseudo-measurements of `[from, to)` to `meter_usage`.
+///
+/// Readings get `natural_key` event ids, so a re-run replaces the window's
+/// earlier readings; `meter_usage` must deduplicate on `(ts, event_id)`.
+/// Returns the readings written.
+pub async fn run(
+    pool: &PgPool,
+    cfg: &PseudoMeasurementConfig,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> anyhow::Result<u64> {
+    validate(cfg).map_err(|e| anyhow::anyhow!("[pseudo_measurements]: {e}"))?;
+    let store = QuestDbPgwireStore::new(pool.clone()).with_event_id(EventIdStrategy::NaturalKey);
+    store.require_event_id_dedup("meter_usage").await?;
+
+    let loads: Vec<UnmeteredLoad> = sqlx::query_as(LOADS_SQL).bind(from).bind(to).fetch_all(pool).await?;
+    let temperatures: Vec<(String, OffsetDateTime, Option<f64>)> = sqlx::query_as(TEMPERATURE_SQL)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    let temperatures: Temperatures = temperatures
+        .into_iter()
+        .filter_map(|(station, hour, t)| Some(((station, hour), t?)))
+        .collect();
+
+    let generated = generate(cfg, &loads, &temperatures, from, to);
+    if !generated.unknown_profiles.is_empty() {
+        metrics::counter!("pseudo_measurement_unknown_profiles_total")
+            .increment(generated.unknown_profiles.len() as u64);
+        tracing::warn!(profiles = ?generated.unknown_profiles, "unmetered loads with unconfigured profiles left out");
+    }
+    if generated.missing_weather > 0 {
+        metrics::counter!("pseudo_measurement_missing_weather_total").increment(generated.missing_weather);
+        tracing::warn!(
+            readings = generated.missing_weather,
+            "no temperature for weather-sensitive loads; readings made without it"
+        );
+    }
+
+    for chunk in generated.readings.chunks(INSERT_BATCH) {
+        let batch: Vec<_> = chunk.iter().cloned().map(Envelope::new).collect();
+        store.insert_batch(&batch).await?;
+    }
+    let written = generated.readings.len() as u64;
+    metrics::counter!("pseudo_measurements_written_total").increment(written);
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn config() -> PseudoMeasurementConfig {
+        toml::from_str(
+            r#"
+            interval_minutes = 30
+            utc_offset_minutes = -300
+            [profiles.streetlight]
+            hours = [1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1]
+            months = [1, 1, 1, 1, 1, 0.8, 1, 1, 1, 1, 1, 1]
+            [profiles.cabinet]
+            hours = [0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5, 0.5]
+            weekend_hours = [0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25, 0.25]
+            heating_per_c = 0.1
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn load(load_id: &str, profile: &str, rated_kw: f64, station_id: Option<&str>) -> UnmeteredLoad {
+        UnmeteredLoad {
+            load_id: load_id.to_string(),
+            profile: profile.to_string(),
+            rated_kw,
+            station_id: station_id.map(str::to_string),
+            from_ts: datetime!(2024-01-01 00:00 UTC),
+            to_ts: datetime!(2100-01-01 00:00 UTC),
+        }
+    }
+
+    #[test]
+    fn follows_the_local_schedule_and_season() {
+        let cfg = config();
+        let streetlight = &cfg.profiles["streetlight"];
+        // 23:00 UTC is 18:00 at UTC-5: on.
+        let at =
+            |ts: OffsetDateTime| load_factor(streetlight, ts.to_offset(UtcOffset::from_hms(-5, 0, 0).unwrap()), None);
+        assert_eq!(at(datetime!(2024-03-04 23:00 UTC)), 1.0);
+        assert_eq!(at(datetime!(2024-03-04 22:59 UTC)), 0.0);
+        assert_eq!(at(datetime!(2024-06-04 23:00 UTC)), 0.8);
+        let cabinet = &cfg.profiles["cabinet"];
+        // Saturday, 8 °C below the balance point.
+        let factor = load_factor(cabinet, datetime!(2024-03-09 12:00 UTC), Some(10.0));
+        assert!((factor - 0.25 * 1.8).abs() < 1e-9);
+    }
+
+    #[test]
+    fn generates_flagged_readings_per_interval() {
+        let cfg = config();
+        let mut cabinet = load("TC-9", "cabinet", 2.0, Some("KBOS"));
+        cabinet.to_ts = datetime!(2024-03-04 12:00 UTC);
+        let loads = vec![
+            load("SL-1", "streetlight", 4.0, None),
+            cabinet,
+            load("X-1", "fountain", 1.0, None),
+        ];
+        let temperatures = Temperatures::from([(("KBOS".to_string(), datetime!(2024-03-04 11:00 UTC)), 28.0)]);
+        let generated = generate(
+            &cfg,
+            &loads,
+            &temperatures,
+            datetime!(2024-03-04 10:00 UTC),
+            datetime!(2024-03-04 13:00 UTC),
+        );
+
+        // 05:00-08:00 local, on until 06:00.
+        let streetlight: Vec<_> = generated
+            .readings
+            .iter()
+            .filter(|r| r.meter_id == "SL-1")
+            .map(|r| r.kwh)
+            .collect();
+        assert_eq!(streetlight, [2.0, 2.0, 0.0, 0.0, 0.0, 0.0]);
+        // Removed at 12:00; no temperature before 11:00, above the balance point at 11:00.
+        let cabinet: Vec<_> = generated
+            .readings
+            .iter()
+            .filter(|r| r.meter_id == "TC-9")
+            .map(|r| r.kwh)
+            .collect();
+        assert_eq!(cabinet, [0.5, 0.5, 0.5, 0.5]);
+        assert_eq!(generated.missing_weather, 2);
+        assert!(generated.unknown_profiles.contains("fountain"));
+
+        let first = &generated.readings[0];
+        assert_eq!(first.ts, datetime!(2024-03-04 10:00 UTC));
+        assert_eq!(first.quality_flag.as_deref(), Some(PSEUDO_QUALITY_FLAG));
+        assert_eq!(first.source_system.as_deref(), Some(PSEUDO_SOURCE_SYSTEM));
+    }
+
+    #[test]
+    fn rejects_malformed_profiles() {
+        assert!(validate(&config()).is_ok());
+        let mut cfg = config();
+        cfg.profiles.get_mut("streetlight").unwrap().hours.pop();
+        assert!(validate(&cfg)
+            .unwrap_err()
+            .contains("profiles.streetlight.hours: expected 24 factors, got 23"));
+        let mut cfg = config();
+        cfg.profiles.get_mut("cabinet").unwrap().months = Some(vec![-1.0; 12]);
+        assert!(validate(&cfg).is_err());
+        let mut cfg = config();
+        cfg.interval_minutes = 7;
+        assert!(validate(&cfg).is_err());
+    }
+}
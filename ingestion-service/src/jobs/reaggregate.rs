@@ -0,0 +1,185 @@
+use std::{collections::HashMap, fmt};
+
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use crate::config::LossUncertaintyConfig;
+
+use super::{
+    dr_performance,
+    feeder_balance::{self, FeederBalanceOptions},
+    forecast_accuracy,
+    loss_uncertainty,
+    partitions::sql_ts,
+    premise_usage,
+    registry::{self, DerivedTable, Refresh},
+    rollups::{self, RollupMode},
+};
+
+/// What a batch of corrections touched.
+#[derive(Debug, Clone)]
+pub struct CorrectionScope {
+    /// Corrected readings lie in `[from, to)`.
+    pub from: OffsetDateTime,
+    pub to: OffsetDateTime,
+    /// Meters whose `meter_usage` was corrected.
+    pub meter_ids: Vec<String>,
+    /// Feeders whose generation (`generation_output` of their plants) was corrected.
+    pub feeder_ids: Vec<String>,
+}
+
+impl CorrectionScope {
+    /// Base tables whose derived tables are stale: `meter_usage` for meters,
+    /// `generation_output` for feeders, both if neither is given.
+    pub fn base_tables(&self) -> Vec<&'static str> {
+        match (self.meter_ids.is_empty(), self.feeder_ids.is_empty()) {
+            (false, true) => vec!["meter_usage"],
+            (true, false) => vec!["generation_output"],
+            _ => vec!["meter_usage", "generation_output"],
+        }
+    }
+}
+
+/// A derived table with the range it recomputes.
+#[derive(Debug, Clone, Copy)]
+pub struct PlannedStep {
+    pub table: DerivedTable,
+    pub from: OffsetDateTime,
+    pub to: OffsetDateTime,
+}
+
+impl fmt::Display for PlannedStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}, {})", self.table.name, sql_ts(self.from), sql_ts(self.to))
+    }
+}
+
+/// Derived tables to recompute after the corrections in `scope`, in refresh order.
+///
+/// The stale tables and their order come from the [registry](super::registry).
+/// Each table is recomputed over its [stale range](DerivedTable::stale_range)
+/// of the ranges recomputed for its sources, so a table rebuilt per month
+/// on top of a daily rollup covers the whole month. Meter and feeder ids only
+/// decide which base tables changed.
+pub fn plan(scope: &CorrectionScope, baseline_days: u32) -> anyhow::Result<Vec<PlannedStep>> {
+    plan_changes(&scope.base_tables(), scope.from, scope.to, baseline_days)
+}
+
+/// Derived tables to recompute after `changed` tables changed in `[from, to)`,
+/// in refresh order; see [`plan`].
+pub fn plan_changes(
+    changed: &[&str],
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+    baseline_days: u32,
+) -> anyhow::Result<Vec<PlannedStep>> {
+    let mut ranges: HashMap<&str, (OffsetDateTime, OffsetDateTime)> =
+        changed.iter().map(|table| (*table, (from, to))).collect();
+
+    let mut steps = Vec::new();
+    for table in registry::stale_tables(changed).map_err(anyhow::Error::msg)? {
+        let (from, to) = table
+            .sources
+            .iter()
+            .filter_map(|source| ranges.get(source))
+            .copied()
+            .reduce(|(f1, t1), (f2, t2)| (f1.min(f2), t1.max(t2)))
+            .expect("stale tables have a changed source");
+        let (from, to) = table.stale_range(from, to, baseline_days);
+        ranges.insert(table.name, (from, to));
+        steps.push(PlannedStep { table, from, to });
+    }
+    Ok(steps)
+}
+
+/// Settings of the recomputed jobs.
+#[derive(Debug, Clone)]
+pub struct ReaggregateOptions {
+    pub rollup_mode: RollupMode,
+    pub feeder_balance: FeederBalanceOptions,
+    pub baseline_days: u32,
+    /// Without it, `feeder_loss_uncertainty` steps are skipped.
+    pub loss_uncertainty: Option<LossUncertaintyConfig>,
+}
+
+/// Run `steps` in order, stopping at the first failure.
+///
+/// Each step replaces its whole range, so re-running a plan after a failure is safe.
+pub async fn execute(pool: &PgPool, steps: &[PlannedStep], opts: &ReaggregateOptions) -> anyhow::Result<()> {
+    for planned in steps {
+        let PlannedStep { table, from, to } = *planned;
+        let rows = match table.refresh {
+            Refresh::Rollup(def) => {
+                rollups::refresh_range(pool, opts.rollup_mode, def, from, to).await?;
+                None
+            }
+            Refresh::FeederBalance => Some(feeder_balance::recompute_range(pool, &opts.feeder_balance, from, to).await?),
+            Refresh::DrPerformance => Some(dr_performance::run(pool, from, to, opts.baseline_days).await?),
+            Refresh::PremiseUsage => Some(premise_usage::recompute_range(pool, from, to).await?),
+            Refresh::ForecastAccuracy => Some(forecast_accuracy::recompute_range(pool, from, to).await?),
+            Refresh::LossUncertainty => match &opts.loss_uncertainty {
+                Some(cfg) => {
+                    Some(loss_uncertainty::recompute_range(pool, cfg, &opts.feeder_balance, from, to).await?)
+                }
+                None => {
+                    tracing::info!(step = %planned, "no [loss_uncertainty] section; step skipped");
+                    continue;
+                }
+            },
+        };
+        metrics::counter!("reaggregate_steps_total", "table" => table.name).increment(1);
+        tracing::info!(step = %planned, rows, "derived table recomputed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn scope(meter_ids: &[&str], feeder_ids: &[&str]) -> CorrectionScope {
+        CorrectionScope {
+            from: datetime!(2024-03-10 06:00 UTC),
+            to: datetime!(2024-03-11 00:00 UTC),
+            meter_ids: meter_ids.iter().map(ToString::to_string).collect(),
+            feeder_ids: feeder_ids.iter().map(ToString::to_string).collect(),
+        }
+    }
+
+    #[test]
+    fn meter_corrections_recompute_meter_tables_in_dependency_order() {
+        let steps = plan(&scope(&["m-1"], &[]), 10).unwrap();
+        let steps: Vec<String> = steps.iter().map(ToString::to_string).collect();
+
+        assert_eq!(
+            steps,
+            [
+                "meter_usage_1h [2024-03-10T00:00:00Z, 2024-03-11T00:00:00Z)",
+                "meter_usage_1d [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+                "feeder_energy_balance [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+                "dr_event_performance [2024-03-09T06:00:00Z, 2024-03-21T00:00:00Z)",
+                "feeder_loss_uncertainty [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+                "premise_usage [2024-03-10T00:00:00Z, 2024-03-11T00:00:00Z)",
+                "forecast_accuracy [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+            ]
+        );
+    }
+
+    #[test]
+    fn feeder_corrections_skip_meter_derived_tables() {
+        let steps = plan(&scope(&[], &["f-1"]), 10).unwrap();
+        let tables: Vec<&str> = steps.iter().map(|s| s.table.name).collect();
+        assert_eq!(
+            tables,
+            [
+                "generation_output_1h",
+                "feeder_energy_balance",
+                "feeder_loss_uncertainty",
+                "forecast_accuracy"
+            ]
+        );
+
+        assert_eq!(plan(&scope(&[], &[]), 10).unwrap().len(), registry::derived_tables().len());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashMap, HashSet};
+
+use time::{Duration, OffsetDateTime};
+
+use super::{
+    feeder_balance, loss_uncertainty,
+    partitions::PartitionBy,
+    premise_usage,
+    rollups::{RollupDef, ROLLUPS},
+};
+
+/// How a derived table is recomputed.
+#[derive(Debug, Clone, Copy)]
+pub enum Refresh {
+    /// `rollups::refresh_range` of the rollup.
+    Rollup(&'static RollupDef),
+    /// `feeder_balance::recompute_range`.
+    FeederBalance,
+    /// `dr_performance::run`.
+    DrPerformance,
+    /// `loss_uncertainty::recompute_range`, when `[loss_uncertainty]` is configured.
+    LossUncertainty,
+    /// `premise_usage::recompute_range`.
+    PremiseUsage,
+    /// `forecast_accuracy::recompute_range`.
+    ForecastAccuracy,
+}
+
+/// A table computed from other tables.
+#[derive(Debug, Clone, Copy)]
+pub struct DerivedTable {
+    pub name: &'static str,
+    /// Tables the refresh reads; a change to any of them makes this table stale.
+    pub sources: &'static [&'static str],
+    pub refresh: Refresh,
+}
+
+impl DerivedTable {
+    /// Range this table has to be recomputed over after its sources changed in `[from, to)`.
+    ///
+    /// Tables rebuilt per partition widen the range to whole partitions and
+    /// forecast accuracy to whole days; DR
+    /// performance covers events whose event or baseline window overlaps it:
+    /// events starting up to a day before `from` and up to `baseline_days` after `to`.
+    pub fn stale_range(
+        &self,
+        from: OffsetDateTime,
+        to: OffsetDateTime,
+        baseline_days: u32,
+    ) -> (OffsetDateTime, OffsetDateTime) {
+        match self.refresh {
+            Refresh::Rollup(def) => def.partition_by.widen(from, to),
+            Refresh::FeederBalance => feeder_balance::PARTITION_BY.widen(from, to),
+            Refresh::LossUncertainty => loss_uncertainty::PARTITION_BY.widen(from, to),
+            Refresh::PremiseUsage => premise_usage::PARTITION_BY.widen(from, to),
+            Refresh::ForecastAccuracy => PartitionBy::Day.widen(from, to),
+            Refresh::DrPerformance => (from - Duration::days(1), to + Duration::days(i64::from(baseline_days))),
+        }
+    }
+}
+
+/// Every derived table of the service, in no particular order.
+///
+/// New derived tables must be registered here so that corrections to their
+/// sources recompute them (see `reaggregate`).
+pub fn derived_tables() -> Vec<DerivedTable> {
+    let mut tables: Vec<DerivedTable> = ROLLUPS
+        .iter()
+        .map(|def| DerivedTable {
+            name: def.name,
+            sources: std::slice::from_ref(&def.base_table),
+            refresh: Refresh::Rollup(def),
+        })
+        .collect();
+    tables.push(DerivedTable {
+        name: "feeder_energy_balance",
+        sources: &[
+            "generation_output",
+            "meter_usage",
+            "plant_feeder_map",
+            "meter_feeder_map",
+            "meter_scale_map",
+            "unmetered_loads",
+            "topology_events",
+            "meter_events",
+        ],
+        refresh: Refresh::FeederBalance,
+    });
+    tables.push(DerivedTable {
+        name: "dr_event_performance",
+        sources: &["meter_usage", "dr_events"],
+        refresh: Refresh::DrPerformance,
+    });
+    tables.push(DerivedTable {
+        name: "feeder_loss_uncertainty",
+        sources: &[
+            "feeder_energy_balance",
+            "generation_output",
+            "meter_usage",
+            "meters",
+            "plant_feeder_map",
+            "meter_feeder_map",
+            "meter_scale_map",
+        ],
+        refresh: Refresh::LossUncertainty,
+    });
+    tables.push(DerivedTable {
+        name: "premise_usage",
+        sources: &["meter_usage", "premise_meter_config", "meter_scale_map"],
+        refresh: Refresh::PremiseUsage,
+    });
+    tables.push(DerivedTable {
+        name: "forecast_accuracy",
+        sources: &["load_forecast", "feeder_energy_balance"],
+        refresh: Refresh::ForecastAccuracy,
+    });
+    tables
+}
+
+/// `tables` ordered so that every table comes after the derived tables it reads.
+///
+/// Independent tables keep their relative order. Fails on a dependency cycle.
+pub fn refresh_order(tables: &[DerivedTable]) -> Result<Vec<DerivedTable>, String> {
+    let index: HashMap<&str, usize> = tables.iter().enumerate().map(|(i, t)| (t.name, i)).collect();
+    let mut pending: Vec<usize> = tables
+        .iter()
+        .map(|t| t.sources.iter().filter(|s| index.contains_key(*s)).count())
+        .collect();
+    let mut done = vec![false; tables.len()];
+    let mut ordered = Vec::with_capacity(tables.len());
+
+    while ordered.len() < tables.len() {
+        let Some(next) = (0..tables.len()).find(|&i| !done[i] && pending[i] == 0) else {
+            let cycle: Vec<&str> = (0..tables.len()).filter(|&i| !done[i]).map(|i| tables[i].name).collect();
+            return Err(format!("derived tables depend on each other: {}", cycle.join(", ")));
+        };
+        done[next] = true;
+        ordered.push(tables[next]);
+        for (i, t) in tables.iter().enumerate() {
+            if t.sources.contains(&tables[next].name) {
+                pending[i] -= 1;
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// Derived tables that are stale after `changed` tables changed, directly or
+/// through other derived tables, in refresh order.
+pub fn stale_tables(changed: &[&str]) -> Result<Vec<DerivedTable>, String> {
+    let mut stale: HashSet<&str> = changed.iter().copied().collect();
+    Ok(refresh_order(&derived_tables())?
+        .into_iter()
+        .filter(|t| {
+            let hit = t.sources.iter().any(|s| stale.contains(s));
+            if hit {
+                stale.insert(t.name);
+            }
+            hit
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(name: &'static str, sources: &'static [&'static str]) -> DerivedTable {
+        DerivedTable {
+            name,
+            sources,
+            refresh: Refresh::FeederBalance,
+        }
+    }
+
+    #[test]
+    fn orders_tables_after_their_sources() {
+        let tables = [
+            table("feeder_peaks", &["feeder_daily"]),
+            table("meter_1h", &["meter_usage"]),
+            table("feeder_daily", &["meter_1h", "feeder_map"]),
+        ];
+        let names: Vec<&str> = refresh_order(&tables).unwrap().iter().map(|t| t.name).collect();
+        assert_eq!(names, ["meter_1h", "feeder_daily", "feeder_peaks"]);
+
+        let cyclic = [table("a", &["b"]), table("b", &["a"]), table("c", &["base"])];
+        assert_eq!(
+            refresh_order(&cyclic).unwrap_err(),
+            "derived tables depend on each other: a, b"
+        );
+    }
+
+    #[test]
+    fn registry_is_acyclic_and_tracks_base_tables() {
+        assert_eq!(refresh_order(&derived_tables()).unwrap().len(), ROLLUPS.len() + 5);
+
+        let names = |changed: &[&str]| -> Vec<&str> {
+            stale_tables(changed).unwrap().iter().map(|t| t.name).collect()
+        };
+        assert_eq!(
+            names(&["generation_output"]),
+            [
+                "generation_output_1h",
+                "feeder_energy_balance",
+                "feeder_loss_uncertainty",
+                "forecast_accuracy"
+            ]
+        );
+        assert_eq!(names(&["load_forecast"]), ["forecast_accuracy"]);
+        assert_eq!(names(&["meters"]), ["feeder_loss_uncertainty"]);
+        assert_eq!(names(&["dr_events"]), ["dr_event_performance"]);
+        assert_eq!(names(&["premise_meter_config"]), ["premise_usage"]);
+        assert!(names(&["customers"]).is_empty());
+    }
+}
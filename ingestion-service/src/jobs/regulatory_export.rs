@@ -0,0 +1,408 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use sqlx::postgres::PgPool;
+use time::{Date, Month, OffsetDateTime};
+
+use crate::config::{RegulatoryColumn, RegulatoryReportConfig, RegulatoryRows};
+
+/// Sales of one customer segment in a month.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct SegmentSales {
+    pub segment: Option<String>,
+    pub mwh: f64,
+    /// Customers with usage in the month.
+    pub customers: i64,
+}
+
+/// Generation of one plant in a month.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct PlantGeneration {
+    pub plant_id: String,
+    pub name: Option<String>,
+    pub fuel_type: Option<String>,
+    pub mwh: f64,
+    pub max_mw: Option<f64>,
+}
+
+/// The month's sales and generation aggregates the reports are built from.
+#[derive(Debug, Clone)]
+pub struct MonthlyAggregates {
+    pub year: i32,
+    pub month: Month,
+    pub sales: Vec<SegmentSales>,
+    pub generation: Vec<PlantGeneration>,
+}
+
+/// Sales per customer segment, from the `meter_usage_1d` rollup and the
+/// meter's customer as of each day.
+const SALES_SQL: &str = r#"
+    SELECT c.segment, sum(u.kwh) / 1000.0 AS mwh, count_distinct(m.customer_id) AS customers
+    FROM meter_usage_1d u
+    ASOF JOIN meters m ON (meter_id)
+    ASOF JOIN customers c ON (customer_id)
+    WHERE u.ts >= $1 AND u.ts < $2 AND m.deleted = false
+    GROUP BY c.segment
+"#;
+
+/// Generation per plant, from the `generation_output_1h` rollup: the hourly
+/// average MW of each unit is the MWh of that hour.
+const GENERATION_SQL: &str = r#"
+    SELECT g.plant_id, p.name, p.fuel_type, sum(g.avg_mw) AS mwh, max(g.max_mw) AS max_mw
+    FROM generation_output_1h g
+    LEFT JOIN plants p ON p.plant_id = g.plant_id
+    WHERE g.ts >= $1 AND g.ts < $2
+    GROUP BY g.plant_id, p.name, p.fuel_type
+    ORDER BY g.plant_id
+"#;
+
+/// First day of the month and of the next one, in UTC.
+pub(crate) fn month_bounds(year: i32, month: Month) -> Result<(OffsetDateTime, OffsetDateTime), time::error::ComponentRange> {
+    let start = Date::from_calendar_date(year, month, 1)?;
+    let next_year = if month == Month::December { year + 1 } else { year };
+    let end = Date::from_calendar_date(next_year, month.next(), 1)?;
+    Ok((start.midnight().assume_utc(), end.midnight().assume_utc()))
+}
+
+/// Load the aggregates of a month.
+pub async fn aggregates(pool: &PgPool, year: i32, month: Month) -> anyhow::Result<MonthlyAggregates> {
+    let (from, to) = month_bounds(year, month)?;
+    let sales = sqlx::query_as(SALES_SQL).bind(from).bind(to).fetch_all(pool).await?;
+    let generation = sqlx::query_as(GENERATION_SQL)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(MonthlyAggregates {
+        year,
+        month,
+        sales,
+        generation,
+    })
+}
+
+/// What a report column holds.
+#[derive(Debug, Clone, PartialEq)]
+enum Field {
+    Text(String),
+    Year,
+    Month,
+    /// `YYYYMM`.
+    Period,
+    /// Of the row's category, or the total (`None`) / one category on summary rows.
+    SalesMwh(Option<String>),
+    Customers(Option<String>),
+    GenerationMwh(Option<String>),
+    Category,
+    PlantId,
+    PlantName,
+    FuelCategory,
+    MaxMw,
+}
+
+impl Field {
+    fn parse(column: &RegulatoryColumn, rows: RegulatoryRows) -> Result<Self, String> {
+        let value = match (&column.value, &column.text) {
+            (None, Some(text)) => return Ok(Self::Text(text.clone())),
+            (Some(value), None) => value.as_str(),
+            _ => {
+                return Err(format!(
+                    "column '{}' needs exactly one of value and text",
+                    column.header
+                ))
+            }
+        };
+        let (name, category) = match value.split_once(':') {
+            Some((name, category)) => (name, Some(category.to_string())),
+            None => (value, None),
+        };
+        let field = match (name, category) {
+            ("year", None) => Self::Year,
+            ("month", None) => Self::Month,
+            ("period", None) => Self::Period,
+            ("sales_mwh", c) => Self::SalesMwh(c),
+            ("customers", c) => Self::Customers(c),
+            ("generation_mwh", c) => Self::GenerationMwh(c),
+            ("category", None) => Self::Category,
+            ("plant_id", None) => Self::PlantId,
+            ("plant_name", None) => Self::PlantName,
+            ("fuel_category", None) => Self::FuelCategory,
+            ("max_mw", None) => Self::MaxMw,
+            _ => return Err(format!("unknown value '{value}' in column '{}'", column.header)),
+        };
+
+        let fits = matches!(
+            (&field, rows),
+            (Self::Text(_) | Self::Year | Self::Month | Self::Period, _)
+                | (
+                    Self::SalesMwh(_) | Self::Customers(_) | Self::GenerationMwh(_),
+                    RegulatoryRows::Summary
+                )
+                | (
+                    Self::SalesMwh(None) | Self::Customers(None) | Self::Category,
+                    RegulatoryRows::Sales
+                )
+                | (
+                    Self::GenerationMwh(None) | Self::PlantId | Self::PlantName | Self::FuelCategory | Self::MaxMw,
+                    RegulatoryRows::Generation,
+                )
+        );
+        if !fits {
+            return Err(format!(
+                "value '{value}' in column '{}' doesn't fit {rows:?} rows",
+                column.header
+            ));
+        }
+        Ok(field)
+    }
+}
+
+/// MWh and customers of one sales category.
+#[derive(Debug, Clone, Copy, Default)]
+struct CategorySales {
+    mwh: f64,
+    customers: i64,
+}
+
+/// Sales summed per report category.
+fn sales_by_category(
+    report: &RegulatoryReportConfig,
+    sales: &[SegmentSales],
+) -> Result<BTreeMap<String, CategorySales>, String> {
+    let mut out: BTreeMap<String, CategorySales> = BTreeMap::new();
+    let mut unmapped = Vec::new();
+    for s in sales {
+        let segment = s.segment.as_deref().unwrap_or("");
+        let category = if report.segments.is_empty() {
+            segment
+        } else if let Some(category) = report.segments.get(segment) {
+            category
+        } else {
+            unmapped.push(format!("'{segment}' ({:.3} MWh)", s.mwh));
+            continue;
+        };
+        let entry = out.entry(category.to_string()).or_default();
+        entry.mwh += s.mwh;
+        entry.customers += s.customers;
+    }
+    if !unmapped.is_empty() {
+        return Err(format!(
+            "report '{}': no category for customer segments {}",
+            report.name,
+            unmapped.join(", ")
+        ));
+    }
+    Ok(out)
+}
+
+fn fuel_category<'a>(report: &'a RegulatoryReportConfig, plant: &'a PlantGeneration) -> &'a str {
+    let fuel = plant.fuel_type.as_deref().unwrap_or("");
+    report.fuel_types.get(fuel).map_or(fuel, String::as_str)
+}
+
+/// Render one report as CSV in its fixed layout.
+pub fn render(report: &RegulatoryReportConfig, agg: &MonthlyAggregates) -> Result<String, String> {
+    let fields: Vec<(Field, usize)> = report
+        .columns
+        .iter()
+        .map(|c| Field::parse(c, report.rows).map(|f| (f, c.decimals)))
+        .collect::<Result<_, _>>()?;
+    let sales = sales_by_category(report, &agg.sales)?;
+    let total_sales = sales.values().fold(CategorySales::default(), |acc, s| CategorySales {
+        mwh: acc.mwh + s.mwh,
+        customers: acc.customers + s.customers,
+    });
+    let generation_mwh = |category: Option<&str>| -> f64 {
+        agg.generation
+            .iter()
+            .filter(|p| category.is_none_or(|c| fuel_category(report, p) == c))
+            .map(|p| p.mwh)
+            .sum()
+    };
+    let mwh = |v: f64, decimals: usize| format!("{v:.decimals$}");
+
+    // One cell per column, given the row's sales category or plant.
+    let cell =
+        |field: &Field, decimals: usize, category: Option<(&str, CategorySales)>, plant: Option<&PlantGeneration>| {
+            let category_sales = |c: &Option<String>| match (c, category) {
+                (Some(c), _) => sales.get(c).copied().unwrap_or_default(),
+                (None, Some((_, s))) => s,
+                (None, None) => total_sales,
+            };
+            match field {
+                Field::Text(text) => text.clone(),
+                Field::Year => agg.year.to_string(),
+                Field::Month => format!("{:02}", agg.month as u8),
+                Field::Period => format!("{}{:02}", agg.year, agg.month as u8),
+                Field::SalesMwh(c) => mwh(category_sales(c).mwh, decimals),
+                Field::Customers(c) => category_sales(c).customers.to_string(),
+                Field::GenerationMwh(c) => match plant {
+                    Some(p) => mwh(p.mwh, decimals),
+                    None => mwh(generation_mwh(c.as_deref()), decimals),
+                },
+                Field::Category => category.map(|(c, _)| c.to_string()).unwrap_or_default(),
+                Field::PlantId => plant.map(|p| p.plant_id.clone()).unwrap_or_default(),
+                Field::PlantName => plant.and_then(|p| p.name.clone()).unwrap_or_default(),
+                Field::FuelCategory => plant.map(|p| fuel_category(report, p).to_string()).unwrap_or_default(),
+                Field::MaxMw => plant
+                    .and_then(|p| p.max_mw)
+                    .map(|v| mwh(v, decimals))
+                    .unwrap_or_default(),
+            }
+        };
+
+    let mut w = csv::Writer::from_writer(Vec::new());
+    if report.header {
+        w.write_record(report.columns.iter().map(|c| &c.header))
+            .expect("writing to memory");
+    }
+    let mut row = |category: Option<(&str, CategorySales)>, plant: Option<&PlantGeneration>| {
+        let cells = fields.iter().map(|(f, decimals)| cell(f, *decimals, category, plant));
+        w.write_record(cells.collect::<Vec<_>>()).expect("writing to memory");
+    };
+    match report.rows {
+        RegulatoryRows::Summary => row(None, None),
+        RegulatoryRows::Sales => sales.iter().for_each(|(c, s)| row(Some((c, *s)), None)),
+        RegulatoryRows::Generation => agg.generation.iter().for_each(|p| row(None, Some(p))),
+    }
+    Ok(String::from_utf8(w.into_inner().expect("writing to memory")).expect("CSV is UTF-8"))
+}
+
+/// The report's file name for the month.
+pub fn file_name(report: &RegulatoryReportConfig, year: i32, month: Month) -> String {
+    report
+        .file_name
+        .replace("{year}", &year.to_string())
+        .replace("{month}", &format!("{:02}", month as u8))
+}
+
+/// Write a rendered report to `dir`, replacing an earlier export of the month.
+pub async fn write_report(dir: &Path, name: &str, body: &str) -> anyhow::Result<PathBuf> {
+    tokio::fs::create_dir_all(dir)
+        .await
+        .with_context(|| format!("creating {}", dir.display()))?;
+    let path = dir.join(name);
+    // Write aside and rename, so a filing picked up from the directory is never partial.
+    let partial = dir.join(format!("{name}.partial"));
+    tokio::fs::write(&partial, body)
+        .await
+        .with_context(|| format!("writing {}", partial.display()))?;
+    tokio::fs::rename(&partial, &path)
+        .await
+        .with_context(|| format!("renaming {}", partial.display()))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn column(header: &str, value: &str) -> RegulatoryColumn {
+        RegulatoryColumn {
+            header: header.to_string(),
+            value: Some(value.to_string()),
+            text: None,
+            decimals: 0,
+        }
+    }
+
+    fn report(rows: RegulatoryRows, columns: Vec<RegulatoryColumn>) -> RegulatoryReportConfig {
+        RegulatoryReportConfig {
+            name: "eia861m".to_string(),
+            file_name: "eia861m_{year}{month}.csv".to_string(),
+            rows,
+            header: true,
+            segments: HashMap::from([
+                ("residential".to_string(), "RES".to_string()),
+                ("small_business".to_string(), "COM".to_string()),
+                ("large_business".to_string(), "COM".to_string()),
+            ]),
+            fuel_types: HashMap::from([("ccgt".to_string(), "NG".to_string())]),
+            columns,
+        }
+    }
+
+    fn aggregates() -> MonthlyAggregates {
+        let sales = |segment: &str, mwh: f64, customers: i64| SegmentSales {
+            segment: Some(segment.to_string()),
+            mwh,
+            customers,
+        };
+        let plant = |plant_id: &str, fuel: &str, mwh: f64| PlantGeneration {
+            plant_id: plant_id.to_string(),
+            name: Some(format!("{plant_id} station")),
+            fuel_type: Some(fuel.to_string()),
+            mwh,
+            max_mw: Some(mwh / 100.0),
+        };
+        MonthlyAggregates {
+            year: 2024,
+            month: Month::March,
+            sales: vec![
+                sales("residential", 1200.4, 900),
+                sales("small_business", 300.2, 40),
+                sales("large_business", 700.0, 3),
+            ],
+            generation: vec![plant("p-1", "ccgt", 5000.0), plant("p-2", "wind", 1200.0)],
+        }
+    }
+
+    #[test]
+    fn renders_fixed_layouts_from_mapped_categories() {
+        let mut state = column("STATE", "");
+        (state.value, state.text) = (None, Some("NY".to_string()));
+        let summary = report(
+            RegulatoryRows::Summary,
+            vec![
+                column("PERIOD", "period"),
+                state,
+                column("RES_MWH", "sales_mwh:RES"),
+                column("COM_MWH", "sales_mwh:COM"),
+                column("COM_CUST", "customers:COM"),
+                column("TOTAL_MWH", "sales_mwh"),
+                column("NG_GEN_MWH", "generation_mwh:NG"),
+            ],
+        );
+        let agg = aggregates();
+        assert_eq!(
+            render(&summary, &agg).unwrap(),
+            "PERIOD,STATE,RES_MWH,COM_MWH,COM_CUST,TOTAL_MWH,NG_GEN_MWH\n202403,NY,1200,1000,43,2201,5000\n"
+        );
+        assert_eq!(file_name(&summary, 2024, Month::March), "eia861m_202403.csv");
+
+        let plants = report(
+            RegulatoryRows::Generation,
+            vec![
+                column("PLANT", "plant_id"),
+                column("FUEL", "fuel_category"),
+                column("MWH", "generation_mwh"),
+            ],
+        );
+        assert_eq!(
+            render(&plants, &agg).unwrap(),
+            "PLANT,FUEL,MWH\np-1,NG,5000\np-2,wind,1200\n"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_values_and_unmapped_segments() {
+        let agg = aggregates();
+        let err = render(&report(RegulatoryRows::Summary, vec![column("X", "revenue")]), &agg).unwrap_err();
+        assert!(err.contains("unknown value 'revenue'"), "{err}");
+        let err = render(&report(RegulatoryRows::Sales, vec![column("P", "plant_id")]), &agg).unwrap_err();
+        assert!(err.contains("doesn't fit"), "{err}");
+
+        let mut agg = aggregates();
+        agg.sales.push(SegmentSales {
+            segment: Some("street_lighting".to_string()),
+            mwh: 12.0,
+            customers: 1,
+        });
+        let err = render(&report(RegulatoryRows::Sales, vec![column("C", "category")]), &agg).unwrap_err();
+        assert!(err.contains("'street_lighting' (12.000 MWh)"), "{err}");
+    }
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::HashSet,
+    f64::consts::PI,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use futures::TryStreamExt;
+use sqlx::postgres::PgPool;
+use time::{Month, OffsetDateTime};
+use tokio::io::AsyncWriteExt;
+
+use super::regulatory_export::month_bounds;
+use crate::{
+    config::{ResearchExportConfig, ResearchResolution},
+    parquet::{ColumnType, ColumnValues, ParquetWriter},
+};
+
+/// Shortest accepted `hash_key`, in bytes.
+pub const MIN_HASH_KEY_LEN: usize = 32;
+/// Smallest accepted `jitter_m`.
+pub const MIN_JITTER_M: f64 = 100.0;
+/// Smallest accepted `min_group_size`.
+pub const MIN_GROUP_SIZE: u32 = 5;
+
+/// Rows per Parquet row group.
+const ROW_GROUP_ROWS: usize = 100_000;
+
+/// Meters per region and customer segment in the month.
+const GROUPS_SQL: &str = r#"
+    SELECT c.region_id, c.segment, count_distinct(u.meter_id) AS meters
+    FROM {table} u
+    ASOF JOIN meters m ON (meter_id)
+    ASOF JOIN customers c ON (customer_id)
+    WHERE u.ts >= $1 AND u.ts < $2 AND m.deleted = false
+    GROUP BY c.region_id, c.segment
+"#;
+
+/// Usage with the meter's customer as of each interval.
+const ROWS_SQL: &str = r#"
+    SELECT u.ts, u.meter_id, u.kwh, c.segment, c.region_id, m.customer_id, c.lat, c.lon
+    FROM {table} u
+    ASOF JOIN meters m ON (meter_id)
+    ASOF JOIN customers c ON (customer_id)
+    WHERE u.ts >= $1 AND u.ts < $2 AND m.deleted = false
+"#;
+
+/// One interval of a meter, before de-identification.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct UsageRow {
+    pub ts: OffsetDateTime,
+    pub meter_id: String,
+    pub kwh: Option<f64>,
+    pub segment: Option<String>,
+    pub region_id: Option<String>,
+    pub customer_id: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+/// One row of the dataset.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResearchRow {
+    pub meter_key: String,
+    pub ts: OffsetDateTime,
+    pub kwh: Option<f64>,
+    pub segment: Option<String>,
+    pub region_id: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, sqlx::FromRow)]
+struct Group {
+    region_id: Option<String>,
+    segment: Option<String>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct GroupSize {
+    #[sqlx(flatten)]
+    group: Group,
+    meters: i64,
+}
+
+/// The privacy ruleset applied to every exported row (see the README).
+pub struct PrivacyRules {
+    key: [u8; 32],
+    jitter_m: f64,
+    min_group_size: u32,
+}
+
+impl PrivacyRules {
+    /// Rules of `[research_export]`; settings weaker than the floors are refused.
+    pub fn from_config(cfg: &ResearchExportConfig) -> Result<Self, String> {
+        if cfg.hash_key.len() < MIN_HASH_KEY_LEN {
+            return Err(format!(
+                "research_export.hash_key must be at least {MIN_HASH_KEY_LEN} bytes"
+            ));
+        }
+        if cfg.jitter_m.is_nan() || cfg.jitter_m < MIN_JITTER_M {
+            return Err(format!("research_export.jitter_m must be at least {MIN_JITTER_M}"));
+        }
+        if cfg.min_group_size < MIN_GROUP_SIZE {
+            return Err(format!(
+                "research_export.min_group_size must be at least {MIN_GROUP_SIZE}"
+            ));
+        }
+        Ok(Self {
+            key: blake3::derive_key("questdb-utility-analytics research_export", cfg.hash_key.as_bytes()),
+            jitter_m: cfg.jitter_m,
+            min_group_size: cfg.min_group_size,
+        })
+    }
+
+    /// Pseudonymous meter id: a keyed hash, stable as long as the key is.
+    pub fn meter_key(&self, meter_id: &str) -> String {
+        let hash = blake3::keyed_hash(&self.key, format!("meter:{meter_id}").as_bytes());
+        hash.to_hex()[..32].to_string()
+    }
+
+    /// Location displaced by between half and all of `jitter_m`, in a direction
+    /// derived from the customer, then rounded to 3 decimals.
+    ///
+    /// The offset is the same in every export, so averaging files doesn't
+    /// recover the location.
+    pub fn jitter(&self, customer_id: &str, lat: f64, lon: f64) -> (f64, f64) {
+        let hash = blake3::keyed_hash(&self.key, format!("location:{customer_id}").as_bytes());
+        let bytes = hash.as_bytes();
+        let unit = |b: &[u8]| f64::from(u32::from_le_bytes([b[0], b[1], b[2], b[3]])) / f64::from(u32::MAX);
+        let angle = 2.0 * PI * unit(&bytes[0..4]);
+        // uniform over the ring's area
+        let (inner, outer) = (self.jitter_m / 2.0, self.jitter_m);
+        let distance = (inner * inner + unit(&bytes[4..8]) * (outer * outer - inner * inner)).sqrt();
+
+        const M_PER_DEGREE: f64 = 111_320.0;
+        let dlat = distance * angle.cos() / M_PER_DEGREE;
+        let dlon = distance * angle.sin() / (M_PER_DEGREE * lat.to_radians().cos().max(0.01));
+        let round = |v: f64| (v * 1000.0).round() / 1000.0;
+        (round(lat + dlat), round(lon + dlon))
+    }
+
+    /// Whether a group of this many meters is left out.
+    pub fn suppressed(&self, meters: i64) -> bool {
+        meters < i64::from(self.min_group_size)
+    }
+
+    /// The row without customer and meter ids, location jittered.
+    pub fn anonymize(&self, row: UsageRow) -> ResearchRow {
+        let (lat, lon) = match (&row.customer_id, row.lat, row.lon) {
+            (Some(customer), Some(lat), Some(lon)) => {
+                let (lat, lon) = self.jitter(customer, lat, lon);
+                (Some(lat), Some(lon))
+            }
+            _ => (None, None),
+        };
+        ResearchRow {
+            meter_key: self.meter_key(&row.meter_id),
+            ts: row.ts,
+            kwh: row.kwh,
+            segment: row.segment,
+            region_id: row.region_id,
+            lat,
+            lon,
+        }
+    }
+}
+
+/// Column layout of the dataset.
+fn schema() -> Vec<(String, ColumnType)> {
+    [
+        ("meter_key", ColumnType::Text),
+        ("ts", ColumnType::TimestampMicros),
+        ("kwh", ColumnType::Double),
+        ("segment", ColumnType::Text),
+        ("region_id", ColumnType::Text),
+        ("lat", ColumnType::Double),
+        ("lon", ColumnType::Double),
+    ]
+    .into_iter()
+    .map(|(name, kind)| (name.to_string(), kind))
+    .collect()
+}
+
+fn columns(rows: Vec<ResearchRow>) -> Vec<ColumnValues> {
+    let mut meter_key = Vec::with_capacity(rows.len());
+    let mut ts = Vec::with_capacity(rows.len());
+    let mut kwh = Vec::with_capacity(rows.len());
+    let mut segment = Vec::with_capacity(rows.len());
+    let mut region_id = Vec::with_capacity(rows.len());
+    let mut lat = Vec::with_capacity(rows.len());
+    let mut lon = Vec::with_capacity(rows.len());
+    for row in rows {
+        meter_key.push(Some(row.meter_key));
+        ts.push(Some((row.ts.unix_timestamp_nanos() / 1000) as i64));
+        kwh.push(row.kwh);
+        segment.push(row.segment);
+        region_id.push(row.region_id);
+        lat.push(row.lat);
+        lon.push(row.lon);
+    }
+    vec![
+        ColumnValues::Text(meter_key),
+        ColumnValues::TimestampMicros(ts),
+        ColumnValues::Double(kwh),
+        ColumnValues::Text(segment),
+        ColumnValues::Text(region_id),
+        ColumnValues::Double(lat),
+        ColumnValues::Double(lon),
+    ]
+}
+
+/// File name of a month's dataset.
+pub fn file_name(year: i32, month: Month) -> String {
+    format!("meter_usage_research_{year}-{:02}.parquet", u8::from(month))
+}
+
+/// What an export wrote and left out.
+#[derive(Debug, Clone)]
+pub struct ExportSummary {
+    pub path: PathBuf,
+    pub rows: u64,
+    pub suppressed_groups: usize,
+    pub suppressed_meters: i64,
+}
+
+/// Write the de-identified dataset of a month to `out_dir`, replacing an
+/// earlier file of the month.
+pub async fn export(
+    pool: &PgPool,
+    cfg: &ResearchExportConfig,
+    year: i32,
+    month: Month,
+    out_dir: &Path,
+) -> anyhow::Result<ExportSummary> {
+    let rules = PrivacyRules::from_config(cfg).map_err(|e| anyhow!(e))?;
+    let table = match cfg.resolution {
+        ResearchResolution::Hour => "meter_usage_1h",
+        ResearchResolution::Day => "meter_usage_1d",
+    };
+    let (from, to) = month_bounds(year, month)?;
+
+    let groups: Vec<GroupSize> = sqlx::query_as(&GROUPS_SQL.replace("{table}", table))
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    let suppressed: HashSet<Group> = groups
+        .iter()
+        .filter(|g| rules.suppressed(g.meters))
+        .map(|g| g.group.clone())
+        .collect();
+    let suppressed_meters = groups
+        .iter()
+        .filter(|g| rules.suppressed(g.meters))
+        .map(|g| g.meters)
+        .sum();
+
+    tokio::fs::create_dir_all(out_dir)
+        .await
+        .with_context(|| format!("creating {}", out_dir.display()))?;
+    let name = file_name(year, month);
+    let path = out_dir.join(&name);
+    // Write aside and rename, so a dataset picked up from the directory is never partial.
+    let partial = out_dir.join(format!("{name}.partial"));
+    let mut file = tokio::fs::File::create(&partial)
+        .await
+        .with_context(|| format!("creating {}", partial.display()))?;
+
+    let sql = ROWS_SQL.replace("{table}", table);
+    let mut rows = sqlx::query_as::<_, UsageRow>(&sql).bind(from).bind(to).fetch(pool);
+    let mut writer = ParquetWriter::new(schema());
+    let mut batch = Vec::with_capacity(ROW_GROUP_ROWS);
+    let mut written = 0u64;
+    while let Some(row) = rows.try_next().await? {
+        let group = Group {
+            region_id: row.region_id.clone(),
+            segment: row.segment.clone(),
+        };
+        if suppressed.contains(&group) {
+            continue;
+        }
+        batch.push(rules.anonymize(row));
+        if batch.len() == ROW_GROUP_ROWS {
+            written += batch.len() as u64;
+            let bytes = writer
+                .row_group(&columns(std::mem::take(&mut batch)))
+                .map_err(|e| anyhow!(e))?;
+            file.write_all(&bytes).await?;
+        }
+    }
+    if !batch.is_empty() {
+        written += batch.len() as u64;
+        let bytes = writer.row_group(&columns(batch)).map_err(|e| anyhow!(e))?;
+        file.write_all(&bytes).await?;
+    }
+    file.write_all(&writer.finish()).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(&partial, &path)
+        .await
+        .with_context(|| format!("renaming {}", partial.display()))?;
+
+    Ok(ExportSummary {
+        path,
+        rows: written,
+        suppressed_groups: suppressed.len(),
+        suppressed_meters,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn config() -> ResearchExportConfig {
+        ResearchExportConfig {
+            out_dir: PathBuf::from("/tmp"),
+            hash_key: "0123456789abcdef0123456789abcdef".to_string(),
+            jitter_m: 500.0,
+            min_group_size: 10,
+            resolution: ResearchResolution::Hour,
+        }
+    }
+
+    #[test]
+    fn weaker_settings_than_the_floors_are_refused() {
+        let short_key = ResearchExportConfig {
+            hash_key: "secret".to_string(),
+            ..config()
+        };
+        assert!(PrivacyRules::from_config(&short_key).is_err());
+        let small_jitter = ResearchExportConfig {
+            jitter_m: 10.0,
+            ..config()
+        };
+        assert!(PrivacyRules::from_config(&small_jitter).is_err());
+        let small_groups = ResearchExportConfig {
+            min_group_size: 2,
+            ..config()
+        };
+        assert!(PrivacyRules::from_config(&small_groups).is_err());
+        assert!(PrivacyRules::from_config(&config()).is_ok());
+    }
+
+    #[test]
+    fn meter_keys_are_stable_per_key() {
+        let rules = PrivacyRules::from_config(&config()).unwrap();
+        let key = rules.meter_key("M-1001");
+        assert_eq!(key.len(), 32);
+        assert_eq!(key, rules.meter_key("M-1001"));
+        assert_ne!(key, rules.meter_key("M-1002"));
+        assert!(!key.contains("1001"));
+
+        let other = PrivacyRules::from_config(&ResearchExportConfig {
+            hash_key: "fedcba9876543210fedcba9876543210".to_string(),
+            ..config()
+        })
+        .unwrap();
+        assert_ne!(key, other.meter_key("M-1001"));
+    }
+
+    #[test]
+    fn locations_move_within_the_jitter_ring() {
+        let rules = PrivacyRules::from_config(&config()).unwrap();
+        let (lat, lon) = (45.5, -122.6);
+        for customer in ["C-1", "C-2", "C-3", "C-4", "C-5"] {
+            let (jlat, jlon) = rules.jitter(customer, lat, lon);
+            assert_eq!((jlat, jlon), rules.jitter(customer, lat, lon));
+            let dy = (jlat - lat) * 111_320.0;
+            let dx = (jlon - lon) * 111_320.0 * lat.to_radians().cos();
+            let distance = (dx * dx + dy * dy).sqrt();
+            // rounding to 3 decimals moves a point by up to ~80 m
+            assert!(
+                (250.0 - 80.0..=500.0 + 80.0).contains(&distance),
+                "{customer}: {distance}"
+            );
+        }
+    }
+
+    #[test]
+    fn anonymized_rows_drop_ids() {
+        let rules = PrivacyRules::from_config(&config()).unwrap();
+        let row = rules.anonymize(UsageRow {
+            ts: datetime!(2024-03-01 00:00 UTC),
+            meter_id: "M-1".to_string(),
+            kwh: Some(1.25),
+            segment: Some("residential".to_string()),
+            region_id: Some("north".to_string()),
+            customer_id: None,
+            lat: Some(45.5),
+            lon: Some(-122.6),
+        });
+        assert_eq!(row.meter_key, rules.meter_key("M-1"));
+        assert_eq!((row.lat, row.lon), (None, None));
+        assert!(rules.suppressed(9));
+        assert!(!rules.suppressed(10));
+    }
+}
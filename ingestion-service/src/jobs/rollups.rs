@@ -0,0 +1,260 @@
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use super::partitions::{drop_partitions_sql, sql_ts, PartitionBy};
+
+/// A rollup of a base time-series table.
+///
+/// The query must produce a designated `ts` column (typically via `SAMPLE BY`).
+/// This is the single source of truth for rollup DDL: it is used both for
+/// QuestDB materialized views and for the manual fallback tables.
+#[derive(Debug, Clone, Copy)]
+pub struct RollupDef {
+    pub name: &'static str,
+    pub base_table: &'static str,
+    pub query: &'static str,
+    pub partition_by: PartitionBy,
+}
+
+pub const ROLLUPS: &[RollupDef] = &[
+    RollupDef {
+        name: "meter_usage_1h",
+        base_table: "meter_usage",
+        query: "SELECT ts, meter_id, sum(kwh) AS kwh, sum(kvarh) AS kvarh, max(kva_demand) AS max_kva_demand, count() AS samples \
+                FROM meter_usage SAMPLE BY 1h",
+        partition_by: PartitionBy::Day,
+    },
+    RollupDef {
+        name: "meter_usage_1d",
+        base_table: "meter_usage",
+        query: "SELECT ts, meter_id, sum(kwh) AS kwh, sum(kvarh) AS kvarh, max(kva_demand) AS max_kva_demand, count() AS samples \
+                FROM meter_usage SAMPLE BY 1d",
+        partition_by: PartitionBy::Month,
+    },
+    RollupDef {
+        name: "generation_output_1h",
+        base_table: "generation_output",
+        query: "SELECT ts, plant_id, unit_id, avg(mw) AS avg_mw, max(mw) AS max_mw, avg(mvar) AS avg_mvar, count() AS samples \
+                FROM generation_output SAMPLE BY 1h",
+        partition_by: PartitionBy::Day,
+    },
+];
+
+/// First QuestDB release with materialized views.
+pub const MATERIALIZED_VIEWS_MIN_VERSION: ServerVersion = ServerVersion {
+    major: 8,
+    minor: 3,
+    patch: 0,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ServerVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ServerVersion {
+    /// Parse the version out of QuestDB's `build()` string,
+    /// e.g. `"Build Information: QuestDB 8.3.1, JDK 17.0.7, Commit Hash ..."`.
+    pub fn parse_build_info(build: &str) -> Option<Self> {
+        let rest = &build[build.find("QuestDB ")? + "QuestDB ".len()..];
+        let version = rest
+            .split(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .next()?;
+
+        let mut parts = version.split('.').map(|p| p.parse::<u32>().ok());
+        Some(Self {
+            major: parts.next()??,
+            minor: parts.next().flatten().unwrap_or(0),
+            patch: parts.next().flatten().unwrap_or(0),
+        })
+    }
+}
+
+/// How rollups are maintained on a given server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollupMode {
+    /// QuestDB materialized views, refreshed by the server.
+    MaterializedView,
+    /// Plain tables rebuilt by this job (servers without materialized views).
+    ManualTable,
+}
+
+impl RollupMode {
+    pub fn for_version(version: Option<ServerVersion>) -> Self {
+        match version {
+            Some(v) if v >= MATERIALIZED_VIEWS_MIN_VERSION => Self::MaterializedView,
+            _ => Self::ManualTable,
+        }
+    }
+}
+
+/// Detect the server version via `build()`.
+///
+/// Returns `None` if the function is missing or the output can't be parsed.
+pub async fn detect_server_version(pool: &PgPool) -> Option<ServerVersion> {
+    let build: String = sqlx::query_scalar("SELECT build()").fetch_one(pool).await.ok()?;
+    ServerVersion::parse_build_info(&build)
+}
+
+pub fn create_sql(def: &RollupDef, mode: RollupMode) -> String {
+    match mode {
+        RollupMode::MaterializedView => format!(
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS {} AS ({}) PARTITION BY {};",
+            def.name, def.query, def.partition_by
+        ),
+        RollupMode::ManualTable => format!(
+            "CREATE TABLE IF NOT EXISTS {} AS ({}) TIMESTAMP(ts) PARTITION BY {};",
+            def.name, def.query, def.partition_by
+        ),
+    }
+}
+
+/// Statements that bring a rollup up to date with its base table.
+pub fn refresh_sql(def: &RollupDef, mode: RollupMode) -> Vec<String> {
+    match mode {
+        RollupMode::MaterializedView => {
+            vec![format!("REFRESH MATERIALIZED VIEW {} INCREMENTAL;", def.name)]
+        }
+        RollupMode::ManualTable => vec![
+            format!("TRUNCATE TABLE {};", def.name),
+            format!("INSERT INTO {} {};", def.name, def.query),
+        ],
+    }
+}
+
+/// The rollup query restricted to base rows in `[from, to)`.
+fn range_query(def: &RollupDef, from: OffsetDateTime, to: OffsetDateTime) -> String {
+    let (select, sample_by) = def
+        .query
+        .split_once(" SAMPLE BY ")
+        .expect("rollup queries end with SAMPLE BY");
+    format!(
+        "{select} WHERE ts >= '{}' AND ts < '{}' SAMPLE BY {sample_by}",
+        sql_ts(from),
+        sql_ts(to)
+    )
+}
+
+/// Statements that recompute a rollup for `[from, to)`, widened to whole partitions.
+///
+/// Manual tables drop the affected partitions and rebuild them, since QuestDB
+/// can't delete individual rows.
+pub fn refresh_range_sql(def: &RollupDef, mode: RollupMode, from: OffsetDateTime, to: OffsetDateTime) -> Vec<String> {
+    let (from, to) = def.partition_by.widen(from, to);
+    match mode {
+        RollupMode::MaterializedView => vec![format!(
+            "REFRESH MATERIALIZED VIEW {} RANGE FROM '{}' TO '{}';",
+            def.name,
+            sql_ts(from),
+            sql_ts(to)
+        )],
+        RollupMode::ManualTable => vec![
+            drop_partitions_sql(def.name, from, to),
+            format!("INSERT INTO {} {};", def.name, range_query(def, from, to)),
+        ],
+    }
+}
+
+/// Recompute one rollup for `[from, to)`; see [`refresh_range_sql`].
+pub async fn refresh_range(
+    pool: &PgPool,
+    mode: RollupMode,
+    def: &RollupDef,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> Result<(), sqlx::Error> {
+    for stmt in refresh_range_sql(def, mode, from, to) {
+        sqlx::query(&stmt).execute(pool).await?;
+    }
+    metrics::counter!("rollup_refresh_total", "rollup" => def.name).increment(1);
+    Ok(())
+}
+
+/// Create all rollups that don't exist yet.
+pub async fn apply(pool: &PgPool, mode: RollupMode) -> Result<(), sqlx::Error> {
+    for def in ROLLUPS {
+        sqlx::query(&create_sql(def, mode)).execute(pool).await?;
+        tracing::info!(rollup = def.name, mode = ?mode, "rollup ensured");
+    }
+    Ok(())
+}
+
+/// Refresh rollups, optionally only those built on `base_table`.
+pub async fn refresh(pool: &PgPool, mode: RollupMode, base_table: Option<&str>) -> Result<(), sqlx::Error> {
+    for def in ROLLUPS
+        .iter()
+        .filter(|d| base_table.is_none_or(|t| t == d.base_table))
+    {
+        for stmt in refresh_sql(def, mode) {
+            sqlx::query(&stmt).execute(pool).await?;
+        }
+        metrics::counter!("rollup_refresh_total", "rollup" => def.name).increment(1);
+        tracing::info!(rollup = def.name, mode = ?mode, "rollup refreshed");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_questdb_build_info() {
+        let v = ServerVersion::parse_build_info(
+            "Build Information: QuestDB 8.3.1, JDK 17.0.7, Commit Hash 1234abcd",
+        )
+        .unwrap();
+        assert_eq!(v, ServerVersion { major: 8, minor: 3, patch: 1 });
+
+        let v = ServerVersion::parse_build_info("Build Information: QuestDB 7.4, JDK 11").unwrap();
+        assert_eq!(v, ServerVersion { major: 7, minor: 4, patch: 0 });
+
+        assert!(ServerVersion::parse_build_info("PostgreSQL 12.3").is_none());
+    }
+
+    #[test]
+    fn mode_falls_back_below_materialized_view_support() {
+        let old = ServerVersion { major: 8, minor: 2, patch: 3 };
+        let new = ServerVersion { major: 8, minor: 3, patch: 0 };
+        assert_eq!(RollupMode::for_version(Some(old)), RollupMode::ManualTable);
+        assert_eq!(RollupMode::for_version(Some(new)), RollupMode::MaterializedView);
+        assert_eq!(RollupMode::for_version(None), RollupMode::ManualTable);
+    }
+
+    #[test]
+    fn manual_refresh_rebuilds_table_from_view_query() {
+        let def = &ROLLUPS[0];
+        let stmts = refresh_sql(def, RollupMode::ManualTable);
+        assert_eq!(stmts[0], "TRUNCATE TABLE meter_usage_1h;");
+        assert!(stmts[1].starts_with("INSERT INTO meter_usage_1h SELECT ts, meter_id"));
+
+        let create = create_sql(def, RollupMode::MaterializedView);
+        assert!(create.starts_with("CREATE MATERIALIZED VIEW IF NOT EXISTS meter_usage_1h AS (SELECT"));
+        assert!(create.ends_with("PARTITION BY DAY;"));
+    }
+
+    #[test]
+    fn range_refresh_rebuilds_whole_partitions() {
+        use time::macros::datetime;
+
+        let def = &ROLLUPS[1]; // meter_usage_1d, partitioned by month
+        let (from, to) = (datetime!(2024-03-10 06:00:00 UTC), datetime!(2024-03-12 00:00:00 UTC));
+
+        let stmts = refresh_range_sql(def, RollupMode::ManualTable, from, to);
+        assert_eq!(
+            stmts[0],
+            "ALTER TABLE meter_usage_1d DROP PARTITION WHERE ts >= '2024-03-01T00:00:00Z' AND ts < '2024-04-01T00:00:00Z';"
+        );
+        assert!(stmts[1].ends_with(
+            "FROM meter_usage WHERE ts >= '2024-03-01T00:00:00Z' AND ts < '2024-04-01T00:00:00Z' SAMPLE BY 1d;"
+        ));
+
+        let stmts = refresh_range_sql(def, RollupMode::MaterializedView, from, to);
+        assert_eq!(
+            stmts,
+            ["REFRESH MATERIALIZED VIEW meter_usage_1d RANGE FROM '2024-03-01T00:00:00Z' TO '2024-04-01T00:00:00Z';"]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use sqlx::postgres::PgPool;
+use time::{Date, Month, OffsetDateTime};
+
+use super::partitions::{sql_ts, PartitionBy};
+
+/// A snapshot table of billing-relevant aggregates.
+///
+/// `query` selects `columns` from the live tables; `{range}` is replaced by
+/// the period filter on `ts`. Snapshot tables add `period_start` and
+/// `frozen_at` and are never rewritten, so they are deliberately not part of
+/// the derived-table registry.
+#[derive(Debug, Clone, Copy)]
+pub struct SettledTable {
+    pub name: &'static str,
+    pub columns: &'static str,
+    pub query: &'static str,
+}
+
+pub const SETTLED_TABLES: &[SettledTable] = &[
+    SettledTable {
+        name: "settled_meter_usage_1d",
+        columns: "ts, meter_id, kwh, kvarh, max_kva_demand, samples",
+        query: "SELECT ts, meter_id, sum(kwh) AS kwh, sum(kvarh) AS kvarh, max(kva_demand) AS max_kva_demand, count() AS samples \
+                FROM meter_usage WHERE {range} SAMPLE BY 1d",
+    },
+    SettledTable {
+        name: "settled_feeder_energy_balance",
+        columns: "ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_kwh, loss_pct, meter_coverage_pct, complete",
+        query: "SELECT ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_kwh, loss_pct, meter_coverage_pct, complete \
+                FROM feeder_energy_balance WHERE {range}",
+    },
+];
+
+/// A billing period `[start, end)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementPeriod {
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+}
+
+impl SettlementPeriod {
+    /// The calendar month `month` of `year` (UTC).
+    pub fn month(year: i32, month: Month) -> Result<Self, String> {
+        let start = Date::from_calendar_date(year, month, 1)
+            .map_err(|e| format!("invalid month {year}-{month}: {e}"))?
+            .midnight()
+            .assume_utc();
+        let (start, end) = PartitionBy::Month.widen(start, start + time::Duration::days(1));
+        Ok(Self { start, end })
+    }
+
+    /// The last month that ended before `now`.
+    pub fn previous_month(now: OffsetDateTime) -> Self {
+        let this_month = PartitionBy::Month.floor(now);
+        let (start, end) = PartitionBy::Month.widen(this_month - time::Duration::days(1), this_month);
+        Self { start, end }
+    }
+}
+
+/// Statement copying the period's rows of `table` into its snapshot, stamped with `frozen_at`.
+pub fn freeze_sql(table: &SettledTable, period: SettlementPeriod, frozen_at: OffsetDateTime) -> String {
+    let range = format!("ts >= '{}' AND ts < '{}'", sql_ts(period.start), sql_ts(period.end));
+    format!(
+        "INSERT INTO {} ({cols}, period_start, frozen_at) \
+         SELECT {cols}, cast('{}' AS TIMESTAMP), cast('{}' AS TIMESTAMP) FROM ({});",
+        table.name,
+        sql_ts(period.start),
+        sql_ts(frozen_at),
+        table.query.replace("{range}", &range),
+        cols = table.columns,
+    )
+}
+
+/// When `table` was frozen for the period starting at `period_start`, if it was.
+pub async fn freeze_time(
+    pool: &PgPool,
+    table: &str,
+    period_start: OffsetDateTime,
+) -> Result<Option<OffsetDateTime>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT frozen_at FROM settlement_periods WHERE table_name = $1 AND period_start = $2 \
+         ORDER BY frozen_at LIMIT 1",
+    )
+    .bind(table)
+    .bind(period_start)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Outcome of freezing one table.
+#[derive(Debug, Clone, Copy)]
+pub struct Frozen {
+    pub table: &'static str,
+    pub frozen_at: OffsetDateTime,
+    /// Rows in the snapshot; `None` if the period was already frozen.
+    pub rows: Option<i64>,
+}
+
+/// Snapshot every [settled table](SETTLED_TABLES) for a closed `period`.
+///
+/// Tables already frozen for the period are left alone: a freeze is final,
+/// and re-running after a partial failure completes the remaining tables.
+/// Readers select the snapshot rows with the `frozen_at` recorded in
+/// `settlement_periods`; rows of an interrupted freeze have no record there.
+pub async fn freeze(pool: &PgPool, period: SettlementPeriod, now: OffsetDateTime) -> anyhow::Result<Vec<Frozen>> {
+    if period.end > now {
+        anyhow::bail!("period ending {} has not closed yet", sql_ts(period.end));
+    }
+    // QuestDB timestamps have microsecond precision.
+    let frozen_at = now.replace_nanosecond(now.nanosecond() / 1000 * 1000)?;
+
+    let mut out = Vec::with_capacity(SETTLED_TABLES.len());
+    for table in SETTLED_TABLES {
+        if let Some(existing) = freeze_time(pool, table.name, period.start).await? {
+            tracing::info!(table = table.name, frozen_at = %existing, "period already frozen; keeping the snapshot");
+            out.push(Frozen {
+                table: table.name,
+                frozen_at: existing,
+                rows: None,
+            });
+            continue;
+        }
+
+        sqlx::query(&freeze_sql(table, period, frozen_at)).execute(pool).await?;
+        let rows: i64 = sqlx::query_scalar(&format!(
+            "SELECT count() FROM {} WHERE ts >= $1 AND ts < $2 AND frozen_at = $3",
+            table.name
+        ))
+        .bind(period.start)
+        .bind(period.end)
+        .bind(frozen_at)
+        .fetch_one(pool)
+        .await?;
+        sqlx::query(
+            "INSERT INTO settlement_periods (frozen_at, table_name, period_start, period_end, rows) \
+             VALUES ($1, $2, $3, $4, $5)",
+        )
+        .bind(frozen_at)
+        .bind(table.name)
+        .bind(period.start)
+        .bind(period.end)
+        .bind(rows)
+        .execute(pool)
+        .await?;
+
+        metrics::counter!("settlement_rows_frozen_total", "table" => table.name).increment(rows.max(0) as u64);
+        out.push(Frozen {
+            table: table.name,
+            frozen_at,
+            rows: Some(rows),
+        });
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn periods_are_calendar_months() {
+        assert_eq!(
+            SettlementPeriod::previous_month(datetime!(2024-01-15 10:00 UTC)),
+            SettlementPeriod {
+                start: datetime!(2023-12-01 00:00 UTC),
+                end: datetime!(2024-01-01 00:00 UTC),
+            }
+        );
+        assert_eq!(
+            SettlementPeriod::month(2024, Month::February).unwrap(),
+            SettlementPeriod {
+                start: datetime!(2024-02-01 00:00 UTC),
+                end: datetime!(2024-03-01 00:00 UTC),
+            }
+        );
+    }
+
+    #[test]
+    fn freeze_copies_the_period_with_its_freeze_timestamp() {
+        let period = SettlementPeriod::month(2024, Month::March).unwrap();
+        let sql = freeze_sql(&SETTLED_TABLES[1], period, datetime!(2024-04-05 08:30:00.25 UTC));
+        assert_eq!(
+            sql,
+            "INSERT INTO settled_feeder_energy_balance (ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_kwh, \
+             loss_pct, meter_coverage_pct, complete, period_start, frozen_at) \
+             SELECT ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_kwh, loss_pct, meter_coverage_pct, complete, \
+             cast('2024-03-01T00:00:00Z' AS TIMESTAMP), cast('2024-04-05T08:30:00.25Z' AS TIMESTAMP) \
+             FROM (SELECT ts, feeder_id, feeder_kwh_gen, feeder_kwh_demand, loss_kwh, loss_pct, meter_coverage_pct, \
+             complete FROM feeder_energy_balance WHERE ts >= '2024-03-01T00:00:00Z' AND ts < '2024-04-01T00:00:00Z');"
+        );
+    }
+}
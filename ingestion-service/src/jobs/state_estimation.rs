@@ -0,0 +1,236 @@
+use std::path::PathBuf;
+
+use sqlx::postgres::PgPool;
+use time::{format_description::well_known::Rfc3339, macros::format_description, Duration, OffsetDateTime};
+
+use super::{job_runs, regulatory_export::write_report};
+use crate::config::StateEstimationExportConfig;
+
+/// Feeder head injections: the window's average output of each generating
+/// unit, summed per feeder. Bind parameters: `$1` window start, `$2` end.
+const FEEDER_HEAD_SQL: &str = r#"
+    SELECT feeder_id AS id, sum(avg_mw) AS p_mw, sum(avg_mvar) AS q_mvar, count() AS count
+    FROM (
+        SELECT pfm.feeder_id, go.plant_id, go.unit_id, avg(go.mw) AS avg_mw, avg(go.mvar) AS avg_mvar
+        FROM generation_output go
+        JOIN plant_feeder_map pfm
+          ON pfm.plant_id = go.plant_id
+         AND (pfm.unit_id IS NULL OR pfm.unit_id = go.unit_id)
+         AND pfm.from_ts <= go.ts
+         AND pfm.to_ts   >  go.ts
+        WHERE go.ts >= $1 AND go.ts < $2
+        GROUP BY pfm.feeder_id, go.plant_id, go.unit_id
+    )
+    GROUP BY feeder_id
+    ORDER BY feeder_id
+"#;
+
+/// Load per network node: the window's metered energy as average MW/MVAr.
+/// Bind parameters: `$1` window start, `$2` end, `$3` MW per kWh over the window.
+const NODE_LOAD_SQL: &str = r#"
+    SELECT
+        nm.node_id AS id,
+        sum(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) * $3 AS p_mw,
+        sum(mu.kvarh * COALESCE(msm.kvarh_multiplier, 1.0)) * $3 AS q_mvar,
+        count_distinct(mu.meter_id) AS count
+    FROM meter_usage mu
+    JOIN meter_node_map nm
+      ON nm.meter_id = mu.meter_id
+     AND nm.from_ts <= mu.ts
+     AND nm.to_ts   >  mu.ts
+    LEFT JOIN meter_scale_map msm
+      ON msm.meter_id = mu.meter_id
+     AND msm.from_ts <= mu.ts
+     AND msm.to_ts   >  mu.ts
+    WHERE mu.ts >= $1 AND mu.ts < $2
+    GROUP BY nm.node_id
+    ORDER BY nm.node_id
+"#;
+
+/// Active and reactive power of one feeder head or node over a window.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct Injection {
+    pub id: String,
+    pub p_mw: Option<f64>,
+    pub q_mvar: Option<f64>,
+    /// Generating units (feeder heads) or meters (nodes) behind the values.
+    pub count: i64,
+}
+
+/// State estimator input for the window ending at `end`.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub end: OffsetDateTime,
+    pub feeder_heads: Vec<Injection>,
+    pub loads: Vec<Injection>,
+}
+
+/// Check the cadence of `cfg` aligns snapshots to the day.
+pub fn validate(cfg: &StateEstimationExportConfig) -> Result<(), String> {
+    if cfg.cadence_minutes == 0 || 1440 % cfg.cadence_minutes != 0 {
+        return Err(format!(
+            "cadence_minutes must divide a day (1440 minutes), got {}",
+            cfg.cadence_minutes
+        ));
+    }
+    Ok(())
+}
+
+/// End of the latest window that is `lag` old at `now`; windows are aligned
+/// to midnight UTC.
+pub fn latest_window_end(now: OffsetDateTime, cadence: Duration, lag: Duration) -> OffsetDateTime {
+    let secs = (now - lag).unix_timestamp();
+    let step = cadence.whole_seconds();
+    OffsetDateTime::from_unix_timestamp(secs - secs.rem_euclid(step)).expect("within the range of `now`")
+}
+
+/// Load the snapshot of the window `[end - cadence, end)`.
+pub async fn snapshot(pool: &PgPool, end: OffsetDateTime, cadence: Duration) -> Result<Snapshot, sqlx::Error> {
+    let start = end - cadence;
+    let feeder_heads = sqlx::query_as(FEEDER_HEAD_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+    let mw_per_kwh = 1.0 / (cadence.as_seconds_f64() / 3600.0) / 1000.0;
+    let loads = sqlx::query_as(NODE_LOAD_SQL)
+        .bind(start)
+        .bind(end)
+        .bind(mw_per_kwh)
+        .fetch_all(pool)
+        .await?;
+    Ok(Snapshot {
+        end,
+        feeder_heads,
+        loads,
+    })
+}
+
+/// The snapshot as the estimator's CSV input: feeder heads, then loads.
+pub fn render(snapshot: &Snapshot) -> String {
+    let end = snapshot
+        .end
+        .format(&Rfc3339)
+        .expect("RFC 3339 timestamps within year 0..=9999");
+    let value = |v: Option<f64>| v.map(|v| format!("{v:.4}")).unwrap_or_default();
+
+    let mut w = csv::Writer::from_writer(Vec::new());
+    w.write_record(["snapshot", "kind", "element_id", "p_mw", "q_mvar", "count"])
+        .expect("writing to memory");
+    let rows = snapshot
+        .feeder_heads
+        .iter()
+        .map(|i| ("feeder_head", i))
+        .chain(snapshot.loads.iter().map(|i| ("load", i)));
+    for (kind, i) in rows {
+        w.write_record([
+            end.as_str(),
+            kind,
+            &i.id,
+            &value(i.p_mw),
+            &value(i.q_mvar),
+            &i.count.to_string(),
+        ])
+        .expect("writing to memory");
+    }
+    String::from_utf8(w.into_inner().expect("writing to memory")).expect("CSV is UTF-8")
+}
+
+/// The snapshot's file name, e.g. `se_input_20240601T0015Z.csv`.
+pub fn file_name(end: OffsetDateTime) -> String {
+    let stamp = end
+        .format(format_description!("[year][month][day]T[hour][minute]Z"))
+        .expect("formatting a UTC timestamp");
+    format!("se_input_{stamp}.csv")
+}
+
+/// Write the snapshot of the window ending at `end`, replacing an earlier export of it.
+pub async fn export(pool: &PgPool, cfg: &StateEstimationExportConfig, end: OffsetDateTime) -> anyhow::Result<PathBuf> {
+    let cadence = Duration::minutes(i64::from(cfg.cadence_minutes));
+    let snapshot = snapshot(pool, end, cadence).await?;
+    let path = write_report(&cfg.out_dir, &file_name(end), &render(&snapshot)).await?;
+    metrics::counter!("state_estimation_snapshots_total").increment(1);
+    Ok(path)
+}
+
+/// Export a snapshot every `cadence_minutes`, `lag_minutes` after its window
+/// ends, forever. Starts with the latest window that is old enough; a failed
+/// snapshot is logged and the next one is tried on time.
+pub async fn run(pool: &PgPool, cfg: &StateEstimationExportConfig) {
+    let cadence = Duration::minutes(i64::from(cfg.cadence_minutes));
+    let lag = Duration::minutes(i64::from(cfg.lag_minutes));
+    let mut end = latest_window_end(OffsetDateTime::now_utc(), cadence, lag);
+    loop {
+        let wait = end + lag - OffsetDateTime::now_utc();
+        if wait.is_positive() {
+            tokio::time::sleep(wait.unsigned_abs()).await;
+        }
+        match job_runs::tracked(pool, "state_estimation_export", export(pool, cfg, end)).await {
+            Ok(path) => tracing::info!(snapshot = %end, file = %path.display(), "state estimation input written"),
+            Err(e) => {
+                metrics::counter!("state_estimation_export_failures_total").increment(1);
+                tracing::warn!(snapshot = %end, error = %e, "state estimation export failed");
+            }
+        }
+        end += cadence;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn injection(id: &str, p_mw: Option<f64>, q_mvar: Option<f64>, count: i64) -> Injection {
+        Injection {
+            id: id.to_string(),
+            p_mw,
+            q_mvar,
+            count,
+        }
+    }
+
+    #[test]
+    fn windows_align_to_the_cadence_and_wait_for_the_lag() {
+        let (cadence, lag) = (Duration::minutes(15), Duration::minutes(20));
+        let end = latest_window_end(datetime!(2024-06-01 00:50:10 UTC), cadence, lag);
+        assert_eq!(end, datetime!(2024-06-01 00:30 UTC));
+        let end = latest_window_end(datetime!(2024-06-01 00:34:59 UTC), cadence, lag);
+        assert_eq!(end, datetime!(2024-06-01 00:00 UTC));
+        let end = latest_window_end(datetime!(2024-06-01 00:10 UTC), Duration::hours(1), lag);
+        assert_eq!(end, datetime!(2024-05-31 23:00 UTC));
+    }
+
+    #[test]
+    fn renders_feeder_heads_then_loads() {
+        let snapshot = Snapshot {
+            end: datetime!(2024-06-01 00:15 UTC),
+            feeder_heads: vec![injection("F12", Some(4.21), Some(1.05), 3)],
+            loads: vec![
+                injection("N-1001", Some(0.12), None, 42),
+                injection("N,1002", Some(0.0834567), Some(-0.01), 7),
+            ],
+        };
+        assert_eq!(
+            render(&snapshot),
+            "snapshot,kind,element_id,p_mw,q_mvar,count\n\
+             2024-06-01T00:15:00Z,feeder_head,F12,4.2100,1.0500,3\n\
+             2024-06-01T00:15:00Z,load,N-1001,0.1200,,42\n\
+             2024-06-01T00:15:00Z,load,\"N,1002\",0.0835,-0.0100,7\n"
+        );
+        assert_eq!(file_name(snapshot.end), "se_input_20240601T0015Z.csv");
+    }
+
+    #[test]
+    fn cadence_must_divide_a_day() {
+        let cfg = |cadence_minutes| StateEstimationExportConfig {
+            out_dir: PathBuf::from("/tmp"),
+            cadence_minutes,
+            lag_minutes: 20,
+        };
+        assert!(validate(&cfg(15)).is_ok());
+        assert!(validate(&cfg(60)).is_ok());
+        assert!(validate(&cfg(7)).is_err());
+        assert!(validate(&cfg(0)).is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use anyhow::anyhow;
+use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::{Date, Duration, OffsetDateTime};
+
+use super::{
+    reaggregate::{self, PlannedStep},
+    registry,
+};
+
+/// Meter intervals per day; meter usage is read every 15 minutes.
+pub const INTERVALS_PER_DAY: i64 = 96;
+
+/// Meter data of one feeder for one day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeederCompleteness {
+    pub feeder_id: String,
+    /// Meters mapped to the feeder at some point of the day.
+    pub mapped_meters: i64,
+    /// Intervals of the day covered by those meters' mappings, so a meter
+    /// exchanged mid-day only counts for the part of the day it was mapped.
+    pub expected_intervals: i64,
+    /// Distinct `(meter_id, ts)` rows of those meters.
+    pub reported_intervals: i64,
+}
+
+impl FeederCompleteness {
+    /// Share of the day's meter intervals that arrived, at most 1.
+    pub fn completeness(&self) -> f64 {
+        if self.expected_intervals == 0 {
+            return 0.0;
+        }
+        (self.reported_intervals as f64 / self.expected_intervals as f64).min(1.0)
+    }
+}
+
+/// One row of `meter_feeder_map` overlapping a day.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FeederMapping {
+    pub feeder_id: String,
+    pub meter_id: String,
+    pub from_ts: OffsetDateTime,
+    pub to_ts: OffsetDateTime,
+}
+
+impl FeederMapping {
+    /// Intervals of the day starting at `day_start` this mapping expects:
+    /// those whose `ts` falls in `[from_ts, to_ts)`, as reported rows are
+    /// matched to mappings.
+    pub fn expected_intervals(&self, day_start: OffsetDateTime) -> i64 {
+        let interval = Duration::DAY / INTERVALS_PER_DAY as u32;
+        // Index of the first interval starting at or after `t`.
+        let first_from = |t: OffsetDateTime| {
+            let offset = (t - day_start).clamp(Duration::ZERO, Duration::DAY);
+            (offset.whole_nanoseconds() as u128).div_ceil(interval.whole_nanoseconds() as u128) as i64
+        };
+        first_from(self.to_ts) - first_from(self.from_ts)
+    }
+}
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const MAPPINGS_SQL: &str = r#"
+SELECT feeder_id, meter_id, from_ts, to_ts
+FROM meter_feeder_map
+WHERE from_ts < $2 AND to_ts > $1
+"#;
+
+/// Bind parameters: `$1` day start, `$2` day end.
+const REPORTED_SQL: &str = r#"
+SELECT mfm.feeder_id AS feeder_id, COUNT(*) AS intervals
+FROM (SELECT DISTINCT meter_id, ts FROM meter_usage WHERE ts >= $1 AND ts < $2) mu
+JOIN meter_feeder_map mfm
+  ON mfm.meter_id = mu.meter_id
+ AND mfm.from_ts <= mu.ts
+ AND mfm.to_ts   >  mu.ts
+GROUP BY mfm.feeder_id
+"#;
+
+/// Completeness of every feeder with a mapping on the day starting at
+/// `day_start`, ordered by feeder.
+pub fn summarize(
+    mappings: &[FeederMapping],
+    reported: &HashMap<String, i64>,
+    day_start: OffsetDateTime,
+) -> Vec<FeederCompleteness> {
+    let mut feeders: BTreeMap<&str, (HashSet<&str>, i64)> = BTreeMap::new();
+    for m in mappings {
+        let (meters, expected) = feeders.entry(&m.feeder_id).or_default();
+        meters.insert(&m.meter_id);
+        *expected += m.expected_intervals(day_start);
+    }
+
+    feeders
+        .into_iter()
+        .map(|(feeder_id, (meters, expected_intervals))| FeederCompleteness {
+            feeder_id: feeder_id.to_string(),
+            mapped_meters: meters.len() as i64,
+            expected_intervals,
+            reported_intervals: reported.get(feeder_id).copied().unwrap_or(0),
+        })
+        .collect()
+}
+
+/// Meter completeness of every feeder for `day` (UTC).
+pub async fn feeder_completeness(pool: &PgPool, day: Date) -> Result<Vec<FeederCompleteness>, sqlx::Error> {
+    let start = day.midnight().assume_utc();
+    let end = start + Duration::days(1);
+    let mappings = sqlx::query_as::<_, FeederMapping>(MAPPINGS_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+    let reported: HashMap<String, i64> = sqlx::query_as::<_, (String, i64)>(REPORTED_SQL)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+    Ok(summarize(&mappings, &reported, start))
+}
+
+/// Write one day's completeness to `feeder_completeness`.
+///
+/// The table deduplicates on `(day, feeder_id)`, so later runs replace the rows.
+pub async fn store_completeness(pool: &PgPool, day: Date, rows: &[FeederCompleteness]) -> Result<u64, sqlx::Error> {
+    if rows.is_empty() {
+        return Ok(0);
+    }
+
+    let day_ts: OffsetDateTime = day.midnight().assume_utc();
+    let mut builder = QueryBuilder::<Postgres>::new(
+        "INSERT INTO feeder_completeness (day, feeder_id, mapped_meters, expected_intervals, reported_intervals, \
+         completeness) ",
+    );
+    builder.push_values(rows, |mut b, r| {
+        b.push_bind(day_ts)
+            .push_bind(&r.feeder_id)
+            .push_bind(r.mapped_meters)
+            .push_bind(r.expected_intervals)
+            .push_bind(r.reported_intervals)
+            .push_bind(r.completeness());
+    });
+
+    let res = builder.build().execute(pool).await?;
+    Ok(res.rows_affected())
+}
+
+/// Lowest feeder completeness of a day, if it reaches `min_completeness`.
+///
+/// A day without mapped feeders never fires.
+pub fn ready(rows: &[FeederCompleteness], min_completeness: f64) -> Option<f64> {
+    let lowest = rows.iter().map(FeederCompleteness::completeness).reduce(f64::min)?;
+    (lowest >= min_completeness).then_some(lowest)
+}
+
+/// Steps recomputing `table` for `day`, followed by the tables built on it.
+pub fn plan(table: &str, day: Date, baseline_days: u32) -> anyhow::Result<Vec<PlannedStep>> {
+    let table = registry::derived_tables()
+        .into_iter()
+        .find(|t| t.name == table)
+        .ok_or_else(|| anyhow!("'{table}' is not a registered derived table"))?;
+    let start = day.midnight().assume_utc();
+    let (from, to) = table.stale_range(start, start + Duration::days(1), baseline_days);
+
+    let mut steps = vec![PlannedStep { table, from, to }];
+    steps.extend(reaggregate::plan_changes(&[table.name], from, to, baseline_days)?);
+    Ok(steps)
+}
+
+/// Days from `from` on for which `trigger` already fired.
+pub async fn fired_days(pool: &PgPool, trigger: &str, from: Date) -> Result<HashSet<Date>, sqlx::Error> {
+    let days: Vec<(OffsetDateTime,)> =
+        sqlx::query_as("SELECT DISTINCT day FROM job_triggers WHERE trigger_name = $1 AND day >= $2")
+            .bind(trigger)
+            .bind(from.midnight().assume_utc())
+            .fetch_all(pool)
+            .await?;
+    Ok(days.into_iter().map(|(d,)| d.date()).collect())
+}
+
+/// Record that `trigger` fired for `day` after running `steps` recompute steps.
+pub async fn record_fired(
+    pool: &PgPool,
+    trigger: &str,
+    day: Date,
+    min_completeness: f64,
+    steps: usize,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO job_triggers (fired_at, trigger_name, day, min_completeness, steps) VALUES ($1, $2, $3, $4, $5)",
+    )
+    .bind(OffsetDateTime::now_utc())
+    .bind(trigger)
+    .bind(day.midnight().assume_utc())
+    .bind(min_completeness)
+    .bind(steps as i32)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::{date, datetime};
+
+    fn feeder(feeder_id: &str, mapped_meters: i64, reported_intervals: i64) -> FeederCompleteness {
+        FeederCompleteness {
+            feeder_id: feeder_id.to_string(),
+            mapped_meters,
+            expected_intervals: mapped_meters * INTERVALS_PER_DAY,
+            reported_intervals,
+        }
+    }
+
+    #[test]
+    fn fires_once_every_feeder_is_complete_enough() {
+        let day = [feeder("f-1", 10, 960), feeder("f-2", 4, 370)];
+        assert!((day[1].completeness() - 370.0 / 384.0).abs() < 1e-9);
+        assert_eq!(ready(&day, 0.95), Some(370.0 / 384.0));
+        assert_eq!(ready(&day, 0.97), None);
+
+        // Duplicated intervals don't push a feeder past 100%.
+        assert_eq!(feeder("f-3", 1, 200).completeness(), 1.0);
+        assert_eq!(ready(&[], 0.95), None);
+    }
+
+    #[test]
+    fn meters_exchanged_mid_day_count_for_their_mapped_intervals_only() {
+        let day_start = datetime!(2024-03-10 00:00 UTC);
+        let mapping = |meter_id: &str, from_ts: OffsetDateTime, to_ts: OffsetDateTime| FeederMapping {
+            feeder_id: "f-1".to_string(),
+            meter_id: meter_id.to_string(),
+            from_ts,
+            to_ts,
+        };
+
+        // Ten meters all along; an eleventh replaced by a twelfth at 12:07.
+        let (installed, removed) = (datetime!(2024-01-01 00:00 UTC), datetime!(2025-01-01 00:00 UTC));
+        let exchange = datetime!(2024-03-10 12:07 UTC);
+        let mut mappings: Vec<_> = (0..10)
+            .map(|i| mapping(&format!("m-{i}"), installed, removed))
+            .collect();
+        mappings.push(mapping("old", installed, exchange));
+        mappings.push(mapping("new", exchange, removed));
+
+        // 00:00..=12:00 for the old meter, 12:15..=23:45 for the new one.
+        assert_eq!(mappings[10].expected_intervals(day_start), 49);
+        assert_eq!(mappings[11].expected_intervals(day_start), 47);
+
+        let reported = HashMap::from([("f-1".to_string(), 10 * INTERVALS_PER_DAY + 49 + 47)]);
+        let rows = summarize(&mappings, &reported, day_start);
+        assert_eq!(
+            rows,
+            [FeederCompleteness {
+                feeder_id: "f-1".to_string(),
+                mapped_meters: 12,
+                expected_intervals: 11 * INTERVALS_PER_DAY,
+                reported_intervals: 11 * INTERVALS_PER_DAY,
+            }]
+        );
+        assert_eq!(ready(&rows, 0.99), Some(1.0));
+
+        // A meter mapped only the day before expects nothing, and a feeder without reports is at 0.
+        let before = mapping("gone", installed, day_start);
+        assert_eq!(before.expected_intervals(day_start), 0);
+        let rows = summarize(&mappings[..1], &HashMap::new(), day_start);
+        assert_eq!(rows[0].expected_intervals, INTERVALS_PER_DAY);
+        assert_eq!(rows[0].completeness(), 0.0);
+    }
+
+    #[test]
+    fn plans_the_table_and_the_tables_built_on_it() {
+        let steps: Vec<String> = plan("feeder_energy_balance", date!(2024 - 03 - 10), 10)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            steps,
+            [
+                "feeder_energy_balance [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+                "feeder_loss_uncertainty [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+                "forecast_accuracy [2024-03-01T00:00:00Z, 2024-04-01T00:00:00Z)",
+            ]
+        );
+        assert!(plan("meter_usage", date!(2024 - 03 - 10), 10).is_err());
+    }
+}
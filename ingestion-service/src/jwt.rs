@@ -0,0 +1,358 @@
+//! JWT bearer tokens from the corporate OIDC identity provider (`jwt` feature).
+//!
+//! Tokens are verified against the provider's JWKS, fetched at startup and
+//! refetched every `jwks_refresh_secs` (sooner when a token names an unknown
+//! `kid`), then checked for issuer, audience, expiry and `required_claims`.
+//! Roles come from `roles_claim` through `role_mapping`, the tenant from
+//! `tenant_claim`. A valid token then passes the same role checks as an API
+//! key (see [`crate::auth`]); tenant-scoped tokens only keep the `ingest` role.
+
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Empty};
+use hyper::{header, Request, StatusCode};
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde_json::Value;
+use time::OffsetDateTime;
+use tokio::sync::Notify;
+
+use crate::{
+    auth::{ApiKey, TokenValidator},
+    config::{JwtConfig, Role},
+};
+
+/// Fewest time between two JWKS fetches, however many unknown `kid`s arrive.
+const MIN_REFETCH_INTERVAL: Duration = Duration::from_secs(30);
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+type HttpsClient = Client<HttpsConnector<HttpConnector>, Empty<Bytes>>;
+
+pub struct JwtValidator {
+    cfg: JwtConfig,
+    algorithms: Vec<Algorithm>,
+    /// Verification keys by `kid`.
+    keys: RwLock<HashMap<String, DecodingKey>>,
+    /// Wakes the refresher early, when a token names an unknown `kid`.
+    refetch: Notify,
+}
+
+/// The value at a dotted claim path, e.g. `realm_access.roles`.
+fn claim<'a>(claims: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.').try_fold(claims, |v, key| v.get(key))
+}
+
+/// Strings of a claim: an array's string elements, or a space-separated string's words.
+fn claim_values(value: Option<&Value>) -> Vec<&str> {
+    match value {
+        Some(Value::String(s)) => s.split_whitespace().collect(),
+        Some(Value::Array(items)) => items.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    }
+}
+
+async fn fetch_jwks(client: &HttpsClient, url: &str) -> Result<JwkSet, String> {
+    let req = Request::get(url)
+        .header(header::ACCEPT, "application/json")
+        .body(Empty::new())
+        .map_err(|e| e.to_string())?;
+    let resp = tokio::time::timeout(FETCH_TIMEOUT, client.request(req))
+        .await
+        .map_err(|_| "JWKS request timed out".to_string())?
+        .map_err(|e| format!("JWKS request failed: {e}"))?;
+    let status = resp.status();
+    let body = resp
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| format!("JWKS response: {e}"))?
+        .to_bytes();
+    if status != StatusCode::OK {
+        return Err(format!("JWKS endpoint returned {status}"));
+    }
+    serde_json::from_slice(&body).map_err(|e| format!("invalid JWKS: {e}"))
+}
+
+impl JwtValidator {
+    fn new(cfg: &JwtConfig) -> anyhow::Result<Self> {
+        let algorithms = cfg
+            .algorithms
+            .iter()
+            .map(|a| Algorithm::from_str(a).map_err(|_| anyhow::anyhow!("unsupported jwt algorithm {a:?}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        anyhow::ensure!(!algorithms.is_empty(), "[jwt] algorithms may not be empty");
+        anyhow::ensure!(!cfg.audiences.is_empty(), "[jwt] audiences may not be empty");
+        Ok(Self {
+            cfg: cfg.clone(),
+            algorithms,
+            keys: RwLock::new(HashMap::new()),
+            refetch: Notify::new(),
+        })
+    }
+
+    /// Fetch the JWKS (failing if the provider is unreachable) and keep it fresh
+    /// in the background.
+    pub async fn start(cfg: &JwtConfig) -> anyhow::Result<Arc<Self>> {
+        let validator = Arc::new(Self::new(cfg)?);
+        let connector = HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_only()
+            .enable_http1()
+            .build();
+        let client: HttpsClient = Client::builder(TokioExecutor::new()).build(connector);
+
+        let jwks = fetch_jwks(&client, &cfg.jwks_url)
+            .await
+            .map_err(|e| anyhow::anyhow!("failed to load JWKS from {}: {e}", cfg.jwks_url))?;
+        let loaded = validator.set_keys(&jwks);
+        anyhow::ensure!(loaded > 0, "JWKS at {} has no usable keys", cfg.jwks_url);
+        tracing::info!(keys = loaded, url = %cfg.jwks_url, "JWKS loaded");
+
+        let refresher = validator.clone();
+        tokio::spawn(async move {
+            let every = Duration::from_secs(refresher.cfg.jwks_refresh_secs.max(1));
+            let mut last = Instant::now();
+            loop {
+                tokio::select! {
+                    _ = tokio::time::sleep(every) => {}
+                    _ = refresher.refetch.notified() => {}
+                }
+                tokio::time::sleep(MIN_REFETCH_INTERVAL.saturating_sub(last.elapsed())).await;
+                last = Instant::now();
+                match fetch_jwks(&client, &refresher.cfg.jwks_url).await {
+                    Ok(jwks) => {
+                        let loaded = refresher.set_keys(&jwks);
+                        metrics::counter!("jwt_jwks_refreshes_total").increment(1);
+                        tracing::debug!(keys = loaded, "JWKS refreshed");
+                    }
+                    // Keep the keys we have; tokens signed with them stay valid.
+                    Err(e) => {
+                        metrics::counter!("jwt_jwks_refresh_errors_total").increment(1);
+                        tracing::warn!(error = %e, "JWKS refresh failed");
+                    }
+                }
+            }
+        });
+        Ok(validator)
+    }
+
+    /// Replace the verification keys; returns how many are usable.
+    fn set_keys(&self, jwks: &JwkSet) -> usize {
+        let keys: HashMap<String, DecodingKey> = jwks
+            .keys
+            .iter()
+            .filter_map(|jwk| {
+                let key = DecodingKey::from_jwk(jwk)
+                    .inspect_err(|e| tracing::warn!(kid = ?jwk.common.key_id, error = %e, "skipping JWK"))
+                    .ok()?;
+                Some((jwk.common.key_id.clone().unwrap_or_default(), key))
+            })
+            .collect();
+        let loaded = keys.len();
+        *self.keys.write().unwrap_or_else(|e| e.into_inner()) = keys;
+        loaded
+    }
+
+    /// Verify `token` and map its claims to a credential.
+    fn decode(&self, token: &str) -> Result<ApiKey, String> {
+        let header = decode_header(token).map_err(|e| e.to_string())?;
+        if !self.algorithms.contains(&header.alg) {
+            return Err(format!("algorithm {:?} is not accepted", header.alg));
+        }
+        let kid = header.kid.unwrap_or_default();
+
+        let mut validation = Validation::new(header.alg);
+        validation.set_issuer(&[&self.cfg.issuer]);
+        validation.set_audience(&self.cfg.audiences);
+        validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+        validation.leeway = self.cfg.leeway_secs;
+        validation.validate_nbf = true;
+
+        let claims = {
+            let keys = self.keys.read().unwrap_or_else(|e| e.into_inner());
+            let Some(key) = keys.get(&kid) else {
+                self.refetch.notify_one();
+                return Err(format!("unknown key id {kid:?}"));
+            };
+            decode::<Value>(token, key, &validation).map_err(|e| e.to_string())?.claims
+        };
+        self.credential(&claims)
+    }
+
+    /// The credential verified `claims` stand for.
+    fn credential(&self, claims: &Value) -> Result<ApiKey, String> {
+        for (name, expected) in &self.cfg.required_claims {
+            let matches = match claim(claims, name) {
+                Some(Value::String(s)) => s == expected,
+                Some(Value::Array(items)) => items.iter().any(|v| v.as_str() == Some(expected)),
+                _ => false,
+            };
+            if !matches {
+                return Err(format!("claim {name:?} is not {expected:?}"));
+            }
+        }
+
+        let subject = claim(claims, &self.cfg.subject_claim)
+            .and_then(Value::as_str)
+            .ok_or_else(|| format!("missing {:?} claim", self.cfg.subject_claim))?;
+        let tenant = match &self.cfg.tenant_claim {
+            Some(path) => claim(claims, path).and_then(Value::as_str).map(str::to_string),
+            None => None,
+        };
+
+        let mut roles = Vec::new();
+        for value in claim_values(claim(claims, &self.cfg.roles_claim)) {
+            let role = if self.cfg.role_mapping.is_empty() {
+                serde_json::from_value(Value::String(value.to_string())).ok()
+            } else {
+                self.cfg.role_mapping.get(value).copied()
+            };
+            if let Some(role) = role.filter(|r| !roles.contains(r)) {
+                roles.push(role);
+            }
+        }
+        if tenant.is_some() {
+            roles.retain(|r| *r == Role::Ingest);
+        }
+
+        let expires_at = claims
+            .get("exp")
+            .and_then(Value::as_i64)
+            .and_then(|exp| OffsetDateTime::from_unix_timestamp(exp).ok());
+        Ok(ApiKey::new(format!("jwt:{subject}"), roles, tenant, expires_at))
+    }
+}
+
+impl TokenValidator for JwtValidator {
+    fn validate(&self, token: &str) -> Option<Arc<ApiKey>> {
+        // Not a JWT (e.g. a mistyped API key): nothing to report.
+        if token.split('.').count() != 3 {
+            return None;
+        }
+        match self.decode(token) {
+            Ok(key) => Some(Arc::new(key)),
+            Err(e) => {
+                metrics::counter!("jwt_rejected_total").increment(1);
+                tracing::debug!(error = %e, "JWT rejected");
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+    use serde_json::json;
+
+    const SECRET: &[u8] = b"test-signing-secret";
+
+    fn validator(extra: &str) -> JwtValidator {
+        let cfg: JwtConfig = toml::from_str(&format!(
+            r#"
+            issuer = "https://idp.example.com"
+            audiences = ["utility-analytics"]
+            jwks_url = "https://idp.example.com/jwks"
+            algorithms = ["HS256"]
+            roles_claim = "realm_access.roles"
+            {extra}
+            "#
+        ))
+        .unwrap();
+        let validator = JwtValidator::new(&cfg).unwrap();
+        let jwks: JwkSet = serde_json::from_value(json!({
+            "keys": [{"kty": "oct", "kid": "k1", "alg": "HS256", "k": "dGVzdC1zaWduaW5nLXNlY3JldA"}]
+        }))
+        .unwrap();
+        assert_eq!(validator.set_keys(&jwks), 1);
+        validator
+    }
+
+    fn token(kid: &str, claims: Value) -> String {
+        let mut header = Header::new(Algorithm::HS256);
+        header.kid = Some(kid.to_string());
+        encode(&header, &claims, &EncodingKey::from_secret(SECRET)).unwrap()
+    }
+
+    fn claims(aud: &str, roles: Value) -> Value {
+        let exp = OffsetDateTime::now_utc().unix_timestamp() + 600;
+        json!({
+            "iss": "https://idp.example.com",
+            "aud": aud,
+            "sub": "alice",
+            "exp": exp,
+            "realm_access": {"roles": roles},
+            "org": "vendor-a",
+        })
+    }
+
+    #[test]
+    fn rejects_jwks_urls_without_tls() {
+        let err = toml::from_str::<JwtConfig>(
+            r#"
+            issuer = "https://idp.example.com"
+            audiences = ["utility-analytics"]
+            jwks_url = "http://idp.example.com/jwks"
+            "#,
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("is not an https URL"), "{err}");
+    }
+
+    #[test]
+    fn verifies_signature_issuer_and_audience() {
+        let validator = validator("");
+
+        let key = validator
+            .validate(&token("k1", claims("utility-analytics", json!(["read", "other"]))))
+            .unwrap();
+        assert_eq!(key.name, "jwt:alice");
+        assert!(key.has_role(Role::Read) && !key.has_role(Role::Ingest));
+        assert!(key.expires_at.is_some());
+
+        assert!(validator.validate(&token("k1", claims("someone-else", json!(["read"])))).is_none());
+        assert!(validator.validate(&token("k2", claims("utility-analytics", json!(["read"])))).is_none());
+        let forged = encode(
+            &Header::new(Algorithm::HS256),
+            &claims("utility-analytics", json!(["admin"])),
+            &EncodingKey::from_secret(b"other"),
+        )
+        .unwrap();
+        assert!(validator.validate(&forged).is_none());
+        assert!(validator.validate("not-a-jwt").is_none());
+    }
+
+    #[test]
+    fn maps_claims_to_roles_and_tenant() {
+        let validator = validator(
+            r#"
+            tenant_claim = "org"
+            [role_mapping]
+            "ami-writers" = "ingest"
+            "ops" = "admin"
+            [required_claims]
+            "realm_access.roles" = "ami-writers"
+            "#,
+        );
+
+        // The tenant-scoped token keeps only its ingest role.
+        let key = validator
+            .credential(&claims("utility-analytics", json!(["ami-writers", "ops"])))
+            .unwrap();
+        assert_eq!(key.tenant.as_deref(), Some("vendor-a"));
+        assert!(key.has_role(Role::Ingest) && !key.has_role(Role::Read));
+
+        assert!(validator.credential(&claims("utility-analytics", json!(["ops"]))).is_err());
+    }
+}
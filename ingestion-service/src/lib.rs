@@ -5,5 +5,18 @@ pub mod sinks;
 pub mod transform;
 pub mod observability;
 pub mod metrics_server;
+pub mod tls;
+pub mod auth;
+pub mod api_key_store;
+pub mod admin_api;
+#[cfg(feature = "jwt")]
+pub mod jwt;
+pub mod usage_api;
+#[cfg(feature = "graphql")]
+pub mod graphql_api;
+#[cfg(feature = "arrow")]
+pub mod arrow_export;
+pub mod jobs;
+pub mod parquet;
 
 pub use pipeline::{Pipeline, Envelope};
@@ -1,20 +1,317 @@
 use anyhow::Result;
 use ingestion_service::{
-    config::{AppConfig, SinkKind},
+    admin_api,
+    api_key_store::ApiKeyStore,
+    auth::{self, ApiKeys},
+    config::{
+        AppConfig, ClickHouseConfig, EdgeConfig, IlpProtocolSetting, MeterEventsConfig, PipelineConfig, ReferenceJoinConfig,
+        DuplicateStatsConfig, EventIdStrategy, RejectLogConfig, SinkKind,
+        StreamAlertsConfig, UnitRuntimeConfig, WindowAggregatesConfig, WindowKey,
+    },
+    jobs::{
+        ndjson_shipper::NdjsonShipper,
+        rollups::{detect_server_version, ServerVersion},
+    },
     metrics_server,
     observability,
-    pipeline::{Pipeline, Sink},
-    sinks::{QuestDbGenerationSink, QuestDbIlpGenerationSink, QuestDbIlpMeterUsageSink, QuestDbSink},
-    sources::{http_generation_output::HttpGenerationOutputSource, http_json::HttpJsonSource},
-    transform,
+    pipeline::{Blocking, Envelope, LatencySampler, Pipeline, PipelineError, Sink, SinkLag, Source, Transform, WithConcurrency},
+    sinks::{
+        questdb_ilp::{IlpProtocolVersion, QuestDbIlpSink},
+        IlpConnectionPool,
+        QuestDbGenerationSink, QuestDbIlpCustomerSink, QuestDbIlpDrEventSink,
+        QuestDbIlpGenerationSink, QuestDbIlpMeterExchangeSink, QuestDbIlpMeterSink, QuestDbIlpMeterUsageSink,
+        ClickHouseStore, FileNdjsonStore, QuestDbSink, StoreSink, TimescaleStore,
+    },
+    sources::{
+        http_generation_output::HttpGenerationOutputSource, http_json::HttpJsonSource, HttpReferenceSource,
+        SampledSource, SyslogUdpSource,
+    },
+    transform::{
+        self,
+        duplicates::{DuplicateCounts, DuplicateLog, DuplicateLogSource, MeterUsageDuplicates},
+        reference_join::{self, MeterReference, ReferenceJoin, ReferenceTable, ReferenceUpdates},
+        rejects::{IngestReject, RecordMeterUsageRejects, RejectLog, RejectLogSource},
+        threshold_alerts::{self, StreamAlert, StreamAlertSource, StreamAlerts, ThresholdAlerts},
+        unit_state::{UnitStateTracker, UnitTransition, UnitTransitionSource},
+        window_aggregate::{self, FeederMap, WindowAggregate, WindowAggregateSource, WindowAggregator},
+    },
+    usage_api,
 };
-use rust_client::domain::{GenerationOutput, MeterUsage};
+#[cfg(feature = "kafka")]
+use ingestion_service::sources::KafkaSource;
+#[cfg(feature = "mqtt")]
+use ingestion_service::sources::MqttSource;
+#[cfg(feature = "redis")]
+use ingestion_service::sources::RedisStreamSource;
+use ingestion_service::sources::{Dnp3Source, Iec104Source, ModbusSource};
+#[cfg(feature = "grpc")]
+use ingestion_service::sources::{grpc::GrpcRecord, GrpcIngestSource};
+use rust_client::domain::{Customer, DrEvent, GenerationOutput, Meter, MeterEvent, MeterExchange, MeterUsage};
 use sqlx::postgres::PgPoolOptions;
-use std::{net::SocketAddr, sync::Arc, time::Duration};
+use std::{net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
+use tokio::sync::mpsc;
+
+type RecordStream<T> = Pin<Box<dyn futures::Stream<Item = Result<Envelope<T>, PipelineError>> + Send>>;
+
+/// Fails for a `[<pipeline>.kafka]` section in a build without the `kafka` feature.
+#[cfg(not(feature = "kafka"))]
+fn kafka_unavailable(cfg: &PipelineConfig) -> anyhow::Error {
+    anyhow::anyhow!("[{}.kafka] requires building with `--features kafka`", cfg.name)
+}
+
+/// Fails for a `[<pipeline>.mqtt]` section in a build without the `mqtt` feature.
+#[cfg(not(feature = "mqtt"))]
+fn mqtt_unavailable(cfg: &PipelineConfig) -> anyhow::Error {
+    anyhow::anyhow!("[{}.mqtt] requires building with `--features mqtt`", cfg.name)
+}
+
+/// Fails for a `[<pipeline>.redis]` section in a build without the `redis` feature.
+#[cfg(not(feature = "redis"))]
+fn redis_unavailable(cfg: &PipelineConfig) -> anyhow::Error {
+    anyhow::anyhow!("[{}.redis] requires building with `--features redis`", cfg.name)
+}
+
+fn conflicting_sources(cfg: &PipelineConfig) -> anyhow::Error {
+    anyhow::anyhow!(
+        "[{name}.kafka], [{name}.mqtt] and [{name}.redis] are mutually exclusive",
+        name = cfg.name
+    )
+}
+
+/// The gRPC endpoint of `[<pipeline>.grpc]`, if set; it runs alongside the pipeline's source.
+#[cfg(feature = "grpc")]
+async fn grpc_source<T: GrpcRecord>(cfg: &PipelineConfig) -> Result<Option<Box<dyn Source<T>>>> {
+    match &cfg.grpc {
+        Some(grpc) => Ok(Some(Box::new(GrpcIngestSource::<T>::from_config(grpc).await?))),
+        None => Ok(None),
+    }
+}
+
+/// Fails for a `[<pipeline>.grpc]` section in a build without the `grpc` feature.
+#[cfg(not(feature = "grpc"))]
+async fn grpc_source<T>(cfg: &PipelineConfig) -> Result<Option<Box<dyn Source<T>>>> {
+    match &cfg.grpc {
+        Some(_) => Err(anyhow::anyhow!("[{}.grpc] requires building with `--features grpc`", cfg.name)),
+        None => Ok(None),
+    }
+}
+
+/// A pipeline's source merged with its gRPC endpoint, if any.
+struct WithGrpc<S, T> {
+    source: S,
+    grpc: Option<Box<dyn Source<T>>>,
+}
+
+#[async_trait::async_trait]
+impl<S, T> Source<T> for WithGrpc<S, T>
+where
+    S: Source<T>,
+    T: Send + 'static,
+{
+    async fn stream(&self) -> RecordStream<T> {
+        let stream = self.source.stream().await;
+        match &self.grpc {
+            Some(grpc) => Box::pin(futures::stream::select(stream, grpc.stream().await)),
+            None => stream,
+        }
+    }
+}
+
+/// Sampled share and target table from `[<pipeline>.sample]`, if set.
+fn sample_settings(cfg: &PipelineConfig) -> Result<(Option<f64>, Option<&str>)> {
+    let Some(sample) = &cfg.sample else {
+        return Ok((None, None));
+    };
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("[{}.sample] only supports sink.kind = \"ilp\"", cfg.name);
+    }
+    if !(0.0..=100.0).contains(&sample.percent) {
+        anyhow::bail!("[{}.sample] percent must be between 0 and 100", cfg.name);
+    }
+    if sample.table.is_empty() || !sample.table.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        anyhow::bail!("[{}.sample] table must be a plain table name", cfg.name);
+    }
+    tracing::warn!(
+        pipeline = %cfg.name,
+        percent = sample.percent,
+        table = %sample.table,
+        "sampling mode: only a sample of the traffic is ingested, into another table"
+    );
+    Ok((Some(sample.percent), Some(&sample.table)))
+}
+
+enum MeterUsageSource {
+    Http(HttpJsonSource),
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaSource<MeterUsage>),
+    #[cfg(feature = "mqtt")]
+    Mqtt(Box<MqttSource<MeterUsage>>),
+    #[cfg(feature = "redis")]
+    Redis(Box<RedisStreamSource<MeterUsage>>),
+}
+
+impl MeterUsageSource {
+    async fn from_config(cfg: &PipelineConfig) -> Result<Self> {
+        if cfg.iec104.is_some() || cfg.dnp3.is_some() || cfg.modbus.is_some() {
+            anyhow::bail!(
+                "[{name}.iec104], [{name}.dnp3] and [{name}.modbus] are only supported for generation_output",
+                name = cfg.name
+            );
+        }
+        if [cfg.kafka.is_some(), cfg.mqtt.is_some(), cfg.redis.is_some()].iter().filter(|s| **s).count() > 1 {
+            return Err(conflicting_sources(cfg));
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &cfg.redis {
+            return Ok(Self::Redis(Box::new(RedisStreamSource::from_config(redis)?)));
+        }
+        #[cfg(not(feature = "redis"))]
+        if cfg.redis.is_some() {
+            return Err(redis_unavailable(cfg));
+        }
+        match (&cfg.kafka, &cfg.mqtt) {
+            (Some(_), Some(_)) => Err(conflicting_sources(cfg)),
+            #[cfg(feature = "kafka")]
+            (Some(kafka), None) => Ok(Self::Kafka(KafkaSource::from_config(kafka, cfg.avro.as_ref())?)),
+            #[cfg(not(feature = "kafka"))]
+            (Some(_), None) => Err(kafka_unavailable(cfg)),
+            #[cfg(feature = "mqtt")]
+            (None, Some(mqtt)) => Ok(Self::Mqtt(Box::new(MqttSource::from_config(mqtt)?))),
+            #[cfg(not(feature = "mqtt"))]
+            (None, Some(_)) => Err(mqtt_unavailable(cfg)),
+            (None, None) => Ok(Self::Http(HttpJsonSource::from_config(&cfg.source).await?)),
+        }
+    }
+
+    /// Lag handle for the sink; only the HTTP source sheds load on it.
+    fn sink_lag(&self) -> SinkLag {
+        match self {
+            Self::Http(s) => s.sink_lag(),
+            #[cfg(feature = "kafka")]
+            Self::Kafka(_) => SinkLag::new(),
+            #[cfg(feature = "mqtt")]
+            Self::Mqtt(_) => SinkLag::new(),
+            #[cfg(feature = "redis")]
+            Self::Redis(_) => SinkLag::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for MeterUsageSource {
+    async fn stream(&self) -> RecordStream<MeterUsage> {
+        match self {
+            Self::Http(s) => s.stream().await,
+            #[cfg(feature = "kafka")]
+            Self::Kafka(s) => s.stream().await,
+            #[cfg(feature = "mqtt")]
+            Self::Mqtt(s) => s.stream().await,
+            #[cfg(feature = "redis")]
+            Self::Redis(s) => s.stream().await,
+        }
+    }
+}
+
+enum GenerationSource {
+    Http(HttpGenerationOutputSource),
+    #[cfg(feature = "kafka")]
+    Kafka(KafkaSource<GenerationOutput>),
+    #[cfg(feature = "mqtt")]
+    Mqtt(Box<MqttSource<GenerationOutput>>),
+    #[cfg(feature = "redis")]
+    Redis(Box<RedisStreamSource<GenerationOutput>>),
+    Iec104(Iec104Source),
+    Dnp3(Dnp3Source),
+    Modbus(ModbusSource),
+}
+
+impl GenerationSource {
+    async fn from_config(cfg: &PipelineConfig) -> Result<Self> {
+        let sources = [
+            cfg.kafka.is_some(),
+            cfg.mqtt.is_some(),
+            cfg.iec104.is_some(),
+            cfg.dnp3.is_some(),
+            cfg.modbus.is_some(),
+            cfg.redis.is_some(),
+        ];
+        if sources.iter().filter(|s| **s).count() > 1 {
+            anyhow::bail!(
+                "[{name}.kafka], [{name}.mqtt], [{name}.redis], [{name}.iec104], [{name}.dnp3] and [{name}.modbus] are mutually exclusive",
+                name = cfg.name
+            );
+        }
+        if let Some(iec104) = &cfg.iec104 {
+            return Ok(Self::Iec104(Iec104Source::from_config(iec104)?));
+        }
+        if let Some(dnp3) = &cfg.dnp3 {
+            return Ok(Self::Dnp3(Dnp3Source::from_config(dnp3)?));
+        }
+        if let Some(modbus) = &cfg.modbus {
+            return Ok(Self::Modbus(ModbusSource::from_config(modbus)?));
+        }
+        #[cfg(feature = "redis")]
+        if let Some(redis) = &cfg.redis {
+            return Ok(Self::Redis(Box::new(RedisStreamSource::from_config(redis)?)));
+        }
+        #[cfg(not(feature = "redis"))]
+        if cfg.redis.is_some() {
+            return Err(redis_unavailable(cfg));
+        }
+        match (&cfg.kafka, &cfg.mqtt) {
+            (Some(_), Some(_)) => Err(conflicting_sources(cfg)),
+            #[cfg(feature = "kafka")]
+            (Some(kafka), None) => Ok(Self::Kafka(KafkaSource::from_config(kafka, cfg.avro.as_ref())?)),
+            #[cfg(not(feature = "kafka"))]
+            (Some(_), None) => Err(kafka_unavailable(cfg)),
+            #[cfg(feature = "mqtt")]
+            (None, Some(mqtt)) => Ok(Self::Mqtt(Box::new(MqttSource::from_config(mqtt)?))),
+            #[cfg(not(feature = "mqtt"))]
+            (None, Some(_)) => Err(mqtt_unavailable(cfg)),
+            (None, None) => Ok(Self::Http(HttpGenerationOutputSource::from_config(&cfg.source).await?)),
+        }
+    }
+
+    fn sink_lag(&self) -> SinkLag {
+        match self {
+            Self::Http(s) => s.sink_lag(),
+            #[cfg(feature = "kafka")]
+            Self::Kafka(_) => SinkLag::new(),
+            #[cfg(feature = "mqtt")]
+            Self::Mqtt(_) => SinkLag::new(),
+            #[cfg(feature = "redis")]
+            Self::Redis(_) => SinkLag::new(),
+            Self::Iec104(_) => SinkLag::new(),
+            Self::Dnp3(_) => SinkLag::new(),
+            Self::Modbus(_) => SinkLag::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<GenerationOutput> for GenerationSource {
+    async fn stream(&self) -> RecordStream<GenerationOutput> {
+        match self {
+            Self::Http(s) => s.stream().await,
+            #[cfg(feature = "kafka")]
+            Self::Kafka(s) => s.stream().await,
+            #[cfg(feature = "mqtt")]
+            Self::Mqtt(s) => s.stream().await,
+            #[cfg(feature = "redis")]
+            Self::Redis(s) => s.stream().await,
+            Self::Iec104(s) => s.stream().await,
+            Self::Dnp3(s) => s.stream().await,
+            Self::Modbus(s) => s.stream().await,
+        }
+    }
+}
 
 enum MeterUsageSink {
     Ilp(QuestDbIlpMeterUsageSink),
     Pgwire(QuestDbSink),
+    ClickHouse(Box<StoreSink<MeterUsage, ClickHouseStore<MeterUsage>>>),
+    Timescale(StoreSink<MeterUsage, TimescaleStore<MeterUsage>>),
+    File(Box<StoreSink<MeterUsage, FileNdjsonStore<MeterUsage>>>),
 }
 
 #[async_trait::async_trait]
@@ -29,6 +326,9 @@ impl Sink<MeterUsage> for MeterUsageSink {
         match self {
             Self::Ilp(s) => s.run(input).await,
             Self::Pgwire(s) => s.run(input).await,
+            Self::ClickHouse(s) => s.run(input).await,
+            Self::Timescale(s) => s.run(input).await,
+            Self::File(s) => s.run(input).await,
         }
     }
 }
@@ -36,6 +336,9 @@ impl Sink<MeterUsage> for MeterUsageSink {
 enum GenerationSink {
     Ilp(QuestDbIlpGenerationSink),
     Pgwire(QuestDbGenerationSink),
+    ClickHouse(Box<StoreSink<GenerationOutput, ClickHouseStore<GenerationOutput>>>),
+    Timescale(StoreSink<GenerationOutput, TimescaleStore<GenerationOutput>>),
+    File(Box<StoreSink<GenerationOutput, FileNdjsonStore<GenerationOutput>>>),
 }
 
 #[async_trait::async_trait]
@@ -50,22 +353,130 @@ impl Sink<GenerationOutput> for GenerationSink {
         match self {
             Self::Ilp(s) => s.run(input).await,
             Self::Pgwire(s) => s.run(input).await,
+            Self::ClickHouse(s) => s.run(input).await,
+            Self::Timescale(s) => s.run(input).await,
+            Self::File(s) => s.run(input).await,
         }
     }
 }
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn clickhouse_cfg(cfg: &AppConfig) -> Result<&ClickHouseConfig> {
+    cfg.clickhouse
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("sink.kind = \"clickhouse\" requires a [clickhouse] section"))
+}
+
+type Stage<T> = Arc<dyn Transform<T, T> + Send + Sync>;
+
+/// Apply the `[<pipeline>.stages.<name>]` settings to a transform stage.
+fn configured_stage<T: Send + 'static>(cfg: &PipelineConfig, name: &str, transform: Stage<T>) -> Stage<T> {
+    let concurrency = cfg.stage_concurrency(name);
+    let transform: Stage<T> = if cfg.stage_blocking(name) {
+        let max_in_flight = cfg.stages.get(name).map_or(1, |s| s.concurrency);
+        Arc::new(Blocking::new(transform, max_in_flight))
+    } else {
+        transform
+    };
+    Arc::new(WithConcurrency::new(transform, concurrency))
+}
+
+fn main() -> Result<()> {
     observability::init_tracing();
 
     // Load configuration
     let cfg = AppConfig::load()?;
+    auth::install(ApiKeys::from_config(&cfg.api_keys)?);
 
-    // Start metrics server if configured
-    if let Some(metrics_cfg) = &cfg.metrics {
-        metrics_server::init(&metrics_cfg.bind_addr);
+    // Edge agents run on a small runtime.
+    let mut runtime = tokio::runtime::Builder::new_multi_thread();
+    if let Some(edge) = &cfg.edge {
+        runtime.worker_threads(edge.worker_threads.max(1));
     }
+    runtime.enable_all().build()?.block_on(async {
+        // Start metrics server if configured
+        if let Some(metrics_cfg) = &cfg.metrics {
+            metrics_server::init(metrics_cfg)?;
+        }
+
+        #[cfg(feature = "jwt")]
+        if let Some(jwt_cfg) = &cfg.jwt {
+            auth::install_validator(ingestion_service::jwt::JwtValidator::start(jwt_cfg).await?)?;
+        }
+        #[cfg(not(feature = "jwt"))]
+        if cfg.jwt.is_some() {
+            anyhow::bail!("[jwt] requires building with `--features jwt`");
+        }
+
+        // Stored keys (and the admin API managing them) are live before any endpoint serves.
+        if let Some(store_cfg) = &cfg.api_key_store {
+            let store = Arc::new(ApiKeyStore::open(store_cfg, &cfg.api_keys).await?);
+            if let Some(admin_cfg) = &cfg.admin_api {
+                admin_api::serve(admin_cfg, store).await?;
+            }
+        } else if cfg.admin_api.is_some() {
+            anyhow::bail!("[admin_api] requires an [api_key_store] section");
+        }
+
+        match &cfg.edge {
+            Some(edge) => run_edge(&cfg, edge).await,
+            None => run(&cfg).await,
+        }
+    })
+}
+
+/// Edge buffering agent: HTTP sources spool validated records to a local disk
+/// queue, which is forwarded to the central ingestion service when reachable.
+async fn run_edge(cfg: &AppConfig, edge: &EdgeConfig) -> Result<()> {
+    let mu_cfg = &cfg.meter_usage;
+    let gen_cfg = &cfg.generation_output;
+    let spool = edge.file_sink();
+    let linger = Duration::from_millis(edge.max_batch_linger_ms);
+
+    let mu_source = HttpJsonSource::from_config(&edge.source(&mu_cfg.source)).await?;
+    let mu_sink = StoreSink::new(
+        FileNdjsonStore::from_config(&spool),
+        edge.batch_size,
+        mu_cfg.sink.max_retries,
+        Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+        linger,
+    )
+    .with_lag(mu_source.sink_lag())
+    .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every));
+    let mu_pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source: mu_source,
+        transforms: vec![configured_stage(mu_cfg, "validation", Arc::new(transform::MeterUsageValidation))],
+        sink: mu_sink,
+    };
+
+    let gen_source = HttpGenerationOutputSource::from_config(&edge.source(&gen_cfg.source)).await?;
+    let gen_sink = StoreSink::new(
+        FileNdjsonStore::from_config(&spool),
+        edge.batch_size,
+        gen_cfg.sink.max_retries,
+        Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
+        linger,
+    )
+    .with_lag(gen_source.sink_lag())
+    .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every));
+    let gen_pipeline: Pipeline<_, GenerationOutput, _> = Pipeline {
+        source: gen_source,
+        transforms: vec![configured_stage(gen_cfg, "validation", Arc::new(transform::GenerationOutputValidation))],
+        sink: gen_sink,
+    };
+
+    let shipper = NdjsonShipper::new(edge.shipper());
+    let shipper_run = async {
+        shipper.run().await;
+        Ok::<(), PipelineError>(())
+    };
+
+    tracing::info!(spool_dir = %edge.spool_dir.display(), "running in edge mode");
+    tokio::try_join!(mu_pipeline.run(), gen_pipeline.run(), shipper_run)?;
 
+    Ok(())
+}
+
+async fn run(cfg: &AppConfig) -> Result<()> {
     let mu_cfg = &cfg.meter_usage;
     let gen_cfg = &cfg.generation_output;
 
@@ -83,13 +494,103 @@ async fn main() -> Result<()> {
         None
     };
 
+    // TimescaleDB / Postgres pool, only if any pipeline writes there.
+    let timescale_pool = if mu_cfg.sink.kind == SinkKind::Timescale || gen_cfg.sink.kind == SinkKind::Timescale {
+        let ts_cfg = cfg
+            .timescale
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("sink.kind = \"timescale\" requires a [timescale] section"))?;
+        Some(
+            PgPoolOptions::new()
+                .max_connections(ts_cfg.max_connections)
+                .connect(&ts_cfg.uri)
+                .await?,
+        )
+    } else {
+        None
+    };
+
+    // Portal usage API, on its own listener and QuestDB pool
+    if let Some(api_cfg) = &cfg.usage_api {
+        let api_pool = PgPoolOptions::new()
+            .max_connections(api_cfg.max_connections)
+            .connect(&cfg.questdb.uri)
+            .await?;
+        usage_api::serve(api_cfg, api_pool).await?;
+    }
+
+    // Dashboard GraphQL API, likewise on its own listener and pool
+    #[cfg(feature = "graphql")]
+    if let Some(gql_cfg) = &cfg.graphql_api {
+        let gql_pool = PgPoolOptions::new()
+            .max_connections(gql_cfg.max_connections)
+            .connect(&cfg.questdb.uri)
+            .await?;
+        ingestion_service::graphql_api::serve(gql_cfg, gql_pool).await?;
+    }
+    #[cfg(not(feature = "graphql"))]
+    if cfg.graphql_api.is_some() {
+        anyhow::bail!("[graphql_api] requires building with `--features graphql`");
+    }
+
+    // Arrow IPC exports for data science clients, likewise on their own listener and pool
+    #[cfg(feature = "arrow")]
+    if let Some(arrow_cfg) = &cfg.arrow_export {
+        let arrow_pool = PgPoolOptions::new()
+            .max_connections(arrow_cfg.max_connections)
+            .connect(&cfg.questdb.uri)
+            .await?;
+        ingestion_service::arrow_export::serve(arrow_cfg, arrow_pool).await?;
+    }
+    #[cfg(not(feature = "arrow"))]
+    if cfg.arrow_export.is_some() {
+        anyhow::bail!("[arrow_export] requires building with `--features arrow`");
+    }
+
     let ilp_addr: SocketAddr = cfg
         .questdb
         .ilp_tcp_addr
         .parse()
         .map_err(|e| anyhow::anyhow!("invalid questdb.ilp_tcp_addr: {e}"))?;
+    let ilp_pool = cfg.questdb.ilp_pool_connections.map(|n| IlpConnectionPool::new(ilp_addr, n));
+
+    // Only ask the server for its version if some ILP sink negotiates its dialect.
+    let wants_auto_protocol = [
+        Some(&mu_cfg.sink),
+        Some(&gen_cfg.sink),
+        cfg.reference.as_ref().map(|c| &c.sink),
+        cfg.reject_log.as_ref().map(|c| &c.sink),
+        cfg.duplicate_stats.as_ref().map(|c| &c.sink),
+        cfg.unit_runtime.as_ref().map(|c| &c.sink),
+        cfg.meter_events.as_ref().map(|c| &c.sink),
+        cfg.stream_alerts.as_ref().map(|c| &c.sink),
+        cfg.window_aggregates.as_ref().map(|c| &c.sink),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|sink| sink.ilp_protocol == IlpProtocolSetting::Auto);
+    let server_version = if wants_auto_protocol {
+        let version = match PgPoolOptions::new().max_connections(1).connect(&cfg.questdb.uri).await {
+            Ok(probe) => detect_server_version(&probe).await,
+            Err(e) => {
+                tracing::warn!(error = %e, "could not query QuestDB version, using ILP v1");
+                None
+            }
+        };
+        tracing::info!(
+            server_version = ?version,
+            protocol = ?IlpProtocolVersion::for_server(version),
+            "ILP protocol negotiated"
+        );
+        version
+    } else {
+        None
+    };
 
     // Meter usage pipeline
+    let mu_source = MeterUsageSource::from_config(mu_cfg).await?;
+    let mu_grpc = grpc_source::<MeterUsage>(mu_cfg).await?;
+    let (mu_sample, mu_sample_table) = sample_settings(mu_cfg)?;
     let mu_sink = match mu_cfg.sink.kind {
         SinkKind::Ilp => MeterUsageSink::Ilp(QuestDbIlpMeterUsageSink::new(
             ilp_addr,
@@ -98,7 +599,14 @@ async fn main() -> Result<()> {
             Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
             Duration::from_millis(mu_cfg.sink.max_batch_linger_ms),
             mu_cfg.sink.workers,
-        )),
+        )
+        .with_ordering(mu_cfg.sink.ordering)
+        .with_protocol(IlpProtocolVersion::resolve(mu_cfg.sink.ilp_protocol, server_version))
+        .with_event_id(mu_cfg.sink.event_id)
+        .with_lag(mu_source.sink_lag())
+        .with_pool(ilp_pool.clone())
+        .with_table(mu_sample_table)
+        .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every))),
         SinkKind::Pgwire => {
             let pool = pool.clone().expect("pgwire pool must be initialized");
             MeterUsageSink::Pgwire(QuestDbSink::new(
@@ -106,26 +614,177 @@ async fn main() -> Result<()> {
                 mu_cfg.sink.batch_size,
                 mu_cfg.sink.max_retries,
                 Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
-            ))
+            )
+            .with_event_id(mu_cfg.sink.event_id)
+            .with_lag(mu_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every)))
+        }
+        SinkKind::Clickhouse => MeterUsageSink::ClickHouse(Box::new(
+            StoreSink::new(
+                ClickHouseStore::from_config(clickhouse_cfg(cfg)?),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(mu_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(mu_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every)),
+        )),
+        SinkKind::Timescale => MeterUsageSink::Timescale(
+            StoreSink::new(
+                TimescaleStore::new(timescale_pool.clone().expect("timescale pool must be initialized")),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(mu_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(mu_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every)),
+        ),
+        SinkKind::File => MeterUsageSink::File(Box::new(
+            StoreSink::new(
+                FileNdjsonStore::from_config(
+                    cfg.file_sink
+                        .as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("sink.kind = \"file\" requires a [file_sink] section"))?,
+                ),
+                mu_cfg.sink.batch_size,
+                mu_cfg.sink.max_retries,
+                Duration::from_millis(mu_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(mu_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(mu_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&mu_cfg.name, mu_cfg.sink.latency_sample_every)),
+        )),
+    };
+    // Optional persistence of validation rejects
+    let (mu_validation, reject_pipeline): (Arc<dyn Transform<MeterUsage, MeterUsage>>, _) = match &cfg.reject_log {
+        Some(rl_cfg) => {
+            let (log, pipeline) = build_reject_log_pipeline(rl_cfg, ilp_addr, ilp_pool.as_ref(), server_version)?;
+            (
+                Arc::new(RecordMeterUsageRejects::new(transform::MeterUsageValidation, log)),
+                Some(pipeline),
+            )
         }
+        None => (Arc::new(transform::MeterUsageValidation), None),
     };
-    let mu_source = HttpJsonSource::new(
-        &mu_cfg.source.http_bind_addr,
-        mu_cfg.source.channel_capacity,
-        mu_cfg.source.auth_bearer_token.clone(),
-        mu_cfg.source.max_body_bytes,
-        mu_cfg.source.max_request_records,
-        mu_cfg.source.max_line_bytes,
-        mu_cfg.source.ndjson_strict,
-    )
-    .await?;
+    let reject_run = async move {
+        match reject_pipeline {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Optional duplicate-rate counting (needs deterministic event ids)
+    let (duplicates, duplicate_pipeline) = match &cfg.duplicate_stats {
+        Some(ds_cfg) => {
+            if mu_cfg.sink.event_id == EventIdStrategy::UuidV7 {
+                anyhow::bail!("duplicate_stats needs a deterministic meter_usage.sink.event_id, not \"uuid_v7\"");
+            }
+            let (log, pipeline) = build_duplicate_log_pipeline(ds_cfg, ilp_addr, ilp_pool.as_ref(), server_version)?;
+            let t = MeterUsageDuplicates::new(
+                mu_cfg.sink.event_id,
+                ds_cfg.window_records,
+                Duration::from_secs(ds_cfg.flush_interval_secs),
+                log,
+            );
+            (Some(t), Some(pipeline))
+        }
+        None => (None, None),
+    };
+    let duplicate_run = async move {
+        match duplicate_pipeline {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Optional threshold alerting on live records (after validation)
+    let (stream_alerts, stream_alerts_pipeline) = match &cfg.stream_alerts {
+        Some(sa_cfg) => {
+            let (alerts, pipeline) = build_stream_alerts_pipeline(sa_cfg, ilp_addr, ilp_pool.as_ref(), server_version)?;
+            (Some(alerts), Some(pipeline))
+        }
+        None => (None, None),
+    };
+    let stream_alerts_run = async move {
+        match stream_alerts_pipeline {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Optional windowed aggregation of live records (after validation)
+    let (window_aggregates, window_aggregates_pipeline) = match &cfg.window_aggregates {
+        Some(wa_cfg) => {
+            let (tx, feeders, pipeline) =
+                build_window_aggregates_pipeline(wa_cfg, &cfg.questdb.uri, ilp_addr, ilp_pool.as_ref(), server_version).await?;
+            (Some((tx, feeders)), Some(pipeline))
+        }
+        None => (None, None),
+    };
+    let window_aggregates_run = async move {
+        match window_aggregates_pipeline {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Optional join to the meters reference data, kept current by the reference pipeline
+    let meter_references = match &cfg.reference_join {
+        Some(join_cfg) => Some(load_meter_references(join_cfg, cfg).await?),
+        None => None,
+    };
+
+    let mut mu_transforms = vec![configured_stage(mu_cfg, "validation", mu_validation)];
+    if let (Some(join_cfg), Some(table)) = (&cfg.reference_join, &meter_references) {
+        if join_cfg.fill_premise_id {
+            mu_transforms.push(Arc::new(ReferenceJoin::new(
+                "meters",
+                table.clone(),
+                reference_join::meter_usage_key,
+                reference_join::fill_meter_usage,
+            )));
+        }
+    }
+    if let (Some(sa_cfg), Some(alerts)) = (&cfg.stream_alerts, &stream_alerts) {
+        let t = ThresholdAlerts::<MeterUsage>::new(
+            &sa_cfg.rules,
+            Duration::from_secs(sa_cfg.cooldown_secs),
+            alerts.clone(),
+        );
+        if !t.is_empty() {
+            mu_transforms.push(Arc::new(t));
+        }
+    }
+
+    if let (Some(wa_cfg), Some((tx, feeders))) = (&cfg.window_aggregates, &window_aggregates) {
+        let t = WindowAggregator::<MeterUsage>::new(
+            &wa_cfg.windows,
+            wa_cfg.allowed_lateness_secs,
+            feeders.clone(),
+            tx.clone(),
+        );
+        if !t.is_empty() {
+            mu_transforms.push(Arc::new(t));
+        }
+    }
+
+    // Last, so ids are derived from the records as the sink writes them.
+    if let Some(t) = duplicates {
+        mu_transforms.push(Arc::new(t));
+    }
+
     let mu_pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
-        source: mu_source,
-        transforms: vec![Arc::new(transform::MeterUsageValidation::default())],
+        source: SampledSource::new(WithGrpc { source: mu_source, grpc: mu_grpc }, &mu_cfg.name, mu_sample),
+        transforms: mu_transforms,
         sink: mu_sink,
     };
 
     // Generation output pipeline
+    let gen_source = GenerationSource::from_config(gen_cfg).await?;
+    let gen_grpc = grpc_source::<GenerationOutput>(gen_cfg).await?;
+    let (gen_sample, gen_sample_table) = sample_settings(gen_cfg)?;
     let gen_sink = match gen_cfg.sink.kind {
         SinkKind::Ilp => GenerationSink::Ilp(QuestDbIlpGenerationSink::new(
             ilp_addr,
@@ -134,7 +793,14 @@ async fn main() -> Result<()> {
             Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
             Duration::from_millis(gen_cfg.sink.max_batch_linger_ms),
             gen_cfg.sink.workers,
-        )),
+        )
+        .with_ordering(gen_cfg.sink.ordering)
+        .with_protocol(IlpProtocolVersion::resolve(gen_cfg.sink.ilp_protocol, server_version))
+        .with_event_id(gen_cfg.sink.event_id)
+        .with_lag(gen_source.sink_lag())
+        .with_pool(ilp_pool.clone())
+        .with_table(gen_sample_table)
+        .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every))),
         SinkKind::Pgwire => {
             let pool = pool.expect("pgwire pool must be initialized");
             GenerationSink::Pgwire(QuestDbGenerationSink::new(
@@ -142,27 +808,432 @@ async fn main() -> Result<()> {
                 gen_cfg.sink.batch_size,
                 gen_cfg.sink.max_retries,
                 Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
-            ))
+            )
+            .with_event_id(gen_cfg.sink.event_id)
+            .with_lag(gen_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every)))
+        }
+        SinkKind::Clickhouse => GenerationSink::ClickHouse(Box::new(
+            StoreSink::new(
+                ClickHouseStore::from_config(clickhouse_cfg(cfg)?),
+                gen_cfg.sink.batch_size,
+                gen_cfg.sink.max_retries,
+                Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(gen_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(gen_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every)),
+        )),
+        SinkKind::Timescale => GenerationSink::Timescale(
+            StoreSink::new(
+                TimescaleStore::new(timescale_pool.clone().expect("timescale pool must be initialized")),
+                gen_cfg.sink.batch_size,
+                gen_cfg.sink.max_retries,
+                Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(gen_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(gen_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every)),
+        ),
+        SinkKind::File => GenerationSink::File(Box::new(
+            StoreSink::new(
+                FileNdjsonStore::from_config(
+                    cfg.file_sink
+                        .as_ref()
+                        .ok_or_else(|| anyhow::anyhow!("sink.kind = \"file\" requires a [file_sink] section"))?,
+                ),
+                gen_cfg.sink.batch_size,
+                gen_cfg.sink.max_retries,
+                Duration::from_millis(gen_cfg.sink.retry_backoff_ms),
+                Duration::from_millis(gen_cfg.sink.max_batch_linger_ms),
+            )
+            .with_lag(gen_source.sink_lag())
+            .with_latency_sampler(LatencySampler::new(&gen_cfg.name, gen_cfg.sink.latency_sample_every)),
+        )),
+    };
+    let mut gen_transforms: Vec<Arc<dyn Transform<GenerationOutput, GenerationOutput> + Send + Sync>> =
+        vec![configured_stage(gen_cfg, "validation", Arc::new(transform::GenerationOutputValidation))];
+
+    // Optional unit start/stop tracking (after validation, so rejected samples don't count)
+    let unit_runtime_pipeline = match &cfg.unit_runtime {
+        Some(ur_cfg) => {
+            let (tracker, pipeline) = build_unit_runtime_pipeline(ur_cfg, ilp_addr, ilp_pool.as_ref(), server_version)?;
+            gen_transforms.push(Arc::new(tracker));
+            Some(pipeline)
         }
+        None => None,
     };
-    let gen_source = HttpGenerationOutputSource::new(
-        &gen_cfg.source.http_bind_addr,
-        gen_cfg.source.channel_capacity,
-        gen_cfg.source.auth_bearer_token.clone(),
-        gen_cfg.source.max_body_bytes,
-        gen_cfg.source.max_request_records,
-        gen_cfg.source.max_line_bytes,
-        gen_cfg.source.ndjson_strict,
-    )
-    .await?;
+    if let (Some(sa_cfg), Some(alerts)) = (&cfg.stream_alerts, &stream_alerts) {
+        let t = ThresholdAlerts::<GenerationOutput>::new(
+            &sa_cfg.rules,
+            Duration::from_secs(sa_cfg.cooldown_secs),
+            alerts.clone(),
+        );
+        if !t.is_empty() {
+            gen_transforms.push(Arc::new(t));
+        }
+    }
+    if let (Some(wa_cfg), Some((tx, feeders))) = (&cfg.window_aggregates, &window_aggregates) {
+        let t = WindowAggregator::<GenerationOutput>::new(
+            &wa_cfg.windows,
+            wa_cfg.allowed_lateness_secs,
+            feeders.clone(),
+            tx.clone(),
+        );
+        if !t.is_empty() {
+            gen_transforms.push(Arc::new(t));
+        }
+    }
+    let unit_runtime_run = async move {
+        match unit_runtime_pipeline {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
     let gen_pipeline: Pipeline<_, GenerationOutput, _> = Pipeline {
-        source: gen_source,
-        transforms: vec![Arc::new(transform::GenerationOutputValidation::default())],
+        source: SampledSource::new(WithGrpc { source: gen_source, grpc: gen_grpc }, &gen_cfg.name, gen_sample),
+        transforms: gen_transforms,
         sink: gen_sink,
     };
 
-    // Run both pipelines concurrently
-    tokio::try_join!(mu_pipeline.run(), gen_pipeline.run())?;
+    // Optional reference-data (meters/customers) sync pipelines
+    let reference = match &cfg.reference {
+        Some(ref_cfg) => Some(build_reference_pipelines(ref_cfg, ilp_addr, ilp_pool.as_ref(), server_version, meter_references).await?),
+        None => None,
+    };
+    let reference_run = async move {
+        match reference {
+            Some((meters, customers, exchanges, dr_events)) => {
+                tokio::try_join!(meters.run(), customers.run(), exchanges.run(), dr_events.run()).map(|_| ())
+            }
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Optional syslog listener for head-end meter events
+    let meter_events = match &cfg.meter_events {
+        Some(me_cfg) => Some(build_meter_events_pipeline(me_cfg, ilp_addr, ilp_pool.as_ref(), server_version).await?),
+        None => None,
+    };
+    let meter_events_run = async move {
+        match meter_events {
+            Some(p) => p.run().await,
+            None => Ok::<(), PipelineError>(()),
+        }
+    };
+
+    // Run all pipelines concurrently
+    tokio::try_join!(
+        mu_pipeline.run(),
+        gen_pipeline.run(),
+        reference_run,
+        reject_run,
+        duplicate_run,
+        unit_runtime_run,
+        meter_events_run,
+        stream_alerts_run,
+        window_aggregates_run
+    )?;
 
     Ok(())
 }
+
+type DuplicateLogPipeline = Pipeline<DuplicateLogSource, DuplicateCounts, QuestDbIlpSink<DuplicateCounts>>;
+
+fn build_duplicate_log_pipeline(
+    cfg: &DuplicateStatsConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<(DuplicateLog, DuplicateLogPipeline)> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("duplicate_stats only supports sink.kind = \"ilp\"");
+    }
+
+    let (log, source) = DuplicateLog::channel(cfg.channel_capacity);
+    let pipeline = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((log, pipeline))
+}
+
+type UnitRuntimePipeline = Pipeline<UnitTransitionSource, UnitTransition, QuestDbIlpSink<UnitTransition>>;
+
+fn build_unit_runtime_pipeline(
+    cfg: &UnitRuntimeConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<(UnitStateTracker, UnitRuntimePipeline)> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("unit_runtime only supports sink.kind = \"ilp\"");
+    }
+
+    let (tracker, source) = UnitStateTracker::new(cfg.min_running_mw, cfg.channel_capacity);
+    let pipeline = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((tracker, pipeline))
+}
+
+type MeterEventsPipeline = Pipeline<SyslogUdpSource, MeterEvent, QuestDbIlpSink<MeterEvent>>;
+
+async fn build_meter_events_pipeline(
+    cfg: &MeterEventsConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<MeterEventsPipeline> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("meter_events only supports sink.kind = \"ilp\"");
+    }
+
+    let source = SyslogUdpSource::bind(cfg)
+        .await
+        .map_err(|e| anyhow::anyhow!("meter_events: cannot listen on {}: {e}", cfg.bind_addr))?;
+    Ok(Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    })
+}
+
+type StreamAlertsPipeline = Pipeline<StreamAlertSource, StreamAlert, QuestDbIlpSink<StreamAlert>>;
+
+fn build_stream_alerts_pipeline(
+    cfg: &StreamAlertsConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<(StreamAlerts, StreamAlertsPipeline)> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("stream_alerts only supports sink.kind = \"ilp\"");
+    }
+    threshold_alerts::validate_rules(&cfg.rules).map_err(|e| anyhow::anyhow!(e))?;
+
+    let (alerts, source) = StreamAlerts::channel(cfg.channel_capacity);
+    let pipeline = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((alerts, pipeline))
+}
+
+type WindowAggregatesPipeline = Pipeline<WindowAggregateSource, WindowAggregate, QuestDbIlpSink<WindowAggregate>>;
+
+async fn build_window_aggregates_pipeline(
+    cfg: &WindowAggregatesConfig,
+    questdb_uri: &str,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<(mpsc::Sender<Envelope<WindowAggregate>>, Arc<FeederMap>, WindowAggregatesPipeline)> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("window_aggregates only supports sink.kind = \"ilp\"");
+    }
+    window_aggregate::validate_windows(&cfg.windows).map_err(|e| anyhow::anyhow!(e))?;
+
+    // Feeder assignments are read once at startup.
+    let feeders = if cfg.windows.iter().any(|w| w.key_by == WindowKey::Feeder) {
+        let pool = PgPoolOptions::new().max_connections(1).connect(questdb_uri).await?;
+        let feeders = FeederMap::load(&pool).await?;
+        tracing::info!(mappings = feeders.len(), "loaded feeder map for window aggregates");
+        feeders
+    } else {
+        FeederMap::default()
+    };
+
+    let (tx, source) = window_aggregate::channel(cfg.channel_capacity);
+    let pipeline = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((tx, Arc::new(feeders), pipeline))
+}
+
+type RejectLogPipeline = Pipeline<RejectLogSource, IngestReject, QuestDbIlpSink<IngestReject>>;
+
+fn build_reject_log_pipeline(
+    cfg: &RejectLogConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+) -> Result<(RejectLog, RejectLogPipeline)> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("reject_log only supports sink.kind = \"ilp\"");
+    }
+
+    let (log, source) = RejectLog::channel(cfg.channel_capacity);
+    let pipeline = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+        )
+        .with_protocol(IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version))
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((log, pipeline))
+}
+
+/// Bootstrap the meters reference table for `[reference_join]` from QuestDB.
+async fn load_meter_references(
+    join_cfg: &ReferenceJoinConfig,
+    cfg: &AppConfig,
+) -> Result<ReferenceTable<MeterReference>> {
+    let pool = PgPoolOptions::new().max_connections(1).connect(&cfg.questdb.uri).await?;
+    let table = reference_join::load_meter_references(&pool).await?;
+    tracing::info!(meters = table.len(), fill_premise_id = join_cfg.fill_premise_id, "loaded meter reference data");
+    if cfg.reference.is_none() {
+        tracing::warn!("reference_join without [reference]: meter changes apply after a restart");
+    }
+    Ok(table)
+}
+
+type ReferencePipelines = (
+    Pipeline<HttpReferenceSource, Meter, QuestDbIlpMeterSink>,
+    Pipeline<HttpReferenceSource, Customer, QuestDbIlpCustomerSink>,
+    Pipeline<HttpReferenceSource, MeterExchange, QuestDbIlpMeterExchangeSink>,
+    Pipeline<HttpReferenceSource, DrEvent, QuestDbIlpDrEventSink>,
+);
+
+async fn build_reference_pipelines(
+    cfg: &PipelineConfig,
+    ilp_addr: SocketAddr,
+    ilp_pool: Option<&IlpConnectionPool>,
+    server_version: Option<ServerVersion>,
+    meter_references: Option<ReferenceTable<MeterReference>>,
+) -> Result<ReferencePipelines> {
+    if cfg.sink.kind != SinkKind::Ilp {
+        anyhow::bail!("reference pipeline only supports sink.kind = \"ilp\"");
+    }
+    let protocol = IlpProtocolVersion::resolve(cfg.sink.ilp_protocol, server_version);
+
+    let source = HttpReferenceSource::from_config(&cfg.source).await?;
+
+    let mut meter_transforms: Vec<Arc<dyn Transform<Meter, Meter> + Send + Sync>> = Vec::new();
+    if let Some(table) = meter_references {
+        meter_transforms.push(Arc::new(ReferenceUpdates::new(table, reference_join::meter_reference_update)));
+    }
+
+    let meters = Pipeline {
+        source: source.clone(),
+        transforms: meter_transforms,
+        sink: QuestDbIlpMeterSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+            cfg.sink.workers,
+        )
+        .with_ordering(cfg.sink.ordering)
+        .with_protocol(protocol)
+        .with_pool(ilp_pool.cloned()),
+    };
+    let customers = Pipeline {
+        source: source.clone(),
+        transforms: vec![],
+        sink: QuestDbIlpCustomerSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+            cfg.sink.workers,
+        )
+        .with_ordering(cfg.sink.ordering)
+        .with_protocol(protocol)
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    let exchanges = Pipeline {
+        source: source.clone(),
+        transforms: vec![],
+        sink: QuestDbIlpMeterExchangeSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+            cfg.sink.workers,
+        )
+        .with_ordering(cfg.sink.ordering)
+        .with_protocol(protocol)
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    let dr_events = Pipeline {
+        source,
+        transforms: vec![],
+        sink: QuestDbIlpDrEventSink::new(
+            ilp_addr,
+            cfg.sink.batch_size,
+            cfg.sink.max_retries,
+            Duration::from_millis(cfg.sink.retry_backoff_ms),
+            Duration::from_millis(cfg.sink.max_batch_linger_ms),
+            cfg.sink.workers,
+        )
+        .with_ordering(cfg.sink.ordering)
+        .with_protocol(protocol)
+        .with_pool(ilp_pool.cloned()),
+    };
+
+    Ok((meters, customers, exchanges, dr_events))
+}
@@ -1,12 +1,24 @@
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use axum::{routing::get, Router};
+use hyper_util::{rt::TokioExecutor, server::conn::auto};
 use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 use once_cell::sync::OnceCell;
 
+use crate::{config::MetricsConfig, tls::Acceptor};
+
 static PROM_HANDLE: OnceCell<PrometheusHandle> = OnceCell::new();
 
-pub fn init(bind_addr: &str) {
+/// Install the Prometheus recorder and serve `/metrics` on `cfg.bind_addr`,
+/// over HTTPS when `cfg.tls` is set.
+pub fn init(cfg: &MetricsConfig) -> anyhow::Result<()> {
+    let tls = cfg
+        .tls
+        .as_ref()
+        .map(|tls| Acceptor::from_config(tls, false))
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("metrics tls: {e:#}"))?;
+
     let builder = PrometheusBuilder::new();
     let handle = builder
         .install_recorder()
@@ -15,24 +27,44 @@ pub fn init(bind_addr: &str) {
     // Ignore error if the handle was already set; this should only be called once.
     let _ = PROM_HANDLE.set(handle);
 
-    let addr: SocketAddr = bind_addr
+    let addr: SocketAddr = cfg
+        .bind_addr
         .parse()
         .expect("invalid metrics bind address");
 
     tokio::spawn(async move {
         let app = Router::new().route("/metrics", get(metrics_handler));
 
-        match tokio::net::TcpListener::bind(addr).await {
-            Ok(listener) => {
-                if let Err(e) = axum::serve(listener, app.into_make_service()).await {
-                    tracing::error!(error = %e, "metrics server error");
-                }
-            }
+        let listener = match tokio::net::TcpListener::bind(addr).await {
+            Ok(listener) => listener,
             Err(e) => {
                 tracing::error!(error = %e, "failed to bind metrics listener");
+                return;
             }
+        };
+        let Some(tls) = tls else {
+            if let Err(e) = axum::serve(listener, app.into_make_service()).await {
+                tracing::error!(error = %e, "metrics server error");
+            }
+            return;
+        };
+
+        let builder = Arc::new(auto::Builder::new(TokioExecutor::new()));
+        loop {
+            let stream = match listener.accept().await {
+                Ok((stream, _peer)) => stream,
+                Err(e) => {
+                    tracing::error!(error = %e, "metrics server accept error");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let (tls, builder, app) = (tls.clone(), builder.clone(), app.clone());
+            tokio::spawn(async move { tls.serve_connection(stream, &builder, app, "metrics").await });
         }
     });
+
+    Ok(())
 }
 
 async fn metrics_handler() -> String {
@@ -0,0 +1,407 @@
+//! Minimal Parquet file writer.
+//!
+//! Writes flat schemas of optional columns, one uncompressed PLAIN-encoded
+//! data page (v1) per column chunk, which every Parquet reader accepts. Files
+//! are produced a row group at a time, so large exports never sit in memory
+//! whole: [`ParquetWriter::row_group`] returns the bytes to append, and
+//! [`ParquetWriter::finish`] the footer.
+
+const MAGIC: &[u8] = b"PAR1";
+
+/// Values of one column in a row group; `None` is null.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ColumnValues {
+    /// `BYTE_ARRAY` annotated as UTF-8.
+    Text(Vec<Option<String>>),
+    Double(Vec<Option<f64>>),
+    /// `INT64` microseconds since the epoch, UTC.
+    TimestampMicros(Vec<Option<i64>>),
+}
+
+/// Physical and converted type of a column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Text,
+    Double,
+    TimestampMicros,
+}
+
+impl ColumnType {
+    /// Parquet physical type (`Type` enum).
+    fn physical(self) -> i32 {
+        match self {
+            Self::Text => 6,
+            Self::Double => 5,
+            Self::TimestampMicros => 2,
+        }
+    }
+
+    /// Parquet `ConvertedType`, if any.
+    fn converted(self) -> Option<i32> {
+        match self {
+            Self::Text => Some(0),
+            Self::Double => None,
+            Self::TimestampMicros => Some(10),
+        }
+    }
+}
+
+impl ColumnValues {
+    fn column_type(&self) -> ColumnType {
+        match self {
+            Self::Text(_) => ColumnType::Text,
+            Self::Double(_) => ColumnType::Double,
+            Self::TimestampMicros(_) => ColumnType::TimestampMicros,
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Self::Text(v) => v.len(),
+            Self::Double(v) => v.len(),
+            Self::TimestampMicros(v) => v.len(),
+        }
+    }
+
+    /// Definition levels (1 = present) and PLAIN-encoded present values.
+    fn encode(&self) -> (Vec<bool>, Vec<u8>) {
+        let mut out = Vec::new();
+        let present = match self {
+            Self::Text(values) => values
+                .iter()
+                .map(|v| {
+                    if let Some(s) = v {
+                        out.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                        out.extend_from_slice(s.as_bytes());
+                    }
+                    v.is_some()
+                })
+                .collect(),
+            Self::Double(values) => values
+                .iter()
+                .map(|v| {
+                    if let Some(x) = v {
+                        out.extend_from_slice(&x.to_le_bytes());
+                    }
+                    v.is_some()
+                })
+                .collect(),
+            Self::TimestampMicros(values) => values
+                .iter()
+                .map(|v| {
+                    if let Some(x) = v {
+                        out.extend_from_slice(&x.to_le_bytes());
+                    }
+                    v.is_some()
+                })
+                .collect(),
+        };
+        (present, out)
+    }
+}
+
+/// Definition levels of bit width 1 as one bit-packed run of the RLE/bit-packing
+/// hybrid, prefixed with its length as data pages v1 require.
+fn definition_levels(present: &[bool]) -> Vec<u8> {
+    let groups = present.len().div_ceil(8);
+    let mut run = Vec::with_capacity(groups + 5);
+    put_varint(&mut run, ((groups as u64) << 1) | 1);
+    for chunk in present.chunks(8) {
+        run.push(
+            chunk
+                .iter()
+                .enumerate()
+                .fold(0u8, |byte, (i, p)| byte | (u8::from(*p) << i)),
+        );
+    }
+    let mut out = (run.len() as u32).to_le_bytes().to_vec();
+    out.extend(run);
+    out
+}
+
+fn put_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push((v as u8) | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+/// Thrift compact protocol encoder, for page headers and the file footer.
+#[derive(Default)]
+struct Thrift {
+    out: Vec<u8>,
+    /// Last field id of each open struct.
+    last_field: Vec<i16>,
+}
+
+const T_I32: u8 = 5;
+const T_I64: u8 = 6;
+const T_BINARY: u8 = 8;
+const T_LIST: u8 = 9;
+const T_STRUCT: u8 = 12;
+
+impl Thrift {
+    fn field(&mut self, id: i16, kind: u8) {
+        let last = self.last_field.last_mut().expect("field outside a struct");
+        let delta = id - *last;
+        if (1..=15).contains(&delta) {
+            self.out.push(((delta as u8) << 4) | kind);
+        } else {
+            self.out.push(kind);
+            put_varint(&mut self.out, zigzag(i64::from(id)));
+        }
+        *last = id;
+    }
+
+    fn begin(&mut self) {
+        self.last_field.push(0);
+    }
+
+    fn end(&mut self) {
+        self.out.push(0);
+        self.last_field.pop();
+    }
+
+    fn i32(&mut self, id: i16, v: i32) {
+        self.field(id, T_I32);
+        put_varint(&mut self.out, zigzag(i64::from(v)));
+    }
+
+    fn i64(&mut self, id: i16, v: i64) {
+        self.field(id, T_I64);
+        put_varint(&mut self.out, zigzag(v));
+    }
+
+    fn string(&mut self, id: i16, v: &str) {
+        self.field(id, T_BINARY);
+        self.bytes(v);
+    }
+
+    fn bytes(&mut self, v: &str) {
+        put_varint(&mut self.out, v.len() as u64);
+        self.out.extend_from_slice(v.as_bytes());
+    }
+
+    fn list(&mut self, id: i16, kind: u8, len: usize) {
+        self.field(id, T_LIST);
+        if len < 15 {
+            self.out.push(((len as u8) << 4) | kind);
+        } else {
+            self.out.push(0xf0 | kind);
+            put_varint(&mut self.out, len as u64);
+        }
+    }
+
+    fn struct_field(&mut self, id: i16) {
+        self.field(id, T_STRUCT);
+        self.begin();
+    }
+}
+
+fn zigzag(v: i64) -> u64 {
+    ((v << 1) ^ (v >> 63)) as u64
+}
+
+/// Where a column chunk landed in the file, for the footer.
+struct ChunkMeta {
+    offset: i64,
+    size: i64,
+    values: i64,
+}
+
+struct RowGroupMeta {
+    rows: i64,
+    chunks: Vec<ChunkMeta>,
+}
+
+/// Writes a Parquet file of the given schema a row group at a time.
+pub struct ParquetWriter {
+    schema: Vec<(String, ColumnType)>,
+    /// Bytes handed out so far.
+    offset: i64,
+    row_groups: Vec<RowGroupMeta>,
+}
+
+impl ParquetWriter {
+    /// A file of these columns, all optional.
+    pub fn new(schema: Vec<(String, ColumnType)>) -> Self {
+        Self {
+            schema,
+            offset: 0,
+            row_groups: Vec::new(),
+        }
+    }
+
+    /// Encodes one row group, in schema order; returns the bytes to append
+    /// (preceded by the file magic on the first call).
+    pub fn row_group(&mut self, columns: &[ColumnValues]) -> Result<Vec<u8>, String> {
+        if columns.len() != self.schema.len() {
+            return Err(format!(
+                "{} columns for a schema of {}",
+                columns.len(),
+                self.schema.len()
+            ));
+        }
+        let rows = columns.first().map_or(0, ColumnValues::len);
+        let mut out = Vec::new();
+        if self.offset == 0 {
+            out.extend_from_slice(MAGIC);
+        }
+        let mut chunks = Vec::with_capacity(columns.len());
+        for (values, (name, kind)) in columns.iter().zip(&self.schema) {
+            if values.column_type() != *kind {
+                return Err(format!("column {name} is {:?}, not {kind:?}", values.column_type()));
+            }
+            if values.len() != rows {
+                return Err(format!("column {name} has {} values, not {rows}", values.len()));
+            }
+            let (present, data) = values.encode();
+            let mut page = definition_levels(&present);
+            page.extend(data);
+            let page_size = i32::try_from(page.len()).map_err(|_| format!("column {name} exceeds a page"))?;
+
+            let mut header = Thrift::default();
+            header.begin();
+            header.i32(1, 0); // DATA_PAGE
+            header.i32(2, page_size);
+            header.i32(3, page_size);
+            header.struct_field(5);
+            header.i32(1, rows as i32);
+            header.i32(2, 0); // PLAIN
+            header.i32(3, 3); // RLE
+            header.i32(4, 3);
+            header.end();
+            header.end();
+
+            let size = (header.out.len() + page.len()) as i64;
+            chunks.push(ChunkMeta {
+                offset: self.offset + out.len() as i64,
+                size,
+                values: rows as i64,
+            });
+            out.extend(header.out);
+            out.extend(page);
+        }
+        self.offset += out.len() as i64;
+        self.row_groups.push(RowGroupMeta {
+            rows: rows as i64,
+            chunks,
+        });
+        Ok(out)
+    }
+
+    /// The file footer; a file without row groups is just the magic and footer.
+    pub fn finish(self) -> Vec<u8> {
+        let mut t = Thrift::default();
+        t.begin();
+        t.i32(1, 1);
+        t.list(2, T_STRUCT, self.schema.len() + 1);
+        t.begin();
+        t.string(4, "schema");
+        t.i32(5, self.schema.len() as i32);
+        t.end();
+        for (name, kind) in &self.schema {
+            t.begin();
+            t.i32(1, kind.physical());
+            t.i32(3, 1); // OPTIONAL
+            t.string(4, name);
+            if let Some(converted) = kind.converted() {
+                t.i32(6, converted);
+            }
+            t.end();
+        }
+        t.i64(3, self.row_groups.iter().map(|g| g.rows).sum());
+        t.list(4, T_STRUCT, self.row_groups.len());
+        for group in &self.row_groups {
+            t.begin();
+            t.list(1, T_STRUCT, group.chunks.len());
+            for (chunk, (name, kind)) in group.chunks.iter().zip(&self.schema) {
+                t.begin();
+                t.i64(2, chunk.offset);
+                t.struct_field(3);
+                t.i32(1, kind.physical());
+                t.list(2, T_I32, 2);
+                put_varint(&mut t.out, zigzag(0)); // PLAIN
+                put_varint(&mut t.out, zigzag(3)); // RLE
+                t.list(3, T_BINARY, 1);
+                t.bytes(name);
+                t.i32(4, 0); // UNCOMPRESSED
+                t.i64(5, chunk.values);
+                t.i64(6, chunk.size);
+                t.i64(7, chunk.size);
+                t.i64(9, chunk.offset);
+                t.end();
+                t.end();
+            }
+            t.i64(2, group.chunks.iter().map(|c| c.size).sum());
+            t.i64(3, group.rows);
+            t.end();
+        }
+        t.string(6, concat!("ingestion-service ", env!("CARGO_PKG_VERSION")));
+        t.end();
+
+        let mut out = Vec::new();
+        if self.offset == 0 {
+            out.extend_from_slice(MAGIC);
+        }
+        let footer_len = t.out.len() as u32;
+        out.extend(t.out);
+        out.extend_from_slice(&footer_len.to_le_bytes());
+        out.extend_from_slice(MAGIC);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thrift_fields_use_compact_deltas() {
+        let mut t = Thrift::default();
+        t.begin();
+        t.i32(1, 1);
+        t.i64(3, -1);
+        t.i32(20, 2);
+        t.end();
+        assert_eq!(t.out, vec![0x15, 0x02, 0x26, 0x01, 0x05, 0x28, 0x04, 0x00]);
+    }
+
+    #[test]
+    fn definition_levels_are_bit_packed() {
+        let levels = definition_levels(&[true, false, true, true, true, true, true, true, false]);
+        // length 3, header (2 groups << 1) | 1, then LSB-first bits
+        assert_eq!(levels, vec![3, 0, 0, 0, 0x05, 0b1111_1101, 0b0000_0000]);
+    }
+
+    #[test]
+    fn file_is_framed_by_magic_and_footer_length() {
+        let mut writer = ParquetWriter::new(vec![
+            ("id".to_string(), ColumnType::Text),
+            ("kwh".to_string(), ColumnType::Double),
+        ]);
+        let mut file = writer
+            .row_group(&[
+                ColumnValues::Text(vec![Some("a".into()), None]),
+                ColumnValues::Double(vec![Some(1.5), Some(2.0)]),
+            ])
+            .unwrap();
+        let body = file.len();
+        file.extend(writer.finish());
+
+        assert_eq!(&file[..4], MAGIC);
+        assert_eq!(&file[file.len() - 4..], MAGIC);
+        let footer_len = u32::from_le_bytes(file[file.len() - 8..file.len() - 4].try_into().unwrap()) as usize;
+        assert_eq!(body + footer_len + 8, file.len());
+        // the first page header follows the magic: DATA_PAGE, then the page sizes
+        assert_eq!(&file[4..6], &[0x15, 0x00]);
+    }
+
+    #[test]
+    fn mismatched_row_groups_are_rejected() {
+        let mut writer = ParquetWriter::new(vec![("kwh".to_string(), ColumnType::Double)]);
+        assert!(writer.row_group(&[ColumnValues::Text(vec![None])]).is_err());
+        assert!(writer.row_group(&[]).is_err());
+    }
+}
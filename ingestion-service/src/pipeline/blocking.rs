@@ -0,0 +1,59 @@
+use std::sync::Arc;
+
+use tokio::{runtime::Handle, sync::Semaphore};
+
+use super::{Concurrency, Envelope, PipelineError, Transform};
+
+/// Runs `inner` on tokio's blocking thread pool instead of a reactor thread.
+///
+/// For CPU-heavy stages (hashing, parsing, scripting) that would otherwise
+/// stall other tasks on the same worker during large backfills. At most
+/// `max_in_flight` records are handed to the pool at once; this also holds
+/// when the pipeline is cancelled, since a permit is only released once its
+/// blocking call has returned. Combine with [`WithConcurrency`](super::WithConcurrency)
+/// to let the pipeline feed several records at a time.
+pub struct Blocking<I, O> {
+    inner: Arc<dyn Transform<I, O> + Send + Sync>,
+    permits: Arc<Semaphore>,
+}
+
+impl<I, O> Blocking<I, O> {
+    pub fn new(inner: Arc<dyn Transform<I, O> + Send + Sync>, max_in_flight: usize) -> Self {
+        Self {
+            inner,
+            permits: Arc::new(Semaphore::new(max_in_flight.max(1))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<I, O> Transform<I, O> for Blocking<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    async fn apply(&self, input: Envelope<I>) -> Result<Envelope<O>, PipelineError> {
+        let permit = self
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| PipelineError::Transform(format!("blocking stage closed: {e}")))?;
+        let inner = self.inner.clone();
+        let handle = Handle::current();
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            handle.block_on(inner.apply(input))
+        })
+        .await
+        .map_err(|e| PipelineError::Transform(format!("blocking stage panicked: {e}")))?
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn concurrency(&self) -> Concurrency {
+        self.inner.concurrency()
+    }
+}
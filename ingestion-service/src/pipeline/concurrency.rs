@@ -0,0 +1,61 @@
+use std::sync::Arc;
+
+use super::{Envelope, PipelineError, Transform};
+
+/// How [`Pipeline::run`](super::Pipeline::run) drives one transform stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Concurrency {
+    /// One record at a time (default).
+    #[default]
+    Sequential,
+    /// Up to `n` records in flight; output keeps input order.
+    Ordered(usize),
+    /// Up to `n` records in flight; records leave as soon as they're done.
+    Unordered(usize),
+}
+
+impl Concurrency {
+    /// `Sequential` for `n <= 1`, otherwise ordered or unordered with `n` in flight.
+    pub fn new(n: usize, ordered: bool) -> Self {
+        match (n, ordered) {
+            (0 | 1, _) => Self::Sequential,
+            (n, true) => Self::Ordered(n),
+            (n, false) => Self::Unordered(n),
+        }
+    }
+}
+
+/// Runs `inner` with the given [`Concurrency`].
+///
+/// Only wrap stateless transforms (lookups, parsing, validation); a stateful
+/// stage such as [`UnitStateTracker`](crate::transform::unit_state::UnitStateTracker)
+/// relies on seeing records one at a time and in order.
+pub struct WithConcurrency<I, O> {
+    inner: Arc<dyn Transform<I, O> + Send + Sync>,
+    concurrency: Concurrency,
+}
+
+impl<I, O> WithConcurrency<I, O> {
+    pub fn new(inner: Arc<dyn Transform<I, O> + Send + Sync>, concurrency: Concurrency) -> Self {
+        Self { inner, concurrency }
+    }
+}
+
+#[async_trait::async_trait]
+impl<I, O> Transform<I, O> for WithConcurrency<I, O>
+where
+    I: Send + 'static,
+    O: Send + 'static,
+{
+    async fn apply(&self, input: Envelope<I>) -> Result<Envelope<O>, PipelineError> {
+        self.inner.apply(input).await
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn concurrency(&self) -> Concurrency {
+        self.concurrency
+    }
+}
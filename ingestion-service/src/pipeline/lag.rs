@@ -0,0 +1,85 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How far a pipeline's sink is behind its source.
+///
+/// Sinks record every successful flush; sources read the lag for admission
+/// control. Clones share the same state.
+#[derive(Debug, Clone)]
+pub struct SinkLag {
+    inner: Arc<SinkLagInner>,
+}
+
+#[derive(Debug)]
+struct SinkLagInner {
+    origin: Instant,
+    /// Milliseconds after `origin` of the last successful flush.
+    last_flush_ms: AtomicU64,
+    /// End-to-end latency of the oldest record of the last flush.
+    latency_ms: AtomicU64,
+}
+
+impl Default for SinkLag {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SinkLag {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(SinkLagInner {
+                origin: Instant::now(),
+                last_flush_ms: AtomicU64::new(0),
+                latency_ms: AtomicU64::new(0),
+            }),
+        }
+    }
+
+    /// Record a successful flush whose oldest record was received at `oldest_received`.
+    pub fn record_flush(&self, oldest_received: SystemTime) {
+        let latency = SystemTime::now()
+            .duration_since(oldest_received)
+            .unwrap_or_default();
+        self.inner
+            .latency_ms
+            .store(latency.as_millis() as u64, Ordering::Relaxed);
+        self.inner
+            .last_flush_ms
+            .store(self.inner.origin.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Current lag.
+    ///
+    /// With records still queued (`backlogged`) this is the latency of the last
+    /// flush or the time since it, whichever is larger, so a stalled sink shows a
+    /// growing lag. Without, the sink has caught up and the lag is zero.
+    pub fn current(&self, backlogged: bool) -> Duration {
+        if !backlogged {
+            return Duration::ZERO;
+        }
+        let latency = self.inner.latency_ms.load(Ordering::Relaxed);
+        let since_flush = (self.inner.origin.elapsed().as_millis() as u64)
+            .saturating_sub(self.inner.last_flush_ms.load(Ordering::Relaxed));
+        Duration::from_millis(latency.max(since_flush))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lag_is_flush_latency_while_backlogged() {
+        let lag = SinkLag::new();
+        lag.record_flush(SystemTime::now() - Duration::from_secs(30));
+
+        assert!(lag.current(true) >= Duration::from_secs(30));
+        assert_eq!(lag.current(false), Duration::ZERO);
+    }
+}
@@ -0,0 +1,118 @@
+use futures::Stream;
+use tokio::sync::mpsc;
+
+/// Priority of a record (or of the request that carried it).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Priority {
+    /// Live reads; never shed for lag and drained first.
+    RealTime,
+    /// Backfills and replays; shed first when the sink falls behind.
+    Bulk,
+}
+
+/// Create a real-time and a bulk lane with their own capacities.
+pub fn lanes<T>(realtime_capacity: usize, bulk_capacity: usize) -> (LaneSender<T>, LaneReceiver<T>) {
+    let (realtime_tx, realtime_rx) = mpsc::channel(realtime_capacity);
+    let (bulk_tx, bulk_rx) = mpsc::channel(bulk_capacity);
+    (
+        LaneSender::new(realtime_tx, bulk_tx),
+        LaneReceiver {
+            realtime: realtime_rx,
+            bulk: bulk_rx,
+        },
+    )
+}
+
+/// Sending side of a pair of priority lanes.
+#[derive(Debug)]
+pub struct LaneSender<T> {
+    realtime: mpsc::Sender<T>,
+    bulk: mpsc::Sender<T>,
+}
+
+impl<T> Clone for LaneSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            realtime: self.realtime.clone(),
+            bulk: self.bulk.clone(),
+        }
+    }
+}
+
+impl<T> LaneSender<T> {
+    pub fn new(realtime: mpsc::Sender<T>, bulk: mpsc::Sender<T>) -> Self {
+        Self { realtime, bulk }
+    }
+
+    /// The channel records of `priority` are sent on.
+    pub fn lane(&self, priority: Priority) -> &mpsc::Sender<T> {
+        match priority {
+            Priority::RealTime => &self.realtime,
+            Priority::Bulk => &self.bulk,
+        }
+    }
+
+    /// Whether any record is waiting in either lane.
+    pub fn is_backlogged(&self) -> bool {
+        [&self.realtime, &self.bulk]
+            .into_iter()
+            .any(|tx| tx.capacity() < tx.max_capacity())
+    }
+}
+
+/// Receiving side of a pair of priority lanes.
+#[derive(Debug)]
+pub struct LaneReceiver<T> {
+    realtime: mpsc::Receiver<T>,
+    bulk: mpsc::Receiver<T>,
+}
+
+impl<T: Send + 'static> LaneReceiver<T> {
+    /// Merge both lanes into one stream.
+    ///
+    /// Real-time records are preferred, but while both lanes have data one bulk
+    /// record is taken after every `realtime_weight` real-time ones, so bulk
+    /// traffic keeps moving without delaying live reads. Ends once both lanes
+    /// are closed and drained.
+    pub fn into_stream(self, realtime_weight: u32) -> impl Stream<Item = T> + Send {
+        let weight = realtime_weight.max(1);
+        futures::stream::unfold((self, 0u32), move |(mut lanes, mut streak)| async move {
+            if streak >= weight {
+                if let Ok(item) = lanes.bulk.try_recv() {
+                    return Some((item, (lanes, 0)));
+                }
+            }
+
+            tokio::select! {
+                biased;
+                Some(item) = lanes.realtime.recv() => {
+                    streak = streak.saturating_add(1);
+                    Some((item, (lanes, streak)))
+                }
+                Some(item) = lanes.bulk.recv() => Some((item, (lanes, 0))),
+                else => None,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn drains_bulk_at_the_configured_weight() {
+        let (tx, rx) = lanes(16, 16);
+        for i in 0..6 {
+            tx.lane(Priority::RealTime).try_send(format!("r{i}")).unwrap();
+        }
+        for i in 0..3 {
+            tx.lane(Priority::Bulk).try_send(format!("b{i}")).unwrap();
+        }
+        drop(tx);
+
+        let order: Vec<String> = rx.into_stream(3).collect().await;
+        assert_eq!(order, ["r0", "r1", "r2", "b0", "r3", "r4", "r5", "b1", "b2"]);
+    }
+}
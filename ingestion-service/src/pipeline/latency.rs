@@ -0,0 +1,78 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::SystemTime,
+};
+
+use super::Envelope;
+
+/// Samples per-record end-to-end latency (source receive to sink flush) of one pipeline.
+///
+/// Every `every`-th flushed record is recorded in the
+/// `ingest_record_latency_seconds{pipeline}` histogram and logged at debug
+/// level. `every = 0` disables sampling. Clones share the same counter.
+#[derive(Debug, Clone)]
+pub struct LatencySampler {
+    pipeline: String,
+    every: u64,
+    seen: Arc<AtomicU64>,
+}
+
+impl LatencySampler {
+    pub fn new(pipeline: impl Into<String>, every: u64) -> Self {
+        Self {
+            pipeline: pipeline.into(),
+            every,
+            seen: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Record the sampled records of a batch that was just flushed.
+    pub fn record<T>(&self, batch: &[Envelope<T>]) {
+        let now = SystemTime::now();
+        for env in self.sample(batch) {
+            let latency = now.duration_since(env.received_at).unwrap_or_default();
+            metrics::histogram!("ingest_record_latency_seconds", "pipeline" => self.pipeline.clone())
+                .record(latency.as_secs_f64());
+            tracing::debug!(
+                pipeline = %self.pipeline,
+                latency_ms = latency.as_millis() as u64,
+                "sampled record latency"
+            );
+        }
+    }
+
+    fn sample<'a, T>(&self, batch: &'a [Envelope<T>]) -> impl Iterator<Item = &'a Envelope<T>> {
+        let every = self.every;
+        // Skip whole batch without touching the counter when disabled.
+        let (first, step) = if every == 0 || batch.is_empty() {
+            (batch.len(), 1)
+        } else {
+            let start = self.seen.fetch_add(batch.len() as u64, Ordering::Relaxed);
+            (((every - start % every) % every) as usize, every as usize)
+        };
+        batch.iter().skip(first).step_by(step)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_every_nth_record_across_batches() {
+        let sampler = LatencySampler::new("meter_usage", 3);
+        let batch: Vec<Envelope<u32>> = (0..4).map(Envelope::new).collect();
+
+        let first: Vec<u32> = sampler.sample(&batch).map(|e| e.payload).collect();
+        let second: Vec<u32> = sampler.sample(&batch).map(|e| e.payload).collect();
+        // Records 0..8 overall: 0, 3 and 6 are sampled.
+        assert_eq!(first, [0, 3]);
+        assert_eq!(second, [2]);
+
+        let disabled = LatencySampler::new("meter_usage", 0);
+        assert_eq!(disabled.sample(&batch).count(), 0);
+    }
+}
@@ -1,11 +1,264 @@
-use std::{pin::Pin, sync::Arc, time::SystemTime};
+use std::{
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Instant, SystemTime},
+};
 
 use futures::{Stream, StreamExt};
+use tokio::sync::{oneshot, watch};
 
+mod blocking;
+mod concurrency;
+mod lag;
+mod lanes;
+mod latency;
+
+pub use blocking::Blocking;
+pub use concurrency::{Concurrency, WithConcurrency};
+pub use lag::SinkLag;
+pub use latency::LatencySampler;
+pub use lanes::{lanes, LaneReceiver, LaneSender, Priority};
+
+/// A record flowing through a pipeline.
+///
+/// Delivery is at-least-once. Producers that must acknowledge upstream only
+/// after a durable write (Kafka offsets, AMQP acks, synchronous HTTP) attach a
+/// [`Completion`]; sinks call [`Envelope::complete`] after a successful flush.
+/// A record rejected by a transform reports [`AckOutcome::Rejected`], and an
+/// envelope that is dropped otherwise (lost on a failed flush) reports
+/// [`AckOutcome::Dropped`].
 #[derive(Debug, Clone)]
 pub struct Envelope<T> {
     pub payload: T,
     pub received_at: SystemTime,
+    /// Set when the producer waits for the record to be durably written.
+    pub completion: Option<Completion>,
+    /// Tenant of the API key the record was sent with, for tenant-scoped keys.
+    /// Sinks store it in the table's `tenant` column.
+    pub tenant: Option<Arc<str>>,
+    /// Common name of the verified client certificate the record was sent
+    /// with, for sources serving mutual TLS.
+    pub source_identity: Option<Arc<str>>,
+}
+
+impl<T> Envelope<T> {
+    /// An untracked envelope received now.
+    pub fn new(payload: T) -> Self {
+        Self {
+            payload,
+            received_at: SystemTime::now(),
+            completion: None,
+            tenant: None,
+            source_identity: None,
+        }
+    }
+
+    /// An envelope received now whose write is reported through `completion`.
+    pub fn tracked(payload: T, completion: Completion) -> Self {
+        Self {
+            payload,
+            received_at: SystemTime::now(),
+            completion: Some(completion),
+            tenant: None,
+            source_identity: None,
+        }
+    }
+
+    /// Attribute this record to the tenant of the key that sent it.
+    pub fn with_tenant(mut self, tenant: Option<Arc<str>>) -> Self {
+        self.tenant = tenant;
+        self
+    }
+
+    /// Attribute this record to the client certificate it was sent with.
+    pub fn with_source_identity(mut self, identity: Option<Arc<str>>) -> Self {
+        self.source_identity = identity;
+        self
+    }
+
+    /// Mark this record as durably written. Sinks call this after a successful flush.
+    pub fn complete(&self) {
+        if let Some(c) = &self.completion {
+            c.complete();
+        }
+    }
+
+    /// Report that this record will not be written, without waiting for it to be dropped.
+    pub fn fail(&self) {
+        if let Some(c) = &self.completion {
+            c.fail();
+        }
+    }
+}
+
+/// Final state of a tracked envelope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AckOutcome {
+    /// The sink flushed the record.
+    Written,
+    /// A transform rejected the record (e.g. failed validation).
+    Rejected,
+    /// The record was lost before it could be written.
+    Dropped,
+}
+
+/// Receiving side of a per-envelope [`Completion`] created by [`Completion::oneshot`].
+#[derive(Debug)]
+pub struct AckReceiver(oneshot::Receiver<AckOutcome>);
+
+impl AckReceiver {
+    /// Wait for the envelope to be written or dropped.
+    pub async fn outcome(self) -> AckOutcome {
+        self.0.await.unwrap_or(AckOutcome::Dropped)
+    }
+}
+
+/// Tracks durable completion of a group of envelopes (e.g. one HTTP request).
+#[derive(Debug, Clone)]
+pub struct CompletionGroup {
+    pending: Arc<watch::Sender<usize>>,
+    failed: Arc<AtomicBool>,
+    lost: Arc<AtomicBool>,
+}
+
+impl Default for CompletionGroup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CompletionGroup {
+    pub fn new() -> Self {
+        Self {
+            pending: Arc::new(watch::channel(0).0),
+            failed: Arc::new(AtomicBool::new(false)),
+            lost: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    /// Register one more envelope with the group.
+    pub fn track(&self) -> Completion {
+        self.pending.send_modify(|n| *n += 1);
+        Completion::new(CompletionTarget::Group(self.clone()))
+    }
+
+    /// Wait until every tracked envelope was completed or dropped.
+    ///
+    /// Returns `false` if any envelope was not written (rejected by a
+    /// transform or lost on a failed sink flush).
+    pub async fn wait(&self) -> bool {
+        self.drained().await;
+        !self.failed.load(Ordering::SeqCst)
+    }
+
+    /// Like [`CompletionGroup::wait`], but rejected envelopes count as settled:
+    /// returns `false` only if an envelope was lost.
+    pub async fn wait_settled(&self) -> bool {
+        self.drained().await;
+        !self.lost.load(Ordering::SeqCst)
+    }
+
+    async fn drained(&self) {
+        let mut rx = self.pending.subscribe();
+        // The sender lives in `self`, so this can't fail.
+        let _ = rx.wait_for(|n| *n == 0).await;
+    }
+
+    fn finish(&self, outcome: AckOutcome) {
+        if outcome != AckOutcome::Written {
+            self.failed.store(true, Ordering::SeqCst);
+        }
+        if outcome == AckOutcome::Dropped {
+            self.lost.store(true, Ordering::SeqCst);
+        }
+        self.pending.send_modify(|n| *n = n.saturating_sub(1));
+    }
+}
+
+#[derive(Debug)]
+enum CompletionTarget {
+    Group(CompletionGroup),
+    Oneshot(Mutex<Option<oneshot::Sender<AckOutcome>>>),
+    /// Copy of a oneshot-tracked envelope; only the original reports.
+    Detached,
+}
+
+/// Per-envelope completion guard.
+///
+/// Reports exactly once: [`Completion::complete`], [`Completion::reject`],
+/// [`Completion::fail`], or [`AckOutcome::Dropped`] when dropped without any.
+#[derive(Debug)]
+pub struct Completion {
+    target: CompletionTarget,
+    done: AtomicBool,
+}
+
+impl Completion {
+    fn new(target: CompletionTarget) -> Self {
+        Self {
+            target,
+            done: AtomicBool::new(false),
+        }
+    }
+
+    /// A completion for a single envelope, e.g. one Kafka message or AMQP delivery.
+    pub fn oneshot() -> (Self, AckReceiver) {
+        let (tx, rx) = oneshot::channel();
+        (
+            Self::new(CompletionTarget::Oneshot(Mutex::new(Some(tx)))),
+            AckReceiver(rx),
+        )
+    }
+
+    pub fn complete(&self) {
+        self.finish(AckOutcome::Written);
+    }
+
+    /// Report that a transform rejected the record. [`Pipeline::run`] calls this.
+    pub fn reject(&self) {
+        self.finish(AckOutcome::Rejected);
+    }
+
+    pub fn fail(&self) {
+        self.finish(AckOutcome::Dropped);
+    }
+
+    fn finish(&self, outcome: AckOutcome) {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        match &self.target {
+            CompletionTarget::Group(group) => group.finish(outcome),
+            CompletionTarget::Oneshot(tx) => {
+                let tx = tx.lock().unwrap_or_else(|e| e.into_inner()).take();
+                if let Some(tx) = tx {
+                    let _ = tx.send(outcome);
+                }
+            }
+            CompletionTarget::Detached => {}
+        }
+    }
+}
+
+impl Clone for Completion {
+    /// A cloned envelope in a group is tracked separately and must be completed
+    /// on its own. A clone of a oneshot completion is detached: only the
+    /// original envelope reports to the producer.
+    fn clone(&self) -> Self {
+        match &self.target {
+            CompletionTarget::Group(group) => group.track(),
+            CompletionTarget::Oneshot(_) | CompletionTarget::Detached => Self::new(CompletionTarget::Detached),
+        }
+    }
+}
+
+impl Drop for Completion {
+    fn drop(&mut self) {
+        self.finish(AckOutcome::Dropped);
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -18,6 +271,11 @@ pub enum PipelineError {
     Sink(String),
 }
 
+/// Produces envelopes for a pipeline.
+///
+/// Sources that acknowledge upstream (message brokers, synchronous HTTP)
+/// should attach a [`Completion`] and ack only once it reports
+/// [`AckOutcome::Written`]; this gives at-least-once delivery end-to-end.
 #[async_trait::async_trait]
 pub trait Source<T>: Send + Sync {
     async fn stream(
@@ -28,6 +286,16 @@ pub trait Source<T>: Send + Sync {
 #[async_trait::async_trait]
 pub trait Transform<I, O>: Send + Sync {
     async fn apply(&self, input: Envelope<I>) -> Result<Envelope<O>, PipelineError>;
+
+    /// Stage name, used as the `stage` label of the per-stage metrics.
+    fn name(&self) -> &'static str {
+        "transform"
+    }
+
+    /// How many records the pipeline may run through this stage at once; see [`WithConcurrency`].
+    fn concurrency(&self) -> Concurrency {
+        Concurrency::Sequential
+    }
 }
 
 #[async_trait::async_trait]
@@ -37,6 +305,24 @@ pub trait Sink<T>: Send + Sync {
         S: Stream<Item = Result<Envelope<T>, PipelineError>> + Send + Unpin + 'static;
 }
 
+struct StageMetrics {
+    records_in: metrics::Counter,
+    records_out: metrics::Counter,
+    records_dropped: metrics::Counter,
+    seconds: metrics::Histogram,
+}
+
+impl StageMetrics {
+    fn new(stage: &'static str) -> Self {
+        Self {
+            records_in: metrics::counter!("pipeline_stage_records_in_total", "stage" => stage),
+            records_out: metrics::counter!("pipeline_stage_records_out_total", "stage" => stage),
+            records_dropped: metrics::counter!("pipeline_stage_records_dropped_total", "stage" => stage),
+            seconds: metrics::histogram!("pipeline_stage_seconds", "stage" => stage),
+        }
+    }
+}
+
 pub struct Pipeline<S, T, K> {
     pub source: S,
     pub transforms: Vec<Arc<dyn Transform<T, T> + Send + Sync>>, // same-type transforms chain
@@ -49,23 +335,314 @@ where
     S: Source<T> + Send + Sync + 'static,
     K: Sink<T> + Send + Sync + 'static,
 {
+    /// Run the pipeline until the source ends.
+    ///
+    /// Each transform stage reports `pipeline_stage_records_{in,out,dropped}_total`
+    /// and `pipeline_stage_seconds` labeled by [`Transform::name`]. Errors from
+    /// earlier stages pass through uncounted, so `dropped` is what the stage
+    /// itself rejected. Tracked records a stage rejects report
+    /// [`AckOutcome::Rejected`].
     pub async fn run(self) -> Result<(), PipelineError> {
         let mut stream = self.source.stream().await;
 
-        // Apply transforms in sequence (if any).
+        // Chain transforms (if any); each stage may run several records at once.
         for t in self.transforms {
-            let t_arc = t.clone();
-            stream = Box::pin(stream.then(move |item| {
-                let t_inner = t_arc.clone();
+            let concurrency = t.concurrency();
+            let metrics = Arc::new(StageMetrics::new(t.name()));
+            let apply = move |item: Result<Envelope<T>, PipelineError>| {
+                let t_inner = t.clone();
+                let metrics = metrics.clone();
                 async move {
                     match item {
-                        Ok(env) => t_inner.apply(env).await,
+                        Ok(mut env) => {
+                            metrics.records_in.increment(1);
+                            // Held here so a rejected record can report why it wasn't written.
+                            let completion = env.completion.take();
+                            let started = Instant::now();
+                            let result = t_inner.apply(env).await;
+                            metrics.seconds.record(started.elapsed().as_secs_f64());
+                            match result {
+                                Ok(mut env) => {
+                                    metrics.records_out.increment(1);
+                                    env.completion = env.completion.or(completion);
+                                    Ok(env)
+                                }
+                                Err(e) => {
+                                    metrics.records_dropped.increment(1);
+                                    if let Some(c) = completion {
+                                        c.reject();
+                                    }
+                                    Err(e)
+                                }
+                            }
+                        }
                         Err(e) => Err(e),
                     }
                 }
-            }));
+            };
+            stream = match concurrency {
+                Concurrency::Sequential => Box::pin(stream.then(apply)),
+                Concurrency::Ordered(n) => Box::pin(stream.map(apply).buffered(n)),
+                Concurrency::Unordered(n) => Box::pin(stream.map(apply).buffer_unordered(n)),
+            };
         }
 
         self.sink.run(stream).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracked(group: &CompletionGroup) -> Envelope<()> {
+        Envelope {
+            payload: (),
+            received_at: SystemTime::now(),
+            completion: Some(group.track()),
+            tenant: None,
+            source_identity: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn completion_group_succeeds_when_all_envelopes_complete() {
+        let group = CompletionGroup::new();
+        let a = tracked(&group);
+        let b = tracked(&group);
+
+        a.complete();
+        b.complete();
+        drop((a, b));
+
+        assert!(group.wait().await);
+    }
+
+    #[tokio::test]
+    async fn completion_group_fails_when_envelope_is_dropped() {
+        let group = CompletionGroup::new();
+        let a = tracked(&group);
+        let b = tracked(&group);
+
+        a.complete();
+        drop(b);
+
+        assert!(!group.wait().await);
+    }
+
+    #[tokio::test]
+    async fn completion_group_settles_rejected_but_not_lost_envelopes() {
+        let rejected = CompletionGroup::new();
+        let a = tracked(&rejected);
+        a.complete();
+        rejected.track().reject();
+        drop(a);
+        assert!(!rejected.wait().await);
+        assert!(rejected.wait_settled().await);
+
+        let lost = CompletionGroup::new();
+        drop(tracked(&lost));
+        assert!(!lost.wait_settled().await);
+    }
+
+    #[tokio::test]
+    async fn oneshot_completion_reports_written() {
+        let (completion, ack) = Completion::oneshot();
+        let env = Envelope::tracked((), completion);
+
+        env.complete();
+        drop(env);
+
+        assert_eq!(ack.outcome().await, AckOutcome::Written);
+    }
+
+    #[tokio::test]
+    async fn oneshot_completion_reports_dropped_and_ignores_clones() {
+        let (completion, ack) = Completion::oneshot();
+        let env = Envelope::tracked((), completion);
+
+        // Completing a copy must not ack the original.
+        let copy = env.clone();
+        copy.complete();
+        drop(env);
+
+        assert_eq!(ack.outcome().await, AckOutcome::Dropped);
+    }
+
+    #[tokio::test]
+    async fn explicit_fail_wins_over_later_complete() {
+        let (completion, ack) = Completion::oneshot();
+        let env = Envelope::tracked((), completion);
+
+        env.fail();
+        env.complete();
+
+        assert_eq!(ack.outcome().await, AckOutcome::Dropped);
+    }
+
+    struct VecSource(Mutex<Option<Vec<u64>>>);
+
+    #[async_trait::async_trait]
+    impl Source<u64> for VecSource {
+        async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<u64>, PipelineError>> + Send>> {
+            let items = self.0.lock().unwrap().take().unwrap_or_default();
+            Box::pin(futures::stream::iter(items.into_iter().map(|v| Ok(Envelope::new(v)))))
+        }
+    }
+
+    /// Sleeps longer for smaller values, so unordered output comes back reversed.
+    struct SlowLookup;
+
+    #[async_trait::async_trait]
+    impl Transform<u64, u64> for SlowLookup {
+        async fn apply(&self, input: Envelope<u64>) -> Result<Envelope<u64>, PipelineError> {
+            tokio::time::sleep(std::time::Duration::from_millis(50 * (5 - input.payload))).await;
+            Ok(input)
+        }
+    }
+
+    struct CollectSink(Arc<Mutex<Vec<u64>>>);
+
+    #[async_trait::async_trait]
+    impl Sink<u64> for CollectSink {
+        async fn run<St>(&self, mut input: St) -> Result<(), PipelineError>
+        where
+            St: Stream<Item = Result<Envelope<u64>, PipelineError>> + Send + Unpin + 'static,
+        {
+            while let Some(item) = input.next().await {
+                self.0.lock().unwrap().push(item?.payload);
+            }
+            Ok(())
+        }
+    }
+
+    async fn run_with(concurrency: Concurrency) -> (Vec<u64>, std::time::Duration) {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = Pipeline {
+            source: VecSource(Mutex::new(Some(vec![1, 2, 3, 4]))),
+            transforms: vec![Arc::new(WithConcurrency::new(Arc::new(SlowLookup), concurrency))],
+            sink: CollectSink(out.clone()),
+        };
+        let started = std::time::Instant::now();
+        pipeline.run().await.unwrap();
+        let elapsed = started.elapsed();
+        let out = out.lock().unwrap().clone();
+        (out, elapsed)
+    }
+
+    #[test]
+    fn wrappers_keep_the_stage_name() {
+        let stage = Blocking::new(Arc::new(crate::transform::MeterUsageValidation), 2);
+        let stage = WithConcurrency::new(Arc::new(stage), Concurrency::Ordered(2));
+        assert_eq!(stage.name(), "meter_usage_validation");
+        assert_eq!(SlowLookup.name(), "transform");
+    }
+
+    /// Burns 50 ms of a thread, like a CPU-bound parser would.
+    struct BusyParse;
+
+    #[async_trait::async_trait]
+    impl Transform<u64, u64> for BusyParse {
+        async fn apply(&self, input: Envelope<u64>) -> Result<Envelope<u64>, PipelineError> {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            Ok(input)
+        }
+    }
+
+    #[tokio::test]
+    async fn blocking_stages_run_off_the_reactor() {
+        let out = Arc::new(Mutex::new(Vec::new()));
+        let stage = Blocking::new(Arc::new(BusyParse), 4);
+        let pipeline = Pipeline {
+            source: VecSource(Mutex::new(Some(vec![1, 2, 3, 4]))),
+            transforms: vec![Arc::new(WithConcurrency::new(Arc::new(stage), Concurrency::Ordered(4)))],
+            sink: CollectSink(out.clone()),
+        };
+        // The test runtime has a single thread; ticks only get through if the stage doesn't hold it.
+        let ticker = tokio::spawn(async {
+            let mut ticks = 0;
+            let mut interval = tokio::time::interval(std::time::Duration::from_millis(5));
+            loop {
+                interval.tick().await;
+                ticks += 1;
+                if ticks == 10 {
+                    return std::time::Instant::now();
+                }
+            }
+        });
+
+        let started = std::time::Instant::now();
+        pipeline.run().await.unwrap();
+        let elapsed = started.elapsed();
+        assert_eq!(*out.lock().unwrap(), [1, 2, 3, 4]);
+        assert!(elapsed < std::time::Duration::from_millis(150), "{elapsed:?}");
+        let ticked_at = ticker.await.unwrap();
+        assert!(ticked_at.duration_since(started) < std::time::Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn concurrent_stages_overlap_records() {
+        // Sequentially the four lookups take 200+150+100+50 ms.
+        let (out, elapsed) = run_with(Concurrency::Ordered(4)).await;
+        assert_eq!(out, [1, 2, 3, 4]);
+        assert!(elapsed < std::time::Duration::from_millis(400), "{elapsed:?}");
+
+        let (out, _) = run_with(Concurrency::Unordered(4)).await;
+        assert_eq!(out, [4, 3, 2, 1]);
+    }
+
+    struct RejectOdd;
+
+    #[async_trait::async_trait]
+    impl Transform<u64, u64> for RejectOdd {
+        async fn apply(&self, input: Envelope<u64>) -> Result<Envelope<u64>, PipelineError> {
+            if input.payload % 2 == 1 {
+                return Err(PipelineError::Transform("odd".to_string()));
+            }
+            Ok(input)
+        }
+    }
+
+    struct TrackedSource(Mutex<Vec<Envelope<u64>>>);
+
+    #[async_trait::async_trait]
+    impl Source<u64> for TrackedSource {
+        async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<u64>, PipelineError>> + Send>> {
+            let items = std::mem::take(&mut *self.0.lock().unwrap());
+            Box::pin(futures::stream::iter(items.into_iter().map(Ok)))
+        }
+    }
+
+    /// Completes every record it receives.
+    struct AckSink;
+
+    #[async_trait::async_trait]
+    impl Sink<u64> for AckSink {
+        async fn run<St>(&self, mut input: St) -> Result<(), PipelineError>
+        where
+            St: Stream<Item = Result<Envelope<u64>, PipelineError>> + Send + Unpin + 'static,
+        {
+            while let Some(item) = input.next().await {
+                if let Ok(env) = item {
+                    env.complete();
+                }
+            }
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn records_rejected_by_a_transform_report_rejected() {
+        let (c1, ack1) = Completion::oneshot();
+        let (c2, ack2) = Completion::oneshot();
+        let pipeline = Pipeline {
+            source: TrackedSource(Mutex::new(vec![Envelope::tracked(1, c1), Envelope::tracked(2, c2)])),
+            transforms: vec![Arc::new(RejectOdd)],
+            sink: AckSink,
+        };
+        pipeline.run().await.unwrap();
+
+        assert_eq!(ack1.outcome().await, AckOutcome::Rejected);
+        assert_eq!(ack2.outcome().await, AckOutcome::Written);
+    }
+}
@@ -0,0 +1,262 @@
+use std::{marker::PhantomData, time::SystemTime};
+
+use bytes::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::{header, Method, Request, StatusCode};
+use hyper_util::{
+    client::legacy::{connect::HttpConnector, Client},
+    rt::TokioExecutor,
+};
+use rust_client::domain::{GenerationOutput, MeterUsage, PhaseChannels};
+use serde_json::{json, Map, Value};
+use time::{format_description::FormatItem, macros::format_description, OffsetDateTime, UtcOffset};
+
+use crate::{
+    config::ClickHouseConfig,
+    pipeline::{Envelope, PipelineError},
+};
+
+use super::store::TimeSeriesStore;
+
+/// `DateTime64(6)` text format accepted by ClickHouse's default input parser.
+const CLICKHOUSE_TS: &[FormatItem<'static>] =
+    format_description!("[year]-[month]-[day] [hour]:[minute]:[second].[subsecond digits:6]");
+
+fn format_ts(ts: OffsetDateTime) -> String {
+    ts.to_offset(UtcOffset::UTC)
+        .format(CLICKHOUSE_TS)
+        .expect("fixed format description")
+}
+
+/// A record type that can be written to ClickHouse as one `JSONEachRow` row.
+pub trait ClickHouseRow {
+    /// Unqualified table name.
+    const TABLE: &'static str;
+
+    /// `CREATE TABLE IF NOT EXISTS` statement for `database`.
+    fn ddl(database: &str) -> String;
+
+    /// One row, with the time the record was received and the tenant of the
+    /// key that sent it where the table has them.
+    fn to_row(&self, received_at: SystemTime, tenant: Option<&str>) -> Map<String, Value>;
+}
+
+impl ClickHouseRow for MeterUsage {
+    const TABLE: &'static str = "meter_usage";
+
+    fn ddl(database: &str) -> String {
+        let phases: String = PhaseChannels::COLUMNS
+            .iter()
+            .map(|c| format!(", {c} Nullable(Float64)"))
+            .collect();
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{database}`.meter_usage (\
+             ts DateTime64(6, 'UTC'), event_id Nullable(String), meter_id LowCardinality(String), \
+             premise_id Nullable(String), kwh Float64, kvarh Nullable(Float64), kva_demand Nullable(Float64), \
+             quality_flag LowCardinality(Nullable(String)), source_system LowCardinality(Nullable(String)), \
+             tenant LowCardinality(Nullable(String)), ingested_at DateTime64(6, 'UTC'){phases}\
+             ) ENGINE = ReplacingMergeTree(ingested_at) PARTITION BY toYYYYMM(ts) ORDER BY (meter_id, ts)"
+        )
+    }
+
+    fn to_row(&self, received_at: SystemTime, tenant: Option<&str>) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("ts".into(), json!(format_ts(self.ts)));
+        row.insert("event_id".into(), json!(self.event_id));
+        row.insert("meter_id".into(), json!(self.meter_id));
+        row.insert("premise_id".into(), json!(self.premise_id));
+        row.insert("kwh".into(), json!(self.kwh));
+        row.insert("kvarh".into(), json!(self.kvarh));
+        row.insert("kva_demand".into(), json!(self.kva_demand));
+        row.insert("quality_flag".into(), json!(self.quality_flag));
+        row.insert("source_system".into(), json!(self.source_system));
+        row.insert("tenant".into(), json!(tenant));
+        row.insert("ingested_at".into(), json!(format_ts(received_at.into())));
+        for (column, value) in PhaseChannels::COLUMNS.into_iter().zip(self.phases.values()) {
+            row.insert(column.into(), json!(value));
+        }
+        row
+    }
+}
+
+impl ClickHouseRow for GenerationOutput {
+    const TABLE: &'static str = "generation_output";
+
+    fn ddl(database: &str) -> String {
+        format!(
+            "CREATE TABLE IF NOT EXISTS `{database}`.generation_output (\
+             ts DateTime64(6, 'UTC'), event_id Nullable(String), plant_id LowCardinality(String), \
+             unit_id LowCardinality(String) DEFAULT '', mw Float64, mvar Nullable(Float64), \
+             status LowCardinality(Nullable(String)), fuel_type LowCardinality(Nullable(String)), \
+             aux_mw Nullable(Float64), availability_pct Nullable(Float64), curtailed_mw Nullable(Float64)\
+             ) ENGINE = ReplacingMergeTree PARTITION BY toYYYYMM(ts) ORDER BY (plant_id, unit_id, ts)"
+        )
+    }
+
+    fn to_row(&self, _received_at: SystemTime, _tenant: Option<&str>) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("ts".into(), json!(format_ts(self.ts)));
+        row.insert("event_id".into(), json!(self.event_id));
+        row.insert("plant_id".into(), json!(self.plant_id));
+        row.insert("unit_id".into(), json!(self.unit_id.as_deref().unwrap_or_default()));
+        row.insert("mw".into(), json!(self.mw));
+        row.insert("mvar".into(), json!(self.mvar));
+        row.insert("status".into(), json!(self.status));
+        row.insert("fuel_type".into(), json!(self.fuel_type));
+        row.insert("aux_mw".into(), json!(self.aux_mw));
+        row.insert("availability_pct".into(), json!(self.availability_pct));
+        row.insert("curtailed_mw".into(), json!(self.curtailed_mw));
+        row
+    }
+}
+
+/// ClickHouse over its HTTP interface.
+///
+/// Batches are sent as one `INSERT ... FORMAT JSONEachRow` request. Tables
+/// use `ReplacingMergeTree` keyed on the natural key, so rows re-sent by a
+/// retried batch collapse on merge (query with `FINAL` for exact results).
+pub struct ClickHouseStore<T> {
+    client: Client<HttpConnector, Full<Bytes>>,
+    url: String,
+    database: String,
+    user: Option<String>,
+    password: Option<String>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> ClickHouseStore<T> {
+    pub fn from_config(cfg: &ClickHouseConfig) -> Self {
+        Self {
+            client: Client::builder(TokioExecutor::new()).build_http(),
+            url: cfg.url.trim_end_matches('/').to_string(),
+            database: cfg.database.clone(),
+            user: cfg.user.clone(),
+            password: cfg.password.clone(),
+            _marker: PhantomData,
+        }
+    }
+
+    async fn request(&self, method: Method, path: &str, body: String) -> Result<(), PipelineError> {
+        let mut req = Request::builder().method(method).uri(format!("{}{path}", self.url));
+        if let Some(user) = &self.user {
+            req = req.header("X-ClickHouse-User", user);
+        }
+        if let Some(password) = &self.password {
+            req = req.header("X-ClickHouse-Key", password);
+        }
+        let req = req
+            .header(header::CONTENT_TYPE, "text/plain; charset=utf-8")
+            .body(Full::new(Bytes::from(body)))
+            .map_err(|e| PipelineError::Sink(format!("clickhouse request: {e}")))?;
+
+        let resp = self
+            .client
+            .request(req)
+            .await
+            .map_err(|e| PipelineError::Sink(format!("clickhouse request failed: {e}")))?;
+        let status = resp.status();
+        if status == StatusCode::OK {
+            return Ok(());
+        }
+        let body = resp
+            .into_body()
+            .collect()
+            .await
+            .map(|b| String::from_utf8_lossy(&b.to_bytes()).trim().to_string())
+            .unwrap_or_default();
+        Err(PipelineError::Sink(format!("clickhouse returned {status}: {body}")))
+    }
+}
+
+/// Request body for one batch: the `INSERT` statement followed by NDJSON rows.
+fn insert_body<T: ClickHouseRow>(database: &str, batch: &[Envelope<T>]) -> String {
+    let mut body = format!("INSERT INTO `{database}`.{} FORMAT JSONEachRow\n", T::TABLE);
+    for env in batch {
+        let row = env.payload.to_row(env.received_at, env.tenant.as_deref());
+        body.push_str(&Value::Object(row).to_string());
+        body.push('\n');
+    }
+    body
+}
+
+#[async_trait::async_trait]
+impl<T> TimeSeriesStore<T> for ClickHouseStore<T>
+where
+    T: ClickHouseRow + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "clickhouse"
+    }
+
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        self.request(Method::POST, "/", T::ddl(&self.database)).await
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
+        self.request(Method::POST, "/", insert_body(&self.database, batch)).await
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        self.request(Method::GET, "/ping", String::new()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn insert_body_has_one_json_row_per_record() {
+        let g = GenerationOutput {
+            ts: datetime!(2024-05-01 12:00:00.5 +02:00),
+            plant_id: "P1".to_string(),
+            unit_id: None,
+            mw: 120.0,
+            mvar: None,
+            status: Some("online".to_string()),
+            fuel_type: None,
+            event_id: None,
+            aux_mw: Some(4.0),
+            availability_pct: None,
+            curtailed_mw: None,
+        };
+
+        let body = insert_body("grid", &[Envelope::new(g)]);
+        let mut lines = body.lines();
+        assert_eq!(lines.next(), Some("INSERT INTO `grid`.generation_output FORMAT JSONEachRow"));
+
+        let row: Value = serde_json::from_str(lines.next().unwrap()).unwrap();
+        assert_eq!(row["ts"], "2024-05-01 10:00:00.500000");
+        assert_eq!(row["unit_id"], "");
+        assert_eq!(row["aux_mw"], 4.0);
+        assert!(row["mvar"].is_null());
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn meter_usage_rows_carry_the_tenant() {
+        let m = MeterUsage {
+            ts: datetime!(2024-05-01 00:15:00 UTC),
+            meter_id: "M1".to_string(),
+            premise_id: None,
+            kwh: 1.25,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some("coop-a-mdm".to_string()),
+            event_id: None,
+            phases: Default::default(),
+        };
+
+        let batch = [
+            Envelope::new(m.clone()).with_tenant(Some("co-op a".into())),
+            Envelope::new(m),
+        ];
+        let body = insert_body("grid", &batch);
+        let rows: Vec<Value> = body.lines().skip(1).map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(rows[0]["tenant"], "co-op a");
+        assert!(rows[1]["tenant"].is_null());
+        assert!(MeterUsage::ddl("grid").contains(" tenant LowCardinality(Nullable(String)),"));
+    }
+}
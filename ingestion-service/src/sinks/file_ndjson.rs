@@ -0,0 +1,333 @@
+use std::{
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use serde_json::{json, Map, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::{
+    config::FileSinkConfig,
+    pipeline::{Envelope, PipelineError},
+};
+
+use super::store::TimeSeriesStore;
+
+/// Suffix of the file currently being written.
+pub const PART_SUFFIX: &str = ".ndjson.part";
+/// Suffix of closed files, ready to be shipped.
+pub const READY_SUFFIX: &str = ".ndjson";
+
+/// A record type that can be spooled as one NDJSON line in the shape the HTTP
+/// ingest endpoints accept, so spooled files can be posted back unchanged.
+pub trait NdjsonRecord {
+    /// File name prefix, e.g. `meter_usage`; also names the ingest endpoint.
+    const KIND: &'static str;
+
+    fn to_json(&self) -> Map<String, Value>;
+}
+
+fn rfc3339(ts: OffsetDateTime) -> String {
+    ts.format(&Rfc3339).expect("RFC 3339 timestamps within year 0..=9999")
+}
+
+impl NdjsonRecord for MeterUsage {
+    const KIND: &'static str = "meter_usage";
+
+    fn to_json(&self) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("ts".into(), json!(rfc3339(self.ts)));
+        row.insert("meter_id".into(), json!(self.meter_id));
+        row.insert("premise_id".into(), json!(self.premise_id));
+        row.insert("kwh".into(), json!(self.kwh));
+        row.insert("kvarh".into(), json!(self.kvarh));
+        row.insert("kva_demand".into(), json!(self.kva_demand));
+        row.insert("quality_flag".into(), json!(self.quality_flag));
+        row.insert("source_system".into(), json!(self.source_system));
+        row.insert("event_id".into(), json!(self.event_id));
+        for (column, value) in self.phases.present() {
+            row.insert(column.into(), json!(value));
+        }
+        row
+    }
+}
+
+impl NdjsonRecord for GenerationOutput {
+    const KIND: &'static str = "generation_output";
+
+    fn to_json(&self) -> Map<String, Value> {
+        let mut row = Map::new();
+        row.insert("ts".into(), json!(rfc3339(self.ts)));
+        row.insert("plant_id".into(), json!(self.plant_id));
+        row.insert("unit_id".into(), json!(self.unit_id));
+        row.insert("mw".into(), json!(self.mw));
+        row.insert("mvar".into(), json!(self.mvar));
+        row.insert("status".into(), json!(self.status));
+        row.insert("fuel_type".into(), json!(self.fuel_type));
+        row.insert("event_id".into(), json!(self.event_id));
+        row.insert("aux_mw".into(), json!(self.aux_mw));
+        row.insert("availability_pct".into(), json!(self.availability_pct));
+        row.insert("curtailed_mw".into(), json!(self.curtailed_mw));
+        row
+    }
+}
+
+struct OpenFile {
+    file: fs::File,
+    path: PathBuf,
+    bytes: u64,
+    opened: Instant,
+}
+
+/// Spools records to rotated NDJSON files, for sites without a reachable database.
+///
+/// Records go to `<dir>/<kind>-<unix_ms>.ndjson.part`; once the file reaches
+/// `max_file_bytes` or `max_file_age_secs` (checked on the next write) it is
+/// renamed to `.ndjson` and picked up by the `ship_ndjson` job. Every batch is
+/// synced to disk before it is acknowledged. With `max_spool_bytes`, the oldest
+/// closed files are deleted on rotation to keep the spool within the cap.
+pub struct FileNdjsonStore<T> {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_file_age: Duration,
+    max_spool_bytes: Option<u64>,
+    current: tokio::sync::Mutex<Option<OpenFile>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> FileNdjsonStore<T> {
+    pub fn from_config(cfg: &FileSinkConfig) -> Self {
+        Self {
+            dir: cfg.dir.clone(),
+            max_file_bytes: cfg.max_file_bytes,
+            max_file_age: Duration::from_secs(cfg.max_file_age_secs),
+            max_spool_bytes: cfg.max_spool_bytes,
+            current: tokio::sync::Mutex::new(None),
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn io_err<'a>(context: &'a str, path: &'a Path) -> impl FnOnce(std::io::Error) -> PipelineError + 'a {
+    move |e| PipelineError::Sink(format!("file sink: {context} {}: {e}", path.display()))
+}
+
+/// Close a spool file by renaming it from `.ndjson.part` to `.ndjson`.
+async fn finalize(path: &Path) -> Result<(), PipelineError> {
+    let name = path.to_string_lossy();
+    let Some(stem) = name.strip_suffix(PART_SUFFIX) else {
+        return Ok(());
+    };
+    let ready = PathBuf::from(format!("{stem}{READY_SUFFIX}"));
+    fs::rename(path, &ready).await.map_err(io_err("rename", path))?;
+    metrics::counter!("file_sink_files_rotated_total").increment(1);
+    Ok(())
+}
+
+/// Delete the oldest closed files in `dir` until it holds at most `max_bytes`.
+///
+/// Open `.part` files count towards the total but are never deleted.
+async fn evict_oldest(dir: &Path, max_bytes: u64) -> Result<(), PipelineError> {
+    let mut total = 0;
+    let mut ready = Vec::new();
+    let mut entries = fs::read_dir(dir).await.map_err(io_err("read", dir))?;
+    while let Some(entry) = entries.next_entry().await.map_err(io_err("read", dir))? {
+        let Ok(meta) = entry.metadata().await else {
+            continue;
+        };
+        if !meta.is_file() {
+            continue;
+        }
+        total += meta.len();
+        if entry.file_name().to_string_lossy().ends_with(READY_SUFFIX) {
+            ready.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), entry.path()));
+        }
+    }
+    ready.sort();
+
+    for (_, len, path) in ready {
+        if total <= max_bytes {
+            break;
+        }
+        fs::remove_file(&path).await.map_err(io_err("evict", &path))?;
+        tracing::warn!(file = %path.display(), "spool over max_spool_bytes, deleted oldest file");
+        metrics::counter!("file_sink_evicted_files_total").increment(1);
+        total -= len;
+    }
+    Ok(())
+}
+
+impl<T: NdjsonRecord> FileNdjsonStore<T> {
+    fn prefix() -> String {
+        format!("{}-", T::KIND)
+    }
+
+    async fn open(&self) -> Result<OpenFile, PipelineError> {
+        let millis = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        // Zero-padded so lexical order is creation order.
+        let mut stem = format!("{}{millis:015}", Self::prefix());
+        let mut n = 0;
+        // Skip names of files rotated within the same millisecond too; finalizing would overwrite them.
+        while fs::try_exists(self.dir.join(format!("{stem}{PART_SUFFIX}"))).await.unwrap_or(false)
+            || fs::try_exists(self.dir.join(format!("{stem}{READY_SUFFIX}"))).await.unwrap_or(false)
+        {
+            n += 1;
+            stem = format!("{}{millis:015}.{n}", Self::prefix());
+        }
+        let path = self.dir.join(format!("{stem}{PART_SUFFIX}"));
+        let file = fs::OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(io_err("open", &path))?;
+        Ok(OpenFile {
+            file,
+            path,
+            bytes: 0,
+            opened: Instant::now(),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> TimeSeriesStore<T> for FileNdjsonStore<T>
+where
+    T: NdjsonRecord + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "file_ndjson"
+    }
+
+    /// Creates the spool directory and closes files left open by a previous run.
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        fs::create_dir_all(&self.dir).await.map_err(io_err("create", &self.dir))?;
+        let mut entries = fs::read_dir(&self.dir).await.map_err(io_err("read", &self.dir))?;
+        while let Some(entry) = entries.next_entry().await.map_err(io_err("read", &self.dir))? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with(&Self::prefix()) && name.ends_with(PART_SUFFIX) {
+                finalize(&entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
+        let mut buf = Vec::with_capacity(batch.len().saturating_mul(200));
+        for env in batch {
+            serde_json::to_writer(&mut buf, &Value::Object(env.payload.to_json()))
+                .map_err(|e| PipelineError::Sink(format!("file sink: encode: {e}")))?;
+            buf.push(b'\n');
+        }
+
+        let mut current = self.current.lock().await;
+        if let Some(open) = current.as_ref() {
+            if open.bytes >= self.max_file_bytes || open.opened.elapsed() >= self.max_file_age {
+                let open = current.take().expect("checked above");
+                finalize(&open.path).await?;
+                if let Some(max) = self.max_spool_bytes {
+                    evict_oldest(&self.dir, max).await?;
+                }
+            }
+        }
+        if current.is_none() {
+            *current = Some(self.open().await?);
+        }
+        let open = current.as_mut().expect("opened above");
+
+        let res = async {
+            open.file.write_all(&buf).await?;
+            open.file.sync_data().await
+        }
+        .await;
+        if let Err(e) = res {
+            // Start a fresh file next time; the failed one may end in a partial line.
+            let open = current.take().expect("opened above");
+            let _ = finalize(&open.path).await;
+            return Err(io_err("write", &open.path)(e));
+        }
+        open.bytes += buf.len() as u64;
+        metrics::counter!("file_sink_bytes_total").increment(buf.len() as u64);
+        Ok(())
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        fs::create_dir_all(&self.dir).await.map_err(io_err("create", &self.dir))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn usage(meter_id: &str) -> Envelope<MeterUsage> {
+        Envelope::new(MeterUsage {
+            ts: datetime!(2024-05-01 00:15:00 UTC),
+            meter_id: meter_id.to_string(),
+            premise_id: None,
+            kwh: 1.5,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some("edge".to_string()),
+            event_id: None,
+            phases: Default::default(),
+        })
+    }
+
+    async fn files(dir: &Path, suffix: &str) -> Vec<PathBuf> {
+        let mut out = Vec::new();
+        let mut entries = fs::read_dir(dir).await.unwrap();
+        while let Some(e) = entries.next_entry().await.unwrap() {
+            if e.file_name().to_string_lossy().ends_with(suffix) {
+                out.push(e.path());
+            }
+        }
+        out.sort();
+        out
+    }
+
+    #[tokio::test]
+    async fn rotates_full_files_and_finalizes_leftovers_on_start() {
+        let dir = std::env::temp_dir().join(format!("file-ndjson-{}", uuid::Uuid::now_v7()));
+        let cfg = FileSinkConfig {
+            dir: dir.clone(),
+            max_file_bytes: 1,
+            max_file_age_secs: 3600,
+            max_spool_bytes: None,
+        };
+
+        let store = FileNdjsonStore::<MeterUsage>::from_config(&cfg);
+        store.ensure_schema().await.unwrap();
+        store.insert_batch(&[usage("M1"), usage("M2")]).await.unwrap();
+        store.insert_batch(&[usage("M3")]).await.unwrap();
+
+        // The first file exceeded max_file_bytes and was closed before the second batch.
+        let ready = files(&dir, READY_SUFFIX).await;
+        assert_eq!(ready.len(), 1);
+        let lines: Vec<Value> = fs::read_to_string(&ready[0])
+            .await
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["meter_id"], "M1");
+        assert_eq!(lines[0]["ts"], "2024-05-01T00:15:00Z");
+        assert_eq!(files(&dir, PART_SUFFIX).await.len(), 1);
+
+        // A restart closes the file left open.
+        FileNdjsonStore::<MeterUsage>::from_config(&cfg).ensure_schema().await.unwrap();
+        assert_eq!(files(&dir, READY_SUFFIX).await.len(), 2);
+        assert!(files(&dir, PART_SUFFIX).await.is_empty());
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,139 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use tokio::{
+    net::TcpStream,
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+use crate::{pipeline::PipelineError, sinks::questdb_ilp::connect_ilp};
+
+struct PoolInner {
+    addr: SocketAddr,
+    permits: Arc<Semaphore>,
+    idle: Mutex<Vec<TcpStream>>,
+}
+
+/// ILP/TCP connections to one QuestDB shared by many sinks.
+///
+/// At most `max_connections` sockets are open at a time. A sink checks a
+/// connection out for one batch write and returns it afterwards, so lines of
+/// different tables and pipelines are multiplexed over the same sockets; a
+/// batch is only ever written whole, so lines never interleave. Waiting
+/// writers are served first come, first served, and no writer holds a
+/// connection for longer than one batch, so a busy pipeline cannot starve the
+/// others. Connections are opened lazily and dropped after a failed write.
+#[derive(Clone)]
+pub struct IlpConnectionPool {
+    inner: Arc<PoolInner>,
+}
+
+impl IlpConnectionPool {
+    pub fn new(addr: SocketAddr, max_connections: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                addr,
+                permits: Arc::new(Semaphore::new(max_connections.max(1))),
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.inner.addr
+    }
+
+    /// Wait for a free connection, opening one if none is idle.
+    pub async fn acquire(&self) -> Result<PooledIlpConnection, PipelineError> {
+        let started = Instant::now();
+        let permit = self
+            .inner
+            .permits
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|_| PipelineError::Sink("ILP connection pool closed".to_string()))?;
+        metrics::histogram!("questdb_ilp_pool_wait_seconds").record(started.elapsed().as_secs_f64());
+
+        let idle = self.inner.idle.lock().unwrap_or_else(|e| e.into_inner()).pop();
+        let stream = match idle {
+            Some(stream) => stream,
+            None => {
+                let stream = connect_ilp(self.inner.addr).await?;
+                metrics::gauge!("questdb_ilp_pool_connections").increment(1.0);
+                stream
+            }
+        };
+        Ok(PooledIlpConnection {
+            stream: Some(stream),
+            pool: self.inner.clone(),
+            _permit: permit,
+        })
+    }
+}
+
+/// A connection checked out of an [`IlpConnectionPool`]; returned to the pool
+/// on drop unless [`discard`](Self::discard)ed.
+pub struct PooledIlpConnection {
+    stream: Option<TcpStream>,
+    pool: Arc<PoolInner>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl PooledIlpConnection {
+    pub fn stream(&mut self) -> &mut TcpStream {
+        self.stream.as_mut().expect("stream is only taken on drop or discard")
+    }
+
+    /// Close the connection instead of returning it, e.g. after a failed write.
+    pub fn discard(mut self) {
+        if self.stream.take().is_some() {
+            metrics::gauge!("questdb_ilp_pool_connections").decrement(1.0);
+        }
+    }
+}
+
+impl Drop for PooledIlpConnection {
+    fn drop(&mut self) {
+        if let Some(stream) = self.stream.take() {
+            self.pool.idle.lock().unwrap_or_else(|e| e.into_inner()).push(stream);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn reuses_connections_and_caps_them() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let accepted = tokio::spawn(async move {
+            let mut conns = Vec::new();
+            while let Ok(Ok((conn, _))) = tokio::time::timeout(Duration::from_millis(300), listener.accept()).await {
+                conns.push(conn);
+            }
+            conns.len()
+        });
+
+        let pool = IlpConnectionPool::new(addr, 2);
+        let first = pool.acquire().await.unwrap();
+        let second = pool.acquire().await.unwrap();
+        // Both connections are checked out: the next writer waits.
+        assert!(tokio::time::timeout(Duration::from_millis(50), pool.acquire()).await.is_err());
+
+        // A returned connection is reused, a discarded one replaced.
+        drop(first);
+        let _third = pool.acquire().await.unwrap();
+        second.discard();
+        let _fourth = pool.acquire().await.unwrap();
+
+        assert_eq!(accepted.await.unwrap(), 3);
+    }
+}
@@ -1,7 +1,20 @@
+pub mod clickhouse;
+pub mod file_ndjson;
+pub mod ilp_pool;
 pub mod questdb;
 pub mod questdb_generation;
 pub mod questdb_ilp;
+pub mod store;
+pub mod timescale;
 
-pub use questdb::QuestDbSink;
+pub use clickhouse::ClickHouseStore;
+pub use file_ndjson::FileNdjsonStore;
+pub use ilp_pool::IlpConnectionPool;
+pub use questdb::{QuestDbPgwireStore, QuestDbSink};
 pub use questdb_generation::QuestDbGenerationSink;
-pub use questdb_ilp::{QuestDbIlpGenerationSink, QuestDbIlpMeterUsageSink};
+pub use questdb_ilp::{
+    QuestDbIlpCustomerSink, QuestDbIlpDrEventSink, QuestDbIlpGenerationSink, QuestDbIlpMeterExchangeSink,
+    QuestDbIlpMeterSink, QuestDbIlpMeterUsageSink, generation_output_event_id, meter_usage_event_id,
+};
+pub use store::{StoreSink, TimeSeriesStore};
+pub use timescale::TimescaleStore;
@@ -3,26 +3,203 @@ use std::time::Duration;
 use futures::StreamExt;
 use rust_client::domain::MeterUsage;
 use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
+use time::OffsetDateTime;
 
-use crate::pipeline::{Envelope, PipelineError, Sink};
+use crate::{
+    config::EventIdStrategy,
+    pipeline::{Envelope, LatencySampler, PipelineError, Sink, SinkLag},
+};
 
-pub struct QuestDbSink {
+use super::{questdb_ilp::meter_usage_event_id, store::TimeSeriesStore};
+
+/// Mirrors `meter_usage` in `sql/schema/01_core_timeseries.sql`.
+const METER_USAGE_DDL: &str = "CREATE TABLE IF NOT EXISTS meter_usage (\
+    ts TIMESTAMP, event_id SYMBOL, meter_id SYMBOL, premise_id SYMBOL, \
+    kwh DOUBLE, kvarh DOUBLE, kva_demand DOUBLE, quality_flag SYMBOL, source_system SYMBOL, tenant SYMBOL, ingested_at TIMESTAMP, \
+    kwh_phase_a DOUBLE, kwh_phase_b DOUBLE, kwh_phase_c DOUBLE, \
+    current_phase_a DOUBLE, current_phase_b DOUBLE, current_phase_c DOUBLE, \
+    voltage_phase_a DOUBLE, voltage_phase_b DOUBLE, voltage_phase_c DOUBLE\
+    ) TIMESTAMP(ts) PARTITION BY DAY";
+
+/// QuestDB over PostgreSQL wire protocol.
+#[derive(Clone)]
+pub struct QuestDbPgwireStore {
     pool: PgPool,
+    event_id: EventIdStrategy,
+}
+
+impl QuestDbPgwireStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            event_id: EventIdStrategy::ContentHash,
+        }
+    }
+
+    /// How the `event_id` column is derived, as for the ILP sinks.
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.event_id = strategy;
+        self
+    }
+
+    pub(crate) fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    pub(crate) fn event_id_strategy(&self) -> EventIdStrategy {
+        self.event_id
+    }
+
+    /// Fail unless re-sent records of `table` collapse into one row: the table
+    /// must deduplicate on `(ts, event_id)` and ids must be deterministic.
+    pub async fn require_event_id_dedup(&self, table: &str) -> Result<(), PipelineError> {
+        if self.event_id == EventIdStrategy::UuidV7 {
+            return Err(PipelineError::Sink(
+                "event_id = \"uuid_v7\" gives re-sent records new ids; use content_hash, natural_key or client".to_string(),
+            ));
+        }
+        let keys = self.dedup_upsert_keys(table).await?;
+        if ["ts", "event_id"].iter().all(|k| keys.iter().any(|c| c == k)) {
+            return Ok(());
+        }
+        Err(PipelineError::Sink(format!(
+            "{table} does not deduplicate on (ts, event_id) (upsert keys: {keys:?}); \
+             run: ALTER TABLE {table} DEDUP ENABLE UPSERT KEYS(ts, event_id)"
+        )))
+    }
+
+    /// Fail unless a record re-sent to `table` replaces the stored one: the
+    /// table must deduplicate on exactly `(ts, meter_id)`, whatever its `event_id`.
+    pub async fn require_meter_id_dedup(&self, table: &str) -> Result<(), PipelineError> {
+        let mut keys = self.dedup_upsert_keys(table).await?;
+        keys.sort();
+        if keys == ["meter_id", "ts"] {
+            return Ok(());
+        }
+        Err(PipelineError::Sink(format!(
+            "{table} does not deduplicate on (ts, meter_id) alone (upsert keys: {keys:?}); \
+             run: ALTER TABLE {table} DEDUP ENABLE UPSERT KEYS(ts, meter_id)"
+        )))
+    }
+
+    /// Upsert keys of `table` if it has deduplication enabled, otherwise empty.
+    pub async fn dedup_upsert_keys(&self, table: &str) -> Result<Vec<String>, PipelineError> {
+        sqlx::query_scalar(r#"SELECT "column" FROM table_columns($1) WHERE upsertKey"#)
+            .bind(table)
+            .fetch_all(&self.pool)
+            .await
+            .map_err(|e| PipelineError::Sink(format!("questdb dedup keys of {table}: {e}")))
+    }
+
+    pub(crate) async fn execute_ddl(&self, ddl: &str) -> Result<(), PipelineError> {
+        sqlx::query(ddl)
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| PipelineError::Sink(format!("questdb schema: {e}")))
+    }
+
+    pub(crate) async fn ping(&self) -> Result<(), PipelineError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(|e| PipelineError::Sink(format!("questdb health check: {e}")))
+    }
+}
+
+#[async_trait::async_trait]
+impl TimeSeriesStore<MeterUsage> for QuestDbPgwireStore {
+    fn name(&self) -> &'static str {
+        "questdb_pgwire"
+    }
+
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        self.execute_ddl(METER_USAGE_DDL).await
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<MeterUsage>]) -> Result<(), PipelineError> {
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO meter_usage (ts, event_id, meter_id, premise_id, kwh, kvarh, kva_demand, quality_flag, source_system, tenant, ingested_at, \
+             kwh_phase_a, kwh_phase_b, kwh_phase_c, current_phase_a, current_phase_b, current_phase_c, \
+             voltage_phase_a, voltage_phase_b, voltage_phase_c) ",
+        );
+
+        builder.push("VALUES ");
+        builder.push_values(batch, |mut b, env| {
+            let m = &env.payload;
+            b.push_bind(m.ts)
+                .push_bind(meter_usage_event_id(self.event_id, m))
+                .push_bind(&m.meter_id)
+                .push_bind(&m.premise_id)
+                .push_bind(m.kwh)
+                .push_bind(m.kvarh)
+                .push_bind(m.kva_demand)
+                .push_bind(&m.quality_flag)
+                .push_bind(&m.source_system)
+                .push_bind(env.tenant.as_deref())
+                .push_bind(OffsetDateTime::from(env.received_at));
+            for v in m.phases.values() {
+                b.push_bind(v);
+            }
+        });
+
+        let query = builder.build();
+        query
+            .execute(self.pool())
+            .await
+            .map(|_| ())
+            .map_err(|e| PipelineError::Sink(e.to_string()))
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        self.ping().await
+    }
+}
+
+pub struct QuestDbSink {
+    store: QuestDbPgwireStore,
     batch_size: usize,
     max_retries: u32,
     retry_backoff: Duration,
+    lag: Option<SinkLag>,
+    latency_sampler: Option<LatencySampler>,
 }
 
 impl QuestDbSink {
     pub fn new(pool: PgPool, batch_size: usize, max_retries: u32, retry_backoff: Duration) -> Self {
         Self {
-            pool,
+            store: QuestDbPgwireStore::new(pool),
             batch_size,
             max_retries,
             retry_backoff,
+            lag: None,
+            latency_sampler: None,
         }
     }
 
+    /// How the `event_id` column is derived; see [`EventIdStrategy`].
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.store = self.store.with_event_id(strategy);
+        self
+    }
+
+    pub fn store(&self) -> &QuestDbPgwireStore {
+        &self.store
+    }
+
+    /// Publish flush latency to `lag`, e.g. the source's [`SinkLag`] for bulk shedding.
+    pub fn with_lag(mut self, lag: SinkLag) -> Self {
+        self.lag = Some(lag);
+        self
+    }
+
+    /// Sample per-record end-to-end latency of flushed records; see [`LatencySampler`].
+    pub fn with_latency_sampler(mut self, sampler: LatencySampler) -> Self {
+        self.latency_sampler = Some(sampler);
+        self
+    }
+
     async fn flush_batch(&self, batch: &[Envelope<MeterUsage>]) -> Result<(), PipelineError> {
         if batch.is_empty() {
             return Ok(());
@@ -30,7 +207,7 @@ impl QuestDbSink {
 
         let mut attempt: u32 = 0;
         loop {
-            let res = self.insert_batch(batch).await;
+            let res = self.store.insert_batch(batch).await;
             match res {
                 Ok(()) => {
                     // Successful write: record metrics.
@@ -39,12 +216,23 @@ impl QuestDbSink {
 
                     // Approximate end-to-end latency from earliest received_at to now.
                     if let Some(min_received) = batch.iter().map(|e| e.received_at).min() {
+                        if let Some(lag) = &self.lag {
+                            lag.record_flush(min_received);
+                        }
                         if let Ok(dur) = std::time::SystemTime::now().duration_since(min_received) {
                             let hist = metrics::histogram!("ingest_end_to_end_latency_seconds");
                             hist.record(dur.as_secs_f64());
                         }
                     }
 
+                    if let Some(sampler) = &self.latency_sampler {
+                        sampler.record(batch);
+                    }
+
+                    for env in batch {
+                        env.complete();
+                    }
+
                     return Ok(());
                 }
                 Err(e) if attempt < self.max_retries => {
@@ -60,33 +248,11 @@ impl QuestDbSink {
                 Err(e) => {
                     tracing::error!(error = %e, "questdb sink flush failed, giving up");
                     metrics::counter!("questdb_sink_errors_total").increment(1);
-                    return Err(PipelineError::Sink(e.to_string()));
+                    return Err(e);
                 }
             }
         }
     }
-
-    async fn insert_batch(&self, batch: &[Envelope<MeterUsage>]) -> Result<(), sqlx::Error> {
-        let mut builder = QueryBuilder::<Postgres>::new(
-            "INSERT INTO meter_usage (ts, meter_id, premise_id, kwh, kvarh, kva_demand, quality_flag, source_system) ",
-        );
-
-        builder.push("VALUES ");
-        builder.push_values(batch, |mut b, env| {
-            let m = &env.payload;
-            b.push_bind(m.ts)
-                .push_bind(&m.meter_id)
-                .push_bind(&m.premise_id)
-                .push_bind(m.kwh)
-                .push_bind(&m.kvarh)
-                .push_bind(&m.kva_demand)
-                .push_bind(&m.quality_flag)
-                .push_bind(&m.source_system);
-        });
-
-        let query = builder.build();
-        query.execute(&self.pool).await.map(|_| ())
-    }
 }
 
 #[async_trait::async_trait]
@@ -4,25 +4,103 @@ use futures::StreamExt;
 use rust_client::domain::GenerationOutput;
 use sqlx::{postgres::PgPool, Postgres, QueryBuilder};
 
-use crate::pipeline::{Envelope, PipelineError, Sink};
+use crate::{
+    config::EventIdStrategy,
+    pipeline::{Envelope, LatencySampler, PipelineError, Sink, SinkLag},
+};
+
+use super::{questdb::QuestDbPgwireStore, questdb_ilp::generation_output_event_id, store::TimeSeriesStore};
+
+/// Mirrors `generation_output` in `sql/schema/01_core_timeseries.sql`.
+const GENERATION_OUTPUT_DDL: &str = "CREATE TABLE IF NOT EXISTS generation_output (\
+    ts TIMESTAMP, event_id SYMBOL, plant_id SYMBOL, unit_id SYMBOL, mw DOUBLE, mvar DOUBLE, \
+    status SYMBOL, fuel_type SYMBOL, aux_mw DOUBLE, availability_pct DOUBLE, curtailed_mw DOUBLE\
+    ) TIMESTAMP(ts) PARTITION BY DAY";
+
+#[async_trait::async_trait]
+impl TimeSeriesStore<GenerationOutput> for QuestDbPgwireStore {
+    fn name(&self) -> &'static str {
+        "questdb_pgwire"
+    }
+
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        self.execute_ddl(GENERATION_OUTPUT_DDL).await
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<GenerationOutput>]) -> Result<(), PipelineError> {
+        let mut builder = QueryBuilder::<Postgres>::new(
+            "INSERT INTO generation_output (ts, event_id, plant_id, unit_id, mw, mvar, status, fuel_type, aux_mw, availability_pct, curtailed_mw) ",
+        );
+
+        let strategy = self.event_id_strategy();
+        builder.push("VALUES ");
+        builder.push_values(batch, |mut b, env| {
+            let g = &env.payload;
+            b.push_bind(g.ts)
+                .push_bind(generation_output_event_id(strategy, g))
+                .push_bind(&g.plant_id)
+                .push_bind(&g.unit_id)
+                .push_bind(g.mw)
+                .push_bind(g.mvar)
+                .push_bind(&g.status)
+                .push_bind(&g.fuel_type)
+                .push_bind(g.aux_mw)
+                .push_bind(g.availability_pct)
+                .push_bind(g.curtailed_mw);
+        });
+
+        let query = builder.build();
+        query
+            .execute(self.pool())
+            .await
+            .map(|_| ())
+            .map_err(|e| PipelineError::Sink(e.to_string()))
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        self.ping().await
+    }
+}
 
 pub struct QuestDbGenerationSink {
-    pool: PgPool,
+    store: QuestDbPgwireStore,
     batch_size: usize,
     max_retries: u32,
     retry_backoff: Duration,
+    lag: Option<SinkLag>,
+    latency_sampler: Option<LatencySampler>,
 }
 
 impl QuestDbGenerationSink {
     pub fn new(pool: PgPool, batch_size: usize, max_retries: u32, retry_backoff: Duration) -> Self {
         Self {
-            pool,
+            store: QuestDbPgwireStore::new(pool),
             batch_size,
             max_retries,
             retry_backoff,
+            lag: None,
+            latency_sampler: None,
         }
     }
 
+    /// How the `event_id` column is derived; see [`EventIdStrategy`].
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.store = self.store.with_event_id(strategy);
+        self
+    }
+
+    /// Publish flush latency to `lag`, e.g. the source's [`SinkLag`] for bulk shedding.
+    pub fn with_lag(mut self, lag: SinkLag) -> Self {
+        self.lag = Some(lag);
+        self
+    }
+
+    /// Sample per-record end-to-end latency of flushed records; see [`LatencySampler`].
+    pub fn with_latency_sampler(mut self, sampler: LatencySampler) -> Self {
+        self.latency_sampler = Some(sampler);
+        self
+    }
+
     async fn flush_batch(&self, batch: &[Envelope<GenerationOutput>]) -> Result<(), PipelineError> {
         if batch.is_empty() {
             return Ok(());
@@ -30,7 +108,7 @@ impl QuestDbGenerationSink {
 
         let mut attempt: u32 = 0;
         loop {
-            let res = self.insert_batch(batch).await;
+            let res = self.store.insert_batch(batch).await;
             match res {
                 Ok(()) => {
                     // Successful write: record metrics.
@@ -38,12 +116,23 @@ impl QuestDbGenerationSink {
                     counter.increment(batch.len() as u64);
 
                     if let Some(min_received) = batch.iter().map(|e| e.received_at).min() {
+                        if let Some(lag) = &self.lag {
+                            lag.record_flush(min_received);
+                        }
                         if let Ok(dur) = std::time::SystemTime::now().duration_since(min_received) {
                             let hist = metrics::histogram!("ingest_end_to_end_latency_seconds");
                             hist.record(dur.as_secs_f64());
                         }
                     }
 
+                    if let Some(sampler) = &self.latency_sampler {
+                        sampler.record(batch);
+                    }
+
+                    for env in batch {
+                        env.complete();
+                    }
+
                     return Ok(());
                 }
                 Err(e) if attempt < self.max_retries => {
@@ -59,32 +148,11 @@ impl QuestDbGenerationSink {
                 Err(e) => {
                     tracing::error!(error = %e, "questdb generation sink flush failed, giving up");
                     metrics::counter!("questdb_generation_sink_errors_total").increment(1);
-                    return Err(PipelineError::Sink(e.to_string()));
+                    return Err(e);
                 }
             }
         }
     }
-
-    async fn insert_batch(&self, batch: &[Envelope<GenerationOutput>]) -> Result<(), sqlx::Error> {
-        let mut builder = QueryBuilder::<Postgres>::new(
-            "INSERT INTO generation_output (ts, plant_id, unit_id, mw, mvar, status, fuel_type) ",
-        );
-
-        builder.push("VALUES ");
-        builder.push_values(batch, |mut b, env| {
-            let g = &env.payload;
-            b.push_bind(g.ts)
-                .push_bind(&g.plant_id)
-                .push_bind(&g.unit_id)
-                .push_bind(g.mw)
-                .push_bind(&g.mvar)
-                .push_bind(&g.status)
-                .push_bind(&g.fuel_type);
-        });
-
-        let query = builder.build();
-        query.execute(&self.pool).await.map(|_| ())
-    }
 }
 
 #[async_trait::async_trait]
@@ -1,20 +1,141 @@
 use std::{
     marker::PhantomData,
     net::SocketAddr,
+    sync::Arc,
     time::{Duration, SystemTime},
 };
 
 use futures::StreamExt;
-use rust_client::domain::{GenerationOutput, MeterUsage};
+use rust_client::domain::{Customer, DrEvent, GenerationOutput, Meter, MeterEvent, MeterExchange, MeterUsage};
 use time::OffsetDateTime;
-use tokio::{io::AsyncWriteExt, net::TcpStream};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    config::{EventIdStrategy, IlpProtocolSetting, OrderingMode},
+    jobs::rollups::ServerVersion,
+    pipeline::{Envelope, LatencySampler, PipelineError, Sink, SinkLag},
+    sinks::ilp_pool::IlpConnectionPool,
+    transform::{
+        duplicates::DuplicateCounts, rejects::IngestReject, threshold_alerts::StreamAlert, unit_state::UnitTransition,
+        window_aggregate::WindowAggregate,
+    },
+};
 
-use crate::pipeline::{Envelope, PipelineError, Sink};
+/// ILP dialect spoken to the server.
+///
+/// - `V1`: text only; the designated timestamp is bare nanoseconds.
+/// - `V2` (QuestDB 9.0+): adds binary `DOUBLE[]` array fields, and designated
+///   timestamps carry an explicit unit (microseconds, `t` suffix).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IlpProtocolVersion {
+    #[default]
+    V1,
+    V2,
+}
+
+/// First QuestDB release accepting ILP protocol version 2.
+pub const ILP_V2_MIN_VERSION: ServerVersion = ServerVersion {
+    major: 9,
+    minor: 0,
+    patch: 0,
+};
+
+impl IlpProtocolVersion {
+    /// Highest version the server supports; `V1` if unknown.
+    pub fn for_server(version: Option<ServerVersion>) -> Self {
+        match version {
+            Some(v) if v >= ILP_V2_MIN_VERSION => Self::V2,
+            _ => Self::V1,
+        }
+    }
+
+    /// Resolve a configured setting; `server` is only consulted for `auto`.
+    pub fn resolve(setting: IlpProtocolSetting, server: Option<ServerVersion>) -> Self {
+        match setting {
+            IlpProtocolSetting::V1 => Self::V1,
+            IlpProtocolSetting::V2 => Self::V2,
+            IlpProtocolSetting::Auto => Self::for_server(server),
+        }
+    }
+}
+
+/// Output buffer for ILP lines.
+///
+/// Mostly text, but `V2` array fields are binary, hence bytes rather than a `String`.
+#[derive(Debug, Default)]
+pub struct IlpBuffer {
+    bytes: Vec<u8>,
+    protocol: IlpProtocolVersion,
+    event_id: EventIdStrategy,
+    table: Option<Arc<str>>,
+}
+
+impl IlpBuffer {
+    pub fn new(protocol: IlpProtocolVersion) -> Self {
+        Self::with_capacity(protocol, 0)
+    }
+
+    pub fn with_capacity(protocol: IlpProtocolVersion, capacity: usize) -> Self {
+        Self {
+            bytes: Vec::with_capacity(capacity),
+            protocol,
+            event_id: EventIdStrategy::default(),
+            table: None,
+        }
+    }
+
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.event_id = strategy;
+        self
+    }
+
+    /// Write every line into `table` instead of the record type's own table.
+    pub fn with_table(mut self, table: Option<Arc<str>>) -> Self {
+        self.table = table;
+        self
+    }
+
+    pub fn protocol(&self) -> IlpProtocolVersion {
+        self.protocol
+    }
+
+    pub fn event_id_strategy(&self) -> EventIdStrategy {
+        self.event_id
+    }
+
+    pub fn push_str(&mut self, s: &str) {
+        self.bytes.extend_from_slice(s.as_bytes());
+    }
+
+    /// Start a line for `table`, or for the table set by [`IlpBuffer::with_table`].
+    pub fn push_measurement(&mut self, table: &str) {
+        match &self.table {
+            Some(table) => self.bytes.extend_from_slice(table.as_bytes()),
+            None => self.push_str(table),
+        }
+    }
+
+    pub fn push(&mut self, ch: char) {
+        let mut tmp = [0u8; 4];
+        self.push_str(ch.encode_utf8(&mut tmp));
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
 
 /// Escape measurement/tag keys/tag values/field keys for ILP.
 ///
 /// ILP requires escaping commas, spaces and equals with a backslash.
-fn ilp_escape_ident(s: &str, out: &mut String) {
+fn ilp_escape_ident(s: &str, out: &mut IlpBuffer) {
     for ch in s.chars() {
         match ch {
             ',' | ' ' | '=' => {
@@ -26,14 +147,14 @@ fn ilp_escape_ident(s: &str, out: &mut String) {
     }
 }
 
-fn push_tag(out: &mut String, key: &str, value: &str) {
+fn push_tag(out: &mut IlpBuffer, key: &str, value: &str) {
     out.push(',');
     ilp_escape_ident(key, out);
     out.push('=');
     ilp_escape_ident(value, out);
 }
 
-fn push_field_f64(out: &mut String, first: &mut bool, key: &str, value: f64) {
+fn push_field_f64(out: &mut IlpBuffer, first: &mut bool, key: &str, value: f64) {
     if *first {
         *first = false;
     } else {
@@ -46,6 +167,117 @@ fn push_field_f64(out: &mut String, first: &mut bool, key: &str, value: f64) {
     out.push_str(&value.to_string());
 }
 
+/// Integer field (`i` suffix).
+fn push_field_i64(out: &mut IlpBuffer, first: &mut bool, key: &str, value: i64) {
+    if *first {
+        *first = false;
+    } else {
+        out.push(',');
+    }
+
+    ilp_escape_ident(key, out);
+    out.push('=');
+    out.push_str(&value.to_string());
+    out.push('i');
+}
+
+fn push_field_str(out: &mut IlpBuffer, first: &mut bool, key: &str, value: &str) {
+    if *first {
+        *first = false;
+    } else {
+        out.push(',');
+    }
+
+    ilp_escape_ident(key, out);
+    out.push_str("=\"");
+    for ch in value.chars() {
+        match ch {
+            '"' | '\\' => {
+                out.push('\\');
+                out.push(ch);
+            }
+            '\n' => out.push(' '),
+            _ => out.push(ch),
+        }
+    }
+    out.push('"');
+}
+
+fn push_field_bool(out: &mut IlpBuffer, first: &mut bool, key: &str, value: bool) {
+    if *first {
+        *first = false;
+    } else {
+        out.push(',');
+    }
+
+    ilp_escape_ident(key, out);
+    out.push('=');
+    out.push(if value { 't' } else { 'f' });
+}
+
+/// Timestamp field (`t` suffix: microseconds since the epoch).
+fn push_field_ts(out: &mut IlpBuffer, first: &mut bool, key: &str, value: SystemTime) {
+    if *first {
+        *first = false;
+    } else {
+        out.push(',');
+    }
+
+    let micros = match value.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_micros() as i128,
+        Err(e) => -(e.duration().as_micros() as i128),
+    };
+
+    ilp_escape_ident(key, out);
+    out.push('=');
+    out.push_str(&micros.to_string());
+    out.push('t');
+}
+
+/// `DOUBLE[]` field (one dimension).
+///
+/// With `V2` this is the binary array encoding. `V1` has no arrays, so each element
+/// becomes its own field `<key>_<i>` (0-based) instead.
+pub fn push_field_f64_array(out: &mut IlpBuffer, first: &mut bool, key: &str, values: &[f64]) {
+    const BINARY_FORMAT_TYPE_ARRAY: u8 = 14;
+    const ARRAY_ELEM_TYPE_DOUBLE: u8 = 10;
+
+    if out.protocol == IlpProtocolVersion::V1 {
+        for (i, v) in values.iter().enumerate() {
+            push_field_f64(out, first, &format!("{key}_{i}"), *v);
+        }
+        return;
+    }
+
+    if *first {
+        *first = false;
+    } else {
+        out.push(',');
+    }
+
+    ilp_escape_ident(key, out);
+    out.push_str("==");
+    out.bytes.push(BINARY_FORMAT_TYPE_ARRAY);
+    out.bytes.push(ARRAY_ELEM_TYPE_DOUBLE);
+    out.bytes.push(1); // dimensions
+    out.bytes.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    for v in values {
+        out.bytes.extend_from_slice(&v.to_le_bytes());
+    }
+}
+
+/// Designated timestamp, preceded by the separating space.
+pub fn push_designated_ts(out: &mut IlpBuffer, ts: OffsetDateTime) {
+    out.push(' ');
+    match out.protocol {
+        IlpProtocolVersion::V1 => out.push_str(&ts_to_unix_nanos(ts).to_string()),
+        IlpProtocolVersion::V2 => {
+            out.push_str(&(ts_to_unix_nanos(ts) / 1_000).to_string());
+            out.push('t');
+        }
+    }
+}
+
 fn ts_to_unix_nanos(ts: OffsetDateTime) -> i128 {
     ts.unix_timestamp_nanos()
 }
@@ -94,6 +326,12 @@ fn event_id_meter_usage(m: &MeterUsage) -> String {
     hash_opt_f64(&mut h, m.kva_demand);
     hash_opt_str(&mut h, &m.quality_flag);
     hash_opt_str(&mut h, &m.source_system);
+    // Only polyphase readings hash their channels, so ids of single-value readings are unchanged.
+    if !m.phases.is_empty() {
+        for v in m.phases.values() {
+            hash_opt_f64(&mut h, v);
+        }
+    }
     h.finalize().to_hex().to_string()
 }
 
@@ -106,87 +344,439 @@ fn event_id_generation(g: &GenerationOutput) -> String {
     hash_opt_f64(&mut h, g.mvar);
     hash_opt_str(&mut h, &g.status);
     hash_opt_str(&mut h, &g.fuel_type);
+    // Like per-phase meter channels: only records that report these fields hash them.
+    let extra = [g.aux_mw, g.availability_pct, g.curtailed_mw];
+    if extra.iter().any(Option::is_some) {
+        for v in extra {
+            hash_opt_f64(&mut h, v);
+        }
+    }
+    h.finalize().to_hex().to_string()
+}
+
+fn natural_key_meter_usage(m: &MeterUsage) -> String {
+    let mut h = blake3::Hasher::new();
+    h.update(&ts_to_unix_nanos(m.ts).to_le_bytes());
+    hash_str(&mut h, &m.meter_id);
     h.finalize().to_hex().to_string()
 }
 
+fn natural_key_generation(g: &GenerationOutput) -> String {
+    let mut h = blake3::Hasher::new();
+    h.update(&ts_to_unix_nanos(g.ts).to_le_bytes());
+    hash_str(&mut h, &g.plant_id);
+    hash_opt_str(&mut h, &g.unit_id);
+    h.finalize().to_hex().to_string()
+}
+
+/// Pick the `event_id` of a record according to `strategy`.
+fn event_id(
+    strategy: EventIdStrategy,
+    client: Option<&str>,
+    content_hash: impl FnOnce() -> String,
+    natural_key: impl FnOnce() -> String,
+) -> String {
+    match strategy {
+        EventIdStrategy::ContentHash => content_hash(),
+        EventIdStrategy::NaturalKey => natural_key(),
+        EventIdStrategy::Client => match client.filter(|id| !id.is_empty()) {
+            Some(id) => id.to_string(),
+            None => content_hash(),
+        },
+        EventIdStrategy::UuidV7 => uuid::Uuid::now_v7().to_string(),
+    }
+}
+
+/// The `event_id` a meter usage record is stored with under `strategy`.
+pub fn meter_usage_event_id(strategy: EventIdStrategy, m: &MeterUsage) -> String {
+    event_id(
+        strategy,
+        m.event_id.as_deref(),
+        || event_id_meter_usage(m),
+        || natural_key_meter_usage(m),
+    )
+}
+
+/// The `event_id` a generation output record is stored with under `strategy`.
+pub fn generation_output_event_id(strategy: EventIdStrategy, g: &GenerationOutput) -> String {
+    event_id(
+        strategy,
+        g.event_id.as_deref(),
+        || event_id_generation(g),
+        || natural_key_generation(g),
+    )
+}
+
 pub trait IlpEncode {
-    fn write_ilp_line(&self, out: &mut String);
+    fn write_ilp_line(&self, out: &mut IlpBuffer);
+
+    /// Encode a record together with the time it was received and the tenant
+    /// of the key that sent it.
+    ///
+    /// Tables that track arrival time (for lateness reporting) or tenants
+    /// override this; the default ignores both.
+    fn write_ilp_line_received(&self, received_at: SystemTime, tenant: Option<&str>, out: &mut IlpBuffer) {
+        let _ = (received_at, tenant);
+        self.write_ilp_line(out);
+    }
+}
+
+fn write_meter_usage_line(m: &MeterUsage, ingested_at: Option<SystemTime>, tenant: Option<&str>, out: &mut IlpBuffer) {
+    // measurement
+    out.push_measurement("meter_usage");
+
+    // tags (SYMBOL columns)
+    let event_id = meter_usage_event_id(out.event_id, m);
+    push_tag(out, "event_id", &event_id);
+    push_tag(out, "meter_id", &m.meter_id);
+    if let Some(premise_id) = &m.premise_id {
+        push_tag(out, "premise_id", premise_id);
+    }
+    if let Some(q) = &m.quality_flag {
+        push_tag(out, "quality_flag", q);
+    }
+    if let Some(src) = &m.source_system {
+        push_tag(out, "source_system", src);
+    }
+    if let Some(tenant) = tenant {
+        push_tag(out, "tenant", tenant);
+    }
+
+    // fields (numeric metrics)
+    out.push(' ');
+    let mut first = true;
+    push_field_f64(out, &mut first, "kwh", m.kwh);
+    if let Some(v) = m.kvarh {
+        push_field_f64(out, &mut first, "kvarh", v);
+    }
+    if let Some(v) = m.kva_demand {
+        push_field_f64(out, &mut first, "kva_demand", v);
+    }
+    for (column, v) in m.phases.present() {
+        push_field_f64(out, &mut first, column, v);
+    }
+    if let Some(at) = ingested_at {
+        push_field_ts(out, &mut first, "ingested_at", at);
+    }
+
+    push_designated_ts(out, m.ts);
 }
 
 impl IlpEncode for MeterUsage {
-    fn write_ilp_line(&self, out: &mut String) {
-        // measurement
-        out.push_str("meter_usage");
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        write_meter_usage_line(self, None, None, out);
+    }
+
+    fn write_ilp_line_received(&self, received_at: SystemTime, tenant: Option<&str>, out: &mut IlpBuffer) {
+        write_meter_usage_line(self, Some(received_at), tenant, out);
+    }
+}
+
+impl IlpEncode for GenerationOutput {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("generation_output");
 
-        // tags (SYMBOL columns)
-        let event_id = event_id_meter_usage(self);
+        // tags
+        let event_id = generation_output_event_id(out.event_id, self);
         push_tag(out, "event_id", &event_id);
-        push_tag(out, "meter_id", &self.meter_id);
-        if let Some(premise_id) = &self.premise_id {
-            push_tag(out, "premise_id", premise_id);
+        push_tag(out, "plant_id", &self.plant_id);
+        if let Some(unit_id) = &self.unit_id {
+            push_tag(out, "unit_id", unit_id);
         }
-        if let Some(q) = &self.quality_flag {
-            push_tag(out, "quality_flag", q);
+        if let Some(status) = &self.status {
+            push_tag(out, "status", status);
         }
-        if let Some(src) = &self.source_system {
-            push_tag(out, "source_system", src);
+        if let Some(fuel) = &self.fuel_type {
+            push_tag(out, "fuel_type", fuel);
         }
 
-        // fields (numeric metrics)
+        // fields
         out.push(' ');
         let mut first = true;
-        push_field_f64(out, &mut first, "kwh", self.kwh);
-        if let Some(v) = self.kvarh {
-            push_field_f64(out, &mut first, "kvarh", v);
+        push_field_f64(out, &mut first, "mw", self.mw);
+        for (key, value) in [
+            ("mvar", self.mvar),
+            ("aux_mw", self.aux_mw),
+            ("availability_pct", self.availability_pct),
+            ("curtailed_mw", self.curtailed_mw),
+        ] {
+            if let Some(v) = value {
+                push_field_f64(out, &mut first, key, v);
+            }
         }
-        if let Some(v) = self.kva_demand {
-            push_field_f64(out, &mut first, "kva_demand", v);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+impl IlpEncode for Meter {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("meters");
+
+        push_tag(out, "meter_id", &self.meter_id);
+        for (key, value) in [
+            ("premise_id", &self.premise_id),
+            ("customer_id", &self.customer_id),
+            ("feeder_id", &self.feeder_id),
+            ("substation_id", &self.substation_id),
+            ("tariff_code", &self.tariff_code),
+            ("meter_type", &self.meter_type),
+        ] {
+            if let Some(v) = value {
+                push_tag(out, key, v);
+            }
         }
 
-        // timestamp (nanos)
         out.push(' ');
-        out.push_str(&ts_to_unix_nanos(self.ts).to_string());
+        let mut first = true;
+        push_field_bool(out, &mut first, "deleted", self.deleted);
+
+        push_designated_ts(out, self.effective_from);
     }
 }
 
-impl IlpEncode for GenerationOutput {
-    fn write_ilp_line(&self, out: &mut String) {
-        out.push_str("generation_output");
+impl IlpEncode for MeterExchange {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("meter_exchanges");
+
+        push_tag(out, "premise_id", &self.premise_id);
+        push_tag(out, "old_meter_id", &self.old_meter_id);
+        push_tag(out, "new_meter_id", &self.new_meter_id);
+
+        out.push(' ');
+        let mut first = true;
+        push_field_str(out, &mut first, "reason", &self.reason);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+/// One line per nominated meter.
+impl IlpEncode for DrEvent {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        for (i, meter_id) in self.meter_ids.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            out.push_measurement("dr_events");
+
+            push_tag(out, "event_id", &self.event_id);
+            push_tag(out, "program", &self.program);
+            push_tag(out, "meter_id", meter_id);
+
+            out.push(' ');
+            let mut first = true;
+            push_field_ts(out, &mut first, "end_ts", self.end.into());
+
+            push_designated_ts(out, self.start);
+        }
+    }
+}
+
+impl IlpEncode for MeterEvent {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("meter_events");
+
+        push_tag(out, "meter_id", &self.meter_id);
+        push_tag(out, "event_type", &self.event_type);
+
+        out.push(' ');
+        let mut first = true;
+        push_field_str(out, &mut first, "details", &self.details);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+impl IlpEncode for UnitTransition {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("unit_runtime");
 
-        // tags
-        let event_id = event_id_generation(self);
-        push_tag(out, "event_id", &event_id);
         push_tag(out, "plant_id", &self.plant_id);
         if let Some(unit_id) = &self.unit_id {
             push_tag(out, "unit_id", unit_id);
         }
-        if let Some(status) = &self.status {
-            push_tag(out, "status", status);
+        push_tag(out, "event", self.kind.as_str());
+
+        out.push(' ');
+        let mut first = true;
+        push_field_f64(out, &mut first, "mw", self.mw);
+        if let Some(h) = self.run_hours {
+            push_field_f64(out, &mut first, "run_hours", h);
         }
-        if let Some(fuel) = &self.fuel_type {
-            push_tag(out, "fuel_type", fuel);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+impl IlpEncode for StreamAlert {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("stream_alerts");
+
+        push_tag(out, "rule", &self.rule);
+        push_tag(out, "table_name", self.table);
+        push_tag(out, "alert_id", &self.id);
+        push_tag(out, "field", &self.field);
+        push_tag(out, "op", self.op.as_str());
+
+        out.push(' ');
+        let mut first = true;
+        push_field_f64(out, &mut first, "value", self.value);
+        push_field_f64(out, &mut first, "threshold", self.threshold);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+impl IlpEncode for WindowAggregate {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("window_aggregates");
+
+        push_tag(out, "window", &self.window);
+        push_tag(out, "table_name", self.table);
+        push_tag(out, "key_by", self.key_by.as_str());
+        push_tag(out, "key", &self.key);
+        push_tag(out, "field", &self.field);
+
+        out.push(' ');
+        let mut first = true;
+        push_field_i64(out, &mut first, "window_secs", self.window_secs);
+        push_field_i64(out, &mut first, "samples", self.count);
+        push_field_f64(out, &mut first, "sum", self.sum);
+        push_field_f64(out, &mut first, "avg", self.avg);
+        push_field_f64(out, &mut first, "min", self.min);
+        push_field_f64(out, &mut first, "max", self.max);
+
+        push_designated_ts(out, self.ts);
+    }
+}
+
+impl IlpEncode for IngestReject {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("ingest_rejects");
+
+        push_tag(out, "table_name", self.table);
+        if let Some(src) = &self.source_system {
+            push_tag(out, "source_system", src);
         }
 
-        // fields
         out.push(' ');
         let mut first = true;
-        push_field_f64(out, &mut first, "mw", self.mw);
-        if let Some(v) = self.mvar {
-            push_field_f64(out, &mut first, "mvar", v);
+        push_field_str(out, &mut first, "reason", &self.reason);
+        if let Some(record_ts) = self.record_ts {
+            push_field_ts(out, &mut first, "record_ts", record_ts.into());
+        }
+
+        push_designated_ts(out, self.received_at.into());
+    }
+}
+
+impl IlpEncode for DuplicateCounts {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("ingest_duplicates");
+
+        push_tag(out, "table_name", self.table);
+        if let Some(src) = &self.source_system {
+            push_tag(out, "source_system", src);
         }
 
-        // timestamp (nanos)
         out.push(' ');
-        out.push_str(&ts_to_unix_nanos(self.ts).to_string());
+        let mut first = true;
+        push_field_i64(out, &mut first, "records", self.records as i64);
+        push_field_i64(out, &mut first, "duplicates", self.duplicates as i64);
+
+        push_designated_ts(out, self.ts.into());
     }
 }
 
+impl IlpEncode for Customer {
+    fn write_ilp_line(&self, out: &mut IlpBuffer) {
+        out.push_measurement("customers");
+
+        push_tag(out, "customer_id", &self.customer_id);
+        if let Some(segment) = &self.segment {
+            push_tag(out, "segment", segment);
+        }
+        if let Some(region_id) = &self.region_id {
+            push_tag(out, "region_id", region_id);
+        }
+
+        out.push(' ');
+        let mut first = true;
+        push_field_bool(out, &mut first, "deleted", self.deleted);
+        if let Some(name) = &self.name {
+            push_field_str(out, &mut first, "name", name);
+        }
+        if let Some(v) = self.lat {
+            push_field_f64(out, &mut first, "lat", v);
+        }
+        if let Some(v) = self.lon {
+            push_field_f64(out, &mut first, "lon", v);
+        }
+
+        push_designated_ts(out, self.effective_from);
+    }
+}
+
+fn encode_ilp_batch<T: IlpEncode>(
+    protocol: IlpProtocolVersion,
+    event_id: EventIdStrategy,
+    table: Option<&Arc<str>>,
+    batch: &[Envelope<T>],
+) -> Vec<u8> {
+    // Heuristic capacity: ~160 bytes per line.
+    let mut out = IlpBuffer::with_capacity(protocol, batch.len().saturating_mul(160))
+        .with_event_id(event_id)
+        .with_table(table.cloned());
+    for env in batch {
+        env.payload
+            .write_ilp_line_received(env.received_at, env.tenant.as_deref(), &mut out);
+        out.push('\n');
+    }
+    out.into_bytes()
+}
+
+pub(crate) async fn connect_ilp(addr: SocketAddr) -> Result<TcpStream, PipelineError> {
+    let stream = TcpStream::connect(addr)
+        .await
+        .map_err(|e| PipelineError::Sink(format!("failed to connect to QuestDB ILP: {e}")))?;
+    let _ = stream.set_nodelay(true);
+    Ok(stream)
+}
+
+/// How long a strict-ordering sink waits for the server to close a failed connection.
+const CLOSE_CONFIRM_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Close `stream` and wait until the server closed its side too, i.e. it has
+/// consumed every line it received on the connection.
+async fn confirm_closed(stream: &mut TcpStream) -> Result<(), PipelineError> {
+    let _ = stream.shutdown().await;
+    let mut buf = [0u8; 512];
+    let drained = tokio::time::timeout(CLOSE_CONFIRM_TIMEOUT, async {
+        // A reset also means the server is done with the connection.
+        while let Ok(n) = stream.read(&mut buf).await {
+            if n == 0 {
+                break;
+            }
+        }
+    })
+    .await;
+    drained.map_err(|_| PipelineError::Sink("ILP server did not close the failed connection".to_string()))
+}
+
 pub struct QuestDbIlpSink<T> {
     addr: SocketAddr,
     batch_size: usize,
     max_retries: u32,
     retry_backoff: Duration,
     max_batch_linger: Duration,
+    ordering: OrderingMode,
+    protocol: IlpProtocolVersion,
+    event_id: EventIdStrategy,
+    lag: Option<SinkLag>,
+    latency_sampler: Option<LatencySampler>,
+    pool: Option<IlpConnectionPool>,
+    table: Option<Arc<str>>,
     _marker: PhantomData<fn() -> T>,
 }
 
@@ -204,16 +794,81 @@ impl<T> QuestDbIlpSink<T> {
             max_retries,
             retry_backoff,
             max_batch_linger,
+            ordering: OrderingMode::Relaxed,
+            protocol: IlpProtocolVersion::V1,
+            event_id: EventIdStrategy::ContentHash,
+            lag: None,
+            latency_sampler: None,
+            pool: None,
+            table: None,
             _marker: PhantomData,
         }
     }
 
+    pub fn with_ordering(mut self, ordering: OrderingMode) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// ILP dialect to emit; see [`IlpProtocolVersion::for_server`].
+    pub fn with_protocol(mut self, protocol: IlpProtocolVersion) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// How `event_id` tags are derived; only meter usage and generation output carry one.
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.event_id = strategy;
+        self
+    }
+
+    /// Publish flush latency to `lag`, e.g. the source's [`SinkLag`] for bulk shedding.
+    pub fn with_lag(mut self, lag: SinkLag) -> Self {
+        self.lag = Some(lag);
+        self
+    }
+
+    /// Sample per-record end-to-end latency of flushed records; see [`LatencySampler`].
+    pub fn with_latency_sampler(mut self, sampler: LatencySampler) -> Self {
+        self.latency_sampler = Some(sampler);
+        self
+    }
+
+    /// Write batches over connections of a shared [`IlpConnectionPool`]
+    /// instead of one socket per worker. Ignored in strict ordering mode, which
+    /// needs its own connection; `None` keeps the dedicated sockets.
+    pub fn with_pool(mut self, pool: Option<IlpConnectionPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Write into `table` instead of the record type's own table, e.g. for a
+    /// sampled copy of the traffic; `None` keeps the record type's table.
+    pub fn with_table(mut self, table: Option<&str>) -> Self {
+        self.table = table.map(Arc::from);
+        self
+    }
+
     async fn connect(&self) -> Result<TcpStream, PipelineError> {
-        let stream = TcpStream::connect(self.addr)
-            .await
-            .map_err(|e| PipelineError::Sink(format!("failed to connect to QuestDB ILP: {e}")))?;
-        let _ = stream.set_nodelay(true);
-        Ok(stream)
+        connect_ilp(self.addr).await
+    }
+
+    /// Replace a connection whose write failed.
+    ///
+    /// In strict mode the retried batch is only sent once the server closed
+    /// the failed connection, so lines of the failed attempt that it still
+    /// buffered can't be applied after the retry.
+    async fn reconnect(&self, stream: &mut TcpStream) -> Result<(), PipelineError> {
+        if self.ordering == OrderingMode::Strict {
+            confirm_closed(stream).await?;
+        }
+        *stream = self.connect().await?;
+        Ok(())
+    }
+
+    /// The shared pool to write through, if batches may go over any connection.
+    fn shared_pool(&self) -> Option<&IlpConnectionPool> {
+        self.pool.as_ref().filter(|_| self.ordering == OrderingMode::Relaxed)
     }
 }
 
@@ -222,13 +877,7 @@ where
     T: IlpEncode,
 {
     fn encode_batch(&self, batch: &[Envelope<T>]) -> Vec<u8> {
-        // Heuristic capacity: ~160 bytes per line.
-        let mut s = String::with_capacity(batch.len().saturating_mul(160));
-        for env in batch {
-            env.payload.write_ilp_line(&mut s);
-            s.push('\n');
-        }
-        s.into_bytes()
+        encode_ilp_batch(self.protocol, self.event_id, self.table.as_ref(), batch)
     }
 
     async fn flush_batch(&self, stream: &mut TcpStream, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
@@ -240,17 +889,30 @@ where
 
         let mut attempt: u32 = 0;
         loop {
+            let started = std::time::Instant::now();
             match stream.write_all(&payload).await {
                 Ok(()) => {
+                    metrics::histogram!("questdb_ilp_write_seconds").record(started.elapsed().as_secs_f64());
                     metrics::counter!("questdb_ingested_records_total").increment(batch.len() as u64);
                     metrics::counter!("questdb_ilp_bytes_total").increment(payload.len() as u64);
 
                     if let Some(min_received) = batch.iter().map(|e| e.received_at).min() {
+                        if let Some(lag) = &self.lag {
+                            lag.record_flush(min_received);
+                        }
                         if let Ok(dur) = SystemTime::now().duration_since(min_received) {
                             metrics::histogram!("ingest_end_to_end_latency_seconds").record(dur.as_secs_f64());
                         }
                     }
 
+                    if let Some(sampler) = &self.latency_sampler {
+                        sampler.record(batch);
+                    }
+
+                    for env in batch {
+                        env.complete();
+                    }
+
                     return Ok(());
                 }
                 Err(e) if attempt < self.max_retries => {
@@ -264,7 +926,7 @@ where
                     metrics::counter!("questdb_ilp_retry_total").increment(1);
 
                     tokio::time::sleep(sleep_for).await;
-                    *stream = self.connect().await?;
+                    self.reconnect(stream).await?;
                 }
                 Err(e) => {
                     tracing::error!(error = %e, "QuestDB ILP flush failed, giving up");
@@ -274,6 +936,22 @@ where
             }
         }
     }
+
+    /// Flush over the sink's own connection, or over a pooled one if it has none.
+    async fn flush(&self, dedicated: &mut Option<TcpStream>, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
+        match (dedicated.as_mut(), self.shared_pool()) {
+            (Some(stream), _) => self.flush_batch(stream, batch).await,
+            (None, Some(pool)) if !batch.is_empty() => {
+                let mut conn = pool.acquire().await?;
+                let res = self.flush_batch(conn.stream(), batch).await;
+                if res.is_err() {
+                    conn.discard();
+                }
+                res
+            }
+            (None, _) => Ok(()),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -287,7 +965,10 @@ where
     {
         use tokio::time::MissedTickBehavior;
 
-        let mut stream = self.connect().await?;
+        let mut stream = match self.shared_pool() {
+            Some(_) => None,
+            None => Some(self.connect().await?),
+        };
         let mut buffer: Vec<Envelope<T>> = Vec::with_capacity(self.batch_size);
 
         let mut ticker = tokio::time::interval(self.max_batch_linger);
@@ -300,7 +981,7 @@ where
                         Some(Ok(env)) => {
                             buffer.push(env);
                             if buffer.len() >= self.batch_size {
-                                self.flush_batch(&mut stream, &buffer).await?;
+                                self.flush(&mut stream, &buffer).await?;
                                 buffer.clear();
                             }
                         }
@@ -312,7 +993,7 @@ where
                 }
                 _ = ticker.tick() => {
                     if !buffer.is_empty() {
-                        self.flush_batch(&mut stream, &buffer).await?;
+                        self.flush(&mut stream, &buffer).await?;
                         buffer.clear();
                     }
                 }
@@ -320,17 +1001,19 @@ where
         }
 
         if !buffer.is_empty() {
-            self.flush_batch(&mut stream, &buffer).await?;
+            self.flush(&mut stream, &buffer).await?;
         }
 
-        // Best-effort flush.
-        let _ = stream.shutdown().await;
+        // Best-effort flush; pooled connections stay open for other sinks.
+        if let Some(stream) = stream.as_mut() {
+            let _ = stream.shutdown().await;
+        }
 
         Ok(())
     }
 }
 
-trait ShardKey {
+pub trait ShardKey {
     fn shard_key(&self) -> &str;
 }
 
@@ -346,6 +1029,30 @@ impl ShardKey for GenerationOutput {
     }
 }
 
+impl ShardKey for Meter {
+    fn shard_key(&self) -> &str {
+        &self.meter_id
+    }
+}
+
+impl ShardKey for MeterExchange {
+    fn shard_key(&self) -> &str {
+        &self.premise_id
+    }
+}
+
+impl ShardKey for DrEvent {
+    fn shard_key(&self) -> &str {
+        &self.event_id
+    }
+}
+
+impl ShardKey for Customer {
+    fn shard_key(&self) -> &str {
+        &self.customer_id
+    }
+}
+
 fn shard_index(key: &str, workers: usize) -> usize {
     use std::hash::{Hash, Hasher};
 
@@ -361,6 +1068,13 @@ pub struct QuestDbIlpParallelSink<T> {
     retry_backoff: Duration,
     max_batch_linger: Duration,
     workers: usize,
+    ordering: OrderingMode,
+    protocol: IlpProtocolVersion,
+    event_id: EventIdStrategy,
+    lag: Option<SinkLag>,
+    latency_sampler: Option<LatencySampler>,
+    pool: Option<IlpConnectionPool>,
+    table: Option<Arc<str>>,
     _marker: PhantomData<fn() -> T>,
 }
 
@@ -380,9 +1094,60 @@ impl<T> QuestDbIlpParallelSink<T> {
             retry_backoff,
             max_batch_linger,
             workers: workers.max(1),
+            ordering: OrderingMode::Relaxed,
+            protocol: IlpProtocolVersion::V1,
+            event_id: EventIdStrategy::ContentHash,
+            lag: None,
+            latency_sampler: None,
+            pool: None,
+            table: None,
             _marker: PhantomData,
         }
     }
+
+    pub fn with_ordering(mut self, ordering: OrderingMode) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// ILP dialect to emit; see [`IlpProtocolVersion::for_server`].
+    pub fn with_protocol(mut self, protocol: IlpProtocolVersion) -> Self {
+        self.protocol = protocol;
+        self
+    }
+
+    /// How `event_id` tags are derived; only meter usage and generation output carry one.
+    pub fn with_event_id(mut self, strategy: EventIdStrategy) -> Self {
+        self.event_id = strategy;
+        self
+    }
+
+    /// Publish flush latency to `lag`, e.g. the source's [`SinkLag`] for bulk shedding.
+    pub fn with_lag(mut self, lag: SinkLag) -> Self {
+        self.lag = Some(lag);
+        self
+    }
+
+    /// Sample per-record end-to-end latency of flushed records; see [`LatencySampler`].
+    pub fn with_latency_sampler(mut self, sampler: LatencySampler) -> Self {
+        self.latency_sampler = Some(sampler);
+        self
+    }
+
+    /// Write batches over connections of a shared [`IlpConnectionPool`]
+    /// instead of one socket per worker. Ignored in strict ordering mode, which
+    /// needs its own connection; `None` keeps the dedicated sockets.
+    pub fn with_pool(mut self, pool: Option<IlpConnectionPool>) -> Self {
+        self.pool = pool;
+        self
+    }
+
+    /// Write into `table` instead of the record type's own table, e.g. for a
+    /// sampled copy of the traffic; `None` keeps the record type's table.
+    pub fn with_table(mut self, table: Option<&str>) -> Self {
+        self.table = table.map(Arc::from);
+        self
+    }
 }
 
 #[async_trait::async_trait]
@@ -401,13 +1166,20 @@ where
             let (tx, rx) = tokio::sync::mpsc::channel::<Envelope<T>>(self.batch_size.saturating_mul(2));
             txs.push(tx);
 
-            let sink = QuestDbIlpSink::<T>::new(
+            let mut sink = QuestDbIlpSink::<T>::new(
                 self.addr,
                 self.batch_size,
                 self.max_retries,
                 self.retry_backoff,
                 self.max_batch_linger,
-            );
+            )
+            .with_ordering(self.ordering)
+            .with_protocol(self.protocol)
+            .with_event_id(self.event_id);
+            sink.lag = self.lag.clone();
+            sink.latency_sampler = self.latency_sampler.clone();
+            sink.pool = self.pool.clone();
+            sink.table = self.table.clone();
             let stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok);
 
             joins.push(tokio::spawn(async move { sink.run(stream).await }));
@@ -444,17 +1216,29 @@ where
 
 pub type QuestDbIlpMeterUsageSink = QuestDbIlpParallelSink<MeterUsage>;
 pub type QuestDbIlpGenerationSink = QuestDbIlpParallelSink<GenerationOutput>;
+pub type QuestDbIlpMeterSink = QuestDbIlpParallelSink<Meter>;
+pub type QuestDbIlpCustomerSink = QuestDbIlpParallelSink<Customer>;
+pub type QuestDbIlpMeterExchangeSink = QuestDbIlpParallelSink<MeterExchange>;
+pub type QuestDbIlpDrEventSink = QuestDbIlpParallelSink<DrEvent>;
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::Mutex;
     use time::macros::datetime;
+    use tokio::net::TcpListener;
+
+    fn v1_line<T: IlpEncode>(record: &T) -> String {
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V1);
+        record.write_ilp_line(&mut out);
+        String::from_utf8(out.into_bytes()).unwrap()
+    }
 
     #[test]
     fn ilp_escape_ident_escapes_commas_spaces_and_equals() {
-        let mut out = String::new();
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V1);
         ilp_escape_ident("a b,c=d", &mut out);
-        assert_eq!(out, "a\\ b\\,c\\=d");
+        assert_eq!(out.as_bytes(), b"a\\ b\\,c\\=d");
     }
 
     #[test]
@@ -468,17 +1252,60 @@ mod tests {
             kva_demand: None,
             quality_flag: None,
             source_system: None,
+            event_id: None,
+            phases: Default::default(),
         };
 
-        let mut a = String::new();
-        m.write_ilp_line(&mut a);
-        let mut b = String::new();
-        m.write_ilp_line(&mut b);
+        let a = v1_line(&m);
+        let b = v1_line(&m);
 
         assert!(a.contains("event_id="));
         assert_eq!(a, b);
     }
 
+    #[test]
+    fn event_id_strategies() {
+        let reading = |kwh: f64, event_id: Option<&str>| MeterUsage {
+            ts: datetime!(2024-01-01 00:00:00 UTC),
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: None,
+            event_id: event_id.map(str::to_string),
+            phases: Default::default(),
+        };
+        let id_of = |m: &MeterUsage, strategy: EventIdStrategy| {
+            let mut out = IlpBuffer::new(IlpProtocolVersion::V1).with_event_id(strategy);
+            m.write_ilp_line(&mut out);
+            let line = String::from_utf8(out.into_bytes()).unwrap();
+            let start = line.find("event_id=").unwrap() + "event_id=".len();
+            line[start..].split(',').next().unwrap().to_string()
+        };
+
+        let original = reading(1.0, None);
+        let corrected = reading(1.5, None);
+        assert_ne!(
+            id_of(&original, EventIdStrategy::ContentHash),
+            id_of(&corrected, EventIdStrategy::ContentHash)
+        );
+        assert_eq!(
+            id_of(&original, EventIdStrategy::NaturalKey),
+            id_of(&corrected, EventIdStrategy::NaturalKey)
+        );
+
+        assert_eq!(id_of(&reading(1.0, Some("abc")), EventIdStrategy::Client), "abc");
+        assert_eq!(
+            id_of(&original, EventIdStrategy::Client),
+            id_of(&original, EventIdStrategy::ContentHash)
+        );
+
+        let uuid = id_of(&original, EventIdStrategy::UuidV7);
+        assert_eq!(uuid::Uuid::parse_str(&uuid).unwrap().get_version_num(), 7);
+    }
+
     #[test]
     fn meter_usage_ilp_line_includes_required_fields_and_tags() {
         let m = MeterUsage {
@@ -490,10 +1317,11 @@ mod tests {
             kva_demand: Some(2.0),
             quality_flag: Some("ok".to_string()),
             source_system: None,
+            event_id: None,
+            phases: Default::default(),
         };
 
-        let mut line = String::new();
-        m.write_ilp_line(&mut line);
+        let line = v1_line(&m);
 
         assert!(line.starts_with("meter_usage,"));
         assert!(line.contains("meter_id=m\\ 1"));
@@ -505,6 +1333,28 @@ mod tests {
         // Timestamp should be nanos.
         let ts_nanos = ts_to_unix_nanos(m.ts).to_string();
         assert!(line.ends_with(&ts_nanos));
+        assert!(!line.contains("ingested_at="));
+
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_micros(1_704_067_260_000_123);
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V1);
+        m.write_ilp_line_received(received_at, None, &mut out);
+        let line = String::from_utf8(out.into_bytes()).unwrap();
+        assert!(line.contains(",ingested_at=1704067260000123t "));
+        assert!(line.ends_with(&ts_nanos));
+        assert!(!line.contains("tenant="));
+
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V1);
+        m.write_ilp_line_received(received_at, Some("co-op a"), &mut out);
+        let line = String::from_utf8(out.into_bytes()).unwrap();
+        assert!(line.contains(",tenant=co-op\\ a "));
+
+        // A table override (sampling mode) only changes the measurement.
+        let batch = [Envelope::new(m)];
+        let table = Arc::from("meter_usage_sample");
+        let sampled = encode_ilp_batch(IlpProtocolVersion::V1, EventIdStrategy::ContentHash, Some(&table), &batch);
+        let plain = encode_ilp_batch(IlpProtocolVersion::V1, EventIdStrategy::ContentHash, None, &batch);
+        assert!(sampled.starts_with(b"meter_usage_sample,"));
+        assert_eq!(sampled["meter_usage_sample".len()..], plain["meter_usage".len()..]);
     }
 
     #[test]
@@ -517,10 +1367,13 @@ mod tests {
             mvar: None,
             status: None,
             fuel_type: Some("gas".to_string()),
+            event_id: None,
+            aux_mw: None,
+            availability_pct: None,
+            curtailed_mw: None,
         };
 
-        let mut line = String::new();
-        g.write_ilp_line(&mut line);
+        let line = v1_line(&g);
 
         assert!(line.starts_with("generation_output,"));
         assert!(line.contains("plant_id=plant"));
@@ -529,5 +1382,166 @@ mod tests {
         assert!(line.contains("fuel_type=gas"));
         assert!(line.contains(" mw=10"));
         assert!(!line.contains("mvar="));
+        assert!(!line.contains("aux_mw="));
+
+        let g = GenerationOutput {
+            aux_mw: Some(0.5),
+            curtailed_mw: Some(2.0),
+            ..g
+        };
+        let line = v1_line(&g);
+        assert!(line.contains(" mw=10,aux_mw=0.5,curtailed_mw=2 "));
+        assert!(!line.contains("availability_pct="));
+    }
+
+    #[test]
+    fn meter_exchange_ilp_line_tags_both_meters() {
+        let e = MeterExchange {
+            ts: datetime!(2024-03-01 12:00:00 UTC),
+            premise_id: "p-1".to_string(),
+            old_meter_id: "m-1".to_string(),
+            new_meter_id: "m-2".to_string(),
+            reason: "exchange".to_string(),
+        };
+
+        let line = v1_line(&e);
+
+        assert_eq!(
+            line,
+            format!(
+                "meter_exchanges,premise_id=p-1,old_meter_id=m-1,new_meter_id=m-2 reason=\"exchange\" {}",
+                ts_to_unix_nanos(e.ts)
+            )
+        );
+    }
+
+    #[test]
+    fn dr_event_ilp_writes_one_line_per_meter() {
+        let e = DrEvent {
+            event_id: "e-1".to_string(),
+            program: "peak-saver".to_string(),
+            start: datetime!(2024-07-01 17:00:00 UTC),
+            end: datetime!(2024-07-01 19:00:00 UTC),
+            meter_ids: vec!["m-1".to_string(), "m-2".to_string()],
+        };
+
+        let out = v1_line(&e);
+
+        let lines: Vec<_> = out.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("dr_events,event_id=e-1,program=peak-saver,meter_id=m-1 end_ts=1719860400000000t "));
+        assert!(lines[1].contains(",meter_id=m-2 "));
+        assert!(lines[1].ends_with(&ts_to_unix_nanos(e.start).to_string()));
+    }
+
+    #[test]
+    fn meter_event_ilp_line() {
+        let e = MeterEvent {
+            ts: datetime!(2024-07-01 17:00:00 UTC),
+            meter_id: "m-1".to_string(),
+            event_type: "tamper".to_string(),
+            details: "meter=m-1 event=\"COVER OPEN\"".to_string(),
+        };
+
+        assert_eq!(
+            v1_line(&e),
+            format!(
+                "meter_events,meter_id=m-1,event_type=tamper details=\"meter=m-1 event=\\\"COVER OPEN\\\"\" {}",
+                ts_to_unix_nanos(e.ts)
+            )
+        );
+    }
+
+    #[test]
+    fn customer_ilp_line_quotes_string_fields_and_encodes_soft_delete() {
+        let c = Customer {
+            effective_from: datetime!(2024-01-01 00:00:00 UTC),
+            customer_id: "c-1".to_string(),
+            segment: Some("res".to_string()),
+            name: Some("Ann \"A\" Smith".to_string()),
+            region_id: None,
+            lat: None,
+            lon: None,
+            deleted: true,
+        };
+
+        let line = v1_line(&c);
+
+        assert!(line.starts_with("customers,customer_id=c-1,segment=res "));
+        assert!(line.contains(" deleted=t,name=\"Ann \\\"A\\\" Smith\""));
+        assert!(line.ends_with(&ts_to_unix_nanos(c.effective_from).to_string()));
+    }
+
+    #[test]
+    fn protocol_v2_is_used_from_questdb_9() {
+        let v = |major, minor| Some(ServerVersion { major, minor, patch: 0 });
+        assert_eq!(IlpProtocolVersion::for_server(v(8, 3)), IlpProtocolVersion::V1);
+        assert_eq!(IlpProtocolVersion::for_server(v(9, 0)), IlpProtocolVersion::V2);
+        assert_eq!(IlpProtocolVersion::for_server(None), IlpProtocolVersion::V1);
+    }
+
+    #[test]
+    fn v2_writes_micro_designated_timestamps_and_binary_arrays() {
+        let ts = datetime!(2024-01-01 00:00:00.000001 UTC);
+
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V2);
+        out.push_str("phases");
+        out.push(' ');
+        let mut first = true;
+        push_field_f64_array(&mut out, &mut first, "kv", &[1.0, 2.5]);
+        push_designated_ts(&mut out, ts);
+
+        let mut expected = b"phases kv==".to_vec();
+        expected.extend_from_slice(&[14, 10, 1, 2, 0, 0, 0]);
+        expected.extend_from_slice(&1.0f64.to_le_bytes());
+        expected.extend_from_slice(&2.5f64.to_le_bytes());
+        expected.extend_from_slice(b" 1704067200000001t");
+        assert_eq!(out.as_bytes(), expected.as_slice());
+
+        let mut out = IlpBuffer::new(IlpProtocolVersion::V1);
+        out.push_str("phases");
+        out.push(' ');
+        let mut first = true;
+        push_field_f64_array(&mut out, &mut first, "kv", &[1.0, 2.5]);
+        push_designated_ts(&mut out, ts);
+        assert_eq!(out.as_bytes(), b"phases kv_0=1,kv_1=2.5 1704067200000001000");
+    }
+
+    /// Server events around a sink replacing a failed connection; the server
+    /// takes 100 ms to finish the old connection once the client closed it.
+    async fn reconnect_events(ordering: OrderingMode) -> Vec<&'static str> {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+
+        let server_events = events.clone();
+        let server = tokio::spawn(async move {
+            let (mut old, _) = listener.accept().await.unwrap();
+            let closing_events = server_events.clone();
+            let closing = tokio::spawn(async move {
+                let _ = old.read_to_end(&mut Vec::new()).await;
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                closing_events.lock().unwrap().push("old closed");
+            });
+            let _new = listener.accept().await.unwrap();
+            server_events.lock().unwrap().push("new opened");
+            closing.await.unwrap();
+        });
+
+        let sink = QuestDbIlpSink::<MeterUsage>::new(addr, 1, 1, Duration::ZERO, Duration::from_secs(1))
+            .with_ordering(ordering);
+        let mut stream = sink.connect().await.unwrap();
+        sink.reconnect(&mut stream).await.unwrap();
+        server.await.unwrap();
+
+        let events = events.lock().unwrap().clone();
+        events
+    }
+
+    #[tokio::test]
+    async fn strict_ordering_retries_only_after_the_server_closed_the_failed_connection() {
+        assert_eq!(reconnect_events(OrderingMode::Strict).await, ["old closed", "new opened"]);
+        // Relaxed retries race the lines still pending on the old connection.
+        assert_eq!(reconnect_events(OrderingMode::Relaxed).await, ["new opened", "old closed"]);
     }
 }
@@ -0,0 +1,237 @@
+use std::{marker::PhantomData, time::Duration};
+
+use futures::StreamExt;
+
+use crate::pipeline::{Envelope, LatencySampler, PipelineError, Sink, SinkLag};
+
+/// A time-series database a sink writes to.
+///
+/// Stores only know how to talk to their database; batching, retries, metrics
+/// and completion are handled by [`StoreSink`]. Implementations exist for
+/// QuestDB (ILP and pgwire) and ClickHouse.
+#[async_trait::async_trait]
+pub trait TimeSeriesStore<T>: Send + Sync {
+    /// Short name used in logs and metric labels.
+    fn name(&self) -> &'static str;
+
+    /// Create the target table if it doesn't exist.
+    async fn ensure_schema(&self) -> Result<(), PipelineError>;
+
+    /// Write one batch. A failed batch is retried as a whole, so stores should
+    /// tolerate (or dedup) rows written by an earlier partial attempt.
+    async fn insert_batch(&self, batch: &[Envelope<T>]) -> Result<(), PipelineError>;
+
+    /// Check that the store is reachable.
+    async fn health(&self) -> Result<(), PipelineError>;
+}
+
+/// Generic batching sink over a [`TimeSeriesStore`].
+///
+/// On start it checks the store's health and ensures its schema (fail-fast),
+/// then flushes full batches and, after `max_batch_linger`, partial ones.
+pub struct StoreSink<T, S> {
+    store: S,
+    batch_size: usize,
+    max_retries: u32,
+    retry_backoff: Duration,
+    max_batch_linger: Duration,
+    lag: Option<SinkLag>,
+    latency_sampler: Option<LatencySampler>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T, S> StoreSink<T, S>
+where
+    S: TimeSeriesStore<T>,
+{
+    pub fn new(
+        store: S,
+        batch_size: usize,
+        max_retries: u32,
+        retry_backoff: Duration,
+        max_batch_linger: Duration,
+    ) -> Self {
+        Self {
+            store,
+            batch_size: batch_size.max(1),
+            max_retries,
+            retry_backoff,
+            max_batch_linger,
+            lag: None,
+            latency_sampler: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Publish flush latency to `lag`, e.g. the source's [`SinkLag`] for bulk shedding.
+    pub fn with_lag(mut self, lag: SinkLag) -> Self {
+        self.lag = Some(lag);
+        self
+    }
+
+    /// Sample per-record end-to-end latency of flushed records; see [`LatencySampler`].
+    pub fn with_latency_sampler(mut self, sampler: LatencySampler) -> Self {
+        self.latency_sampler = Some(sampler);
+        self
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    async fn flush_batch(&self, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+
+        let store = self.store.name();
+        let mut attempt: u32 = 0;
+        loop {
+            match self.store.insert_batch(batch).await {
+                Ok(()) => {
+                    metrics::counter!("store_ingested_records_total", "store" => store).increment(batch.len() as u64);
+
+                    if let Some(min_received) = batch.iter().map(|e| e.received_at).min() {
+                        if let Some(lag) = &self.lag {
+                            lag.record_flush(min_received);
+                        }
+                        if let Ok(dur) = std::time::SystemTime::now().duration_since(min_received) {
+                            metrics::histogram!("ingest_end_to_end_latency_seconds").record(dur.as_secs_f64());
+                        }
+                    }
+
+                    if let Some(sampler) = &self.latency_sampler {
+                        sampler.record(batch);
+                    }
+
+                    for env in batch {
+                        env.complete();
+                    }
+
+                    return Ok(());
+                }
+                Err(e) if attempt < self.max_retries => {
+                    attempt += 1;
+                    tracing::warn!(error = %e, attempt, store, "store flush failed, retrying with backoff");
+                    metrics::counter!("store_sink_retry_total", "store" => store).increment(1);
+                    tokio::time::sleep(self.retry_backoff * attempt).await;
+                }
+                Err(e) => {
+                    tracing::error!(error = %e, store, "store flush failed, giving up");
+                    metrics::counter!("store_sink_errors_total", "store" => store).increment(1);
+                    return Err(e);
+                }
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T, S> Sink<T> for StoreSink<T, S>
+where
+    T: Send + Sync + 'static,
+    S: TimeSeriesStore<T>,
+{
+    async fn run<I>(&self, mut input: I) -> Result<(), PipelineError>
+    where
+        I: futures::Stream<Item = Result<Envelope<T>, PipelineError>> + Send + Unpin + 'static,
+    {
+        use tokio::time::MissedTickBehavior;
+
+        self.store.health().await?;
+        self.store.ensure_schema().await?;
+
+        let mut buffer: Vec<Envelope<T>> = Vec::with_capacity(self.batch_size);
+        let mut ticker = tokio::time::interval(self.max_batch_linger);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                maybe_item = input.next() => {
+                    match maybe_item {
+                        Some(Ok(env)) => {
+                            buffer.push(env);
+                            if buffer.len() >= self.batch_size {
+                                self.flush_batch(&buffer).await?;
+                                buffer.clear();
+                            }
+                        }
+                        Some(Err(e)) => {
+                            tracing::error!(error = %e, store = self.store.name(), "error in upstream pipeline for StoreSink");
+                        }
+                        None => break,
+                    }
+                }
+                _ = ticker.tick() => {
+                    if !buffer.is_empty() {
+                        self.flush_batch(&buffer).await?;
+                        buffer.clear();
+                    }
+                }
+            }
+        }
+
+        self.flush_batch(&buffer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// In-memory store failing the first `failures` inserts.
+    #[derive(Default)]
+    struct MemoryStore {
+        failures: Mutex<u32>,
+        rows: Mutex<Vec<u32>>,
+        schema_ensured: Mutex<bool>,
+    }
+
+    #[async_trait::async_trait]
+    impl TimeSeriesStore<u32> for MemoryStore {
+        fn name(&self) -> &'static str {
+            "memory"
+        }
+
+        async fn ensure_schema(&self) -> Result<(), PipelineError> {
+            *self.schema_ensured.lock().unwrap() = true;
+            Ok(())
+        }
+
+        async fn insert_batch(&self, batch: &[Envelope<u32>]) -> Result<(), PipelineError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Err(PipelineError::Sink("unavailable".to_string()));
+            }
+            self.rows.lock().unwrap().extend(batch.iter().map(|e| e.payload));
+            Ok(())
+        }
+
+        async fn health(&self) -> Result<(), PipelineError> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn retries_failed_batches_and_completes_envelopes() {
+        let store = MemoryStore {
+            failures: Mutex::new(1),
+            ..Default::default()
+        };
+        let sink = StoreSink::new(store, 2, 3, Duration::from_millis(1), Duration::from_secs(60));
+
+        let (completion, ack) = crate::pipeline::Completion::oneshot();
+        let input = futures::stream::iter(vec![
+            Ok(Envelope::tracked(1, completion)),
+            Ok(Envelope::new(2)),
+            Ok(Envelope::new(3)),
+        ]);
+        sink.run(input).await.unwrap();
+
+        assert!(*sink.store().schema_ensured.lock().unwrap());
+        assert_eq!(*sink.store().rows.lock().unwrap(), [1, 2, 3]);
+        assert_eq!(ack.outcome().await, crate::pipeline::AckOutcome::Written);
+    }
+}
@@ -0,0 +1,306 @@
+use std::{fmt::Write as _, marker::PhantomData, time::SystemTime};
+
+use rust_client::domain::{GenerationOutput, MeterUsage, PhaseChannels};
+use sqlx::postgres::PgPool;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::pipeline::{Envelope, PipelineError};
+
+use super::store::TimeSeriesStore;
+
+/// A record type that can be written to TimescaleDB / Postgres via `COPY ... FORMAT csv`.
+pub trait TimescaleRow {
+    /// Target table.
+    const TABLE: &'static str;
+
+    /// Column definitions for `CREATE TABLE`, in the order of [`TimescaleRow::columns`].
+    const COLUMN_DEFS: &'static str;
+
+    /// Natural key; a unique index on it lets retried batches be skipped.
+    const KEY: &'static str;
+
+    /// Columns written by [`TimescaleRow::write_csv`].
+    fn columns() -> Vec<&'static str>;
+
+    /// Append one CSV record (without trailing newline), with the time it was
+    /// received and the tenant of the key that sent it where the table has them.
+    fn write_csv(&self, received_at: SystemTime, tenant: Option<&str>, out: &mut String);
+}
+
+fn csv_str(out: &mut String, v: Option<&str>) {
+    // Unquoted empty is NULL in Postgres CSV; quoted empty is the empty string.
+    if let Some(v) = v {
+        out.push('"');
+        out.push_str(&v.replace('"', "\"\""));
+        out.push('"');
+    }
+}
+
+fn csv_f64(out: &mut String, v: Option<f64>) {
+    if let Some(v) = v {
+        let _ = write!(out, "{v}");
+    }
+}
+
+fn csv_ts(out: &mut String, ts: OffsetDateTime) {
+    out.push_str(&ts.format(&Rfc3339).expect("RFC 3339 timestamps within year 0..=9999"));
+}
+
+impl TimescaleRow for MeterUsage {
+    const TABLE: &'static str = "meter_usage";
+
+    const COLUMN_DEFS: &'static str = "ts TIMESTAMPTZ NOT NULL, event_id TEXT, meter_id TEXT NOT NULL, premise_id TEXT, \
+        kwh DOUBLE PRECISION NOT NULL, kvarh DOUBLE PRECISION, kva_demand DOUBLE PRECISION, quality_flag TEXT, \
+        source_system TEXT, tenant TEXT, ingested_at TIMESTAMPTZ, \
+        kwh_phase_a DOUBLE PRECISION, kwh_phase_b DOUBLE PRECISION, kwh_phase_c DOUBLE PRECISION, \
+        current_phase_a DOUBLE PRECISION, current_phase_b DOUBLE PRECISION, current_phase_c DOUBLE PRECISION, \
+        voltage_phase_a DOUBLE PRECISION, voltage_phase_b DOUBLE PRECISION, voltage_phase_c DOUBLE PRECISION";
+
+    const KEY: &'static str = "meter_id, ts";
+
+    fn columns() -> Vec<&'static str> {
+        let mut cols = vec![
+            "ts",
+            "event_id",
+            "meter_id",
+            "premise_id",
+            "kwh",
+            "kvarh",
+            "kva_demand",
+            "quality_flag",
+            "source_system",
+            "tenant",
+            "ingested_at",
+        ];
+        cols.extend(PhaseChannels::COLUMNS);
+        cols
+    }
+
+    fn write_csv(&self, received_at: SystemTime, tenant: Option<&str>, out: &mut String) {
+        csv_ts(out, self.ts);
+        out.push(',');
+        csv_str(out, self.event_id.as_deref());
+        out.push(',');
+        csv_str(out, Some(&self.meter_id));
+        out.push(',');
+        csv_str(out, self.premise_id.as_deref());
+        out.push(',');
+        csv_f64(out, Some(self.kwh));
+        out.push(',');
+        csv_f64(out, self.kvarh);
+        out.push(',');
+        csv_f64(out, self.kva_demand);
+        out.push(',');
+        csv_str(out, self.quality_flag.as_deref());
+        out.push(',');
+        csv_str(out, self.source_system.as_deref());
+        out.push(',');
+        csv_str(out, tenant);
+        out.push(',');
+        csv_ts(out, received_at.into());
+        for v in self.phases.values() {
+            out.push(',');
+            csv_f64(out, v);
+        }
+    }
+}
+
+impl TimescaleRow for GenerationOutput {
+    const TABLE: &'static str = "generation_output";
+
+    // `unit_id` is '' rather than NULL for plant-level rows so it can be part of the unique key.
+    const COLUMN_DEFS: &'static str = "ts TIMESTAMPTZ NOT NULL, event_id TEXT, plant_id TEXT NOT NULL, \
+        unit_id TEXT NOT NULL DEFAULT '', mw DOUBLE PRECISION NOT NULL, mvar DOUBLE PRECISION, status TEXT, \
+        fuel_type TEXT, aux_mw DOUBLE PRECISION, availability_pct DOUBLE PRECISION, curtailed_mw DOUBLE PRECISION";
+
+    const KEY: &'static str = "plant_id, unit_id, ts";
+
+    fn columns() -> Vec<&'static str> {
+        vec![
+            "ts",
+            "event_id",
+            "plant_id",
+            "unit_id",
+            "mw",
+            "mvar",
+            "status",
+            "fuel_type",
+            "aux_mw",
+            "availability_pct",
+            "curtailed_mw",
+        ]
+    }
+
+    fn write_csv(&self, _received_at: SystemTime, _tenant: Option<&str>, out: &mut String) {
+        csv_ts(out, self.ts);
+        out.push(',');
+        csv_str(out, self.event_id.as_deref());
+        out.push(',');
+        csv_str(out, Some(&self.plant_id));
+        out.push(',');
+        csv_str(out, Some(self.unit_id.as_deref().unwrap_or_default()));
+        out.push(',');
+        csv_f64(out, Some(self.mw));
+        out.push(',');
+        csv_f64(out, self.mvar);
+        out.push(',');
+        csv_str(out, self.status.as_deref());
+        out.push(',');
+        csv_str(out, self.fuel_type.as_deref());
+        out.push(',');
+        csv_f64(out, self.aux_mw);
+        out.push(',');
+        csv_f64(out, self.availability_pct);
+        out.push(',');
+        csv_f64(out, self.curtailed_mw);
+    }
+}
+
+fn encode_csv<T: TimescaleRow>(batch: &[Envelope<T>]) -> String {
+    let mut out = String::with_capacity(batch.len().saturating_mul(128));
+    for env in batch {
+        env.payload
+            .write_csv(env.received_at, env.tenant.as_deref(), &mut out);
+        out.push('\n');
+    }
+    out
+}
+
+/// TimescaleDB or vanilla Postgres.
+///
+/// Batches are `COPY`'d into a session-local staging table and moved over with
+/// `INSERT ... ON CONFLICT DO NOTHING`, so retried batches don't duplicate rows.
+/// Tables become hypertables when the `timescaledb` extension is installed.
+pub struct TimescaleStore<T> {
+    pool: PgPool,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> TimescaleStore<T> {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            _marker: PhantomData,
+        }
+    }
+}
+
+fn sink_err(context: &str) -> impl FnOnce(sqlx::Error) -> PipelineError + '_ {
+    move |e| PipelineError::Sink(format!("timescale {context}: {e}"))
+}
+
+#[async_trait::async_trait]
+impl<T> TimeSeriesStore<T> for TimescaleStore<T>
+where
+    T: TimescaleRow + Send + Sync + 'static,
+{
+    fn name(&self) -> &'static str {
+        "timescale"
+    }
+
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        let table = T::TABLE;
+        sqlx::query(&format!("CREATE TABLE IF NOT EXISTS {table} ({})", T::COLUMN_DEFS))
+            .execute(&self.pool)
+            .await
+            .map_err(sink_err("schema"))?;
+        sqlx::query(&format!("CREATE UNIQUE INDEX IF NOT EXISTS {table}_key ON {table} ({})", T::KEY))
+            .execute(&self.pool)
+            .await
+            .map_err(sink_err("schema"))?;
+
+        let timescale: Option<i32> = sqlx::query_scalar("SELECT 1 FROM pg_extension WHERE extname = 'timescaledb'")
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(sink_err("schema"))?;
+        if timescale.is_some() {
+            sqlx::query("SELECT create_hypertable($1::text::regclass, 'ts', if_not_exists => TRUE, migrate_data => TRUE)")
+                .bind(table)
+                .execute(&self.pool)
+                .await
+                .map_err(sink_err("schema"))?;
+        } else {
+            tracing::info!(table, "timescaledb extension not installed, using a plain Postgres table");
+        }
+        Ok(())
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<T>]) -> Result<(), PipelineError> {
+        let table = T::TABLE;
+        let columns = T::columns().join(", ");
+        let csv = encode_csv(batch);
+
+        let mut tx = self.pool.begin().await.map_err(sink_err("insert"))?;
+        sqlx::query(&format!(
+            "CREATE TEMP TABLE IF NOT EXISTS {table}_stage (LIKE {table} INCLUDING DEFAULTS) ON COMMIT DELETE ROWS"
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(sink_err("insert"))?;
+
+        let mut copy = tx
+            .copy_in_raw(&format!("COPY {table}_stage ({columns}) FROM STDIN WITH (FORMAT csv)"))
+            .await
+            .map_err(sink_err("copy"))?;
+        if let Err(e) = copy.send(csv.as_bytes()).await {
+            let _ = copy.abort("send failed").await;
+            return Err(sink_err("copy")(e));
+        }
+        copy.finish().await.map_err(sink_err("copy"))?;
+
+        sqlx::query(&format!(
+            "INSERT INTO {table} ({columns}) SELECT {columns} FROM {table}_stage ON CONFLICT DO NOTHING"
+        ))
+        .execute(&mut *tx)
+        .await
+        .map_err(sink_err("insert"))?;
+        tx.commit().await.map_err(sink_err("insert"))
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        sqlx::query("SELECT 1")
+            .execute(&self.pool)
+            .await
+            .map(|_| ())
+            .map_err(sink_err("health check"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn csv_quotes_text_and_leaves_missing_values_null() {
+        let m = MeterUsage {
+            ts: datetime!(2024-05-01 00:15:00 UTC),
+            meter_id: "M-\"1\",a".to_string(),
+            premise_id: None,
+            kwh: 1.25,
+            kvarh: None,
+            kva_demand: Some(3.0),
+            quality_flag: Some(String::new()),
+            source_system: None,
+            event_id: None,
+            phases: Default::default(),
+        };
+        let env = Envelope {
+            payload: m,
+            received_at: SystemTime::UNIX_EPOCH,
+            completion: None,
+            tenant: None,
+            source_identity: None,
+        };
+        let tenant_env = Envelope::new(env.payload.clone()).with_tenant(Some("co-op a".into()));
+
+        let csv = encode_csv(&[env, tenant_env]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "2024-05-01T00:15:00Z,,\"M-\"\"1\"\",a\",,1.25,,3,\"\",,,1970-01-01T00:00:00Z,,,,,,,,,"
+        );
+        assert_eq!(lines[0].split(',').count(), MeterUsage::columns().len() + 1);
+        assert!(lines[1].contains(",\"\",,\"co-op a\","));
+    }
+}
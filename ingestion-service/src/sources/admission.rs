@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+use axum::http::{HeaderMap, StatusCode};
+
+use crate::{
+    auth::{self, authorize_bearer, bearer_token},
+    config::{HttpSourceConfig, Role},
+    pipeline::{LaneSender, Priority, SinkLag},
+    sources::http_error::ApiError,
+    tls::ClientIdentity,
+};
+
+/// Header clients use to mark a request as bulk (`bulk`) or real-time (anything else).
+pub const PRIORITY_HEADER: &str = "x-ingest-priority";
+
+/// Priority-aware admission control for an HTTP ingest source.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Admission {
+    /// Requests authenticated with this token are bulk.
+    bulk_auth_bearer_token: Option<String>,
+    /// Shed bulk requests while the sink lags more than this.
+    shed_bulk_lag: Option<Duration>,
+    lag: SinkLag,
+}
+
+impl Admission {
+    pub(crate) fn from_config(cfg: &HttpSourceConfig, lag: SinkLag) -> Self {
+        Self {
+            bulk_auth_bearer_token: cfg.bulk_auth_bearer_token.clone(),
+            shed_bulk_lag: cfg.shed_bulk_lag_ms.map(Duration::from_millis),
+            lag,
+        }
+    }
+
+    /// Authorize an ingest request against the bulk token, `token` or an API
+    /// key, and classify it. Requests without a token are admitted on a
+    /// verified client certificate (`identity`). Tenant keys are refused.
+    pub(crate) fn authorize(
+        &self,
+        headers: &HeaderMap,
+        identity: Option<&ClientIdentity>,
+        token: &Option<String>,
+        metric_name: &'static str,
+    ) -> Result<Priority, StatusCode> {
+        let (priority, tenant) = self.authorize_scoped(headers, identity, token, metric_name)?;
+        auth::unscoped(tenant, Role::Ingest)?;
+        Ok(priority)
+    }
+
+    /// Like [`Self::authorize`], but admit tenant keys too and return their
+    /// tenant, which the caller must enforce on every record.
+    pub(crate) fn authorize_scoped(
+        &self,
+        headers: &HeaderMap,
+        identity: Option<&ClientIdentity>,
+        token: &Option<String>,
+        metric_name: &'static str,
+    ) -> Result<(Priority, Option<String>), StatusCode> {
+        let given = bearer_token(headers);
+        if let Some(bulk_token) = &self.bulk_auth_bearer_token {
+            if given == Some(bulk_token.as_str()) {
+                return Ok((Priority::Bulk, None));
+            }
+        }
+
+        let tenant = match (given, identity) {
+            (None, Some(_)) => None,
+            _ => authorize_bearer(given, token, Role::Ingest, metric_name)?,
+        };
+
+        let bulk = headers
+            .get(PRIORITY_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.trim().eq_ignore_ascii_case("bulk"));
+        Ok((if bulk { Priority::Bulk } else { Priority::RealTime }, tenant))
+    }
+
+    /// Reject bulk requests with 503 while records wait in `tx` and the sink lags
+    /// more than the configured threshold. Real-time requests are always admitted.
+    pub(crate) fn admit<T>(&self, priority: Priority, tx: &LaneSender<T>) -> Result<(), ApiError> {
+        let Some(threshold) = self.shed_bulk_lag else {
+            return Ok(());
+        };
+        if priority != Priority::Bulk {
+            return Ok(());
+        }
+
+        let lag = self.lag.current(tx.is_backlogged());
+        if lag > threshold {
+            metrics::counter!("http_ingest_shed_bulk_total").increment(1);
+            return Err(ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                format!("sink lag {}ms over {}ms, bulk requests are shed", lag.as_millis(), threshold.as_millis()),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn admission(lag: SinkLag) -> Admission {
+        Admission {
+            bulk_auth_bearer_token: Some("bulk-secret".to_string()),
+            shed_bulk_lag: Some(Duration::from_secs(10)),
+            lag,
+        }
+    }
+
+    #[test]
+    fn classifies_by_bulk_token_or_header() {
+        let admission = admission(SinkLag::new());
+        let token = Some("secret".to_string());
+
+        let mut headers = HeaderMap::new();
+        headers.insert("authorization", "Bearer bulk-secret".parse().unwrap());
+        assert_eq!(admission.authorize(&headers, None, &token, "test"), Ok(Priority::Bulk));
+
+        headers.insert("authorization", "Bearer secret".parse().unwrap());
+        assert_eq!(admission.authorize(&headers, None, &token, "test"), Ok(Priority::RealTime));
+
+        headers.insert(PRIORITY_HEADER, "bulk".parse().unwrap());
+        assert_eq!(admission.authorize(&headers, None, &token, "test"), Ok(Priority::Bulk));
+
+        headers.insert("authorization", "Bearer wrong".parse().unwrap());
+        assert_eq!(admission.authorize(&headers, None, &token, "test"), Err(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn admits_client_certificates_without_a_token() {
+        let admission = admission(SinkLag::new());
+        let token = Some("secret".to_string());
+        let gateway = ClientIdentity("substation-17".into());
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(admission.authorize(&headers, None, &token, "test"), Err(StatusCode::UNAUTHORIZED));
+        assert_eq!(admission.authorize(&headers, Some(&gateway), &token, "test"), Ok(Priority::RealTime));
+
+        // A token sent along is still checked.
+        headers.insert("authorization", "Bearer wrong".parse().unwrap());
+        assert_eq!(
+            admission.authorize(&headers, Some(&gateway), &token, "test"),
+            Err(StatusCode::UNAUTHORIZED)
+        );
+    }
+
+    #[test]
+    fn sheds_only_bulk_while_sink_lags() {
+        let lag = SinkLag::new();
+        lag.record_flush(SystemTime::now() - Duration::from_secs(60));
+        let admission = admission(lag);
+
+        let (tx, _rx) = crate::pipeline::lanes(10, 10);
+        // Nothing queued: the sink has caught up.
+        assert!(admission.admit(Priority::Bulk, &tx).is_ok());
+
+        tx.lane(Priority::RealTime).try_send(()).unwrap();
+        let err = admission.admit(Priority::Bulk, &tx).unwrap_err();
+        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(admission.admit(Priority::RealTime, &tx).is_ok());
+    }
+}
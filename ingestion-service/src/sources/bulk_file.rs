@@ -0,0 +1,92 @@
+use rust_client::domain::MeterUsage;
+
+use crate::sources::{meter_usage_backfill_file::parse_backfill_line, meter_usage_csv_file::record_to_meter_usage};
+
+/// Formats of bulk `meter_usage` files picked up by the polling sources, by
+/// file name suffix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BulkFormat {
+    /// `.ndjson` / `.jsonl`, in the NDJSON backfill format.
+    Ndjson,
+    /// `.csv`, in the CSV backfill format.
+    Csv,
+    /// `.dat`, the CSV backfill columns separated by `|`.
+    Dat,
+}
+
+impl BulkFormat {
+    pub fn for_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        if name.ends_with(".ndjson") || name.ends_with(".jsonl") {
+            Some(Self::Ndjson)
+        } else if name.ends_with(".csv") {
+            Some(Self::Csv)
+        } else if name.ends_with(".dat") {
+            Some(Self::Dat)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parse a whole file with the backfill parsers. Unparseable lines are
+/// returned as errors; the rest of the file is still read.
+pub fn parse_bulk(format: BulkFormat, data: &[u8]) -> Vec<Result<MeterUsage, String>> {
+    let delimiter = match format {
+        BulkFormat::Ndjson => {
+            return data
+                .split(|b| *b == b'\n')
+                .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+                .filter(|line| !line.iter().all(u8::is_ascii_whitespace))
+                .map(|line| {
+                    let line = std::str::from_utf8(line).map_err(|e| format!("line is not UTF-8: {e}"))?;
+                    parse_backfill_line(line).map_err(|e| e.to_string())
+                })
+                .collect()
+        }
+        BulkFormat::Csv => b',',
+        BulkFormat::Dat => b'|',
+    };
+    let mut rdr = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(data);
+    let headers = match rdr.headers() {
+        Ok(h) => h.clone(),
+        Err(e) => return vec![Err(format!("failed to read headers: {e}"))],
+    };
+    rdr.records()
+        .map(|record| {
+            let record = record.map_err(|e| e.to_string())?;
+            record_to_meter_usage(&record, &headers).map_err(|e| e.to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_by_suffix_and_keeps_reading_past_bad_lines() {
+        assert_eq!(BulkFormat::for_name("mdm/2024-06-01.NDJSON"), Some(BulkFormat::Ndjson));
+        assert_eq!(BulkFormat::for_name("usage_20240601.csv"), Some(BulkFormat::Csv));
+        assert_eq!(BulkFormat::for_name("usage_20240601.DAT"), Some(BulkFormat::Dat));
+        assert_eq!(BulkFormat::for_name("usage_20240601.csv.part"), None);
+        assert_eq!(BulkFormat::for_name("mdm/_SUCCESS"), None);
+
+        let ndjson = b"{\"ts\":\"2024-06-01T00:15:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.5}\r\nnot json\n\n\
+{\"ts\":\"2024-06-01T00:30:00Z\",\"meter_id\":\"m-1\",\"kwh\":2.0}\n";
+        let parsed = parse_bulk(BulkFormat::Ndjson, ndjson);
+        assert_eq!(parsed.len(), 3);
+        assert_eq!(parsed[0].as_ref().unwrap().kwh, 1.5);
+        assert!(parsed[1].is_err());
+        assert_eq!(parsed[2].as_ref().unwrap().kwh, 2.0);
+
+        let csv = b"ts,meter_id,kwh\n2024-06-01T00:15:00Z,m-2,0.75\n2024-06-01T00:30:00Z,m-2,oops\n";
+        let parsed = parse_bulk(BulkFormat::Csv, csv);
+        assert_eq!(parsed[0].as_ref().unwrap().meter_id, "m-2");
+        assert!(parsed[1].is_err());
+
+        let dat = b"ts|meter_id|kwh|source_system\n2024-06-01T00:15:00Z|m-1|1.5|vendor-a\n";
+        let first = parse_bulk(BulkFormat::Dat, dat).remove(0).unwrap();
+        assert_eq!((first.kwh, first.source_system.as_deref()), (1.5, Some("vendor-a")));
+    }
+}
@@ -0,0 +1,38 @@
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::pipeline::{Envelope, PipelineError, Source};
+
+/// Source fed by an in-process channel.
+///
+/// Used for side outputs of transforms (rejects, derived events) that are
+/// written by their own pipeline. The stream ends once every sender is dropped.
+pub struct ChannelSource<T> {
+    rx: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<T>>>>>,
+}
+
+impl<T> ChannelSource<T> {
+    pub fn new(rx: mpsc::Receiver<Envelope<T>>) -> Self {
+        Self {
+            rx: Arc::new(tokio::sync::Mutex::new(Some(rx))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Source<T> for ChannelSource<T>
+where
+    T: Send + 'static,
+{
+    async fn stream(&self) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let mut guard = self.rx.lock().await;
+        let rx = guard
+            .take()
+            .expect("ChannelSource stream already taken; only one consumer supported");
+
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    fs,
+    io::AsyncWriteExt,
+    sync::mpsc,
+    task::JoinHandle,
+};
+
+use crate::pipeline::{AckOutcome, AckReceiver, Completion, Envelope, PipelineError, Source};
+
+/// Progress of a file load, as stored in its checkpoint file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CheckpointState {
+    /// Size of the source file; a checkpoint of a file that changed since is discarded.
+    pub file_len: u64,
+    /// Leading records of the file that are settled (written or rejected by validation).
+    pub records: u64,
+}
+
+/// A checkpoint file, replaced atomically on every save.
+#[derive(Debug, Clone)]
+pub struct Checkpoint {
+    path: PathBuf,
+}
+
+impl Checkpoint {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self { path: path.into() }
+    }
+
+    /// The default checkpoint of `source`: `<source>.checkpoint` next to it.
+    pub fn for_file(source: &Path) -> Self {
+        let mut path = source.as_os_str().to_owned();
+        path.push(".checkpoint");
+        Self::new(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub async fn load(&self) -> io::Result<Option<CheckpointState>> {
+        match fs::read(&self.path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Write `state` to a temporary file, sync it and rename it over the checkpoint.
+    pub async fn save(&self, state: &CheckpointState) -> io::Result<()> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+
+        let mut file = fs::File::create(&tmp).await?;
+        file.write_all(&serde_json::to_vec(state).map_err(io::Error::other)?).await?;
+        file.sync_all().await?;
+        drop(file);
+        fs::rename(&tmp, &self.path).await
+    }
+}
+
+enum Pending {
+    Ack(AckReceiver),
+    /// The source failed to produce the record (unreadable or unparsable).
+    Failed,
+}
+
+/// Settles records in file order and saves the checkpoint every `save_every` records.
+async fn track(
+    mut pending: mpsc::UnboundedReceiver<Pending>,
+    checkpoint: Checkpoint,
+    mut state: CheckpointState,
+    save_every: u64,
+) -> io::Result<u64> {
+    let mut unsaved = 0;
+    let mut stalled = false;
+    while let Some(p) = pending.recv().await {
+        let outcome = match p {
+            Pending::Ack(ack) => ack.outcome().await,
+            Pending::Failed => AckOutcome::Dropped,
+        };
+        if stalled {
+            continue;
+        }
+        match outcome {
+            AckOutcome::Written | AckOutcome::Rejected => {
+                state.records += 1;
+                unsaved += 1;
+                if unsaved >= save_every {
+                    checkpoint.save(&state).await?;
+                    metrics::gauge!("backfill_checkpoint_records").set(state.records as f64);
+                    unsaved = 0;
+                }
+            }
+            AckOutcome::Dropped => {
+                // Later records may be written, but the checkpoint can't move past a lost one.
+                tracing::warn!(
+                    record = state.records + 1,
+                    checkpoint = %checkpoint.path().display(),
+                    "record was not written; checkpoint stops here"
+                );
+                stalled = true;
+            }
+        }
+    }
+    if unsaved > 0 {
+        checkpoint.save(&state).await?;
+        metrics::gauge!("backfill_checkpoint_records").set(state.records as f64);
+    }
+    Ok(state.records)
+}
+
+/// Handle to wait for the checkpoint of a [`CheckpointedSource`] to be saved.
+#[derive(Clone, Default)]
+pub struct CheckpointProgress {
+    task: Arc<Mutex<Option<JoinHandle<io::Result<u64>>>>>,
+}
+
+impl CheckpointProgress {
+    /// Wait until every record the source produced was settled and the final
+    /// checkpoint saved. Returns the number of settled records of the file.
+    ///
+    /// Call after the pipeline finished; the source's stream must be dropped.
+    pub async fn settled(&self) -> Result<u64, PipelineError> {
+        let task = self.task.lock().unwrap_or_else(|e| e.into_inner()).take();
+        let Some(task) = task else {
+            return Ok(0);
+        };
+        task.await
+            .map_err(|e| PipelineError::Source(format!("checkpoint task failed: {e}")))?
+            .map_err(|e| PipelineError::Source(format!("failed to save checkpoint: {e}")))
+    }
+}
+
+/// Wraps a file source and resumes it from a [`Checkpoint`].
+///
+/// Every record is tracked with a [`Completion`]; the checkpoint advances over
+/// the leading records that were written or rejected by a transform, so it
+/// never skips a record that was lost. It is saved every `save_every` records
+/// and once the pipeline ends. On restart the settled records are skipped.
+///
+/// Records flushed after the last save are sent again after a crash; combined
+/// with `event_id` dedup in the store (see the README, "Exactly-once
+/// backfills") each record ends up stored exactly once. The inner source must
+/// produce records in the same order on every run and must not track them itself.
+pub struct CheckpointedSource<S> {
+    inner: S,
+    file: PathBuf,
+    checkpoint: Checkpoint,
+    save_every: u64,
+    progress: CheckpointProgress,
+}
+
+impl<S> CheckpointedSource<S> {
+    /// Checkpoint the load of `file` (read by `inner`) in `checkpoint`.
+    pub fn new<P: Into<PathBuf>>(inner: S, file: P, checkpoint: Checkpoint) -> Self {
+        Self {
+            inner,
+            file: file.into(),
+            checkpoint,
+            save_every: 1000,
+            progress: CheckpointProgress::default(),
+        }
+    }
+
+    /// Save the checkpoint after every `n` settled records (default 1000).
+    pub fn with_save_every(mut self, n: u64) -> Self {
+        self.save_every = n.max(1);
+        self
+    }
+
+    pub fn progress(&self) -> CheckpointProgress {
+        self.progress.clone()
+    }
+
+    async fn resume_state(&self) -> Result<CheckpointState, PipelineError> {
+        let file_len = fs::metadata(&self.file)
+            .await
+            .map_err(|e| PipelineError::Source(format!("failed to stat {}: {e}", self.file.display())))?
+            .len();
+        let fresh = CheckpointState { file_len, records: 0 };
+        match self.checkpoint.load().await {
+            Ok(Some(state)) if state.file_len == file_len => {
+                tracing::info!(records = state.records, file = %self.file.display(), "resuming from checkpoint");
+                Ok(state)
+            }
+            Ok(Some(_)) => {
+                tracing::warn!(
+                    file = %self.file.display(),
+                    "file changed since it was checkpointed; loading it from the start"
+                );
+                Ok(fresh)
+            }
+            Ok(None) => Ok(fresh),
+            Err(e) => Err(PipelineError::Source(format!(
+                "failed to read checkpoint {}: {e}",
+                self.checkpoint.path().display()
+            ))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S, T> Source<T> for CheckpointedSource<S>
+where
+    S: Source<T>,
+    T: Send + 'static,
+{
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let state = match self.resume_state().await {
+            Ok(state) => state,
+            Err(e) => return Box::pin(futures::stream::once(async move { Err(e) })),
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(track(rx, self.checkpoint.clone(), state, self.save_every));
+        *self.progress.task.lock().unwrap_or_else(|e| e.into_inner()) = Some(task);
+
+        let skip = usize::try_from(state.records).unwrap_or(usize::MAX);
+        let s = self.inner.stream().await.skip(skip).map(move |item| match item {
+            Ok(mut env) => {
+                let (completion, ack) = Completion::oneshot();
+                env.completion = Some(completion);
+                let _ = tx.send(Pending::Ack(ack));
+                Ok(env)
+            }
+            Err(e) => {
+                let _ = tx.send(Pending::Failed);
+                Err(e)
+            }
+        });
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn checkpoint_round_trips_and_names_after_the_file() {
+        let dir = std::env::temp_dir().join(format!("checkpoint-{}", uuid::Uuid::now_v7()));
+        fs::create_dir_all(&dir).await.unwrap();
+
+        let checkpoint = Checkpoint::for_file(&dir.join("usage.ndjson"));
+        assert_eq!(checkpoint.path(), dir.join("usage.ndjson.checkpoint"));
+        assert_eq!(checkpoint.load().await.unwrap(), None);
+
+        let state = CheckpointState { file_len: 42, records: 7 };
+        checkpoint.save(&state).await.unwrap();
+        assert_eq!(checkpoint.load().await.unwrap(), Some(state));
+
+        fs::remove_dir_all(&dir).await.unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Read},
+    path::Path,
+    pin::Pin,
+};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression of an input file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileCompression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl FileCompression {
+    /// Detect from the leading bytes, falling back to the extension (`.gz`,
+    /// `.zst`) so a corrupt compressed file fails instead of parsing as text.
+    pub fn detect(path: &Path, head: &[u8]) -> Self {
+        if head.starts_with(GZIP_MAGIC) {
+            return Self::Gzip;
+        }
+        if head.starts_with(ZSTD_MAGIC) {
+            return Self::Zstd;
+        }
+        match path.extension().and_then(|e| e.to_str()).map(str::to_ascii_lowercase).as_deref() {
+            Some("gz" | "gzip") => Self::Gzip,
+            Some("zst" | "zstd") => Self::Zstd,
+            _ => Self::None,
+        }
+    }
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_unsupported() -> io::Error {
+    io::Error::new(io::ErrorKind::Unsupported, "zstd input needs the `zstd` feature")
+}
+
+/// Open `path` for blocking reads, decompressing gzip and zstd content.
+///
+/// Concatenated gzip members (`cat a.gz b.gz`) are read as one stream.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = BufReader::with_capacity(64 * 1024, File::open(path)?);
+    Ok(match FileCompression::detect(path, file.fill_buf()?) {
+        FileCompression::None => Box::new(file),
+        FileCompression::Gzip => Box::new(flate2::bufread::MultiGzDecoder::new(file)),
+        #[cfg(feature = "zstd")]
+        FileCompression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(file)?),
+        #[cfg(not(feature = "zstd"))]
+        FileCompression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+/// Async variant of [`open`].
+pub async fn open_async(path: &Path) -> io::Result<Pin<Box<dyn AsyncBufRead + Send>>> {
+    use async_compression::tokio::bufread::GzipDecoder;
+    use tokio::io::BufReader;
+
+    let mut file = BufReader::with_capacity(64 * 1024, tokio::fs::File::open(path).await?);
+    Ok(match FileCompression::detect(path, file.fill_buf().await?) {
+        FileCompression::None => Box::pin(file),
+        FileCompression::Gzip => {
+            let mut decoder = GzipDecoder::new(file);
+            decoder.multiple_members(true);
+            Box::pin(BufReader::new(decoder))
+        }
+        #[cfg(feature = "zstd")]
+        FileCompression::Zstd => Box::pin(BufReader::new(async_compression::tokio::bufread::ZstdDecoder::new(file))),
+        #[cfg(not(feature = "zstd"))]
+        FileCompression::Zstd => return Err(zstd_unsupported()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[tokio::test]
+    async fn reads_gzip_by_magic_bytes_and_plain_files_unchanged() {
+        let dir = std::env::temp_dir().join(format!("compressed-file-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        // Two concatenated members, without a telling extension.
+        let gz_path = dir.join("export.csv");
+        let mut data = Vec::new();
+        for part in ["a,1\n", "b,2\n"] {
+            let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+            gz.write_all(part.as_bytes()).unwrap();
+            data.extend(gz.finish().unwrap());
+        }
+        std::fs::write(&gz_path, data).unwrap();
+        let plain_path = dir.join("plain.csv");
+        std::fs::write(&plain_path, "a,1\nb,2\n").unwrap();
+
+        for path in [&gz_path, &plain_path] {
+            let mut sync = String::new();
+            open(path).unwrap().read_to_string(&mut sync).unwrap();
+            assert_eq!(sync, "a,1\nb,2\n");
+
+            let mut lines = open_async(path).await.unwrap().lines();
+            let mut async_lines = Vec::new();
+            while let Some(line) = lines.next_line().await.unwrap() {
+                async_lines.push(line);
+            }
+            assert_eq!(async_lines, ["a,1", "b,2"]);
+        }
+
+        assert_eq!(FileCompression::detect(Path::new("day.ndjson.ZST"), b"{\"ts\""), FileCompression::Zstd);
+        assert_eq!(FileCompression::detect(Path::new("day.ndjson"), b"{\"ts\""), FileCompression::None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use futures::Stream;
+use notify::{RecursiveMode, Watcher};
+use rust_client::domain::MeterUsage;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::DirectorySourceConfig,
+    pipeline::{CompletionGroup, Envelope, PipelineError, Source},
+    sources::bulk_file::{parse_bulk, BulkFormat},
+};
+
+/// Files to read: settled files in a [`BulkFormat`], oldest first, and whether
+/// other files are still being written.
+fn pending_files(
+    dir: &Path,
+    settle: Duration,
+    skip: &HashSet<PathBuf>,
+) -> std::io::Result<(Vec<(PathBuf, BulkFormat)>, bool)> {
+    let now = SystemTime::now();
+    let mut unsettled = false;
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let Some(format) = BulkFormat::for_name(&entry.file_name().to_string_lossy()) else {
+            continue;
+        };
+        let meta = entry.metadata()?;
+        if !meta.is_file() || skip.contains(&path) {
+            continue;
+        }
+        let modified = meta.modified()?;
+        if now.duration_since(modified).unwrap_or_default() < settle {
+            unsettled = true;
+            continue;
+        }
+        files.push((modified, path, format));
+    }
+    files.sort_by(|(a_modified, a, _), (b_modified, b, _)| (a_modified, a).cmp(&(b_modified, b)));
+    Ok((files.into_iter().map(|(_, path, format)| (path, format)).collect(), unsettled))
+}
+
+/// Move `path` into `dir`, keeping its file name.
+async fn move_into(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let target = dir.join(path.file_name().unwrap_or(path.as_os_str()));
+    tokio::fs::rename(path, &target).await?;
+    Ok(target)
+}
+
+/// Move `path` to the done or quarantine folder once its records settled.
+async fn finish_when_settled(
+    path: PathBuf,
+    group: CompletionGroup,
+    records: usize,
+    parse_errors: usize,
+    done_dir: PathBuf,
+    quarantine_dir: PathBuf,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+) {
+    if group.wait_settled().await {
+        let (outcome, dir) = if parse_errors > 0 {
+            ("quarantine", &quarantine_dir)
+        } else {
+            ("done", &done_dir)
+        };
+        match move_into(&path, dir).await {
+            Ok(target) => {
+                metrics::counter!("directory_source_files_total", "outcome" => outcome).increment(1);
+                tracing::info!(file = %path.display(), moved_to = %target.display(), records, parse_errors, "dropped file ingested");
+            }
+            Err(e) => {
+                metrics::counter!("directory_source_errors_total").increment(1);
+                tracing::warn!(error = %e, file = %path.display(), "failed to move ingested file; it will be read again");
+            }
+        }
+    } else {
+        tracing::warn!(file = %path.display(), "records of dropped file were not written; it will be read again");
+    }
+    in_flight.lock().unwrap_or_else(|e| e.into_inner()).remove(&path);
+}
+
+/// Watches a local drop folder for bulk `meter_usage` files.
+///
+/// NDJSON, CSV and DAT files ([`BulkFormat`]) are read once they stopped
+/// changing for `settle`, oldest first, and parsed with the backfill parsers.
+/// The folder is rescanned on change notifications and every
+/// `rescan_interval`. Once each record of a file was written or rejected by
+/// validation, the file is moved to `done_dir`, or to `quarantine_dir` if some
+/// of its lines could not be parsed. Files with lost records stay in place and
+/// are read again; enable `event_id` dedup on `meter_usage` to absorb the
+/// repeats. Files are read into memory whole.
+pub struct DirectoryWatchSource {
+    dir: PathBuf,
+    done_dir: PathBuf,
+    quarantine_dir: PathBuf,
+    settle: Duration,
+    rescan_interval: Duration,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl DirectoryWatchSource {
+    /// Creates the done and quarantine folders if needed.
+    pub fn from_config(cfg: &DirectorySourceConfig) -> Result<Self, PipelineError> {
+        let done_dir = cfg.done_dir.clone().unwrap_or_else(|| cfg.dir.join("done"));
+        let quarantine_dir = cfg.quarantine_dir.clone().unwrap_or_else(|| cfg.dir.join("quarantine"));
+        for dir in [&done_dir, &quarantine_dir] {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| PipelineError::Source(format!("failed to create {}: {e}", dir.display())))?;
+        }
+        Ok(Self {
+            dir: cfg.dir.clone(),
+            done_dir,
+            quarantine_dir,
+            settle: Duration::from_secs(cfg.settle_secs),
+            rescan_interval: Duration::from_secs(cfg.rescan_interval_secs),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for DirectoryWatchSource {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let dir = self.dir.clone();
+        let done_dir = self.done_dir.clone();
+        let quarantine_dir = self.quarantine_dir.clone();
+        let settle = self.settle;
+        let rescan_interval = self.rescan_interval;
+        let in_flight = self.in_flight.clone();
+
+        // Change notifications only trigger a rescan, so one pending wake-up is enough.
+        let (wake_tx, mut wake_rx) = mpsc::channel::<()>(1);
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            if event.is_ok() {
+                let _ = wake_tx.try_send(());
+            }
+        })
+        .and_then(|mut watcher| watcher.watch(&dir, RecursiveMode::NonRecursive).map(|_| watcher));
+        let watcher = match watcher {
+            Ok(w) => Some(w),
+            Err(e) => {
+                tracing::warn!(error = %e, dir = %dir.display(), "cannot watch drop folder; rescanning every rescan_interval_secs");
+                None
+            }
+        };
+
+        let s = async_stream::stream! {
+            let _watcher = watcher;
+            loop {
+                let skip = in_flight.lock().unwrap_or_else(|e| e.into_inner()).clone();
+                let (pending, unsettled) = match pending_files(&dir, settle, &skip) {
+                    Ok(p) => p,
+                    Err(e) => {
+                        metrics::counter!("directory_source_errors_total").increment(1);
+                        yield Err(PipelineError::Source(format!("failed to scan {}: {e}", dir.display())));
+                        (Vec::new(), false)
+                    }
+                };
+
+                for (path, format) in pending {
+                    let data = match tokio::fs::read(&path).await {
+                        Ok(d) => d,
+                        Err(e) => {
+                            metrics::counter!("directory_source_errors_total").increment(1);
+                            yield Err(PipelineError::Source(format!("failed to read {}: {e}", path.display())));
+                            continue;
+                        }
+                    };
+                    in_flight.lock().unwrap_or_else(|e| e.into_inner()).insert(path.clone());
+                    tracing::info!(file = %path.display(), bytes = data.len(), "reading dropped file");
+
+                    let group = CompletionGroup::new();
+                    let (mut records, mut parse_errors) = (0, 0);
+                    for (line, record) in parse_bulk(format, &data).into_iter().enumerate() {
+                        match record {
+                            Ok(usage) => {
+                                records += 1;
+                                yield Ok(Envelope {
+                                    payload: usage,
+                                    received_at: SystemTime::now(),
+                                    completion: Some(group.track()),
+                                    tenant: None,
+                                    source_identity: None,
+                                });
+                            }
+                            Err(e) => {
+                                parse_errors += 1;
+                                metrics::counter!("directory_source_parse_errors_total").increment(1);
+                                yield Err(PipelineError::Source(format!(
+                                    "invalid record {} in {}: {e}",
+                                    line + 1,
+                                    path.display()
+                                )));
+                            }
+                        }
+                    }
+                    tokio::spawn(finish_when_settled(
+                        path,
+                        group,
+                        records,
+                        parse_errors,
+                        done_dir.clone(),
+                        quarantine_dir.clone(),
+                        in_flight.clone(),
+                    ));
+                }
+
+                // Files still being written are looked at again once they could have settled.
+                let wait = if unsettled { settle.min(rescan_interval) } else { rescan_interval };
+                tokio::select! {
+                    _ = wake_rx.recv() => {}
+                    _ = tokio::time::sleep(wait) => {}
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn picks_up_settled_files_and_moves_them_when_done() {
+        let dir = std::env::temp_dir().join(format!("directory-watch-{}", uuid::Uuid::now_v7()));
+        let source = DirectoryWatchSource::from_config(&DirectorySourceConfig {
+            dir: dir.clone(),
+            done_dir: None,
+            quarantine_dir: None,
+            settle_secs: 0,
+            rescan_interval_secs: 60,
+        })
+        .unwrap();
+        std::fs::write(dir.join("b.dat"), "ts|meter_id|kwh\n2024-06-01T00:15:00Z|m-1|1.5\n").unwrap();
+        std::fs::write(dir.join("a.csv"), "ts,meter_id,kwh\n2024-06-01T00:15:00Z,m-2,oops\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "ignored").unwrap();
+
+        let (pending, unsettled) = pending_files(&dir, Duration::ZERO, &HashSet::new()).unwrap();
+        let mut names: Vec<_> = pending.iter().map(|(p, _)| p.file_name().unwrap().to_owned()).collect();
+        names.sort();
+        assert_eq!(names, ["a.csv", "b.dat"]);
+        assert!(!unsettled);
+        // Just written: not settled yet.
+        let (pending, unsettled) = pending_files(&dir, Duration::from_secs(3600), &HashSet::new()).unwrap();
+        assert!(pending.is_empty() && unsettled);
+
+        for (name, parse_errors) in [("b.dat", 0), ("a.csv", 1)] {
+            let path = dir.join(name);
+            source.in_flight.lock().unwrap().insert(path.clone());
+            let group = CompletionGroup::new();
+            group.track().complete();
+            finish_when_settled(
+                path.clone(),
+                group,
+                1 - parse_errors,
+                parse_errors,
+                source.done_dir.clone(),
+                source.quarantine_dir.clone(),
+                source.in_flight.clone(),
+            )
+            .await;
+            assert!(!path.exists());
+        }
+        assert!(dir.join("done/b.dat").exists());
+        assert!(dir.join("quarantine/a.csv").exists());
+        assert!(source.in_flight.lock().unwrap().is_empty());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
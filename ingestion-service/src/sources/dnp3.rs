@@ -0,0 +1,685 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use bytes::BytesMut;
+use futures::Stream;
+use rust_client::domain::GenerationOutput;
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    config::{Dnp3Outstation, Dnp3Point, Dnp3SourceConfig, TelemetryField},
+    pipeline::{Envelope, PipelineError, Source},
+    sources::telemetry::UnitTelemetry,
+};
+
+/// Link layer: start bytes, and control bits and functions.
+const START: [u8; 2] = [0x05, 0x64];
+const LINK_DIR: u8 = 0x80;
+const LINK_PRM: u8 = 0x40;
+const LINK_RESET: u8 = 0;
+const LINK_TEST: u8 = 2;
+const LINK_CONFIRMED_DATA: u8 = 3;
+const LINK_UNCONFIRMED_DATA: u8 = 4;
+const LINK_REQUEST_STATUS: u8 = 9;
+const LINK_ACK: u8 = 0;
+const LINK_STATUS: u8 = 11;
+
+/// Transport header bits.
+const TRANSPORT_FIN: u8 = 0x80;
+const TRANSPORT_FIR: u8 = 0x40;
+
+/// Application control bits.
+const APP_FIR: u8 = 0x80;
+const APP_FIN: u8 = 0x40;
+const APP_CON: u8 = 0x20;
+const APP_UNS: u8 = 0x10;
+
+/// Application function codes.
+const FC_CONFIRM: u8 = 0x00;
+const FC_READ: u8 = 0x01;
+const FC_WRITE: u8 = 0x02;
+const FC_RESPONSE: u8 = 0x81;
+const FC_UNSOLICITED: u8 = 0x82;
+
+/// IIN1: the outstation restarted; cleared by writing group 80 index 7.
+const IIN1_DEVICE_RESTART: u8 = 0x80;
+/// IIN2: function not supported, object unknown, parameter error.
+const IIN2_REQUEST_ERRORS: u8 = 0x07;
+
+/// Analog input flags: the point is online.
+const FLAG_ONLINE: u8 = 0x01;
+
+/// DNP3 CRC-16 (polynomial 0x3D65, reflected), stored little-endian.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &b in data {
+        crc ^= u16::from(b);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xa6bc } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// A link-layer frame with its user data, CRCs removed.
+#[derive(Debug, PartialEq)]
+struct LinkFrame {
+    control: u8,
+    dest: u16,
+    src: u16,
+    data: Vec<u8>,
+}
+
+fn link_frame(control: u8, dest: u16, src: u16, data: &[u8]) -> Vec<u8> {
+    let mut frame = vec![START[0], START[1], (5 + data.len()) as u8, control];
+    frame.extend(dest.to_le_bytes());
+    frame.extend(src.to_le_bytes());
+    frame.extend(crc16(&frame).to_le_bytes());
+    for block in data.chunks(16) {
+        frame.extend(block);
+        frame.extend(crc16(block).to_le_bytes());
+    }
+    frame
+}
+
+/// Take one link frame off the front of `buf`, if it is complete.
+fn decode_link_frame(buf: &mut BytesMut) -> Result<Option<LinkFrame>, String> {
+    if buf.len() < 10 {
+        return Ok(None);
+    }
+    if buf[..2] != START {
+        return Err(format!("invalid start bytes {:02x} {:02x}", buf[0], buf[1]));
+    }
+    if crc16(&buf[..8]) != u16::from_le_bytes([buf[8], buf[9]]) {
+        return Err("link header CRC mismatch".to_string());
+    }
+    let len = buf[2] as usize;
+    if len < 5 {
+        return Err(format!("invalid link frame length {len}"));
+    }
+    let data_len = len - 5;
+    let total = 10 + data_len + 2 * data_len.div_ceil(16);
+    if buf.len() < total {
+        return Ok(None);
+    }
+    let frame = buf.split_to(total);
+    let mut data = Vec::with_capacity(data_len);
+    for block in frame[10..].chunks(18) {
+        let (bytes, crc) = block.split_at(block.len() - 2);
+        if crc16(bytes) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err("link data CRC mismatch".to_string());
+        }
+        data.extend(bytes);
+    }
+    Ok(Some(LinkFrame {
+        control: frame[3],
+        dest: u16::from_le_bytes([frame[4], frame[5]]),
+        src: u16::from_le_bytes([frame[6], frame[7]]),
+        data,
+    }))
+}
+
+/// Transport-layer reassembly of application fragments.
+#[derive(Debug, Default)]
+struct Reassembly {
+    fragment: Vec<u8>,
+    seq: Option<u8>,
+}
+
+impl Reassembly {
+    /// Add a segment; returns the fragment once its last segment arrived.
+    fn push(&mut self, segment: &[u8]) -> Option<Vec<u8>> {
+        let (&header, body) = segment.split_first()?;
+        let seq = header & 0x3f;
+        if header & TRANSPORT_FIR != 0 {
+            self.fragment.clear();
+        } else if self.seq.map(|s| (s + 1) & 0x3f) != Some(seq) {
+            // Out of sequence: drop the partial fragment.
+            self.fragment.clear();
+            self.seq = None;
+            return None;
+        }
+        self.fragment.extend(body);
+        self.seq = Some(seq);
+        if header & TRANSPORT_FIN == 0 {
+            return None;
+        }
+        self.seq = None;
+        Some(std::mem::take(&mut self.fragment))
+    }
+}
+
+/// One analog input (group 30) of a response.
+#[derive(Debug, Clone, PartialEq)]
+struct AnalogValue {
+    index: u32,
+    value: f64,
+    online: bool,
+}
+
+/// Split `n` bytes off the front of `objects`.
+fn take<'a>(objects: &mut &'a [u8], n: usize) -> Result<&'a [u8], String> {
+    if objects.len() < n {
+        return Err("truncated object".to_string());
+    }
+    let (head, tail) = objects.split_at(n);
+    *objects = tail;
+    Ok(head)
+}
+
+fn uint_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |n, b| (n << 8) | u32::from(*b))
+}
+
+/// The analog inputs of a response's object headers.
+///
+/// Supports group 30 variations 1-6 with start-stop or count ranges and
+/// optional index prefixes; any other object fails the fragment, as its size
+/// is unknown.
+fn parse_analogs(mut objects: &[u8]) -> Result<Vec<AnalogValue>, String> {
+    let mut out = Vec::new();
+    while !objects.is_empty() {
+        let [group, variation, qualifier, rest @ ..] = objects else {
+            return Err("truncated object header".to_string());
+        };
+        objects = rest;
+        let size = match (group, variation) {
+            (30, 1) | (30, 5) => 5,
+            (30, 2) => 3,
+            (30, 3) => 4,
+            (30, 4) => 2,
+            (30, 6) => 9,
+            _ => return Err(format!("unsupported object g{group}v{variation}")),
+        };
+        let prefix = match (qualifier >> 4) & 0x07 {
+            0 => 0,
+            1 => 1,
+            2 => 2,
+            3 => 4,
+            other => {
+                return Err(format!(
+                    "unsupported index prefix {other} in qualifier {qualifier:#04x}"
+                ))
+            }
+        };
+        let (start, count) = match qualifier & 0x0f {
+            range @ 0..=2 => {
+                let width = 1 << range;
+                let start = uint_le(take(&mut objects, width)?);
+                let stop = uint_le(take(&mut objects, width)?);
+                if stop < start {
+                    return Err(format!("range {start}-{stop} ends before it starts"));
+                }
+                (start, u64::from(stop - start) + 1)
+            }
+            range @ 7..=9 => (0, u64::from(uint_le(take(&mut objects, 1 << (range - 7))?))),
+            other => return Err(format!("unsupported range code {other} in qualifier {qualifier:#04x}")),
+        };
+        let object_len = (prefix + size) as u64;
+        if count.saturating_mul(object_len) > objects.len() as u64 {
+            return Err(format!("truncated g{group}v{variation} objects"));
+        }
+        for i in 0..count as u32 {
+            let index = if prefix > 0 { uint_le(take(&mut objects, prefix)?) } else { start + i };
+            let o = take(&mut objects, size)?;
+            let (flags, value) = match variation {
+                1 => (o[0], f64::from(i32::from_le_bytes([o[1], o[2], o[3], o[4]]))),
+                2 => (o[0], f64::from(i16::from_le_bytes([o[1], o[2]]))),
+                3 => (FLAG_ONLINE, f64::from(i32::from_le_bytes([o[0], o[1], o[2], o[3]]))),
+                4 => (FLAG_ONLINE, f64::from(i16::from_le_bytes([o[0], o[1]]))),
+                5 => (o[0], f64::from(f32::from_le_bytes([o[1], o[2], o[3], o[4]]))),
+                _ => (
+                    o[0],
+                    f64::from_le_bytes([o[1], o[2], o[3], o[4], o[5], o[6], o[7], o[8]]),
+                ),
+            };
+            out.push(AnalogValue {
+                index,
+                value,
+                online: flags & FLAG_ONLINE != 0,
+            });
+        }
+    }
+    Ok(out)
+}
+
+/// Map one poll's values to records at `ts`.
+///
+/// Non-MW points are applied first, so a unit's MW record carries the values
+/// of the same poll. Values of unmapped indexes are ignored; offline values
+/// are skipped and counted.
+fn map_poll(
+    outstation: &str,
+    points: &HashMap<u16, Dnp3Point>,
+    units: &mut UnitTelemetry,
+    values: &[AnalogValue],
+    ts: OffsetDateTime,
+) -> Vec<GenerationOutput> {
+    let mut mapped: Vec<(&AnalogValue, &Dnp3Point)> = values
+        .iter()
+        .filter_map(|v| Some((v, points.get(&u16::try_from(v.index).ok()?)?)))
+        .collect();
+    mapped.sort_by_key(|(_, p)| p.field == TelemetryField::Mw);
+
+    let mut records = Vec::new();
+    for (v, p) in mapped {
+        if !v.online {
+            metrics::counter!("dnp3_offline_values_total", "outstation" => outstation.to_string()).increment(1);
+            continue;
+        }
+        records.extend(units.apply(&p.plant_id, p.unit_id.as_deref(), p.field, v.value * p.scale, ts));
+    }
+    records
+}
+
+/// A TCP connection to one outstation.
+struct Session {
+    stream: TcpStream,
+    buf: BytesMut,
+    master: u16,
+    outstation: u16,
+    app_seq: u8,
+    transport_seq: u8,
+    reassembly: Reassembly,
+}
+
+impl Session {
+    async fn connect(master: u16, outstation: &Dnp3Outstation, timeout: Duration) -> Result<Self, String> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect((outstation.host.as_str(), outstation.port)))
+            .await
+            .map_err(|_| "connect timed out".to_string())?
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            stream,
+            buf: BytesMut::with_capacity(4096),
+            master,
+            outstation: outstation.address,
+            app_seq: 0,
+            transport_seq: 0,
+            reassembly: Reassembly::default(),
+        })
+    }
+
+    async fn write_link(&mut self, control: u8, data: &[u8]) -> Result<(), String> {
+        let frame = link_frame(control, self.outstation, self.master, data);
+        self.stream.write_all(&frame).await.map_err(|e| e.to_string())
+    }
+
+    /// Send an application fragment as one unconfirmed transport segment.
+    async fn send_fragment(&mut self, fragment: &[u8]) -> Result<(), String> {
+        let mut segment = vec![TRANSPORT_FIR | TRANSPORT_FIN | self.transport_seq];
+        segment.extend(fragment);
+        self.transport_seq = (self.transport_seq + 1) & 0x3f;
+        self.write_link(LINK_DIR | LINK_PRM | LINK_UNCONFIRMED_DATA, &segment)
+            .await
+    }
+
+    /// The next application fragment, answering link-layer requests on the way.
+    async fn next_fragment(&mut self) -> Result<Vec<u8>, String> {
+        loop {
+            if let Some(frame) = decode_link_frame(&mut self.buf)? {
+                if frame.control & LINK_PRM == 0 {
+                    continue;
+                }
+                match frame.control & 0x0f {
+                    LINK_REQUEST_STATUS => self.write_link(LINK_DIR | LINK_STATUS, &[]).await?,
+                    LINK_RESET | LINK_TEST => self.write_link(LINK_DIR | LINK_ACK, &[]).await?,
+                    function @ (LINK_CONFIRMED_DATA | LINK_UNCONFIRMED_DATA) => {
+                        if function == LINK_CONFIRMED_DATA {
+                            self.write_link(LINK_DIR | LINK_ACK, &[]).await?;
+                        }
+                        if let Some(fragment) = self.reassembly.push(&frame.data) {
+                            return Ok(fragment);
+                        }
+                    }
+                    _ => {}
+                }
+                continue;
+            }
+            if self.stream.read_buf(&mut self.buf).await.map_err(|e| e.to_string())? == 0 {
+                return Err("connection closed by the outstation".to_string());
+            }
+        }
+    }
+
+    /// Read all analog inputs (group 30 variation 0, all points).
+    async fn poll(&mut self, timeout: Duration) -> Result<Vec<AnalogValue>, String> {
+        let seq = self.app_seq;
+        self.app_seq = (seq + 1) & 0x0f;
+        self.send_fragment(&[APP_FIR | APP_FIN | seq, FC_READ, 30, 0, 0x06])
+            .await?;
+        tokio::time::timeout(timeout, self.read_response(seq))
+            .await
+            .map_err(|_| format!("no response within {} ms", timeout.as_millis()))?
+    }
+
+    /// Collect the fragments of the response to request `seq`.
+    ///
+    /// Unsolicited and stale responses are confirmed if asked and dropped.
+    async fn read_response(&mut self, mut seq: u8) -> Result<Vec<AnalogValue>, String> {
+        let mut values = Vec::new();
+        loop {
+            let fragment = self.next_fragment().await?;
+            let [control, function, iin1, iin2, objects @ ..] = fragment.as_slice() else {
+                continue;
+            };
+            if control & APP_CON != 0 {
+                let uns = if *function == FC_UNSOLICITED { APP_UNS } else { 0 };
+                self.send_fragment(&[APP_FIR | APP_FIN | uns | (control & 0x0f), FC_CONFIRM])
+                    .await?;
+            }
+            if *function != FC_RESPONSE || control & 0x0f != seq {
+                continue;
+            }
+            if iin2 & IIN2_REQUEST_ERRORS != 0 {
+                return Err(format!("outstation rejected the poll (IIN2 {iin2:#04x})"));
+            }
+            values.extend(parse_analogs(objects)?);
+            if iin1 & IIN1_DEVICE_RESTART != 0 {
+                self.clear_restart().await?;
+            }
+            if control & APP_FIN != 0 {
+                return Ok(values);
+            }
+            seq = (seq + 1) & 0x0f;
+        }
+    }
+
+    /// Clear the outstation's restart bit; its response is dropped with the stale ones.
+    async fn clear_restart(&mut self) -> Result<(), String> {
+        let seq = self.app_seq;
+        self.app_seq = (seq + 1) & 0x0f;
+        self.send_fragment(&[APP_FIR | APP_FIN | seq, FC_WRITE, 80, 1, 0x00, 7, 7, 0x00])
+            .await
+    }
+}
+
+fn poll_outstation(
+    master: u16,
+    outstation: Dnp3Outstation,
+    points: HashMap<u16, Dnp3Point>,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Pin<Box<dyn Stream<Item = Result<Envelope<GenerationOutput>, PipelineError>> + Send>> {
+    let s = async_stream::stream! {
+        let name = outstation.name.clone();
+        let mut units = UnitTelemetry::default();
+        let mut ticks = tokio::time::interval(poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let mut session = match Session::connect(master, &outstation, timeout).await {
+                Ok(session) => session,
+                Err(e) => {
+                    metrics::counter!("dnp3_poll_errors_total", "outstation" => name.clone()).increment(1);
+                    yield Err(PipelineError::Source(format!("DNP3 outstation {name}: connect failed: {e}")));
+                    continue;
+                }
+            };
+            tracing::info!(outstation = %name, host = %outstation.host, "DNP3 outstation connected");
+            loop {
+                let values = match session.poll(timeout).await {
+                    Ok(values) => values,
+                    Err(e) => {
+                        metrics::counter!("dnp3_poll_errors_total", "outstation" => name.clone()).increment(1);
+                        yield Err(PipelineError::Source(format!("DNP3 outstation {name}: poll failed: {e}")));
+                        break;
+                    }
+                };
+                metrics::counter!("dnp3_polls_total", "outstation" => name.clone()).increment(1);
+                for record in map_poll(&name, &points, &mut units, &values, OffsetDateTime::now_utc()) {
+                    yield Ok(Envelope {
+                        payload: record,
+                        received_at: std::time::SystemTime::now(),
+                        completion: None,
+                        tenant: None,
+                        source_identity: None,
+                    });
+                }
+                ticks.tick().await;
+            }
+        }
+    };
+    Box::pin(s)
+}
+
+/// DNP3 master polling analog inputs into `GenerationOutput`.
+///
+/// Each outstation is polled over its own TCP connection every
+/// `poll_interval_secs` with a read of all analog inputs (group 30); indexes
+/// are mapped to plant/unit fields with the outstation's `points`. MW points
+/// yield a record stamped with the poll time, carrying the unit's other
+/// fields from the same poll. A failed or timed-out poll drops the
+/// connection, which is re-established at the next poll. Records are not
+/// acknowledged; polls missed while disconnected are lost.
+pub struct Dnp3Source {
+    cfg: Dnp3SourceConfig,
+    points: Vec<HashMap<u16, Dnp3Point>>,
+}
+
+impl Dnp3Source {
+    pub fn from_config(cfg: &Dnp3SourceConfig) -> Result<Self, PipelineError> {
+        if cfg.outstations.is_empty() {
+            return Err(PipelineError::Source("dnp3.outstations is empty".to_string()));
+        }
+        let mut points = Vec::with_capacity(cfg.outstations.len());
+        for outstation in &cfg.outstations {
+            if outstation.points.is_empty() {
+                return Err(PipelineError::Source(format!(
+                    "DNP3 outstation {} has no points",
+                    outstation.name
+                )));
+            }
+            let mut by_index = HashMap::new();
+            for p in &outstation.points {
+                if by_index.insert(p.index, p.clone()).is_some() {
+                    return Err(PipelineError::Source(format!(
+                        "DNP3 outstation {}: index {} is configured twice",
+                        outstation.name, p.index
+                    )));
+                }
+            }
+            points.push(by_index);
+        }
+        tracing::info!(
+            outstations = cfg.outstations.len(),
+            poll_interval_secs = cfg.poll_interval_secs,
+            "DNP3 source configured"
+        );
+        Ok(Self {
+            cfg: cfg.clone(),
+            points,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<GenerationOutput> for Dnp3Source {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<GenerationOutput>, PipelineError>> + Send>> {
+        let poll_interval = Duration::from_secs(self.cfg.poll_interval_secs.max(1));
+        let timeout = Duration::from_millis(self.cfg.response_timeout_ms);
+        let streams = self.cfg.outstations.iter().zip(&self.points).map(|(o, points)| {
+            poll_outstation(
+                self.cfg.master_address,
+                o.clone(),
+                points.clone(),
+                poll_interval,
+                timeout,
+            )
+        });
+        Box::pin(futures::stream::select_all(streams))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use time::macros::datetime;
+    use tokio::net::TcpListener;
+
+    fn point(index: u16, unit: &str, field: TelemetryField) -> Dnp3Point {
+        Dnp3Point {
+            index,
+            plant_id: "hydro-1".to_string(),
+            unit_id: Some(unit.to_string()),
+            field,
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn frames_user_data_with_block_crcs() {
+        // Reset link states from master 1024 to outstation 1, a common capture.
+        assert_eq!(
+            link_frame(0xc0, 1, 1024, &[]),
+            [0x05, 0x64, 0x05, 0xc0, 0x01, 0x00, 0x00, 0x04, 0xe9, 0x21]
+        );
+
+        let data: Vec<u8> = (0..40).collect();
+        let mut buf = BytesMut::from(&link_frame(0x44, 1, 10, &data)[..]);
+        assert_eq!(buf.len(), 10 + 40 + 2 * 3);
+        buf.extend_from_slice(&[0x05]);
+        let frame = decode_link_frame(&mut buf).unwrap().unwrap();
+        assert_eq!((frame.control, frame.dest, frame.src), (0x44, 1, 10));
+        assert_eq!(frame.data, data);
+        assert_eq!(&buf[..], [0x05]);
+
+        let mut corrupt = BytesMut::from(&link_frame(0x44, 1, 10, &data)[..]);
+        corrupt[12] ^= 0xff;
+        assert!(decode_link_frame(&mut corrupt).is_err());
+    }
+
+    #[test]
+    fn reassembles_segments_in_sequence() {
+        let mut r = Reassembly::default();
+        assert_eq!(r.push(&[TRANSPORT_FIR | 5, 1, 2]), None);
+        assert_eq!(r.push(&[TRANSPORT_FIN | 6, 3]), Some(vec![1, 2, 3]));
+        // A segment out of sequence drops the fragment.
+        assert_eq!(r.push(&[TRANSPORT_FIR | 7, 1]), None);
+        assert_eq!(r.push(&[TRANSPORT_FIN | 9, 2]), None);
+    }
+
+    #[test]
+    fn parses_analog_inputs_of_each_qualifier() {
+        let mut objects = vec![30, 1, 0x00, 3, 4];
+        objects.extend([0x01, 0x10, 0, 0, 0]); // index 3: 16, online
+        objects.extend([0x00, 0xff, 0xff, 0xff, 0xff]); // index 4: -1, offline
+        objects.extend([30, 4, 0x07, 2, 0x05, 0x00, 0xfb, 0xff]); // count 2 from 0: 5, -5
+        objects.extend([30, 5, 0x28, 1, 0, 0x10, 0x01]); // one prefixed by index 272
+        objects.extend([0x01]);
+        objects.extend(12.5f32.to_le_bytes());
+
+        let values = parse_analogs(&objects).unwrap();
+        let got: Vec<(u32, f64, bool)> = values.iter().map(|v| (v.index, v.value, v.online)).collect();
+        assert_eq!(
+            got,
+            [
+                (3, 16.0, true),
+                (4, -1.0, false),
+                (0, 5.0, true),
+                (1, -5.0, true),
+                (272, 12.5, true)
+            ]
+        );
+
+        assert!(parse_analogs(&[30, 1, 0x00, 0, 1, 0x01]).is_err());
+        assert!(parse_analogs(&[1, 2, 0x00, 0, 0, 0x01]).unwrap_err().contains("g1v2"));
+    }
+
+    #[test]
+    fn applies_a_polls_other_fields_before_its_mw() {
+        let points: HashMap<u16, Dnp3Point> = [
+            point(0, "g1", TelemetryField::Mw),
+            point(1, "g1", TelemetryField::Mvar),
+            point(2, "g2", TelemetryField::Mw),
+        ]
+        .into_iter()
+        .map(|p| (p.index, p))
+        .collect();
+        let values = [
+            AnalogValue {
+                index: 0,
+                value: 40.0,
+                online: true,
+            },
+            AnalogValue {
+                index: 1,
+                value: 3.0,
+                online: true,
+            },
+            AnalogValue {
+                index: 2,
+                value: 7.0,
+                online: false,
+            },
+            AnalogValue {
+                index: 9,
+                value: 1.0,
+                online: true,
+            },
+        ];
+        let ts = datetime!(2024-06-01 00:00 UTC);
+        let records = map_poll("os-1", &points, &mut UnitTelemetry::default(), &values, ts);
+        assert_eq!(records.len(), 1);
+        assert_eq!((records[0].mw, records[0].mvar, records[0].ts), (40.0, Some(3.0), ts));
+        assert_eq!(records[0].unit_id.as_deref(), Some("g1"));
+    }
+
+    #[tokio::test]
+    async fn polls_an_outstation_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let outstation = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = BytesMut::new();
+            let request = loop {
+                if let Some(frame) = decode_link_frame(&mut buf).unwrap() {
+                    break frame;
+                }
+                socket.read_buf(&mut buf).await.unwrap();
+            };
+            assert_eq!((request.dest, request.src), (10, 1));
+            assert_eq!(&request.data[2..], [FC_READ, 30, 0, 0x06]);
+
+            // Float values of indexes 0 and 1, as a response to the request's sequence.
+            let mut fragment = vec![APP_FIR | APP_FIN | (request.data[1] & 0x0f), FC_RESPONSE, 0, 0];
+            fragment.extend([30, 5, 0x00, 0, 1, 0x01]);
+            fragment.extend(42.0f32.to_le_bytes());
+            fragment.push(0x01);
+            fragment.extend(2.5f32.to_le_bytes());
+            let mut segment = vec![TRANSPORT_FIR | TRANSPORT_FIN];
+            segment.extend(fragment);
+            socket
+                .write_all(&link_frame(LINK_PRM | LINK_UNCONFIRMED_DATA, 1, 10, &segment))
+                .await
+                .unwrap();
+            socket
+        });
+
+        let cfg = Dnp3SourceConfig {
+            master_address: 1,
+            poll_interval_secs: 60,
+            response_timeout_ms: 2_000,
+            outstations: vec![Dnp3Outstation {
+                name: "hydro-1".to_string(),
+                host: "127.0.0.1".to_string(),
+                port,
+                address: 10,
+                points: vec![point(0, "g1", TelemetryField::Mw), point(1, "g1", TelemetryField::Mvar)],
+            }],
+        };
+        let source = Dnp3Source::from_config(&cfg).unwrap();
+        let mut stream = source.stream().await;
+        let record = stream.next().await.unwrap().unwrap().payload;
+        assert_eq!(
+            (record.plant_id.as_str(), record.mw, record.mvar),
+            ("hydro-1", 42.0, Some(2.5))
+        );
+        outstation.await.unwrap();
+    }
+}
@@ -0,0 +1,515 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use futures::Stream;
+use roxmltree::{Document, Node};
+use rust_client::domain::{MeterUsage, PhaseChannels};
+use time::{Duration, OffsetDateTime};
+
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sources::compressed_file,
+};
+
+/// `source_system` of records loaded from Green Button files.
+const SOURCE_SYSTEM: &str = "green_button";
+
+/// ESPI unit of measure (`uom`) codes the source maps.
+const UOM_VA: u32 = 61;
+const UOM_VAH: u32 = 71;
+pub(crate) const UOM_WH: u32 = 72;
+const UOM_VARH: u32 = 73;
+
+/// ESPI `flowDirection` of energy delivered to the customer.
+pub(crate) const FLOW_FORWARD: u32 = 1;
+
+/// The `MeterUsage` column a reading type fills.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum Channel {
+    /// Wh, into `kwh`.
+    Energy,
+    /// VArh, into `kvarh`.
+    Reactive,
+    /// VA, into `kva_demand`.
+    Apparent,
+    /// VAh, into `kva_demand` as the average over the interval.
+    ApparentEnergy,
+}
+
+/// The parts of an ESPI `ReadingType` needed to scale its readings.
+#[derive(Debug, Clone, Copy, Default)]
+struct ReadingType {
+    uom: Option<u32>,
+    power_of_ten: i32,
+    interval_length: Option<i64>,
+    flow_direction: Option<u32>,
+}
+
+impl ReadingType {
+    /// The channel of the readings, or why they are skipped.
+    fn channel(&self) -> Result<Channel, &'static str> {
+        if self.flow_direction.is_some_and(|f| f != FLOW_FORWARD) {
+            return Err("not_forward_flow");
+        }
+        match self.uom {
+            Some(UOM_WH) => Ok(Channel::Energy),
+            Some(UOM_VARH) => Ok(Channel::Reactive),
+            Some(UOM_VA) => Ok(Channel::Apparent),
+            Some(UOM_VAH) => Ok(Channel::ApparentEnergy),
+            _ => Err("unsupported_uom"),
+        }
+    }
+}
+
+/// One interval reading, scaled to its base unit (Wh, VArh, VA or VAh).
+#[derive(Debug)]
+struct Reading {
+    usage_point: String,
+    channel: Channel,
+    start: i64,
+    duration: i64,
+    value: f64,
+    quality: Option<&'static str>,
+}
+
+/// `quality_flag` for an ESPI `ReadingQuality` code; valid readings have none.
+fn quality_flag(code: u32) -> Option<&'static str> {
+    match code {
+        0 | 17 | 18 | 19 => None,
+        7 => Some("manually_edited"),
+        8 => Some("estimated_reference_day"),
+        9 => Some("estimated_interpolation"),
+        10 => Some("questionable"),
+        11 => Some("derived"),
+        12 => Some("projected"),
+        13 => Some("mixed"),
+        14 => Some("raw"),
+        15 => Some("weather_normalized"),
+        _ => Some("other"),
+    }
+}
+
+/// ESPI `ReadingQuality` code for a `quality_flag`, the inverse of [`quality_flag`].
+///
+/// Flags set by other sources (e.g. `estimated` from an MDM) have no ESPI
+/// equivalent and are exported as 16 (other).
+pub(crate) fn quality_code(flag: &str) -> u32 {
+    match flag {
+        "manually_edited" => 7,
+        "estimated_reference_day" => 8,
+        "estimated_interpolation" => 9,
+        "questionable" => 10,
+        "derived" => 11,
+        "projected" => 12,
+        "mixed" => 13,
+        "raw" => 14,
+        "weather_normalized" => 15,
+        _ => 16,
+    }
+}
+
+/// Interval data read from one Green Button file.
+#[derive(Debug, Default)]
+pub struct EspiReadings {
+    /// One record per usage point and interval, in time order per usage point.
+    pub records: Vec<MeterUsage>,
+    /// Readings left out, by reason: `not_forward_flow`, `unsupported_uom`,
+    /// `coarser_interval` (e.g. daily totals next to hourly data) and
+    /// `no_energy_reading` (intervals with other channels but no Wh).
+    pub skipped: BTreeMap<&'static str, usize>,
+}
+
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &'a str) -> Option<Node<'a, 'i>> {
+    elements(node, name).next()
+}
+
+fn elements<'a, 'i>(node: Node<'a, 'i>, name: &'a str) -> impl Iterator<Item = Node<'a, 'i>> {
+    node.children()
+        .filter(move |c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn child_value<T: std::str::FromStr>(node: Node, name: &str) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    let Some(text) = child(node, name).and_then(|c| c.text()) else {
+        return Ok(None);
+    };
+    text.trim()
+        .parse()
+        .map(Some)
+        .map_err(|e| format!("invalid {name} '{}': {e}", text.trim()))
+}
+
+/// The path of `href` from `marker` on, so links resolve whatever host the export used.
+fn path_from<'a>(href: &'a str, marker: &str) -> Option<&'a str> {
+    href.find(marker).map(|i| &href[i..])
+}
+
+fn links<'a>(entry: Node<'a, '_>) -> impl Iterator<Item = (&'a str, &'a str)> {
+    elements(entry, "link").filter_map(|l| Some((l.attribute("rel").unwrap_or(""), l.attribute("href")?)))
+}
+
+fn link<'a>(entry: Node<'a, '_>, rel: &str) -> Option<&'a str> {
+    links(entry).find(|(r, _)| *r == rel).map(|(_, href)| href)
+}
+
+/// The resource an Atom entry holds, e.g. `ReadingType`.
+fn content<'a, 'i>(entry: Node<'a, 'i>) -> Option<Node<'a, 'i>> {
+    child(entry, "content").and_then(|c| c.first_element_child())
+}
+
+/// Parse an ESPI Atom feed into `MeterUsage` records.
+///
+/// `IntervalBlock` entries are tied to their `ReadingType` through the
+/// `MeterReading` they belong to (Atom `up` / `related` links). Values are
+/// scaled by `powerOfTenMultiplier`; Wh and VArh become `kwh` / `kvarh`, VA
+/// and VAh (averaged over the interval) `kva_demand`. Readings of one usage
+/// point and interval start are merged into one record with `ts` at the
+/// interval start. Records are keyed by usage point id, or by `meter_id` when
+/// given, in which case the file must hold a single usage point.
+pub fn parse_espi(xml: &str, meter_id: Option<&str>) -> Result<EspiReadings, String> {
+    let doc = Document::parse(xml).map_err(|e| format!("invalid XML: {e}"))?;
+    let entries: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "entry")
+        .collect();
+
+    let mut reading_types: HashMap<&str, ReadingType> = HashMap::new();
+    let mut meter_readings: HashMap<&str, &str> = HashMap::new();
+    for &entry in &entries {
+        let (Some(body), Some(self_href)) = (content(entry), link(entry, "self")) else {
+            continue;
+        };
+        match body.tag_name().name() {
+            "ReadingType" => {
+                let Some(key) = path_from(self_href, "ReadingType/") else {
+                    continue;
+                };
+                let rt = ReadingType {
+                    uom: child_value(body, "uom")?,
+                    power_of_ten: child_value(body, "powerOfTenMultiplier")?.unwrap_or(0),
+                    interval_length: child_value(body, "intervalLength")?,
+                    flow_direction: child_value(body, "flowDirection")?,
+                };
+                reading_types.insert(key, rt);
+            }
+            "MeterReading" => {
+                let related = links(entry)
+                    .find_map(|(rel, href)| (rel == "related").then(|| path_from(href, "ReadingType/")).flatten());
+                if let (Some(key), Some(rt)) = (path_from(self_href, "UsagePoint/"), related) {
+                    meter_readings.insert(key, rt);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut out = EspiReadings::default();
+    let mut readings = Vec::new();
+    for &entry in &entries {
+        let blocks: Vec<Node> = child(entry, "content")
+            .into_iter()
+            .flat_map(|c| elements(c, "IntervalBlock"))
+            .collect();
+        if blocks.is_empty() {
+            continue;
+        }
+
+        let href = link(entry, "up")
+            .or_else(|| link(entry, "self"))
+            .and_then(|h| path_from(h, "UsagePoint/"))
+            .ok_or("IntervalBlock entry without a UsagePoint link")?;
+        let meter_reading = href.split("/IntervalBlock").next().unwrap_or(href);
+        let usage_point = meter_reading["UsagePoint/".len()..]
+            .split('/')
+            .next()
+            .unwrap_or_default();
+        let rt = meter_readings
+            .get(meter_reading)
+            .and_then(|key| reading_types.get(key))
+            .ok_or_else(|| format!("no ReadingType for {meter_reading}"))?;
+        let channel = match rt.channel() {
+            Ok(channel) => channel,
+            Err(reason) => {
+                let skipped: usize = blocks.iter().map(|b| elements(*b, "IntervalReading").count()).sum();
+                *out.skipped.entry(reason).or_default() += skipped;
+                continue;
+            }
+        };
+        let scale = 10f64.powi(rt.power_of_ten);
+
+        for block in blocks {
+            for reading in elements(block, "IntervalReading") {
+                let period = child(reading, "timePeriod").ok_or("IntervalReading without timePeriod")?;
+                let start: i64 = child_value(period, "start")?.ok_or("timePeriod without start")?;
+                let duration = child_value(period, "duration")?
+                    .or(rt.interval_length)
+                    .ok_or("IntervalReading without a duration")?;
+                let value: f64 = child_value(reading, "value")?.ok_or("IntervalReading without value")?;
+                let quality = elements(reading, "ReadingQuality")
+                    .filter_map(|q| child_value::<u32>(q, "quality").ok().flatten())
+                    .find_map(quality_flag);
+                readings.push(Reading {
+                    usage_point: usage_point.to_string(),
+                    channel,
+                    start,
+                    duration,
+                    value: value * scale,
+                    quality,
+                });
+            }
+        }
+    }
+
+    // Exports often carry daily totals next to the interval data; keep the finest.
+    let mut finest: HashMap<(&str, Channel), i64> = HashMap::new();
+    for r in &readings {
+        let d = finest.entry((&r.usage_point, r.channel)).or_insert(r.duration);
+        *d = (*d).min(r.duration);
+    }
+    let finest: HashMap<(String, Channel), i64> = finest
+        .into_iter()
+        .map(|((up, channel), d)| ((up.to_string(), channel), d))
+        .collect();
+
+    if let Some(id) = meter_id {
+        let mut points = readings.iter().map(|r| r.usage_point.as_str());
+        if let Some(first) = points.next() {
+            if points.any(|p| p != first) {
+                return Err(format!(
+                    "file holds several usage points; cannot load them all as meter '{id}'"
+                ));
+            }
+        }
+    }
+
+    let mut intervals: BTreeMap<(String, i64), MeterUsage> = BTreeMap::new();
+    let mut energy: BTreeMap<(String, i64), bool> = BTreeMap::new();
+    for r in readings {
+        if finest[&(r.usage_point.clone(), r.channel)] != r.duration {
+            *out.skipped.entry("coarser_interval").or_default() += 1;
+            continue;
+        }
+        let key = (r.usage_point, r.start);
+        let record = intervals.entry(key.clone()).or_insert_with(|| MeterUsage {
+            ts: OffsetDateTime::UNIX_EPOCH + Duration::seconds(r.start),
+            meter_id: meter_id.map_or_else(|| key.0.clone(), str::to_string),
+            premise_id: None,
+            kwh: 0.0,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some(SOURCE_SYSTEM.to_string()),
+            event_id: None,
+            phases: PhaseChannels::default(),
+        });
+        match r.channel {
+            Channel::Energy => {
+                record.kwh = r.value / 1000.0;
+                energy.insert(key, true);
+            }
+            Channel::Reactive => record.kvarh = Some(r.value / 1000.0),
+            Channel::Apparent => record.kva_demand = Some(r.value / 1000.0),
+            Channel::ApparentEnergy if r.duration > 0 => {
+                record.kva_demand = Some(r.value / 1000.0 / (r.duration as f64 / 3600.0))
+            }
+            Channel::ApparentEnergy => {}
+        }
+        if record.quality_flag.is_none() {
+            record.quality_flag = r.quality.map(str::to_string);
+        }
+    }
+
+    for (key, record) in intervals {
+        if energy.contains_key(&key) {
+            out.records.push(record);
+        } else {
+            *out.skipped.entry("no_energy_reading").or_default() += 1;
+        }
+    }
+    Ok(out)
+}
+
+/// Green Button (NAESB ESPI) file source for `MeterUsage`.
+///
+/// Reads an Atom/XML export (`Download My Data` or a bulk feed) with
+/// [`parse_espi`]; gzip and zstd files are decompressed while reading. The
+/// whole file is parsed before the first record is yielded.
+pub struct GreenButtonSource {
+    path: PathBuf,
+    meter_id: Option<String>,
+}
+
+impl GreenButtonSource {
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Self {
+            path: path.into(),
+            meter_id: None,
+        }
+    }
+
+    /// Load the file's single usage point as `meter_id` instead of its ESPI id.
+    pub fn with_meter_id(mut self, meter_id: impl Into<String>) -> Self {
+        self.meter_id = Some(meter_id.into());
+        self
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for GreenButtonSource {
+    async fn stream(
+        &self,
+    ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let path = self.path.clone();
+        let meter_id = self.meter_id.clone();
+        let s = async_stream::try_stream! {
+            let mut xml = String::new();
+            compressed_file::open(&path)
+                .and_then(|mut file| file.read_to_string(&mut xml))
+                .map_err(|e| PipelineError::Source(format!("failed to read Green Button file: {e}")))?;
+
+            let parsed = match parse_espi(&xml, meter_id.as_deref()) {
+                Ok(parsed) => parsed,
+                Err(e) => {
+                    metrics::counter!("green_button_parse_errors_total").increment(1);
+                    Err(PipelineError::Source(format!("invalid Green Button file {}: {e}", path.display())))?
+                }
+            };
+            for (reason, count) in &parsed.skipped {
+                metrics::counter!("green_button_skipped_readings_total", "reason" => *reason).increment(*count as u64);
+                tracing::info!(file = %path.display(), reason, count, "skipped Green Button readings");
+            }
+
+            for usage in parsed.records {
+                yield Envelope {
+                    payload: usage,
+                    received_at: SystemTime::now(),
+                    completion: None,
+                    tenant: None,
+                    source_identity: None,
+                };
+            }
+        };
+
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn reading_type(id: &str, uom: u32, power_of_ten: i32, interval: i64, flow: u32) -> String {
+        format!(
+            r#"<entry><link rel="self" href="https://gb.example/espi/1_1/resource/ReadingType/{id}"/>
+<content><ReadingType xmlns="http://naesb.org/espi"><flowDirection>{flow}</flowDirection>
+<intervalLength>{interval}</intervalLength><powerOfTenMultiplier>{power_of_ten}</powerOfTenMultiplier>
+<uom>{uom}</uom></ReadingType></content></entry>"#
+        )
+    }
+
+    fn meter_reading(up: &str, mr: &str, rt: &str) -> String {
+        format!(
+            r#"<entry><link rel="self" href="https://gb.example/espi/1_1/resource/Subscription/9/UsagePoint/{up}/MeterReading/{mr}"/>
+<link rel="related" href="https://gb.example/espi/1_1/resource/ReadingType/{rt}"/>
+<content><MeterReading xmlns="http://naesb.org/espi"/></content></entry>"#
+        )
+    }
+
+    fn block(up: &str, mr: &str, readings: &[(i64, i64, i64, Option<u32>)]) -> String {
+        let readings: String = readings
+            .iter()
+            .map(|(start, duration, value, quality)| {
+                let quality = quality.map_or(String::new(), |q| format!("<ReadingQuality><quality>{q}</quality></ReadingQuality>"));
+                format!("<IntervalReading>{quality}<timePeriod><duration>{duration}</duration><start>{start}</start></timePeriod><value>{value}</value></IntervalReading>")
+            })
+            .collect();
+        format!(
+            r#"<entry><link rel="up" href="https://gb.example/espi/1_1/resource/Subscription/9/UsagePoint/{up}/MeterReading/{mr}/IntervalBlock"/>
+<content><IntervalBlock xmlns="http://naesb.org/espi">{readings}</IntervalBlock></content></entry>"#
+        )
+    }
+
+    fn feed(entries: &[String]) -> String {
+        format!(
+            r#"<?xml version="1.0"?><feed xmlns="http://www.w3.org/2005/Atom">{}</feed>"#,
+            entries.concat()
+        )
+    }
+
+    // 2024-06-01T00:00:00Z
+    const T0: i64 = 1_717_200_000;
+
+    #[test]
+    fn maps_interval_blocks_with_multipliers_and_quality() {
+        let xml = feed(&[
+            reading_type("01", UOM_WH, 0, 3600, 1),
+            reading_type("02", UOM_VARH, -3, 3600, 1),
+            reading_type("03", UOM_WH, 3, 86400, 1),
+            reading_type("04", UOM_WH, 0, 3600, 19),
+            reading_type("05", UOM_VAH, 0, 3600, 1),
+            meter_reading("7", "1", "01"),
+            meter_reading("7", "2", "02"),
+            meter_reading("7", "3", "03"),
+            meter_reading("7", "4", "04"),
+            meter_reading("7", "5", "05"),
+            block("7", "1", &[(T0, 3600, 1250, None), (T0 + 3600, 3600, 900, Some(8))]),
+            block("7", "2", &[(T0, 3600, 400_000, None), (T0 + 7200, 3600, 1, None)]),
+            block("7", "3", &[(T0, 86400, 25, None)]),
+            block("7", "4", &[(T0, 3600, 300, None)]),
+            block("7", "5", &[(T0 + 3600, 3600, 1800, None)]),
+        ]);
+        let parsed = parse_espi(&xml, None).unwrap();
+
+        assert_eq!(parsed.records.len(), 2);
+        let first = &parsed.records[0];
+        assert_eq!(
+            (first.ts, first.meter_id.as_str()),
+            (datetime!(2024-06-01 00:00 UTC), "7")
+        );
+        assert_eq!(
+            (first.kwh, first.kvarh, first.quality_flag.as_deref()),
+            (1.25, Some(0.4), None)
+        );
+        assert_eq!(first.source_system.as_deref(), Some("green_button"));
+        let second = &parsed.records[1];
+        assert_eq!((second.kwh, second.kva_demand), (0.9, Some(1.8)));
+        assert_eq!(second.quality_flag.as_deref(), Some("estimated_reference_day"));
+
+        let skipped: Vec<(&str, usize)> = parsed.skipped.into_iter().collect();
+        assert_eq!(
+            skipped,
+            [
+                ("coarser_interval", 1),
+                ("no_energy_reading", 1),
+                ("not_forward_flow", 1)
+            ]
+        );
+    }
+
+    #[test]
+    fn meter_id_override_needs_a_single_usage_point() {
+        let mut entries = vec![
+            reading_type("01", UOM_WH, 0, 900, 1),
+            meter_reading("7", "1", "01"),
+            block("7", "1", &[(T0, 900, 100, None)]),
+        ];
+        let parsed = parse_espi(&feed(&entries), Some("m-42")).unwrap();
+        assert_eq!(parsed.records[0].meter_id, "m-42");
+
+        entries.push(meter_reading("8", "1", "01"));
+        entries.push(block("8", "1", &[(T0, 900, 100, None)]));
+        assert!(parse_espi(&feed(&entries), Some("m-42")).is_err());
+        assert_eq!(parse_espi(&feed(&entries), None).unwrap().records.len(), 2);
+
+        // A block whose MeterReading isn't in the file can't be scaled.
+        let orphan = feed(&[block("7", "9", &[(T0, 900, 100, None)])]);
+        assert!(parse_espi(&orphan, None).unwrap_err().contains("no ReadingType"));
+    }
+}
@@ -0,0 +1,383 @@
+use std::{net::SocketAddr, pin::Pin, sync::Arc, time::Duration};
+
+use axum::http::StatusCode;
+use futures::{Stream, StreamExt};
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use tokio::{
+    net::TcpListener,
+    sync::{mpsc, Mutex},
+};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::{
+    transport::{server::TcpIncoming, Server},
+    Request, Response, Status, Streaming,
+};
+
+use crate::{
+    auth,
+    config::{GrpcSourceConfig, Role},
+    pipeline::{AckOutcome, AckReceiver, Completion, Envelope, PipelineError, Source},
+    sources::protobuf::{generation_output_from_record, meter_usage_from_record},
+};
+
+pub use super::protobuf::proto;
+
+use proto::{
+    ingest_ack,
+    ingest_server::{Ingest, IngestServer},
+    GenerationOutputRecord, IngestAck, MeterUsageRecord,
+};
+
+/// A record type with an RPC on the `Ingest` service.
+pub trait GrpcRecord: Sized + Send + 'static {
+    /// The RPC's request message.
+    type Message: Send + 'static;
+
+    /// RPC name, for logs and metric labels.
+    const RPC: &'static str;
+
+    /// The message's `seq` and the record, or why it is malformed.
+    fn from_message(m: Self::Message) -> (u64, Result<Self, String>);
+
+    /// The `Ingest` service answering this type's RPC; the other RPCs are UNIMPLEMENTED.
+    fn service(ingest: StreamIngest<Self>) -> IngestService;
+}
+
+impl GrpcRecord for MeterUsage {
+    type Message = MeterUsageRecord;
+    const RPC: &'static str = "IngestMeterUsage";
+
+    fn from_message(m: MeterUsageRecord) -> (u64, Result<Self, String>) {
+        (m.seq, meter_usage_from_record(m).map_err(|e| e.to_string()))
+    }
+
+    fn service(ingest: StreamIngest<Self>) -> IngestService {
+        IngestService {
+            meter_usage: Some(ingest),
+            generation_output: None,
+        }
+    }
+}
+
+impl GrpcRecord for GenerationOutput {
+    type Message = GenerationOutputRecord;
+    const RPC: &'static str = "IngestGenerationOutput";
+
+    fn from_message(m: GenerationOutputRecord) -> (u64, Result<Self, String>) {
+        (m.seq, generation_output_from_record(m).map_err(|e| e.to_string()))
+    }
+
+    fn service(ingest: StreamIngest<Self>) -> IngestService {
+        IngestService {
+            meter_usage: None,
+            generation_output: Some(ingest),
+        }
+    }
+}
+
+/// Shared state of one RPC: where its records go and how streams are limited.
+pub struct StreamIngest<T> {
+    tx: mpsc::Sender<Envelope<T>>,
+    auth_bearer_token: Option<String>,
+    max_unacked: usize,
+}
+
+impl<T> Clone for StreamIngest<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tx: self.tx.clone(),
+            auth_bearer_token: self.auth_bearer_token.clone(),
+            max_unacked: self.max_unacked,
+        }
+    }
+}
+
+/// A record of a stream awaiting its ack.
+enum Pending {
+    Tracked(u64, AckReceiver),
+    Invalid(u64, String),
+}
+
+fn ack(seq: u64, status: ingest_ack::Status, error: String) -> IngestAck {
+    IngestAck {
+        seq,
+        status: status as i32,
+        error,
+    }
+}
+
+impl<T: GrpcRecord> StreamIngest<T> {
+    /// Check the request's bearer token against the configured token and the
+    /// API keys (role `ingest`). Tenant keys are refused.
+    fn authorize<M>(&self, request: &Request<M>) -> Result<(), StatusCode> {
+        let given = request
+            .metadata()
+            .get("authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+        match auth::resolve(given, self.auth_bearer_token.as_deref(), Role::Ingest) {
+            Ok(key) if key.as_ref().is_none_or(|k| k.tenant.is_none()) => Ok(()),
+            Ok(_) | Err(StatusCode::FORBIDDEN) => {
+                metrics::counter!("api_key_forbidden_total", "role" => Role::Ingest.as_str()).increment(1);
+                Err(StatusCode::FORBIDDEN)
+            }
+            Err(status) => {
+                metrics::counter!("grpc_ingest_unauthorized_total", "rpc" => T::RPC).increment(1);
+                Err(status)
+            }
+        }
+    }
+
+    /// Serve one client stream: forward its records to the pipeline and
+    /// answer each with an ack, in the order they were sent.
+    fn ingest(&self, request: Request<Streaming<T::Message>>) -> Response<AckStream> {
+        metrics::counter!("grpc_ingest_streams_total", "rpc" => T::RPC).increment(1);
+
+        let mut incoming = request.into_inner();
+        let (pending_tx, pending_rx) = mpsc::channel(self.max_unacked.max(1));
+        let (acks_tx, acks_rx) = mpsc::channel(self.max_unacked.max(1));
+        tokio::spawn(send_acks(pending_rx, acks_tx.clone()));
+
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            loop {
+                let message = match incoming.message().await {
+                    Ok(Some(m)) => m,
+                    Ok(None) => break,
+                    Err(status) => {
+                        tracing::debug!(rpc = T::RPC, error = %status, "gRPC ingest stream ended with an error");
+                        break;
+                    }
+                };
+                let pending = match T::from_message(message) {
+                    (seq, Ok(record)) => {
+                        let (completion, ack) = Completion::oneshot();
+                        // Waiting for room in the channel holds back the client through flow control.
+                        if tx.send(Envelope::tracked(record, completion)).await.is_err() {
+                            let _ = acks_tx
+                                .send(Err(Status::unavailable("pipeline is shutting down")))
+                                .await;
+                            break;
+                        }
+                        metrics::counter!("grpc_ingest_records_total", "rpc" => T::RPC).increment(1);
+                        Pending::Tracked(seq, ack)
+                    }
+                    (seq, Err(e)) => {
+                        metrics::counter!("grpc_ingest_invalid_total", "rpc" => T::RPC).increment(1);
+                        Pending::Invalid(seq, e)
+                    }
+                };
+                if pending_tx.send(pending).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        Response::new(Box::pin(ReceiverStream::new(acks_rx)))
+    }
+}
+
+/// Resolve the pending records of a stream in order, sending their acks.
+async fn send_acks(mut pending: mpsc::Receiver<Pending>, acks: mpsc::Sender<Result<IngestAck, Status>>) {
+    while let Some(p) = pending.recv().await {
+        let reply = match p {
+            Pending::Tracked(seq, ack_rx) => match ack_rx.outcome().await {
+                AckOutcome::Written => ack(seq, ingest_ack::Status::Written, String::new()),
+                AckOutcome::Rejected => ack(seq, ingest_ack::Status::Rejected, String::new()),
+                AckOutcome::Dropped => ack(seq, ingest_ack::Status::Dropped, String::new()),
+            },
+            Pending::Invalid(seq, error) => ack(seq, ingest_ack::Status::Invalid, error),
+        };
+        if acks.send(Ok(reply)).await.is_err() {
+            // The client went away; its remaining acks have no receiver.
+            return;
+        }
+    }
+}
+
+type AckStream = Pin<Box<dyn Stream<Item = Result<IngestAck, Status>> + Send>>;
+
+/// The `Ingest` service of one pipeline; see [`GrpcRecord::service`].
+pub struct IngestService {
+    meter_usage: Option<StreamIngest<MeterUsage>>,
+    generation_output: Option<StreamIngest<GenerationOutput>>,
+}
+
+/// Serve `request` with `ingest`, if this endpoint has the RPC.
+async fn serve<T: GrpcRecord>(
+    ingest: Option<&StreamIngest<T>>,
+    request: Request<Streaming<T::Message>>,
+) -> Result<Response<AckStream>, Status> {
+    match ingest {
+        None => Err(Status::unimplemented(format!(
+            "{} is not served on this endpoint",
+            T::RPC
+        ))),
+        Some(ingest) => match ingest.authorize(&request) {
+            Ok(()) => Ok(ingest.ingest(request)),
+            Err(StatusCode::FORBIDDEN) => Err(Status::permission_denied("API key may not ingest over gRPC")),
+            Err(_) => Err(Status::unauthenticated("missing or invalid bearer token")),
+        },
+    }
+}
+
+#[tonic::async_trait]
+impl Ingest for IngestService {
+    type IngestMeterUsageStream = AckStream;
+    type IngestGenerationOutputStream = AckStream;
+
+    async fn ingest_meter_usage(
+        &self,
+        request: Request<Streaming<MeterUsageRecord>>,
+    ) -> Result<Response<AckStream>, Status> {
+        serve(self.meter_usage.as_ref(), request).await
+    }
+
+    async fn ingest_generation_output(
+        &self,
+        request: Request<Streaming<GenerationOutputRecord>>,
+    ) -> Result<Response<AckStream>, Status> {
+        serve(self.generation_output.as_ref(), request).await
+    }
+}
+
+/// Records received over the `Ingest` gRPC service (`proto/ingest.proto`).
+///
+/// Clients keep a bidirectional stream open and get one ack per record, in
+/// send order, once the sink wrote it (`WRITTEN`), a transform rejected it
+/// (`REJECTED`), or it was lost (`DROPPED`, safe to resend). Malformed records
+/// are acked `INVALID` without entering the pipeline. Instead of shedding
+/// load like the HTTP source, a full channel stops reading from the streams.
+pub struct GrpcIngestSource<T> {
+    receiver: Arc<Mutex<Option<mpsc::Receiver<Envelope<T>>>>>,
+    local_addr: SocketAddr,
+}
+
+impl<T: GrpcRecord> GrpcIngestSource<T> {
+    pub async fn from_config(cfg: &GrpcSourceConfig) -> Result<Self, PipelineError> {
+        let addr: SocketAddr = cfg
+            .bind_addr
+            .parse()
+            .map_err(|e| PipelineError::Source(format!("invalid gRPC bind addr: {e}")))?;
+        // Bind here so a taken port fails startup.
+        let listener = TcpListener::bind(addr)
+            .await
+            .map_err(|e| PipelineError::Source(format!("failed to bind gRPC source: {e}")))?;
+        let local_addr = listener
+            .local_addr()
+            .map_err(|e| PipelineError::Source(format!("failed to bind gRPC source: {e}")))?;
+        let incoming = TcpIncoming::from_listener(listener, true, None)
+            .map_err(|e| PipelineError::Source(format!("failed to bind gRPC source: {e}")))?;
+
+        let (tx, rx) = mpsc::channel(cfg.channel_capacity);
+        let service = T::service(StreamIngest {
+            tx,
+            auth_bearer_token: cfg.auth_bearer_token.clone(),
+            max_unacked: cfg.max_unacked,
+        });
+        let server = Server::builder()
+            .http2_keepalive_interval(cfg.keep_alive_interval_secs.map(Duration::from_secs))
+            .add_service(IngestServer::new(service));
+        tokio::spawn(async move {
+            if let Err(e) = server.serve_with_incoming(incoming).await {
+                tracing::error!(rpc = T::RPC, error = %e, "gRPC source stopped");
+            }
+        });
+        tracing::info!(addr = %local_addr, rpc = T::RPC, "gRPC source listening");
+
+        Ok(Self {
+            receiver: Arc::new(Mutex::new(Some(rx))),
+            local_addr,
+        })
+    }
+
+    /// Address the endpoint listens on (the bound port for `:0`).
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: GrpcRecord> Source<T> for GrpcIngestSource<T> {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let rx = self
+            .receiver
+            .lock()
+            .await
+            .take()
+            .expect("GrpcIngestSource stream already taken; only one consumer supported");
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::ingest_client::IngestClient;
+
+    fn record(seq: u64, meter_id: &str) -> MeterUsageRecord {
+        MeterUsageRecord {
+            seq,
+            ts_micros: 1_717_200_000_000_000,
+            meter_id: meter_id.to_string(),
+            kwh: 1.5,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn acks_each_record_in_order_once_settled() {
+        let source = GrpcIngestSource::<MeterUsage>::from_config(&GrpcSourceConfig {
+            bind_addr: "127.0.0.1:0".to_string(),
+            channel_capacity: 16,
+            auth_bearer_token: None,
+            max_unacked: 16,
+            keep_alive_interval_secs: None,
+        })
+        .await
+        .unwrap();
+        let mut records = source.stream().await;
+
+        // Settle the records like a sink would: write m-1, reject m-3.
+        tokio::spawn(async move {
+            while let Some(Ok(env)) = records.next().await {
+                if env.payload.meter_id == "m-3" {
+                    env.completion.as_ref().unwrap().reject();
+                } else {
+                    assert_eq!(env.payload.ts.unix_timestamp(), 1_717_200_000);
+                    env.complete();
+                }
+            }
+        });
+
+        let mut client = IngestClient::connect(format!("http://{}", source.local_addr()))
+            .await
+            .unwrap();
+        let requests = futures::stream::iter([record(1, "m-1"), record(2, ""), record(3, "m-3")]);
+        let acks: Vec<IngestAck> = client
+            .ingest_meter_usage(requests)
+            .await
+            .unwrap()
+            .into_inner()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        let got: Vec<(u64, ingest_ack::Status)> = acks.iter().map(|a| (a.seq, a.status())).collect();
+        assert_eq!(
+            got,
+            [
+                (1, ingest_ack::Status::Written),
+                (2, ingest_ack::Status::Invalid),
+                (3, ingest_ack::Status::Rejected)
+            ]
+        );
+        assert_eq!(acks[1].error, "meter_id: must not be empty");
+
+        // This endpoint only serves meter usage.
+        let err = client
+            .ingest_generation_output(futures::stream::empty())
+            .await
+            .unwrap_err();
+        assert_eq!(err.code(), tonic::Code::Unimplemented);
+    }
+}
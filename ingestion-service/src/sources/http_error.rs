@@ -0,0 +1,275 @@
+use axum::{
+    extract::rejection::JsonRejection,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+
+/// At most this many per-record errors are returned in one response.
+pub(crate) const MAX_ERROR_DETAILS: usize = 100;
+
+/// A problem with one record of an ingest request.
+#[derive(Debug, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct FieldError {
+    /// Position in a JSON array body (0-based).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<usize>,
+    /// Line of an NDJSON body (1-based).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    /// The offending field; absent when the record as a whole is malformed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub reason: String,
+}
+
+impl FieldError {
+    pub(crate) fn field(field: &str, reason: impl Into<String>) -> Self {
+        Self {
+            index: None,
+            line: None,
+            field: Some(field.to_string()),
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn record(reason: impl Into<String>) -> Self {
+        Self {
+            index: None,
+            line: None,
+            field: None,
+            reason: reason.into(),
+        }
+    }
+
+    pub(crate) fn at_index(mut self, index: usize) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    pub(crate) fn at_line(mut self, line: usize) -> Self {
+        self.line = Some(line);
+        self
+    }
+}
+
+impl std::fmt::Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.field {
+            Some(field) => write!(f, "{field}: {}", self.reason),
+            None => f.write_str(&self.reason),
+        }
+    }
+}
+
+/// Error response of the ingest endpoints: `{"error": "...", "details": [...]}`.
+#[derive(Debug)]
+pub(crate) struct ApiError {
+    pub status: StatusCode,
+    pub error: String,
+    pub details: Vec<FieldError>,
+}
+
+impl ApiError {
+    pub(crate) fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        Self {
+            status,
+            error: error.into(),
+            details: Vec::new(),
+        }
+    }
+
+    /// 400 listing the records that failed validation.
+    pub(crate) fn invalid(details: Vec<FieldError>) -> Self {
+        Self {
+            status: StatusCode::BAD_REQUEST,
+            error: "invalid records".to_string(),
+            details,
+        }
+    }
+
+    pub(crate) fn with_details(mut self, details: Vec<FieldError>) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        Self::new(status, status.canonical_reason().unwrap_or("error").to_lowercase())
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::new(rejection.status(), rejection.body_text())
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    details: &'a [FieldError],
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorBody {
+            error: &self.error,
+            details: &self.details,
+        };
+        (self.status, Json(body)).into_response()
+    }
+}
+
+fn deserialize_error<E: std::fmt::Display>(err: serde_path_to_error::Error<E>) -> FieldError {
+    let path = err.path().to_string();
+    let reason = err.inner().to_string();
+    if path == "." {
+        FieldError::record(reason)
+    } else {
+        FieldError::field(&path, reason)
+    }
+}
+
+/// Error of a request body that failed while streaming: 413 when it went over
+/// a size limit (see `http_server`), otherwise 400.
+pub(crate) fn body_read_error(err: std::io::Error) -> ApiError {
+    // `io::Error::source` skips the wrapped error itself, so start from it.
+    let mut source = err.get_ref().map(|e| e as &(dyn std::error::Error + 'static));
+    while let Some(e) = source {
+        if e.is::<http_body_util::LengthLimitError>() {
+            return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "request body too large");
+        }
+        source = e.source();
+    }
+    ApiError::new(StatusCode::BAD_REQUEST, format!("failed to read body: {err}"))
+}
+
+/// Deserialize one NDJSON line, naming the offending field where possible.
+pub(crate) fn parse_record<T: DeserializeOwned>(line: &str) -> Result<T, FieldError> {
+    serde_path_to_error::deserialize(&mut serde_json::Deserializer::from_str(line)).map_err(deserialize_error)
+}
+
+/// Parse an RFC 3339 timestamp field.
+pub(crate) fn parse_ts_field(field: &str, ts: &str) -> Result<time::OffsetDateTime, FieldError> {
+    use time::format_description::well_known::Rfc3339;
+
+    time::OffsetDateTime::parse(ts.trim(), &Rfc3339)
+        .map_err(|e| FieldError::field(field, format!("invalid RFC 3339 timestamp '{ts}': {e}")))
+}
+
+/// Outcome of converting the records of a batch request.
+#[derive(Debug)]
+pub(crate) struct Converted<T> {
+    pub records: Vec<T>,
+    /// Number of records that failed to convert.
+    pub rejected: usize,
+    /// Errors of the first [`MAX_ERROR_DETAILS`] failed records.
+    pub errors: Vec<FieldError>,
+}
+
+impl<T> Converted<T> {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            records: Vec::with_capacity(capacity),
+            rejected: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Add the record at `index` of the batch, or its error.
+    pub(crate) fn push(&mut self, index: usize, converted: Result<T, FieldError>) {
+        match converted {
+            Ok(record) => self.records.push(record),
+            Err(e) => {
+                self.rejected += 1;
+                if self.errors.len() < MAX_ERROR_DETAILS {
+                    self.errors.push(e.at_index(index));
+                }
+            }
+        }
+    }
+
+    /// All records, or 400 listing the bad ones.
+    pub(crate) fn all(self) -> Result<Vec<T>, ApiError> {
+        if self.rejected == 0 {
+            Ok(self.records)
+        } else {
+            Err(ApiError::invalid(self.errors))
+        }
+    }
+}
+
+/// Deserialize and convert every record of a JSON array body.
+pub(crate) fn convert_each<I, T>(
+    values: Vec<serde_json::Value>,
+    convert: impl Fn(I) -> Result<T, FieldError>,
+) -> Converted<T>
+where
+    I: DeserializeOwned,
+{
+    let mut converted = Converted::with_capacity(values.len());
+    for (index, value) in values.into_iter().enumerate() {
+        converted.push(
+            index,
+            serde_path_to_error::deserialize(value)
+                .map_err(deserialize_error)
+                .and_then(&convert),
+        );
+    }
+    converted
+}
+
+/// Deserialize and convert every record of a JSON array body.
+///
+/// Either all records convert, or the request fails with the errors of the
+/// first [`MAX_ERROR_DETAILS`] bad records.
+pub(crate) fn convert_records<I, T>(
+    values: Vec<serde_json::Value>,
+    convert: impl Fn(I) -> Result<T, FieldError>,
+) -> Result<Vec<T>, ApiError>
+where
+    I: DeserializeOwned,
+{
+    convert_each(values, convert).all()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Rec {
+        ts: String,
+        #[allow(dead_code)]
+        kwh: f64,
+    }
+
+    #[test]
+    fn errors_name_the_field_and_position() {
+        let convert = |r: Rec| parse_ts_field("ts", &r.ts);
+        let values = serde_json::from_str(
+            r#"[{"ts":"2024-01-01T00:00:00Z","kwh":1.0},
+                {"ts":"2024-01-01T00:15:00Z","kwh":"x"},
+                {"ts":"yesterday","kwh":1.0},
+                {"kwh":1.0}]"#,
+        )
+        .unwrap();
+
+        let err = convert_records(values, convert).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        let fields: Vec<_> = err
+            .details
+            .iter()
+            .map(|d| (d.index, d.field.as_deref()))
+            .collect();
+        assert_eq!(fields, vec![(Some(1), Some("kwh")), (Some(2), Some("ts")), (Some(3), None)]);
+        assert!(err.details[2].reason.contains("missing field `ts`"));
+
+        let err = parse_record::<Rec>(r#"{"ts":1,"kwh":1.0}"#).unwrap_err().at_line(7);
+        assert_eq!((err.line, err.field.as_deref()), (Some(7), Some("ts")));
+    }
+}
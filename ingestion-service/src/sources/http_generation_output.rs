@@ -1,33 +1,56 @@
-use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use axum::{
     body::Body,
-    extract::{DefaultBodyLimit, State},
+    extract::{rejection::JsonRejection, FromRequest, Request, State},
+    middleware,
     routing::post,
-    Json, Router,
+    Extension, Json, Router,
 };
 use futures::{Stream, StreamExt, TryStreamExt};
 use rust_client::domain::GenerationOutput;
 use tokio::io::AsyncBufReadExt;
-use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::TrySendError;
-use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::io::StreamReader;
 
-use crate::pipeline::{Envelope, PipelineError, Source};
+use crate::{
+    config::{self, HttpSourceConfig},
+    sources::admission::Admission,
+    sources::http_server,
+    sources::idempotency::{self, IdempotencyCache},
+    sources::json_record::JsonRecord,
+    sources::http_error::{body_read_error, convert_each, parse_record, parse_ts_field, ApiError, Converted, FieldError, MAX_ERROR_DETAILS},
+    sources::http_json::{
+        accept_batch, await_sync_ack, batch_response, error_ratio_exceeded, source_identity, BatchResponse,
+        ErrorRatioCheck, IngestSummary,
+    },
+    pipeline::{lanes, CompletionGroup, Envelope, LaneReceiver, LaneSender, PipelineError, Priority, SinkLag, Source},
+    tls::ClientIdentity,
+};
+#[cfg(feature = "protobuf")]
+use crate::sources::protobuf;
 
 #[derive(Clone)]
 struct SharedSender {
-    tx: mpsc::Sender<Envelope<GenerationOutput>>,
+    tx: LaneSender<Envelope<GenerationOutput>>,
     auth_bearer_token: Option<String>,
+    admission: Admission,
     max_request_records: usize,
     max_line_bytes: usize,
+    json_strict: bool,
     ndjson_strict: bool,
+    ndjson_error_ratio: Option<(f64, usize)>,
+    sync_ack: Option<Duration>,
 }
 
 #[derive(Clone)]
 pub struct HttpGenerationOutputSource {
-    receiver: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<GenerationOutput>>>>>,
+    receiver: Arc<tokio::sync::Mutex<Option<LaneReceiver<Envelope<GenerationOutput>>>>>,
+    realtime_drain_weight: u32,
+    lag: SinkLag,
 }
 
 #[derive(serde::Deserialize)]
@@ -39,27 +62,34 @@ struct IncomingGenerationOutput {
     mvar: Option<f64>,
     status: Option<String>,
     fuel_type: Option<String>,
+    event_id: Option<String>,
+    aux_mw: Option<f64>,
+    availability_pct: Option<f64>,
+    curtailed_mw: Option<f64>,
 }
 
-fn parse_ts(ts: &str) -> Result<time::OffsetDateTime, axum::http::StatusCode> {
-    use axum::http::StatusCode;
-    use time::format_description::well_known::Rfc3339;
-
-    time::OffsetDateTime::parse(ts.trim(), &Rfc3339).map_err(|_e| StatusCode::BAD_REQUEST)
-}
-
-fn incoming_to_output(i: IncomingGenerationOutput) -> Result<GenerationOutput, axum::http::StatusCode> {
+fn incoming_to_output(i: IncomingGenerationOutput) -> Result<GenerationOutput, FieldError> {
     Ok(GenerationOutput {
-        ts: parse_ts(&i.ts)?,
+        ts: parse_ts_field("ts", &i.ts)?,
         plant_id: i.plant_id,
         unit_id: i.unit_id,
         mw: i.mw,
         mvar: i.mvar,
         status: i.status,
         fuel_type: i.fuel_type,
+        event_id: i.event_id,
+        aux_mw: i.aux_mw,
+        availability_pct: i.availability_pct,
+        curtailed_mw: i.curtailed_mw,
     })
 }
 
+impl JsonRecord for GenerationOutput {
+    fn from_json(json: &str) -> Result<Self, String> {
+        parse_record(json).and_then(incoming_to_output).map_err(|e| e.to_string())
+    }
+}
+
 impl HttpGenerationOutputSource {
     pub async fn new(
         bind_addr: &str,
@@ -70,42 +100,83 @@ impl HttpGenerationOutputSource {
         max_line_bytes: usize,
         ndjson_strict: bool,
     ) -> Result<Self, PipelineError> {
-        let (tx, rx) = mpsc::channel(channel_capacity);
-        let shared = SharedSender {
-            tx,
+        Self::from_config(&HttpSourceConfig {
+            http_bind_addr: bind_addr.to_string(),
+            channel_capacity,
             auth_bearer_token,
+            bulk_auth_bearer_token: None,
+            shed_bulk_lag_ms: None,
+            bulk_channel_capacity: None,
+            realtime_drain_weight: config::default_realtime_drain_weight(),
+            max_body_bytes,
+            max_decompressed_bytes: config::default_max_decompressed_bytes(),
             max_request_records,
             max_line_bytes,
+            json_strict: true,
             ndjson_strict,
-        };
-
-        let app = Router::new()
-            .route("/ingest/generation_output", post(ingest_generation_output))
-            .route("/ingest/generation_output/ndjson", post(ingest_generation_output_ndjson))
-            .with_state(shared.clone())
-            .layer(DefaultBodyLimit::max(max_body_bytes));
+            ndjson_max_error_ratio: None,
+            ndjson_error_ratio_window: 0,
+            sync_ack: false,
+            sync_ack_timeout_ms: 0,
+            idempotency_ttl_secs: config::default_idempotency_ttl_secs(),
+            idempotency_max_keys: config::default_idempotency_max_keys(),
+            ws_ack_interval_ms: config::default_ws_ack_interval_ms(),
+            http2: config::default_true(),
+            http1_keep_alive: config::default_true(),
+            http1_header_read_timeout_ms: config::default_http1_header_read_timeout_ms(),
+            http2_keep_alive_interval_secs: None,
+            http2_keep_alive_timeout_secs: config::default_http2_keep_alive_timeout_secs(),
+            http2_max_concurrent_streams: None,
+            tcp_backlog: config::default_tcp_backlog(),
+            accept_gzip: config::default_true(),
+            tls: None,
+        })
+        .await
+    }
 
-        let addr: SocketAddr = bind_addr
-            .parse()
-            .map_err(|e| PipelineError::Source(format!("invalid bind addr: {e}")))?;
+    pub async fn from_config(cfg: &HttpSourceConfig) -> Result<Self, PipelineError> {
+        let (tx, rx) = lanes(
+            cfg.channel_capacity,
+            cfg.bulk_channel_capacity.unwrap_or(cfg.channel_capacity),
+        );
+        let lag = SinkLag::new();
+        let shared = SharedSender {
+            tx,
+            auth_bearer_token: cfg.auth_bearer_token.clone(),
+            admission: Admission::from_config(cfg, lag.clone()),
+            max_request_records: cfg.max_request_records,
+            max_line_bytes: cfg.max_line_bytes,
+            json_strict: cfg.json_strict,
+            ndjson_strict: cfg.ndjson_strict,
+            ndjson_error_ratio: cfg
+                .ndjson_max_error_ratio
+                .map(|ratio| (ratio, cfg.ndjson_error_ratio_window)),
+            sync_ack: cfg
+                .sync_ack
+                .then(|| Duration::from_millis(cfg.sync_ack_timeout_ms)),
+        };
 
-        // Fail-fast: if we can't bind, return an error to the caller.
-        let listener = tokio::net::TcpListener::bind(addr)
-            .await
-            .map_err(|e| PipelineError::Source(format!(
-                "failed to bind generation_output HTTP source: {e}"
-            )))?;
+        let mut app = Router::new()
+            .route("/ingest/generation_output", post(ingest_generation_output_body))
+            .route("/ingest/generation_output/ndjson", post(ingest_generation_output_ndjson));
+        if let Some(cache) = IdempotencyCache::from_config(cfg) {
+            app = app.route_layer(middleware::from_fn_with_state(cache, idempotency::replay_or_run));
+        }
+        let app = app.with_state(shared.clone());
 
-        tokio::spawn(async move {
-            if let Err(e) = axum::serve(listener, app.into_make_service()).await {
-                tracing::error!(error = %e, "HTTP generation_output source server error");
-            }
-        });
+        http_server::serve(cfg, app, "HTTP generation_output source")?;
 
         Ok(Self {
             receiver: Arc::new(tokio::sync::Mutex::new(Some(rx))),
+            realtime_drain_weight: cfg.realtime_drain_weight,
+            lag,
         })
     }
+
+    /// Lag handle to pass to this pipeline's sink (`with_lag`); drives bulk shedding.
+    pub fn sink_lag(&self) -> SinkLag {
+        self.lag.clone()
+    }
 }
 
 #[async_trait::async_trait]
@@ -120,121 +191,170 @@ impl Source<GenerationOutput> for HttpGenerationOutputSource {
             .take()
             .expect("HttpGenerationOutputSource stream already taken; only one consumer supported");
 
-        let stream = ReceiverStream::new(rx).map(Ok);
+        let stream = rx.into_stream(self.realtime_drain_weight).map(Ok);
         Box::pin(stream)
     }
 }
 
+/// `POST /ingest/generation_output`: a JSON array, or with the `protobuf`
+/// feature a `GenerationOutputBatch` sent as `application/x-protobuf`.
+async fn ingest_generation_output_body(
+    State(sender): State<SharedSender>,
+    request: Request,
+) -> Result<BatchResponse, ApiError> {
+    let headers = request.headers().clone();
+    let identity = request.extensions().get::<ClientIdentity>().cloned().map(Extension);
+    #[cfg(feature = "protobuf")]
+    if protobuf::is_protobuf(&headers) {
+        let body = axum::body::Bytes::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        return ingest_generation_output_protobuf(State(sender), identity, headers, body).await;
+    }
+    let payload = Json::from_request(request, &()).await;
+    ingest_generation_output(State(sender), identity, headers, payload).await
+}
+
 async fn ingest_generation_output(
     State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
     headers: axum::http::HeaderMap,
-    Json(payload): Json<Vec<IncomingGenerationOutput>>,
-) -> Result<(), axum::http::StatusCode> {
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<BatchResponse, ApiError> {
     use axum::http::StatusCode;
 
     metrics::counter!("http_generation_ingest_requests_total").increment(1);
 
-    crate::sources::http_json::authorize(
+    let priority = sender.admission.authorize(
         &headers,
+        identity.as_deref(),
         &sender.auth_bearer_token,
         "http_generation_ingest_unauthorized_total",
     )?;
+    sender.admission.admit(priority, &sender.tx)?;
 
+    let Json(payload) = payload?;
     if payload.len() > sender.max_request_records {
         metrics::counter!("http_generation_ingest_rejected_too_large_total").increment(1);
-        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    // Convert everything first so that in strict mode a bad record rejects the whole request.
+    let converted = convert_each(payload, incoming_to_output);
+    enqueue(&sender, priority, source_identity(identity), converted).await
+}
+
+/// A `GenerationOutputBatch` body; handled like a JSON array.
+#[cfg(feature = "protobuf")]
+async fn ingest_generation_output_protobuf(
+    State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<BatchResponse, ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_generation_ingest_requests_total").increment(1);
+    metrics::counter!("http_generation_ingest_protobuf_requests_total").increment(1);
+
+    let priority = sender.admission.authorize(
+        &headers,
+        identity.as_deref(),
+        &sender.auth_bearer_token,
+        "http_generation_ingest_unauthorized_total",
+    )?;
+    sender.admission.admit(priority, &sender.tx)?;
+
+    let batch: protobuf::proto::GenerationOutputBatch = protobuf::decode_batch(&body)?;
+    if batch.records.len() > sender.max_request_records {
+        metrics::counter!("http_generation_ingest_rejected_too_large_total").increment(1);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
     }
 
-    for incoming in payload {
-        let output: GenerationOutput = incoming_to_output(incoming)?;
+    let converted = protobuf::convert_messages(batch.records, protobuf::generation_output_from_record);
+    enqueue(&sender, priority, source_identity(identity), converted).await
+}
+
+/// Send the accepted records of a batch request to the pipeline (see
+/// `http_json::accept_batch`), answering once they were flushed in synchronous-ack mode.
+async fn enqueue(
+    sender: &SharedSender,
+    priority: Priority,
+    source_identity: Option<Arc<str>>,
+    converted: Converted<GenerationOutput>,
+) -> Result<BatchResponse, ApiError> {
+    use axum::http::StatusCode;
+
+    let (records, summary) =
+        accept_batch(converted, sender.json_strict, "http_generation_ingest_invalid_records_total")?;
+    let group = sender.sync_ack.map(|_| CompletionGroup::new());
+
+    for output in records {
         let env = Envelope {
             payload: output,
             received_at: SystemTime::now(),
+            completion: group.as_ref().map(CompletionGroup::track),
+            tenant: None,
+            source_identity: source_identity.clone(),
         };
 
-        match sender.tx.try_send(env) {
+        match sender.tx.lane(priority).try_send(env) {
             Ok(()) => {}
             Err(TrySendError::Full(_env)) => {
                 metrics::counter!("http_generation_ingest_rejected_overloaded_total").increment(1);
-                return Err(StatusCode::TOO_MANY_REQUESTS);
+                return Err(StatusCode::TOO_MANY_REQUESTS.into());
             }
             Err(TrySendError::Closed(_env)) => {
                 metrics::counter!("http_generation_ingest_failed_total").increment(1);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
             }
         }
     }
 
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[tokio::test]
-    async fn ndjson_lenient_skips_bad_lines_and_accepts_good_lines() {
-        let (tx, mut rx) = mpsc::channel(10);
-        let sender = SharedSender {
-            tx,
-            auth_bearer_token: None,
-            max_request_records: 10,
-            max_line_bytes: 1024,
-            ndjson_strict: false,
-        };
-
-        let body = Body::from(
-            "{\"ts\":\"2024-01-01T00:00:00Z\",\"plant_id\":\"p\",\"mw\":1.0}\nnot json\n{\"ts\":\"2024-01-01T00:15:00Z\",\"plant_id\":\"p\",\"mw\":2.0}\n",
-        );
-
-        let headers = axum::http::HeaderMap::new();
-        let res = ingest_generation_output_ndjson(State(sender), headers, body).await.unwrap();
-        assert_eq!(res.0.accepted, 2);
-        assert_eq!(res.0.parse_errors, 1);
-
-        let mut seen = 0;
-        while let Ok(_env) = rx.try_recv() {
-            seen += 1;
-        }
-        assert_eq!(seen, 2);
-    }
-}
-
-#[derive(Debug, serde::Serialize)]
-struct IngestSummary {
-    accepted: usize,
-    parse_errors: usize,
+    await_sync_ack(group, sender.sync_ack).await?;
+    Ok(batch_response(summary))
 }
 
 async fn ingest_generation_output_ndjson(
     State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
     headers: axum::http::HeaderMap,
     body: Body,
-) -> Result<axum::Json<IngestSummary>, axum::http::StatusCode> {
+) -> Result<axum::Json<IngestSummary>, ApiError> {
     use axum::http::StatusCode;
 
     metrics::counter!("http_generation_ingest_ndjson_requests_total").increment(1);
 
-    crate::sources::http_json::authorize(
+    let priority = sender.admission.authorize(
         &headers,
+        identity.as_deref(),
         &sender.auth_bearer_token,
         "http_generation_ingest_ndjson_unauthorized_total",
     )?;
+    sender.admission.admit(priority, &sender.tx)?;
+    let source_identity = source_identity(identity);
 
     let reader = StreamReader::new(
         body.into_data_stream()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
+            .map_err(std::io::Error::other),
     );
     let mut lines = tokio::io::BufReader::new(reader).lines();
 
     let mut accepted: usize = 0;
     let mut parse_errors: usize = 0;
+    let mut errors: Vec<FieldError> = Vec::new();
+    let mut line_no: usize = 0;
+    let mut error_ratio = sender
+        .ndjson_error_ratio
+        .map(|(ratio, window)| ErrorRatioCheck::new(ratio, window));
+    let group = sender.sync_ack.map(|_| CompletionGroup::new());
 
     while let Some(line) = lines
         .next_line()
         .await
-        .map_err(|_e| StatusCode::BAD_REQUEST)?
+        .map_err(body_read_error)?
     {
+        line_no += 1;
         let line = line.trim();
         if line.is_empty() {
             continue;
@@ -242,36 +362,34 @@ async fn ingest_generation_output_ndjson(
 
         if line.len() > sender.max_line_bytes {
             metrics::counter!("http_generation_ingest_ndjson_rejected_line_too_large_total").increment(1);
-            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "line too large").with_details(vec![
+                FieldError::record(format!("line exceeds {} bytes", sender.max_line_bytes)).at_line(line_no),
+            ]));
         }
 
         if accepted + parse_errors + 1 > sender.max_request_records {
             metrics::counter!("http_generation_ingest_ndjson_rejected_too_large_total").increment(1);
-            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
         }
 
-        let incoming: IncomingGenerationOutput = match serde_json::from_str(line) {
+        let output: GenerationOutput = match parse_record(line).and_then(incoming_to_output) {
             Ok(v) => v,
-            Err(_e) => {
+            Err(e) => {
                 parse_errors += 1;
                 metrics::counter!("http_generation_ingest_ndjson_parse_errors_total").increment(1);
+                let e = e.at_line(line_no);
 
                 if sender.ndjson_strict {
-                    return Err(StatusCode::BAD_REQUEST);
+                    return Err(ApiError::invalid(vec![e]));
                 }
 
-                continue;
-            }
-        };
-
-        let output: GenerationOutput = match incoming_to_output(incoming) {
-            Ok(v) => v,
-            Err(_e) => {
-                parse_errors += 1;
-                metrics::counter!("http_generation_ingest_ndjson_parse_errors_total").increment(1);
+                if errors.len() < MAX_ERROR_DETAILS {
+                    errors.push(e);
+                }
 
-                if sender.ndjson_strict {
-                    return Err(StatusCode::BAD_REQUEST);
+                if error_ratio.as_mut().is_some_and(|r| r.record(true)) {
+                    metrics::counter!("http_generation_ingest_ndjson_rejected_error_ratio_total").increment(1);
+                    return Err(error_ratio_exceeded(errors));
                 }
 
                 continue;
@@ -280,25 +398,76 @@ async fn ingest_generation_output_ndjson(
         let env = Envelope {
             payload: output,
             received_at: SystemTime::now(),
+            completion: group.as_ref().map(CompletionGroup::track),
+            tenant: None,
+            source_identity: source_identity.clone(),
         };
 
-        match sender.tx.try_send(env) {
+        match sender.tx.lane(priority).try_send(env) {
             Ok(()) => {
                 accepted += 1;
+                if let Some(r) = error_ratio.as_mut() {
+                    r.record(false);
+                }
             }
             Err(TrySendError::Full(_env)) => {
                 metrics::counter!("http_generation_ingest_ndjson_rejected_overloaded_total").increment(1);
-                return Err(StatusCode::TOO_MANY_REQUESTS);
+                return Err(StatusCode::TOO_MANY_REQUESTS.into());
             }
             Err(TrySendError::Closed(_env)) => {
                 metrics::counter!("http_generation_ingest_failed_total").increment(1);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
             }
         }
     }
 
+    if error_ratio.as_ref().is_some_and(ErrorRatioCheck::exceeded) {
+        metrics::counter!("http_generation_ingest_ndjson_rejected_error_ratio_total").increment(1);
+        return Err(error_ratio_exceeded(errors));
+    }
+
+    await_sync_ack(group, sender.sync_ack).await?;
+
     Ok(axum::Json(IngestSummary {
         accepted,
         parse_errors,
+        errors,
     }))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn ndjson_lenient_skips_bad_lines_and_accepts_good_lines() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+        };
+
+        let body = Body::from(
+            "{\"ts\":\"2024-01-01T00:00:00Z\",\"plant_id\":\"p\",\"mw\":1.0}\nnot json\n{\"ts\":\"2024-01-01T00:15:00Z\",\"plant_id\":\"p\",\"mw\":2.0}\n",
+        );
+
+        let headers = axum::http::HeaderMap::new();
+        let res = ingest_generation_output_ndjson(State(sender), None, headers, body).await.unwrap();
+        assert_eq!(res.0.accepted, 2);
+        assert_eq!(res.0.parse_errors, 1);
+
+        let mut seen = 0;
+        while let Ok(_env) = rx.try_recv() {
+            seen += 1;
+        }
+        assert_eq!(seen, 2);
+    }
+}
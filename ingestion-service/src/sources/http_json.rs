@@ -1,33 +1,56 @@
-use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use axum::{
     body::Body,
-    extract::{DefaultBodyLimit, State},
-    routing::post,
-    Json, Router,
+    extract::{rejection::JsonRejection, ws::WebSocketUpgrade, FromRequest, Request, State},
+    middleware,
+    response::Response,
+    routing::{get, post},
+    Extension, Json, Router,
 };
 use futures::{Stream, StreamExt, TryStreamExt};
-use rust_client::domain::MeterUsage;
+use rust_client::domain::{MeterUsage, PhaseChannels};
 use tokio::io::AsyncBufReadExt;
-use tokio::sync::mpsc;
 use tokio::sync::mpsc::error::TrySendError;
-use tokio_stream::wrappers::ReceiverStream;
 use tokio_util::io::StreamReader;
 
-use crate::pipeline::{Envelope, PipelineError, Source};
+use crate::{
+    config::{self, HttpSourceConfig},
+    pipeline::{lanes, CompletionGroup, Envelope, LaneReceiver, LaneSender, PipelineError, Priority, SinkLag, Source},
+    sources::admission::Admission,
+    sources::http_server,
+    sources::idempotency::{self, IdempotencyCache},
+    sources::http_ws::{self, WsIngest},
+    sources::json_record::JsonRecord,
+    sources::http_error::{body_read_error, convert_each, parse_record, parse_ts_field, ApiError, Converted, FieldError, MAX_ERROR_DETAILS},
+    tls::ClientIdentity,
+};
+#[cfg(feature = "protobuf")]
+use crate::sources::protobuf;
 
 #[derive(Clone)]
 struct SharedSender {
-    tx: mpsc::Sender<Envelope<MeterUsage>>,
+    tx: LaneSender<Envelope<MeterUsage>>,
     auth_bearer_token: Option<String>,
+    admission: Admission,
     max_request_records: usize,
     max_line_bytes: usize,
+    json_strict: bool,
     ndjson_strict: bool,
+    ndjson_error_ratio: Option<(f64, usize)>,
+    sync_ack: Option<Duration>,
+    max_body_bytes: usize,
+    ws_ack_interval: Duration,
 }
 
 #[derive(Clone)]
 pub struct HttpJsonSource {
-    receiver: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<MeterUsage>>>>>,
+    receiver: Arc<tokio::sync::Mutex<Option<LaneReceiver<Envelope<MeterUsage>>>>>,
+    realtime_drain_weight: u32,
+    lag: SinkLag,
 }
 
 #[derive(serde::Deserialize)]
@@ -40,18 +63,44 @@ struct IncomingMeterUsage {
     kva_demand: Option<f64>,
     quality_flag: Option<String>,
     source_system: Option<String>,
+    event_id: Option<String>,
+    #[serde(flatten)]
+    phases: IncomingPhases,
 }
 
-fn parse_ts(ts: &str) -> Result<time::OffsetDateTime, axum::http::StatusCode> {
-    use axum::http::StatusCode;
-    use time::format_description::well_known::Rfc3339;
+/// Optional per-phase channels of a meter usage payload (polyphase meters).
+#[derive(Debug, Default, serde::Deserialize)]
+pub(crate) struct IncomingPhases {
+    kwh_phase_a: Option<f64>,
+    kwh_phase_b: Option<f64>,
+    kwh_phase_c: Option<f64>,
+    current_phase_a: Option<f64>,
+    current_phase_b: Option<f64>,
+    current_phase_c: Option<f64>,
+    voltage_phase_a: Option<f64>,
+    voltage_phase_b: Option<f64>,
+    voltage_phase_c: Option<f64>,
+}
 
-    time::OffsetDateTime::parse(ts.trim(), &Rfc3339).map_err(|_e| StatusCode::BAD_REQUEST)
+impl From<IncomingPhases> for PhaseChannels {
+    fn from(i: IncomingPhases) -> Self {
+        PhaseChannels {
+            kwh_phase_a: i.kwh_phase_a,
+            kwh_phase_b: i.kwh_phase_b,
+            kwh_phase_c: i.kwh_phase_c,
+            current_phase_a: i.current_phase_a,
+            current_phase_b: i.current_phase_b,
+            current_phase_c: i.current_phase_c,
+            voltage_phase_a: i.voltage_phase_a,
+            voltage_phase_b: i.voltage_phase_b,
+            voltage_phase_c: i.voltage_phase_c,
+        }
+    }
 }
 
-fn incoming_to_usage(i: IncomingMeterUsage) -> Result<MeterUsage, axum::http::StatusCode> {
+fn incoming_to_usage(i: IncomingMeterUsage) -> Result<MeterUsage, FieldError> {
     Ok(MeterUsage {
-        ts: parse_ts(&i.ts)?,
+        ts: parse_ts_field("ts", &i.ts)?,
         meter_id: i.meter_id,
         premise_id: i.premise_id,
         kwh: i.kwh,
@@ -59,9 +108,34 @@ fn incoming_to_usage(i: IncomingMeterUsage) -> Result<MeterUsage, axum::http::St
         kva_demand: i.kva_demand,
         quality_flag: i.quality_flag,
         source_system: i.source_system,
+        event_id: i.event_id,
+        phases: i.phases.into(),
     })
 }
 
+/// Hold a record to the tenant of the key that sent it: `source_system`
+/// defaults to the tenant and may not name another.
+fn scope_to_tenant(mut usage: MeterUsage, tenant: Option<&str>) -> Result<MeterUsage, FieldError> {
+    let Some(tenant) = tenant else {
+        return Ok(usage);
+    };
+    match usage.source_system.as_deref() {
+        None => usage.source_system = Some(tenant.to_string()),
+        Some(s) if s == tenant => {}
+        Some(_) => {
+            metrics::counter!("http_ingest_tenant_mismatch_total").increment(1);
+            return Err(FieldError::field("source_system", format!("must be {tenant:?} for this API key")));
+        }
+    }
+    Ok(usage)
+}
+
+impl JsonRecord for MeterUsage {
+    fn from_json(json: &str) -> Result<Self, String> {
+        parse_record(json).and_then(incoming_to_usage).map_err(|e| e.to_string())
+    }
+}
+
 impl HttpJsonSource {
     pub async fn new(
         bind_addr: &str,
@@ -72,40 +146,87 @@ impl HttpJsonSource {
         max_line_bytes: usize,
         ndjson_strict: bool,
     ) -> Result<Self, PipelineError> {
-        let (tx, rx) = mpsc::channel(channel_capacity);
-        let shared = SharedSender {
-            tx,
+        Self::from_config(&HttpSourceConfig {
+            http_bind_addr: bind_addr.to_string(),
+            channel_capacity,
             auth_bearer_token,
+            bulk_auth_bearer_token: None,
+            shed_bulk_lag_ms: None,
+            bulk_channel_capacity: None,
+            realtime_drain_weight: config::default_realtime_drain_weight(),
+            max_body_bytes,
+            max_decompressed_bytes: config::default_max_decompressed_bytes(),
             max_request_records,
             max_line_bytes,
+            json_strict: true,
             ndjson_strict,
-        };
-
-        let app = Router::new()
-            .route("/ingest/meter_usage", post(ingest_meter_usage))
-            .route("/ingest/meter_usage/ndjson", post(ingest_meter_usage_ndjson))
-            .with_state(shared.clone())
-            .layer(DefaultBodyLimit::max(max_body_bytes));
+            ndjson_max_error_ratio: None,
+            ndjson_error_ratio_window: 0,
+            sync_ack: false,
+            sync_ack_timeout_ms: 0,
+            idempotency_ttl_secs: config::default_idempotency_ttl_secs(),
+            idempotency_max_keys: config::default_idempotency_max_keys(),
+            ws_ack_interval_ms: config::default_ws_ack_interval_ms(),
+            http2: config::default_true(),
+            http1_keep_alive: config::default_true(),
+            http1_header_read_timeout_ms: config::default_http1_header_read_timeout_ms(),
+            http2_keep_alive_interval_secs: None,
+            http2_keep_alive_timeout_secs: config::default_http2_keep_alive_timeout_secs(),
+            http2_max_concurrent_streams: None,
+            tcp_backlog: config::default_tcp_backlog(),
+            accept_gzip: config::default_true(),
+            tls: None,
+        })
+        .await
+    }
 
-        let addr: SocketAddr = bind_addr
-            .parse()
-            .map_err(|e| PipelineError::Source(format!("invalid bind addr: {e}")))?;
+    pub async fn from_config(cfg: &HttpSourceConfig) -> Result<Self, PipelineError> {
+        let (tx, rx) = lanes(
+            cfg.channel_capacity,
+            cfg.bulk_channel_capacity.unwrap_or(cfg.channel_capacity),
+        );
+        let lag = SinkLag::new();
+        let shared = SharedSender {
+            tx,
+            auth_bearer_token: cfg.auth_bearer_token.clone(),
+            admission: Admission::from_config(cfg, lag.clone()),
+            max_request_records: cfg.max_request_records,
+            max_line_bytes: cfg.max_line_bytes,
+            json_strict: cfg.json_strict,
+            ndjson_strict: cfg.ndjson_strict,
+            ndjson_error_ratio: cfg
+                .ndjson_max_error_ratio
+                .map(|ratio| (ratio, cfg.ndjson_error_ratio_window)),
+            sync_ack: cfg
+                .sync_ack
+                .then(|| Duration::from_millis(cfg.sync_ack_timeout_ms)),
+            max_body_bytes: cfg.max_body_bytes,
+            ws_ack_interval: Duration::from_millis(cfg.ws_ack_interval_ms.max(1)),
+        };
 
-        // Fail-fast: if we can't bind, return an error to the caller.
-        let listener = tokio::net::TcpListener::bind(addr)
-            .await
-            .map_err(|e| PipelineError::Source(format!("failed to bind HTTP JSON source: {e}")))?;
+        let mut app = Router::new()
+            .route("/ingest/meter_usage", post(ingest_meter_usage_body))
+            .route("/ingest/meter_usage/ndjson", post(ingest_meter_usage_ndjson));
+        if let Some(cache) = IdempotencyCache::from_config(cfg) {
+            app = app.route_layer(middleware::from_fn_with_state(cache, idempotency::replay_or_run));
+        }
+        let app = app
+            .route("/ingest/meter_usage/ws", get(ingest_meter_usage_ws))
+            .with_state(shared.clone());
 
-        tokio::spawn(async move {
-            if let Err(e) = axum::serve(listener, app.into_make_service()).await {
-                tracing::error!(error = %e, "HTTP JSON source server error");
-            }
-        });
+        http_server::serve(cfg, app, "HTTP JSON source")?;
 
         Ok(Self {
             receiver: Arc::new(tokio::sync::Mutex::new(Some(rx))),
+            realtime_drain_weight: cfg.realtime_drain_weight,
+            lag,
         })
     }
+
+    /// Lag handle to pass to this pipeline's sink (`with_lag`); drives bulk shedding.
+    pub fn sink_lag(&self) -> SinkLag {
+        self.lag.clone()
+    }
 }
 
 #[async_trait::async_trait]
@@ -120,167 +241,272 @@ impl Source<MeterUsage> for HttpJsonSource {
             .take()
             .expect("HttpJsonSource stream already taken; only one consumer supported");
 
-        let stream = ReceiverStream::new(rx).map(Ok);
+        let stream = rx.into_stream(self.realtime_drain_weight).map(Ok);
         Box::pin(stream)
     }
 }
 
+/// `POST /ingest/meter_usage`: a JSON array, or with the `protobuf` feature a
+/// `MeterUsageBatch` sent as `application/x-protobuf`.
+async fn ingest_meter_usage_body(
+    State(sender): State<SharedSender>,
+    request: Request,
+) -> Result<BatchResponse, ApiError> {
+    let headers = request.headers().clone();
+    let identity = request.extensions().get::<ClientIdentity>().cloned().map(Extension);
+    #[cfg(feature = "protobuf")]
+    if protobuf::is_protobuf(&headers) {
+        let body = axum::body::Bytes::from_request(request, &())
+            .await
+            .map_err(|e| ApiError::new(e.status(), e.body_text()))?;
+        return ingest_meter_usage_protobuf(State(sender), identity, headers, body).await;
+    }
+    let payload = Json::from_request(request, &()).await;
+    ingest_meter_usage(State(sender), identity, headers, payload).await
+}
+
 async fn ingest_meter_usage(
     State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
     headers: axum::http::HeaderMap,
-    Json(payload): Json<Vec<IncomingMeterUsage>>,
-) -> Result<(), axum::http::StatusCode> {
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<BatchResponse, ApiError> {
     use axum::http::StatusCode;
 
     metrics::counter!("http_ingest_requests_total").increment(1);
 
-    authorize(&headers, &sender.auth_bearer_token, "http_ingest_unauthorized_total")?;
+    let (priority, tenant) =
+        sender.admission.authorize_scoped(&headers, identity.as_deref(), &sender.auth_bearer_token, "http_ingest_unauthorized_total")?;
+    sender.admission.admit(priority, &sender.tx)?;
 
+    let Json(payload) = payload?;
     if payload.len() > sender.max_request_records {
         metrics::counter!("http_ingest_rejected_too_large_total").increment(1);
-        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    // Convert everything first so that in strict mode a bad record rejects the whole request.
+    let converted = convert_each(payload, |i| {
+        incoming_to_usage(i).and_then(|u| scope_to_tenant(u, tenant.as_deref()))
+    });
+    enqueue(&sender, priority, tenant, source_identity(identity), converted).await
+}
+
+/// A `MeterUsageBatch` body; handled like a JSON array.
+#[cfg(feature = "protobuf")]
+async fn ingest_meter_usage_protobuf(
+    State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: axum::http::HeaderMap,
+    body: axum::body::Bytes,
+) -> Result<BatchResponse, ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_ingest_requests_total").increment(1);
+    metrics::counter!("http_ingest_protobuf_requests_total").increment(1);
+
+    let (priority, tenant) =
+        sender.admission.authorize_scoped(&headers, identity.as_deref(), &sender.auth_bearer_token, "http_ingest_unauthorized_total")?;
+    sender.admission.admit(priority, &sender.tx)?;
+
+    let batch: protobuf::proto::MeterUsageBatch = protobuf::decode_batch(&body)?;
+    if batch.records.len() > sender.max_request_records {
+        metrics::counter!("http_ingest_rejected_too_large_total").increment(1);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
     }
 
-    for incoming in payload {
-        let usage: MeterUsage = incoming_to_usage(incoming)?;
+    let converted = protobuf::convert_messages(batch.records, |m| {
+        protobuf::meter_usage_from_record(m).and_then(|u| scope_to_tenant(u, tenant.as_deref()))
+    });
+    enqueue(&sender, priority, tenant, source_identity(identity), converted).await
+}
+
+/// Send the accepted records of a batch request to the pipeline (see
+/// [`accept_batch`]), answering once they were flushed in synchronous-ack mode.
+async fn enqueue(
+    sender: &SharedSender,
+    priority: Priority,
+    tenant: Option<String>,
+    source_identity: Option<Arc<str>>,
+    converted: Converted<MeterUsage>,
+) -> Result<BatchResponse, ApiError> {
+    use axum::http::StatusCode;
+
+    let (records, summary) = accept_batch(converted, sender.json_strict, "http_ingest_invalid_records_total")?;
+    let group = sender.sync_ack.map(|_| CompletionGroup::new());
+    let tenant: Option<Arc<str>> = tenant.map(Into::into);
+
+    for usage in records {
         let env = Envelope {
             payload: usage,
             received_at: SystemTime::now(),
+            completion: group.as_ref().map(CompletionGroup::track),
+            tenant: tenant.clone(),
+            source_identity: source_identity.clone(),
         };
 
-        match sender.tx.try_send(env) {
+        match sender.tx.lane(priority).try_send(env) {
             Ok(()) => {}
             Err(TrySendError::Full(_env)) => {
                 // Overloaded: apply load-shedding rather than holding the request open.
                 metrics::counter!("http_ingest_rejected_overloaded_total").increment(1);
-                return Err(StatusCode::TOO_MANY_REQUESTS);
+                return Err(StatusCode::TOO_MANY_REQUESTS.into());
             }
             Err(TrySendError::Closed(_env)) => {
                 metrics::counter!("http_ingest_failed_total").increment(1);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
             }
         }
     }
 
-    Ok(())
+    await_sync_ack(group, sender.sync_ack).await?;
+    Ok(batch_response(summary))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Response of the NDJSON and batch endpoints; `errors` lists the skipped lines
+/// or records (lenient mode).
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct IngestSummary {
+    pub(crate) accepted: usize,
+    pub(crate) parse_errors: usize,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+}
 
-    #[tokio::test]
-    async fn ndjson_lenient_skips_bad_lines_and_accepts_good_lines() {
-        let (tx, mut rx) = mpsc::channel(10);
-        let sender = SharedSender {
-            tx,
-            auth_bearer_token: None,
-            max_request_records: 10,
-            max_line_bytes: 1024,
-            ndjson_strict: false,
-        };
+/// Response of the batch endpoints (JSON array or protobuf): 200, or 207 when
+/// some records were rejected and the rest accepted.
+pub(crate) type BatchResponse = (axum::http::StatusCode, axum::Json<IngestSummary>);
 
-        let body = Body::from(
-            "{\"ts\":\"2024-01-01T00:00:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.0}\nnot json\n{\"ts\":\"2024-01-01T00:15:00Z\",\"meter_id\":\"m-1\",\"kwh\":2.0}\n",
-        );
+/// The records of a batch to ingest, and its summary.
+///
+/// In strict mode (`json_strict`) a bad record rejects the whole batch with 400.
+/// Otherwise the valid records are ingested and the bad ones reported in the
+/// summary and counted in `invalid_metric`; a batch without a valid record still
+/// gets 400.
+pub(crate) fn accept_batch<T>(
+    converted: Converted<T>,
+    strict: bool,
+    invalid_metric: &'static str,
+) -> Result<(Vec<T>, IngestSummary), ApiError> {
+    if converted.rejected > 0 && (strict || converted.records.is_empty()) {
+        return Err(ApiError::invalid(converted.errors));
+    }
+    metrics::counter!(invalid_metric).increment(converted.rejected as u64);
+    let summary = IngestSummary {
+        accepted: converted.records.len(),
+        parse_errors: converted.rejected,
+        errors: converted.errors,
+    };
+    Ok((converted.records, summary))
+}
 
-        let headers = axum::http::HeaderMap::new();
-        let res = ingest_meter_usage_ndjson(State(sender), headers, body).await.unwrap();
-        assert_eq!(res.0.accepted, 2);
-        assert_eq!(res.0.parse_errors, 1);
+pub(crate) fn batch_response(summary: IngestSummary) -> BatchResponse {
+    let status = if summary.parse_errors > 0 {
+        axum::http::StatusCode::MULTI_STATUS
+    } else {
+        axum::http::StatusCode::OK
+    };
+    (status, axum::Json(summary))
+}
 
-        // Drain accepted messages.
-        let mut seen = 0;
-        while let Ok(_env) = rx.try_recv() {
-            seen += 1;
+/// Lenient NDJSON kill switch: tracks parse errors among the first `window` lines.
+#[derive(Debug)]
+pub(crate) struct ErrorRatioCheck {
+    max_ratio: f64,
+    window: usize,
+    lines: usize,
+    errors: usize,
+}
+
+impl ErrorRatioCheck {
+    pub(crate) fn new(max_ratio: f64, window: usize) -> Self {
+        Self {
+            max_ratio,
+            window: window.max(1),
+            lines: 0,
+            errors: 0,
         }
-        assert_eq!(seen, 2);
     }
 
-    #[tokio::test]
-    async fn auth_rejects_when_token_set() {
-        let (tx, _rx) = mpsc::channel(10);
-        let sender = SharedSender {
-            tx,
-            auth_bearer_token: Some("secret".to_string()),
-            max_request_records: 10,
-            max_line_bytes: 1024,
-            ndjson_strict: false,
-        };
-
-        let headers = axum::http::HeaderMap::new();
-        let body = Body::from("{}\n");
-        let err = ingest_meter_usage_ndjson(State(sender), headers, body).await.unwrap_err();
-        assert_eq!(err, axum::http::StatusCode::UNAUTHORIZED);
+    /// Record one line. Returns true as soon as the window is certain to exceed the ratio.
+    pub(crate) fn record(&mut self, is_error: bool) -> bool {
+        if self.lines < self.window {
+            self.lines += 1;
+            if is_error {
+                self.errors += 1;
+            }
+        }
+        self.errors as f64 > self.max_ratio * self.window as f64
     }
-}
 
-#[derive(Debug, serde::Serialize)]
-struct IngestSummary {
-    accepted: usize,
-    parse_errors: usize,
+    /// Whether the ratio is exceeded over the lines seen (checked at the end of a request).
+    pub(crate) fn exceeded(&self) -> bool {
+        self.lines > 0 && self.errors as f64 > self.max_ratio * self.lines as f64
+    }
 }
 
-pub(crate) fn authorize(
-    headers: &axum::http::HeaderMap,
-    token: &Option<String>,
-    metric_name: &'static str,
+/// In synchronous-ack mode, wait until the sink flushed every record of the request.
+pub(crate) async fn await_sync_ack(
+    group: Option<CompletionGroup>,
+    timeout: Option<Duration>,
 ) -> Result<(), axum::http::StatusCode> {
     use axum::http::StatusCode;
 
-    let Some(expected) = token else {
+    let (Some(group), Some(timeout)) = (group, timeout) else {
         return Ok(());
     };
 
-    let Some(auth) = headers.get(axum::http::header::AUTHORIZATION) else {
-        metrics::counter!(metric_name).increment(1);
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    let Ok(auth) = auth.to_str() else {
-        metrics::counter!(metric_name).increment(1);
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    let Some(given) = auth.strip_prefix("Bearer ") else {
-        metrics::counter!(metric_name).increment(1);
-        return Err(StatusCode::UNAUTHORIZED);
-    };
-
-    if given != expected {
-        metrics::counter!(metric_name).increment(1);
-        return Err(StatusCode::UNAUTHORIZED);
+    match tokio::time::timeout(timeout, group.wait()).await {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            metrics::counter!("http_ingest_sync_ack_failed_total").increment(1);
+            Err(StatusCode::BAD_GATEWAY)
+        }
+        Err(_elapsed) => {
+            metrics::counter!("http_ingest_sync_ack_timeout_total").increment(1);
+            Err(StatusCode::GATEWAY_TIMEOUT)
+        }
     }
-
-    Ok(())
 }
 
 async fn ingest_meter_usage_ndjson(
     State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
     headers: axum::http::HeaderMap,
     body: Body,
-) -> Result<axum::Json<IngestSummary>, axum::http::StatusCode> {
+) -> Result<axum::Json<IngestSummary>, ApiError> {
     use axum::http::StatusCode;
 
     metrics::counter!("http_ingest_ndjson_requests_total").increment(1);
 
-    authorize(&headers, &sender.auth_bearer_token, "http_ingest_ndjson_unauthorized_total")?;
+    let (priority, tenant) =
+        sender.admission.authorize_scoped(&headers, identity.as_deref(), &sender.auth_bearer_token, "http_ingest_ndjson_unauthorized_total")?;
+    sender.admission.admit(priority, &sender.tx)?;
+    let tenant: Option<Arc<str>> = tenant.map(Into::into);
+    let source_identity = source_identity(identity);
 
     // Convert Body -> data stream -> AsyncRead -> lines() for streaming NDJSON parsing.
     let reader = StreamReader::new(
         body.into_data_stream()
-            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e)),
+            .map_err(std::io::Error::other),
     );
     let mut lines = tokio::io::BufReader::new(reader).lines();
 
     let mut accepted: usize = 0;
     let mut parse_errors: usize = 0;
+    let mut errors: Vec<FieldError> = Vec::new();
+    let mut line_no: usize = 0;
+    let mut error_ratio = sender
+        .ndjson_error_ratio
+        .map(|(ratio, window)| ErrorRatioCheck::new(ratio, window));
+    let group = sender.sync_ack.map(|_| CompletionGroup::new());
 
     while let Some(line) = lines
         .next_line()
         .await
-        .map_err(|_e| StatusCode::BAD_REQUEST)?
+        .map_err(body_read_error)?
     {
+        line_no += 1;
         let line = line.trim();
         if line.is_empty() {
             continue;
@@ -288,36 +514,37 @@ async fn ingest_meter_usage_ndjson(
 
         if line.len() > sender.max_line_bytes {
             metrics::counter!("http_ingest_ndjson_rejected_line_too_large_total").increment(1);
-            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            return Err(ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "line too large").with_details(vec![
+                FieldError::record(format!("line exceeds {} bytes", sender.max_line_bytes)).at_line(line_no),
+            ]));
         }
 
         if accepted + parse_errors + 1 > sender.max_request_records {
             metrics::counter!("http_ingest_ndjson_rejected_too_large_total").increment(1);
-            return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
         }
 
-        let incoming: IncomingMeterUsage = match serde_json::from_str(line) {
+        let parsed = parse_record(line)
+            .and_then(incoming_to_usage)
+            .and_then(|u| scope_to_tenant(u, tenant.as_deref()));
+        let usage: MeterUsage = match parsed {
             Ok(v) => v,
-            Err(_e) => {
+            Err(e) => {
                 parse_errors += 1;
                 metrics::counter!("http_ingest_ndjson_parse_errors_total").increment(1);
+                let e = e.at_line(line_no);
 
                 if sender.ndjson_strict {
-                    return Err(StatusCode::BAD_REQUEST);
+                    return Err(ApiError::invalid(vec![e]));
                 }
 
-                continue;
-            }
-        };
-
-        let usage: MeterUsage = match incoming_to_usage(incoming) {
-            Ok(v) => v,
-            Err(_e) => {
-                parse_errors += 1;
-                metrics::counter!("http_ingest_ndjson_parse_errors_total").increment(1);
+                if errors.len() < MAX_ERROR_DETAILS {
+                    errors.push(e);
+                }
 
-                if sender.ndjson_strict {
-                    return Err(StatusCode::BAD_REQUEST);
+                if error_ratio.as_mut().is_some_and(|r| r.record(true)) {
+                    metrics::counter!("http_ingest_ndjson_rejected_error_ratio_total").increment(1);
+                    return Err(error_ratio_exceeded(errors));
                 }
 
                 continue;
@@ -326,25 +553,511 @@ async fn ingest_meter_usage_ndjson(
         let env = Envelope {
             payload: usage,
             received_at: SystemTime::now(),
+            completion: group.as_ref().map(CompletionGroup::track),
+            tenant: tenant.clone(),
+            source_identity: source_identity.clone(),
         };
 
-        match sender.tx.try_send(env) {
+        match sender.tx.lane(priority).try_send(env) {
             Ok(()) => {
                 accepted += 1;
+                if let Some(r) = error_ratio.as_mut() {
+                    r.record(false);
+                }
             }
             Err(TrySendError::Full(_env)) => {
                 metrics::counter!("http_ingest_ndjson_rejected_overloaded_total").increment(1);
-                return Err(StatusCode::TOO_MANY_REQUESTS);
+                return Err(StatusCode::TOO_MANY_REQUESTS.into());
             }
             Err(TrySendError::Closed(_env)) => {
                 metrics::counter!("http_ingest_failed_total").increment(1);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+                return Err(StatusCode::INTERNAL_SERVER_ERROR.into());
             }
         }
     }
 
+    if error_ratio.as_ref().is_some_and(ErrorRatioCheck::exceeded) {
+        metrics::counter!("http_ingest_ndjson_rejected_error_ratio_total").increment(1);
+        return Err(error_ratio_exceeded(errors));
+    }
+
+    await_sync_ack(group, sender.sync_ack).await?;
+
     Ok(axum::Json(IngestSummary {
         accepted,
         parse_errors,
+        errors,
     }))
 }
+
+/// Long-lived NDJSON stream over a WebSocket; see [`http_ws::serve`].
+///
+/// Authorization and bulk shedding apply when the connection opens; after
+/// that a full channel holds the connection back instead of shedding it.
+async fn ingest_meter_usage_ws(
+    State(sender): State<SharedSender>,
+    identity: Option<Extension<ClientIdentity>>,
+    headers: axum::http::HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, ApiError> {
+    let (priority, tenant) =
+        sender.admission.authorize_scoped(&headers, identity.as_deref(), &sender.auth_bearer_token, "http_ingest_ws_unauthorized_total")?;
+    sender.admission.admit(priority, &sender.tx)?;
+
+    let ingest = WsIngest {
+        tx: sender.tx.lane(priority).clone(),
+        max_line_bytes: sender.max_line_bytes,
+        ndjson_strict: sender.ndjson_strict,
+        ndjson_error_ratio: sender.ndjson_error_ratio,
+        ack_interval: sender.ws_ack_interval,
+        tenant: tenant.as_deref().map(Into::into),
+        source_identity: source_identity(identity),
+        parse: Box::new(move |line| {
+            parse_record(line)
+                .and_then(incoming_to_usage)
+                .and_then(|u| scope_to_tenant(u, tenant.as_deref()))
+        }),
+    };
+    Ok(ws
+        .max_message_size(sender.max_body_bytes)
+        .on_upgrade(move |socket| http_ws::serve(socket, ingest)))
+}
+
+/// Common name of the client certificate a request was sent with, for its envelopes.
+pub(crate) fn source_identity(identity: Option<Extension<ClientIdentity>>) -> Option<Arc<str>> {
+    identity.map(|Extension(ClientIdentity(cn))| cn)
+}
+
+/// 400 for a lenient NDJSON request over the parse-error ratio, listing the bad lines.
+pub(crate) fn error_ratio_exceeded(errors: Vec<FieldError>) -> ApiError {
+    ApiError::new(axum::http::StatusCode::BAD_REQUEST, "parse error ratio exceeded").with_details(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    #[tokio::test]
+    async fn ndjson_lenient_skips_bad_lines_and_accepts_good_lines() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let body = Body::from(
+            "{\"ts\":\"2024-01-01T00:00:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.0}\nnot json\n{\"ts\":\"2024-01-01T00:15:00Z\",\"meter_id\":\"m-1\",\"kwh\":2.0}\n",
+        );
+
+        let headers = axum::http::HeaderMap::new();
+        let res = ingest_meter_usage_ndjson(State(sender), None, headers, body).await.unwrap();
+        assert_eq!(res.0.accepted, 2);
+        assert_eq!(res.0.parse_errors, 1);
+        assert_eq!(res.0.errors[0].line, Some(2));
+
+        // Drain accepted messages.
+        let mut seen = 0;
+        while let Ok(_env) = rx.try_recv() {
+            seen += 1;
+        }
+        assert_eq!(seen, 2);
+    }
+
+    #[tokio::test]
+    async fn auth_rejects_when_token_set() {
+        let (tx, _rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: Some("secret".to_string()),
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let headers = axum::http::HeaderMap::new();
+        let body = Body::from("{}\n");
+        let err = ingest_meter_usage_ndjson(State(sender), None, headers, body).await.unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn sync_ack_waits_for_sink_completion() {
+        let (tx, mut rx) = mpsc::channel::<Envelope<MeterUsage>>(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: Some(Duration::from_secs(5)),
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        // Fake sink: complete the first record, drop the second without completing it.
+        let sink = tokio::spawn(async move {
+            let first = rx.recv().await.unwrap();
+            first.complete();
+            let _second = rx.recv().await.unwrap();
+        });
+
+        let body = Body::from(
+            "{\"ts\":\"2024-01-01T00:00:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.0}\n{\"ts\":\"2024-01-01T00:15:00Z\",\"meter_id\":\"m-1\",\"kwh\":2.0}\n",
+        );
+        let err = ingest_meter_usage_ndjson(State(sender), None, axum::http::HeaderMap::new(), body)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::BAD_GATEWAY);
+        sink.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn sync_ack_times_out_when_sink_is_stalled() {
+        let (tx, _rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: Some(Duration::from_millis(20)),
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let body = Body::from("{\"ts\":\"2024-01-01T00:00:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.0}\n");
+        let err = ingest_meter_usage_ndjson(State(sender), None, axum::http::HeaderMap::new(), body)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::GATEWAY_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn ndjson_over_body_limit_is_payload_too_large() {
+        let (tx, _rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        // As limited by `http_server`.
+        let line = "{\"ts\":\"2024-01-01T00:00:00Z\",\"meter_id\":\"m-1\",\"kwh\":1.0}\n";
+        let body = Body::new(http_body_util::Limited::new(Body::from(line.repeat(3)), 100));
+        let err = ingest_meter_usage_ndjson(State(sender), None, axum::http::HeaderMap::new(), body)
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    #[tokio::test]
+    async fn ndjson_lenient_rejects_request_over_error_ratio() {
+        let (tx, _rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: Some((0.5, 1000)),
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let body = Body::from("meter_id,ts,kwh\nm-1,2024-01-01T00:00:00Z,1.0\n");
+
+        let headers = axum::http::HeaderMap::new();
+        let err = ingest_meter_usage_ndjson(State(sender), None, headers, body).await.unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(err.details[0].line, Some(1));
+    }
+
+    #[tokio::test]
+    async fn strict_json_array_reports_every_bad_record_and_enqueues_none() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: true,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let payload = serde_json::from_str(
+            r#"[{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0},
+                {"ts":"2024-01-01","meter_id":"m-1","kwh":1.0},
+                {"ts":"2024-01-01T00:30:00Z","meter_id":"m-1"}]"#,
+        )
+        .unwrap();
+        let err = ingest_meter_usage(State(sender), None, axum::http::HeaderMap::new(), Ok(Json(payload)))
+            .await
+            .unwrap_err();
+
+        assert_eq!(err.status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(err.details.len(), 2);
+        assert_eq!((err.details[0].index, err.details[0].field.as_deref()), (Some(1), Some("ts")));
+        assert_eq!(err.details[1].index, Some(2));
+        assert!(err.details[1].reason.contains("missing field `kwh`"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[tokio::test]
+    async fn protobuf_bodies_are_selected_by_content_type() {
+        use prost::Message;
+        use protobuf::proto::{MeterUsageBatch, MeterUsageRecord};
+
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+        let request = |content_type: &str, body: Vec<u8>| {
+            axum::http::Request::post("/ingest/meter_usage")
+                .header("content-type", content_type)
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let batch = MeterUsageBatch {
+            records: vec![MeterUsageRecord {
+                ts_micros: 1_704_067_200_000_000,
+                meter_id: "m-1".to_string(),
+                kwh: 1.5,
+                ..Default::default()
+            }],
+        };
+        let (status, _) =
+            ingest_meter_usage_body(State(sender.clone()), request("application/x-protobuf", batch.encode_to_vec()))
+                .await
+                .unwrap();
+        assert_eq!(status, axum::http::StatusCode::OK);
+        let env = rx.try_recv().unwrap();
+        assert_eq!((env.payload.meter_id.as_str(), env.payload.kwh), ("m-1", 1.5));
+
+        let json = br#"[{"ts":"2024-01-01T00:15:00Z","meter_id":"m-2","kwh":2.0}]"#.to_vec();
+        let (status, _) = ingest_meter_usage_body(State(sender.clone()), request("application/json", json))
+            .await
+            .unwrap();
+        assert_eq!(status, axum::http::StatusCode::OK);
+        assert_eq!(rx.try_recv().unwrap().payload.meter_id, "m-2");
+
+        let err = ingest_meter_usage_body(State(sender), request("application/x-protobuf", b"{}".to_vec()))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn json_array_accepts_valid_records_and_lists_the_rest() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+
+        let payload = serde_json::from_str(
+            r#"[{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0},
+                {"ts":"2024-01-01","meter_id":"m-1","kwh":1.0},
+                {"ts":"2024-01-01T00:30:00Z","meter_id":"m-2","kwh":3.0}]"#,
+        )
+        .unwrap();
+        let (status, Json(summary)) =
+            ingest_meter_usage(State(sender.clone()), None, axum::http::HeaderMap::new(), Ok(Json(payload)))
+                .await
+                .unwrap();
+        assert_eq!(status, axum::http::StatusCode::MULTI_STATUS);
+        assert_eq!((summary.accepted, summary.parse_errors), (2, 1));
+        assert_eq!((summary.errors[0].index, summary.errors[0].field.as_deref()), (Some(1), Some("ts")));
+        assert_eq!(rx.try_recv().unwrap().payload.meter_id, "m-1");
+        assert_eq!(rx.try_recv().unwrap().payload.meter_id, "m-2");
+
+        // Nothing valid: still a 400.
+        let payload = serde_json::from_str(r#"[{"ts":"2024-01-01","meter_id":"m-1","kwh":1.0}]"#).unwrap();
+        let err = ingest_meter_usage(State(sender), None, axum::http::HeaderMap::new(), Ok(Json(payload)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn parses_optional_phase_channels() {
+        let single: IncomingMeterUsage =
+            parse_record(r#"{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0}"#).unwrap();
+        assert!(incoming_to_usage(single).unwrap().phases.is_empty());
+
+        let polyphase: IncomingMeterUsage = parse_record(
+            r#"{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":3.0,"kwh_phase_b":1.5,"voltage_phase_b":480}"#,
+        )
+        .unwrap();
+        let phases = incoming_to_usage(polyphase).unwrap().phases;
+        assert_eq!(phases.kwh_phase_b, Some(1.5));
+        assert_eq!(phases.voltage_phase_b, Some(480.0));
+        assert_eq!(phases.kwh_phase_a, None);
+    }
+
+    #[test]
+    fn tenant_keys_fill_in_and_enforce_source_system() {
+        let usage = |source: &str| {
+            let line = format!(r#"{{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0{source}}}"#);
+            incoming_to_usage(parse_record(&line).unwrap()).unwrap()
+        };
+
+        let scoped = scope_to_tenant(usage(""), Some("vendor-a")).unwrap();
+        assert_eq!(scoped.source_system.as_deref(), Some("vendor-a"));
+        assert!(scope_to_tenant(usage(r#","source_system":"vendor-a""#), Some("vendor-a")).is_ok());
+
+        let err = scope_to_tenant(usage(r#","source_system":"vendor-b""#), Some("vendor-a")).unwrap_err();
+        assert_eq!(err.field.as_deref(), Some("source_system"));
+
+        // Keys without a tenant leave records as they are.
+        assert_eq!(scope_to_tenant(usage(""), None).unwrap().source_system, None);
+    }
+
+    #[tokio::test]
+    async fn batches_carry_the_tenant_of_their_key() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: None,
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+        let record = || {
+            let line = r#"{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0}"#;
+            let mut converted = Converted::with_capacity(1);
+            converted.push(0, incoming_to_usage(parse_record(line).unwrap()));
+            converted
+        };
+
+        let _ = enqueue(&sender, Priority::RealTime, Some("vendor-a".to_string()), None, record()).await.unwrap();
+        let _ = enqueue(&sender, Priority::RealTime, None, None, record()).await.unwrap();
+
+        assert_eq!(rx.recv().await.unwrap().tenant.as_deref(), Some("vendor-a"));
+        assert_eq!(rx.recv().await.unwrap().tenant, None);
+    }
+
+    #[tokio::test]
+    async fn client_certificates_authenticate_and_identify_the_source() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            tx: LaneSender::new(tx.clone(), tx),
+            auth_bearer_token: Some("secret".to_string()),
+            admission: Admission::default(),
+            max_request_records: 10,
+            max_line_bytes: 1024,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_error_ratio: None,
+            sync_ack: None,
+            max_body_bytes: 1 << 20,
+            ws_ack_interval: Duration::from_secs(1),
+        };
+        let payload = || {
+            let record = serde_json::json!({"ts": "2024-01-01T00:00:00Z", "meter_id": "m-1", "kwh": 1.0});
+            Ok(Json(vec![record]))
+        };
+
+        let err = ingest_meter_usage(State(sender.clone()), None, axum::http::HeaderMap::new(), payload())
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::UNAUTHORIZED);
+
+        let gateway = Extension(ClientIdentity("substation-17".into()));
+        let _ = ingest_meter_usage(State(sender), Some(gateway), axum::http::HeaderMap::new(), payload())
+            .await
+            .unwrap();
+        let env = rx.recv().await.unwrap();
+        assert_eq!(env.source_identity.as_deref(), Some("substation-17"));
+        assert_eq!(env.tenant, None);
+    }
+
+    #[test]
+    fn error_ratio_check_only_considers_the_window() {
+        let mut check = ErrorRatioCheck::new(0.5, 4);
+        assert!(!check.record(true));
+        assert!(!check.record(true));
+        assert!(!check.record(false));
+        assert!(!check.record(false));
+        // Errors after the window don't count.
+        assert!(!check.record(true));
+        assert!(!check.exceeded());
+
+        let mut check = ErrorRatioCheck::new(0.5, 4);
+        assert!(!check.record(true));
+        assert!(!check.record(true));
+        assert!(check.record(true));
+
+        // Shorter than the window: judged on the lines seen.
+        let mut check = ErrorRatioCheck::new(0.5, 1000);
+        check.record(false);
+        check.record(true);
+        assert!(!check.exceeded());
+        check.record(true);
+        assert!(check.exceeded());
+    }
+}
@@ -0,0 +1,507 @@
+use std::{sync::Arc, time::SystemTime};
+
+use axum::{
+    extract::{rejection::JsonRejection, State},
+    routing::post,
+    Json, Router,
+};
+use futures::{Stream, StreamExt};
+use rust_client::domain::{Customer, DrEvent, Meter, MeterExchange};
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::{self, HttpSourceConfig, Role},
+    pipeline::{Envelope, PipelineError, Source},
+    sources::http_error::{convert_records, parse_ts_field, ApiError, FieldError},
+    sources::http_server,
+};
+
+#[derive(Clone)]
+struct SharedSender {
+    meters_tx: mpsc::Sender<Envelope<Meter>>,
+    customers_tx: mpsc::Sender<Envelope<Customer>>,
+    exchanges_tx: mpsc::Sender<Envelope<MeterExchange>>,
+    dr_events_tx: mpsc::Sender<Envelope<DrEvent>>,
+    auth_bearer_token: Option<String>,
+    max_request_records: usize,
+}
+
+/// HTTP source for the `meters` / `customers` / `meter_exchanges` / `dr_events`
+/// reference tables.
+///
+/// Serves:
+/// - `POST /reference/meters` (JSON array of meter versions)
+/// - `POST /reference/customers` (JSON array of customer versions)
+/// - `POST /reference/meter_exchanges` (JSON array of exchange events)
+/// - `POST /reference/dr_events` (JSON array of demand response events)
+///
+/// Each meter/customer record is an effective-dated version. `effective_from`
+/// defaults to the time of the request; `deleted: true` soft-deletes the entity
+/// from then on. Exchange and DR events must carry their own timestamps.
+///
+/// The same value is a `Source<Meter>`, `Source<Customer>`, `Source<MeterExchange>`
+/// and `Source<DrEvent>`; clone it to feed one pipeline per table.
+#[derive(Clone)]
+pub struct HttpReferenceSource {
+    meters_rx: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<Meter>>>>>,
+    customers_rx: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<Customer>>>>>,
+    exchanges_rx: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<MeterExchange>>>>>,
+    dr_events_rx: Arc<tokio::sync::Mutex<Option<mpsc::Receiver<Envelope<DrEvent>>>>>,
+}
+
+#[derive(serde::Deserialize)]
+struct IncomingMeter {
+    meter_id: String,
+    effective_from: Option<String>,
+    premise_id: Option<String>,
+    customer_id: Option<String>,
+    feeder_id: Option<String>,
+    substation_id: Option<String>,
+    tariff_code: Option<String>,
+    meter_type: Option<String>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct IncomingCustomer {
+    customer_id: String,
+    effective_from: Option<String>,
+    segment: Option<String>,
+    name: Option<String>,
+    region_id: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(default)]
+    deleted: bool,
+}
+
+#[derive(serde::Deserialize)]
+struct IncomingMeterExchange {
+    ts: String,
+    premise_id: String,
+    old_meter_id: String,
+    new_meter_id: String,
+    reason: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct IncomingDrEvent {
+    event_id: String,
+    program: String,
+    start: String,
+    end: String,
+    meter_ids: Vec<String>,
+}
+
+fn parse_effective_from(ts: Option<&str>) -> Result<time::OffsetDateTime, FieldError> {
+    match ts {
+        Some(ts) => parse_ts_field("effective_from", ts),
+        None => Ok(time::OffsetDateTime::now_utc()),
+    }
+}
+
+fn incoming_to_meter(i: IncomingMeter) -> Result<Meter, FieldError> {
+    Ok(Meter {
+        effective_from: parse_effective_from(i.effective_from.as_deref())?,
+        meter_id: i.meter_id,
+        premise_id: i.premise_id,
+        customer_id: i.customer_id,
+        feeder_id: i.feeder_id,
+        substation_id: i.substation_id,
+        tariff_code: i.tariff_code,
+        meter_type: i.meter_type,
+        deleted: i.deleted,
+    })
+}
+
+fn incoming_to_customer(i: IncomingCustomer) -> Result<Customer, FieldError> {
+    Ok(Customer {
+        effective_from: parse_effective_from(i.effective_from.as_deref())?,
+        customer_id: i.customer_id,
+        segment: i.segment,
+        name: i.name,
+        region_id: i.region_id,
+        lat: i.lat,
+        lon: i.lon,
+        deleted: i.deleted,
+    })
+}
+
+fn incoming_to_exchange(i: IncomingMeterExchange) -> Result<MeterExchange, FieldError> {
+    if i.old_meter_id == i.new_meter_id {
+        return Err(FieldError::field("new_meter_id", "must differ from old_meter_id"));
+    }
+
+    Ok(MeterExchange {
+        ts: parse_ts_field("ts", &i.ts)?,
+        premise_id: i.premise_id,
+        old_meter_id: i.old_meter_id,
+        new_meter_id: i.new_meter_id,
+        reason: i.reason.unwrap_or_else(|| "exchange".to_string()),
+    })
+}
+
+fn incoming_to_dr_event(i: IncomingDrEvent) -> Result<DrEvent, FieldError> {
+    let start = parse_ts_field("start", &i.start)?;
+    let end = parse_ts_field("end", &i.end)?;
+    if end <= start {
+        return Err(FieldError::field("end", "must be after start"));
+    }
+    if i.meter_ids.is_empty() {
+        return Err(FieldError::field("meter_ids", "must not be empty"));
+    }
+
+    Ok(DrEvent {
+        event_id: i.event_id,
+        program: i.program,
+        start,
+        end,
+        meter_ids: i.meter_ids,
+    })
+}
+
+impl HttpReferenceSource {
+    pub async fn new(
+        bind_addr: &str,
+        channel_capacity: usize,
+        auth_bearer_token: Option<String>,
+        max_body_bytes: usize,
+        max_request_records: usize,
+    ) -> Result<Self, PipelineError> {
+        Self::from_config(&HttpSourceConfig {
+            http_bind_addr: bind_addr.to_string(),
+            channel_capacity,
+            auth_bearer_token,
+            bulk_auth_bearer_token: None,
+            shed_bulk_lag_ms: None,
+            bulk_channel_capacity: None,
+            realtime_drain_weight: config::default_realtime_drain_weight(),
+            max_body_bytes,
+            max_decompressed_bytes: config::default_max_decompressed_bytes(),
+            max_request_records,
+            max_line_bytes: 0,
+            json_strict: false,
+            ndjson_strict: false,
+            ndjson_max_error_ratio: None,
+            ndjson_error_ratio_window: 0,
+            sync_ack: false,
+            sync_ack_timeout_ms: 0,
+            idempotency_ttl_secs: config::default_idempotency_ttl_secs(),
+            idempotency_max_keys: config::default_idempotency_max_keys(),
+            ws_ack_interval_ms: config::default_ws_ack_interval_ms(),
+            http2: config::default_true(),
+            http1_keep_alive: config::default_true(),
+            http1_header_read_timeout_ms: config::default_http1_header_read_timeout_ms(),
+            http2_keep_alive_interval_secs: None,
+            http2_keep_alive_timeout_secs: config::default_http2_keep_alive_timeout_secs(),
+            http2_max_concurrent_streams: None,
+            tcp_backlog: config::default_tcp_backlog(),
+            accept_gzip: config::default_true(),
+            tls: None,
+        })
+        .await
+    }
+
+    /// Build from a source config; NDJSON and sync-ack settings don't apply here.
+    pub async fn from_config(cfg: &HttpSourceConfig) -> Result<Self, PipelineError> {
+        let (meters_tx, meters_rx) = mpsc::channel(cfg.channel_capacity);
+        let (customers_tx, customers_rx) = mpsc::channel(cfg.channel_capacity);
+        let (exchanges_tx, exchanges_rx) = mpsc::channel(cfg.channel_capacity);
+        let (dr_events_tx, dr_events_rx) = mpsc::channel(cfg.channel_capacity);
+        let shared = SharedSender {
+            meters_tx,
+            customers_tx,
+            exchanges_tx,
+            dr_events_tx,
+            auth_bearer_token: cfg.auth_bearer_token.clone(),
+            max_request_records: cfg.max_request_records,
+        };
+
+        let app = Router::new()
+            .route("/reference/meters", post(sync_meters))
+            .route("/reference/customers", post(sync_customers))
+            .route("/reference/meter_exchanges", post(sync_meter_exchanges))
+            .route("/reference/dr_events", post(sync_dr_events))
+            .with_state(shared);
+
+        http_server::serve(cfg, app, "HTTP reference source")?;
+
+        Ok(Self {
+            meters_rx: Arc::new(tokio::sync::Mutex::new(Some(meters_rx))),
+            customers_rx: Arc::new(tokio::sync::Mutex::new(Some(customers_rx))),
+            exchanges_rx: Arc::new(tokio::sync::Mutex::new(Some(exchanges_rx))),
+            dr_events_rx: Arc::new(tokio::sync::Mutex::new(Some(dr_events_rx))),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<Meter> for HttpReferenceSource {
+    async fn stream(&self) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<Meter>, PipelineError>> + Send>> {
+        let mut guard = self.meters_rx.lock().await;
+        let rx = guard
+            .take()
+            .expect("HttpReferenceSource meters stream already taken; only one consumer supported");
+
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<Customer> for HttpReferenceSource {
+    async fn stream(&self) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<Customer>, PipelineError>> + Send>> {
+        let mut guard = self.customers_rx.lock().await;
+        let rx = guard
+            .take()
+            .expect("HttpReferenceSource customers stream already taken; only one consumer supported");
+
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterExchange> for HttpReferenceSource {
+    async fn stream(
+        &self,
+    ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterExchange>, PipelineError>> + Send>> {
+        let mut guard = self.exchanges_rx.lock().await;
+        let rx = guard
+            .take()
+            .expect("HttpReferenceSource meter_exchanges stream already taken; only one consumer supported");
+
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<DrEvent> for HttpReferenceSource {
+    async fn stream(&self) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<DrEvent>, PipelineError>> + Send>> {
+        let mut guard = self.dr_events_rx.lock().await;
+        let rx = guard
+            .take()
+            .expect("HttpReferenceSource dr_events stream already taken; only one consumer supported");
+
+        Box::pin(ReceiverStream::new(rx).map(Ok))
+    }
+}
+
+fn enqueue<T>(tx: &mpsc::Sender<Envelope<T>>, payload: T) -> Result<(), axum::http::StatusCode> {
+    use axum::http::StatusCode;
+
+    let env = Envelope {
+        payload,
+        received_at: SystemTime::now(),
+        completion: None,
+        tenant: None,
+        source_identity: None,
+    };
+
+    match tx.try_send(env) {
+        Ok(()) => Ok(()),
+        Err(TrySendError::Full(_env)) => {
+            metrics::counter!("http_reference_rejected_overloaded_total").increment(1);
+            Err(StatusCode::TOO_MANY_REQUESTS)
+        }
+        Err(TrySendError::Closed(_env)) => {
+            metrics::counter!("http_reference_failed_total").increment(1);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn sync_meters(
+    State(sender): State<SharedSender>,
+    headers: axum::http::HeaderMap,
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<(), ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_reference_meters_requests_total").increment(1);
+
+    crate::auth::authorize(&headers, &sender.auth_bearer_token, Role::Admin, "http_reference_unauthorized_total")?;
+
+    let Json(payload) = payload?;
+    if payload.len() > sender.max_request_records {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    // Convert everything first so a bad record rejects the whole request.
+    let meters = convert_records(payload, incoming_to_meter)?;
+
+    for meter in meters {
+        enqueue(&sender.meters_tx, meter)?;
+    }
+
+    Ok(())
+}
+
+async fn sync_customers(
+    State(sender): State<SharedSender>,
+    headers: axum::http::HeaderMap,
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<(), ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_reference_customers_requests_total").increment(1);
+
+    crate::auth::authorize(&headers, &sender.auth_bearer_token, Role::Admin, "http_reference_unauthorized_total")?;
+
+    let Json(payload) = payload?;
+    if payload.len() > sender.max_request_records {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    let customers = convert_records(payload, incoming_to_customer)?;
+
+    for customer in customers {
+        enqueue(&sender.customers_tx, customer)?;
+    }
+
+    Ok(())
+}
+
+async fn sync_meter_exchanges(
+    State(sender): State<SharedSender>,
+    headers: axum::http::HeaderMap,
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<(), ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_reference_meter_exchanges_requests_total").increment(1);
+
+    crate::auth::authorize(&headers, &sender.auth_bearer_token, Role::Admin, "http_reference_unauthorized_total")?;
+
+    let Json(payload) = payload?;
+    if payload.len() > sender.max_request_records {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    let exchanges = convert_records(payload, incoming_to_exchange)?;
+
+    for exchange in exchanges {
+        enqueue(&sender.exchanges_tx, exchange)?;
+    }
+
+    Ok(())
+}
+
+async fn sync_dr_events(
+    State(sender): State<SharedSender>,
+    headers: axum::http::HeaderMap,
+    payload: Result<Json<Vec<serde_json::Value>>, JsonRejection>,
+) -> Result<(), ApiError> {
+    use axum::http::StatusCode;
+
+    metrics::counter!("http_reference_dr_events_requests_total").increment(1);
+
+    crate::auth::authorize(&headers, &sender.auth_bearer_token, Role::Admin, "http_reference_unauthorized_total")?;
+
+    let Json(payload) = payload?;
+    if payload.len() > sender.max_request_records {
+        return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+    }
+
+    let events = convert_records(payload, incoming_to_dr_event)?;
+
+    for event in events {
+        enqueue(&sender.dr_events_tx, event)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sender() -> (SharedSender, mpsc::Receiver<Envelope<Meter>>) {
+        let (meters_tx, meters_rx) = mpsc::channel(10);
+        let (customers_tx, _customers_rx) = mpsc::channel(10);
+        let (exchanges_tx, _exchanges_rx) = mpsc::channel(10);
+        let (dr_events_tx, _dr_events_rx) = mpsc::channel(10);
+        let sender = SharedSender {
+            meters_tx,
+            customers_tx,
+            exchanges_tx,
+            dr_events_tx,
+            auth_bearer_token: None,
+            max_request_records: 10,
+        };
+        (sender, meters_rx)
+    }
+
+    #[tokio::test]
+    async fn meter_versions_are_enqueued_with_soft_delete() {
+        let (sender, mut rx) = sender();
+        let payload = serde_json::from_str(
+            r#"[{"meter_id":"m-1","customer_id":"c-1","effective_from":"2024-01-01T00:00:00Z"},
+                {"meter_id":"m-1","effective_from":"2024-06-01T00:00:00Z","deleted":true}]"#,
+        )
+        .unwrap();
+
+        sync_meters(State(sender), axum::http::HeaderMap::new(), Ok(Json(payload)))
+            .await
+            .unwrap();
+
+        let first = rx.try_recv().unwrap().payload;
+        assert_eq!(first.customer_id.as_deref(), Some("c-1"));
+        assert!(!first.deleted);
+        assert!(rx.try_recv().unwrap().payload.deleted);
+    }
+
+    #[tokio::test]
+    async fn invalid_effective_from_rejects_whole_request() {
+        let (sender, mut rx) = sender();
+        let payload = serde_json::from_str(
+            r#"[{"meter_id":"m-1"},{"meter_id":"m-2","effective_from":"yesterday"}]"#,
+        )
+        .unwrap();
+
+        let err = sync_meters(State(sender), axum::http::HeaderMap::new(), Ok(Json(payload)))
+            .await
+            .unwrap_err();
+        assert_eq!(err.status, axum::http::StatusCode::BAD_REQUEST);
+        assert_eq!(err.details[0].index, Some(1));
+        assert_eq!(err.details[0].field.as_deref(), Some("effective_from"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn exchange_to_same_meter_is_rejected() {
+        let payload: IncomingMeterExchange = serde_json::from_str(
+            r#"{"ts":"2024-03-01T12:00:00Z","premise_id":"p-1","old_meter_id":"m-1","new_meter_id":"m-1"}"#,
+        )
+        .unwrap();
+        assert_eq!(incoming_to_exchange(payload).unwrap_err().field.as_deref(), Some("new_meter_id"));
+
+        let payload: IncomingMeterExchange = serde_json::from_str(
+            r#"{"ts":"2024-03-01T12:00:00Z","premise_id":"p-1","old_meter_id":"m-1","new_meter_id":"m-2"}"#,
+        )
+        .unwrap();
+        assert_eq!(incoming_to_exchange(payload).unwrap().reason, "exchange");
+    }
+
+    #[test]
+    fn dr_event_requires_window_and_meters() {
+        let parse = |json: &str| incoming_to_dr_event(serde_json::from_str(json).unwrap());
+
+        let event = parse(
+            r#"{"event_id":"e-1","program":"peak-saver","start":"2024-07-01T17:00:00Z","end":"2024-07-01T19:00:00Z","meter_ids":["m-1","m-2"]}"#,
+        )
+        .unwrap();
+        assert_eq!(event.meter_ids.len(), 2);
+
+        let reversed = parse(
+            r#"{"event_id":"e-1","program":"peak-saver","start":"2024-07-01T19:00:00Z","end":"2024-07-01T17:00:00Z","meter_ids":["m-1"]}"#,
+        );
+        assert_eq!(reversed.unwrap_err().field.as_deref(), Some("end"));
+
+        let no_meters = parse(
+            r#"{"event_id":"e-1","program":"peak-saver","start":"2024-07-01T17:00:00Z","end":"2024-07-01T19:00:00Z","meter_ids":[]}"#,
+        );
+        assert_eq!(no_meters.unwrap_err().field.as_deref(), Some("meter_ids"));
+    }
+}
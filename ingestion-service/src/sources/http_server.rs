@@ -0,0 +1,306 @@
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use async_compression::tokio::bufread::{GzipDecoder, ZlibDecoder};
+use axum::{
+    body::Body,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    Router,
+};
+use futures::TryStreamExt;
+use http_body_util::Limited;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo, TokioTimer},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use tokio::{
+    io::BufReader,
+    net::{TcpListener, TcpSocket},
+};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::{config::HttpSourceConfig, pipeline::PipelineError, tls::Acceptor};
+
+/// Bind `cfg.http_bind_addr` and serve `app` in the background.
+///
+/// Connections are served with the HTTP/1.1, HTTP/2 and TCP settings from `cfg`;
+/// `name` identifies the source in errors and logs. Request bodies are limited
+/// to `cfg.max_body_bytes`; with `cfg.accept_gzip`, `Content-Encoding: gzip` and
+/// `deflate` bodies are decoded before they reach `app`, up to
+/// `cfg.max_decompressed_bytes`. With `cfg.tls`, connections are HTTPS.
+pub(crate) fn serve(
+    cfg: &HttpSourceConfig,
+    app: Router,
+    name: &'static str,
+) -> Result<(), PipelineError> {
+    let addr: SocketAddr = cfg
+        .http_bind_addr
+        .parse()
+        .map_err(|e| PipelineError::Source(format!("invalid bind addr: {e}")))?;
+
+    let tls = cfg
+        .tls
+        .as_ref()
+        .map(|tls| Acceptor::from_config(tls, cfg.http2))
+        .transpose()
+        .map_err(|e| PipelineError::Source(format!("{name} tls: {e:#}")))?;
+
+    // Fail-fast: if we can't bind, return an error to the caller.
+    let listener = bind(addr, cfg.tcp_backlog)
+        .map_err(|e| PipelineError::Source(format!("failed to bind {name}: {e}")))?;
+
+    let limits = BodyLimits {
+        body: cfg.max_body_bytes,
+        decompressed: cfg.max_decompressed_bytes,
+        decode: cfg.accept_gzip,
+    };
+    // `limit_body` does the limiting; axum's default would cap decoded bodies at 2 MB.
+    let app = app
+        .layer(middleware::from_fn_with_state(limits, limit_body))
+        .layer(DefaultBodyLimit::disable());
+
+    let builder = Arc::new(connection_builder(cfg));
+    tokio::spawn(accept_loop(listener, app, builder, tls, name));
+
+    Ok(())
+}
+
+/// Request body limits of [`limit_body`].
+#[derive(Debug, Clone, Copy)]
+struct BodyLimits {
+    /// Bytes received, compressed or not.
+    body: usize,
+    /// Decoded bytes of a compressed body.
+    decompressed: usize,
+    /// Decode gzip and deflate bodies; otherwise they are passed on as they are.
+    decode: bool,
+}
+
+enum Encoding {
+    Gzip,
+    Deflate,
+}
+
+/// Limit request bodies and decode gzip and deflate (zlib) bodies as they stream in.
+///
+/// A body going over a limit fails while it is read, which the handlers answer
+/// with 413; a `Content-Length` over `body` is refused right away. Other
+/// encodings are rejected with 415.
+async fn limit_body(State(limits): State<BodyLimits>, req: Request, next: Next) -> Response {
+    let too_large = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok()?.parse::<usize>().ok())
+        .is_some_and(|len| len > limits.body);
+    if too_large {
+        return (StatusCode::PAYLOAD_TOO_LARGE, "request body too large").into_response();
+    }
+
+    let encoding = req
+        .headers()
+        .get(header::CONTENT_ENCODING)
+        .map(|v| v.to_str().unwrap_or_default().trim().to_ascii_lowercase());
+    let (mut parts, body) = req.into_parts();
+    let body = Body::new(Limited::new(body, limits.body));
+    let encoding = match encoding.as_deref() {
+        None | Some("identity") => None,
+        Some(_) if !limits.decode => None,
+        Some("gzip" | "x-gzip") => Some(Encoding::Gzip),
+        Some("deflate") => Some(Encoding::Deflate),
+        Some(_) => return (StatusCode::UNSUPPORTED_MEDIA_TYPE, "unsupported content-encoding").into_response(),
+    };
+    let Some(encoding) = encoding else {
+        return next.run(Request::from_parts(parts, body)).await;
+    };
+
+    parts.headers.remove(header::CONTENT_ENCODING);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let compressed = BufReader::new(StreamReader::new(body.into_data_stream().map_err(std::io::Error::other)));
+    let decoded = match encoding {
+        Encoding::Gzip => {
+            metrics::counter!("http_ingest_gzip_requests_total").increment(1);
+            Body::from_stream(ReaderStream::new(GzipDecoder::new(compressed)))
+        }
+        Encoding::Deflate => {
+            metrics::counter!("http_ingest_deflate_requests_total").increment(1);
+            Body::from_stream(ReaderStream::new(ZlibDecoder::new(compressed)))
+        }
+    };
+    let decoded = Body::new(Limited::new(decoded, limits.decompressed));
+    next.run(Request::from_parts(parts, decoded)).await
+}
+
+fn bind(addr: SocketAddr, backlog: u32) -> std::io::Result<TcpListener> {
+    let socket = match addr {
+        SocketAddr::V4(_) => TcpSocket::new_v4()?,
+        SocketAddr::V6(_) => TcpSocket::new_v6()?,
+    };
+    #[cfg(unix)]
+    socket.set_reuseaddr(true)?;
+    socket.bind(addr)?;
+    socket.listen(backlog)
+}
+
+fn connection_builder(cfg: &HttpSourceConfig) -> auto::Builder<TokioExecutor> {
+    let mut builder = auto::Builder::new(TokioExecutor::new());
+
+    builder
+        .http1()
+        .timer(TokioTimer::new())
+        .keep_alive(cfg.http1_keep_alive)
+        .header_read_timeout(Duration::from_millis(cfg.http1_header_read_timeout_ms));
+
+    builder
+        .http2()
+        .timer(TokioTimer::new())
+        .keep_alive_interval(cfg.http2_keep_alive_interval_secs.map(Duration::from_secs))
+        .keep_alive_timeout(Duration::from_secs(cfg.http2_keep_alive_timeout_secs))
+        .max_concurrent_streams(cfg.http2_max_concurrent_streams);
+
+    if cfg.http2 {
+        builder
+    } else {
+        builder.http1_only()
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    app: Router,
+    builder: Arc<auto::Builder<TokioExecutor>>,
+    tls: Option<Acceptor>,
+    name: &'static str,
+) {
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _peer)) => stream,
+            Err(e) => {
+                // Typically out of file descriptors; back off instead of spinning.
+                tracing::error!(error = %e, source = name, "accept error");
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+
+        let builder = builder.clone();
+        if let Some(tls) = &tls {
+            let (tls, app) = (tls.clone(), app.clone());
+            tokio::spawn(async move { tls.serve_connection(stream, &builder, app, name).await });
+            continue;
+        }
+        let service = TowerToHyperService::new(app.clone());
+        tokio::spawn(async move {
+            if let Err(e) = builder.serve_connection_with_upgrades(TokioIo::new(stream), service).await {
+                tracing::debug!(error = %e, source = name, "connection closed with error");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::routing::{get, post};
+    use std::io::Write;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn test_config() -> HttpSourceConfig {
+        toml::from_str("http_bind_addr = \"127.0.0.1:0\"\nchannel_capacity = 1").unwrap()
+    }
+
+    #[tokio::test]
+    async fn keeps_http1_connections_alive() {
+        let cfg = test_config();
+        let listener = bind("127.0.0.1:0".parse().unwrap(), cfg.tcp_backlog).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new().route("/", get(|| async { "ok" }));
+        tokio::spawn(accept_loop(listener, app, Arc::new(connection_builder(&cfg)), None, "test"));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        for _ in 0..2 {
+            stream
+                .write_all(b"GET / HTTP/1.1\r\nHost: test\r\n\r\n")
+                .await
+                .unwrap();
+            let mut buf = [0u8; 1024];
+            let n = stream.read(&mut buf).await.unwrap();
+            let response = String::from_utf8_lossy(&buf[..n]);
+            assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+            assert!(response.ends_with("ok"), "{response}");
+        }
+    }
+
+    /// POST `body` with `headers` to an echo app behind [`limit_body`]; returns the raw response.
+    async fn send(limits: BodyLimits, headers: &str, body: &[u8]) -> String {
+        let cfg = test_config();
+        let listener = bind("127.0.0.1:0".parse().unwrap(), cfg.tcp_backlog).unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = Router::new()
+            .route("/", post(|body: String| async move { body }))
+            .layer(middleware::from_fn_with_state(limits, limit_body))
+            .layer(DefaultBodyLimit::disable());
+        tokio::spawn(accept_loop(listener, app, Arc::new(connection_builder(&cfg)), None, "test"));
+
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let head = format!(
+            "POST / HTTP/1.1\r\nHost: test\r\n{headers}Content-Length: {}\r\n\r\n",
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).await.unwrap();
+        stream.write_all(body).await.unwrap();
+
+        let mut buf = [0u8; 1024];
+        let n = stream.read(&mut buf).await.unwrap();
+        String::from_utf8_lossy(&buf[..n]).into_owned()
+    }
+
+    const LIMITS: BodyLimits = BodyLimits {
+        body: 1024,
+        decompressed: 4096,
+        decode: true,
+    };
+
+    #[tokio::test]
+    async fn decodes_gzip_and_deflate_request_bodies() {
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(b"{\"kwh\":1.5}\n").unwrap();
+        let response = send(LIMITS, "Content-Encoding: gzip\r\n", &gz.finish().unwrap()).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+        assert!(response.ends_with("{\"kwh\":1.5}\n"), "{response}");
+
+        let mut zlib = flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        zlib.write_all(b"{\"kwh\":2.5}\n").unwrap();
+        let response = send(LIMITS, "Content-Encoding: deflate\r\n", &zlib.finish().unwrap()).await;
+        assert!(response.ends_with("{\"kwh\":2.5}\n"), "{response}");
+
+        let response = send(LIMITS, "Content-Encoding: br\r\n", b"x").await;
+        assert!(response.starts_with("HTTP/1.1 415"), "{response}");
+    }
+
+    #[tokio::test]
+    async fn limits_received_and_decoded_sizes_separately() {
+        // 8 KiB of NDJSON compresses well below `body` but decodes above `decompressed`.
+        let ndjson = b"{\"meter_id\":\"m-1\",\"kwh\":1.0}\n".repeat(256);
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        gz.write_all(&ndjson).unwrap();
+        let compressed = gz.finish().unwrap();
+        assert!(compressed.len() < LIMITS.body);
+
+        let response = send(LIMITS, "Content-Encoding: gzip\r\n", &compressed).await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+        let roomy = BodyLimits {
+            decompressed: 16 * 1024,
+            ..LIMITS
+        };
+        let response = send(roomy, "Content-Encoding: gzip\r\n", &compressed).await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{response}");
+
+        // Uncompressed bodies only have `body`.
+        let response = send(roomy, "", &ndjson).await;
+        assert!(response.starts_with("HTTP/1.1 413"), "{response}");
+    }
+}
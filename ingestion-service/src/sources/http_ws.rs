@@ -0,0 +1,323 @@
+use std::{
+    borrow::Cow,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::extract::ws::{close_code, CloseFrame, Message, WebSocket};
+use futures::{SinkExt, StreamExt};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+
+use crate::{
+    pipeline::{AckOutcome, AckReceiver, Completion, Envelope},
+    sources::{
+        http_error::{FieldError, MAX_ERROR_DETAILS},
+        http_json::ErrorRatioCheck,
+    },
+};
+
+/// Parses one line of a connection into a record.
+pub(crate) type ParseLine<T> = Box<dyn Fn(&str) -> Result<T, FieldError> + Send>;
+
+/// Settings of one WebSocket ingest connection.
+pub(crate) struct WsIngest<T> {
+    /// Lane the connection's records go to.
+    pub(crate) tx: mpsc::Sender<Envelope<T>>,
+    pub(crate) max_line_bytes: usize,
+    pub(crate) ndjson_strict: bool,
+    pub(crate) ndjson_error_ratio: Option<(f64, usize)>,
+    pub(crate) ack_interval: Duration,
+    pub(crate) parse: ParseLine<T>,
+    /// Tenant of the key the connection was opened with, stamped on its records.
+    pub(crate) tenant: Option<Arc<str>>,
+    /// Client certificate the connection was opened with, stamped on its records.
+    pub(crate) source_identity: Option<Arc<str>>,
+}
+
+/// Settled records of a connection, counted as the sink reports them.
+#[derive(Debug, Default)]
+struct Settled {
+    written: AtomicU64,
+    rejected: AtomicU64,
+    dropped: AtomicU64,
+}
+
+/// Ack summary sent to the client; counts are totals since the connection opened.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize)]
+pub(crate) struct WsAckSummary {
+    pub(crate) lines: usize,
+    pub(crate) accepted: usize,
+    pub(crate) parse_errors: usize,
+    /// Written by the sink.
+    pub(crate) written: u64,
+    /// Rejected by a transform (e.g. validation).
+    pub(crate) rejected: u64,
+    /// Lost before they were written; resend them.
+    pub(crate) dropped: u64,
+    /// Lines skipped since the previous summary.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) errors: Vec<FieldError>,
+    /// Why the server closes the connection, on the last summary.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) closing: Option<String>,
+}
+
+/// Count each record's outcome, in arrival order.
+async fn settle(mut acks: mpsc::UnboundedReceiver<AckReceiver>, settled: Arc<Settled>) {
+    while let Some(ack) = acks.recv().await {
+        let counter = match ack.outcome().await {
+            AckOutcome::Written => &settled.written,
+            AckOutcome::Rejected => &settled.rejected,
+            AckOutcome::Dropped => &settled.dropped,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// Parse state of one connection.
+struct Connection<T> {
+    ingest: WsIngest<T>,
+    acks: mpsc::UnboundedSender<AckReceiver>,
+    settled: Arc<Settled>,
+    summary: WsAckSummary,
+    error_ratio: Option<ErrorRatioCheck>,
+}
+
+impl<T> Connection<T> {
+    /// Ingest the NDJSON lines of one frame. Returns why the connection must
+    /// close, if it must.
+    async fn frame(&mut self, text: &str) -> Result<(), String> {
+        metrics::counter!("http_ingest_ws_frames_total").increment(1);
+        for line in text.lines() {
+            self.summary.lines += 1;
+            let line_no = self.summary.lines;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let parsed = if line.len() > self.ingest.max_line_bytes {
+                Err(FieldError::record(format!(
+                    "line exceeds {} bytes",
+                    self.ingest.max_line_bytes
+                )))
+            } else {
+                (self.ingest.parse)(line)
+            };
+            let record = match parsed {
+                Ok(record) => record,
+                Err(e) => {
+                    self.summary.parse_errors += 1;
+                    metrics::counter!("http_ingest_ws_parse_errors_total").increment(1);
+                    let e = e.at_line(line_no);
+                    let reason = e.to_string();
+                    if self.summary.errors.len() < MAX_ERROR_DETAILS {
+                        self.summary.errors.push(e);
+                    }
+                    if self.ingest.ndjson_strict {
+                        return Err(format!("invalid record on line {line_no}: {reason}"));
+                    }
+                    if self.error_ratio.as_mut().is_some_and(|r| r.record(true)) {
+                        metrics::counter!("http_ingest_ws_rejected_error_ratio_total").increment(1);
+                        return Err("parse error ratio exceeded".to_string());
+                    }
+                    continue;
+                }
+            };
+
+            let (completion, ack) = Completion::oneshot();
+            // Waiting for room in the channel stops reading the socket, so TCP holds the gateway back.
+            if self
+                .ingest
+                .tx
+                .send(
+                    Envelope::tracked(record, completion)
+                        .with_tenant(self.ingest.tenant.clone())
+                        .with_source_identity(self.ingest.source_identity.clone()),
+                )
+                .await
+                .is_err()
+            {
+                metrics::counter!("http_ingest_failed_total").increment(1);
+                return Err("pipeline is shutting down".to_string());
+            }
+            let _ = self.acks.send(ack);
+            self.summary.accepted += 1;
+            if let Some(r) = self.error_ratio.as_mut() {
+                r.record(false);
+            }
+        }
+        Ok(())
+    }
+
+    /// The summary to send now, if anything changed since `last`.
+    fn take_summary(&mut self, last: &WsAckSummary, closing: Option<String>) -> Option<WsAckSummary> {
+        let mut summary = self.summary.clone();
+        summary.written = self.settled.written.load(Ordering::Relaxed);
+        summary.rejected = self.settled.rejected.load(Ordering::Relaxed);
+        summary.dropped = self.settled.dropped.load(Ordering::Relaxed);
+        summary.closing = closing;
+        // `last` may list errors that were already reported; only the counts matter.
+        let unchanged = WsAckSummary {
+            errors: Vec::new(),
+            ..last.clone()
+        };
+        if summary == unchanged && summary.closing.is_none() {
+            return None;
+        }
+        self.summary.errors.clear();
+        Some(summary)
+    }
+}
+
+fn summary_message(summary: &WsAckSummary) -> Message {
+    Message::Text(serde_json::to_string(summary).expect("ack summary serializes"))
+}
+
+/// Serve one WebSocket ingest connection.
+///
+/// Text (or UTF-8 binary) frames hold NDJSON lines in the HTTP payload
+/// format; lines are numbered across the connection. Every `ack_interval`
+/// the client gets a [`WsAckSummary`] if anything changed. In strict mode, or
+/// once the lenient error ratio is exceeded, the server sends a last summary
+/// with `closing` set and closes with code 1007.
+pub(crate) async fn serve<T: Send + 'static>(socket: WebSocket, ingest: WsIngest<T>) {
+    metrics::counter!("http_ingest_ws_connections_total").increment(1);
+    metrics::gauge!("http_ingest_ws_open_connections").increment(1.0);
+
+    let (mut outgoing, mut incoming) = socket.split();
+    let settled = Arc::new(Settled::default());
+    let (acks, acks_rx) = mpsc::unbounded_channel();
+    tokio::spawn(settle(acks_rx, settled.clone()));
+
+    let mut ticker = tokio::time::interval(ingest.ack_interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let error_ratio = ingest
+        .ndjson_error_ratio
+        .map(|(ratio, window)| ErrorRatioCheck::new(ratio, window));
+    let mut conn = Connection {
+        ingest,
+        acks,
+        settled,
+        summary: WsAckSummary::default(),
+        error_ratio,
+    };
+    let mut last = WsAckSummary::default();
+
+    let closing = loop {
+        tokio::select! {
+            msg = incoming.next() => {
+                let result = match msg {
+                    Some(Ok(Message::Text(text))) => conn.frame(&text).await,
+                    Some(Ok(Message::Binary(data))) => match std::str::from_utf8(&data) {
+                        Ok(text) => conn.frame(text).await,
+                        Err(e) => Err(format!("binary frame is not UTF-8: {e}")),
+                    },
+                    Some(Ok(Message::Close(_))) | None => break None,
+                    Some(Ok(_)) => Ok(()),
+                    Some(Err(e)) => {
+                        tracing::debug!(error = %e, "WebSocket ingest connection failed");
+                        break None;
+                    }
+                };
+                if let Err(reason) = result {
+                    break Some(reason);
+                }
+            }
+            _ = ticker.tick() => {
+                if let Some(summary) = conn.take_summary(&last, None) {
+                    if outgoing.send(summary_message(&summary)).await.is_err() {
+                        break None;
+                    }
+                    last = summary;
+                }
+            }
+        }
+    };
+
+    // A client that closed gets what can still be sent; a closing server explains why.
+    if let Some(summary) = conn.take_summary(&last, closing.clone()) {
+        let _ = outgoing.send(summary_message(&summary)).await;
+    }
+    if let Some(reason) = closing {
+        let frame = CloseFrame {
+            code: close_code::INVALID,
+            reason: Cow::Owned(reason.chars().take(120).collect()),
+        };
+        let _ = outgoing.send(Message::Close(Some(frame))).await;
+    }
+    metrics::gauge!("http_ingest_ws_open_connections").decrement(1.0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Result<String, FieldError> {
+        line.strip_prefix("ok:")
+            .map(str::to_string)
+            .ok_or_else(|| FieldError::record("bad line"))
+    }
+
+    fn connection(strict: bool) -> (Connection<String>, mpsc::Receiver<Envelope<String>>) {
+        let (tx, rx) = mpsc::channel(10);
+        let settled = Arc::new(Settled::default());
+        let (acks, acks_rx) = mpsc::unbounded_channel();
+        tokio::spawn(settle(acks_rx, settled.clone()));
+        let ingest = WsIngest {
+            tx,
+            max_line_bytes: 16,
+            ndjson_strict: strict,
+            ndjson_error_ratio: None,
+            ack_interval: Duration::from_secs(1),
+            parse: Box::new(parse),
+            tenant: None,
+            source_identity: None,
+        };
+        let conn = Connection {
+            ingest,
+            acks,
+            settled,
+            summary: WsAckSummary::default(),
+            error_ratio: None,
+        };
+        (conn, rx)
+    }
+
+    #[tokio::test]
+    async fn summaries_count_lines_across_frames_and_settled_records() {
+        let (mut conn, mut rx) = connection(false);
+        conn.frame("ok:a\nnope\n").await.unwrap();
+        conn.frame("ok:b\n\nok:this-line-is-too-long").await.unwrap();
+
+        let first = rx.recv().await.unwrap();
+        let second = rx.recv().await.unwrap();
+        assert_eq!((first.payload.as_str(), second.payload.as_str()), ("a", "b"));
+        first.complete();
+        second.completion.as_ref().unwrap().reject();
+        tokio::task::yield_now().await;
+
+        let summary = conn.take_summary(&WsAckSummary::default(), None).unwrap();
+        assert_eq!((summary.lines, summary.accepted, summary.parse_errors), (5, 2, 2));
+        assert_eq!((summary.written, summary.rejected, summary.dropped), (1, 1, 0));
+        let lines: Vec<Option<usize>> = summary.errors.iter().map(|e| e.line).collect();
+        assert_eq!(lines, [Some(2), Some(5)]);
+
+        // Nothing new: no summary; errors are only reported once.
+        assert_eq!(conn.take_summary(&summary, None), None);
+        conn.frame("nope").await.unwrap();
+        let next = conn.take_summary(&summary, None).unwrap();
+        assert_eq!((next.parse_errors, next.errors.len()), (3, 1));
+    }
+
+    #[tokio::test]
+    async fn strict_mode_closes_on_the_first_bad_line() {
+        let (mut conn, _rx) = connection(true);
+        let err = conn.frame("ok:a\nnope\nok:b").await.unwrap_err();
+        assert_eq!(err, "invalid record on line 2: bad line");
+        assert_eq!(conn.summary.accepted, 1);
+    }
+}
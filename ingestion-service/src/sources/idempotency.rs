@@ -0,0 +1,440 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderName, HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    config::HttpSourceConfig,
+    sources::http_error::{body_read_error, ApiError},
+    tls::ClientIdentity,
+};
+
+/// Request header naming a batch, so that a retry of it is answered instead of ingested again.
+pub(crate) const IDEMPOTENCY_KEY: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Response header set on answers replayed from the cache.
+pub(crate) const IDEMPOTENT_REPLAYED: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest accepted `Idempotency-Key`.
+const MAX_KEY_LEN: usize = 255;
+
+enum Slot {
+    /// The first request with the key is still being handled.
+    InFlight,
+    /// Its successful response.
+    Done { status: StatusCode, body: Bytes },
+}
+
+struct Entry {
+    seen: Instant,
+    /// Hash of the body of the request that claimed the key.
+    body: blake3::Hash,
+    slot: Slot,
+}
+
+#[derive(Default)]
+struct Keys {
+    entries: HashMap<String, Entry>,
+    /// Keys by first sighting; may still list keys that were released.
+    order: VecDeque<(Instant, String)>,
+}
+
+impl Keys {
+    fn remove_oldest(&mut self) {
+        while let Some((seen, key)) = self.order.pop_front() {
+            if self.entries.get(&key).is_some_and(|e| e.seen == seen) {
+                self.entries.remove(&key);
+                return;
+            }
+        }
+    }
+}
+
+/// Recently seen `Idempotency-Key`s of an ingest source and the responses
+/// they got.
+///
+/// A key is remembered for `ttl` from its first request, at most `max_keys`
+/// at a time (the oldest are forgotten first). Only successful (2xx)
+/// responses are kept; after an error the key is released, so the retry is
+/// ingested. A key only matches requests with the same body.
+#[derive(Clone)]
+pub(crate) struct IdempotencyCache {
+    keys: Arc<Mutex<Keys>>,
+    ttl: Duration,
+    max_keys: usize,
+}
+
+/// What to do with a request carrying an `Idempotency-Key`.
+enum Claim {
+    /// First request with the key: handle it, then [`IdempotencyCache::complete`] it.
+    New,
+    Replay {
+        status: StatusCode,
+        body: Bytes,
+    },
+    /// The first request is still being handled.
+    InFlight,
+    /// The key was claimed by a request with another body.
+    Mismatch,
+}
+
+impl IdempotencyCache {
+    pub(crate) fn new(ttl: Duration, max_keys: usize) -> Self {
+        Self {
+            keys: Arc::new(Mutex::new(Keys::default())),
+            ttl,
+            max_keys,
+        }
+    }
+
+    /// The cache configured by `idempotency_ttl_secs` / `idempotency_max_keys`;
+    /// `None` when either is 0.
+    pub(crate) fn from_config(cfg: &HttpSourceConfig) -> Option<Self> {
+        (cfg.idempotency_ttl_secs > 0 && cfg.idempotency_max_keys > 0)
+            .then(|| Self::new(Duration::from_secs(cfg.idempotency_ttl_secs), cfg.idempotency_max_keys))
+    }
+
+    fn claim(&self, key: &str, body: blake3::Hash, now: Instant) -> Claim {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        while keys
+            .order
+            .front()
+            .is_some_and(|(seen, _)| now.duration_since(*seen) >= self.ttl)
+        {
+            keys.remove_oldest();
+        }
+        if let Some(entry) = keys.entries.get(key) {
+            if entry.body != body {
+                return Claim::Mismatch;
+            }
+            return match &entry.slot {
+                Slot::InFlight => Claim::InFlight,
+                Slot::Done { status, body } => Claim::Replay {
+                    status: *status,
+                    body: body.clone(),
+                },
+            };
+        }
+
+        while keys.entries.len() >= self.max_keys {
+            keys.remove_oldest();
+        }
+        if keys.order.len() > 2 * self.max_keys {
+            let Keys { entries, order } = &mut *keys;
+            order.retain(|(seen, k)| entries.get(k).is_some_and(|e| e.seen == *seen));
+        }
+        keys.entries.insert(
+            key.to_string(),
+            Entry {
+                seen: now,
+                body,
+                slot: Slot::InFlight,
+            },
+        );
+        keys.order.push_back((now, key.to_string()));
+        Claim::New
+    }
+
+    /// Keep the response to a claimed key, or release the key if there is none.
+    fn complete(&self, key: &str, response: Option<(StatusCode, Bytes)>) {
+        let mut keys = self.keys.lock().unwrap_or_else(|e| e.into_inner());
+        match response {
+            Some((status, body)) => {
+                if let Some(entry) = keys.entries.get_mut(key) {
+                    entry.slot = Slot::Done { status, body };
+                }
+            }
+            None => {
+                keys.entries.remove(key);
+            }
+        }
+    }
+}
+
+/// Releases a claimed key whose request did not complete, e.g. because the
+/// client disconnected and the handler was dropped.
+struct ClaimGuard<'a> {
+    cache: &'a IdempotencyCache,
+    key: &'a str,
+    completed: bool,
+}
+
+impl ClaimGuard<'_> {
+    fn complete(mut self, response: Option<(StatusCode, Bytes)>) {
+        self.cache.complete(self.key, response);
+        self.completed = true;
+    }
+}
+
+impl Drop for ClaimGuard<'_> {
+    fn drop(&mut self) {
+        if !self.completed {
+            self.cache.complete(self.key, None);
+        }
+    }
+}
+
+/// Cache key: the key is scoped to the route and the caller's credentials
+/// (token and client certificate), so clients can't replay each other's batches.
+fn scoped_key(req: &Request, key: &str) -> String {
+    let credentials = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let identity = req
+        .extensions()
+        .get::<ClientIdentity>()
+        .map(|ClientIdentity(cn)| &**cn)
+        .unwrap_or_default();
+    format!("{}\n{credentials}\n{identity}\n{key}", req.uri().path())
+}
+
+/// The request's `Idempotency-Key`, if any (400 if it is empty, too long or not visible ASCII).
+fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, ApiError> {
+    let Some(value) = headers.get(IDEMPOTENCY_KEY) else {
+        return Ok(None);
+    };
+    match value.to_str().map(str::trim) {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => Ok(Some(key)),
+        _ => Err(ApiError::new(
+            StatusCode::BAD_REQUEST,
+            format!("Idempotency-Key must be 1 to {MAX_KEY_LEN} visible ASCII characters"),
+        )),
+    }
+}
+
+/// Answer a repeated `Idempotency-Key` with the response the first request
+/// got, marked `Idempotent-Replayed: true`, instead of ingesting the batch again.
+///
+/// A repeat that arrives while the first request is still being handled
+/// (typically a client retry after a slow synchronous ack) gets 409; retrying
+/// it later returns the original response. Reusing a key for another body
+/// gets 422.
+///
+/// Bodies of requests with a key are read in full before they are handled,
+/// to compare them.
+pub(crate) async fn replay_or_run(State(cache): State<IdempotencyCache>, req: Request, next: Next) -> Response {
+    let key = match idempotency_key(req.headers()) {
+        Ok(Some(key)) => scoped_key(&req, key),
+        Ok(None) => return next.run(req).await,
+        Err(e) => return e.into_response(),
+    };
+
+    let (parts, body) = req.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return body_read_error(std::io::Error::other(e)).into_response(),
+    };
+    let hash = blake3::hash(&body);
+    let req = Request::from_parts(parts, Body::from(body));
+
+    match cache.claim(&key, hash, Instant::now()) {
+        Claim::New => {}
+        Claim::Replay { status, body } => {
+            metrics::counter!("http_ingest_idempotent_replays_total").increment(1);
+            return (
+                status,
+                [
+                    (header::CONTENT_TYPE, HeaderValue::from_static("application/json")),
+                    (IDEMPOTENT_REPLAYED, HeaderValue::from_static("true")),
+                ],
+                body,
+            )
+                .into_response();
+        }
+        Claim::InFlight => {
+            metrics::counter!("http_ingest_idempotency_conflicts_total").increment(1);
+            return ApiError::new(
+                StatusCode::CONFLICT,
+                "a request with this Idempotency-Key is still being processed",
+            )
+            .into_response();
+        }
+        Claim::Mismatch => {
+            metrics::counter!("http_ingest_idempotency_mismatches_total").increment(1);
+            return ApiError::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "this Idempotency-Key was used for a different request body",
+            )
+            .into_response();
+        }
+    }
+
+    let guard = ClaimGuard {
+        cache: &cache,
+        key: &key,
+        completed: false,
+    };
+    let response = next.run(req).await;
+    if !response.status().is_success() {
+        guard.complete(None);
+        return response;
+    }
+
+    // Ingest responses are small JSON summaries.
+    let (parts, body) = response.into_parts();
+    match to_bytes(body, usize::MAX).await {
+        Ok(body) => {
+            guard.complete(Some((parts.status, body.clone())));
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => {
+            guard.complete(None);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("failed to read response: {e}"),
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::post, Router};
+    use hyper::service::Service;
+    use hyper_util::service::TowerToHyperService;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn app(cache: IdempotencyCache, calls: Arc<AtomicUsize>) -> Router {
+        Router::new()
+            .route(
+                "/ingest",
+                post(move |body: String| async move {
+                    let n = calls.fetch_add(1, Ordering::SeqCst) + 1;
+                    if body == "fail" {
+                        return (StatusCode::TOO_MANY_REQUESTS, String::new());
+                    }
+                    (StatusCode::OK, format!("{{\"accepted\":{n}}}"))
+                }),
+            )
+            .route_layer(middleware::from_fn_with_state(cache, replay_or_run))
+    }
+
+    async fn send(app: &Router, key: Option<&str>, token: &str, body: &'static str) -> (StatusCode, bool, String) {
+        let mut req = Request::post("/ingest");
+        if !token.is_empty() {
+            req = req.header(header::AUTHORIZATION, format!("Bearer {token}"));
+        }
+        if let Some(key) = key {
+            req = req.header(IDEMPOTENCY_KEY, key);
+        }
+        send_request(app, req.body(Body::from(body)).unwrap()).await
+    }
+
+    async fn send_request(app: &Router, req: Request) -> (StatusCode, bool, String) {
+        let res = TowerToHyperService::new(app.clone()).call(req).await.unwrap();
+        let replayed = res.headers().contains_key(IDEMPOTENT_REPLAYED);
+        let status = res.status();
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        (status, replayed, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn replays_the_first_response_per_key_and_caller() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(IdempotencyCache::new(Duration::from_secs(60), 100), calls.clone());
+
+        let first = send(&app, Some("batch-1"), "a", "ok").await;
+        assert_eq!(first, (StatusCode::OK, false, "{\"accepted\":1}".to_string()));
+        let replay = send(&app, Some("batch-1"), "a", "ok").await;
+        assert_eq!(replay, (StatusCode::OK, true, "{\"accepted\":1}".to_string()));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Another caller's key, and requests without a key, are handled.
+        assert!(!send(&app, Some("batch-1"), "b", "ok").await.1);
+        assert!(!send(&app, None, "a", "ok").await.1);
+        assert!(!send(&app, None, "a", "ok").await.1);
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+
+        assert_eq!(send(&app, Some(""), "a", "ok").await.0, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn scopes_keys_to_client_certificates() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(IdempotencyCache::new(Duration::from_secs(60), 100), calls.clone());
+        let from = |cn: &str| {
+            let mut req = Request::post("/ingest")
+                .header(IDEMPOTENCY_KEY, "batch-1")
+                .body(Body::from("ok"))
+                .unwrap();
+            req.extensions_mut().insert(ClientIdentity(cn.into()));
+            req
+        };
+
+        assert!(!send_request(&app, from("substation-17")).await.1);
+        assert!(send_request(&app, from("substation-17")).await.1);
+        assert!(!send_request(&app, from("substation-18")).await.1);
+        assert!(!send(&app, Some("batch-1"), "", "ok").await.1);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_reused_for_another_body() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(IdempotencyCache::new(Duration::from_secs(60), 100), calls.clone());
+
+        assert_eq!(send(&app, Some("k"), "a", "ok").await.0, StatusCode::OK);
+        assert_eq!(
+            send(&app, Some("k"), "a", "other").await.0,
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert!(send(&app, Some("k"), "a", "ok").await.1);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failed_requests_release_their_key() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = app(IdempotencyCache::new(Duration::from_secs(60), 100), calls.clone());
+
+        assert_eq!(
+            send(&app, Some("k"), "a", "fail").await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        let retry = send(&app, Some("k"), "a", "ok").await;
+        assert_eq!(retry, (StatusCode::OK, false, "{\"accepted\":2}".to_string()));
+    }
+
+    #[test]
+    fn keys_expire_and_are_bounded() {
+        let cache = IdempotencyCache::new(Duration::from_secs(60), 2);
+        let t0 = Instant::now();
+        let done = |key: &str| cache.complete(key, Some((StatusCode::OK, Bytes::from_static(b"{}"))));
+        let body = blake3::hash(b"ok");
+
+        assert!(matches!(cache.claim("a", body, t0), Claim::New));
+        assert!(matches!(cache.claim("a", body, t0), Claim::InFlight));
+        done("a");
+        assert!(matches!(
+            cache.claim("a", body, t0 + Duration::from_secs(59)),
+            Claim::Replay { .. }
+        ));
+        assert!(matches!(
+            cache.claim("a", body, t0 + Duration::from_secs(60)),
+            Claim::New
+        ));
+
+        let t1 = t0 + Duration::from_secs(61);
+        for key in ["b", "c"] {
+            assert!(matches!(cache.claim(key, body, t1), Claim::New));
+            done(key);
+        }
+        // "a" (claimed again at t0 + 60s) was the oldest of three.
+        assert!(matches!(cache.claim("c", body, t1), Claim::Replay { .. }));
+        assert!(matches!(cache.claim("a", body, t1), Claim::New));
+        assert!(matches!(cache.claim("b", body, t1), Claim::New));
+    }
+}
@@ -0,0 +1,552 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use bytes::BytesMut;
+use futures::Stream;
+use rust_client::domain::GenerationOutput;
+use time::{Date, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+use crate::{
+    config::{Iec104Point, Iec104SourceConfig},
+    pipeline::{Envelope, PipelineError, Source},
+    sources::telemetry::UnitTelemetry,
+};
+
+const START: u8 = 0x68;
+const STARTDT_ACT: u8 = 0x07;
+const STARTDT_CON: u8 = 0x0b;
+const TESTFR_ACT: u8 = 0x43;
+const TESTFR_CON: u8 = 0x83;
+
+/// Interrogation command (C_IC_NA_1), activation, station interrogation.
+const C_IC_NA_1: u8 = 100;
+const COT_ACTIVATION: u8 = 6;
+const QOI_STATION: u8 = 20;
+
+/// Measured values: normalized, scaled and short float, without and with a CP56Time2a tag.
+const M_ME_NA_1: u8 = 9;
+const M_ME_NB_1: u8 = 11;
+const M_ME_NC_1: u8 = 13;
+const M_ME_TD_1: u8 = 34;
+const M_ME_TE_1: u8 = 35;
+const M_ME_TF_1: u8 = 36;
+
+/// Quality descriptor: the value is invalid.
+const QDS_INVALID: u8 = 0x80;
+
+/// How long the outstation has to confirm STARTDT (t1).
+const CONFIRM_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// An APCI frame; only what the client acts on is kept.
+#[derive(Debug, PartialEq)]
+enum Apdu {
+    I { send_seq: u16, asdu: Vec<u8> },
+    S,
+    U(u8),
+}
+
+/// Take one frame off the front of `buf`, if it is complete.
+fn decode_apdu(buf: &mut BytesMut) -> Result<Option<Apdu>, String> {
+    if buf.len() < 2 {
+        return Ok(None);
+    }
+    if buf[0] != START {
+        return Err(format!("invalid start byte {:#04x}", buf[0]));
+    }
+    let len = buf[1] as usize;
+    if len < 4 {
+        return Err(format!("invalid APDU length {len}"));
+    }
+    if buf.len() < 2 + len {
+        return Ok(None);
+    }
+    let frame = buf.split_to(2 + len);
+    let control = &frame[2..6];
+    Ok(Some(if control[0] & 0x01 == 0 {
+        Apdu::I {
+            send_seq: u16::from_le_bytes([control[0], control[1]]) >> 1,
+            asdu: frame[6..].to_vec(),
+        }
+    } else if control[0] & 0x03 == 0x01 {
+        Apdu::S
+    } else {
+        Apdu::U(control[0])
+    }))
+}
+
+fn u_frame(function: u8) -> [u8; 6] {
+    [START, 4, function, 0, 0, 0]
+}
+
+fn s_frame(recv_seq: u16) -> [u8; 6] {
+    let [lo, hi] = (recv_seq << 1).to_le_bytes();
+    [START, 4, 0x01, 0, lo, hi]
+}
+
+fn i_frame(send_seq: u16, recv_seq: u16, asdu: &[u8]) -> Vec<u8> {
+    let mut frame = vec![START, (4 + asdu.len()) as u8];
+    frame.extend((send_seq << 1).to_le_bytes());
+    frame.extend((recv_seq << 1).to_le_bytes());
+    frame.extend(asdu);
+    frame
+}
+
+/// A station interrogation of `common_address`.
+fn interrogation(common_address: u16) -> Vec<u8> {
+    let [lo, hi] = common_address.to_le_bytes();
+    vec![C_IC_NA_1, 1, COT_ACTIVATION, 0, lo, hi, 0, 0, 0, QOI_STATION]
+}
+
+/// A CP56Time2a time tag in the outstation's time, or `None` if flagged invalid.
+fn cp56time2a(b: &[u8], offset: UtcOffset) -> Option<OffsetDateTime> {
+    if b[2] & 0x80 != 0 {
+        return None;
+    }
+    let ms = u16::from_le_bytes([b[0], b[1]]);
+    let date = Date::from_calendar_date(
+        2000 + i32::from(b[6] & 0x7f),
+        Month::try_from(b[5] & 0x0f).ok()?,
+        b[4] & 0x1f,
+    )
+    .ok()?;
+    let time = Time::from_hms_milli(b[3] & 0x1f, b[2] & 0x3f, (ms / 1000) as u8, ms % 1000).ok()?;
+    Some(
+        PrimitiveDateTime::new(date, time)
+            .assume_offset(offset)
+            .to_offset(UtcOffset::UTC),
+    )
+}
+
+/// One measured value of an ASDU.
+#[derive(Debug, Clone, PartialEq)]
+struct MeasuredValue {
+    ioa: u32,
+    value: f64,
+    invalid: bool,
+    /// From the CP56Time2a tag; `None` for untagged types or invalid tags.
+    time: Option<OffsetDateTime>,
+}
+
+/// The measured values of an ASDU; other types are ignored.
+fn parse_asdu(asdu: &[u8], offset: UtcOffset) -> Result<Vec<MeasuredValue>, String> {
+    let [type_id, vsq, _cot, _originator, _ca_lo, _ca_hi, objects @ ..] = asdu else {
+        return Err(format!("ASDU of {} bytes is shorter than its header", asdu.len()));
+    };
+    let (value_len, tagged) = match *type_id {
+        M_ME_NA_1 | M_ME_NB_1 => (3, false),
+        M_ME_NC_1 => (5, false),
+        M_ME_TD_1 | M_ME_TE_1 => (3, true),
+        M_ME_TF_1 => (5, true),
+        _ => return Ok(Vec::new()),
+    };
+    let element_len = value_len + if tagged { 7 } else { 0 };
+    let count = usize::from(vsq & 0x7f);
+    let sequence = vsq & 0x80 != 0;
+    let expected = if sequence {
+        3 + count * element_len
+    } else {
+        count * (3 + element_len)
+    };
+    if objects.len() != expected {
+        return Err(format!(
+            "type {type_id} ASDU with {count} objects has {} bytes, expected {expected}",
+            objects.len()
+        ));
+    }
+
+    let ioa = |b: &[u8]| u32::from_le_bytes([b[0], b[1], b[2], 0]);
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        let (address, element) = if sequence {
+            (ioa(objects) + i as u32, &objects[3 + i * element_len..][..element_len])
+        } else {
+            let object = &objects[i * (3 + element_len)..];
+            (ioa(object), &object[3..3 + element_len])
+        };
+        let value = match *type_id {
+            M_ME_NA_1 | M_ME_TD_1 => f64::from(i16::from_le_bytes([element[0], element[1]])) / 32768.0,
+            M_ME_NB_1 | M_ME_TE_1 => f64::from(i16::from_le_bytes([element[0], element[1]])),
+            _ => f64::from(f32::from_le_bytes([element[0], element[1], element[2], element[3]])),
+        };
+        out.push(MeasuredValue {
+            ioa: address,
+            value,
+            invalid: element[value_len - 1] & QDS_INVALID != 0,
+            time: tagged.then(|| cp56time2a(&element[value_len..], offset)).flatten(),
+        });
+    }
+    Ok(out)
+}
+
+/// Configured points by IOA.
+#[derive(Debug, Clone)]
+struct Points {
+    by_ioa: HashMap<u32, Iec104Point>,
+    units: UnitTelemetry,
+}
+
+impl Points {
+    fn new(points: &[Iec104Point]) -> Result<Self, String> {
+        let mut by_ioa = HashMap::new();
+        for p in points {
+            if by_ioa.insert(p.ioa, p.clone()).is_some() {
+                return Err(format!("IOA {} is configured twice", p.ioa));
+            }
+        }
+        Ok(Self {
+            by_ioa,
+            units: UnitTelemetry::default(),
+        })
+    }
+
+    /// Apply a value; MW points yield a record.
+    fn apply(&mut self, v: &MeasuredValue, received: OffsetDateTime) -> Option<GenerationOutput> {
+        let Some(point) = self.by_ioa.get(&v.ioa) else {
+            metrics::counter!("iec104_unmapped_values_total").increment(1);
+            return None;
+        };
+        if v.invalid {
+            metrics::counter!("iec104_invalid_values_total").increment(1);
+            return None;
+        }
+        self.units.apply(
+            &point.plant_id,
+            point.unit_id.as_deref(),
+            point.field,
+            v.value * point.scale,
+            v.time.unwrap_or(received),
+        )
+    }
+}
+
+/// A started data transfer with one outstation.
+struct Session {
+    stream: TcpStream,
+    buf: BytesMut,
+    send_seq: u16,
+    recv_seq: u16,
+    unacked: u16,
+    ack_every: u16,
+    test_interval: Duration,
+}
+
+impl Session {
+    async fn connect(cfg: &Iec104SourceConfig) -> Result<Self, String> {
+        let stream = tokio::time::timeout(CONFIRM_TIMEOUT, TcpStream::connect((cfg.host.as_str(), cfg.port)))
+            .await
+            .map_err(|_| "connect timed out".to_string())?
+            .map_err(|e| e.to_string())?;
+        let mut session = Self {
+            stream,
+            buf: BytesMut::with_capacity(4096),
+            send_seq: 0,
+            recv_seq: 0,
+            unacked: 0,
+            ack_every: cfg.ack_every.max(1),
+            test_interval: Duration::from_secs(cfg.test_interval_secs.max(1)),
+        };
+        session.write(&u_frame(STARTDT_ACT)).await?;
+        tokio::time::timeout(CONFIRM_TIMEOUT, async {
+            loop {
+                match session.read_frame().await? {
+                    Apdu::U(STARTDT_CON) => return Ok::<_, String>(()),
+                    Apdu::U(TESTFR_ACT) => session.write(&u_frame(TESTFR_CON)).await?,
+                    _ => {}
+                }
+            }
+        })
+        .await
+        .map_err(|_| "STARTDT not confirmed".to_string())??;
+
+        if cfg.interrogate {
+            let frame = i_frame(session.send_seq, session.recv_seq, &interrogation(cfg.common_address));
+            session.write(&frame).await?;
+            session.send_seq = (session.send_seq + 1) & 0x7fff;
+        }
+        Ok(session)
+    }
+
+    async fn write(&mut self, frame: &[u8]) -> Result<(), String> {
+        self.stream.write_all(frame).await.map_err(|e| e.to_string())
+    }
+
+    async fn read_frame(&mut self) -> Result<Apdu, String> {
+        loop {
+            if let Some(apdu) = decode_apdu(&mut self.buf)? {
+                return Ok(apdu);
+            }
+            if self.stream.read_buf(&mut self.buf).await.map_err(|e| e.to_string())? == 0 {
+                return Err("connection closed by the outstation".to_string());
+            }
+        }
+    }
+
+    /// The next ASDU, acknowledging I-frames and answering link tests on the way.
+    async fn next_asdu(&mut self) -> Result<Vec<u8>, String> {
+        let mut testing = false;
+        loop {
+            let frame = match tokio::time::timeout(self.test_interval, self.read_frame()).await {
+                Ok(frame) => frame?,
+                Err(_) if testing => return Err("no response to TESTFR".to_string()),
+                Err(_) => {
+                    if self.unacked > 0 {
+                        self.write(&s_frame(self.recv_seq)).await?;
+                        self.unacked = 0;
+                    }
+                    self.write(&u_frame(TESTFR_ACT)).await?;
+                    testing = true;
+                    continue;
+                }
+            };
+            testing = false;
+            match frame {
+                Apdu::I { send_seq, asdu } => {
+                    if send_seq != self.recv_seq {
+                        return Err(format!("expected I-frame {}, got {send_seq}", self.recv_seq));
+                    }
+                    self.recv_seq = (self.recv_seq + 1) & 0x7fff;
+                    self.unacked += 1;
+                    if self.unacked >= self.ack_every {
+                        self.write(&s_frame(self.recv_seq)).await?;
+                        self.unacked = 0;
+                    }
+                    return Ok(asdu);
+                }
+                Apdu::U(TESTFR_ACT) => self.write(&u_frame(TESTFR_CON)).await?,
+                Apdu::S | Apdu::U(_) => {}
+            }
+        }
+    }
+}
+
+/// IEC 60870-5-104 client source for `GenerationOutput`.
+///
+/// Connects to the outstation, starts data transfer and (optionally) sends a
+/// station interrogation, then reads measured values (types 9, 11, 13 and
+/// their CP56Time2a-tagged forms 34, 35, 36) of the configured IOAs. MW points
+/// yield a record stamped with the value's time tag, or the time received for
+/// untagged values; other points update their unit's latest value, carried on
+/// its next MW record. Invalid values are skipped and counted. The source
+/// reconnects after a connection failure; records are not acknowledged back,
+/// so values sent while disconnected are covered by the next interrogation.
+pub struct Iec104Source {
+    cfg: Iec104SourceConfig,
+    points: Points,
+    offset: UtcOffset,
+}
+
+impl Iec104Source {
+    pub fn from_config(cfg: &Iec104SourceConfig) -> Result<Self, PipelineError> {
+        let points = Points::new(&cfg.points).map_err(PipelineError::Source)?;
+        if points.by_ioa.is_empty() {
+            return Err(PipelineError::Source("iec104.points is empty".to_string()));
+        }
+        let offset = UtcOffset::from_whole_seconds(cfg.utc_offset_minutes * 60)
+            .map_err(|e| PipelineError::Source(format!("invalid iec104.utc_offset_minutes: {e}")))?;
+        tracing::info!(host = %cfg.host, port = cfg.port, common_address = cfg.common_address, points = cfg.points.len(), "IEC 104 source configured");
+        Ok(Self {
+            cfg: cfg.clone(),
+            points,
+            offset,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<GenerationOutput> for Iec104Source {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<GenerationOutput>, PipelineError>> + Send>> {
+        let cfg = self.cfg.clone();
+        let mut points = self.points.clone();
+        let offset = self.offset;
+        let addr = format!("{}:{}", cfg.host, cfg.port);
+
+        let s = async_stream::stream! {
+            loop {
+                match Session::connect(&cfg).await {
+                    Ok(mut session) => {
+                        tracing::info!(%addr, "IEC 104 data transfer started");
+                        loop {
+                            let asdu = match session.next_asdu().await {
+                                Ok(asdu) => asdu,
+                                Err(e) => {
+                                    metrics::counter!("iec104_source_errors_total").increment(1);
+                                    yield Err(PipelineError::Source(format!("IEC 104 connection to {addr} failed: {e}")));
+                                    break;
+                                }
+                            };
+                            let values = match parse_asdu(&asdu, offset) {
+                                Ok(values) => values,
+                                Err(e) => {
+                                    metrics::counter!("iec104_source_parse_errors_total").increment(1);
+                                    yield Err(PipelineError::Source(format!("invalid ASDU from {addr}: {e}")));
+                                    continue;
+                                }
+                            };
+                            let received = OffsetDateTime::now_utc();
+                            for v in &values {
+                                if let Some(record) = points.apply(v, received) {
+                                    metrics::counter!("iec104_source_records_total").increment(1);
+                                    yield Ok(Envelope {
+                                        payload: record,
+                                        received_at: std::time::SystemTime::now(),
+                                        completion: None,
+                                        tenant: None,
+                                        source_identity: None,
+                                    });
+                                }
+                            }
+                        }
+                    }
+                    Err(e) => {
+                        metrics::counter!("iec104_source_errors_total").increment(1);
+                        yield Err(PipelineError::Source(format!("IEC 104 connection to {addr} failed: {e}")));
+                    }
+                }
+                tokio::time::sleep(Duration::from_secs(cfg.reconnect_delay_secs)).await;
+            }
+        };
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::TelemetryField;
+    use futures::StreamExt;
+    use time::macros::datetime;
+    use tokio::net::TcpListener;
+
+    /// 2024-06-01 12:30:15.250 in CP56Time2a.
+    const CP56: [u8; 7] = [0x92, 0x3b, 30, 12, 1, 6, 24];
+
+    fn point(ioa: u32, unit: &str, field: TelemetryField, scale: f64) -> Iec104Point {
+        Iec104Point {
+            ioa,
+            plant_id: "p-1".to_string(),
+            unit_id: Some(unit.to_string()),
+            field,
+            scale,
+        }
+    }
+
+    fn float_asdu(values: &[(u32, f32, u8)]) -> Vec<u8> {
+        let mut asdu = vec![M_ME_TF_1, values.len() as u8, 3, 0, 1, 0];
+        for (ioa, value, qds) in values {
+            asdu.extend(&ioa.to_le_bytes()[..3]);
+            asdu.extend(value.to_le_bytes());
+            asdu.push(*qds);
+            asdu.extend(CP56);
+        }
+        asdu
+    }
+
+    #[test]
+    fn parses_tagged_and_sequenced_measured_values() {
+        let values = parse_asdu(
+            &float_asdu(&[(4001, 12.5, 0), (4002, 1.0, QDS_INVALID)]),
+            UtcOffset::UTC,
+        )
+        .unwrap();
+        assert_eq!(
+            values[0],
+            MeasuredValue {
+                ioa: 4001,
+                value: 12.5,
+                invalid: false,
+                time: Some(datetime!(2024-06-01 12:30:15.250 UTC)),
+            }
+        );
+        assert!(values[1].invalid);
+
+        // Scaled values as a sequence from IOA 100, untagged.
+        let asdu = [M_ME_NB_1, 0x82, 20, 0, 1, 0, 100, 0, 0, 0xe8, 0x03, 0, 0x18, 0xfc, 0];
+        let values = parse_asdu(&asdu, UtcOffset::UTC).unwrap();
+        let got: Vec<(u32, f64, Option<OffsetDateTime>)> = values.iter().map(|v| (v.ioa, v.value, v.time)).collect();
+        assert_eq!(got, [(100, 1000.0, None), (101, -1000.0, None)]);
+
+        // Local time tags are shifted to UTC; other types are ignored; short ASDUs fail.
+        let values = parse_asdu(&float_asdu(&[(1, 0.0, 0)]), UtcOffset::from_hms(-5, 0, 0).unwrap()).unwrap();
+        assert_eq!(values[0].time, Some(datetime!(2024-06-01 17:30:15.250 UTC)));
+        assert!(parse_asdu(&[1, 1, 3, 0, 1, 0, 1, 0, 0, 1], UtcOffset::UTC)
+            .unwrap()
+            .is_empty());
+        assert!(parse_asdu(&[M_ME_NC_1, 1, 3, 0, 1, 0, 1, 0, 0], UtcOffset::UTC).is_err());
+    }
+
+    #[test]
+    fn carries_a_units_latest_values_on_its_mw_records() {
+        let mut points = Points::new(&[
+            point(1, "u-1", TelemetryField::Mw, 0.001),
+            point(2, "u-1", TelemetryField::Mvar, 1.0),
+            point(3, "u-2", TelemetryField::Mw, 1.0),
+        ])
+        .unwrap();
+        let now = datetime!(2024-06-01 00:00 UTC);
+        let value = |ioa, value| MeasuredValue {
+            ioa,
+            value,
+            invalid: false,
+            time: None,
+        };
+
+        assert!(points.apply(&value(2, 4.5), now).is_none());
+        let record = points.apply(&value(1, 120_000.0), now).unwrap();
+        assert_eq!((record.mw, record.mvar, record.ts), (120.0, Some(4.5), now));
+        assert_eq!(points.apply(&value(3, 50.0), now).unwrap().mvar, None);
+        assert!(points.apply(&value(9, 1.0), now).is_none());
+
+        assert!(Points::new(&[
+            point(1, "u-1", TelemetryField::Mw, 1.0),
+            point(1, "u-2", TelemetryField::Mw, 1.0)
+        ])
+        .is_err());
+    }
+
+    #[tokio::test]
+    async fn starts_data_transfer_interrogates_and_reads_values() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let outstation = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut frame = [0u8; 6];
+            socket.read_exact(&mut frame).await.unwrap();
+            assert_eq!(frame, u_frame(STARTDT_ACT));
+            socket.write_all(&u_frame(STARTDT_CON)).await.unwrap();
+
+            let mut gi = [0u8; 16];
+            socket.read_exact(&mut gi).await.unwrap();
+            assert_eq!(&gi[6..], &interrogation(7)[..]);
+            socket
+                .write_all(&i_frame(0, 1, &float_asdu(&[(4001, 80.0, 0)])))
+                .await
+                .unwrap();
+
+            // With ack_every = 1 the client acknowledges right away.
+            socket.read_exact(&mut frame).await.unwrap();
+            assert_eq!(frame, s_frame(1));
+            socket
+        });
+
+        let cfg = Iec104SourceConfig {
+            host: "127.0.0.1".to_string(),
+            port,
+            common_address: 7,
+            interrogate: true,
+            test_interval_secs: 20,
+            ack_every: 1,
+            reconnect_delay_secs: 1,
+            utc_offset_minutes: 0,
+            points: vec![point(4001, "u-1", TelemetryField::Mw, 1.0)],
+        };
+        let source = Iec104Source::from_config(&cfg).unwrap();
+        let mut stream = source.stream().await;
+        let record = stream.next().await.unwrap().unwrap().payload;
+        assert_eq!((record.plant_id.as_str(), record.mw), ("p-1", 80.0));
+        assert_eq!(record.ts, datetime!(2024-06-01 12:30:15.250 UTC));
+        outstation.await.unwrap();
+    }
+}
@@ -0,0 +1,53 @@
+/// A record that can be decoded from the JSON object the HTTP sources accept.
+///
+/// Lets message-based sources (Kafka, MQTT) share the HTTP payload format.
+pub trait JsonRecord: Sized {
+    /// Decode one JSON object; the error names the offending field where possible.
+    fn from_json(json: &str) -> Result<Self, String>;
+}
+
+/// Decode a message payload holding a JSON array, NDJSON, or a single JSON object.
+///
+/// Every record is decoded on its own, so one bad record doesn't discard the
+/// rest of the message. Blank lines are skipped.
+pub fn parse_payload<T: JsonRecord>(payload: &str) -> Vec<Result<T, String>> {
+    let payload = payload.trim();
+    if payload.starts_with('[') {
+        return match serde_json::from_str::<Vec<serde_json::Value>>(payload) {
+            Ok(values) => values.iter().map(|v| T::from_json(&v.to_string())).collect(),
+            Err(e) => vec![Err(format!("invalid JSON array: {e}"))],
+        };
+    }
+    payload
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(T::from_json)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_client::domain::MeterUsage;
+
+    #[test]
+    fn parses_arrays_ndjson_and_single_objects() {
+        let one = r#"{"ts":"2024-01-01T00:00:00Z","meter_id":"m-1","kwh":1.0}"#;
+        let bad = r#"{"ts":"yesterday","meter_id":"m-2","kwh":2.0}"#;
+
+        let single = parse_payload::<MeterUsage>(one);
+        assert_eq!(single.len(), 1);
+        assert_eq!(single[0].as_ref().unwrap().meter_id, "m-1");
+
+        let ndjson = parse_payload::<MeterUsage>(&format!("{one}\n\n{bad}\n"));
+        assert_eq!(ndjson.len(), 2);
+        assert!(ndjson[0].is_ok());
+        assert!(ndjson[1].as_ref().unwrap_err().starts_with("ts: invalid RFC 3339 timestamp"));
+
+        let array = parse_payload::<MeterUsage>(&format!("[{one}, {one}]"));
+        assert_eq!(array.iter().filter(|r| r.is_ok()).count(), 2);
+
+        assert_eq!(parse_payload::<MeterUsage>("[{").len(), 1);
+    }
+}
@@ -0,0 +1,188 @@
+use std::{collections::HashSet, marker::PhantomData, pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt};
+use rdkafka::{
+    consumer::{Consumer, StreamConsumer},
+    ClientConfig, Message,
+};
+use tokio::sync::mpsc;
+
+#[cfg(feature = "avro")]
+use crate::sources::avro::AvroDecoder;
+use crate::{
+    config::{AvroDecodeConfig, KafkaSourceConfig},
+    pipeline::{AckOutcome, AckReceiver, Completion, Envelope, PipelineError, Source},
+    sources::json_record::{parse_payload, JsonRecord},
+};
+
+/// The records of one consumed message, settled together.
+struct PendingMessage {
+    topic: String,
+    partition: i32,
+    offset: i64,
+    acks: Vec<AckReceiver>,
+}
+
+/// Stores the offset of every message whose records were all written or
+/// rejected, in consumption order; auto-commit then commits the stored offsets.
+///
+/// A partition stops advancing at the first message with a lost record, so
+/// after a restart the group resumes there (at-least-once).
+async fn store_offsets(consumer: Arc<StreamConsumer>, mut pending: mpsc::UnboundedReceiver<PendingMessage>) {
+    let mut stalled: HashSet<(String, i32)> = HashSet::new();
+    while let Some(msg) = pending.recv().await {
+        let mut settled = true;
+        for ack in msg.acks {
+            if ack.outcome().await == AckOutcome::Dropped {
+                settled = false;
+            }
+        }
+        let partition = (msg.topic, msg.partition);
+        if stalled.contains(&partition) {
+            continue;
+        }
+        if !settled {
+            tracing::warn!(
+                topic = %partition.0,
+                partition = partition.1,
+                offset = msg.offset,
+                "record was not written; offsets of this partition stop here"
+            );
+            stalled.insert(partition);
+            continue;
+        }
+        if let Err(e) = consumer.store_offset(&partition.0, partition.1, msg.offset) {
+            tracing::warn!(error = %e, topic = %partition.0, partition = partition.1, "failed to store Kafka offset");
+        }
+    }
+}
+
+/// Consumes records from Kafka topics as a consumer-group member.
+///
+/// Messages hold one JSON object, NDJSON, or a JSON array of records in the
+/// HTTP source's payload format, or with [`AvroDecodeConfig`] one Avro record
+/// each (see [`AvroDecoder`](crate::sources::avro::AvroDecoder), `avro`
+/// build feature). A message's offset is committed (via
+/// `enable.auto.commit` of stored offsets) only once each of its records was
+/// written by the sink or rejected by validation; records that fail to parse
+/// are skipped and counted. Delivery is at-least-once: after a restart or
+/// rebalance, messages after the last commit are consumed again.
+pub struct KafkaSource<T> {
+    consumer: Arc<StreamConsumer>,
+    #[cfg(feature = "avro")]
+    avro: Option<Arc<AvroDecoder>>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> KafkaSource<T> {
+    pub fn from_config(cfg: &KafkaSourceConfig, avro: Option<&AvroDecodeConfig>) -> Result<Self, PipelineError> {
+        #[cfg(feature = "avro")]
+        let avro = avro.map(AvroDecoder::from_config).transpose()?.map(Arc::new);
+        #[cfg(not(feature = "avro"))]
+        if avro.is_some() {
+            return Err(PipelineError::Source(
+                "Avro messages require building with `--features avro`".to_string(),
+            ));
+        }
+
+        let mut client = ClientConfig::new();
+        client
+            .set("bootstrap.servers", &cfg.brokers)
+            .set("group.id", &cfg.group_id)
+            .set("auto.offset.reset", &cfg.auto_offset_reset)
+            .set("enable.auto.commit", "true")
+            .set("enable.auto.offset.store", "false");
+        for (key, value) in &cfg.properties {
+            client.set(key, value);
+        }
+        let consumer: StreamConsumer = client
+            .create()
+            .map_err(|e| PipelineError::Source(format!("failed to create Kafka consumer: {e}")))?;
+
+        let topics: Vec<&str> = cfg.topics.iter().map(String::as_str).collect();
+        consumer
+            .subscribe(&topics)
+            .map_err(|e| PipelineError::Source(format!("failed to subscribe to {topics:?}: {e}")))?;
+        tracing::info!(brokers = %cfg.brokers, group_id = %cfg.group_id, ?topics, "Kafka source subscribed");
+
+        Ok(Self {
+            consumer: Arc::new(consumer),
+            #[cfg(feature = "avro")]
+            avro,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Source<T> for KafkaSource<T>
+where
+    T: JsonRecord + Send + 'static,
+{
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let consumer = self.consumer.clone();
+        #[cfg(feature = "avro")]
+        let avro = self.avro.clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(store_offsets(consumer.clone(), rx));
+
+        let s = async_stream::stream! {
+            let mut messages = consumer.stream();
+            while let Some(message) = messages.next().await {
+                let message = match message {
+                    Ok(m) => m,
+                    Err(e) => {
+                        metrics::counter!("kafka_source_errors_total").increment(1);
+                        yield Err(PipelineError::Source(format!("Kafka consume failed: {e}")));
+                        continue;
+                    }
+                };
+                let topic = message.topic().to_string();
+                let (partition, offset) = (message.partition(), message.offset());
+                let payload = message.payload().map(<[u8]>::to_vec);
+                drop(message);
+                metrics::counter!("kafka_source_messages_total", "topic" => topic.clone()).increment(1);
+
+                let records = match payload {
+                    #[cfg(feature = "avro")]
+                    Some(payload) if avro.is_some() => {
+                        vec![avro.as_ref().expect("checked above").decode::<T>(&payload).await]
+                    }
+                    Some(payload) => match std::str::from_utf8(&payload) {
+                        Ok(payload) => parse_payload::<T>(payload),
+                        Err(e) => vec![Err(format!("payload is not UTF-8: {e}"))],
+                    },
+                    None => Vec::new(),
+                };
+                let mut envelopes = Vec::new();
+                let mut acks = Vec::new();
+                let mut errors = Vec::new();
+                for record in records {
+                    match record {
+                        Ok(record) => {
+                            let (completion, ack) = Completion::oneshot();
+                            envelopes.push(Envelope::tracked(record, completion));
+                            acks.push(ack);
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                }
+                let _ = tx.send(PendingMessage {
+                    topic: topic.clone(),
+                    partition,
+                    offset,
+                    acks,
+                });
+
+                for e in errors {
+                    metrics::counter!("kafka_source_parse_errors_total", "topic" => topic.clone()).increment(1);
+                    yield Err(PipelineError::Source(format!("invalid record on {topic}: {e}")));
+                }
+                for env in envelopes {
+                    yield Ok(env);
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
@@ -2,21 +2,43 @@ use std::{path::PathBuf, time::SystemTime};
 
 use futures::Stream;
 use rust_client::domain::MeterUsage;
-use tokio::{fs::File, io::{AsyncBufReadExt, BufReader}};
+use tokio::io::AsyncBufReadExt;
 use async_stream::try_stream;
 
-use crate::pipeline::{Envelope, PipelineError, Source};
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sources::{compressed_file, http_json::IncomingPhases},
+};
 
 /// A simple NDJSON backfill source for `MeterUsage`.
 ///
 /// Each line in the file is expected to be a JSON object with the same shape
 /// as the HTTP ingestion "incoming" payload (ts, meter_id, kwh, etc.).
+/// Gzip and zstd files are decompressed while reading.
 pub struct MeterUsageBackfillFileSource {
     path: PathBuf,
 }
 
+/// Backfill timestamps: `time`'s own serde form, as written by existing
+/// exports, or an RFC 3339 string, as found in vendor bulk files.
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum BackfillTs {
+    Rfc3339(#[serde(with = "time::serde::rfc3339")] time::OffsetDateTime),
+    Native(time::OffsetDateTime),
+}
+
+impl From<BackfillTs> for time::OffsetDateTime {
+    fn from(ts: BackfillTs) -> Self {
+        match ts {
+            BackfillTs::Rfc3339(ts) | BackfillTs::Native(ts) => ts,
+        }
+    }
+}
+
 #[derive(serde::Deserialize)]
 struct BackfillMeterUsage {
+    #[serde(deserialize_with = "deserialize_ts")]
     ts: time::OffsetDateTime,
     meter_id: String,
     premise_id: Option<String>,
@@ -25,6 +47,13 @@ struct BackfillMeterUsage {
     kva_demand: Option<f64>,
     quality_flag: Option<String>,
     source_system: Option<String>,
+    event_id: Option<String>,
+    #[serde(flatten)]
+    phases: IncomingPhases,
+}
+
+fn deserialize_ts<'de, D: serde::Deserializer<'de>>(d: D) -> Result<time::OffsetDateTime, D::Error> {
+    <BackfillTs as serde::Deserialize>::deserialize(d).map(Into::into)
 }
 
 impl From<BackfillMeterUsage> for MeterUsage {
@@ -38,44 +67,23 @@ impl From<BackfillMeterUsage> for MeterUsage {
             kva_demand: i.kva_demand,
             quality_flag: i.quality_flag,
             source_system: i.source_system,
+            event_id: i.event_id,
+            phases: i.phases.into(),
         }
     }
 }
 
+/// Parse one NDJSON backfill line.
+pub(crate) fn parse_backfill_line(line: &str) -> Result<MeterUsage, serde_json::Error> {
+    serde_json::from_str::<BackfillMeterUsage>(line).map(Into::into)
+}
+
 impl MeterUsageBackfillFileSource {
     pub fn new<P: Into<PathBuf>>(path: P) -> Self {
         Self { path: path.into() }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn backfill_meter_usage_parses_from_struct() {
-        use time::macros::datetime;
-
-        let parsed = BackfillMeterUsage {
-            ts: datetime!(2024-01-01 00:00:00 UTC),
-            meter_id: "m-123".to_string(),
-            premise_id: None,
-            kwh: 1.23,
-            kvarh: None,
-            kva_demand: None,
-            quality_flag: None,
-            source_system: Some("scada".to_string()),
-        };
-        assert_eq!(parsed.meter_id, "m-123");
-        assert_eq!(parsed.kwh, 1.23);
-
-        let usage: MeterUsage = parsed.into();
-        assert_eq!(usage.meter_id, "m-123");
-        assert_eq!(usage.kwh, 1.23);
-        assert!(usage.premise_id.is_none());
-    }
-}
-
 #[async_trait::async_trait]
 impl Source<MeterUsage> for MeterUsageBackfillFileSource {
     async fn stream(
@@ -83,16 +91,15 @@ impl Source<MeterUsage> for MeterUsageBackfillFileSource {
     ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
         let path = self.path.clone();
         let s = try_stream! {
-            let file = File::open(&path).await.map_err(|e| {
+            let reader = compressed_file::open_async(&path).await.map_err(|e| {
                 PipelineError::Source(format!("failed to open backfill file: {e}"))
             })?;
-            let reader = BufReader::new(file);
             let mut lines = reader.lines();
 
             while let Some(line) = lines.next_line().await.map_err(|e| {
                 PipelineError::Source(format!("failed to read backfill line: {e}"))
             })? {
-                let parsed: BackfillMeterUsage = match serde_json::from_str(&line) {
+                let usage = match parse_backfill_line(&line) {
                     Ok(v) => v,
                     Err(e) => {
                         metrics::counter!("backfill_meter_usage_parse_errors_total").increment(1);
@@ -101,10 +108,12 @@ impl Source<MeterUsage> for MeterUsageBackfillFileSource {
                         )))?
                     }
                 };
-                let usage: MeterUsage = parsed.into();
                 yield Envelope {
                     payload: usage,
                     received_at: SystemTime::now(),
+                    completion: None,
+                    tenant: None,
+                    source_identity: None,
                 };
             }
         };
@@ -112,3 +121,42 @@ impl Source<MeterUsage> for MeterUsageBackfillFileSource {
         Box::pin(s)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backfill_meter_usage_parses_from_struct() {
+        use time::macros::datetime;
+
+        let parsed = BackfillMeterUsage {
+            ts: datetime!(2024-01-01 00:00:00 UTC),
+            meter_id: "m-123".to_string(),
+            premise_id: None,
+            kwh: 1.23,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some("scada".to_string()),
+            event_id: None,
+            phases: IncomingPhases::default(),
+        };
+        assert_eq!(parsed.meter_id, "m-123");
+        assert_eq!(parsed.kwh, 1.23);
+
+        let usage: MeterUsage = parsed.into();
+        assert_eq!(usage.meter_id, "m-123");
+        assert_eq!(usage.kwh, 1.23);
+        assert!(usage.premise_id.is_none());
+    }
+
+    #[test]
+    fn backfill_line_accepts_native_and_rfc3339_timestamps() {
+        let ts = time::macros::datetime!(2024-01-01 00:15:00 UTC);
+        let native = format!("{{\"ts\":{},\"meter_id\":\"m-1\",\"kwh\":1.5}}", serde_json::to_string(&ts).unwrap());
+        assert_eq!(parse_backfill_line(&native).unwrap().ts, ts);
+        let rfc3339 = r#"{"ts":"2024-01-01T00:15:00Z","meter_id":"m-1","kwh":1.5}"#;
+        assert_eq!(parse_backfill_line(rfc3339).unwrap().ts, ts);
+    }
+}
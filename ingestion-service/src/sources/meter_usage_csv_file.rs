@@ -1,11 +1,14 @@
-use std::{fs::File, path::PathBuf, time::SystemTime};
+use std::{path::PathBuf, time::SystemTime};
 
 use csv::StringRecord;
 use futures::Stream;
-use rust_client::domain::MeterUsage;
+use rust_client::domain::{MeterUsage, PhaseChannels};
 use time::OffsetDateTime;
 
-use crate::pipeline::{Envelope, PipelineError, Source};
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sources::compressed_file,
+};
 
 /// CSV backfill/source for `MeterUsage`.
 ///
@@ -18,6 +21,8 @@ use crate::pipeline::{Envelope, PipelineError, Source};
 /// - kva_demand (optional)
 /// - quality_flag (optional)
 /// - source_system (optional)
+///
+/// Gzip and zstd files are decompressed while reading.
 pub struct MeterUsageCsvFileSource {
     path: PathBuf,
 }
@@ -45,7 +50,7 @@ fn parse_optional_string(s: &str) -> Option<String> {
     }
 }
 
-fn record_to_meter_usage(record: &StringRecord, headers: &csv::StringRecord) -> Result<MeterUsage, PipelineError> {
+pub(crate) fn record_to_meter_usage(record: &StringRecord, headers: &csv::StringRecord) -> Result<MeterUsage, PipelineError> {
     let get = |name: &str| -> Result<&str, PipelineError> {
         headers
             .iter()
@@ -81,6 +86,8 @@ fn record_to_meter_usage(record: &StringRecord, headers: &csv::StringRecord) ->
         kva_demand,
         quality_flag,
         source_system,
+        event_id: None,
+        phases: PhaseChannels::default(),
     })
 }
 
@@ -93,7 +100,7 @@ impl Source<MeterUsage> for MeterUsageCsvFileSource {
         // For large files, you might want to move this onto a dedicated thread pool.
         let path = self.path.clone();
         let s = async_stream::try_stream! {
-            let file = File::open(&path)
+            let file = compressed_file::open(&path)
                 .map_err(|e| PipelineError::Source(format!("failed to open CSV file: {e}")))?;
             let mut rdr = csv::Reader::from_reader(file);
             let headers = rdr
@@ -117,6 +124,9 @@ impl Source<MeterUsage> for MeterUsageCsvFileSource {
                 yield Envelope {
                     payload: usage,
                     received_at: SystemTime::now(),
+                    completion: None,
+                    tenant: None,
+                    source_identity: None,
                 };
             }
         };
@@ -1,16 +1,20 @@
-use std::{fs::File, path::PathBuf, time::SystemTime};
+use std::{path::PathBuf, time::SystemTime};
 
 use csv::StringRecord;
 use futures::Stream;
-use rust_client::domain::MeterUsage;
+use rust_client::domain::{MeterUsage, PhaseChannels};
 use time::OffsetDateTime;
 
-use crate::pipeline::{Envelope, PipelineError, Source};
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sources::compressed_file,
+};
 
 /// Pipe-delimited (`.dat`) source for `MeterUsage`.
 ///
 /// Assumes a header row with the same column names as the CSV source, but
-/// fields are separated by `|` instead of `,`.
+/// fields are separated by `|` instead of `,`. Gzip and zstd files are
+/// decompressed while reading.
 pub struct MeterUsageDatFileSource {
     path: PathBuf,
 }
@@ -74,6 +78,8 @@ fn record_to_meter_usage(record: &StringRecord, headers: &csv::StringRecord) ->
         kva_demand,
         quality_flag,
         source_system,
+        event_id: None,
+        phases: PhaseChannels::default(),
     })
 }
 
@@ -84,7 +90,7 @@ impl Source<MeterUsage> for MeterUsageDatFileSource {
     ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
         let path = self.path.clone();
         let s = async_stream::try_stream! {
-            let file = File::open(&path)
+            let file = compressed_file::open(&path)
                 .map_err(|e| PipelineError::Source(format!("failed to open DAT file: {e}")))?;
             let mut rdr = csv::ReaderBuilder::new()
                 .delimiter(b'|')
@@ -110,6 +116,9 @@ impl Source<MeterUsage> for MeterUsageDatFileSource {
                 yield Envelope {
                     payload: usage,
                     received_at: SystemTime::now(),
+                    completion: None,
+                    tenant: None,
+                    source_identity: None,
                 };
             }
         };
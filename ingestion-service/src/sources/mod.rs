@@ -1,11 +1,91 @@
+pub mod admission;
+#[cfg(feature = "avro")]
+pub mod avro;
+pub mod bulk_file;
+pub mod channel;
+pub mod checkpoint;
+pub mod compressed_file;
+pub mod directory_watch;
+pub mod dnp3;
+pub mod fixed_width_file;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod green_button;
+pub(crate) mod http_error;
 pub mod http_json;
 pub mod http_generation_output;
+pub mod http_reference;
+mod http_server;
+mod http_ws;
+mod idempotency;
+pub mod iec104;
+pub mod itron_mdm;
+pub mod json_record;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+pub mod mapped_csv;
 pub mod meter_usage_backfill_file;
 pub mod meter_usage_csv_file;
 pub mod meter_usage_dat_file;
+pub mod modbus;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod mv90_hhf;
+#[cfg(feature = "protobuf")]
+pub mod protobuf;
+pub mod questdb_replication;
+#[cfg(feature = "redis")]
+pub mod redis_stream;
+#[cfg(feature = "s3")]
+pub mod s3_archive;
+#[cfg(feature = "s3")]
+pub mod s3_file;
+pub mod sampled;
+#[cfg(feature = "sftp")]
+pub mod sftp_directory;
+pub mod skip_existing;
+pub mod syslog_udp;
+mod telemetry;
+#[cfg(feature = "zip")]
+pub mod zip_archive;
 
+#[cfg(feature = "avro")]
+pub use avro::{AvroDecoder, AvroFileSource};
+pub use channel::ChannelSource;
+pub use checkpoint::{Checkpoint, CheckpointProgress, CheckpointedSource};
+pub use directory_watch::DirectoryWatchSource;
+pub use dnp3::Dnp3Source;
+pub use fixed_width_file::FixedWidthFileSource;
+pub use green_button::GreenButtonSource;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcIngestSource;
 pub use http_json::HttpJsonSource;
 pub use http_generation_output::HttpGenerationOutputSource;
+pub use http_reference::HttpReferenceSource;
+pub use iec104::Iec104Source;
+pub use itron_mdm::ItronMdmFileSource;
+pub use json_record::JsonRecord;
+#[cfg(feature = "kafka")]
+pub use kafka::KafkaSource;
+pub use mapped_csv::MappedCsvSource;
 pub use meter_usage_backfill_file::MeterUsageBackfillFileSource;
 pub use meter_usage_csv_file::MeterUsageCsvFileSource;
 pub use meter_usage_dat_file::MeterUsageDatFileSource;
+pub use modbus::ModbusSource;
+#[cfg(feature = "mqtt")]
+pub use mqtt::MqttSource;
+pub use mv90_hhf::Mv90FileSource;
+pub use questdb_replication::QuestDbReplicationSource;
+#[cfg(feature = "redis")]
+pub use redis_stream::RedisStreamSource;
+#[cfg(feature = "s3")]
+pub use s3_archive::S3ArchiveSource;
+#[cfg(feature = "s3")]
+pub use s3_file::S3FileSource;
+pub use sampled::SampledSource;
+#[cfg(feature = "sftp")]
+pub use sftp_directory::SftpDirectorySource;
+pub use skip_existing::{count_existing_meter_usage, OnConflict, SkipExistingMeterUsageSource};
+pub use syslog_udp::SyslogUdpSource;
+#[cfg(feature = "zip")]
+pub use zip_archive::ZipArchiveSource;
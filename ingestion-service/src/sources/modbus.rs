@@ -0,0 +1,536 @@
+use std::{collections::HashMap, pin::Pin, time::Duration};
+
+use futures::Stream;
+use rust_client::domain::GenerationOutput;
+use time::OffsetDateTime;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    time::MissedTickBehavior,
+};
+
+use crate::{
+    config::{
+        ModbusDataType, ModbusDevice, ModbusRegister, ModbusRegisterKind, ModbusSourceConfig, ModbusWordOrder,
+        TelemetryField,
+    },
+    pipeline::{Envelope, PipelineError, Source},
+    sources::telemetry::UnitTelemetry,
+};
+
+const FC_READ_HOLDING: u8 = 0x03;
+const FC_READ_INPUT: u8 = 0x04;
+/// Set on the function code of an exception response.
+const EXCEPTION: u8 = 0x80;
+/// Most registers one read may return.
+const MAX_READ_REGISTERS: u16 = 125;
+
+fn function_code(kind: ModbusRegisterKind) -> u8 {
+    match kind {
+        ModbusRegisterKind::Holding => FC_READ_HOLDING,
+        ModbusRegisterKind::Input => FC_READ_INPUT,
+    }
+}
+
+fn register_count(data_type: ModbusDataType) -> u16 {
+    match data_type {
+        ModbusDataType::U16 | ModbusDataType::I16 => 1,
+        ModbusDataType::U32 | ModbusDataType::I32 | ModbusDataType::F32 => 2,
+    }
+}
+
+/// A read request: MBAP header and PDU.
+fn read_request(transaction: u16, unit: u8, function: u8, start: u16, count: u16) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(12);
+    frame.extend(transaction.to_be_bytes());
+    frame.extend(0u16.to_be_bytes()); // protocol: Modbus
+    frame.extend(6u16.to_be_bytes()); // unit identifier + PDU
+    frame.push(unit);
+    frame.push(function);
+    frame.extend(start.to_be_bytes());
+    frame.extend(count.to_be_bytes());
+    frame
+}
+
+fn exception_name(code: u8) -> &'static str {
+    match code {
+        1 => "illegal function",
+        2 => "illegal data address",
+        3 => "illegal data value",
+        4 => "server device failure",
+        6 => "server device busy",
+        10 => "gateway path unavailable",
+        11 => "gateway target device failed to respond",
+        _ => "unknown exception",
+    }
+}
+
+/// The registers of a read response's PDU (unit identifier and MBAP header removed).
+fn parse_read_response(pdu: &[u8], function: u8, count: u16) -> Result<Vec<u16>, String> {
+    match pdu {
+        [f, code, ..] if *f == function | EXCEPTION => Err(format!("exception {code}: {}", exception_name(*code))),
+        [f, byte_count, data @ ..] if *f == function => {
+            if usize::from(*byte_count) != 2 * usize::from(count) || data.len() != usize::from(*byte_count) {
+                return Err(format!("expected {count} registers, got {byte_count} bytes"));
+            }
+            Ok(data.chunks_exact(2).map(|w| u16::from_be_bytes([w[0], w[1]])).collect())
+        }
+        [f, ..] => Err(format!("unexpected function code {f:#04x}")),
+        [] => Err("empty response".to_string()),
+    }
+}
+
+/// One read covering consecutive registers of a table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Block {
+    kind: ModbusRegisterKind,
+    start: u16,
+    count: u16,
+}
+
+/// The reads covering all `registers`: each table's registers are merged into
+/// as few reads of at most `MAX_READ_REGISTERS` as possible, gaps included.
+fn plan_blocks(registers: &[ModbusRegister]) -> Vec<Block> {
+    let mut spans: Vec<(ModbusRegisterKind, u16, u16)> = registers
+        .iter()
+        .map(|r| (r.kind, r.address, r.address + register_count(r.data_type) - 1))
+        .collect();
+    spans.sort_by_key(|&(kind, first, _)| (kind == ModbusRegisterKind::Input, first));
+
+    let mut blocks: Vec<Block> = Vec::new();
+    for (kind, first, last) in spans {
+        if let Some(block) = blocks.last_mut() {
+            if block.kind == kind && last - block.start < MAX_READ_REGISTERS {
+                block.count = block.count.max(last - block.start + 1);
+                continue;
+            }
+        }
+        blocks.push(Block {
+            kind,
+            start: first,
+            count: last - first + 1,
+        });
+    }
+    blocks
+}
+
+/// Decode a value from its one or two registers.
+fn decode(data_type: ModbusDataType, word_order: ModbusWordOrder, words: &[u16]) -> f64 {
+    let pair = || {
+        let (hi, lo) = match word_order {
+            ModbusWordOrder::Big => (words[0], words[1]),
+            ModbusWordOrder::Little => (words[1], words[0]),
+        };
+        (u32::from(hi) << 16) | u32::from(lo)
+    };
+    match data_type {
+        ModbusDataType::U16 => f64::from(words[0]),
+        ModbusDataType::I16 => f64::from(words[0] as i16),
+        ModbusDataType::U32 => f64::from(pair()),
+        ModbusDataType::I32 => f64::from(pair() as i32),
+        ModbusDataType::F32 => f64::from(f32::from_bits(pair())),
+    }
+}
+
+/// Registers read in one poll, by table and address.
+type PollValues = HashMap<(ModbusRegisterKind, u16), u16>;
+
+/// Map one poll's registers to records at `ts`.
+///
+/// Non-MW registers are applied first, so a unit's MW record carries the
+/// values of the same poll. Non-finite values (e.g. a NaN float of a device
+/// that is starting up) are skipped and counted.
+fn map_poll(
+    device: &ModbusDevice,
+    units: &mut UnitTelemetry,
+    values: &PollValues,
+    ts: OffsetDateTime,
+) -> Vec<GenerationOutput> {
+    let mut registers: Vec<&ModbusRegister> = device.registers.iter().collect();
+    registers.sort_by_key(|r| r.field == TelemetryField::Mw);
+
+    let mut records = Vec::new();
+    for r in registers {
+        let words: Option<Vec<u16>> = (0..register_count(r.data_type))
+            .map(|i| values.get(&(r.kind, r.address + i)).copied())
+            .collect();
+        let Some(words) = words else {
+            continue;
+        };
+        let value = decode(r.data_type, device.word_order, &words) * r.scale;
+        if !value.is_finite() {
+            metrics::counter!("modbus_invalid_values_total", "device" => device.name.clone()).increment(1);
+            continue;
+        }
+        records.extend(units.apply(&r.plant_id, r.unit_id.as_deref(), r.field, value, ts));
+    }
+    records
+}
+
+/// A TCP connection to one device.
+struct Session {
+    stream: TcpStream,
+    unit: u8,
+    transaction: u16,
+}
+
+impl Session {
+    async fn connect(device: &ModbusDevice, timeout: Duration) -> Result<Self, String> {
+        let stream = tokio::time::timeout(timeout, TcpStream::connect((device.host.as_str(), device.port)))
+            .await
+            .map_err(|_| "connect timed out".to_string())?
+            .map_err(|e| e.to_string())?;
+        Ok(Self {
+            stream,
+            unit: device.unit_identifier,
+            transaction: 0,
+        })
+    }
+
+    /// Read one block; responses to other transactions are skipped.
+    async fn read(&mut self, block: Block) -> Result<Vec<u16>, String> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let function = function_code(block.kind);
+        let request = read_request(self.transaction, self.unit, function, block.start, block.count);
+        self.stream.write_all(&request).await.map_err(|e| e.to_string())?;
+        loop {
+            let mut header = [0u8; 7];
+            self.stream.read_exact(&mut header).await.map_err(|e| e.to_string())?;
+            let transaction = u16::from_be_bytes([header[0], header[1]]);
+            let len = usize::from(u16::from_be_bytes([header[4], header[5]]));
+            if len < 2 {
+                return Err(format!("invalid MBAP length {len}"));
+            }
+            let mut pdu = vec![0u8; len - 1];
+            self.stream.read_exact(&mut pdu).await.map_err(|e| e.to_string())?;
+            if transaction == self.transaction {
+                return parse_read_response(&pdu, function, block.count);
+            }
+        }
+    }
+
+    /// Read all blocks of a poll.
+    async fn poll(&mut self, blocks: &[Block], timeout: Duration) -> Result<PollValues, String> {
+        let mut values = PollValues::new();
+        for &block in blocks {
+            let words = tokio::time::timeout(timeout, self.read(block))
+                .await
+                .map_err(|_| format!("no response within {} ms", timeout.as_millis()))?
+                .map_err(|e| {
+                    let table = match block.kind {
+                        ModbusRegisterKind::Holding => "holding",
+                        ModbusRegisterKind::Input => "input",
+                    };
+                    let last = block.start + block.count - 1;
+                    format!("reading {table} registers {}-{last}: {e}", block.start)
+                })?;
+            values.extend(
+                words
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, w)| ((block.kind, block.start + i as u16), w)),
+            );
+        }
+        Ok(values)
+    }
+}
+
+fn poll_device(
+    device: ModbusDevice,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> Pin<Box<dyn Stream<Item = Result<Envelope<GenerationOutput>, PipelineError>> + Send>> {
+    let s = async_stream::stream! {
+        let name = device.name.clone();
+        let blocks = plan_blocks(&device.registers);
+        let mut units = UnitTelemetry::default();
+        let mut ticks = tokio::time::interval(poll_interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        loop {
+            ticks.tick().await;
+            let mut session = match Session::connect(&device, timeout).await {
+                Ok(session) => session,
+                Err(e) => {
+                    metrics::counter!("modbus_poll_errors_total", "device" => name.clone()).increment(1);
+                    yield Err(PipelineError::Source(format!("Modbus device {name}: connect failed: {e}")));
+                    continue;
+                }
+            };
+            tracing::info!(device = %name, host = %device.host, "Modbus device connected");
+            loop {
+                let values = match session.poll(&blocks, timeout).await {
+                    Ok(values) => values,
+                    Err(e) => {
+                        metrics::counter!("modbus_poll_errors_total", "device" => name.clone()).increment(1);
+                        yield Err(PipelineError::Source(format!("Modbus device {name}: poll failed: {e}")));
+                        break;
+                    }
+                };
+                metrics::counter!("modbus_polls_total", "device" => name.clone()).increment(1);
+                for record in map_poll(&device, &mut units, &values, OffsetDateTime::now_utc()) {
+                    yield Ok(Envelope {
+                        payload: record,
+                        received_at: std::time::SystemTime::now(),
+                        completion: None,
+                        tenant: None,
+                        source_identity: None,
+                    });
+                }
+                ticks.tick().await;
+            }
+        }
+    };
+    Box::pin(s)
+}
+
+/// Modbus TCP client polling register maps into `GenerationOutput`.
+///
+/// Each device is polled over its own TCP connection every
+/// `poll_interval_secs`; its registers are read with as few requests as
+/// possible (function 3 for holding, 4 for input registers), decoded, scaled
+/// and mapped to plant/unit fields. MW registers yield a record stamped with
+/// the poll time, carrying the unit's other fields from the same poll. A failed
+/// read, exception response or timeout drops the connection, which is
+/// re-established at the next poll. Records are not acknowledged; polls missed
+/// while disconnected are lost.
+pub struct ModbusSource {
+    cfg: ModbusSourceConfig,
+}
+
+impl ModbusSource {
+    pub fn from_config(cfg: &ModbusSourceConfig) -> Result<Self, PipelineError> {
+        if cfg.devices.is_empty() {
+            return Err(PipelineError::Source("modbus.devices is empty".to_string()));
+        }
+        for device in &cfg.devices {
+            if device.registers.is_empty() {
+                return Err(PipelineError::Source(format!(
+                    "Modbus device {} has no registers",
+                    device.name
+                )));
+            }
+            for r in &device.registers {
+                if r.address.checked_add(register_count(r.data_type) - 1).is_none() {
+                    return Err(PipelineError::Source(format!(
+                        "Modbus device {}: register {} runs past the last address",
+                        device.name, r.address
+                    )));
+                }
+            }
+        }
+        tracing::info!(
+            devices = cfg.devices.len(),
+            poll_interval_secs = cfg.poll_interval_secs,
+            "Modbus source configured"
+        );
+        Ok(Self { cfg: cfg.clone() })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<GenerationOutput> for ModbusSource {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<GenerationOutput>, PipelineError>> + Send>> {
+        let poll_interval = Duration::from_secs(self.cfg.poll_interval_secs.max(1));
+        let timeout = Duration::from_millis(self.cfg.response_timeout_ms);
+        let streams = self
+            .cfg
+            .devices
+            .iter()
+            .map(|d| poll_device(d.clone(), poll_interval, timeout));
+        Box::pin(futures::stream::select_all(streams))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use time::macros::datetime;
+    use tokio::net::TcpListener;
+
+    fn register(
+        address: u16,
+        kind: ModbusRegisterKind,
+        data_type: ModbusDataType,
+        field: TelemetryField,
+    ) -> ModbusRegister {
+        ModbusRegister {
+            address,
+            kind,
+            data_type,
+            plant_id: "solar-agg-1".to_string(),
+            unit_id: Some("inv-1".to_string()),
+            field,
+            scale: 1.0,
+        }
+    }
+
+    #[test]
+    fn encodes_reads_and_decodes_responses() {
+        assert_eq!(
+            read_request(0x0102, 1, FC_READ_HOLDING, 40, 2),
+            [0x01, 0x02, 0, 0, 0, 6, 1, 0x03, 0, 40, 0, 2]
+        );
+        assert_eq!(
+            parse_read_response(&[0x03, 4, 0x00, 0x2a, 0xff, 0xff], FC_READ_HOLDING, 2).unwrap(),
+            [42, 0xffff]
+        );
+        assert_eq!(
+            parse_read_response(&[0x83, 2], FC_READ_HOLDING, 2).unwrap_err(),
+            "exception 2: illegal data address"
+        );
+        assert!(parse_read_response(&[0x03, 2, 0, 1], FC_READ_HOLDING, 2).is_err());
+
+        let words = [0x4148, 0x0000]; // 12.5f32, high word first
+        assert_eq!(decode(ModbusDataType::F32, ModbusWordOrder::Big, &words), 12.5);
+        assert_eq!(
+            decode(ModbusDataType::F32, ModbusWordOrder::Little, &[0x0000, 0x4148]),
+            12.5
+        );
+        assert_eq!(decode(ModbusDataType::I16, ModbusWordOrder::Big, &[0xfffe]), -2.0);
+        assert_eq!(
+            decode(ModbusDataType::U32, ModbusWordOrder::Big, &[0x0001, 0x0002]),
+            65538.0
+        );
+        assert_eq!(
+            decode(ModbusDataType::I32, ModbusWordOrder::Little, &[0xfffe, 0xffff]),
+            -2.0
+        );
+    }
+
+    #[test]
+    fn merges_registers_into_reads_per_table() {
+        use ModbusDataType::*;
+        use ModbusRegisterKind::*;
+        let registers = [
+            register(10, Holding, U16, TelemetryField::Mw),
+            register(0, Holding, F32, TelemetryField::Mvar),
+            register(5, Input, U32, TelemetryField::AvailabilityPct),
+            register(200, Holding, I16, TelemetryField::CurtailedMw),
+        ];
+        assert_eq!(
+            plan_blocks(&registers),
+            [
+                Block {
+                    kind: Holding,
+                    start: 0,
+                    count: 11
+                },
+                Block {
+                    kind: Holding,
+                    start: 200,
+                    count: 1
+                },
+                Block {
+                    kind: Input,
+                    start: 5,
+                    count: 2
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn applies_a_polls_other_fields_before_its_mw() {
+        let mut power = register(0, ModbusRegisterKind::Holding, ModbusDataType::I32, TelemetryField::Mw);
+        power.scale = 0.001; // kW
+        let device = ModbusDevice {
+            name: "agg-1".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 502,
+            unit_identifier: 1,
+            word_order: ModbusWordOrder::Big,
+            registers: vec![
+                power,
+                register(
+                    2,
+                    ModbusRegisterKind::Holding,
+                    ModbusDataType::F32,
+                    TelemetryField::Mvar,
+                ),
+                register(
+                    4,
+                    ModbusRegisterKind::Input,
+                    ModbusDataType::U16,
+                    TelemetryField::AvailabilityPct,
+                ),
+            ],
+        };
+        let nan = f32::NAN.to_bits();
+        let mut values: PollValues = [
+            ((ModbusRegisterKind::Holding, 0), 0x0000),
+            ((ModbusRegisterKind::Holding, 1), 1500),
+            ((ModbusRegisterKind::Holding, 2), (nan >> 16) as u16),
+            ((ModbusRegisterKind::Holding, 3), nan as u16),
+        ]
+        .into_iter()
+        .collect();
+        let ts = datetime!(2024-06-01 12:00 UTC);
+        let mut units = UnitTelemetry::default();
+
+        // The NaN MVAr is skipped and the unread availability left unset.
+        let records = map_poll(&device, &mut units, &values, ts);
+        assert_eq!(records.len(), 1);
+        assert_eq!(
+            (records[0].mw, records[0].mvar, records[0].availability_pct),
+            (1.5, None, None)
+        );
+
+        values.insert((ModbusRegisterKind::Input, 4), 98);
+        let records = map_poll(&device, &mut units, &values, ts);
+        assert_eq!(records[0].availability_pct, Some(98.0));
+    }
+
+    #[tokio::test]
+    async fn polls_a_device_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let device = tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 12];
+            socket.read_exact(&mut request).await.unwrap();
+            assert_eq!(&request[6..], [7, FC_READ_INPUT, 0, 30, 0, 3]);
+
+            // A stale response first, then registers 30-32: 4.2 MW as f32 and 0.8 MVAr in kVAr.
+            let mut stale = request[..7].to_vec();
+            stale[1] = stale[1].wrapping_sub(1);
+            stale[5] = 3;
+            stale.extend([FC_READ_INPUT | EXCEPTION, 4]);
+            socket.write_all(&stale).await.unwrap();
+            let mw = 4.2f32.to_bits();
+            let mut response = request[..7].to_vec();
+            response[5] = 9;
+            response.extend([FC_READ_INPUT, 6]);
+            response.extend(((mw >> 16) as u16).to_be_bytes());
+            response.extend((mw as u16).to_be_bytes());
+            response.extend(800u16.to_be_bytes());
+            socket.write_all(&response).await.unwrap();
+            socket
+        });
+
+        let mut kvar = register(32, ModbusRegisterKind::Input, ModbusDataType::U16, TelemetryField::Mvar);
+        kvar.scale = 0.001;
+        let cfg = ModbusSourceConfig {
+            poll_interval_secs: 60,
+            response_timeout_ms: 2_000,
+            devices: vec![ModbusDevice {
+                name: "agg-1".to_string(),
+                host: "127.0.0.1".to_string(),
+                port,
+                unit_identifier: 7,
+                word_order: ModbusWordOrder::Big,
+                registers: vec![
+                    register(30, ModbusRegisterKind::Input, ModbusDataType::F32, TelemetryField::Mw),
+                    kvar,
+                ],
+            }],
+        };
+        let source = ModbusSource::from_config(&cfg).unwrap();
+        let mut stream = source.stream().await;
+        let record = stream.next().await.unwrap().unwrap().payload;
+        assert_eq!(record.plant_id, "solar-agg-1");
+        assert!((record.mw - 4.2).abs() < 1e-6);
+        assert_eq!(record.mvar, Some(0.8));
+        device.await.unwrap();
+    }
+}
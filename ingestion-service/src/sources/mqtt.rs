@@ -0,0 +1,200 @@
+use std::{marker::PhantomData, pin::Pin, time::Duration};
+
+use futures::Stream;
+use rumqttc::{
+    AsyncClient, Event, EventLoop, MqttOptions, Packet, Publish, QoS, SubscribeFilter, TlsConfiguration, Transport,
+};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::{
+    config::{MqttSourceConfig, MqttTlsConfig},
+    pipeline::{AckOutcome, AckReceiver, Completion, Envelope, PipelineError, Source},
+    sources::json_record::{parse_payload, JsonRecord},
+};
+
+/// Pause before polling again after a connection error; the next poll reconnects.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The records of one received publish, settled together.
+struct PendingPublish {
+    publish: Publish,
+    acks: Vec<AckReceiver>,
+}
+
+/// Acknowledges every publish whose records were all written or rejected, in
+/// arrival order.
+///
+/// A publish with a lost record stays unacknowledged; the broker redelivers
+/// it when the session resumes (at-least-once for QoS 1 and 2).
+async fn ack_publishes(client: AsyncClient, mut pending: mpsc::UnboundedReceiver<PendingPublish>) {
+    while let Some(msg) = pending.recv().await {
+        let mut settled = true;
+        for ack in msg.acks {
+            if ack.outcome().await == AckOutcome::Dropped {
+                settled = false;
+            }
+        }
+        if !settled {
+            tracing::warn!(topic = %msg.publish.topic, pkid = msg.publish.pkid, "record was not written; leaving the message unacknowledged");
+            continue;
+        }
+        if let Err(e) = client.ack(&msg.publish).await {
+            tracing::warn!(error = %e, topic = %msg.publish.topic, "failed to acknowledge MQTT message");
+        }
+    }
+}
+
+fn read_pem(path: &str) -> Result<Vec<u8>, PipelineError> {
+    std::fs::read(path).map_err(|e| PipelineError::Source(format!("failed to read {path}: {e}")))
+}
+
+fn transport(tls: &MqttTlsConfig) -> Result<Transport, PipelineError> {
+    let client_auth = match (&tls.client_cert_file, &tls.client_key_file) {
+        (Some(cert), Some(key)) => Some((read_pem(cert)?, read_pem(key)?)),
+        (None, None) => None,
+        _ => {
+            return Err(PipelineError::Source(
+                "mqtt.tls needs both client_cert_file and client_key_file".to_string(),
+            ))
+        }
+    };
+    Ok(match (&tls.ca_file, client_auth) {
+        (None, None) => Transport::tls_with_default_config(),
+        (None, Some(_)) => {
+            return Err(PipelineError::Source(
+                "mqtt.tls client authentication needs ca_file".to_string(),
+            ))
+        }
+        (Some(ca), client_auth) => Transport::tls_with_config(TlsConfiguration::Simple {
+            ca: read_pem(ca)?,
+            alpn: None,
+            client_auth,
+        }),
+    })
+}
+
+/// Subscribes to MQTT topics and reads JSON records from their messages.
+///
+/// Messages hold one JSON object, NDJSON, or a JSON array of records in the
+/// HTTP source's payload format. Acknowledgements are manual: a QoS 1/2
+/// message is acknowledged only once each of its records was written by the
+/// sink or rejected by validation; records that fail to parse are skipped and
+/// counted. With a persistent session (`clean_session = false`) the broker
+/// redelivers unacknowledged messages after a reconnect.
+pub struct MqttSource<T> {
+    client: AsyncClient,
+    eventloop: Mutex<Option<EventLoop>>,
+    subscriptions: Vec<SubscribeFilter>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> MqttSource<T> {
+    pub fn from_config(cfg: &MqttSourceConfig) -> Result<Self, PipelineError> {
+        let qos = rumqttc::qos(cfg.qos).map_err(|_| PipelineError::Source(format!("invalid MQTT QoS {}", cfg.qos)))?;
+        if cfg.topics.is_empty() {
+            return Err(PipelineError::Source("mqtt.topics is empty".to_string()));
+        }
+
+        let mut options = MqttOptions::new(&cfg.client_id, &cfg.host, cfg.port);
+        options
+            .set_keep_alive(Duration::from_secs(cfg.keep_alive_secs))
+            .set_clean_session(cfg.clean_session)
+            .set_manual_acks(true);
+        if let Some(username) = &cfg.username {
+            options.set_credentials(username, cfg.password.clone().unwrap_or_default());
+        }
+        if let Some(tls) = &cfg.tls {
+            options.set_transport(transport(tls)?);
+        }
+
+        let (client, eventloop) = AsyncClient::new(options, 1024);
+        let subscriptions = cfg
+            .topics
+            .iter()
+            .map(|topic| SubscribeFilter::new(topic.clone(), qos))
+            .collect();
+        tracing::info!(host = %cfg.host, port = cfg.port, client_id = %cfg.client_id, topics = ?cfg.topics, ?qos, "MQTT source configured");
+
+        Ok(Self {
+            client,
+            eventloop: Mutex::new(Some(eventloop)),
+            subscriptions,
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Source<T> for MqttSource<T>
+where
+    T: JsonRecord + Send + 'static,
+{
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let Some(mut eventloop) = self.eventloop.lock().await.take() else {
+            return Box::pin(futures::stream::once(async {
+                Err(PipelineError::Source("MQTT source already streaming".to_string()))
+            }));
+        };
+        let client = self.client.clone();
+        let subscriptions = self.subscriptions.clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(ack_publishes(client.clone(), rx));
+
+        let s = async_stream::stream! {
+            loop {
+                let publish = match eventloop.poll().await {
+                    Ok(Event::Incoming(Packet::ConnAck(ack))) => {
+                        tracing::info!(session_present = ack.session_present, "MQTT connected");
+                        if !ack.session_present {
+                            if let Err(e) = client.try_subscribe_many(subscriptions.clone()) {
+                                yield Err(PipelineError::Source(format!("MQTT subscribe failed: {e}")));
+                            }
+                        }
+                        continue;
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => publish,
+                    Ok(_) => continue,
+                    Err(e) => {
+                        metrics::counter!("mqtt_source_errors_total").increment(1);
+                        yield Err(PipelineError::Source(format!("MQTT connection failed: {e}")));
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                };
+                let topic = publish.topic.clone();
+                metrics::counter!("mqtt_source_messages_total", "topic" => topic.clone()).increment(1);
+
+                let mut envelopes = Vec::new();
+                let mut acks = Vec::new();
+                let mut errors = Vec::new();
+                match std::str::from_utf8(&publish.payload) {
+                    Ok(payload) => {
+                        for record in parse_payload::<T>(payload) {
+                            match record {
+                                Ok(record) => {
+                                    let (completion, ack) = Completion::oneshot();
+                                    envelopes.push(Envelope::tracked(record, completion));
+                                    acks.push(ack);
+                                }
+                                Err(e) => errors.push(e),
+                            }
+                        }
+                    }
+                    Err(e) => errors.push(format!("payload is not UTF-8: {e}")),
+                }
+                if publish.qos != QoS::AtMostOnce {
+                    let _ = tx.send(PendingPublish { publish, acks });
+                }
+
+                for e in errors {
+                    metrics::counter!("mqtt_source_parse_errors_total", "topic" => topic.clone()).increment(1);
+                    yield Err(PipelineError::Source(format!("invalid record on {topic}: {e}")));
+                }
+                for env in envelopes {
+                    yield Ok(env);
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
@@ -0,0 +1,340 @@
+use std::{
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    time::SystemTime,
+};
+
+use futures::Stream;
+use rust_client::domain::{MeterUsage, PhaseChannels};
+use time::{macros::format_description, Duration, PrimitiveDateTime, UtcOffset};
+
+use crate::{
+    config::{Mv90Config, Mv90Field},
+    pipeline::{Envelope, PipelineError, Source},
+    sources::compressed_file,
+};
+
+/// `source_system` of records loaded from MV-90 files.
+const SOURCE_SYSTEM: &str = "mv90";
+
+/// The recorder section being read.
+#[derive(Debug)]
+struct Section {
+    recorder_id: String,
+    meter_id: String,
+    interval: Duration,
+    channels: usize,
+    records: usize,
+}
+
+/// Line-by-line parser of MV-90 HHF interval files.
+///
+/// A file holds one section per recorder:
+///
+/// ```text
+/// HDR,<recorder_id>,<meter_id>,<interval_minutes>,<channel_count>
+/// DAT,<YYYYMMDDhhmm>,<value>,<status>[,<value>,<status>...]
+/// TRL,<dat_record_count>
+/// ```
+///
+/// `DAT` timestamps are interval ends in the recorder's standard time; records
+/// get `ts` at the interval start, in UTC. Each `DAT` record carries a value
+/// and a status per channel; a blank or `0` status is a valid reading, any
+/// other status is kept as `quality_flag`. A section must end with a `TRL`
+/// record whose count matches, so a cut-off file fails instead of loading part
+/// of a recorder.
+pub struct HhfParser {
+    config: Mv90Config,
+    offset: UtcOffset,
+    section: Option<Section>,
+}
+
+impl HhfParser {
+    pub fn new(config: &Mv90Config) -> Result<Self, String> {
+        let kwh = config.channels.iter().filter(|c| c.field == Mv90Field::Kwh).count();
+        if kwh != 1 {
+            return Err(format!("exactly one MV-90 channel must map to kwh, found {kwh}"));
+        }
+        if let Some(c) = config.channels.iter().find(|c| c.channel == 0) {
+            return Err(format!("MV-90 channels are numbered from 1, got {}", c.channel));
+        }
+        let offset = UtcOffset::from_whole_seconds(config.utc_offset_minutes * 60)
+            .map_err(|e| format!("invalid utc_offset_minutes {}: {e}", config.utc_offset_minutes))?;
+        Ok(Self {
+            config: config.clone(),
+            offset,
+            section: None,
+        })
+    }
+
+    /// Parse one line; `DAT` records with a kWh reading yield a record.
+    pub fn line(&mut self, line: &str) -> Result<Option<MeterUsage>, String> {
+        let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split(',').map(str::trim).collect();
+        match fields[0] {
+            "" if fields.len() == 1 => Ok(None),
+            "HDR" => {
+                if let Some(open) = &self.section {
+                    return Err(format!("recorder {} has no TRL record", open.recorder_id));
+                }
+                self.section = Some(self.header(&fields)?);
+                Ok(None)
+            }
+            "DAT" => self.data(&fields),
+            "TRL" => {
+                let section = self.section.take().ok_or("TRL record outside a recorder section")?;
+                let count: usize = fields
+                    .get(1)
+                    .and_then(|c| c.parse().ok())
+                    .ok_or("TRL record without a record count")?;
+                if count != section.records {
+                    return Err(format!(
+                        "recorder {}: TRL counts {count} DAT records, the file has {}",
+                        section.recorder_id, section.records
+                    ));
+                }
+                Ok(None)
+            }
+            other => Err(format!("unknown record type '{other}'")),
+        }
+    }
+
+    /// Check the file ended after a complete section.
+    pub fn finish(self) -> Result<(), String> {
+        match self.section {
+            Some(open) => Err(format!(
+                "file ends inside recorder {} (no TRL record)",
+                open.recorder_id
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn header(&self, fields: &[&str]) -> Result<Section, String> {
+        let [_, recorder_id, meter_id, interval_minutes, channels] = fields else {
+            return Err(format!("HDR record has {} fields, expected 5", fields.len()));
+        };
+        let interval_minutes: i64 = interval_minutes
+            .parse()
+            .ok()
+            .filter(|m| *m > 0)
+            .ok_or_else(|| format!("invalid interval length '{interval_minutes}'"))?;
+        let channels: usize = channels
+            .parse()
+            .map_err(|e| format!("invalid channel count '{channels}': {e}"))?;
+        if let Some(c) = self.config.channels.iter().find(|c| c.channel > channels) {
+            return Err(format!(
+                "recorder {recorder_id} has {channels} channels; channel {} is mapped",
+                c.channel
+            ));
+        }
+        Ok(Section {
+            recorder_id: recorder_id.to_string(),
+            meter_id: if meter_id.is_empty() { recorder_id } else { meter_id }.to_string(),
+            interval: Duration::minutes(interval_minutes),
+            channels,
+            records: 0,
+        })
+    }
+
+    fn data(&mut self, fields: &[&str]) -> Result<Option<MeterUsage>, String> {
+        let section = self.section.as_mut().ok_or("DAT record outside a recorder section")?;
+        section.records += 1;
+        if fields.len() != 2 + 2 * section.channels {
+            return Err(format!(
+                "DAT record has {} fields, expected {} for {} channels",
+                fields.len(),
+                2 + 2 * section.channels,
+                section.channels
+            ));
+        }
+        let end = PrimitiveDateTime::parse(fields[1], format_description!("[year][month][day][hour][minute]"))
+            .map_err(|e| format!("invalid interval time '{}': {e}", fields[1]))?
+            .assume_offset(self.offset);
+        let hours = section.interval.as_seconds_f64() / 3600.0;
+
+        let mut usage = MeterUsage {
+            ts: (end - section.interval).to_offset(UtcOffset::UTC),
+            meter_id: section.meter_id.clone(),
+            premise_id: None,
+            kwh: 0.0,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some(SOURCE_SYSTEM.to_string()),
+            event_id: None,
+            phases: PhaseChannels::default(),
+        };
+        let mut has_kwh = false;
+        // The kWh channel's status is the one kept when several are flagged.
+        let mut mapped: Vec<_> = self.config.channels.iter().collect();
+        mapped.sort_by_key(|c| c.field != Mv90Field::Kwh);
+        for channel in mapped {
+            let raw = fields[2 * channel.channel];
+            let status = fields[2 * channel.channel + 1];
+            if raw.is_empty() {
+                continue;
+            }
+            let value = raw
+                .parse::<f64>()
+                .map_err(|e| format!("invalid value '{raw}' on channel {}: {e}", channel.channel))?
+                * channel.multiplier;
+            match channel.field {
+                Mv90Field::Kwh => {
+                    usage.kwh = value;
+                    has_kwh = true;
+                }
+                Mv90Field::Kvarh => usage.kvarh = Some(value),
+                Mv90Field::KvaDemand => usage.kva_demand = Some(value),
+                Mv90Field::Kvah => usage.kva_demand = Some(value / hours),
+            }
+            if usage.quality_flag.is_none() && !status.is_empty() && status != "0" {
+                usage.quality_flag = Some(status.to_string());
+            }
+        }
+        Ok(has_kwh.then_some(usage))
+    }
+}
+
+/// MV-90 HHF interval file source for `MeterUsage` (see [`HhfParser`]).
+///
+/// Channels are mapped with `[meter_usage.mv90]`. Intervals without a kWh
+/// value are skipped and counted in `mv90_missing_intervals_total`. Gzip and
+/// zstd files are decompressed while reading.
+pub struct Mv90FileSource {
+    path: PathBuf,
+    config: Mv90Config,
+}
+
+impl Mv90FileSource {
+    pub fn new<P: Into<PathBuf>>(path: P, config: &Mv90Config) -> Self {
+        Self {
+            path: path.into(),
+            config: config.clone(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for Mv90FileSource {
+    async fn stream(
+        &self,
+    ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let path = self.path.clone();
+        let config = self.config.clone();
+        let s = async_stream::try_stream! {
+            let mut parser = HhfParser::new(&config).map_err(PipelineError::Source)?;
+            let file = compressed_file::open(&path)
+                .map_err(|e| PipelineError::Source(format!("failed to open MV-90 file: {e}")))?;
+
+            for (idx, line) in BufReader::new(file).lines().enumerate() {
+                let line = line.map_err(|e| PipelineError::Source(format!("failed to read MV-90 file: {e}")))?;
+                let usage = match parser.line(&line) {
+                    Ok(Some(u)) => u,
+                    Ok(None) => {
+                        if line.starts_with("DAT") {
+                            metrics::counter!("mv90_missing_intervals_total").increment(1);
+                        }
+                        continue;
+                    }
+                    Err(e) => {
+                        metrics::counter!("mv90_parse_errors_total").increment(1);
+                        Err(PipelineError::Source(format!("{}: line {}: {e}", path.display(), idx + 1)))?
+                    }
+                };
+
+                yield Envelope {
+                    payload: usage,
+                    received_at: SystemTime::now(),
+                    completion: None,
+                    tenant: None,
+                    source_identity: None,
+                };
+            }
+            parser
+                .finish()
+                .map_err(|e| PipelineError::Source(format!("{}: {e}", path.display())))?;
+        };
+
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Mv90Channel;
+    use time::macros::datetime;
+
+    fn parse(config: &Mv90Config, text: &str) -> Result<Vec<MeterUsage>, String> {
+        let mut parser = HhfParser::new(config)?;
+        let mut out = Vec::new();
+        for line in text.lines() {
+            out.extend(parser.line(line)?);
+        }
+        parser.finish()?;
+        Ok(out)
+    }
+
+    #[test]
+    fn maps_channels_with_multipliers_and_status() {
+        let config = Mv90Config {
+            channels: vec![
+                Mv90Channel {
+                    channel: 2,
+                    field: Mv90Field::Kwh,
+                    multiplier: 0.5,
+                },
+                Mv90Channel {
+                    channel: 3,
+                    field: Mv90Field::Kvah,
+                    multiplier: 1.0,
+                },
+            ],
+            utc_offset_minutes: -300,
+        };
+        let text = "HDR,R-100,ci-7,15,3\n\
+                    DAT,202406010015,9,0,10,,3,\n\
+                    DAT,202406010030,9,,,E,3,\n\
+                    DAT,202406010045,9,,12,,4,P\n\
+                    TRL,3\n\
+                    HDR,R-200,,60,3\n\
+                    DAT,202406010100,0,,4,,1,\n\
+                    TRL,1\n";
+        let records = parse(&config, text).unwrap();
+
+        // The interval with no kWh value is skipped.
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].ts, datetime!(2024-06-01 05:00 UTC));
+        assert_eq!((records[0].meter_id.as_str(), records[0].kwh), ("ci-7", 5.0));
+        assert_eq!(
+            (records[0].kva_demand, records[0].quality_flag.as_deref()),
+            (Some(12.0), None)
+        );
+        assert_eq!(records[1].quality_flag.as_deref(), Some("P"));
+        assert_eq!(records[1].source_system.as_deref(), Some("mv90"));
+
+        // A blank meter id falls back to the recorder id.
+        assert_eq!(records[2].meter_id, "R-200");
+        assert_eq!(records[2].ts, datetime!(2024-06-01 05:00 UTC));
+        assert_eq!(records[2].kva_demand, Some(1.0));
+    }
+
+    #[test]
+    fn rejects_cut_off_and_malformed_files() {
+        let config = Mv90Config::default();
+        let err = parse(&config, "HDR,R-1,m-1,15,2\nDAT,202406010015,1,,0,\n").unwrap_err();
+        assert!(err.contains("no TRL"), "{err}");
+        let err = parse(&config, "HDR,R-1,m-1,15,2\nDAT,202406010015,1,,0,\nTRL,2\n").unwrap_err();
+        assert!(err.contains("TRL counts 2"), "{err}");
+        let err = parse(&config, "HDR,R-1,m-1,15,2\nDAT,202406010015,1,\nTRL,1\n").unwrap_err();
+        assert!(err.contains("expected 6"), "{err}");
+        let err = parse(&config, "HDR,R-1,m-1,15,1\n").unwrap_err();
+        assert!(err.contains("channel 2 is mapped"), "{err}");
+
+        let no_kwh = Mv90Config {
+            channels: Vec::new(),
+            ..Mv90Config::default()
+        };
+        assert!(HhfParser::new(&no_kwh).is_err());
+    }
+}
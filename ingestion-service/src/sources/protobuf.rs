@@ -0,0 +1,145 @@
+use axum::http::{header::CONTENT_TYPE, HeaderMap, StatusCode};
+use prost::Message;
+use rust_client::domain::{GenerationOutput, MeterUsage, PhaseChannels};
+use time::OffsetDateTime;
+
+use crate::sources::http_error::{ApiError, Converted, FieldError};
+
+/// Types generated from `proto/ingest.proto`.
+pub mod proto {
+    include!(concat!(env!("OUT_DIR"), "/utility.ingest.v1.rs"));
+}
+
+use proto::{GenerationOutputRecord, MeterUsageRecord};
+
+/// Content type selecting a protobuf batch body on the HTTP ingest endpoints.
+pub const CONTENT_TYPE_PROTOBUF: &str = "application/x-protobuf";
+
+/// Whether the request body is a protobuf batch rather than JSON.
+pub(crate) fn is_protobuf(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(';').next())
+        .is_some_and(|essence| essence.trim().eq_ignore_ascii_case(CONTENT_TYPE_PROTOBUF))
+}
+
+fn ts_from_micros(ts_micros: i64) -> Result<OffsetDateTime, FieldError> {
+    OffsetDateTime::from_unix_timestamp_nanos(i128::from(ts_micros) * 1_000)
+        .map_err(|e| FieldError::field("ts_micros", e.to_string()))
+}
+
+fn required(field: &str, value: String) -> Result<String, FieldError> {
+    if value.trim().is_empty() {
+        return Err(FieldError::field(field, "must not be empty"));
+    }
+    Ok(value)
+}
+
+pub(crate) fn meter_usage_from_record(m: MeterUsageRecord) -> Result<MeterUsage, FieldError> {
+    Ok(MeterUsage {
+        ts: ts_from_micros(m.ts_micros)?,
+        meter_id: required("meter_id", m.meter_id)?,
+        premise_id: m.premise_id,
+        kwh: m.kwh,
+        kvarh: m.kvarh,
+        kva_demand: m.kva_demand,
+        quality_flag: m.quality_flag,
+        source_system: m.source_system,
+        event_id: m.event_id,
+        phases: PhaseChannels {
+            kwh_phase_a: m.kwh_phase_a,
+            kwh_phase_b: m.kwh_phase_b,
+            kwh_phase_c: m.kwh_phase_c,
+            current_phase_a: m.current_phase_a,
+            current_phase_b: m.current_phase_b,
+            current_phase_c: m.current_phase_c,
+            voltage_phase_a: m.voltage_phase_a,
+            voltage_phase_b: m.voltage_phase_b,
+            voltage_phase_c: m.voltage_phase_c,
+        },
+    })
+}
+
+pub(crate) fn generation_output_from_record(m: GenerationOutputRecord) -> Result<GenerationOutput, FieldError> {
+    Ok(GenerationOutput {
+        ts: ts_from_micros(m.ts_micros)?,
+        plant_id: required("plant_id", m.plant_id)?,
+        unit_id: m.unit_id,
+        mw: m.mw,
+        mvar: m.mvar,
+        status: m.status,
+        fuel_type: m.fuel_type,
+        event_id: m.event_id,
+        aux_mw: m.aux_mw,
+        availability_pct: m.availability_pct,
+        curtailed_mw: m.curtailed_mw,
+    })
+}
+
+/// Decode a batch message body (400 when it is not valid protobuf).
+pub(crate) fn decode_batch<M: Message + Default>(body: &[u8]) -> Result<M, ApiError> {
+    M::decode(body).map_err(|e| ApiError::new(StatusCode::BAD_REQUEST, format!("invalid protobuf body: {e}")))
+}
+
+/// Convert every record of a batch, like `http_error::convert_each` for JSON
+/// arrays: errors carry the record's position in the batch.
+pub(crate) fn convert_messages<M, T>(messages: Vec<M>, convert: impl Fn(M) -> Result<T, FieldError>) -> Converted<T> {
+    let mut converted = Converted::with_capacity(messages.len());
+    for (index, message) in messages.into_iter().enumerate() {
+        converted.push(index, convert(message));
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proto::MeterUsageBatch;
+
+    #[test]
+    fn selects_protobuf_by_content_type() {
+        let headers = |ct: &str| HeaderMap::from_iter([(CONTENT_TYPE, ct.parse().unwrap())]);
+        assert!(is_protobuf(&headers("application/x-protobuf")));
+        assert!(is_protobuf(&headers(
+            "Application/X-Protobuf; messageType=MeterUsageBatch"
+        )));
+        assert!(!is_protobuf(&headers("application/json")));
+        assert!(!is_protobuf(&HeaderMap::new()));
+    }
+
+    #[test]
+    fn decodes_batches_and_reports_bad_records_by_index() {
+        let batch = MeterUsageBatch {
+            records: vec![
+                MeterUsageRecord {
+                    ts_micros: 1_717_200_000_000_000,
+                    meter_id: "m-1".to_string(),
+                    kwh: 1.5,
+                    kwh_phase_b: Some(0.5),
+                    ..Default::default()
+                },
+                MeterUsageRecord {
+                    ts_micros: 1_717_200_900_000_000,
+                    ..Default::default()
+                },
+            ],
+        };
+        let decoded: MeterUsageBatch = decode_batch(&batch.encode_to_vec()).unwrap();
+        assert_eq!(decoded, batch);
+
+        let err = convert_messages(decoded.records.clone(), meter_usage_from_record).all().unwrap_err();
+        assert_eq!(err.details.len(), 1);
+        assert_eq!(
+            (err.details[0].index, err.details[0].field.as_deref()),
+            (Some(1), Some("meter_id"))
+        );
+
+        let usage = convert_messages(decoded.records[..1].to_vec(), meter_usage_from_record).all().unwrap();
+        assert_eq!(usage[0].ts.unix_timestamp(), 1_717_200_000);
+        assert_eq!(usage[0].phases.kwh_phase_b, Some(0.5));
+
+        let err = decode_batch::<MeterUsageBatch>(b"\xff\xff\xff").unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,386 @@
+use std::{future::Future, marker::PhantomData, sync::Arc, time::{Duration, SystemTime}};
+
+use futures::Stream;
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use sqlx::{
+    postgres::{PgPool, PgRow},
+    FromRow, Row,
+};
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::EventIdStrategy,
+    pipeline::{CompletionGroup, Envelope, PipelineError, Source},
+};
+
+/// `event_id` strategy of the replication sink: the id read from the primary
+/// is written unchanged, whatever strategy produced it, so rows copied again
+/// after an overlapping restart stay dedupable on the target.
+pub const REPLICATED_EVENT_IDS: EventIdStrategy = EventIdStrategy::Client;
+
+/// A table that can be incrementally copied between QuestDB instances.
+///
+/// `COLUMNS` must select exactly the fields decoded by the `FromRow` impl,
+/// plus `tenant` for tables that have one.
+pub trait ReplicatedTable: for<'r> FromRow<'r, PgRow> + Send + Unpin + 'static {
+    const TABLE: &'static str;
+    const COLUMNS: &'static str;
+
+    fn ts(&self) -> OffsetDateTime;
+
+    /// Tenant of a row, copied to its envelope so the target keeps it.
+    fn tenant(row: &PgRow) -> Result<Option<Arc<str>>, sqlx::Error> {
+        let _ = row;
+        Ok(None)
+    }
+}
+
+impl ReplicatedTable for MeterUsage {
+    const TABLE: &'static str = "meter_usage";
+    const COLUMNS: &'static str =
+        "ts, meter_id, premise_id, kwh, kvarh, kva_demand, quality_flag, source_system, tenant, event_id, \
+         kwh_phase_a, kwh_phase_b, kwh_phase_c, current_phase_a, current_phase_b, current_phase_c, \
+         voltage_phase_a, voltage_phase_b, voltage_phase_c";
+
+    fn ts(&self) -> OffsetDateTime {
+        self.ts
+    }
+
+    fn tenant(row: &PgRow) -> Result<Option<Arc<str>>, sqlx::Error> {
+        Ok(row.try_get::<Option<&str>, _>("tenant")?.map(Arc::from))
+    }
+}
+
+impl ReplicatedTable for GenerationOutput {
+    const TABLE: &'static str = "generation_output";
+    const COLUMNS: &'static str =
+        "ts, plant_id, unit_id, mw, mvar, status, fuel_type, event_id, aux_mw, availability_pct, curtailed_mw";
+
+    fn ts(&self) -> OffsetDateTime {
+        self.ts
+    }
+}
+
+/// Polling source that reads new rows of a table from a primary QuestDB.
+///
+/// Rows are read in `(watermark, upper]` time windows, where `upper` never
+/// gets closer to "now" than `max_lateness`. Windows are used instead of a
+/// `LIMIT` so rows sharing a timestamp are never split across polls.
+///
+/// The watermark starts at `start_after` (usually the target's
+/// [`committed_watermark`]). If there is none, replication starts from the
+/// oldest row on the primary.
+pub struct QuestDbReplicationSource<T> {
+    pool: PgPool,
+    start_after: Option<OffsetDateTime>,
+    window: Duration,
+    max_lateness: Duration,
+    poll_interval: Duration,
+    commit_to: Option<PgPool>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> QuestDbReplicationSource<T> {
+    pub fn new(
+        pool: PgPool,
+        start_after: Option<OffsetDateTime>,
+        window: Duration,
+        max_lateness: Duration,
+        poll_interval: Duration,
+    ) -> Self {
+        Self {
+            pool,
+            start_after,
+            window,
+            max_lateness,
+            poll_interval,
+            commit_to: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Record the committed watermark in `replication_watermarks` on `target`.
+    ///
+    /// Rows are tracked per window, and a window's upper bound is recorded
+    /// once every row of it and of the windows before it was written. Sinks
+    /// flush shards independently, so the target's `max(ts)` can be ahead of
+    /// rows still pending on other shards; this watermark can't.
+    pub fn with_committed_watermarks(mut self, target: PgPool) -> Self {
+        self.commit_to = Some(target);
+        self
+    }
+}
+
+/// Read the committed watermark of a replicated table on the target, to resume from.
+pub async fn committed_watermark<T: ReplicatedTable>(
+    target: &PgPool,
+) -> Result<Option<OffsetDateTime>, PipelineError> {
+    sqlx::query_scalar::<_, OffsetDateTime>(
+        "SELECT watermark FROM replication_watermarks WHERE table_name = $1 ORDER BY ts DESC LIMIT 1",
+    )
+    .bind(T::TABLE)
+    .fetch_optional(target)
+    .await
+    .map_err(|e| PipelineError::Source(format!("failed to read watermark for {}: {e}", T::TABLE)))
+}
+
+async fn store_watermark(target: &PgPool, table: &str, watermark: OffsetDateTime) -> Result<(), sqlx::Error> {
+    sqlx::query("INSERT INTO replication_watermarks (ts, table_name, watermark) VALUES ($1, $2, $3)")
+        .bind(OffsetDateTime::now_utc())
+        .bind(table)
+        .bind(watermark)
+        .execute(target)
+        .await?;
+    Ok(())
+}
+
+/// Commit the upper bound of each window, in window order, once all of its
+/// rows were written. Fails at a window that lost rows, so the watermark
+/// never passes them.
+async fn commit_in_order<F, Fut>(
+    mut windows: mpsc::UnboundedReceiver<(OffsetDateTime, CompletionGroup)>,
+    mut commit: F,
+) -> Result<(), PipelineError>
+where
+    F: FnMut(OffsetDateTime) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    while let Some((to, rows)) = windows.recv().await {
+        if !rows.wait().await {
+            return Err(PipelineError::Source(format!(
+                "replicated rows of the window ending {to} were lost, not advancing the watermark"
+            )));
+        }
+        commit(to).await;
+    }
+    Ok(())
+}
+
+async fn oldest_ts<T: ReplicatedTable>(pool: &PgPool) -> Result<Option<OffsetDateTime>, PipelineError> {
+    let sql = format!("SELECT min(ts) FROM {}", T::TABLE);
+    sqlx::query_scalar::<_, Option<OffsetDateTime>>(&sql)
+        .fetch_one(pool)
+        .await
+        .map_err(|e| PipelineError::Source(format!("failed to read oldest ts for {}: {e}", T::TABLE)))
+}
+
+/// Compute the next `(from, to]` window, or `None` if the watermark is caught up.
+fn next_window(
+    watermark: OffsetDateTime,
+    now: OffsetDateTime,
+    window: Duration,
+    max_lateness: Duration,
+) -> Option<(OffsetDateTime, OffsetDateTime)> {
+    let horizon = now - max_lateness;
+    if watermark >= horizon {
+        return None;
+    }
+    let to = (watermark + window).min(horizon);
+    Some((watermark, to))
+}
+
+#[async_trait::async_trait]
+impl<T> Source<T> for QuestDbReplicationSource<T>
+where
+    T: ReplicatedTable,
+{
+    async fn stream(&self) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let pool = self.pool.clone();
+        let start_after = self.start_after;
+        let window = self.window;
+        let max_lateness = self.max_lateness;
+        let poll_interval = self.poll_interval;
+
+        let mut committer = None;
+        let commits = self.commit_to.clone().map(|target| {
+            let (tx, rx) = mpsc::unbounded_channel();
+            committer = Some(tokio::spawn(commit_in_order(rx, move |to| {
+                let target = target.clone();
+                async move {
+                    match store_watermark(&target, T::TABLE, to).await {
+                        Ok(()) => metrics::gauge!("replication_watermark_seconds", "table" => T::TABLE)
+                            .set(to.unix_timestamp() as f64),
+                        Err(e) => tracing::warn!(table = T::TABLE, error = %e, "failed to store replication watermark"),
+                    }
+                }
+            })));
+            tx
+        });
+
+        let s = async_stream::try_stream! {
+            let select_sql = format!(
+                "SELECT {} FROM {} WHERE ts > $1 AND ts <= $2 ORDER BY ts",
+                T::COLUMNS,
+                T::TABLE
+            );
+
+            let mut watermark = start_after;
+
+            loop {
+                // A window lost rows: end the stream so the job restarts from the committed watermark
+                // instead of copying on while the watermark stays behind.
+                if let Some(committer) = committer.take_if(|c| c.is_finished()) {
+                    committer
+                        .await
+                        .map_err(|e| PipelineError::Source(format!("{} watermark committer failed: {e}", T::TABLE)))??;
+                }
+
+                let Some(current) = watermark else {
+                    // Target is empty: start just before the oldest row on the primary.
+                    match oldest_ts::<T>(&pool).await? {
+                        Some(oldest) => watermark = Some(oldest - time::Duration::nanoseconds(1)),
+                        None => tokio::time::sleep(poll_interval).await,
+                    }
+                    continue;
+                };
+
+                let now = OffsetDateTime::now_utc();
+                metrics::gauge!("replication_lag_seconds", "table" => T::TABLE)
+                    .set((now - current).as_seconds_f64().max(0.0));
+
+                let Some((from, to)) = next_window(current, now, window, max_lateness) else {
+                    tokio::time::sleep(poll_interval).await;
+                    continue;
+                };
+
+                let read_err =
+                    |e: sqlx::Error| PipelineError::Source(format!("failed to read {} window: {e}", T::TABLE));
+                let rows = sqlx::query(&select_sql)
+                    .bind(from)
+                    .bind(to)
+                    .fetch_all(&pool)
+                    .await
+                    .map_err(read_err)?;
+
+                metrics::counter!("replication_rows_read_total", "table" => T::TABLE).increment(rows.len() as u64);
+
+                let group = commits.as_ref().map(|_| CompletionGroup::new());
+                for row in rows {
+                    yield Envelope {
+                        payload: T::from_row(&row).map_err(read_err)?,
+                        received_at: SystemTime::now(),
+                        completion: group.as_ref().map(CompletionGroup::track),
+                        tenant: T::tenant(&row).map_err(read_err)?,
+                        source_identity: None,
+                    };
+                }
+
+                watermark = Some(to);
+                if let (Some(commits), Some(group)) = (&commits, group) {
+                    let _ = commits.send((to, group));
+                }
+            }
+        };
+
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use time::macros::datetime;
+
+    #[tokio::test]
+    async fn commits_windows_in_order_once_all_their_rows_were_written() {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let log = committed.clone();
+        let committer = tokio::spawn(commit_in_order(rx, move |to| {
+            log.lock().unwrap().push(to);
+            async {}
+        }));
+
+        let (t1, t2, t3) = (
+            datetime!(2024-01-01 01:00 UTC),
+            datetime!(2024-01-01 02:00 UTC),
+            datetime!(2024-01-01 03:00 UTC),
+        );
+        let windows: Vec<_> = (0..3).map(|_| CompletionGroup::new()).collect();
+        let first = windows[0].track();
+        let second = windows[1].track();
+        let third = windows[2].track();
+        for (to, group) in [t1, t2, t3].into_iter().zip(windows) {
+            tx.send((to, group)).unwrap();
+        }
+
+        // Another shard flushed the second window first.
+        second.complete();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(committed.lock().unwrap().is_empty());
+
+        first.complete();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(*committed.lock().unwrap(), [t1, t2]);
+
+        // A lost row stops the committer before its window, with an error for the source.
+        drop(third);
+        let err = committer.await.unwrap().unwrap_err();
+        assert!(err.to_string().contains("were lost"), "{err}");
+        assert_eq!(*committed.lock().unwrap(), [t1, t2]);
+        drop(tx);
+    }
+
+    #[test]
+    fn rows_keep_the_event_id_of_the_primary() {
+        use crate::sinks::questdb_ilp::{IlpBuffer, IlpEncode, IlpProtocolVersion};
+
+        let line_of = |row: &dyn IlpEncode| {
+            let mut out = IlpBuffer::new(IlpProtocolVersion::V1).with_event_id(REPLICATED_EVENT_IDS);
+            row.write_ilp_line_received(SystemTime::now(), None, &mut out);
+            String::from_utf8(out.into_bytes()).unwrap()
+        };
+
+        // Written on the primary with `uuid_v7`, which the target can't recompute.
+        let usage = MeterUsage {
+            ts: datetime!(2024-01-01 00:15 UTC),
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh: 1.25,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: None,
+            event_id: Some("0190a5b2-7c4e-7def-8a12-3456789abcde".to_string()),
+            phases: Default::default(),
+        };
+        assert!(line_of(&usage).contains("event_id=0190a5b2-7c4e-7def-8a12-3456789abcde"));
+
+        let output = GenerationOutput {
+            ts: datetime!(2024-01-01 00:15 UTC),
+            plant_id: "plant-1".to_string(),
+            unit_id: None,
+            mw: 120.0,
+            mvar: None,
+            status: None,
+            fuel_type: None,
+            event_id: Some("gen-42".to_string()),
+            aux_mw: None,
+            availability_pct: None,
+            curtailed_mw: None,
+        };
+        assert!(line_of(&output).contains("event_id=gen-42"));
+    }
+
+    #[test]
+    fn next_window_is_capped_by_window_size() {
+        let wm = datetime!(2024-01-01 00:00:00 UTC);
+        let now = datetime!(2024-01-02 00:00:00 UTC);
+        let (from, to) = next_window(wm, now, Duration::from_secs(3600), Duration::from_secs(60)).unwrap();
+        assert_eq!(from, wm);
+        assert_eq!(to, datetime!(2024-01-01 01:00:00 UTC));
+    }
+
+    #[test]
+    fn next_window_stops_at_lateness_horizon() {
+        let wm = datetime!(2024-01-01 00:00:00 UTC);
+        let now = datetime!(2024-01-01 00:10:00 UTC);
+        let (_, to) = next_window(wm, now, Duration::from_secs(3600), Duration::from_secs(60)).unwrap();
+        assert_eq!(to, datetime!(2024-01-01 00:09:00 UTC));
+
+        let caught_up = datetime!(2024-01-01 00:09:30 UTC);
+        assert!(next_window(caught_up, now, Duration::from_secs(3600), Duration::from_secs(60)).is_none());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{marker::PhantomData, pin::Pin, time::Duration};
+
+use futures::Stream;
+use redis::{
+    aio::MultiplexedConnection,
+    streams::{StreamId, StreamReadOptions, StreamReadReply},
+    AsyncCommands, Client, RedisResult, Value,
+};
+use tokio::sync::mpsc;
+
+use crate::{
+    config::RedisStreamSourceConfig,
+    pipeline::{AckOutcome, AckReceiver, Completion, Envelope, PipelineError, Source},
+    sources::json_record::{parse_payload, JsonRecord},
+};
+
+/// Pause before reconnecting after a Redis error.
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// The records of one stream entry, settled together.
+struct PendingEntry {
+    id: String,
+    acks: Vec<AckReceiver>,
+}
+
+/// The JSON text in `field` of a stream entry.
+fn entry_payload(entry: &StreamId, field: &str) -> Result<String, String> {
+    match entry.map.get(field) {
+        Some(Value::BulkString(bytes)) => {
+            String::from_utf8(bytes.clone()).map_err(|e| format!("field {field} is not UTF-8: {e}"))
+        }
+        Some(Value::SimpleString(s)) => Ok(s.clone()),
+        Some(other) => Err(format!("field {field} is not a string: {other:?}")),
+        None => Err(format!("entry has no field {field}")),
+    }
+}
+
+/// Creates the consumer group (and the stream) unless it already exists.
+async fn create_group(conn: &mut MultiplexedConnection, cfg: &RedisStreamSourceConfig) -> RedisResult<()> {
+    let created: RedisResult<()> = conn
+        .xgroup_create_mkstream(&cfg.stream, &cfg.group, &cfg.start_id)
+        .await;
+    match created {
+        Err(e) if e.code() == Some("BUSYGROUP") => Ok(()),
+        other => other,
+    }
+}
+
+/// Acknowledges (XACK) every entry whose records were all written or rejected,
+/// in read order, on a connection of its own so acks don't queue behind a
+/// blocking read.
+///
+/// An entry with a lost record stays in the consumer's pending list and is
+/// read again when the source restarts.
+async fn ack_entries(
+    client: Client,
+    stream: String,
+    group: String,
+    mut pending: mpsc::UnboundedReceiver<PendingEntry>,
+) {
+    let mut conn: Option<MultiplexedConnection> = None;
+    while let Some(entry) = pending.recv().await {
+        let mut settled = true;
+        for ack in entry.acks {
+            if ack.outcome().await == AckOutcome::Dropped {
+                settled = false;
+            }
+        }
+        if !settled {
+            tracing::warn!(stream = %stream, id = %entry.id, "record was not written; leaving the entry pending");
+            continue;
+        }
+        let acked = match &mut conn {
+            Some(conn) => conn.xack::<_, _, _, ()>(&stream, &group, &[&entry.id]).await,
+            None => match client.get_multiplexed_async_connection().await {
+                Ok(mut c) => {
+                    let acked = c.xack::<_, _, _, ()>(&stream, &group, &[&entry.id]).await;
+                    conn = Some(c);
+                    acked
+                }
+                Err(e) => Err(e),
+            },
+        };
+        if let Err(e) = acked {
+            metrics::counter!("redis_source_errors_total").increment(1);
+            tracing::warn!(error = %e, stream = %stream, id = %entry.id, "failed to acknowledge Redis stream entry");
+            conn = None;
+        }
+    }
+}
+
+/// Reads JSON records from a Redis stream through a consumer group.
+///
+/// Each entry holds one JSON object, NDJSON, or a JSON array of records in the
+/// HTTP source's payload format, in the configured field. An entry is
+/// acknowledged only once each of its records was written by the sink or
+/// rejected by validation; entries that fail to parse are skipped, counted and
+/// acknowledged. On start the consumer first re-reads its own pending entries,
+/// so entries read before a crash or a failed write are delivered again.
+pub struct RedisStreamSource<T> {
+    client: Client,
+    cfg: RedisStreamSourceConfig,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> RedisStreamSource<T> {
+    pub fn from_config(cfg: &RedisStreamSourceConfig) -> Result<Self, PipelineError> {
+        if cfg.batch_size == 0 {
+            return Err(PipelineError::Source("redis.batch_size must be positive".to_string()));
+        }
+        let client =
+            Client::open(cfg.url.as_str()).map_err(|e| PipelineError::Source(format!("invalid redis.url: {e}")))?;
+        tracing::info!(stream = %cfg.stream, group = %cfg.group, consumer = %cfg.consumer, "Redis stream source configured");
+        Ok(Self {
+            client,
+            cfg: cfg.clone(),
+            _marker: PhantomData,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Source<T> for RedisStreamSource<T>
+where
+    T: JsonRecord + Send + 'static,
+{
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let client = self.client.clone();
+        let cfg = self.cfg.clone();
+        let (tx, rx) = mpsc::unbounded_channel();
+        tokio::spawn(ack_entries(client.clone(), cfg.stream.clone(), cfg.group.clone(), rx));
+
+        let s = async_stream::stream! {
+            let options = StreamReadOptions::default()
+                .group(&cfg.group, &cfg.consumer)
+                .count(cfg.batch_size)
+                .block(cfg.block_ms as usize);
+            let mut conn: Option<MultiplexedConnection> = None;
+            // Our own pending entries are read first, from this id on; `None`
+            // once they are exhausted and only new entries (`>`) are read.
+            let mut pending_from = Some("0".to_string());
+            loop {
+                let mut c = match conn.take() {
+                    Some(c) => c,
+                    None => {
+                        let connected = match client.get_multiplexed_async_connection().await {
+                            Ok(mut c) => create_group(&mut c, &cfg).await.map(|_| c),
+                            Err(e) => Err(e),
+                        };
+                        match connected {
+                            Ok(c) => {
+                                tracing::info!(stream = %cfg.stream, group = %cfg.group, "Redis connected");
+                                c
+                            }
+                            Err(e) => {
+                                metrics::counter!("redis_source_errors_total").increment(1);
+                                yield Err(PipelineError::Source(format!("Redis connection failed: {e}")));
+                                tokio::time::sleep(RECONNECT_DELAY).await;
+                                continue;
+                            }
+                        }
+                    }
+                };
+                let from = pending_from.clone().unwrap_or_else(|| ">".to_string());
+                let read: RedisResult<Option<StreamReadReply>> =
+                    c.xread_options(&[&cfg.stream], &[&from], &options).await;
+                let entries: Vec<StreamId> = match read {
+                    Ok(reply) => reply.into_iter().flat_map(|r| r.keys).flat_map(|k| k.ids).collect(),
+                    Err(e) => {
+                        metrics::counter!("redis_source_errors_total").increment(1);
+                        yield Err(PipelineError::Source(format!("XREADGROUP on {} failed: {e}", cfg.stream)));
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                        continue;
+                    }
+                };
+                conn = Some(c);
+                if pending_from.is_some() {
+                    pending_from = entries.last().map(|e| e.id.clone());
+                    if pending_from.is_none() {
+                        tracing::info!(stream = %cfg.stream, consumer = %cfg.consumer, "pending entries replayed; reading new entries");
+                    }
+                }
+
+                for entry in entries {
+                    metrics::counter!("redis_source_messages_total", "stream" => cfg.stream.clone()).increment(1);
+                    let mut envelopes = Vec::new();
+                    let mut acks = Vec::new();
+                    let mut errors = Vec::new();
+                    match entry_payload(&entry, &cfg.field) {
+                        Ok(payload) => {
+                            for record in parse_payload::<T>(&payload) {
+                                match record {
+                                    Ok(record) => {
+                                        let (completion, ack) = Completion::oneshot();
+                                        envelopes.push(Envelope::tracked(record, completion));
+                                        acks.push(ack);
+                                    }
+                                    Err(e) => errors.push(e),
+                                }
+                            }
+                        }
+                        Err(e) => errors.push(e),
+                    }
+                    let _ = tx.send(PendingEntry { id: entry.id.clone(), acks });
+
+                    for e in errors {
+                        metrics::counter!("redis_source_parse_errors_total", "stream" => cfg.stream.clone()).increment(1);
+                        yield Err(PipelineError::Source(format!("invalid record in {} entry {}: {e}", cfg.stream, entry.id)));
+                    }
+                    for env in envelopes {
+                        yield Ok(env);
+                    }
+                }
+            }
+        };
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn entry(field: &str, value: Value) -> StreamId {
+        StreamId {
+            id: "1700000000000-0".to_string(),
+            map: HashMap::from([(field.to_string(), value)]),
+        }
+    }
+
+    #[test]
+    fn payload_is_read_from_the_configured_field() {
+        let e = entry("payload", Value::BulkString(b"{\"a\":1}".to_vec()));
+        assert_eq!(entry_payload(&e, "payload").unwrap(), "{\"a\":1}");
+        assert!(entry_payload(&e, "body").unwrap_err().contains("no field body"));
+    }
+
+    #[test]
+    fn non_text_payloads_are_rejected() {
+        let e = entry("payload", Value::BulkString(vec![0xff, 0xfe]));
+        assert!(entry_payload(&e, "payload").unwrap_err().contains("not UTF-8"));
+        let e = entry("payload", Value::Int(7));
+        assert!(entry_payload(&e, "payload").unwrap_err().contains("not a string"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::HashSet,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use futures::{Stream, TryStreamExt};
+use object_store::{aws::AmazonS3Builder, path::Path, ObjectMeta, ObjectStore};
+use rust_client::domain::MeterUsage;
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use crate::{
+    config::S3SourceConfig,
+    pipeline::{CompletionGroup, Envelope, PipelineError, Source},
+    sources::bulk_file::{parse_bulk, BulkFormat},
+};
+
+/// Client for the bucket of `cfg`, with credentials and region from the config
+/// or the standard AWS environment variables.
+pub(crate) fn build_store(cfg: &S3SourceConfig) -> Result<Arc<dyn ObjectStore>, PipelineError> {
+    let mut builder = AmazonS3Builder::from_env().with_bucket_name(&cfg.bucket);
+    if let Some(region) = &cfg.region {
+        builder = builder.with_region(region);
+    }
+    if let Some(endpoint) = &cfg.endpoint {
+        builder = builder
+            .with_endpoint(endpoint)
+            .with_allow_http(endpoint.starts_with("http://"));
+    }
+    if let (Some(key_id), Some(secret)) = (&cfg.access_key_id, &cfg.secret_access_key) {
+        builder = builder.with_access_key_id(key_id).with_secret_access_key(secret);
+    }
+    let store = builder
+        .build()
+        .map_err(|e| PipelineError::Source(format!("invalid S3 configuration: {e}")))?;
+    Ok(Arc::new(store))
+}
+
+/// Polls an S3 bucket/prefix for new `meter_usage` bulk files.
+///
+/// Every `poll_interval` the prefix is listed; objects not yet recorded in
+/// `s3_ingested_objects` are read (oldest first) and their records streamed.
+/// An object is recorded once each of its records was written or rejected by
+/// validation, so it is ingested once. If records were lost it is read again
+/// on a later poll; enable `event_id` dedup on `meter_usage` to absorb the
+/// repeats. Objects are read into memory whole.
+#[derive(Clone)]
+pub struct S3FileSource {
+    store: Arc<dyn ObjectStore>,
+    bucket: String,
+    prefix: Option<Path>,
+    pool: PgPool,
+    poll_interval: Duration,
+    in_flight: Arc<Mutex<HashSet<String>>>,
+}
+
+impl S3FileSource {
+    pub fn from_config(cfg: &S3SourceConfig, pool: PgPool) -> Result<Self, PipelineError> {
+        let store = build_store(cfg)?;
+
+        Ok(Self {
+            store,
+            bucket: cfg.bucket.clone(),
+            prefix: cfg.prefix.as_deref().filter(|p| !p.is_empty()).map(Path::from),
+            pool,
+            poll_interval: Duration::from_secs(cfg.poll_interval_secs),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    /// New objects of the supported formats, oldest first.
+    async fn pending_objects(&self) -> Result<Vec<(ObjectMeta, BulkFormat)>, PipelineError> {
+        let ingested: HashSet<String> =
+            sqlx::query_scalar::<_, String>("SELECT object_key FROM s3_ingested_objects WHERE bucket = $1")
+                .bind(&self.bucket)
+                .fetch_all(&self.pool)
+                .await
+                .map_err(|e| PipelineError::Source(format!("failed to read s3_ingested_objects: {e}")))?
+                .into_iter()
+                .collect();
+
+        let listed: Vec<ObjectMeta> = self
+            .store
+            .list(self.prefix.as_ref())
+            .try_collect()
+            .await
+            .map_err(|e| PipelineError::Source(format!("failed to list s3://{}: {e}", self.bucket)))?;
+
+        let in_flight = self.in_flight.lock().unwrap_or_else(|e| e.into_inner());
+        let mut pending: Vec<(ObjectMeta, BulkFormat)> = listed
+            .into_iter()
+            .filter(|meta| !ingested.contains(meta.location.as_ref()) && !in_flight.contains(meta.location.as_ref()))
+            .filter_map(|meta| BulkFormat::for_name(meta.location.as_ref()).map(|format| (meta, format)))
+            .collect();
+        pending.sort_by(|(a, _), (b, _)| (a.last_modified, &a.location).cmp(&(b.last_modified, &b.location)));
+        Ok(pending)
+    }
+}
+
+/// Record `meta` in `s3_ingested_objects` once its records settled.
+async fn record_when_settled(
+    pool: PgPool,
+    bucket: String,
+    meta: ObjectMeta,
+    group: CompletionGroup,
+    records: i64,
+    parse_errors: i64,
+    in_flight: Arc<Mutex<HashSet<String>>>,
+) {
+    let key = meta.location.to_string();
+    if group.wait_settled().await {
+        let recorded = sqlx::query(
+            "INSERT INTO s3_ingested_objects (ts, bucket, object_key, etag, size, records, parse_errors) \
+             VALUES ($1, $2, $3, $4, $5, $6, $7)",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(&bucket)
+        .bind(&key)
+        .bind(meta.e_tag.as_deref())
+        .bind(meta.size as i64)
+        .bind(records)
+        .bind(parse_errors)
+        .execute(&pool)
+        .await;
+        match recorded {
+            Ok(_) => {
+                metrics::counter!("s3_source_objects_total").increment(1);
+                tracing::info!(bucket = %bucket, key = %key, records, parse_errors, "S3 object ingested");
+            }
+            Err(e) => tracing::warn!(error = %e, key = %key, "failed to record ingested S3 object; it will be read again"),
+        }
+    } else {
+        tracing::warn!(bucket = %bucket, key = %key, "records of S3 object were not written; it will be read again");
+    }
+    in_flight.lock().unwrap_or_else(|e| e.into_inner()).remove(&key);
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for S3FileSource {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let this = self.clone();
+
+        let s = async_stream::stream! {
+            loop {
+                let pending = match this.pending_objects().await {
+                    Ok(p) => p,
+                    Err(e) => {
+                        metrics::counter!("s3_source_errors_total").increment(1);
+                        yield Err(e);
+                        Vec::new()
+                    }
+                };
+
+                for (meta, format) in pending {
+                    let key = meta.location.to_string();
+                    let data = match this.store.get(&meta.location).await {
+                        Ok(get) => get.bytes().await,
+                        Err(e) => Err(e),
+                    };
+                    let data = match data {
+                        Ok(d) => d,
+                        Err(e) => {
+                            metrics::counter!("s3_source_errors_total").increment(1);
+                            yield Err(PipelineError::Source(format!("failed to read s3://{}/{key}: {e}", this.bucket)));
+                            continue;
+                        }
+                    };
+                    this.in_flight.lock().unwrap_or_else(|e| e.into_inner()).insert(key.clone());
+                    tracing::info!(bucket = %this.bucket, key = %key, bytes = data.len(), "reading S3 object");
+
+                    let group = CompletionGroup::new();
+                    let (mut records, mut parse_errors) = (0i64, 0i64);
+                    for (line, record) in parse_bulk(format, &data).into_iter().enumerate() {
+                        match record {
+                            Ok(usage) => {
+                                records += 1;
+                                yield Ok(Envelope {
+                                    payload: usage,
+                                    received_at: SystemTime::now(),
+                                    completion: Some(group.track()),
+                                    tenant: None,
+                                    source_identity: None,
+                                });
+                            }
+                            Err(e) => {
+                                parse_errors += 1;
+                                metrics::counter!("s3_source_parse_errors_total").increment(1);
+                                yield Err(PipelineError::Source(format!("invalid record {} in {key}: {e}", line + 1)));
+                            }
+                        }
+                    }
+                    tokio::spawn(record_when_settled(
+                        this.pool.clone(),
+                        this.bucket.clone(),
+                        meta,
+                        group,
+                        records,
+                        parse_errors,
+                        this.in_flight.clone(),
+                    ));
+                }
+
+                tokio::time::sleep(this.poll_interval).await;
+            }
+        };
+        Box::pin(s)
+    }
+}
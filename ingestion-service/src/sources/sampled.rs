@@ -0,0 +1,87 @@
+use std::pin::Pin;
+
+use futures::{Stream, StreamExt};
+
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sinks::questdb_ilp::ShardKey,
+};
+
+/// Resolution of the sampled share: keys are placed on a grid of
+/// `SAMPLE_BUCKETS` buckets.
+const SAMPLE_BUCKETS: u64 = 1_000_000;
+
+/// Whether `key` is in the `percent` sample. Stable across processes and
+/// releases (BLAKE3 of the key), and a larger sample contains every smaller one.
+pub fn in_sample(key: &str, percent: f64) -> bool {
+    let hash = blake3::hash(key.as_bytes());
+    let bucket = u64::from_le_bytes(hash.as_bytes()[..8].try_into().expect("8 bytes")) % SAMPLE_BUCKETS;
+    (bucket as f64) < percent / 100.0 * SAMPLE_BUCKETS as f64
+}
+
+/// Passes on only the records whose key ([`ShardKey`]: meter or plant) falls
+/// in a deterministic `percent` sample.
+///
+/// Records left out are reported as written, so acknowledgments and Kafka
+/// offsets advance as if they had been ingested, and counted in
+/// `ingest_sample_skipped_total{pipeline}`. Without a percentage every
+/// record is passed on.
+pub struct SampledSource<S> {
+    inner: S,
+    pipeline: String,
+    percent: Option<f64>,
+}
+
+impl<S> SampledSource<S> {
+    pub fn new(inner: S, pipeline: &str, percent: Option<f64>) -> Self {
+        Self {
+            inner,
+            pipeline: pipeline.to_string(),
+            percent,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S, T> Source<T> for SampledSource<S>
+where
+    S: Source<T>,
+    T: ShardKey + Send + 'static,
+{
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<T>, PipelineError>> + Send>> {
+        let stream = self.inner.stream().await;
+        let Some(percent) = self.percent else {
+            return stream;
+        };
+        let skipped = metrics::counter!("ingest_sample_skipped_total", "pipeline" => self.pipeline.clone());
+        Box::pin(stream.filter(move |item| {
+            let keep = match item {
+                Ok(env) if !in_sample(env.payload.shard_key(), percent) => {
+                    env.complete();
+                    skipped.increment(1);
+                    false
+                }
+                _ => true,
+            };
+            std::future::ready(keep)
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_deterministic_and_nested() {
+        let keys: Vec<String> = (0..10_000).map(|i| format!("m-{i}")).collect();
+        let one: Vec<&String> = keys.iter().filter(|k| in_sample(k, 1.0)).collect();
+        let ten: Vec<&String> = keys.iter().filter(|k| in_sample(k, 10.0)).collect();
+
+        assert!((50..=150).contains(&one.len()), "1% sample has {}", one.len());
+        assert!((800..=1200).contains(&ten.len()), "10% sample has {}", ten.len());
+        assert!(one.iter().all(|k| in_sample(k, 10.0)));
+        assert_eq!(keys.iter().filter(|k| in_sample(k, 100.0)).count(), keys.len());
+        assert!(!keys.iter().any(|k| in_sample(k, 0.0)));
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashSet,
+    io::Read,
+    net::TcpStream,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use futures::Stream;
+use rust_client::domain::MeterUsage;
+use ssh2::{CheckResult, KnownHostFileKind, Session, Sftp};
+
+use crate::{
+    config::SftpSourceConfig,
+    pipeline::{CompletionGroup, Envelope, PipelineError, Source},
+    sources::bulk_file::{parse_bulk, BulkFormat},
+};
+
+/// Network timeout for SFTP operations.
+const SFTP_TIMEOUT: Duration = Duration::from_secs(60);
+
+fn connect(cfg: &SftpSourceConfig) -> Result<Sftp, String> {
+    let tcp = TcpStream::connect((cfg.host.as_str(), cfg.port))
+        .map_err(|e| format!("failed to connect to {}:{}: {e}", cfg.host, cfg.port))?;
+    let mut session = Session::new().map_err(|e| e.to_string())?;
+    session.set_timeout(SFTP_TIMEOUT.as_millis() as u32);
+    session.set_tcp_stream(tcp);
+    session.handshake().map_err(|e| format!("SSH handshake failed: {e}"))?;
+
+    if let Some(known_hosts_file) = &cfg.known_hosts_file {
+        let mut known_hosts = session.known_hosts().map_err(|e| e.to_string())?;
+        known_hosts
+            .read_file(Path::new(known_hosts_file), KnownHostFileKind::OpenSSH)
+            .map_err(|e| format!("failed to read {known_hosts_file}: {e}"))?;
+        let (key, _) = session.host_key().ok_or("server sent no host key")?;
+        match known_hosts.check_port(&cfg.host, cfg.port, key) {
+            CheckResult::Match => {}
+            other => return Err(format!("host key of {} not trusted ({other:?})", cfg.host)),
+        }
+    }
+
+    match (&cfg.private_key_file, &cfg.password) {
+        (Some(key), _) => session
+            .userauth_pubkey_file(&cfg.username, None, Path::new(key), cfg.private_key_passphrase.as_deref())
+            .map_err(|e| format!("public key authentication failed: {e}"))?,
+        (None, Some(password)) => session
+            .userauth_password(&cfg.username, password)
+            .map_err(|e| format!("password authentication failed: {e}"))?,
+        (None, None) => return Err("sftp_source needs private_key_file or password".to_string()),
+    }
+    session.sftp().map_err(|e| format!("failed to start SFTP: {e}"))
+}
+
+/// Files of the supported formats in `remote_dir`, oldest first.
+fn list_pending(sftp: &Sftp, remote_dir: &str, skip: &HashSet<PathBuf>) -> Result<Vec<(PathBuf, BulkFormat)>, String> {
+    let mut files: Vec<(u64, PathBuf, BulkFormat)> = sftp
+        .readdir(Path::new(remote_dir))
+        .map_err(|e| format!("failed to list {remote_dir}: {e}"))?
+        .into_iter()
+        .filter(|(path, stat)| stat.is_file() && !skip.contains(path))
+        .filter_map(|(path, stat)| {
+            let format = BulkFormat::for_name(&path.file_name()?.to_string_lossy())?;
+            Some((stat.mtime.unwrap_or(0), path, format))
+        })
+        .collect();
+    files.sort_by(|(a_mtime, a, _), (b_mtime, b, _)| (a_mtime, a).cmp(&(b_mtime, b)));
+    Ok(files.into_iter().map(|(_, path, format)| (path, format)).collect())
+}
+
+fn download(sftp: &Sftp, path: &Path) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    let mut file = sftp
+        .open(path)
+        .map_err(|e| format!("failed to open {}: {e}", path.display()))?;
+    file.read_to_end(&mut data)
+        .map_err(|e| format!("failed to download {}: {e}", path.display()))?;
+    Ok(data)
+}
+
+fn archive_path(archive_dir: &str, path: &Path) -> PathBuf {
+    Path::new(archive_dir).join(path.file_name().unwrap_or(path.as_os_str()))
+}
+
+/// Polls a directory on an SFTP server for vendor `meter_usage` files.
+///
+/// Every `poll_interval` the directory is listed and the files in a
+/// [`BulkFormat`] are downloaded, oldest first, and parsed with the backfill
+/// parsers. Once each record of a file was written
+/// or rejected by validation the file is moved to `archive_dir`, so it is
+/// ingested once. Files with lost records stay in place and are read again on
+/// a later poll; enable `event_id` dedup on `meter_usage` to absorb the
+/// repeats. Files are read into memory whole.
+#[derive(Clone)]
+pub struct SftpDirectorySource {
+    cfg: Arc<SftpSourceConfig>,
+    poll_interval: Duration,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+}
+
+impl SftpDirectorySource {
+    pub fn from_config(cfg: &SftpSourceConfig) -> Result<Self, PipelineError> {
+        if cfg.private_key_file.is_none() && cfg.password.is_none() {
+            return Err(PipelineError::Source(
+                "sftp_source needs private_key_file or password".to_string(),
+            ));
+        }
+        if cfg.known_hosts_file.is_none() {
+            tracing::warn!(host = %cfg.host, "sftp_source.known_hosts_file not set; the host key is not verified");
+        }
+        Ok(Self {
+            cfg: Arc::new(cfg.clone()),
+            poll_interval: Duration::from_secs(cfg.poll_interval_secs),
+            in_flight: Arc::new(Mutex::new(HashSet::new())),
+        })
+    }
+
+    /// Connect and list the files to read, off the async runtime.
+    async fn poll(&self) -> Result<(Sftp, Vec<(PathBuf, BulkFormat)>), PipelineError> {
+        let cfg = self.cfg.clone();
+        let skip = self.in_flight.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        tokio::task::spawn_blocking(move || {
+            let sftp = connect(&cfg)?;
+            let pending = list_pending(&sftp, &cfg.remote_dir, &skip)?;
+            Ok((sftp, pending))
+        })
+        .await
+        .map_err(|e| PipelineError::Source(format!("SFTP task failed: {e}")))?
+        .map_err(PipelineError::Source)
+    }
+}
+
+/// Move `path` to the archive directory once its records settled.
+async fn archive_when_settled(
+    cfg: Arc<SftpSourceConfig>,
+    path: PathBuf,
+    group: CompletionGroup,
+    records: usize,
+    parse_errors: usize,
+    in_flight: Arc<Mutex<HashSet<PathBuf>>>,
+) {
+    if group.wait_settled().await {
+        let target = archive_path(&cfg.archive_dir, &path);
+        let moved = {
+            let (cfg, path, target) = (cfg.clone(), path.clone(), target.clone());
+            tokio::task::spawn_blocking(move || {
+                connect(&cfg)?
+                    .rename(&path, &target, None)
+                    .map_err(|e| format!("failed to move to {}: {e}", target.display()))
+            })
+            .await
+            .unwrap_or_else(|e| Err(format!("SFTP task failed: {e}")))
+        };
+        match moved {
+            Ok(()) => {
+                metrics::counter!("sftp_source_files_total").increment(1);
+                tracing::info!(file = %path.display(), archived = %target.display(), records, parse_errors, "SFTP file ingested");
+            }
+            Err(e) => {
+                metrics::counter!("sftp_source_errors_total").increment(1);
+                tracing::warn!(error = %e, file = %path.display(), "failed to archive ingested SFTP file; it will be read again");
+            }
+        }
+    } else {
+        tracing::warn!(file = %path.display(), "records of SFTP file were not written; it will be read again");
+    }
+    in_flight.lock().unwrap_or_else(|e| e.into_inner()).remove(&path);
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for SftpDirectorySource {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let this = self.clone();
+
+        let s = async_stream::stream! {
+            loop {
+                let (mut sftp, pending) = match this.poll().await {
+                    Ok((sftp, pending)) => (Some(sftp), pending),
+                    Err(e) => {
+                        metrics::counter!("sftp_source_errors_total").increment(1);
+                        yield Err(e);
+                        (None, Vec::new())
+                    }
+                };
+
+                for (path, format) in pending {
+                    let Some(conn) = sftp.take() else { break };
+                    let downloaded = {
+                        let path = path.clone();
+                        tokio::task::spawn_blocking(move || {
+                            let data = download(&conn, &path);
+                            (conn, data)
+                        })
+                        .await
+                    };
+                    let data = match downloaded {
+                        Ok((conn, data)) => {
+                            sftp = Some(conn);
+                            data
+                        }
+                        Err(e) => Err(format!("SFTP task failed: {e}")),
+                    };
+                    let data = match data {
+                        Ok(d) => d,
+                        Err(e) => {
+                            metrics::counter!("sftp_source_errors_total").increment(1);
+                            yield Err(PipelineError::Source(e));
+                            continue;
+                        }
+                    };
+                    this.in_flight.lock().unwrap_or_else(|e| e.into_inner()).insert(path.clone());
+                    tracing::info!(file = %path.display(), bytes = data.len(), "reading SFTP file");
+
+                    let group = CompletionGroup::new();
+                    let (mut records, mut parse_errors) = (0, 0);
+                    for (line, record) in parse_bulk(format, &data).into_iter().enumerate() {
+                        match record {
+                            Ok(usage) => {
+                                records += 1;
+                                yield Ok(Envelope {
+                                    payload: usage,
+                                    received_at: SystemTime::now(),
+                                    completion: Some(group.track()),
+                                    tenant: None,
+                                    source_identity: None,
+                                });
+                            }
+                            Err(e) => {
+                                parse_errors += 1;
+                                metrics::counter!("sftp_source_parse_errors_total").increment(1);
+                                yield Err(PipelineError::Source(format!(
+                                    "invalid record {} in {}: {e}",
+                                    line + 1,
+                                    path.display()
+                                )));
+                            }
+                        }
+                    }
+                    tokio::spawn(archive_when_settled(
+                        this.cfg.clone(),
+                        path,
+                        group,
+                        records,
+                        parse_errors,
+                        this.in_flight.clone(),
+                    ));
+                }
+                drop(sftp);
+
+                tokio::time::sleep(this.poll_interval).await;
+            }
+        };
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archives_next_to_the_file_name() {
+        assert_eq!(
+            archive_path("/out/archive", Path::new("/out/usage_20240601.dat")),
+            PathBuf::from("/out/archive/usage_20240601.dat")
+        );
+    }
+}
@@ -0,0 +1,279 @@
+use std::{
+    collections::HashSet,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+use futures::{Stream, StreamExt};
+use rust_client::{
+    db::{existing_meter_usage_keys, MeterUsageKey},
+    domain::MeterUsage,
+};
+use sqlx::postgres::PgPool;
+
+use crate::pipeline::{Envelope, PipelineError, Source};
+
+/// What a backfill does with records whose `(ts, meter_id)` is already stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnConflict {
+    /// Write every record; a re-run adds rows unless the table deduplicates them.
+    #[default]
+    Append,
+    /// Drop records that are already stored (see [`SkipExistingMeterUsageSource`]).
+    Skip,
+    /// Replace stored rows, so the latest version wins. `meter_usage` must
+    /// deduplicate on `(ts, meter_id)`.
+    Overwrite,
+    /// Check the whole file first and load nothing if any record is stored.
+    Fail,
+}
+
+impl OnConflict {
+    /// Take `--on-conflict <append|skip|overwrite|fail>` (or the older
+    /// `--skip-existing`) out of `args`.
+    pub fn take_from_args(args: &mut Vec<String>) -> anyhow::Result<Self> {
+        let mut policy = None;
+        let mut set = |p: Self| match policy.replace(p) {
+            Some(previous) if previous != p => Err(anyhow::anyhow!("conflicting --on-conflict policies")),
+            _ => Ok(()),
+        };
+        let mut rest = Vec::with_capacity(args.len());
+        let mut iter = std::mem::take(args).into_iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--on-conflict" => {
+                    let value = iter
+                        .next()
+                        .ok_or_else(|| anyhow::anyhow!("missing value for --on-conflict"))?;
+                    set(value.parse()?)?;
+                }
+                "--skip-existing" => set(Self::Skip)?,
+                _ => rest.push(arg),
+            }
+        }
+        *args = rest;
+        Ok(policy.unwrap_or_default())
+    }
+
+    /// The backfill mode recorded in `backfill_runs`.
+    pub fn mode(self) -> &'static str {
+        match self {
+            Self::Append => "plain",
+            Self::Skip => "skip_existing",
+            Self::Overwrite => "overwrite",
+            Self::Fail => "fail_on_conflict",
+        }
+    }
+}
+
+impl FromStr for OnConflict {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "append" => Ok(Self::Append),
+            "skip" => Ok(Self::Skip),
+            "overwrite" | "overwrite-latest-version" => Ok(Self::Overwrite),
+            "fail" => Ok(Self::Fail),
+            other => Err(anyhow::anyhow!(
+                "unknown --on-conflict '{other}' (expected append|skip|overwrite|fail)"
+            )),
+        }
+    }
+}
+
+/// Wraps a `MeterUsage` source and drops rows whose `(ts, meter_id)` already
+/// exists in QuestDB.
+///
+/// Records are checked in chunks: for each chunk, the keys stored for its
+/// meters within the chunk's time range are fetched in a single query. This
+/// makes re-running a partially loaded backfill file idempotent.
+pub struct SkipExistingMeterUsageSource<S> {
+    inner: S,
+    pool: PgPool,
+    chunk_size: usize,
+    skipped: Arc<AtomicU64>,
+}
+
+impl<S> SkipExistingMeterUsageSource<S> {
+    pub fn new(inner: S, pool: PgPool, chunk_size: usize) -> Self {
+        Self {
+            inner,
+            pool,
+            chunk_size: chunk_size.max(1),
+            skipped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Shared counter of rows skipped because they already existed.
+    pub fn skipped(&self) -> Arc<AtomicU64> {
+        self.skipped.clone()
+    }
+}
+
+/// The stored `(ts, meter_id)` keys among a chunk's records, fetched in one
+/// query over the chunk's meters and time range.
+async fn existing_keys(pool: &PgPool, chunk: &[Envelope<MeterUsage>]) -> Result<HashSet<MeterUsageKey>, PipelineError> {
+    let (Some(start), Some(end)) = (
+        chunk.iter().map(|e| e.payload.ts).min(),
+        chunk.iter().map(|e| e.payload.ts).max(),
+    ) else {
+        return Ok(HashSet::new());
+    };
+    let meter_ids: Vec<String> = chunk
+        .iter()
+        .map(|e| e.payload.meter_id.clone())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+
+    existing_meter_usage_keys(pool, &meter_ids, start, end)
+        .await
+        .map(|keys| keys.into_iter().collect())
+        .map_err(|e| PipelineError::Source(format!("failed to query existing meter_usage keys: {e}")))
+}
+
+/// Count the records of `source` whose `(ts, meter_id)` is already stored,
+/// checking `chunk_size` records per query. Unreadable records are ignored;
+/// the load reports them.
+pub async fn count_existing_meter_usage<S: Source<MeterUsage>>(
+    source: &S,
+    pool: &PgPool,
+    chunk_size: usize,
+) -> Result<u64, PipelineError> {
+    let mut chunks = source.stream().await.chunks(chunk_size.max(1));
+    let mut existing = 0u64;
+    while let Some(items) = chunks.next().await {
+        let mut envs: Vec<_> = items.into_iter().filter_map(Result::ok).collect();
+        let keys = existing_keys(pool, &envs).await?;
+        existing += retain_missing(&mut envs, &keys) as u64;
+    }
+    Ok(existing)
+}
+
+/// Remove envelopes whose key is in `existing`, returning the number removed.
+fn retain_missing(chunk: &mut Vec<Envelope<MeterUsage>>, existing: &HashSet<MeterUsageKey>) -> usize {
+    let before = chunk.len();
+    chunk.retain(|env| {
+        let key = MeterUsageKey {
+            ts: env.payload.ts,
+            meter_id: env.payload.meter_id.clone(),
+        };
+        !existing.contains(&key)
+    });
+    before - chunk.len()
+}
+
+#[async_trait::async_trait]
+impl<S> Source<MeterUsage> for SkipExistingMeterUsageSource<S>
+where
+    S: Source<MeterUsage>,
+{
+    async fn stream(
+        &self,
+    ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        let mut chunks = self.inner.stream().await.chunks(self.chunk_size);
+        let pool = self.pool.clone();
+        let skipped = self.skipped.clone();
+
+        let s = async_stream::stream! {
+            while let Some(items) = chunks.next().await {
+                let mut envs = Vec::with_capacity(items.len());
+                for item in items {
+                    match item {
+                        Ok(env) => envs.push(env),
+                        Err(e) => yield Err(e),
+                    }
+                }
+
+                let existing = match existing_keys(&pool, &envs).await {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        // Loading without the check could double rows; stop instead.
+                        yield Err(e);
+                        return;
+                    }
+                };
+
+                let removed = retain_missing(&mut envs, &existing);
+                if removed > 0 {
+                    skipped.fetch_add(removed as u64, Ordering::Relaxed);
+                    metrics::counter!("backfill_meter_usage_skipped_existing_total").increment(removed as u64);
+                }
+
+                for env in envs {
+                    yield Ok(env);
+                }
+            }
+        };
+
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn env(meter_id: &str, ts: time::OffsetDateTime) -> Envelope<MeterUsage> {
+        Envelope {
+            payload: MeterUsage {
+                ts,
+                meter_id: meter_id.to_string(),
+                premise_id: None,
+                kwh: 1.0,
+                kvarh: None,
+                kva_demand: None,
+                quality_flag: None,
+                source_system: None,
+                event_id: None,
+                phases: Default::default(),
+            },
+            received_at: std::time::SystemTime::now(),
+            completion: None,
+            tenant: None,
+            source_identity: None,
+        }
+    }
+
+    #[test]
+    fn retain_missing_drops_only_existing_keys() {
+        let t0 = datetime!(2024-01-01 00:00:00 UTC);
+        let t1 = datetime!(2024-01-01 00:15:00 UTC);
+        let mut chunk = vec![env("m-1", t0), env("m-1", t1), env("m-2", t0)];
+
+        let existing: HashSet<MeterUsageKey> = [MeterUsageKey {
+            ts: t0,
+            meter_id: "m-1".to_string(),
+        }]
+        .into_iter()
+        .collect();
+
+        let removed = retain_missing(&mut chunk, &existing);
+        assert_eq!(removed, 1);
+        assert_eq!(chunk.len(), 2);
+        assert_eq!(chunk[0].payload.ts, t1);
+        assert_eq!(chunk[1].payload.meter_id, "m-2");
+    }
+    #[test]
+    fn on_conflict_is_taken_out_of_the_arguments() {
+        let args = |a: &[&str]| a.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+        let mut a = args(&["--on-conflict", "overwrite-latest-version", "usage.csv", "--no-verify"]);
+        assert_eq!(OnConflict::take_from_args(&mut a).unwrap(), OnConflict::Overwrite);
+        assert_eq!(a, args(&["usage.csv", "--no-verify"]));
+
+        let mut a = args(&["usage.csv", "--skip-existing"]);
+        assert_eq!(OnConflict::take_from_args(&mut a).unwrap(), OnConflict::Skip);
+        let mut a = args(&["usage.csv"]);
+        assert_eq!(OnConflict::take_from_args(&mut a).unwrap(), OnConflict::Append);
+
+        assert!(OnConflict::take_from_args(&mut args(&["--skip-existing", "--on-conflict", "fail"])).is_err());
+        assert!(OnConflict::take_from_args(&mut args(&["--on-conflict", "ignore"])).is_err());
+        assert!(OnConflict::take_from_args(&mut args(&["usage.csv", "--on-conflict"])).is_err());
+    }
+}
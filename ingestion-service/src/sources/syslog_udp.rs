@@ -0,0 +1,296 @@
+use std::{collections::HashMap, pin::Pin, sync::Arc};
+
+use futures::Stream;
+use rust_client::domain::MeterEvent;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use tokio::{net::UdpSocket, sync::mpsc};
+use tokio_stream::wrappers::ReceiverStream;
+
+use crate::{
+    config::MeterEventsConfig,
+    pipeline::{Envelope, PipelineError, Source},
+};
+
+/// Largest UDP payload; syslog senders stay well below it.
+const MAX_DATAGRAM: usize = 65_535;
+
+/// Header fields of a syslog message that matter here.
+#[derive(Debug, PartialEq)]
+struct SyslogMessage<'a> {
+    /// RFC 5424 TIMESTAMP; RFC 3164 timestamps have no year or zone and are ignored.
+    timestamp: Option<OffsetDateTime>,
+    /// MSG part (RFC 5424), or everything after the priority (RFC 3164).
+    text: &'a str,
+}
+
+/// Split a syslog line into its timestamp and message text.
+fn parse_syslog(line: &str) -> SyslogMessage<'_> {
+    let line = line.trim_end_matches(['\r', '\n', '\0']);
+    let rest = match line.strip_prefix('<').and_then(|r| r.split_once('>')) {
+        Some((pri, rest)) if !pri.is_empty() && pri.bytes().all(|b| b.is_ascii_digit()) => rest,
+        _ => line,
+    };
+    let Some(after_version) = rest.strip_prefix("1 ") else {
+        return SyslogMessage {
+            timestamp: None,
+            text: rest.trim(),
+        };
+    };
+
+    // RFC 5424: TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+    let mut fields = after_version.splitn(6, ' ');
+    let timestamp = fields.next().and_then(|ts| OffsetDateTime::parse(ts, &Rfc3339).ok());
+    let structured = fields.nth(4).unwrap_or("");
+    let text = if let Some(msg) = structured.strip_prefix('-') {
+        msg
+    } else {
+        skip_structured_data(structured)
+    };
+    SyslogMessage {
+        timestamp,
+        text: text.trim_start().trim_start_matches('\u{feff}').trim(),
+    }
+}
+
+/// The text after the `[id param="value" ...]` elements at the start of `s`.
+fn skip_structured_data(s: &str) -> &str {
+    let mut in_value = false;
+    let mut escaped = false;
+    let mut end = 0;
+    for (i, ch) in s.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_value => escaped = true,
+            '"' => in_value = !in_value,
+            ']' if !in_value => {
+                end = i + 1;
+                if !s[end..].starts_with('[') {
+                    break;
+                }
+            }
+            _ => {}
+        }
+    }
+    &s[end..]
+}
+
+/// `key=value` and `key="quoted value"` pairs of a message text.
+fn parse_pairs(text: &str) -> HashMap<String, String> {
+    let mut pairs = HashMap::new();
+    let mut rest = text;
+    while let Some(eq) = rest.find('=') {
+        let key = rest[..eq].rsplit(|c: char| c.is_whitespace()).next().unwrap_or("");
+        let after = &rest[eq + 1..];
+        let (value, next) = match after.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((i, ch)) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        }
+                        '"' => {
+                            end = i + 1;
+                            break;
+                        }
+                        _ => value.push(ch),
+                    }
+                }
+                (value, &quoted[end..])
+            }
+            None => {
+                let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                (after[..end].to_string(), &after[end..])
+            }
+        };
+        if !key.is_empty() {
+            pairs.insert(key.to_string(), value);
+        }
+        rest = next;
+    }
+    pairs
+}
+
+/// Turns syslog lines from head-end systems into [`MeterEvent`]s, per `[meter_events]`.
+pub struct MeterEventParser {
+    meter_key: String,
+    event_key: String,
+    time_key: Option<String>,
+    event_types: HashMap<String, String>,
+}
+
+impl MeterEventParser {
+    pub fn new(cfg: &MeterEventsConfig) -> Self {
+        Self {
+            meter_key: cfg.meter_key.clone(),
+            event_key: cfg.event_key.clone(),
+            time_key: cfg.time_key.clone(),
+            event_types: cfg.event_types.clone(),
+        }
+    }
+
+    /// Parse one syslog line received at `received`.
+    pub fn parse(&self, line: &str, received: OffsetDateTime) -> Result<MeterEvent, String> {
+        let message = parse_syslog(line);
+        let pairs = parse_pairs(message.text);
+        let field = |key: &str| {
+            pairs
+                .get(key)
+                .filter(|v| !v.is_empty())
+                .ok_or_else(|| format!("no {key}= in message"))
+        };
+        let meter_id = field(&self.meter_key)?.clone();
+        let code = field(&self.event_key)?;
+        let event_type = self
+            .event_types
+            .get(code)
+            .cloned()
+            .unwrap_or_else(|| code.to_ascii_lowercase());
+        let ts = match &self.time_key {
+            Some(key) if pairs.contains_key(key) => {
+                let text = field(key)?;
+                OffsetDateTime::parse(text, &Rfc3339).map_err(|e| format!("invalid {key} '{text}': {e}"))?
+            }
+            _ => message.timestamp.unwrap_or(received),
+        };
+        Ok(MeterEvent {
+            ts,
+            meter_id,
+            event_type,
+            details: message.text.to_string(),
+        })
+    }
+}
+
+/// Meter events received as syslog messages over UDP (RFC 5424 or RFC 3164
+/// framing, one message per datagram or per line).
+///
+/// Messages without a meter id or event code are counted in
+/// `syslog_parse_errors_total` and passed on as errors; there is no
+/// redelivery, so UDP suits event feeds where an occasional loss is acceptable.
+pub struct SyslogUdpSource {
+    socket: Arc<UdpSocket>,
+    parser: Arc<MeterEventParser>,
+    channel_capacity: usize,
+}
+
+impl SyslogUdpSource {
+    /// Bind the listening socket, so a taken port fails at startup.
+    pub async fn bind(cfg: &MeterEventsConfig) -> std::io::Result<Self> {
+        let socket = UdpSocket::bind(&cfg.bind_addr).await?;
+        tracing::info!(addr = %socket.local_addr()?, "syslog meter event listener bound");
+        Ok(Self {
+            socket: Arc::new(socket),
+            parser: Arc::new(MeterEventParser::new(cfg)),
+            channel_capacity: cfg.channel_capacity,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Source<MeterEvent> for SyslogUdpSource {
+    async fn stream(&self) -> Pin<Box<dyn Stream<Item = Result<Envelope<MeterEvent>, PipelineError>> + Send>> {
+        let (tx, rx) = mpsc::channel(self.channel_capacity.max(1));
+        let socket = self.socket.clone();
+        let parser = self.parser.clone();
+
+        tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_DATAGRAM];
+            loop {
+                let (len, peer) = match socket.recv_from(&mut buf).await {
+                    Ok(r) => r,
+                    Err(e) => {
+                        tracing::warn!(error = %e, "syslog receive failed");
+                        continue;
+                    }
+                };
+                let received = OffsetDateTime::now_utc();
+                let datagram = String::from_utf8_lossy(&buf[..len]);
+                for line in datagram.lines().filter(|l| !l.trim().is_empty()) {
+                    metrics::counter!("syslog_messages_total").increment(1);
+                    let item = parser.parse(line, received).map(Envelope::new).map_err(|e| {
+                        metrics::counter!("syslog_parse_errors_total").increment(1);
+                        PipelineError::Source(format!("syslog message from {peer}: {e}"))
+                    });
+                    if tx.send(item).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Box::pin(ReceiverStream::new(rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn parser() -> MeterEventParser {
+        let cfg: MeterEventsConfig = toml::from_str(
+            r#"
+            event_types = { "COVER OPEN" = "tamper", REV_ENERGY = "reverse_run" }
+            [sink]
+            kind = "ilp"
+            batch_size = 100
+            max_retries = 3
+            retry_backoff_ms = 100
+            "#,
+        )
+        .unwrap();
+        MeterEventParser::new(&cfg)
+    }
+
+    const RECEIVED: OffsetDateTime = datetime!(2024-07-01 17:00:05 UTC);
+
+    #[test]
+    fn parses_rfc5424_messages() {
+        let line = "<134>1 2024-07-01T12:59:58-04:00 he01 headend 2211 ALARM \
+                    [origin ip=\"10.0.0.5\"][meta note=\"a \\] b\"] \u{feff}meter=M-1001 event=\"COVER OPEN\" sev=2";
+        let event = parser().parse(line, RECEIVED).unwrap();
+        assert_eq!(event.ts, datetime!(2024-07-01 16:59:58 UTC));
+        assert_eq!(event.meter_id, "M-1001");
+        assert_eq!(event.event_type, "tamper");
+        assert_eq!(event.details, "meter=M-1001 event=\"COVER OPEN\" sev=2");
+
+        let line = "<14>1 2024-07-01T17:00:00Z he01 headend - - - meter=M-7 event=REV_ENERGY";
+        let event = parser().parse(line, RECEIVED).unwrap();
+        assert_eq!(
+            (event.event_type.as_str(), event.ts),
+            ("reverse_run", datetime!(2024-07-01 17:00 UTC))
+        );
+    }
+
+    #[test]
+    fn parses_rfc3164_messages_at_receipt_time() {
+        let line = "<13>Jul  1 13:00:01 he02 amihe[77]: meter=M-2 event=POWER_FAIL\n";
+        let event = parser().parse(line, RECEIVED).unwrap();
+        assert_eq!(event.ts, RECEIVED);
+        assert_eq!(
+            (event.meter_id.as_str(), event.event_type.as_str()),
+            ("M-2", "power_fail")
+        );
+        assert_eq!(
+            event.details,
+            "Jul  1 13:00:01 he02 amihe[77]: meter=M-2 event=POWER_FAIL"
+        );
+    }
+
+    #[test]
+    fn rejects_messages_without_meter_or_event() {
+        let err = parser()
+            .parse("<13>Jul  1 13:00:01 he02 amihe: heartbeat ok", RECEIVED)
+            .unwrap_err();
+        assert_eq!(err, "no meter= in message");
+        let err = parser()
+            .parse("<13>1 - he02 amihe - - - meter=M-3", RECEIVED)
+            .unwrap_err();
+        assert_eq!(err, "no event= in message");
+    }
+}
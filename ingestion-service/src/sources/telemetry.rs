@@ -0,0 +1,65 @@
+use std::collections::HashMap;
+
+use rust_client::domain::GenerationOutput;
+use time::OffsetDateTime;
+
+use crate::config::TelemetryField;
+
+/// Latest values of a unit's non-MW points.
+#[derive(Debug, Clone, Copy, Default)]
+struct UnitValues {
+    mvar: Option<f64>,
+    aux_mw: Option<f64>,
+    availability_pct: Option<f64>,
+    curtailed_mw: Option<f64>,
+}
+
+/// Unit state of a SCADA source (IEC 104, DNP3).
+///
+/// SCADA reports each quantity as its own point. MW points yield a
+/// `GenerationOutput` record; the other points update the unit's latest
+/// value, which is carried on its next MW record.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct UnitTelemetry {
+    latest: HashMap<(String, Option<String>), UnitValues>,
+}
+
+impl UnitTelemetry {
+    /// Apply a scaled point value; MW values yield a record at `ts`.
+    pub(crate) fn apply(
+        &mut self,
+        plant_id: &str,
+        unit_id: Option<&str>,
+        field: TelemetryField,
+        value: f64,
+        ts: OffsetDateTime,
+    ) -> Option<GenerationOutput> {
+        let unit = self
+            .latest
+            .entry((plant_id.to_string(), unit_id.map(str::to_string)))
+            .or_default();
+        match field {
+            TelemetryField::Mw => {}
+            TelemetryField::Mvar => unit.mvar = Some(value),
+            TelemetryField::AuxMw => unit.aux_mw = Some(value),
+            TelemetryField::AvailabilityPct => unit.availability_pct = Some(value),
+            TelemetryField::CurtailedMw => unit.curtailed_mw = Some(value),
+        }
+        if field != TelemetryField::Mw {
+            return None;
+        }
+        Some(GenerationOutput {
+            ts,
+            plant_id: plant_id.to_string(),
+            unit_id: unit_id.map(str::to_string),
+            mw: value,
+            mvar: unit.mvar,
+            status: None,
+            fuel_type: None,
+            event_id: None,
+            aux_mw: unit.aux_mw,
+            availability_pct: unit.availability_pct,
+            curtailed_mw: unit.curtailed_mw,
+        })
+    }
+}
@@ -0,0 +1,240 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read},
+    path::PathBuf,
+};
+
+use futures::Stream;
+use rust_client::domain::MeterUsage;
+use tokio::sync::mpsc;
+use zip::ZipArchive;
+
+use crate::{
+    pipeline::{Envelope, PipelineError, Source},
+    sources::{
+        bulk_file::BulkFormat, meter_usage_backfill_file::parse_backfill_line,
+        meter_usage_csv_file::record_to_meter_usage,
+    },
+};
+
+/// Match `name` against a glob of `*` (any run of characters, `/` included)
+/// and `?` (one character), ignoring ASCII case.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let name: Vec<char> = name.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    // Iterative matching with backtracking to the last `*`.
+    let (mut p, mut n) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((sp, sn)) = star {
+            p = sp + 1;
+            n = sn + 1;
+            star = Some((sp, sn + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// `meter_usage` from the members of a `.zip` archive, as one run.
+///
+/// Members whose path matches the `members` glob (e.g. `*.dat`) are read in
+/// name order, so re-reading an archive yields the same records in the same
+/// order (as checkpoints need). Each member is parsed by its suffix like the
+/// bulk sources (`.csv`, `.dat`, `.ndjson`), unless a format is set with
+/// [`with_format`](Self::with_format). Members are decompressed while
+/// reading; nothing is unpacked to disk.
+pub struct ZipArchiveSource {
+    path: PathBuf,
+    members: String,
+    format: Option<BulkFormat>,
+}
+
+impl ZipArchiveSource {
+    pub fn new<P: Into<PathBuf>>(path: P, members: impl Into<String>) -> Self {
+        Self {
+            path: path.into(),
+            members: members.into(),
+            format: None,
+        }
+    }
+
+    /// Parse every member as `format` instead of by suffix.
+    pub fn with_format(mut self, format: Option<BulkFormat>) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Names of the members read, in order.
+    pub fn member_names(&self) -> Result<Vec<String>, PipelineError> {
+        let archive = open_archive(&self.path)?;
+        Ok(matching_members(&archive, &self.members))
+    }
+}
+
+fn open_archive(path: &PathBuf) -> Result<ZipArchive<File>, PipelineError> {
+    let file = File::open(path).map_err(|e| PipelineError::Source(format!("failed to open zip archive: {e}")))?;
+    ZipArchive::new(file).map_err(|e| PipelineError::Source(format!("failed to read zip archive: {e}")))
+}
+
+fn matching_members(archive: &ZipArchive<File>, pattern: &str) -> Vec<String> {
+    let mut names: Vec<String> = archive
+        .file_names()
+        .filter(|name| !name.ends_with('/') && glob_match(pattern, name))
+        .map(str::to_string)
+        .collect();
+    names.sort();
+    names
+}
+
+fn parse_error(member: &str, e: impl std::fmt::Display) -> PipelineError {
+    metrics::counter!("meter_usage_zip_parse_errors_total").increment(1);
+    PipelineError::Source(format!("failed to parse zip member {member}: {e}"))
+}
+
+fn read_error(member: &str, e: impl std::fmt::Display) -> PipelineError {
+    PipelineError::Source(format!("failed to read zip member {member}: {e}"))
+}
+
+/// Parse NDJSON lines of `member` into `send`. Returns `false` once `send` refuses a record.
+fn read_ndjson(name: &str, member: impl Read, send: &impl Fn(MeterUsage) -> bool) -> Result<bool, PipelineError> {
+    for line in BufReader::new(member).lines() {
+        let line = line.map_err(|e| read_error(name, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if !send(parse_backfill_line(&line).map_err(|e| parse_error(name, e))?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Parse CSV records of `member` into `send`. Returns `false` once `send` refuses a record.
+fn read_delimited(
+    name: &str,
+    member: impl Read,
+    delimiter: u8,
+    send: &impl Fn(MeterUsage) -> bool,
+) -> Result<bool, PipelineError> {
+    let mut rdr = csv::ReaderBuilder::new().delimiter(delimiter).from_reader(member);
+    let headers = rdr.headers().map_err(|e| read_error(name, e))?.clone();
+    for record in rdr.records() {
+        let record = record.map_err(|e| read_error(name, e))?;
+        if !send(record_to_meter_usage(&record, &headers).map_err(|e| parse_error(name, e))?) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Read the matching members, sending each record (or the error ending the
+/// run) to `tx`. Stops early once the receiver is gone.
+fn read_members(
+    path: &PathBuf,
+    pattern: &str,
+    format: Option<BulkFormat>,
+    tx: &mpsc::Sender<Result<MeterUsage, PipelineError>>,
+) -> Result<(), PipelineError> {
+    let send = |usage| tx.blocking_send(Ok(usage)).is_ok();
+
+    let mut archive = open_archive(path)?;
+    let members = matching_members(&archive, pattern);
+    if members.is_empty() {
+        tracing::warn!(archive = %path.display(), members = %pattern, "no zip members match");
+    }
+
+    for name in members {
+        let member_format = format.or_else(|| BulkFormat::for_name(&name)).ok_or_else(|| {
+            PipelineError::Source(format!("zip member {name} has no known suffix (.csv, .dat, .ndjson)"))
+        })?;
+        let member = archive.by_name(&name).map_err(|e| read_error(&name, e))?;
+        let completed = match member_format {
+            BulkFormat::Ndjson => read_ndjson(&name, member, &send)?,
+            BulkFormat::Csv => read_delimited(&name, member, b',', &send)?,
+            BulkFormat::Dat => read_delimited(&name, member, b'|', &send)?,
+        };
+        if !completed {
+            return Ok(());
+        }
+        metrics::counter!("meter_usage_zip_members_total").increment(1);
+    }
+    Ok(())
+}
+
+#[async_trait::async_trait]
+impl Source<MeterUsage> for ZipArchiveSource {
+    async fn stream(
+        &self,
+    ) -> std::pin::Pin<Box<dyn Stream<Item = Result<Envelope<MeterUsage>, PipelineError>> + Send>> {
+        // Zip members are read with blocking I/O on a dedicated thread.
+        let (tx, mut rx) = mpsc::channel(1024);
+        let path = self.path.clone();
+        let pattern = self.members.clone();
+        let format = self.format;
+        tokio::task::spawn_blocking(move || {
+            if let Err(e) = read_members(&path, &pattern, format, &tx) {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        let s = async_stream::try_stream! {
+            while let Some(usage) = rx.recv().await {
+                yield Envelope::new(usage?);
+            }
+        };
+
+        Box::pin(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::io::Write;
+    use zip::{write::SimpleFileOptions, ZipWriter};
+
+    #[test]
+    fn globs_match_whole_paths_ignoring_case() {
+        assert!(glob_match("*.dat", "usage_20240601.DAT"));
+        assert!(glob_match("*.dat", "2024/06/usage.dat"));
+        assert!(glob_match("usage_2024060?.csv", "usage_20240601.csv"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("*.dat", "usage.dat.bak"));
+        assert!(!glob_match("usage_*.csv", "readme.txt"));
+    }
+
+    #[tokio::test]
+    async fn streams_matching_members_in_name_order() {
+        let path = std::env::temp_dir().join(format!("zip-archive-{}.zip", std::process::id()));
+        let mut zip = ZipWriter::new(File::create(&path).unwrap());
+        let opts = SimpleFileOptions::default();
+        zip.start_file("b/usage_2.dat", opts).unwrap();
+        zip.write_all(b"ts|meter_id|kwh\n2024-06-01T00:30:00Z|m-2|2.0\n").unwrap();
+        zip.start_file("README.txt", opts).unwrap();
+        zip.write_all(b"not usage data").unwrap();
+        zip.start_file("a/usage_1.DAT", opts).unwrap();
+        zip.write_all(b"ts|meter_id|kwh\n2024-06-01T00:15:00Z|m-1|1.5\n2024-06-01T00:30:00Z|m-1|1.0\n").unwrap();
+        zip.finish().unwrap();
+
+        let source = ZipArchiveSource::new(&path, "*.dat");
+        assert_eq!(source.member_names().unwrap(), ["a/usage_1.DAT", "b/usage_2.dat"]);
+        let records: Vec<_> = source.stream().await.map(|r| r.unwrap().payload).collect().await;
+        let read: Vec<(&str, f64)> = records.iter().map(|m| (m.meter_id.as_str(), m.kwh)).collect();
+        assert_eq!(read, [("m-1", 1.5), ("m-1", 1.0), ("m-2", 2.0)]);
+
+        // A matching member that isn't usage data fails the run.
+        let all: Vec<_> = ZipArchiveSource::new(&path, "*").stream().await.collect().await;
+        assert!(all.last().unwrap().is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
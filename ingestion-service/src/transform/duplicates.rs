@@ -0,0 +1,209 @@
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap, HashSet, VecDeque},
+    hash::{Hash, Hasher},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+use rust_client::domain::MeterUsage;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::EventIdStrategy,
+    pipeline::{Envelope, PipelineError, Transform},
+    sinks::questdb_ilp::meter_usage_event_id,
+    sources::ChannelSource,
+};
+
+/// Records and duplicates seen for one `source_system` since the previous
+/// flush, persisted to `ingest_duplicates`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DuplicateCounts {
+    pub ts: SystemTime,
+    pub table: &'static str,
+    pub source_system: Option<String>,
+    pub records: u64,
+    pub duplicates: u64,
+}
+
+/// Handle used by [`MeterUsageDuplicates`] to hand off its counts.
+///
+/// Like the reject log it never blocks the pipeline: counts that don't fit in
+/// the channel are dropped and counted in `ingest_duplicate_log_dropped_total`.
+#[derive(Clone)]
+pub struct DuplicateLog {
+    tx: mpsc::Sender<Envelope<DuplicateCounts>>,
+}
+
+impl DuplicateLog {
+    /// Create a duplicate log and the source that drains it into a sink.
+    pub fn channel(capacity: usize) -> (Self, DuplicateLogSource) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        (Self { tx }, ChannelSource::new(rx))
+    }
+
+    pub fn record(&self, counts: DuplicateCounts) {
+        if self.tx.try_send(Envelope::new(counts)).is_err() {
+            metrics::counter!("ingest_duplicate_log_dropped_total").increment(1);
+        }
+    }
+}
+
+pub type DuplicateLogSource = ChannelSource<DuplicateCounts>;
+
+/// The last `capacity` ids seen, as 64-bit hashes.
+struct RecentIds {
+    ids: HashSet<u64>,
+    order: VecDeque<u64>,
+    capacity: usize,
+}
+
+impl RecentIds {
+    /// Remember `id`; returns whether it was already among the recent ids.
+    fn seen(&mut self, id: &str) -> bool {
+        let mut hasher = DefaultHasher::new();
+        id.hash(&mut hasher);
+        let id = hasher.finish();
+        if !self.ids.insert(id) {
+            return true;
+        }
+        self.order.push_back(id);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        false
+    }
+}
+
+struct State {
+    recent: RecentIds,
+    counts: HashMap<Option<String>, (u64, u64)>,
+    last_flush: Instant,
+}
+
+/// Counts `meter_usage` records whose `event_id` was already ingested
+/// recently, per `source_system`, without changing them.
+///
+/// Ids are derived as the sink derives them, so a duplicate here is a record
+/// the table's `(ts, event_id)` dedup collapses. Only the last `window`
+/// records are remembered: retries and replays are caught, a resend days later
+/// may not be. Counts go to `ingest_records_by_source_total` and
+/// `ingest_duplicates_total` and, every `flush_interval` (with the next
+/// record), to the duplicate log.
+pub struct MeterUsageDuplicates {
+    strategy: EventIdStrategy,
+    flush_interval: Duration,
+    state: Mutex<State>,
+    log: DuplicateLog,
+}
+
+impl MeterUsageDuplicates {
+    pub fn new(strategy: EventIdStrategy, window: usize, flush_interval: Duration, log: DuplicateLog) -> Self {
+        Self {
+            strategy,
+            flush_interval,
+            state: Mutex::new(State {
+                recent: RecentIds {
+                    ids: HashSet::new(),
+                    order: VecDeque::new(),
+                    capacity: window.max(1),
+                },
+                counts: HashMap::new(),
+                last_flush: Instant::now(),
+            }),
+            log,
+        }
+    }
+
+    fn count(&self, usage: &MeterUsage, now: Instant) {
+        let id = meter_usage_event_id(self.strategy, usage);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let duplicate = state.recent.seen(&id);
+
+        let source_system = usage.source_system.clone().unwrap_or_else(|| "unknown".to_string());
+        metrics::counter!("ingest_records_by_source_total", "table" => "meter_usage", "source_system" => source_system.clone())
+            .increment(1);
+        if duplicate {
+            metrics::counter!("ingest_duplicates_total", "table" => "meter_usage", "source_system" => source_system)
+                .increment(1);
+        }
+        let counts = state.counts.entry(usage.source_system.clone()).or_default();
+        counts.0 += 1;
+        counts.1 += u64::from(duplicate);
+
+        if now.duration_since(state.last_flush) >= self.flush_interval {
+            state.last_flush = now;
+            let ts = SystemTime::now();
+            for (source_system, (records, duplicates)) in state.counts.drain() {
+                self.log.record(DuplicateCounts {
+                    ts,
+                    table: "meter_usage",
+                    source_system,
+                    records,
+                    duplicates,
+                });
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Transform<MeterUsage, MeterUsage> for MeterUsageDuplicates {
+    async fn apply(&self, input: Envelope<MeterUsage>) -> Result<Envelope<MeterUsage>, PipelineError> {
+        self.count(&input.payload, Instant::now());
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "duplicates"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::Source;
+    use futures::StreamExt;
+    use time::macros::datetime;
+
+    fn usage(minute: u8, source_system: &str) -> MeterUsage {
+        MeterUsage {
+            ts: datetime!(2024-01-01 00:00:00 UTC).replace_minute(minute).unwrap(),
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh: 1.0,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some(source_system.to_string()),
+            event_id: None,
+            phases: Default::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn counts_repeated_ids_per_source_within_the_window() {
+        let (log, source) = DuplicateLog::channel(10);
+        let t = MeterUsageDuplicates::new(EventIdStrategy::ContentHash, 2, Duration::from_secs(60), log);
+        let start = Instant::now();
+
+        for (minute, source_system) in [(0, "a"), (0, "a"), (15, "a"), (0, "a"), (30, "a"), (0, "a"), (0, "b")] {
+            t.count(&usage(minute, source_system), start);
+        }
+        // Nothing is flushed before the interval is up.
+        t.count(&usage(45, "b"), start + Duration::from_secs(60));
+
+        drop(t);
+        let mut flushed: Vec<_> = source.stream().await.map(|e| e.unwrap().payload).collect().await;
+        flushed.sort_by(|a, b| a.source_system.cmp(&b.source_system));
+        let counts: Vec<_> = flushed
+            .iter()
+            .map(|c| (c.source_system.as_deref(), c.records, c.duplicates))
+            .collect();
+        // The 2nd and 4th "a" records repeat :00 while it is in the window of 2; the
+        // 6th comes after :15 and :30 pushed it out. "b" at :00 has its own id.
+        assert_eq!(counts, [(Some("a"), 6, 2), (Some("b"), 2, 0)]);
+    }
+}
@@ -1,3 +1,11 @@
+pub mod duplicates;
+pub mod record_fields;
+pub mod reference_join;
+pub mod rejects;
+pub mod threshold_alerts;
+pub mod unit_state;
+pub mod window_aggregate;
+
 use crate::pipeline::{Envelope, PipelineError, Transform};
 use rust_client::domain::{GenerationOutput, MeterUsage};
 use time::macros::datetime;
@@ -6,6 +14,8 @@ use time::macros::datetime;
 ///
 /// Rules:
 /// - kWh must be non-negative.
+/// - Per-phase kWh and current must be non-negative, voltage positive.
+/// - If all three per-phase kWh are present they must add up to `kwh` (within 1%).
 /// - ts must be within a broad sanity window [2000-01-01, 2100-01-01].
 pub fn validate_meter_usage(env: Envelope<MeterUsage>) -> Result<Envelope<MeterUsage>, PipelineError> {
     let m = &env.payload;
@@ -14,6 +24,20 @@ pub fn validate_meter_usage(env: Envelope<MeterUsage>) -> Result<Envelope<MeterU
         return Err(PipelineError::Transform("kwh must be non-negative".to_string()));
     }
 
+    for (column, v) in m.phases.present() {
+        let ok = if column.starts_with("voltage") { v > 0.0 } else { v >= 0.0 };
+        if !ok || !v.is_finite() {
+            return Err(PipelineError::Transform(format!("{column} out of range")));
+        }
+    }
+
+    let p = &m.phases;
+    if let (Some(a), Some(b), Some(c)) = (p.kwh_phase_a, p.kwh_phase_b, p.kwh_phase_c) {
+        if (a + b + c - m.kwh).abs() > 0.01 * m.kwh + 1e-6 {
+            return Err(PipelineError::Transform("per-phase kwh does not add up to kwh".to_string()));
+        }
+    }
+
     let min_ts = datetime!(2000-01-01 00:00:00 UTC);
     let max_ts = datetime!(2100-01-01 00:00:00 UTC);
 
@@ -27,7 +51,9 @@ pub fn validate_meter_usage(env: Envelope<MeterUsage>) -> Result<Envelope<MeterU
 /// Pure validation of a `GenerationOutput` record.
 ///
 /// Rules:
-/// - MW must be non-negative.
+/// - MW must be non-negative, as must `aux_mw` and `curtailed_mw` (aux load may exceed
+///   gross output, e.g. while a unit is offline).
+/// - `availability_pct` must be within [0, 100].
 /// - ts must be within the same sanity window as meter usage.
 pub fn validate_generation_output(
     env: Envelope<GenerationOutput>,
@@ -38,6 +64,16 @@ pub fn validate_generation_output(
         return Err(PipelineError::Transform("mw must be non-negative".to_string()));
     }
 
+    for (field, value) in [("aux_mw", g.aux_mw), ("curtailed_mw", g.curtailed_mw)] {
+        if value.is_some_and(|v| v.is_nan() || v < 0.0) {
+            return Err(PipelineError::Transform(format!("{field} must be non-negative")));
+        }
+    }
+
+    if g.availability_pct.is_some_and(|v| !(0.0..=100.0).contains(&v)) {
+        return Err(PipelineError::Transform("availability_pct must be within 0-100".to_string()));
+    }
+
     let min_ts = datetime!(2000-01-01 00:00:00 UTC);
     let max_ts = datetime!(2100-01-01 00:00:00 UTC);
 
@@ -65,6 +101,10 @@ impl Transform<MeterUsage, MeterUsage> for MeterUsageValidation {
             }
         }
     }
+
+    fn name(&self) -> &'static str {
+        "meter_usage_validation"
+    }
 }
 
 #[derive(Clone, Default)]
@@ -84,11 +124,16 @@ impl Transform<GenerationOutput, GenerationOutput> for GenerationOutputValidatio
             }
         }
     }
+
+    fn name(&self) -> &'static str {
+        "generation_output_validation"
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rust_client::domain::PhaseChannels;
     use time::macros::datetime;
 
     #[test]
@@ -103,8 +148,13 @@ mod tests {
                 kva_demand: None,
                 quality_flag: None,
                 source_system: None,
+                event_id: None,
+                phases: Default::default(),
             },
             received_at: std::time::SystemTime::now(),
+            completion: None,
+            tenant: None,
+            source_identity: None,
         };
 
         let res = validate_meter_usage(env);
@@ -123,8 +173,13 @@ mod tests {
                 kva_demand: None,
                 quality_flag: None,
                 source_system: None,
+                event_id: None,
+                phases: Default::default(),
             },
             received_at: std::time::SystemTime::now(),
+            completion: None,
+            tenant: None,
+            source_identity: None,
         };
 
         let res = validate_meter_usage(env);
@@ -143,11 +198,77 @@ mod tests {
                 kva_demand: None,
                 quality_flag: None,
                 source_system: None,
+                event_id: None,
+                phases: Default::default(),
             },
             received_at: std::time::SystemTime::now(),
+            completion: None,
+            tenant: None,
+            source_identity: None,
         };
 
         let res = validate_meter_usage(env);
         assert!(matches!(res, Err(PipelineError::Transform(_))));
     }
+
+    #[test]
+    fn meter_usage_validation_checks_phase_channels() {
+        let reading = |phases: PhaseChannels| {
+            Envelope::new(MeterUsage {
+                ts: datetime!(2024-01-01 00:00:00 UTC),
+                meter_id: "m-1".to_string(),
+                premise_id: None,
+                kwh: 3.0,
+                kvarh: None,
+                kva_demand: None,
+                quality_flag: None,
+                source_system: None,
+                event_id: None,
+                phases,
+            })
+        };
+        let balanced = PhaseChannels {
+            kwh_phase_a: Some(1.0),
+            kwh_phase_b: Some(1.0),
+            kwh_phase_c: Some(1.0),
+            voltage_phase_a: Some(277.0),
+            ..Default::default()
+        };
+
+        assert!(validate_meter_usage(reading(balanced.clone())).is_ok());
+        assert!(validate_meter_usage(reading(PhaseChannels {
+            kwh_phase_c: Some(2.0),
+            ..balanced.clone()
+        }))
+        .is_err());
+        assert!(validate_meter_usage(reading(PhaseChannels {
+            voltage_phase_a: Some(0.0),
+            ..balanced
+        }))
+        .is_err());
+    }
+
+    #[test]
+    fn generation_output_validation_checks_aux_availability_and_curtailment() {
+        let sample = |aux_mw: Option<f64>, availability_pct: Option<f64>| {
+            Envelope::new(GenerationOutput {
+                ts: datetime!(2024-01-01 00:00:00 UTC),
+                plant_id: "p".to_string(),
+                unit_id: None,
+                mw: 0.0,
+                mvar: None,
+                status: None,
+                fuel_type: None,
+                event_id: None,
+                aux_mw,
+                availability_pct,
+                curtailed_mw: None,
+            })
+        };
+
+        // Aux load of an offline unit exceeds its (zero) gross output.
+        assert!(validate_generation_output(sample(Some(1.5), Some(100.0))).is_ok());
+        assert!(validate_generation_output(sample(Some(-1.0), None)).is_err());
+        assert!(validate_generation_output(sample(None, Some(101.0))).is_err());
+    }
 }
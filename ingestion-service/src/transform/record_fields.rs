@@ -0,0 +1,91 @@
+use rust_client::domain::{GenerationOutput, MeterUsage, PhaseChannels};
+use time::OffsetDateTime;
+
+/// Numeric fields of a record type, by column name, for rule and window
+/// evaluation in the live pipelines.
+pub trait RecordFields {
+    const TABLE: &'static str;
+    /// Fields [`RecordFields::field`] knows.
+    const FIELDS: &'static [&'static str];
+
+    fn ts(&self) -> OffsetDateTime;
+    /// `meter_id` / `plant_id`.
+    fn record_id(&self) -> &str;
+    /// Value of a numeric field, if set.
+    fn field(&self, name: &str) -> Option<f64>;
+}
+
+/// [`RecordFields::FIELDS`] of a supported table.
+pub fn table_fields(table: &str) -> Option<&'static [&'static str]> {
+    match table {
+        MeterUsage::TABLE => Some(MeterUsage::FIELDS),
+        GenerationOutput::TABLE => Some(GenerationOutput::FIELDS),
+        _ => None,
+    }
+}
+
+fn phase_field(phases: &PhaseChannels, name: &str) -> Option<f64> {
+    PhaseChannels::COLUMNS
+        .iter()
+        .position(|c| *c == name)
+        .and_then(|i| phases.values()[i])
+}
+
+impl RecordFields for MeterUsage {
+    const TABLE: &'static str = "meter_usage";
+    const FIELDS: &'static [&'static str] = &[
+        "kwh",
+        "kvarh",
+        "kva_demand",
+        "kwh_phase_a",
+        "kwh_phase_b",
+        "kwh_phase_c",
+        "current_phase_a",
+        "current_phase_b",
+        "current_phase_c",
+        "voltage_phase_a",
+        "voltage_phase_b",
+        "voltage_phase_c",
+    ];
+
+    fn ts(&self) -> OffsetDateTime {
+        self.ts
+    }
+
+    fn record_id(&self) -> &str {
+        &self.meter_id
+    }
+
+    fn field(&self, name: &str) -> Option<f64> {
+        match name {
+            "kwh" => Some(self.kwh),
+            "kvarh" => self.kvarh,
+            "kva_demand" => self.kva_demand,
+            other => phase_field(&self.phases, other),
+        }
+    }
+}
+
+impl RecordFields for GenerationOutput {
+    const TABLE: &'static str = "generation_output";
+    const FIELDS: &'static [&'static str] = &["mw", "mvar", "aux_mw", "availability_pct", "curtailed_mw"];
+
+    fn ts(&self) -> OffsetDateTime {
+        self.ts
+    }
+
+    fn record_id(&self) -> &str {
+        &self.plant_id
+    }
+
+    fn field(&self, name: &str) -> Option<f64> {
+        match name {
+            "mw" => Some(self.mw),
+            "mvar" => self.mvar,
+            "aux_mw" => self.aux_mw,
+            "availability_pct" => self.availability_pct,
+            "curtailed_mw" => self.curtailed_mw,
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, RwLock},
+};
+
+use rust_client::domain::{Meter, MeterUsage};
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+
+use crate::pipeline::{Envelope, PipelineError, Transform};
+
+/// Versions kept per key; older ones are dropped first.
+const MAX_VERSIONS_PER_KEY: usize = 16;
+
+/// Versions of one key, ordered by `effective_from`.
+type Versions<V> = Vec<(OffsetDateTime, Option<V>)>;
+
+/// One effective-dated version of a reference entry; `value: None` removes the
+/// entry from `version` on.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReferenceUpdate<V> {
+    pub key: String,
+    pub version: OffsetDateTime,
+    pub value: Option<V>,
+}
+
+/// In-memory keyed state of a slowly-changing reference table, shared between
+/// the [`ReferenceUpdates`] stage on the reference stream and the
+/// [`ReferenceJoin`] stages reading it.
+///
+/// Entries are effective-dated: a lookup returns the version in effect at the
+/// record's time, or the oldest known version for records that predate all of
+/// them.
+pub struct ReferenceTable<V> {
+    entries: Arc<RwLock<HashMap<String, Versions<V>>>>,
+}
+
+impl<V> Clone for ReferenceTable<V> {
+    fn clone(&self) -> Self {
+        Self {
+            entries: self.entries.clone(),
+        }
+    }
+}
+
+impl<V: Clone> Default for ReferenceTable<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V: Clone> ReferenceTable<V> {
+    pub fn new() -> Self {
+        Self {
+            entries: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Add a version. A version already known for the key is replaced.
+    pub fn apply(&self, update: ReferenceUpdate<V>) {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+        let versions = entries.entry(update.key).or_default();
+        match versions.binary_search_by(|(v, _)| v.cmp(&update.version)) {
+            Ok(i) => versions[i].1 = update.value,
+            Err(i) => versions.insert(i, (update.version, update.value)),
+        }
+        if versions.len() > MAX_VERSIONS_PER_KEY {
+            versions.remove(0);
+        }
+    }
+
+    /// Value of `key` in effect at `at`.
+    pub fn get(&self, key: &str, at: OffsetDateTime) -> Option<V> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+        let versions = entries.get(key)?;
+        let i = versions.partition_point(|(v, _)| *v <= at);
+        versions[i.saturating_sub(1)].1.clone()
+    }
+
+    /// Number of keys.
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Pass-through transform on a reference stream that applies each record to a
+/// [`ReferenceTable`], so joins see changes without a restart.
+pub struct ReferenceUpdates<R, V> {
+    table: ReferenceTable<V>,
+    update: fn(&R) -> ReferenceUpdate<V>,
+}
+
+impl<R, V> ReferenceUpdates<R, V> {
+    pub fn new(table: ReferenceTable<V>, update: fn(&R) -> ReferenceUpdate<V>) -> Self {
+        Self { table, update }
+    }
+}
+
+#[async_trait::async_trait]
+impl<R, V> Transform<R, R> for ReferenceUpdates<R, V>
+where
+    R: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    async fn apply(&self, input: Envelope<R>) -> Result<Envelope<R>, PipelineError> {
+        self.table.apply((self.update)(&input.payload));
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "reference_updates"
+    }
+}
+
+/// Transform joining each record to the [`ReferenceTable`] entry for its key
+/// at the record's time, via `merge`. Records without an entry pass through
+/// unchanged and are counted in `reference_join_misses_total{join}`.
+pub struct ReferenceJoin<T, V> {
+    join: &'static str,
+    table: ReferenceTable<V>,
+    key: fn(&T) -> (&str, OffsetDateTime),
+    merge: fn(&mut T, V),
+}
+
+impl<T, V> ReferenceJoin<T, V> {
+    pub fn new(
+        join: &'static str,
+        table: ReferenceTable<V>,
+        key: fn(&T) -> (&str, OffsetDateTime),
+        merge: fn(&mut T, V),
+    ) -> Self {
+        Self { join, table, key, merge }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T, V> Transform<T, T> for ReferenceJoin<T, V>
+where
+    T: Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    async fn apply(&self, mut input: Envelope<T>) -> Result<Envelope<T>, PipelineError> {
+        let (key, at) = (self.key)(&input.payload);
+        match self.table.get(key, at) {
+            Some(value) => (self.merge)(&mut input.payload, value),
+            None => metrics::counter!("reference_join_misses_total", "join" => self.join).increment(1),
+        }
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "reference_join"
+    }
+}
+
+/// Meter attributes joined onto `meter_usage`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeterReference {
+    pub premise_id: Option<String>,
+}
+
+pub fn meter_reference_update(m: &Meter) -> ReferenceUpdate<MeterReference> {
+    ReferenceUpdate {
+        key: m.meter_id.clone(),
+        version: m.effective_from,
+        value: (!m.deleted).then(|| MeterReference {
+            premise_id: m.premise_id.clone(),
+        }),
+    }
+}
+
+pub fn meter_usage_key(u: &MeterUsage) -> (&str, OffsetDateTime) {
+    (&u.meter_id, u.ts)
+}
+
+/// Fill `premise_id` from the meter reference when the source left it empty.
+pub fn fill_meter_usage(u: &mut MeterUsage, m: MeterReference) {
+    if u.premise_id.is_none() {
+        u.premise_id = m.premise_id;
+    }
+}
+
+/// Bootstrap a meter reference table from the `meters` versions in QuestDB.
+pub async fn load_meter_references(pool: &PgPool) -> Result<ReferenceTable<MeterReference>, sqlx::Error> {
+    let meters: Vec<Meter> = sqlx::query_as(
+        "SELECT effective_from, meter_id, premise_id, customer_id, feeder_id, substation_id, \
+         tariff_code, meter_type, deleted FROM meters ORDER BY effective_from",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let table = ReferenceTable::new();
+    for m in &meters {
+        table.apply(meter_reference_update(m));
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_client::domain::PhaseChannels;
+    use time::macros::datetime;
+
+    fn meter(effective_from: OffsetDateTime, premise_id: &str, deleted: bool) -> Meter {
+        Meter {
+            effective_from,
+            meter_id: "m-1".to_string(),
+            premise_id: Some(premise_id.to_string()),
+            customer_id: None,
+            feeder_id: None,
+            substation_id: None,
+            tariff_code: None,
+            meter_type: None,
+            deleted,
+        }
+    }
+
+    fn usage(ts: OffsetDateTime) -> MeterUsage {
+        MeterUsage {
+            ts,
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh: 1.0,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: None,
+            event_id: None,
+            phases: PhaseChannels::default(),
+        }
+    }
+
+    #[tokio::test]
+    async fn joins_the_version_in_effect_and_applies_updates_live() {
+        let table = ReferenceTable::new();
+        table.apply(meter_reference_update(&meter(datetime!(2024-01-01 0:00 UTC), "prem-1", false)));
+
+        let updates = ReferenceUpdates::new(table.clone(), meter_reference_update);
+        let join = ReferenceJoin::new("meters", table, meter_usage_key, fill_meter_usage);
+
+        let joined = join.apply(Envelope::new(usage(datetime!(2024-06-01 0:00 UTC)))).await.unwrap();
+        assert_eq!(joined.payload.premise_id.as_deref(), Some("prem-1"));
+
+        // The meter moves to another premise, then is retired.
+        updates
+            .apply(Envelope::new(meter(datetime!(2024-07-01 0:00 UTC), "prem-2", false)))
+            .await
+            .unwrap();
+        updates
+            .apply(Envelope::new(meter(datetime!(2024-09-01 0:00 UTC), "prem-2", true)))
+            .await
+            .unwrap();
+
+        let join = &join;
+        let premise = |ts| async move {
+            join.apply(Envelope::new(usage(ts))).await.unwrap().payload.premise_id
+        };
+        assert_eq!(premise(datetime!(2024-06-30 23:45 UTC)).await.as_deref(), Some("prem-1"));
+        assert_eq!(premise(datetime!(2024-07-15 0:00 UTC)).await.as_deref(), Some("prem-2"));
+        assert_eq!(premise(datetime!(2024-09-02 0:00 UTC)).await, None);
+        // Records older than every known version use the oldest one.
+        assert_eq!(premise(datetime!(2023-06-01 0:00 UTC)).await.as_deref(), Some("prem-1"));
+    }
+}
@@ -0,0 +1,124 @@
+use std::time::SystemTime;
+
+use rust_client::domain::MeterUsage;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    pipeline::{Envelope, PipelineError, Transform},
+    sources::ChannelSource,
+};
+
+/// A record dropped by validation, persisted to `ingest_rejects` so reject
+/// rates can be reported per `source_system`.
+#[derive(Debug, Clone)]
+pub struct IngestReject {
+    pub received_at: SystemTime,
+    pub table: &'static str,
+    pub source_system: Option<String>,
+    pub record_ts: Option<OffsetDateTime>,
+    pub reason: String,
+}
+
+/// Handle used by transforms to record rejected records.
+///
+/// Recording never blocks the pipeline: if the reject channel is full the
+/// entry is dropped and counted in `ingest_reject_log_dropped_total`.
+#[derive(Clone)]
+pub struct RejectLog {
+    tx: mpsc::Sender<Envelope<IngestReject>>,
+}
+
+impl RejectLog {
+    /// Create a reject log and the source that drains it into a sink.
+    pub fn channel(capacity: usize) -> (Self, RejectLogSource) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        (Self { tx }, ChannelSource::new(rx))
+    }
+
+    pub fn record(&self, reject: IngestReject) {
+        if self.tx.try_send(Envelope::new(reject)).is_err() {
+            metrics::counter!("ingest_reject_log_dropped_total").increment(1);
+        }
+    }
+}
+
+pub type RejectLogSource = ChannelSource<IngestReject>;
+
+/// Wraps a `MeterUsage` transform and records every record it rejects.
+pub struct RecordMeterUsageRejects<Tr> {
+    inner: Tr,
+    log: RejectLog,
+}
+
+impl<Tr> RecordMeterUsageRejects<Tr> {
+    pub fn new(inner: Tr, log: RejectLog) -> Self {
+        Self { inner, log }
+    }
+}
+
+#[async_trait::async_trait]
+impl<Tr> Transform<MeterUsage, MeterUsage> for RecordMeterUsageRejects<Tr>
+where
+    Tr: Transform<MeterUsage, MeterUsage>,
+{
+    async fn apply(&self, input: Envelope<MeterUsage>) -> Result<Envelope<MeterUsage>, PipelineError> {
+        let received_at = input.received_at;
+        let record_ts = input.payload.ts;
+        let source_system = input.payload.source_system.clone();
+
+        self.inner.apply(input).await.inspect_err(|e| {
+            self.log.record(IngestReject {
+                received_at,
+                table: "meter_usage",
+                source_system,
+                record_ts: Some(record_ts),
+                reason: e.to_string(),
+            });
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{pipeline::Source, transform::MeterUsageValidation};
+    use futures::StreamExt;
+    use time::macros::datetime;
+
+    fn env(kwh: f64) -> Envelope<MeterUsage> {
+        Envelope::new(MeterUsage {
+            ts: datetime!(2024-01-01 00:00:00 UTC),
+            meter_id: "m-1".to_string(),
+            premise_id: None,
+            kwh,
+            kvarh: None,
+            kva_demand: None,
+            quality_flag: None,
+            source_system: Some("vendor-a".to_string()),
+            event_id: None,
+            phases: Default::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn records_only_rejected_envelopes() {
+        let (log, source) = RejectLog::channel(10);
+        let t = RecordMeterUsageRejects::new(MeterUsageValidation, log);
+
+        assert!(t.apply(env(1.0)).await.is_ok());
+        assert!(t.apply(env(-1.0)).await.is_err());
+        drop(t);
+
+        let rejects: Vec<_> = source.stream().await.collect().await;
+        assert_eq!(rejects.len(), 1);
+        let r = &rejects[0].as_ref().unwrap().payload;
+        assert_eq!(r.table, "meter_usage");
+        assert_eq!(r.source_system.as_deref(), Some("vendor-a"));
+        assert!(r.reason.contains("kwh must be non-negative"));
+    }
+}
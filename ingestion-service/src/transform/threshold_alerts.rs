@@ -0,0 +1,231 @@
+use std::{collections::HashMap, marker::PhantomData, sync::Mutex, time::Duration};
+
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{ThresholdOp, ThresholdRuleConfig},
+    pipeline::{Envelope, PipelineError, Transform},
+    sources::ChannelSource,
+    transform::record_fields::{table_fields, RecordFields},
+};
+
+/// A record that breached a threshold rule, written to `stream_alerts`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StreamAlert {
+    /// Timestamp of the record.
+    pub ts: OffsetDateTime,
+    pub rule: String,
+    pub table: &'static str,
+    /// `meter_id` or `plant_id` of the record.
+    pub id: String,
+    pub field: String,
+    pub op: ThresholdOp,
+    pub value: f64,
+    pub threshold: f64,
+}
+
+pub type StreamAlertSource = ChannelSource<StreamAlert>;
+
+/// Handle used by [`ThresholdAlerts`] transforms to emit alerts.
+///
+/// Emitting never blocks the pipeline: if the alert channel is full the alert
+/// is dropped and counted in `stream_alerts_dropped_total`.
+#[derive(Clone)]
+pub struct StreamAlerts {
+    tx: mpsc::Sender<Envelope<StreamAlert>>,
+}
+
+impl StreamAlerts {
+    /// Create the alert handle and the source that drains it into a sink.
+    pub fn channel(capacity: usize) -> (Self, StreamAlertSource) {
+        let (tx, rx) = mpsc::channel(capacity.max(1));
+        (Self { tx }, ChannelSource::new(rx))
+    }
+
+    pub fn emit(&self, alert: StreamAlert) {
+        metrics::counter!("stream_alerts_total", "rule" => alert.rule.clone()).increment(1);
+        tracing::warn!(
+            rule = %alert.rule,
+            table = alert.table,
+            id = %alert.id,
+            field = %alert.field,
+            value = alert.value,
+            threshold = alert.threshold,
+            ts = %alert.ts,
+            "threshold breached"
+        );
+        if self.tx.try_send(Envelope::new(alert)).is_err() {
+            metrics::counter!("stream_alerts_dropped_total").increment(1);
+        }
+    }
+}
+
+/// Check that every rule names a supported table and one of its fields.
+pub fn validate_rules(rules: &[ThresholdRuleConfig]) -> Result<(), String> {
+    for rule in rules {
+        let Some(fields) = table_fields(&rule.table) else {
+            return Err(format!("stream alert rule '{}': unsupported table '{}'", rule.name, rule.table));
+        };
+        if !fields.contains(&rule.field.as_str()) {
+            return Err(format!(
+                "stream alert rule '{}': unknown field '{}' of {} (expected one of {})",
+                rule.name,
+                rule.field,
+                rule.table,
+                fields.join(", ")
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Pass-through transform that evaluates threshold rules on each record and
+/// emits an alert per breached rule.
+///
+/// After alerting, a rule stays quiet for the same meter or plant for
+/// `cooldown` of record time, so a sustained breach raises one alert per
+/// cooldown instead of one per record. Records without the field are skipped.
+pub struct ThresholdAlerts<T> {
+    rules: Vec<ThresholdRuleConfig>,
+    cooldown: time::Duration,
+    last_alert: Mutex<HashMap<(usize, String), OffsetDateTime>>,
+    alerts: StreamAlerts,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T: RecordFields> ThresholdAlerts<T> {
+    /// Evaluate the rules of `T`'s table; rules should have passed [`validate_rules`].
+    pub fn new(rules: &[ThresholdRuleConfig], cooldown: Duration, alerts: StreamAlerts) -> Self {
+        Self {
+            rules: rules.iter().filter(|r| r.table == T::TABLE).cloned().collect(),
+            cooldown: time::Duration::try_from(cooldown).unwrap_or(time::Duration::MAX),
+            last_alert: Mutex::new(HashMap::new()),
+            alerts,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether any rule applies to `T`.
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    fn evaluate(&self, record: &T) -> Vec<StreamAlert> {
+        let id = record.record_id();
+        let ts = record.ts();
+        let mut last_alert = self.last_alert.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = Vec::new();
+        for (i, rule) in self.rules.iter().enumerate() {
+            if !rule.ids.is_empty() && !rule.ids.iter().any(|r| r == id) {
+                continue;
+            }
+            let Some(value) = record.field(&rule.field) else {
+                continue;
+            };
+            if !rule.op.breached(value, rule.threshold) {
+                continue;
+            }
+            let key = (i, id.to_string());
+            if last_alert.get(&key).is_some_and(|last| (ts - *last).abs() < self.cooldown) {
+                continue;
+            }
+            last_alert.insert(key, ts);
+            out.push(StreamAlert {
+                ts,
+                rule: rule.name.clone(),
+                table: T::TABLE,
+                id: id.to_string(),
+                field: rule.field.clone(),
+                op: rule.op,
+                value,
+                threshold: rule.threshold,
+            });
+        }
+        out
+    }
+}
+
+#[async_trait::async_trait]
+impl<T> Transform<T, T> for ThresholdAlerts<T>
+where
+    T: RecordFields + Send + Sync + 'static,
+{
+    async fn apply(&self, input: Envelope<T>) -> Result<Envelope<T>, PipelineError> {
+        for alert in self.evaluate(&input.payload) {
+            self.alerts.emit(alert);
+        }
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "threshold_alerts"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_client::domain::GenerationOutput;
+    use time::macros::datetime;
+
+    fn rule(name: &str, field: &str, op: ThresholdOp, threshold: f64, ids: &[&str]) -> ThresholdRuleConfig {
+        ThresholdRuleConfig {
+            name: name.to_string(),
+            table: "generation_output".to_string(),
+            field: field.to_string(),
+            op,
+            threshold,
+            ids: ids.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn sample(ts: OffsetDateTime, plant_id: &str, mw: f64) -> GenerationOutput {
+        GenerationOutput {
+            ts,
+            plant_id: plant_id.to_string(),
+            unit_id: None,
+            mw,
+            mvar: None,
+            status: None,
+            fuel_type: None,
+            event_id: None,
+            aux_mw: None,
+            availability_pct: None,
+            curtailed_mw: None,
+        }
+    }
+
+    #[test]
+    fn alerts_once_per_cooldown_for_matching_records() {
+        let rules = vec![
+            rule("over_capacity", "mw", ThresholdOp::Gt, 250.0, &["p-1"]),
+            rule("low_availability", "availability_pct", ThresholdOp::Lt, 50.0, &[]),
+        ];
+        validate_rules(&rules).unwrap();
+        let (alerts, _source) = StreamAlerts::channel(10);
+        let t: ThresholdAlerts<GenerationOutput> = ThresholdAlerts::new(&rules, Duration::from_secs(900), alerts);
+
+        let t0 = datetime!(2024-07-01 12:00 UTC);
+        let fired = t.evaluate(&sample(t0, "p-1", 260.0));
+        assert_eq!(fired.len(), 1);
+        assert_eq!((fired[0].rule.as_str(), fired[0].value), ("over_capacity", 260.0));
+
+        // Still breached 5 minutes later: within the cooldown.
+        assert!(t.evaluate(&sample(t0 + time::Duration::minutes(5), "p-1", 270.0)).is_empty());
+        // Another plant isn't covered by the rule; availability is unset.
+        assert!(t.evaluate(&sample(t0, "p-2", 300.0)).is_empty());
+        // After the cooldown it alerts again.
+        assert_eq!(t.evaluate(&sample(t0 + time::Duration::minutes(15), "p-1", 255.0)).len(), 1);
+    }
+
+    #[test]
+    fn rejects_unknown_tables_and_fields() {
+        let mut bad_field = rule("r", "kwh", ThresholdOp::Gt, 1.0, &[]);
+        assert!(validate_rules(std::slice::from_ref(&bad_field)).is_err());
+        bad_field.table = "meter_usage".to_string();
+        assert!(validate_rules(std::slice::from_ref(&bad_field)).is_ok());
+        bad_field.table = "dr_events".to_string();
+        assert!(validate_rules(&[bad_field]).is_err());
+    }
+}
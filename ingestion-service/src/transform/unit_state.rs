@@ -0,0 +1,217 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use rust_client::domain::GenerationOutput;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    pipeline::{Envelope, PipelineError, Transform},
+    sources::ChannelSource,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnitTransitionKind {
+    Start,
+    Stop,
+}
+
+impl UnitTransitionKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Start => "start",
+            Self::Stop => "stop",
+        }
+    }
+}
+
+/// A generating unit started or stopped, written to `unit_runtime`.
+///
+/// `run_hours` is set on a stop when the matching start was observed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitTransition {
+    pub ts: OffsetDateTime,
+    pub plant_id: String,
+    pub unit_id: Option<String>,
+    pub kind: UnitTransitionKind,
+    pub mw: f64,
+    pub run_hours: Option<f64>,
+}
+
+pub type UnitTransitionSource = ChannelSource<UnitTransition>;
+
+/// Whether a sample shows the unit running.
+///
+/// A recognized `status` wins; otherwise the unit is running when `mw > min_running_mw`.
+pub fn is_running(g: &GenerationOutput, min_running_mw: f64) -> bool {
+    let status = g.status.as_deref().map(str::to_ascii_lowercase);
+    match status.as_deref() {
+        Some("online" | "running" | "on" | "generating") => true,
+        Some("offline" | "off" | "stopped" | "outage" | "standby") => false,
+        _ => g.mw > min_running_mw,
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct UnitState {
+    last_ts: OffsetDateTime,
+    running: bool,
+    /// Start time of the current run, if it was observed.
+    started_at: Option<OffsetDateTime>,
+}
+
+/// Pass-through transform that infers unit start/stop events from
+/// `GenerationOutput` status/MW transitions.
+///
+/// State is kept per `(plant_id, unit_id)` in memory. The first sample of a
+/// unit only sets its state; samples older than the last one seen for the unit
+/// are ignored for state tracking. Transitions are sent to a side channel and
+/// dropped (and counted) if it is full.
+pub struct UnitStateTracker {
+    min_running_mw: f64,
+    units: Mutex<HashMap<(String, Option<String>), UnitState>>,
+    tx: mpsc::Sender<Envelope<UnitTransition>>,
+}
+
+impl UnitStateTracker {
+    pub fn new(min_running_mw: f64, channel_capacity: usize) -> (Self, UnitTransitionSource) {
+        let (tx, rx) = mpsc::channel(channel_capacity.max(1));
+        let tracker = Self {
+            min_running_mw,
+            units: Mutex::new(HashMap::new()),
+            tx,
+        };
+        (tracker, ChannelSource::new(rx))
+    }
+
+    /// Update the unit's state with a sample, returning the transition it caused.
+    fn observe(&self, g: &GenerationOutput) -> Option<UnitTransition> {
+        let running = is_running(g, self.min_running_mw);
+        let mut units = self.units.lock().unwrap_or_else(|e| e.into_inner());
+
+        let key = (g.plant_id.clone(), g.unit_id.clone());
+        let Some(state) = units.get_mut(&key) else {
+            units.insert(
+                key,
+                UnitState {
+                    last_ts: g.ts,
+                    running,
+                    started_at: None,
+                },
+            );
+            return None;
+        };
+
+        if g.ts < state.last_ts {
+            metrics::counter!("unit_state_out_of_order_total").increment(1);
+            return None;
+        }
+        state.last_ts = g.ts;
+
+        if running == state.running {
+            return None;
+        }
+        state.running = running;
+
+        let (kind, run_hours) = if running {
+            state.started_at = Some(g.ts);
+            (UnitTransitionKind::Start, None)
+        } else {
+            let run_hours = state
+                .started_at
+                .take()
+                .map(|start| (g.ts - start).as_seconds_f64() / 3600.0);
+            (UnitTransitionKind::Stop, run_hours)
+        };
+
+        Some(UnitTransition {
+            ts: g.ts,
+            plant_id: g.plant_id.clone(),
+            unit_id: g.unit_id.clone(),
+            kind,
+            mw: g.mw,
+            run_hours,
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl Transform<GenerationOutput, GenerationOutput> for UnitStateTracker {
+    async fn apply(&self, input: Envelope<GenerationOutput>) -> Result<Envelope<GenerationOutput>, PipelineError> {
+        if let Some(transition) = self.observe(&input.payload) {
+            metrics::counter!("unit_transitions_total", "kind" => transition.kind.as_str()).increment(1);
+            if self.tx.try_send(Envelope::new(transition)).is_err() {
+                metrics::counter!("unit_transitions_dropped_total").increment(1);
+            }
+        }
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "unit_state"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn sample(ts: OffsetDateTime, mw: f64, status: Option<&str>) -> GenerationOutput {
+        GenerationOutput {
+            ts,
+            plant_id: "plant".to_string(),
+            unit_id: Some("u1".to_string()),
+            mw,
+            mvar: None,
+            status: status.map(str::to_string),
+            fuel_type: None,
+            event_id: None,
+            aux_mw: None,
+            availability_pct: None,
+            curtailed_mw: None,
+        }
+    }
+
+    #[test]
+    fn status_takes_precedence_over_mw() {
+        let t = datetime!(2024-01-01 00:00:00 UTC);
+        assert!(is_running(&sample(t, 0.0, Some("Online")), 0.0));
+        assert!(!is_running(&sample(t, 5.0, Some("outage")), 0.0));
+        assert!(is_running(&sample(t, 5.0, None), 0.0));
+        assert!(!is_running(&sample(t, 0.5, Some("unknown")), 1.0));
+    }
+
+    #[test]
+    fn emits_start_and_stop_with_run_hours() {
+        let (tracker, _source) = UnitStateTracker::new(0.0, 10);
+
+        assert_eq!(tracker.observe(&sample(datetime!(2024-01-01 00:00:00 UTC), 0.0, None)), None);
+
+        let start = tracker
+            .observe(&sample(datetime!(2024-01-01 01:00:00 UTC), 50.0, None))
+            .unwrap();
+        assert_eq!(start.kind, UnitTransitionKind::Start);
+
+        assert_eq!(tracker.observe(&sample(datetime!(2024-01-01 02:00:00 UTC), 60.0, None)), None);
+        // Late sample: ignored.
+        assert_eq!(tracker.observe(&sample(datetime!(2024-01-01 00:30:00 UTC), 0.0, None)), None);
+
+        let stop = tracker
+            .observe(&sample(datetime!(2024-01-01 04:30:00 UTC), 0.0, Some("offline")))
+            .unwrap();
+        assert_eq!(stop.kind, UnitTransitionKind::Stop);
+        assert_eq!(stop.run_hours, Some(3.5));
+    }
+
+    #[test]
+    fn stop_without_observed_start_has_no_run_hours() {
+        let (tracker, _source) = UnitStateTracker::new(0.0, 10);
+
+        tracker.observe(&sample(datetime!(2024-01-01 00:00:00 UTC), 50.0, None));
+        let stop = tracker
+            .observe(&sample(datetime!(2024-01-01 01:00:00 UTC), 0.0, None))
+            .unwrap();
+        assert_eq!(stop.kind, UnitTransitionKind::Stop);
+        assert_eq!(stop.run_hours, None);
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    marker::PhantomData,
+    sync::{Arc, Mutex},
+};
+
+use rust_client::domain::{GenerationOutput, MeterUsage};
+use sqlx::postgres::PgPool;
+use time::OffsetDateTime;
+use tokio::sync::mpsc;
+
+use crate::{
+    config::{WindowConfig, WindowKey},
+    pipeline::{Envelope, PipelineError, Transform},
+    sources::ChannelSource,
+    transform::record_fields::{table_fields, RecordFields},
+};
+
+/// Aggregate of one field over one window and key, written to `window_aggregates`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowAggregate {
+    /// Window start.
+    pub ts: OffsetDateTime,
+    pub window: String,
+    pub table: &'static str,
+    pub key_by: WindowKey,
+    pub key: String,
+    pub field: String,
+    pub window_secs: i64,
+    pub count: i64,
+    pub sum: f64,
+    pub avg: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+pub type WindowAggregateSource = ChannelSource<WindowAggregate>;
+
+/// Current meter and plant to feeder assignments, for [`WindowKey::Feeder`].
+#[derive(Debug, Default)]
+pub struct FeederMap {
+    meters: HashMap<String, String>,
+    /// By `(plant_id, unit_id)`; `unit_id` is `None` for plant-wide rows.
+    plants: HashMap<(String, Option<String>), String>,
+}
+
+impl FeederMap {
+    /// Load the rows of `meter_feeder_map` and `plant_feeder_map` valid now.
+    pub async fn load(pool: &PgPool) -> Result<Self, sqlx::Error> {
+        let meters: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT meter_id, feeder_id FROM meter_feeder_map \
+             WHERE from_ts <= now() AND (to_ts IS NULL OR to_ts > now())",
+        )
+        .fetch_all(pool)
+        .await?;
+        let plants: Vec<(String, Option<String>, Option<String>)> = sqlx::query_as(
+            "SELECT plant_id, unit_id, feeder_id FROM plant_feeder_map \
+             WHERE from_ts <= now() AND (to_ts IS NULL OR to_ts > now())",
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(Self {
+            meters: meters
+                .into_iter()
+                .filter_map(|(meter, feeder)| Some((meter, feeder?)))
+                .collect(),
+            plants: plants
+                .into_iter()
+                .filter_map(|(plant, unit, feeder)| Some(((plant, unit), feeder?)))
+                .collect(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.meters.len() + self.plants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A record type that can be aggregated by [`WindowAggregator`].
+pub trait WindowRecord: RecordFields {
+    /// Feeder the record's meter or plant is currently assigned to.
+    fn feeder<'a>(&self, feeders: &'a FeederMap) -> Option<&'a str>;
+}
+
+impl WindowRecord for MeterUsage {
+    fn feeder<'a>(&self, feeders: &'a FeederMap) -> Option<&'a str> {
+        feeders.meters.get(&self.meter_id).map(String::as_str)
+    }
+}
+
+impl WindowRecord for GenerationOutput {
+    /// The unit's feeder, else the plant-wide one.
+    fn feeder<'a>(&self, feeders: &'a FeederMap) -> Option<&'a str> {
+        let mut key = (self.plant_id.clone(), self.unit_id.clone());
+        if let Some(feeder) = feeders.plants.get(&key) {
+            return Some(feeder);
+        }
+        key.1 = None;
+        feeders.plants.get(&key).map(String::as_str)
+    }
+}
+
+/// Check windows name a supported table, field and key, and sizes that fit.
+pub fn validate_windows(windows: &[WindowConfig]) -> Result<(), String> {
+    for w in windows {
+        let Some(fields) = table_fields(&w.table) else {
+            return Err(format!("window '{}': unsupported table '{}'", w.name, w.table));
+        };
+        if !fields.contains(&w.field.as_str()) {
+            return Err(format!(
+                "window '{}': unknown field '{}' of {} (expected one of {})",
+                w.name,
+                w.field,
+                w.table,
+                fields.join(", ")
+            ));
+        }
+        let key_ok = match w.key_by {
+            WindowKey::Meter => w.table == MeterUsage::TABLE,
+            WindowKey::Plant => w.table == GenerationOutput::TABLE,
+            WindowKey::Feeder => true,
+        };
+        if !key_ok {
+            return Err(format!("window '{}': cannot key {} by {}", w.name, w.table, w.key_by.as_str()));
+        }
+        let slide = w.slide_secs.unwrap_or(w.size_secs);
+        if w.size_secs == 0 || slide == 0 || w.size_secs % slide != 0 {
+            return Err(format!(
+                "window '{}': size_secs must be positive and a multiple of slide_secs",
+                w.name
+            ));
+        }
+        if w.size_secs / slide > 60 {
+            return Err(format!("window '{}': at most 60 slides per window", w.name));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Accumulator {
+    count: i64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Accumulator {
+    fn new(value: f64) -> Self {
+        Self {
+            count: 1,
+            sum: value,
+            min: value,
+            max: value,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+}
+
+/// Open windows of one [`WindowConfig`], by start (unix seconds) and key.
+struct WindowState {
+    cfg: WindowConfig,
+    size: i64,
+    slide: i64,
+    /// Latest record time seen (unix seconds), capped at the wall clock.
+    watermark: Option<i64>,
+    open: BTreeMap<i64, HashMap<String, Accumulator>>,
+}
+
+impl WindowState {
+    fn new(cfg: WindowConfig) -> Self {
+        let size = cfg.size_secs as i64;
+        let slide = cfg.slide_secs.map_or(size, |s| s as i64);
+        Self {
+            cfg,
+            size,
+            slide,
+            watermark: None,
+            open: BTreeMap::new(),
+        }
+    }
+
+    /// Add a value to each window containing `ts` that is still open. Returns
+    /// `false` if all of them were already emitted.
+    fn add(&mut self, ts: i64, key: &str, value: f64, lateness: i64) -> bool {
+        let cutoff = self.watermark.map(|w| w - lateness);
+        let last_start = ts.div_euclid(self.slide) * self.slide;
+        let mut accepted = false;
+        for k in 0..self.size / self.slide {
+            let start = last_start - k * self.slide;
+            if cutoff.is_some_and(|c| start + self.size <= c) {
+                continue;
+            }
+            self.open
+                .entry(start)
+                .or_default()
+                .entry(key.to_string())
+                .and_modify(|acc| acc.add(value))
+                .or_insert_with(|| Accumulator::new(value));
+            accepted = true;
+        }
+        accepted
+    }
+
+    /// Advance the watermark and emit the windows that ended `lateness` before it.
+    fn advance(&mut self, ts: i64, now: i64, lateness: i64, table: &'static str, out: &mut Vec<WindowAggregate>) {
+        let watermark = self.watermark.map_or(ts, |w| w.max(ts)).min(now);
+        self.watermark = Some(watermark);
+        let cutoff = watermark - lateness;
+        while let Some(entry) = self.open.first_entry() {
+            if *entry.key() + self.size > cutoff {
+                break;
+            }
+            let (start, accs) = entry.remove_entry();
+            let Ok(ts) = OffsetDateTime::from_unix_timestamp(start) else {
+                continue;
+            };
+            out.extend(accs.into_iter().map(|(key, acc)| WindowAggregate {
+                ts,
+                window: self.cfg.name.clone(),
+                table,
+                key_by: self.cfg.key_by,
+                key,
+                field: self.cfg.field.clone(),
+                window_secs: self.size,
+                count: acc.count,
+                sum: acc.sum,
+                avg: acc.sum / acc.count as f64,
+                min: acc.min,
+                max: acc.max,
+            }));
+        }
+    }
+}
+
+/// Pass-through transform that aggregates a field over tumbling or sliding
+/// event-time windows per meter, plant or feeder, and sends the results to a
+/// side channel (e.g. a 5-minute rolling MW average per plant).
+///
+/// A window is emitted once a record `allowed_lateness` past its end was seen
+/// (by any key); later records for it are dropped and counted. The watermark
+/// never runs ahead of the wall clock, so a record with a future timestamp
+/// can't close windows early. Open windows are kept in memory and lost on
+/// restart. Aggregates are dropped (and counted) if the channel is full.
+pub struct WindowAggregator<T> {
+    windows: Mutex<Vec<WindowState>>,
+    lateness: i64,
+    feeders: Arc<FeederMap>,
+    tx: mpsc::Sender<Envelope<WindowAggregate>>,
+    _marker: PhantomData<fn(T)>,
+}
+
+impl<T: WindowRecord> WindowAggregator<T> {
+    /// Aggregate the windows on `T`'s table; windows should have passed [`validate_windows`].
+    pub fn new(
+        windows: &[WindowConfig],
+        allowed_lateness_secs: u64,
+        feeders: Arc<FeederMap>,
+        tx: mpsc::Sender<Envelope<WindowAggregate>>,
+    ) -> Self {
+        Self {
+            windows: Mutex::new(
+                windows
+                    .iter()
+                    .filter(|w| w.table == T::TABLE)
+                    .cloned()
+                    .map(WindowState::new)
+                    .collect(),
+            ),
+            lateness: allowed_lateness_secs as i64,
+            feeders,
+            tx,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Whether any window applies to `T`.
+    pub fn is_empty(&self) -> bool {
+        self.windows.lock().unwrap_or_else(|e| e.into_inner()).is_empty()
+    }
+
+    fn observe(&self, record: &T, now: OffsetDateTime) -> Vec<WindowAggregate> {
+        let ts = record.ts().unix_timestamp();
+        let now = now.unix_timestamp();
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let mut out = Vec::new();
+        for w in windows.iter_mut() {
+            if let Some(value) = record.field(&w.cfg.field) {
+                let key = match w.cfg.key_by {
+                    WindowKey::Meter | WindowKey::Plant => Some(record.record_id()),
+                    WindowKey::Feeder => record.feeder(&self.feeders),
+                };
+                match key {
+                    Some(key) => {
+                        if !w.add(ts, key, value, self.lateness) {
+                            metrics::counter!("window_aggregate_late_total", "window" => w.cfg.name.clone())
+                                .increment(1);
+                        }
+                    }
+                    None => {
+                        metrics::counter!("window_aggregate_unmapped_total", "window" => w.cfg.name.clone())
+                            .increment(1);
+                    }
+                }
+            }
+            w.advance(ts, now, self.lateness, T::TABLE, &mut out);
+        }
+        out
+    }
+}
+
+/// Create the channel aggregators send to and the source that drains it into a sink.
+pub fn channel(capacity: usize) -> (mpsc::Sender<Envelope<WindowAggregate>>, WindowAggregateSource) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    (tx, ChannelSource::new(rx))
+}
+
+#[async_trait::async_trait]
+impl<T> Transform<T, T> for WindowAggregator<T>
+where
+    T: WindowRecord + Send + Sync + 'static,
+{
+    async fn apply(&self, input: Envelope<T>) -> Result<Envelope<T>, PipelineError> {
+        for aggregate in self.observe(&input.payload, OffsetDateTime::now_utc()) {
+            metrics::counter!("window_aggregates_total", "window" => aggregate.window.clone()).increment(1);
+            if self.tx.try_send(Envelope::new(aggregate)).is_err() {
+                metrics::counter!("window_aggregates_dropped_total").increment(1);
+            }
+        }
+        Ok(input)
+    }
+
+    fn name(&self) -> &'static str {
+        "window_aggregate"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn window(key_by: WindowKey, size_secs: u64, slide_secs: Option<u64>) -> WindowConfig {
+        WindowConfig {
+            name: "mw_5m".to_string(),
+            table: "generation_output".to_string(),
+            field: "mw".to_string(),
+            key_by,
+            size_secs,
+            slide_secs,
+        }
+    }
+
+    fn sample(ts: OffsetDateTime, plant_id: &str, unit_id: Option<&str>, mw: f64) -> GenerationOutput {
+        GenerationOutput {
+            ts,
+            plant_id: plant_id.to_string(),
+            unit_id: unit_id.map(str::to_string),
+            mw,
+            mvar: None,
+            status: None,
+            fuel_type: None,
+            event_id: None,
+            aux_mw: None,
+            availability_pct: None,
+            curtailed_mw: None,
+        }
+    }
+
+    fn aggregator(windows: &[WindowConfig], feeders: FeederMap) -> WindowAggregator<GenerationOutput> {
+        validate_windows(windows).unwrap();
+        let (tx, _source) = channel(10);
+        WindowAggregator::new(windows, 0, Arc::new(feeders), tx)
+    }
+
+    #[test]
+    fn tumbling_windows_emit_once_the_watermark_passes() {
+        let agg = aggregator(&[window(WindowKey::Plant, 300, None)], FeederMap::default());
+        let now = datetime!(2024-07-01 13:00 UTC);
+        let t0 = datetime!(2024-07-01 12:00 UTC);
+
+        assert!(agg.observe(&sample(t0, "p-1", None, 100.0), now).is_empty());
+        assert!(agg.observe(&sample(t0 + time::Duration::minutes(2), "p-1", None, 120.0), now).is_empty());
+        assert!(agg.observe(&sample(t0 + time::Duration::minutes(4), "p-2", None, 50.0), now).is_empty());
+
+        let mut out = agg.observe(&sample(t0 + time::Duration::minutes(5), "p-1", None, 90.0), now);
+        out.sort_by(|a, b| a.key.cmp(&b.key));
+        assert_eq!(out.len(), 2);
+        assert_eq!((out[0].key.as_str(), out[0].ts, out[0].count), ("p-1", t0, 2));
+        assert_eq!((out[0].avg, out[0].min, out[0].max), (110.0, 100.0, 120.0));
+        assert_eq!((out[1].key.as_str(), out[1].sum), ("p-2", 50.0));
+
+        // The 12:00 window was emitted: a late record is dropped.
+        assert!(!agg.windows.lock().unwrap()[0].open.contains_key(&t0.unix_timestamp()));
+        agg.observe(&sample(t0 + time::Duration::minutes(1), "p-1", None, 500.0), now);
+        assert!(!agg.windows.lock().unwrap()[0].open.contains_key(&t0.unix_timestamp()));
+
+        // A future record doesn't move the watermark past the wall clock.
+        assert_eq!(agg.observe(&sample(now + time::Duration::hours(1), "p-1", None, 1.0), now).len(), 1);
+    }
+
+    #[test]
+    fn sliding_windows_by_feeder() {
+        let mut feeders = FeederMap::default();
+        feeders.plants.insert(("p-1".to_string(), None), "f-1".to_string());
+        feeders.plants.insert(("p-2".to_string(), Some("u-1".to_string())), "f-1".to_string());
+        let agg = aggregator(&[window(WindowKey::Feeder, 300, Some(60))], feeders);
+        let now = datetime!(2024-07-01 13:00 UTC);
+        let t0 = datetime!(2024-07-01 12:00 UTC);
+
+        agg.observe(&sample(t0, "p-1", Some("u-9"), 10.0), now);
+        agg.observe(&sample(t0 + time::Duration::seconds(30), "p-2", Some("u-1"), 20.0), now);
+        agg.observe(&sample(t0 + time::Duration::seconds(30), "p-3", None, 99.0), now); // unmapped
+
+        // 12:01 closes the window starting at 11:56, which holds both samples.
+        let out = agg.observe(&sample(t0 + time::Duration::minutes(1), "p-1", None, 30.0), now);
+        assert_eq!(out.len(), 1);
+        assert_eq!((out[0].key.as_str(), out[0].window_secs), ("f-1", 300));
+        assert_eq!((out[0].ts, out[0].sum, out[0].count), (datetime!(2024-07-01 11:56 UTC), 30.0, 2));
+    }
+
+    #[test]
+    fn rejects_windows_that_do_not_fit() {
+        assert!(validate_windows(&[window(WindowKey::Meter, 300, None)]).is_err());
+        assert!(validate_windows(&[window(WindowKey::Plant, 300, Some(70))]).is_err());
+        assert!(validate_windows(&[window(WindowKey::Plant, 3600, Some(1))]).is_err());
+        assert!(validate_windows(&[window(WindowKey::Plant, 300, Some(60))]).is_ok());
+    }
+}
@@ -0,0 +1,169 @@
+//! Exactly-once backfill: a load that crashes mid-file and is restarted stores
+//! every record once, without duplicates or gaps.
+
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use ingestion_service::{
+    config::EventIdStrategy,
+    pipeline::{Envelope, Pipeline, PipelineError},
+    sinks::{meter_usage_event_id, StoreSink, TimeSeriesStore},
+    sources::{Checkpoint, CheckpointedSource, MeterUsageBackfillFileSource},
+    transform::MeterUsageValidation,
+};
+use rust_client::domain::MeterUsage;
+use time::{macros::datetime, OffsetDateTime};
+use tokio::sync::Notify;
+
+const RECORDS: usize = 100;
+const BATCH: usize = 10;
+/// Line with a negative reading, rejected by validation.
+const INVALID_LINE: usize = 37;
+
+#[derive(Default)]
+struct Table {
+    /// Rows by `(ts, event_id)`, like a QuestDB table with `DEDUP UPSERT KEYS(ts, event_id)`.
+    rows: HashMap<(OffsetDateTime, String), MeterUsage>,
+    inserted: usize,
+    batches: usize,
+}
+
+/// In-memory deduplicating table. With `crash_after`, the n-th batch is
+/// written but never acknowledged, like a process dying right after the write.
+#[derive(Clone, Default)]
+struct DedupStore {
+    table: Arc<Mutex<Table>>,
+    crash_after: Option<usize>,
+    crashed: Arc<Notify>,
+}
+
+#[async_trait::async_trait]
+impl TimeSeriesStore<MeterUsage> for DedupStore {
+    fn name(&self) -> &'static str {
+        "dedup_memory"
+    }
+
+    async fn ensure_schema(&self) -> Result<(), PipelineError> {
+        Ok(())
+    }
+
+    async fn insert_batch(&self, batch: &[Envelope<MeterUsage>]) -> Result<(), PipelineError> {
+        let batches = {
+            let mut table = self.table.lock().unwrap();
+            for env in batch {
+                let m = &env.payload;
+                let key = (m.ts, meter_usage_event_id(EventIdStrategy::ContentHash, m));
+                table.rows.insert(key, m.clone());
+            }
+            table.inserted += batch.len();
+            table.batches += 1;
+            table.batches
+        };
+        if Some(batches) == self.crash_after {
+            self.crashed.notify_one();
+            std::future::pending::<()>().await;
+        }
+        Ok(())
+    }
+
+    async fn health(&self) -> Result<(), PipelineError> {
+        Ok(())
+    }
+}
+
+fn write_backfill_file(path: &Path) {
+    let start = datetime!(2024-01-01 00:00:00 UTC);
+    let lines: String = (1..=RECORDS)
+        .map(|i| {
+            let ts = start + time::Duration::minutes(15 * i as i64);
+            let kwh = if i == INVALID_LINE { -1.0 } else { i as f64 + 0.5 };
+            let ts = serde_json::to_string(&ts).unwrap();
+            format!("{{\"ts\":{ts},\"meter_id\":\"m-{}\",\"kwh\":{kwh}}}\n", i % 5)
+        })
+        .collect();
+    std::fs::write(path, lines).unwrap();
+}
+
+/// Run one backfill of `file` into `store`; returns the settled record count.
+async fn backfill(file: &Path, store: DedupStore) -> Result<u64, PipelineError> {
+    let source = CheckpointedSource::new(MeterUsageBackfillFileSource::new(file), file, Checkpoint::for_file(file))
+        .with_save_every(BATCH as u64);
+    let progress = source.progress();
+    let pipeline: Pipeline<_, MeterUsage, _> = Pipeline {
+        source,
+        transforms: vec![Arc::new(MeterUsageValidation)],
+        sink: StoreSink::new(store, BATCH, 0, Duration::from_millis(1), Duration::from_secs(60)),
+    };
+    pipeline.run().await?;
+    progress.settled().await
+}
+
+#[tokio::test]
+async fn exactly_once_backfill_survives_a_crash_mid_file() {
+    let dir = std::env::temp_dir().join(format!("exactly-once-{}", uuid::Uuid::now_v7()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("usage.ndjson");
+    write_backfill_file(&file);
+
+    // First run: the 4th batch reaches the table, then the process "dies".
+    let store = DedupStore {
+        crash_after: Some(4),
+        ..Default::default()
+    };
+    let crashed = store.crashed.clone();
+    let mut first = tokio::spawn({
+        let (file, store) = (file.clone(), store.clone());
+        async move { backfill(&file, store).await }
+    });
+    tokio::select! {
+        _ = crashed.notified() => {}
+        result = &mut first => panic!("first run ended before the crash: {result:?}"),
+    }
+    first.abort();
+    assert!(first.await.unwrap_err().is_cancelled());
+
+    // The checkpoint only covers the three acknowledged batches (saves may still be landing).
+    let checkpoint = Checkpoint::for_file(&file);
+    let mut saved = 0;
+    for _ in 0..100 {
+        saved = checkpoint.load().await.unwrap().map_or(0, |s| s.records);
+        if saved == 3 * BATCH as u64 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    assert_eq!(saved, 3 * BATCH as u64);
+    assert_eq!(store.table.lock().unwrap().inserted, 4 * BATCH);
+
+    // Restart: resumes after record 30, re-sends the unacknowledged batch and finishes the file.
+    let restarted = DedupStore {
+        table: store.table.clone(),
+        ..Default::default()
+    };
+    let settled = backfill(&file, restarted.clone()).await.unwrap();
+    assert_eq!(settled, RECORDS as u64, "the rejected line doesn't hold the checkpoint back");
+
+    let inserted = {
+        let table = store.table.lock().unwrap();
+        assert!(table.inserted > RECORDS - 1, "the crashed batch was sent again");
+        assert_eq!(table.rows.len(), RECORDS - 1, "one row per valid record");
+        let mut kwh: Vec<f64> = table.rows.values().map(|m| m.kwh).collect();
+        kwh.sort_by(f64::total_cmp);
+        let expected: Vec<f64> = (1..=RECORDS)
+            .filter(|i| *i != INVALID_LINE)
+            .map(|i| i as f64 + 0.5)
+            .collect();
+        assert_eq!(kwh, expected);
+        table.inserted
+    };
+
+    // A completed load is not sent again.
+    assert_eq!(backfill(&file, restarted).await.unwrap(), RECORDS as u64);
+    assert_eq!(store.table.lock().unwrap().inserted, inserted);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,215 @@
+use anyhow::{bail, Result};
+use sqlx::{PgPool, Row};
+use time::OffsetDateTime;
+
+use crate::db::resample_queries::{sql_timestamp, Granularity};
+
+/// A series that can be aligned with others on a common time grid.
+#[derive(Debug, Clone, Copy)]
+pub enum AlignedSeries<'a> {
+    /// kWh of all meters mapped to the feeder (scaled by `meter_scale_map`), summed per bucket.
+    FeederLoadKwh(&'a str),
+    /// MW of all plants/units mapped to the feeder, summed per timestamp and averaged per bucket.
+    FeederGenerationMw(&'a str),
+    /// Air temperature (°C) at a weather station, averaged per bucket.
+    WeatherTemperatureC(&'a str),
+}
+
+/// How the 2nd and later series are matched to the base grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignJoin {
+    /// Value of the same bucket, or the latest earlier one (`ASOF JOIN`).
+    Asof,
+    /// Latest value strictly before the bucket (`LT JOIN`), e.g. for lagged correlation.
+    Lt,
+}
+
+/// One grid timestamp with a value per requested series, in request order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlignedRow {
+    pub ts: OffsetDateTime,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Per-timestamp rows for one series; the key is bound as `$param`.
+fn series_rows_sql(series: &AlignedSeries<'_>, param: usize) -> String {
+    match series {
+        AlignedSeries::FeederLoadKwh(_) => format!(
+            "SELECT mu.ts, sum(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS value
+            FROM meter_usage mu
+            JOIN meter_feeder_map mfm
+              ON mfm.meter_id = mu.meter_id
+             AND mfm.from_ts <= mu.ts
+             AND mfm.to_ts   >  mu.ts
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mfm.feeder_id = ${param}
+              AND mu.ts >= $1
+              AND mu.ts <  $2
+            GROUP BY mu.ts"
+        ),
+        AlignedSeries::FeederGenerationMw(_) => format!(
+            "SELECT go.ts, sum(go.mw) AS value
+            FROM generation_output go
+            JOIN plant_feeder_map pfm
+              ON pfm.plant_id = go.plant_id
+             AND (pfm.unit_id IS NULL OR pfm.unit_id = go.unit_id)
+             AND pfm.from_ts <= go.ts
+             AND pfm.to_ts   >  go.ts
+            WHERE pfm.feeder_id = ${param}
+              AND go.ts >= $1
+              AND go.ts <  $2
+            GROUP BY go.ts"
+        ),
+        AlignedSeries::WeatherTemperatureC(_) => format!(
+            "SELECT ts, temperature_c AS value
+            FROM weather_observations
+            WHERE station_id = ${param}
+              AND ts >= $1
+              AND ts <  $2"
+        ),
+    }
+}
+
+fn bucket_aggregation(series: &AlignedSeries<'_>) -> &'static str {
+    match series {
+        AlignedSeries::FeederLoadKwh(_) => "sum",
+        AlignedSeries::FeederGenerationMw(_) | AlignedSeries::WeatherTemperatureC(_) => "avg",
+    }
+}
+
+/// Build the alignment query.
+///
+/// Bind parameters: `$1` start, `$2` end, then one key per series (`$3`, ...).
+/// The first series defines the grid: every bucket in `[start, end)` is present
+/// (`FILL(NULL)`). Other series are resampled the same way and joined to it.
+pub fn aligned_series_sql(
+    series: &[AlignedSeries<'_>],
+    join: AlignJoin,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    granularity: Granularity,
+) -> String {
+    let ctes: Vec<String> = series
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            let grid = if i == 0 {
+                format!(
+                    " FROM '{}' TO '{}' FILL(NULL)",
+                    sql_timestamp(start),
+                    sql_timestamp(end)
+                )
+            } else {
+                String::new()
+            };
+            format!(
+                "s{i} AS (
+            SELECT ts, {agg}(value) AS value
+            FROM (
+            {rows}
+            ) timestamp(ts)
+            SAMPLE BY {granularity}{grid} ALIGN TO CALENDAR
+        )",
+                agg = bucket_aggregation(s),
+                rows = series_rows_sql(s, i + 3),
+            )
+        })
+        .collect();
+
+    let columns: Vec<String> = (0..series.len())
+        .map(|i| format!("s{i}.value AS v{i}"))
+        .collect();
+    let join_kw = match join {
+        AlignJoin::Asof => "ASOF JOIN",
+        AlignJoin::Lt => "LT JOIN",
+    };
+    let joins: String = (1..series.len())
+        .map(|i| format!("\n        {join_kw} s{i}"))
+        .collect();
+
+    format!(
+        "WITH
+        {ctes}
+        SELECT s0.ts, {columns}
+        FROM s0{joins}
+        ORDER BY s0.ts",
+        ctes = ctes.join(",\n        "),
+        columns = columns.join(", "),
+    )
+}
+
+/// Fetch several series aligned on one time grid in a single query.
+///
+/// E.g. feeder load vs. feeder generation vs. temperature, for correlation
+/// plots without client-side alignment.
+pub async fn aligned_series(
+    pool: &PgPool,
+    series: &[AlignedSeries<'_>],
+    join: AlignJoin,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    granularity: Granularity,
+) -> Result<Vec<AlignedRow>> {
+    if series.is_empty() {
+        bail!("aligned_series needs at least one series");
+    }
+
+    let sql = aligned_series_sql(series, join, start, end, granularity);
+    let mut query = sqlx::query(&sql).bind(start).bind(end);
+    for s in series {
+        let key = match s {
+            AlignedSeries::FeederLoadKwh(id)
+            | AlignedSeries::FeederGenerationMw(id)
+            | AlignedSeries::WeatherTemperatureC(id) => *id,
+        };
+        query = query.bind(key);
+    }
+
+    let rows = query.fetch_all(pool).await?;
+    rows.iter()
+        .map(|row| {
+            let values = (0..series.len())
+                .map(|i| row.try_get::<Option<f64>, _>(i + 1))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(AlignedRow {
+                ts: row.try_get("ts")?,
+                values,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn aligned_sql_joins_later_series_onto_the_first() {
+        let sql = aligned_series_sql(
+            &[
+                AlignedSeries::FeederLoadKwh("f-1"),
+                AlignedSeries::FeederGenerationMw("f-1"),
+                AlignedSeries::WeatherTemperatureC("ws-9"),
+            ],
+            AlignJoin::Asof,
+            datetime!(2024-01-01 00:00:00 UTC),
+            datetime!(2024-01-02 00:00:00 UTC),
+            Granularity::hours(1),
+        );
+
+        assert!(sql.contains("SELECT s0.ts, s0.value AS v0, s1.value AS v1, s2.value AS v2"));
+        assert!(sql.contains("FROM s0\n        ASOF JOIN s1\n        ASOF JOIN s2"));
+        assert!(sql.contains("WHERE mfm.feeder_id = $3"));
+        assert!(sql.contains("WHERE pfm.feeder_id = $4"));
+        assert!(sql.contains("WHERE station_id = $5"));
+        // Only the base series fills the full grid.
+        assert_eq!(sql.matches("FILL(NULL)").count(), 1);
+        assert!(sql.contains(
+            "SAMPLE BY 1h FROM '2024-01-01T00:00:00Z' TO '2024-01-02T00:00:00Z' FILL(NULL)"
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+use std::{collections::HashSet, future::Future};
+
+use anyhow::Result;
+
+/// Ids bound per query by [`for_id_chunks`].
+///
+/// Lists are bound as a single array (`= ANY($n)`), so this is not a protocol
+/// limit but keeps each statement and its result set reasonably small.
+pub const DEFAULT_ID_CHUNK_SIZE: usize = 1_000;
+
+/// Split `ids` into de-duplicated chunks of at most `chunk_size`, keeping first-seen order.
+pub fn id_chunks(ids: &[String], chunk_size: usize) -> Vec<Vec<String>> {
+    let mut seen = HashSet::with_capacity(ids.len());
+    let unique: Vec<String> = ids
+        .iter()
+        .filter(|id| seen.insert(id.as_str()))
+        .cloned()
+        .collect();
+
+    unique
+        .chunks(chunk_size.max(1))
+        .map(<[String]>::to_vec)
+        .collect()
+}
+
+/// Run `query` once per chunk of `ids` and concatenate the results.
+///
+/// For queries filtering on an arbitrarily large id list (e.g. all meters of a
+/// region). Chunks run one after another; results are in chunk order, so
+/// callers that need a global order or per-key totals across chunks must merge.
+pub async fn for_id_chunks<T, F, Fut>(
+    ids: &[String],
+    chunk_size: usize,
+    mut query: F,
+) -> Result<Vec<T>>
+where
+    F: FnMut(Vec<String>) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let mut out = Vec::new();
+    for chunk in id_chunks(ids, chunk_size) {
+        out.extend(query(chunk).await?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_are_deduplicated_and_bounded() {
+        let ids: Vec<String> = ["a", "b", "a", "c", "d", "b", "e"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let chunks = id_chunks(&ids, 2);
+        assert_eq!(chunks, vec![vec!["a", "b"], vec!["c", "d"], vec!["e"]]);
+        assert!(id_chunks(&[], 2).is_empty());
+    }
+}
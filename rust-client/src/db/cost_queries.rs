@@ -0,0 +1,199 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+/// Energy to be priced.
+#[derive(Debug, Clone, Copy)]
+pub enum CostSubject<'a> {
+    /// `meter_usage.kwh` of one meter, scaled by `meter_scale_map.kwh_multiplier`.
+    Meter(&'a str),
+    /// Scaled kWh of all meters mapped to the feeder, summed per timestamp.
+    FeederLoad(&'a str),
+    /// `feeder_energy_balance.loss_kwh` of the feeder; intervals without a loss (incomplete) are skipped.
+    FeederLosses(&'a str),
+}
+
+/// The `nodal_price` series used for pricing.
+#[derive(Debug, Clone, Copy)]
+pub struct PriceNode<'a> {
+    pub node_id: &'a str,
+    /// Market (e.g. `"DA"`, `"RT"`); `None` if the node only has one.
+    pub market: Option<&'a str>,
+}
+
+/// One usage interval with the price in effect at its `ts`.
+///
+/// `price_per_mwh` and `cost` are NULL when no price was published before `ts`.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct CostPoint {
+    pub ts: OffsetDateTime,
+    pub kwh: f64,
+    pub price_per_mwh: Option<f64>,
+    pub cost: Option<f64>,
+}
+
+/// Totals over a window; `unpriced_kwh` is energy without a price (not in `cost`).
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct CostTotal {
+    pub kwh: Option<f64>,
+    pub cost: Option<f64>,
+    pub unpriced_kwh: Option<f64>,
+}
+
+/// Usage rows for the subject; `$1` start, `$2` end, `$3` subject key.
+fn usage_sql(subject: &CostSubject<'_>) -> &'static str {
+    match subject {
+        CostSubject::Meter(_) => {
+            "SELECT mu.ts, mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) AS kwh
+            FROM meter_usage mu
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mu.meter_id = $3
+              AND mu.ts >= $1
+              AND mu.ts <  $2"
+        }
+        CostSubject::FeederLoad(_) => {
+            "SELECT mu.ts, sum(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS kwh
+            FROM meter_usage mu
+            JOIN meter_feeder_map mfm
+              ON mfm.meter_id = mu.meter_id
+             AND mfm.from_ts <= mu.ts
+             AND mfm.to_ts   >  mu.ts
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mfm.feeder_id = $3
+              AND mu.ts >= $1
+              AND mu.ts <  $2
+            GROUP BY mu.ts
+            ORDER BY mu.ts"
+        }
+        CostSubject::FeederLosses(_) => {
+            "SELECT ts, loss_kwh AS kwh
+            FROM feeder_energy_balance
+            WHERE feeder_id = $3
+              AND ts >= $1
+              AND ts <  $2
+              AND loss_kwh IS NOT NULL"
+        }
+    }
+}
+
+/// Build the cost series query.
+///
+/// Bind parameters: `$1` start, `$2` end, `$3` subject key, `$4` node id and,
+/// with a market, `$5` market. Each usage row is `ASOF JOIN`ed to the latest
+/// price at or before its `ts`; prices are per MWh, usage is in kWh.
+pub fn energy_cost_sql(subject: &CostSubject<'_>, node: &PriceNode<'_>) -> String {
+    let market_filter = if node.market.is_some() {
+        "\n              AND market = $5"
+    } else {
+        ""
+    };
+
+    format!(
+        "SELECT u.ts, u.kwh, p.price_per_mwh, u.kwh / 1000.0 * p.price_per_mwh AS cost
+        FROM (
+            {usage}
+        ) u
+        ASOF JOIN (
+            SELECT ts, price_per_mwh
+            FROM nodal_price
+            WHERE node_id = $4{market_filter}
+        ) p
+        ORDER BY u.ts",
+        usage = usage_sql(subject),
+    )
+}
+
+/// Build the total cost query over the same rows as [`energy_cost_sql`].
+pub fn energy_cost_total_sql(subject: &CostSubject<'_>, node: &PriceNode<'_>) -> String {
+    format!(
+        "SELECT
+            sum(kwh) AS kwh,
+            sum(cost) AS cost,
+            sum(CASE WHEN price_per_mwh IS NULL THEN kwh END) AS unpriced_kwh
+        FROM (
+        {}
+        )",
+        energy_cost_sql(subject, node)
+    )
+}
+
+fn subject_key<'a>(subject: &CostSubject<'a>) -> &'a str {
+    match *subject {
+        CostSubject::Meter(id) | CostSubject::FeederLoad(id) | CostSubject::FeederLosses(id) => id,
+    }
+}
+
+/// Energy cost time series for a meter, feeder load or feeder losses in `[start, end)`.
+pub async fn energy_cost_series(
+    pool: &PgPool,
+    subject: CostSubject<'_>,
+    node: PriceNode<'_>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<CostPoint>> {
+    let sql = energy_cost_sql(&subject, &node);
+    let mut query = sqlx::query_as::<_, CostPoint>(&sql)
+        .bind(start)
+        .bind(end)
+        .bind(subject_key(&subject))
+        .bind(node.node_id);
+    if let Some(market) = node.market {
+        query = query.bind(market);
+    }
+
+    Ok(query.fetch_all(pool).await?)
+}
+
+/// Total energy and cost in `[start, end)`, e.g. for cost-of-losses reports.
+pub async fn energy_cost_total(
+    pool: &PgPool,
+    subject: CostSubject<'_>,
+    node: PriceNode<'_>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<CostTotal> {
+    let sql = energy_cost_total_sql(&subject, &node);
+    let mut query = sqlx::query_as::<_, CostTotal>(&sql)
+        .bind(start)
+        .bind(end)
+        .bind(subject_key(&subject))
+        .bind(node.node_id);
+    if let Some(market) = node.market {
+        query = query.bind(market);
+    }
+
+    Ok(query.fetch_one(pool).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cost_sql_asof_joins_prices_of_the_node() {
+        let node = PriceNode {
+            node_id: "N1",
+            market: Some("DA"),
+        };
+        let sql = energy_cost_sql(&CostSubject::FeederLosses("f-1"), &node);
+        assert!(sql.contains("FROM feeder_energy_balance"));
+        assert!(sql.contains(") u\n        ASOF JOIN ("));
+        assert!(sql.contains("WHERE node_id = $4\n              AND market = $5"));
+        assert!(sql.contains("u.kwh / 1000.0 * p.price_per_mwh AS cost"));
+
+        let node = PriceNode {
+            node_id: "N1",
+            market: None,
+        };
+        let sql = energy_cost_total_sql(&CostSubject::Meter("m-1"), &node);
+        assert!(sql.contains("WHERE mu.meter_id = $3"));
+        assert!(!sql.contains("$5"));
+        assert!(sql.contains("sum(CASE WHEN price_per_mwh IS NULL THEN kwh END) AS unpriced_kwh"));
+    }
+}
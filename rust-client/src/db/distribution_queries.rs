@@ -0,0 +1,154 @@
+use anyhow::{bail, Result};
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+/// Which demand values a distribution is computed over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DemandScope {
+    /// `meter_usage.kva_demand`, per meter.
+    MeterKva,
+    /// `network_measurements.mw`, summed over phases per timestamp, per feeder.
+    FeederMw,
+}
+
+/// Demand percentiles of one meter or feeder over a window.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct DemandPercentiles {
+    pub id: String,
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+    pub max: Option<f64>,
+    pub samples: i64,
+}
+
+/// One histogram bucket `[lower, lower + width)`.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct HistogramBucket {
+    pub lower: f64,
+    pub count: i64,
+}
+
+/// `(id, ts, value)` rows for the scope; `$1` ids, `$2` start, `$3` end.
+///
+/// `approx_percentile` only accepts non-negative values, so negative demand
+/// (net export) is left out.
+fn demand_rows_sql(scope: DemandScope) -> &'static str {
+    match scope {
+        DemandScope::MeterKva => {
+            "SELECT meter_id AS id, ts, kva_demand AS value
+            FROM meter_usage
+            WHERE meter_id = ANY($1)
+              AND ts >= $2
+              AND ts <  $3
+              AND kva_demand >= 0"
+        }
+        DemandScope::FeederMw => {
+            "SELECT id, ts, value
+            FROM (
+                SELECT feeder_id AS id, ts, sum(mw) AS value
+                FROM network_measurements
+                WHERE feeder_id = ANY($1)
+                  AND ts >= $2
+                  AND ts <  $3
+                GROUP BY feeder_id, ts
+            )
+            WHERE value >= 0"
+        }
+    }
+}
+
+/// Build the percentile query (see [`demand_rows_sql`] for bind parameters).
+pub fn demand_percentiles_sql(scope: DemandScope) -> String {
+    format!(
+        "SELECT
+            id,
+            approx_percentile(value, 0.5) AS p50,
+            approx_percentile(value, 0.9) AS p90,
+            approx_percentile(value, 0.99) AS p99,
+            max(value) AS max,
+            count() AS samples
+        FROM (
+            {}
+        )
+        GROUP BY id
+        ORDER BY id",
+        demand_rows_sql(scope)
+    )
+}
+
+/// Build the histogram query; `$4` is the bucket width.
+pub fn demand_histogram_sql(scope: DemandScope) -> String {
+    format!(
+        "SELECT floor(value / $4) * $4 AS lower, count() AS count
+        FROM (
+            {}
+        )
+        GROUP BY lower
+        ORDER BY lower",
+        demand_rows_sql(scope)
+    )
+}
+
+/// P50/P90/P99 and maximum demand per meter or feeder in `[start, end)`.
+///
+/// Percentiles are approximate (QuestDB `approx_percentile`); ids without
+/// samples are not returned.
+pub async fn demand_percentiles(
+    pool: &PgPool,
+    scope: DemandScope,
+    ids: &[String],
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<DemandPercentiles>> {
+    let rows = sqlx::query_as::<_, DemandPercentiles>(&demand_percentiles_sql(scope))
+        .bind(ids)
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+/// Demand distribution of one meter or feeder in `[start, end)`, in buckets of
+/// `bucket_width` (kVA or MW). Empty buckets are not returned.
+pub async fn demand_histogram(
+    pool: &PgPool,
+    scope: DemandScope,
+    id: &str,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    bucket_width: f64,
+) -> Result<Vec<HistogramBucket>> {
+    if bucket_width.is_nan() || bucket_width <= 0.0 {
+        bail!("bucket width must be positive, got {bucket_width}");
+    }
+
+    let rows = sqlx::query_as::<_, HistogramBucket>(&demand_histogram_sql(scope))
+        .bind([id.to_string()].as_slice())
+        .bind(start)
+        .bind(end)
+        .bind(bucket_width)
+        .fetch_all(pool)
+        .await?;
+
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn feeder_distribution_sums_phases_first() {
+        let sql = demand_percentiles_sql(DemandScope::FeederMw);
+        assert!(sql.contains("approx_percentile(value, 0.99) AS p99"));
+        assert!(sql.contains("GROUP BY feeder_id, ts"));
+        assert!(sql.contains("WHERE value >= 0"));
+
+        let sql = demand_histogram_sql(DemandScope::MeterKva);
+        assert!(sql.starts_with("SELECT floor(value / $4) * $4 AS lower"));
+        assert!(sql.contains("AND kva_demand >= 0"));
+    }
+}
@@ -0,0 +1,92 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::domain::DrEvent;
+
+/// One `dr_events` row: an event and one of its nominated meters.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DrEventRow {
+    pub ts: OffsetDateTime,
+    pub event_id: String,
+    pub program: String,
+    pub end_ts: OffsetDateTime,
+    pub meter_id: String,
+}
+
+/// Group per-meter rows back into events, in order of first appearance.
+pub fn group_dr_event_rows(rows: Vec<DrEventRow>) -> Vec<DrEvent> {
+    let mut events: Vec<DrEvent> = Vec::new();
+
+    for row in rows {
+        match events.iter_mut().find(|e| e.event_id == row.event_id) {
+            Some(event) => {
+                if !event.meter_ids.contains(&row.meter_id) {
+                    event.meter_ids.push(row.meter_id);
+                }
+            }
+            None => events.push(DrEvent {
+                event_id: row.event_id,
+                program: row.program,
+                start: row.ts,
+                end: row.end_ts,
+                meter_ids: vec![row.meter_id],
+            }),
+        }
+    }
+
+    events
+}
+
+/// Fetch DR events starting in `[start, end)`.
+pub async fn dr_events_between(
+    pool: &PgPool,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<DrEvent>> {
+    let rows = sqlx::query_as::<_, DrEventRow>(
+        r#"
+        SELECT ts, event_id, program, end_ts, meter_id
+        FROM dr_events
+        WHERE ts >= $1
+          AND ts <  $2
+        ORDER BY ts, event_id
+        "#,
+    )
+    .bind(start)
+    .bind(end)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(group_dr_event_rows(rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn row(event_id: &str, meter_id: &str) -> DrEventRow {
+        DrEventRow {
+            ts: datetime!(2024-07-01 17:00:00 UTC),
+            event_id: event_id.to_string(),
+            program: "peak-saver".to_string(),
+            end_ts: datetime!(2024-07-01 19:00:00 UTC),
+            meter_id: meter_id.to_string(),
+        }
+    }
+
+    #[test]
+    fn groups_rows_by_event() {
+        let events = group_dr_event_rows(vec![
+            row("e-1", "m-1"),
+            row("e-2", "m-3"),
+            row("e-1", "m-2"),
+        ]);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event_id, "e-1");
+        assert_eq!(events[0].meter_ids, ["m-1", "m-2"]);
+        assert_eq!(events[1].meter_ids, ["m-3"]);
+    }
+}
@@ -0,0 +1,203 @@
+use anyhow::Result;
+use sqlx::PgPool;
+use time::OffsetDateTime;
+
+use crate::domain::{MeterExchange, MeterUsage};
+
+/// A meter serving a premise during `[from, to)`; `None` means unbounded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MeterSegment {
+    pub meter_id: String,
+    pub from: Option<OffsetDateTime>,
+    pub to: Option<OffsetDateTime>,
+}
+
+/// Fetch all exchanges recorded for a premise, oldest first.
+pub async fn meter_exchanges_for_premise(
+    pool: &PgPool,
+    premise_id: &str,
+) -> Result<Vec<MeterExchange>> {
+    let rows = sqlx::query_as::<_, MeterExchange>(
+        r#"
+        SELECT ts, premise_id, old_meter_id, new_meter_id, reason
+        FROM meter_exchanges
+        WHERE premise_id = $1
+        ORDER BY ts
+        "#,
+    )
+    .bind(premise_id)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows)
+}
+
+/// Turn a premise's exchanges into the sequence of meters that served it.
+///
+/// Exchanges must be sorted by `ts`. Each exchange closes the open segment of
+/// its `old_meter_id`, so premises with several meters keep one chain per
+/// meter. A meter first seen as `old_meter_id` is open-ended in the past and
+/// the meters still installed are open-ended in the future.
+pub fn stitch_meter_segments(exchanges: &[MeterExchange]) -> Vec<MeterSegment> {
+    let mut segments: Vec<MeterSegment> = Vec::with_capacity(exchanges.len() + 1);
+
+    for ex in exchanges {
+        let open = segments
+            .iter_mut()
+            .rev()
+            .find(|s| s.to.is_none() && s.meter_id == ex.old_meter_id);
+        match open {
+            Some(seg) => seg.to = Some(ex.ts),
+            None => segments.push(MeterSegment {
+                meter_id: ex.old_meter_id.clone(),
+                from: None,
+                to: Some(ex.ts),
+            }),
+        }
+        segments.push(MeterSegment {
+            meter_id: ex.new_meter_id.clone(),
+            from: Some(ex.ts),
+            to: None,
+        });
+    }
+
+    segments
+}
+
+/// Fetch a time-ordered load profile for a premise, stitched across meter exchanges.
+///
+/// Each meter only contributes usage from the period it was installed, so a
+/// hardware swap does not show up as a drop (or double count) in the profile.
+/// If the premise never had an exchange, `fallback_meter_id` is used as-is.
+pub async fn premise_load_profile(
+    pool: &PgPool,
+    premise_id: &str,
+    fallback_meter_id: &str,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<MeterUsage>> {
+    let exchanges = meter_exchanges_for_premise(pool, premise_id).await?;
+    let mut segments = stitch_meter_segments(&exchanges);
+    if segments.is_empty() {
+        segments.push(MeterSegment {
+            meter_id: fallback_meter_id.to_string(),
+            from: None,
+            to: None,
+        });
+    }
+
+    let mut rows = Vec::new();
+    for seg in segments {
+        let from = seg.from.map_or(start, |f| f.max(start));
+        let to = seg.to.map_or(end, |t| t.min(end));
+        if from >= to {
+            continue;
+        }
+
+        let mut part = sqlx::query_as::<_, MeterUsage>(
+            r#"
+            SELECT
+                ts,
+                meter_id,
+                premise_id,
+                kwh,
+                kvarh,
+                kva_demand,
+                quality_flag,
+                source_system
+            FROM meter_usage
+            WHERE meter_id = $1
+              AND ts >= $2
+              AND ts <  $3
+            ORDER BY ts
+            "#,
+        )
+        .bind(&seg.meter_id)
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+
+        rows.append(&mut part);
+    }
+
+    rows.sort_by_key(|r| r.ts);
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn exchange(ts: OffsetDateTime, old: &str, new: &str) -> MeterExchange {
+        MeterExchange {
+            ts,
+            premise_id: "p-1".to_string(),
+            old_meter_id: old.to_string(),
+            new_meter_id: new.to_string(),
+            reason: "exchange".to_string(),
+        }
+    }
+
+    #[test]
+    fn stitches_chain_of_exchanges() {
+        let t1 = datetime!(2024-01-01 00:00:00 UTC);
+        let t2 = datetime!(2024-06-01 00:00:00 UTC);
+        let segs = stitch_meter_segments(&[exchange(t1, "m-1", "m-2"), exchange(t2, "m-2", "m-3")]);
+
+        assert_eq!(
+            segs,
+            vec![
+                MeterSegment {
+                    meter_id: "m-1".to_string(),
+                    from: None,
+                    to: Some(t1)
+                },
+                MeterSegment {
+                    meter_id: "m-2".to_string(),
+                    from: Some(t1),
+                    to: Some(t2)
+                },
+                MeterSegment {
+                    meter_id: "m-3".to_string(),
+                    from: Some(t2),
+                    to: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn closes_the_exchanged_meter_of_multi_meter_premises() {
+        let t1 = datetime!(2024-01-01 00:00:00 UTC);
+        let t2 = datetime!(2024-06-01 00:00:00 UTC);
+        let t3 = datetime!(2024-09-01 00:00:00 UTC);
+        let segs = stitch_meter_segments(&[
+            exchange(t1, "m-a", "m-c"),
+            exchange(t2, "m-b", "m-d"),
+            exchange(t3, "m-c", "m-e"),
+        ]);
+
+        let seg = |meter_id: &str, from, to| MeterSegment {
+            meter_id: meter_id.to_string(),
+            from,
+            to,
+        };
+        assert_eq!(
+            segs,
+            vec![
+                seg("m-a", None, Some(t1)),
+                seg("m-c", Some(t1), Some(t3)),
+                seg("m-b", None, Some(t2)),
+                seg("m-d", Some(t2), None),
+                seg("m-e", Some(t3), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn no_exchanges_means_no_segments() {
+        assert!(stitch_meter_segments(&[]).is_empty());
+    }
+}
@@ -1,7 +1,10 @@
+use std::collections::BTreeMap;
+
 use anyhow::Result;
 use sqlx::PgPool;
 use time::OffsetDateTime;
 
+use crate::db::chunked::{for_id_chunks, DEFAULT_ID_CHUNK_SIZE};
 use crate::domain::MeterUsage;
 
 #[derive(Debug, Clone, sqlx::FromRow)]
@@ -11,6 +14,103 @@ pub struct AggregatedSegmentLoad {
     pub total_kwh: f64,
 }
 
+/// Total kWh of a list of meters at one timestamp.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct MeterGroupLoad {
+    pub ts: OffsetDateTime,
+    pub total_kwh: f64,
+}
+
+/// Natural key of a `meter_usage` row.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, sqlx::FromRow)]
+pub struct MeterUsageKey {
+    pub ts: OffsetDateTime,
+    pub meter_id: String,
+}
+
+/// Fetch the `(ts, meter_id)` keys already stored for the given meters in `[start, end]`.
+///
+/// Used by backfills to skip rows that a previous (partial) run already loaded.
+/// Large meter lists are queried in chunks.
+pub async fn existing_meter_usage_keys(
+    pool: &PgPool,
+    meter_ids: &[String],
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<MeterUsageKey>> {
+    for_id_chunks(meter_ids, DEFAULT_ID_CHUNK_SIZE, |chunk| async move {
+        let rows = sqlx::query_as::<_, MeterUsageKey>(
+            r#"
+            SELECT ts, meter_id
+            FROM meter_usage
+            WHERE ts >= $1
+              AND ts <= $2
+              AND meter_id = ANY($3)
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(chunk)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    })
+    .await
+}
+
+/// Sum scaled kWh over an arbitrarily large list of meters, per timestamp.
+///
+/// The list is queried in chunks and the partial sums are merged, so callers
+/// can pass e.g. every meter of a region without hitting statement limits.
+pub async fn aggregated_meter_load(
+    pool: &PgPool,
+    meter_ids: &[String],
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+) -> Result<Vec<MeterGroupLoad>> {
+    let partials = for_id_chunks(meter_ids, DEFAULT_ID_CHUNK_SIZE, |chunk| async move {
+        let rows = sqlx::query_as::<_, MeterGroupLoad>(
+            r#"
+            SELECT
+                mu.ts,
+                SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS total_kwh
+            FROM meter_usage mu
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mu.ts >= $1
+              AND mu.ts <  $2
+              AND mu.meter_id = ANY($3)
+            GROUP BY mu.ts
+            "#,
+        )
+        .bind(start)
+        .bind(end)
+        .bind(chunk)
+        .fetch_all(pool)
+        .await?;
+
+        Ok(rows)
+    })
+    .await?;
+
+    Ok(merge_group_loads(partials))
+}
+
+/// Add up per-chunk partial sums by timestamp, time-ordered.
+fn merge_group_loads(partials: Vec<MeterGroupLoad>) -> Vec<MeterGroupLoad> {
+    let mut by_ts: BTreeMap<OffsetDateTime, f64> = BTreeMap::new();
+    for p in partials {
+        *by_ts.entry(p.ts).or_default() += p.total_kwh;
+    }
+    by_ts
+        .into_iter()
+        .map(|(ts, total_kwh)| MeterGroupLoad { ts, total_kwh })
+        .collect()
+}
+
 /// Fetch a time-ordered load profile for a single meter.
 pub async fn load_profile(
     pool: &PgPool,
@@ -28,7 +128,16 @@ pub async fn load_profile(
             kvarh,
             kva_demand,
             quality_flag,
-            source_system
+            source_system,
+            kwh_phase_a,
+            kwh_phase_b,
+            kwh_phase_c,
+            current_phase_a,
+            current_phase_b,
+            current_phase_c,
+            voltage_phase_a,
+            voltage_phase_b,
+            voltage_phase_c
         FROM meter_usage
         WHERE meter_id = $1
           AND ts >= $2
@@ -46,6 +155,10 @@ pub async fn load_profile(
 }
 
 /// Aggregate kWh by customer segment over time.
+///
+/// `meters` and `customers` are effective-dated: each usage row is attributed to
+/// the meter/customer versions in effect at its `ts` (via `ASOF JOIN`), and
+/// soft-deleted versions are excluded.
 pub async fn aggregated_segment_load(
     pool: &PgPool,
     segments: &[String],
@@ -53,22 +166,24 @@ pub async fn aggregated_segment_load(
     end: OffsetDateTime,
     _sample_by: &str,
 ) -> Result<Vec<AggregatedSegmentLoad>> {
-    // Build a dynamic list for the IN clause. For a small number of segments this
-    // is acceptable; for large sets you would typically join against a temp table.
+    // Segments are few, so they are bound as one array. For large id lists
+    // (e.g. meters) see `aggregated_meter_load`, which queries in chunks.
     let sql = r#"
         SELECT
             mu.ts,
             c.segment,
             SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS total_kwh
         FROM meter_usage mu
-        JOIN meters m ON mu.meter_id = m.meter_id
-        JOIN customers c ON m.customer_id = c.customer_id
+        ASOF JOIN meters m ON (meter_id)
+        ASOF JOIN customers c ON (m.customer_id = c.customer_id)
         LEFT JOIN meter_scale_map msm
           ON msm.meter_id = mu.meter_id
          AND msm.from_ts <= mu.ts
          AND msm.to_ts   >  mu.ts
         WHERE mu.ts >= $1
           AND mu.ts <  $2
+          AND m.deleted = false
+          AND c.deleted = false
           AND c.segment = ANY($3)
         GROUP BY mu.ts, c.segment
         ORDER BY mu.ts, c.segment
@@ -87,3 +202,27 @@ pub async fn aggregated_segment_load(
 
     Ok(rows)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn merges_chunk_sums_by_timestamp() {
+        let t0 = datetime!(2024-01-01 00:00:00 UTC);
+        let t1 = datetime!(2024-01-01 00:15:00 UTC);
+        let merged = merge_group_loads(vec![
+            MeterGroupLoad { ts: t1, total_kwh: 2.0 },
+            MeterGroupLoad { ts: t0, total_kwh: 1.0 },
+            MeterGroupLoad { ts: t1, total_kwh: 3.0 },
+        ]);
+        assert_eq!(
+            merged,
+            vec![
+                MeterGroupLoad { ts: t0, total_kwh: 1.0 },
+                MeterGroupLoad { ts: t1, total_kwh: 5.0 },
+            ]
+        );
+    }
+}
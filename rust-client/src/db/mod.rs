@@ -1,3 +1,26 @@
+pub mod aligned_queries;
+pub mod chunked;
+pub mod cost_queries;
+pub mod distribution_queries;
+pub mod dr_event_queries;
+pub mod meter_exchange_queries;
 pub mod meter_usage_queries;
+pub mod premise_usage_queries;
+pub mod resample_queries;
 
-pub use meter_usage_queries::{aggregated_segment_load, load_profile, AggregatedSegmentLoad};
+pub use aligned_queries::{aligned_series, AlignJoin, AlignedRow, AlignedSeries};
+pub use chunked::{for_id_chunks, id_chunks, DEFAULT_ID_CHUNK_SIZE};
+pub use cost_queries::{
+    energy_cost_series, energy_cost_total, CostPoint, CostSubject, CostTotal, PriceNode,
+};
+pub use distribution_queries::{
+    demand_histogram, demand_percentiles, DemandPercentiles, DemandScope, HistogramBucket,
+};
+pub use dr_event_queries::{dr_events_between, group_dr_event_rows, DrEventRow};
+pub use meter_usage_queries::{
+    aggregated_meter_load, aggregated_segment_load, existing_meter_usage_keys, load_profile, AggregatedSegmentLoad,
+    MeterGroupLoad, MeterUsageKey,
+};
+pub use meter_exchange_queries::{meter_exchanges_for_premise, premise_load_profile, stitch_meter_segments, MeterSegment};
+pub use premise_usage_queries::{current_premise_meter, daily_totals, premise_daily_usage, DailyUsage};
+pub use resample_queries::{resample_profile, Aggregation, Fill, Granularity, ProfileSeries, ResampleSpec, SamplePoint};
@@ -0,0 +1,138 @@
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use sqlx::PgPool;
+use time::{Date, OffsetDateTime, UtcOffset};
+
+use crate::db::meter_exchange_queries::premise_load_profile;
+use crate::domain::MeterUsage;
+
+/// Usage of a premise over one day.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DailyUsage {
+    pub date: Date,
+    pub kwh: f64,
+    /// `None` when no interval of the day had a kVArh reading.
+    pub kvarh: Option<f64>,
+    /// Intervals that contributed to the day.
+    pub intervals: usize,
+    /// Of those, intervals carrying a `quality_flag` (estimated, edited, ...).
+    pub flagged_intervals: usize,
+}
+
+/// The meter currently installed at a premise, per the `meters` reference table.
+///
+/// Returns the most recently effective, non-deleted meter whose current version
+/// points at the premise, or `None` if there is none.
+pub async fn current_premise_meter(pool: &PgPool, premise_id: &str) -> Result<Option<String>> {
+    let meter_id = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT meter_id
+        FROM (meters LATEST ON effective_from PARTITION BY meter_id)
+        WHERE premise_id = $1
+          AND NOT deleted
+        ORDER BY effective_from DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(premise_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(meter_id)
+}
+
+/// Add up interval usage into days of the given UTC offset, in date order.
+pub fn daily_totals(rows: &[MeterUsage], offset: UtcOffset) -> Vec<DailyUsage> {
+    let mut days: BTreeMap<Date, DailyUsage> = BTreeMap::new();
+    for row in rows {
+        let date = row.ts.to_offset(offset).date();
+        let day = days.entry(date).or_insert(DailyUsage {
+            date,
+            kwh: 0.0,
+            kvarh: None,
+            intervals: 0,
+            flagged_intervals: 0,
+        });
+        day.kwh += row.kwh;
+        if let Some(kvarh) = row.kvarh {
+            *day.kvarh.get_or_insert(0.0) += kvarh;
+        }
+        day.intervals += 1;
+        if row.quality_flag.is_some() {
+            day.flagged_intervals += 1;
+        }
+    }
+    days.into_values().collect()
+}
+
+/// Daily usage of a premise over `[start, end)`, stitched across meter exchanges.
+///
+/// Days are calendar days at `offset`; pass `start`/`end` on day boundaries
+/// at that offset to get whole days. See [`premise_load_profile`] for how
+/// `fallback_meter_id` is used.
+pub async fn premise_daily_usage(
+    pool: &PgPool,
+    premise_id: &str,
+    fallback_meter_id: &str,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    offset: UtcOffset,
+) -> Result<Vec<DailyUsage>> {
+    let rows = premise_load_profile(pool, premise_id, fallback_meter_id, start, end).await?;
+    Ok(daily_totals(&rows, offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::PhaseChannels;
+    use time::macros::{date, datetime, offset};
+
+    fn usage(ts: OffsetDateTime, kwh: f64, kvarh: Option<f64>, quality_flag: Option<&str>) -> MeterUsage {
+        MeterUsage {
+            ts,
+            meter_id: "m-1".to_string(),
+            premise_id: Some("p-1".to_string()),
+            kwh,
+            kvarh,
+            kva_demand: None,
+            quality_flag: quality_flag.map(str::to_string),
+            source_system: None,
+            event_id: None,
+            phases: PhaseChannels::default(),
+        }
+    }
+
+    #[test]
+    fn totals_intervals_by_local_day() {
+        let rows = [
+            usage(datetime!(2024-06-01 03:00 UTC), 1.0, None, None),
+            usage(datetime!(2024-06-01 05:00 UTC), 2.0, Some(0.5), Some("estimated")),
+            usage(datetime!(2024-06-01 06:00 UTC), 4.0, None, None),
+        ];
+
+        // At UTC-5 the first interval still belongs to May 31.
+        let days = daily_totals(&rows, offset!(-5));
+        assert_eq!(
+            days,
+            vec![
+                DailyUsage {
+                    date: date!(2024 - 05 - 31),
+                    kwh: 1.0,
+                    kvarh: None,
+                    intervals: 1,
+                    flagged_intervals: 0,
+                },
+                DailyUsage {
+                    date: date!(2024 - 06 - 01),
+                    kwh: 6.0,
+                    kvarh: Some(0.5),
+                    intervals: 2,
+                    flagged_intervals: 1,
+                },
+            ]
+        );
+        assert_eq!(daily_totals(&rows, UtcOffset::UTC).len(), 1);
+    }
+}
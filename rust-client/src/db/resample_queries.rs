@@ -0,0 +1,319 @@
+use std::{fmt, str::FromStr};
+
+use anyhow::{bail, Result};
+use sqlx::PgPool;
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+/// A series that can be resampled.
+#[derive(Debug, Clone, Copy)]
+pub enum ProfileSeries<'a> {
+    /// `meter_usage.kwh` of one meter (energy), scaled by `meter_scale_map.kwh_multiplier`.
+    MeterKwh(&'a str),
+    /// `meter_usage.kvarh` of one meter (energy), scaled by `meter_scale_map.kvarh_multiplier`.
+    MeterKvarh(&'a str),
+    /// `meter_usage.kva_demand` of one meter (power).
+    MeterKvaDemand(&'a str),
+    /// `generation_output.mw` of a plant (power). Without `unit_id`, samples of
+    /// all the plant's units are aggregated together.
+    GenerationMw {
+        plant_id: &'a str,
+        unit_id: Option<&'a str>,
+    },
+}
+
+/// How samples within a bucket are combined.
+///
+/// Use `Sum` for energy series and `Avg`/`Max` for power series.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregation {
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+impl Aggregation {
+    fn sql_fn(self) -> &'static str {
+        match self {
+            Self::Sum => "sum",
+            Self::Avg => "avg",
+            Self::Min => "min",
+            Self::Max => "max",
+        }
+    }
+}
+
+/// How buckets without samples are filled (QuestDB `FILL`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fill {
+    /// Leave empty buckets out.
+    None,
+    /// Return empty buckets with a NULL value.
+    Null,
+    /// Repeat the previous bucket's value.
+    Prev,
+    /// Interpolate linearly between neighbouring buckets.
+    Linear,
+    /// Use a constant.
+    Value(f64),
+}
+
+impl Fill {
+    fn sql(self) -> Option<String> {
+        match self {
+            Self::None => None,
+            Self::Null => Some("FILL(NULL)".to_string()),
+            Self::Prev => Some("FILL(PREV)".to_string()),
+            Self::Linear => Some("FILL(LINEAR)".to_string()),
+            Self::Value(v) => Some(format!("FILL({v:?})")),
+        }
+    }
+}
+
+/// A `SAMPLE BY` bucket size such as `15m`, `1h` or `1d`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Granularity {
+    count: u32,
+    unit: char,
+}
+
+impl Granularity {
+    pub fn minutes(count: u32) -> Self {
+        Self { count, unit: 'm' }
+    }
+
+    pub fn hours(count: u32) -> Self {
+        Self { count, unit: 'h' }
+    }
+
+    pub fn days(count: u32) -> Self {
+        Self { count, unit: 'd' }
+    }
+}
+
+impl FromStr for Granularity {
+    type Err = anyhow::Error;
+
+    /// Accepts `<n><unit>` with unit `s`, `m`, `h`, `d`, `M` (month) or `y`.
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let Some(unit) = s.chars().last() else {
+            bail!("empty granularity");
+        };
+        if !matches!(unit, 's' | 'm' | 'h' | 'd' | 'M' | 'y') {
+            bail!("unsupported granularity unit in '{s}'");
+        }
+        let count: u32 = s[..s.len() - 1]
+            .parse()
+            .map_err(|_| anyhow::anyhow!("invalid granularity '{s}'"))?;
+        if count == 0 {
+            bail!("granularity must be positive");
+        }
+        Ok(Self { count, unit })
+    }
+}
+
+impl fmt::Display for Granularity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.count, self.unit)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ResampleSpec {
+    pub granularity: Granularity,
+    pub aggregation: Aggregation,
+    pub fill: Fill,
+}
+
+/// One bucket of a resampled series; `value` is NULL for `Fill::Null` gaps.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct SamplePoint {
+    pub ts: OffsetDateTime,
+    pub value: Option<f64>,
+}
+
+pub(crate) fn sql_timestamp(ts: OffsetDateTime) -> String {
+    // Formatting a UTC OffsetDateTime as RFC 3339 cannot fail.
+    ts.to_offset(time::UtcOffset::UTC)
+        .format(&Rfc3339)
+        .expect("RFC 3339 formatting")
+}
+
+/// Build the resampling query.
+///
+/// Bind parameters: `$1` series key (meter or plant id), `$2` start, `$3` end,
+/// and `$4` unit id for `GenerationMw` with a unit. Buckets are aligned to the
+/// calendar and, with a fill, span the whole `[start, end)` range (`FROM`/`TO`,
+/// QuestDB 8.0+); the range is inlined there because `FROM`/`TO` take literals.
+pub fn resample_sql(
+    series: &ProfileSeries<'_>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    spec: &ResampleSpec,
+) -> String {
+    let inner = match series {
+        ProfileSeries::MeterKwh(_)
+        | ProfileSeries::MeterKvarh(_)
+        | ProfileSeries::MeterKvaDemand(_) => {
+            let value = match series {
+                ProfileSeries::MeterKwh(_) => "mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)",
+                ProfileSeries::MeterKvarh(_) => "mu.kvarh * COALESCE(msm.kvarh_multiplier, 1.0)",
+                _ => "mu.kva_demand",
+            };
+            format!(
+                "SELECT mu.ts, {value} AS value
+            FROM meter_usage mu
+            LEFT JOIN meter_scale_map msm
+              ON msm.meter_id = mu.meter_id
+             AND msm.from_ts <= mu.ts
+             AND msm.to_ts   >  mu.ts
+            WHERE mu.meter_id = $1
+              AND mu.ts >= $2
+              AND mu.ts <  $3"
+            )
+        }
+        ProfileSeries::GenerationMw { unit_id, .. } => {
+            let unit_filter = if unit_id.is_some() {
+                "\n              AND unit_id = $4"
+            } else {
+                ""
+            };
+            format!(
+                "SELECT ts, mw AS value
+            FROM generation_output
+            WHERE plant_id = $1
+              AND ts >= $2
+              AND ts <  $3{unit_filter}"
+            )
+        }
+    };
+
+    let range = match spec.fill {
+        Fill::None => String::new(),
+        _ => format!(
+            " FROM '{}' TO '{}'",
+            sql_timestamp(start),
+            sql_timestamp(end)
+        ),
+    };
+    let fill = spec.fill.sql().map(|f| format!(" {f}")).unwrap_or_default();
+
+    format!(
+        "SELECT ts, {agg}(value) AS value
+        FROM (
+            {inner}
+        ) timestamp(ts)
+        SAMPLE BY {granularity}{range}{fill} ALIGN TO CALENDAR
+        ORDER BY ts",
+        agg = spec.aggregation.sql_fn(),
+        granularity = spec.granularity,
+    )
+}
+
+/// Resample a load or generation profile to `spec.granularity`.
+///
+/// Returns one point per bucket, time-ordered, ready for charting.
+pub async fn resample_profile(
+    pool: &PgPool,
+    series: ProfileSeries<'_>,
+    start: OffsetDateTime,
+    end: OffsetDateTime,
+    spec: ResampleSpec,
+) -> Result<Vec<SamplePoint>> {
+    let sql = resample_sql(&series, start, end, &spec);
+
+    let key = match series {
+        ProfileSeries::MeterKwh(id)
+        | ProfileSeries::MeterKvarh(id)
+        | ProfileSeries::MeterKvaDemand(id) => id,
+        ProfileSeries::GenerationMw { plant_id, .. } => plant_id,
+    };
+
+    let mut query = sqlx::query_as::<_, SamplePoint>(&sql)
+        .bind(key)
+        .bind(start)
+        .bind(end);
+    if let ProfileSeries::GenerationMw {
+        unit_id: Some(unit_id),
+        ..
+    } = series
+    {
+        query = query.bind(unit_id);
+    }
+
+    Ok(query.fetch_all(pool).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn parses_granularity() {
+        assert_eq!(
+            "15m".parse::<Granularity>().unwrap(),
+            Granularity::minutes(15)
+        );
+        assert_eq!("1M".parse::<Granularity>().unwrap().to_string(), "1M");
+        assert!("0h".parse::<Granularity>().is_err());
+        assert!("1x".parse::<Granularity>().is_err());
+        assert!("h".parse::<Granularity>().is_err());
+    }
+
+    #[test]
+    fn resample_sql_spans_range_when_filling() {
+        let spec = ResampleSpec {
+            granularity: Granularity::hours(1),
+            aggregation: Aggregation::Sum,
+            fill: Fill::Prev,
+        };
+        let sql = resample_sql(
+            &ProfileSeries::MeterKwh("m-1"),
+            datetime!(2024-01-01 00:00:00 UTC),
+            datetime!(2024-01-02 00:00:00 UTC),
+            &spec,
+        );
+
+        assert!(sql.starts_with("SELECT ts, sum(value) AS value"));
+        assert!(sql.contains("mu.kwh * COALESCE(msm.kwh_multiplier, 1.0) AS value"));
+        assert!(sql.contains(
+            "SAMPLE BY 1h FROM '2024-01-01T00:00:00Z' TO '2024-01-02T00:00:00Z' FILL(PREV) ALIGN TO CALENDAR"
+        ));
+    }
+
+    #[test]
+    fn resample_sql_filters_generation_unit() {
+        let spec = ResampleSpec {
+            granularity: Granularity::minutes(5),
+            aggregation: Aggregation::Max,
+            fill: Fill::None,
+        };
+        let start = datetime!(2024-01-01 00:00:00 UTC);
+        let end = datetime!(2024-01-02 00:00:00 UTC);
+
+        let sql = resample_sql(
+            &ProfileSeries::GenerationMw {
+                plant_id: "p",
+                unit_id: Some("u1"),
+            },
+            start,
+            end,
+            &spec,
+        );
+        assert!(sql.contains("AND unit_id = $4"));
+        assert!(sql.contains("SAMPLE BY 5m ALIGN TO CALENDAR"));
+
+        let sql = resample_sql(
+            &ProfileSeries::GenerationMw {
+                plant_id: "p",
+                unit_id: None,
+            },
+            start,
+            end,
+            &spec,
+        );
+        assert!(!sql.contains("$4"));
+    }
+}
@@ -0,0 +1,14 @@
+use time::OffsetDateTime;
+
+/// A demand response event: a program calls for load reduction from a set of
+/// nominated meters during `[start, end)`.
+///
+/// Stored in `dr_events` as one row per nominated meter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DrEvent {
+    pub event_id: String,
+    pub program: String,
+    pub start: OffsetDateTime,
+    pub end: OffsetDateTime,
+    pub meter_ids: Vec<String>,
+}
@@ -5,8 +5,28 @@ pub struct GenerationOutput {
     pub ts: OffsetDateTime,
     pub plant_id: String,
     pub unit_id: Option<String>,
+    /// Gross output.
     pub mw: f64,
     pub mvar: Option<f64>,
     pub status: Option<String>,
     pub fuel_type: Option<String>,
+    /// Producer-supplied `event_id`, if any (used by the `client` event-id strategy).
+    #[sqlx(default)]
+    pub event_id: Option<String>,
+    /// Auxiliary (station service) load; net output is `mw - aux_mw`.
+    #[sqlx(default)]
+    pub aux_mw: Option<f64>,
+    /// Share of capacity available to run (0-100), e.g. after derates.
+    #[sqlx(default)]
+    pub availability_pct: Option<f64>,
+    /// Output withheld on instruction (curtailment) during the interval.
+    #[sqlx(default)]
+    pub curtailed_mw: Option<f64>,
+}
+
+impl GenerationOutput {
+    /// Net output: gross `mw` minus auxiliary load (if reported).
+    pub fn net_mw(&self) -> f64 {
+        self.mw - self.aux_mw.unwrap_or(0.0)
+    }
 }
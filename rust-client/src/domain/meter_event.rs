@@ -0,0 +1,15 @@
+use time::OffsetDateTime;
+
+/// An event reported by a meter through its head-end system, e.g. a tamper
+/// alarm, reverse energy flow or power outage.
+///
+/// Stored in `meter_events`; `feeder_balance` counts theft-related events
+/// (`tamper`, `reverse_run`, `magnetic`, `theft_suspect`) per feeder.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct MeterEvent {
+    pub ts: OffsetDateTime,
+    pub meter_id: String,
+    pub event_type: String,
+    /// The message as received from the head-end.
+    pub details: String,
+}
@@ -10,4 +10,78 @@ pub struct MeterUsage {
     pub kva_demand: Option<f64>,
     pub quality_flag: Option<String>,
     pub source_system: Option<String>,
+    /// Producer-supplied `event_id`, if any (used by the `client` event-id strategy).
+    #[sqlx(default)]
+    pub event_id: Option<String>,
+    /// Per-phase channels of polyphase meters; empty for single-value readings.
+    #[sqlx(flatten)]
+    pub phases: PhaseChannels,
+}
+
+/// Per-phase readings of a polyphase (typically C&I) meter.
+///
+/// Every channel is optional, so meters can report any subset. Energy is kWh
+/// over the interval, current in A and voltage in V.
+#[derive(Debug, Clone, Default, PartialEq, sqlx::FromRow)]
+pub struct PhaseChannels {
+    #[sqlx(default)]
+    pub kwh_phase_a: Option<f64>,
+    #[sqlx(default)]
+    pub kwh_phase_b: Option<f64>,
+    #[sqlx(default)]
+    pub kwh_phase_c: Option<f64>,
+    #[sqlx(default)]
+    pub current_phase_a: Option<f64>,
+    #[sqlx(default)]
+    pub current_phase_b: Option<f64>,
+    #[sqlx(default)]
+    pub current_phase_c: Option<f64>,
+    #[sqlx(default)]
+    pub voltage_phase_a: Option<f64>,
+    #[sqlx(default)]
+    pub voltage_phase_b: Option<f64>,
+    #[sqlx(default)]
+    pub voltage_phase_c: Option<f64>,
+}
+
+impl PhaseChannels {
+    /// Column names, in the order of [`PhaseChannels::values`].
+    pub const COLUMNS: [&'static str; 9] = [
+        "kwh_phase_a",
+        "kwh_phase_b",
+        "kwh_phase_c",
+        "current_phase_a",
+        "current_phase_b",
+        "current_phase_c",
+        "voltage_phase_a",
+        "voltage_phase_b",
+        "voltage_phase_c",
+    ];
+
+    /// Channel values, in the order of [`PhaseChannels::COLUMNS`].
+    pub fn values(&self) -> [Option<f64>; 9] {
+        [
+            self.kwh_phase_a,
+            self.kwh_phase_b,
+            self.kwh_phase_c,
+            self.current_phase_a,
+            self.current_phase_b,
+            self.current_phase_c,
+            self.voltage_phase_a,
+            self.voltage_phase_b,
+            self.voltage_phase_c,
+        ]
+    }
+
+    /// `(column, value)` for every channel that is set.
+    pub fn present(&self) -> impl Iterator<Item = (&'static str, f64)> {
+        Self::COLUMNS
+            .into_iter()
+            .zip(self.values())
+            .filter_map(|(column, value)| value.map(|v| (column, v)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values().iter().all(Option::is_none)
+    }
 }
@@ -1,5 +1,11 @@
+pub mod dr_event;
+pub mod meter_event;
 pub mod meter_usage;
 pub mod generation_output;
+pub mod reference;
 
-pub use meter_usage::MeterUsage;
+pub use dr_event::DrEvent;
+pub use meter_event::MeterEvent;
+pub use meter_usage::{MeterUsage, PhaseChannels};
 pub use generation_output::GenerationOutput;
+pub use reference::{Customer, Meter, MeterExchange};
@@ -0,0 +1,46 @@
+use time::OffsetDateTime;
+
+/// One effective-dated version of a meter's reference data.
+///
+/// Rows are append-only: a change is a new version with a later
+/// `effective_from`, and a removal is a version with `deleted = true`.
+/// Queries pick the version in effect at a given time (e.g. via `ASOF JOIN`).
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Meter {
+    pub effective_from: OffsetDateTime,
+    pub meter_id: String,
+    pub premise_id: Option<String>,
+    pub customer_id: Option<String>,
+    pub feeder_id: Option<String>,
+    pub substation_id: Option<String>,
+    pub tariff_code: Option<String>,
+    pub meter_type: Option<String>,
+    pub deleted: bool,
+}
+
+/// One effective-dated version of a customer's reference data.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Customer {
+    pub effective_from: OffsetDateTime,
+    pub customer_id: String,
+    pub segment: Option<String>,
+    pub name: Option<String>,
+    pub region_id: Option<String>,
+    pub lat: Option<f64>,
+    pub lon: Option<f64>,
+    pub deleted: bool,
+}
+
+/// A meter exchange (hardware swap) or re-programming at a premise.
+///
+/// From `ts` on, `new_meter_id` replaces `old_meter_id` for the premise. A
+/// re-programming that keeps the meter but changes its id is recorded the same way,
+/// with `reason` distinguishing the two (e.g. `"exchange"`, `"reprogram"`).
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MeterExchange {
+    pub ts: OffsetDateTime,
+    pub premise_id: String,
+    pub old_meter_id: String,
+    pub new_meter_id: String,
+    pub reason: String,
+}
@@ -9,7 +9,19 @@ CREATE TABLE IF NOT EXISTS meter_usage (
     kvarh           DOUBLE,
     kva_demand      DOUBLE,
     quality_flag    SYMBOL,
-    source_system   SYMBOL
+    source_system   SYMBOL,
+    tenant          SYMBOL,     -- tenant of the API key that sent the record, if scoped
+    ingested_at     TIMESTAMP,  -- time the ingestion-service received the record
+    -- optional per-phase channels (polyphase C&I meters)
+    kwh_phase_a     DOUBLE,
+    kwh_phase_b     DOUBLE,
+    kwh_phase_c     DOUBLE,
+    current_phase_a DOUBLE,
+    current_phase_b DOUBLE,
+    current_phase_c DOUBLE,
+    voltage_phase_a DOUBLE,
+    voltage_phase_b DOUBLE,
+    voltage_phase_c DOUBLE
 ) TIMESTAMP(ts)
 PARTITION BY DAY;
 
@@ -21,7 +33,10 @@ CREATE TABLE IF NOT EXISTS generation_output (
     mw              DOUBLE,
     mvar            DOUBLE,
     status          SYMBOL,
-    fuel_type       SYMBOL
+    fuel_type       SYMBOL,
+    aux_mw          DOUBLE,     -- auxiliary load; net output = mw - aux_mw
+    availability_pct DOUBLE,
+    curtailed_mw    DOUBLE
 ) TIMESTAMP(ts)
 PARTITION BY DAY;
 
@@ -36,3 +51,12 @@ CREATE TABLE IF NOT EXISTS network_measurements (
     current_a       DOUBLE
 ) TIMESTAMP(ts)
 PARTITION BY DAY;
+
+CREATE TABLE IF NOT EXISTS weather_observations (
+    ts              TIMESTAMP,
+    station_id      SYMBOL,
+    temperature_c   DOUBLE,
+    humidity_pct    DOUBLE,
+    wind_speed_ms   DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY DAY;
@@ -1,6 +1,12 @@
 -- Reference / dimension tables for the electric utility QuestDB project
 
+-- `meters` and `customers` are effective-dated and append-only: each row is a
+-- version valid from `effective_from` until the next version for the same key.
+-- A version with `deleted = true` soft-deletes the entity from that time on.
+-- They are maintained via the ingestion-service `/reference/*` endpoints.
+
 CREATE TABLE IF NOT EXISTS meters (
+    effective_from  TIMESTAMP,
     meter_id        SYMBOL INDEX,
     premise_id      SYMBOL,
     customer_id     SYMBOL,
@@ -9,17 +15,22 @@ CREATE TABLE IF NOT EXISTS meters (
     tariff_code     SYMBOL,
     install_date    DATE,
     retire_date     DATE,
-    meter_type      SYMBOL
-);
+    meter_type      SYMBOL,
+    deleted         BOOLEAN
+) TIMESTAMP(effective_from)
+PARTITION BY YEAR;
 
 CREATE TABLE IF NOT EXISTS customers (
+    effective_from  TIMESTAMP,
     customer_id     SYMBOL INDEX,
     segment         SYMBOL,
     name            STRING,
     region_id       SYMBOL,
     lat             DOUBLE,
-    lon             DOUBLE
-);
+    lon             DOUBLE,
+    deleted         BOOLEAN
+) TIMESTAMP(effective_from)
+PARTITION BY YEAR;
 
 CREATE TABLE IF NOT EXISTS plants (
     plant_id        SYMBOL INDEX,
@@ -28,3 +39,27 @@ CREATE TABLE IF NOT EXISTS plants (
     fuel_type       SYMBOL,
     capacity_mw     DOUBLE
 );
+
+-- Meter exchanges / re-programmings: from `ts` on, `new_meter_id` replaces
+-- `old_meter_id` at the premise. Used to stitch premise-level load profiles
+-- across hardware swaps (see rust-client `premise_load_profile`).
+CREATE TABLE IF NOT EXISTS meter_exchanges (
+    ts              TIMESTAMP,
+    premise_id      SYMBOL INDEX,
+    old_meter_id    SYMBOL,
+    new_meter_id    SYMBOL,
+    reason          STRING
+) TIMESTAMP(ts)
+PARTITION BY YEAR;
+
+-- Contracted demand of key accounts, effective-dated like `meters`.
+-- `demand_unit` is `kw` (average kW from interval kWh) or `kva` (the meters'
+-- `kva_demand`); the account's demand is that of its meters per interval.
+-- Evaluated by the `contract_demand` job.
+CREATE TABLE IF NOT EXISTS contract_demand (
+    effective_from  TIMESTAMP,
+    customer_id     SYMBOL INDEX,
+    contract_demand DOUBLE,
+    demand_unit     SYMBOL
+) TIMESTAMP(effective_from)
+PARTITION BY YEAR;
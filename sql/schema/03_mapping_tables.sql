@@ -13,6 +13,56 @@ CREATE TABLE IF NOT EXISTS meter_feeder_map (
 ) TIMESTAMP(from_ts)
 PARTITION BY YEAR;
 
+-- Meters of multi-meter premises (master/sub-meter sites) over time. role:
+--   'main' measures the premise's supply (summed into premise_usage.kwh),
+--   'sub'  sits behind a main meter (reported as premise_usage.sub_kwh only).
+CREATE TABLE IF NOT EXISTS premise_meter_config (
+    premise_id SYMBOL,
+    meter_id   SYMBOL,
+    role       SYMBOL,
+    from_ts    TIMESTAMP,
+    to_ts      TIMESTAMP
+) TIMESTAMP(from_ts)
+PARTITION BY YEAR;
+
+-- Interval usage per premise (premise_usage job); complete = every main meter reported.
+CREATE TABLE IF NOT EXISTS premise_usage (
+    ts                TIMESTAMP,
+    premise_id        SYMBOL,
+    kwh               DOUBLE,
+    kvarh             DOUBLE,
+    sub_kwh           DOUBLE,
+    main_meters       LONG,
+    reporting_meters  LONG,
+    complete          BOOLEAN
+) TIMESTAMP(ts)
+PARTITION BY DAY;
+
+-- Unmetered loads (streetlights, traffic signals, small unmetered services) over
+-- time. `pseudo_measurements` writes their readings to meter_usage with
+-- meter_id = load_id and quality_flag = 'pseudo'; profile names a
+-- [pseudo_measurements.profiles] entry, station_id a weather_observations station.
+CREATE TABLE IF NOT EXISTS unmetered_loads (
+    load_id    SYMBOL,
+    feeder_id  SYMBOL,
+    profile    SYMBOL,
+    rated_kw   DOUBLE,
+    station_id SYMBOL,
+    from_ts    TIMESTAMP,
+    to_ts      TIMESTAMP
+) TIMESTAMP(from_ts)
+PARTITION BY YEAR;
+
+-- Meter -> network node (bus) mapping over time, for the state estimation input
+-- export (state_estimation_export)
+CREATE TABLE IF NOT EXISTS meter_node_map (
+    meter_id   SYMBOL,
+    node_id    SYMBOL,
+    from_ts    TIMESTAMP,
+    to_ts      TIMESTAMP
+) TIMESTAMP(from_ts)
+PARTITION BY YEAR;
+
 -- Plant/unit -> feeder mapping over time
 CREATE TABLE IF NOT EXISTS plant_feeder_map (
     plant_id   SYMBOL,
@@ -44,7 +94,7 @@ CREATE TABLE IF NOT EXISTS topology_events (
 ) TIMESTAMP(ts)
 PARTITION BY DAY;
 
--- Meter events (tamper, reverse run, etc.)
+-- Meter events (tamper, reverse run, etc.), written by the [meter_events] syslog listener
 CREATE TABLE IF NOT EXISTS meter_events (
     ts          TIMESTAMP,
     meter_id    SYMBOL,
@@ -64,6 +114,100 @@ CREATE TABLE IF NOT EXISTS feeder_energy_balance (
     meter_coverage_pct  DOUBLE,
     data_quality_score  DOUBLE,
     cause_hint          SYMBOL,
-    alert               BOOLEAN
+    alert               BOOLEAN,
+    -- FALSE when too few mapped meters reported for the interval (see feeder_balance --min-completeness)
+    complete            BOOLEAN,
+    -- pseudo-measured unmetered loads (unmetered_loads), included in feeder_kwh_demand
+    feeder_kwh_unmetered DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Confidence intervals of feeder_energy_balance.loss_pct under metering error
+-- ([loss_uncertainty] pass of feeder_balance); `alert` only when the whole
+-- interval lies beyond the loss alert threshold.
+CREATE TABLE IF NOT EXISTS feeder_loss_uncertainty (
+    ts                  TIMESTAMP,
+    feeder_id           SYMBOL,
+    loss_pct            DOUBLE,
+    loss_pct_low        DOUBLE,
+    loss_pct_high       DOUBLE,
+    loss_pct_stddev     DOUBLE,
+    -- share of draws with |loss_pct| above the threshold
+    alert_probability   DOUBLE,
+    alert               BOOLEAN,
+    confidence          DOUBLE,
+    samples             INT
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Alternative meter -> feeder mappings for what-if analyses (feeder_balance
+-- --scenario), one complete mapping per `scenario`. Never read by production jobs.
+CREATE TABLE IF NOT EXISTS meter_feeder_map_scenarios (
+    scenario   SYMBOL,
+    meter_id   SYMBOL,
+    feeder_id  SYMBOL,
+    from_ts    TIMESTAMP,
+    to_ts      TIMESTAMP
+) TIMESTAMP(from_ts)
+PARTITION BY YEAR;
+
+-- Feeder energy balance under a scenario mapping; the columns of
+-- feeder_energy_balance, labeled with the scenario and the run's `computed_at`.
+CREATE TABLE IF NOT EXISTS feeder_energy_balance_scenarios (
+    ts                  TIMESTAMP,
+    feeder_id           SYMBOL,
+    feeder_kwh_gen      DOUBLE,
+    feeder_kwh_demand   DOUBLE,
+    loss_kwh            DOUBLE,
+    loss_pct            DOUBLE,
+    meter_coverage_pct  DOUBLE,
+    data_quality_score  DOUBLE,
+    cause_hint          SYMBOL,
+    alert               BOOLEAN,
+    complete            BOOLEAN,
+    scenario            SYMBOL,
+    computed_at         TIMESTAMP,
+    feeder_kwh_unmetered DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Completed scenario runs; rows of a run without a record here are from an interrupted run.
+CREATE TABLE IF NOT EXISTS feeder_scenario_runs (
+    computed_at TIMESTAMP,
+    scenario    SYMBOL,
+    from_ts     TIMESTAMP,
+    to_ts       TIMESTAMP,
+    rows        LONG
+) TIMESTAMP(computed_at)
+PARTITION BY YEAR;
+
+-- Derived unit start/stop events, written by the ingestion-service when
+-- `[unit_runtime]` is configured. `run_hours` is set on a stop whose start was observed.
+CREATE TABLE IF NOT EXISTS unit_runtime (
+    ts          TIMESTAMP,
+    plant_id    SYMBOL,
+    unit_id     SYMBOL,
+    event       SYMBOL,     -- start | stop
+    mw          DOUBLE,
+    run_hours   DOUBLE
 ) TIMESTAMP(ts)
 PARTITION BY MONTH;
+
+-- Windowed aggregates of live records, written by the ingestion-service when
+-- `[window_aggregates]` is configured. ts is the window start; key is the meter,
+-- plant or feeder id (see key_by).
+CREATE TABLE IF NOT EXISTS window_aggregates (
+    ts          TIMESTAMP,
+    window      SYMBOL,
+    table_name  SYMBOL,
+    key_by      SYMBOL,     -- meter | plant | feeder
+    key         SYMBOL,
+    field       SYMBOL,
+    window_secs LONG,
+    samples     LONG,
+    sum         DOUBLE,
+    avg         DOUBLE,
+    min         DOUBLE,
+    max         DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY DAY;
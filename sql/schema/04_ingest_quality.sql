@@ -0,0 +1,191 @@
+-- Ingestion quality tables for the electric utility QuestDB project
+
+-- Records dropped by validation, written by the ingestion-service when
+-- `[reject_log]` is configured. `ts` is the time the record was received.
+CREATE TABLE IF NOT EXISTS ingest_rejects (
+    ts              TIMESTAMP,
+    table_name      SYMBOL,
+    source_system   SYMBOL,
+    record_ts       TIMESTAMP,
+    reason          STRING
+) TIMESTAMP(ts)
+PARTITION BY DAY;
+
+-- Records and repeated event_ids per source_system, counted at ingest when
+-- `[duplicate_stats]` is configured; one row per source and flush interval.
+CREATE TABLE IF NOT EXISTS ingest_duplicates (
+    ts              TIMESTAMP,
+    table_name      SYMBOL,
+    source_system   SYMBOL,
+    records         LONG,
+    duplicates      LONG
+) TIMESTAMP(ts)
+PARTITION BY DAY;
+
+-- Daily per-source_system statistics (vendor scorecards), written by the
+-- `ingest_source_stats` job. Lateness is `ingested_at - ts` in seconds.
+-- Deduplicated on (day, source_system) so a day can be recomputed.
+CREATE TABLE IF NOT EXISTS ingest_source_stats (
+    day                 TIMESTAMP,
+    source_system       SYMBOL,
+    records             LONG,
+    rejected            LONG,
+    reject_rate         DOUBLE,
+    duplicates          LONG,
+    duplicate_rate      DOUBLE,
+    distinct_meters     LONG,
+    avg_lateness_secs   DOUBLE,
+    max_lateness_secs   DOUBLE,
+    late_within_1h      LONG,
+    late_1h_to_24h      LONG,
+    late_24h_to_72h     LONG,
+    late_over_72h       LONG
+) TIMESTAMP(day)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(day, source_system);
+
+-- Post-load verification of backfill files, written by the `backfill_meter_usage*`
+-- binaries. Expected values come from re-reading the file (valid records, one per
+-- (ts, meter_id)); landed values from the `meter_usage` rows with those keys in
+-- [range_from, range_to]. Checksums are order-independent sums of row hashes of
+-- (ts, meter_id, kwh). status: ok | missing | duplicated | mismatch.
+CREATE TABLE IF NOT EXISTS backfill_runs (
+    ts                  TIMESTAMP,
+    file                STRING,
+    mode                SYMBOL,
+    range_from          TIMESTAMP,
+    range_to            TIMESTAMP,
+    expected_rows       LONG,
+    landed_rows         LONG,
+    expected_checksum   LONG,
+    landed_checksum     LONG,
+    status              SYMBOL
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Daily clock offset per source_system, written by the `clock_drift` job.
+-- offset_secs is min(ingested_at - ts) over the day's arrivals; drift_secs its
+-- distance from the median of the previous days (baseline_offset_secs), and
+-- drift_rate_secs_per_day the trend. Deduplicated on (day, source_system).
+CREATE TABLE IF NOT EXISTS source_clock_drift (
+    day                     TIMESTAMP,
+    source_system           SYMBOL,
+    samples                 LONG,
+    offset_secs             DOUBLE,
+    avg_lateness_secs       DOUBLE,
+    future_records          LONG,
+    baseline_offset_secs    DOUBLE,
+    drift_secs              DOUBLE,
+    drift_rate_secs_per_day DOUBLE,
+    alert                   BOOLEAN
+) TIMESTAMP(day)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(day, source_system);
+
+-- Suspected unrecorded CT/PT multiplier changes, written by the
+-- `multiplier_changes` job for the metering team. before_kwh / after_kwh are the
+-- median daily kWh (scaled by meter_scale_map) of the windows around change_day;
+-- inferred_ratio is the integer ratio (or its inverse for a drop) usage from
+-- change_day on is off by. Deduplicated on (change_day, meter_id).
+CREATE TABLE IF NOT EXISTS meter_multiplier_candidates (
+    change_day      TIMESTAMP,
+    meter_id        SYMBOL,
+    before_kwh      DOUBLE,
+    after_kwh       DOUBLE,
+    observed_ratio  DOUBLE,
+    inferred_ratio  DOUBLE,
+    detected_at     TIMESTAMP
+) TIMESTAMP(change_day)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(change_day, meter_id);
+
+-- Bulk files ingested from S3 by `ingest_s3`, one row per object. An object is
+-- recorded once all its records were written or rejected, and never read again.
+CREATE TABLE IF NOT EXISTS s3_ingested_objects (
+    ts              TIMESTAMP,
+    bucket          SYMBOL,
+    object_key      STRING,
+    etag            STRING,
+    size            LONG,
+    records         LONG,
+    parse_errors    LONG
+) TIMESTAMP(ts)
+PARTITION BY YEAR;
+
+-- Threshold breaches on live records, written by the `[stream_alerts]` transform.
+-- table_name is the evaluated table and alert_id its meter_id / plant_id.
+CREATE TABLE IF NOT EXISTS stream_alerts (
+    ts          TIMESTAMP,
+    rule        SYMBOL,
+    table_name  SYMBOL,
+    alert_id    SYMBOL,
+    field       SYMBOL,
+    op          SYMBOL,
+    value       DOUBLE,
+    threshold   DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- One row per run of the scheduled jobs (clock_drift, rollups, ...), written by
+-- the job binaries. `ts` is when the run started; status: ok | failed.
+CREATE TABLE IF NOT EXISTS job_runs (
+    ts              TIMESTAMP,
+    job             SYMBOL,
+    duration_secs   DOUBLE,
+    status          SYMBOL,
+    error           STRING
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Replication progress, kept on the replication target by `replicate_tables`:
+-- every row of `table_name` up to `watermark` was written to the target.
+-- Only advances once all rows of the windows before it were acknowledged.
+CREATE TABLE IF NOT EXISTS replication_watermarks (
+    ts          TIMESTAMP,
+    table_name  SYMBOL,
+    watermark   TIMESTAMP
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Daily meter completeness per feeder, written by the `triggers` job: the
+-- share of the 15-minute intervals for which meters were mapped to the feeder
+-- that have a `meter_usage` row. Deduplicated on (day, feeder_id); updated as data arrives.
+CREATE TABLE IF NOT EXISTS feeder_completeness (
+    day                 TIMESTAMP,
+    feeder_id           SYMBOL,
+    mapped_meters       LONG,
+    expected_intervals  LONG,
+    reported_intervals  LONG,
+    completeness        DOUBLE
+) TIMESTAMP(day)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(day, feeder_id);
+
+-- Data-arrival triggers that fired, one row per trigger and day, written by
+-- the `triggers` job after the day's recompute succeeded. A day fires once;
+-- min_completeness is the lowest feeder completeness when it fired.
+CREATE TABLE IF NOT EXISTS job_triggers (
+    fired_at            TIMESTAMP,
+    trigger_name        SYMBOL,
+    day                 TIMESTAMP,
+    min_completeness    DOUBLE,
+    steps               INT
+) TIMESTAMP(fired_at)
+PARTITION BY MONTH;
+
+-- Operational events for Grafana annotations (deploys, backfills, maintenance,
+-- pipeline pauses), written by the backfill binaries and the `annotate` tool.
+-- A region when time_end is set; tags are comma-separated, the kind first.
+CREATE TABLE IF NOT EXISTS ops_annotations (
+    ts          TIMESTAMP,
+    time_end    TIMESTAMP,
+    kind        SYMBOL,
+    text        STRING,
+    tags        STRING,
+    source      SYMBOL
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
@@ -0,0 +1,85 @@
+-- Demand response tables for the electric utility QuestDB project
+
+-- DR event definitions, one row per nominated meter. `ts` is the event start.
+-- Maintained via the ingestion-service `/reference/dr_events` endpoint.
+CREATE TABLE IF NOT EXISTS dr_events (
+    ts              TIMESTAMP,
+    event_id        SYMBOL INDEX,
+    program         SYMBOL,
+    meter_id        SYMBOL,
+    end_ts          TIMESTAMP
+) TIMESTAMP(ts)
+PARTITION BY YEAR;
+
+-- Per-meter event performance, written by the `dr_performance` job.
+-- Deduplicated on (ts, event_id, meter_id) so events can be re-evaluated.
+CREATE TABLE IF NOT EXISTS dr_event_performance (
+    ts                  TIMESTAMP,
+    event_id            SYMBOL,
+    program             SYMBOL,
+    meter_id            SYMBOL,
+    actual_kwh          DOUBLE,
+    baseline_kwh        DOUBLE,
+    reduction_kwh       DOUBLE,
+    baseline_days_used  INT
+) TIMESTAMP(ts)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(ts, event_id, meter_id);
+
+-- Peak alerts, written by the `peak_watch` job when system or feeder demand
+-- tracks toward (`tracking`) or exceeds (`new_peak`) the monthly or annual peak
+-- of the days before. `ts` is when the alert was raised; each
+-- (day, scope, scope_id, level, status) is raised at most once.
+CREATE TABLE IF NOT EXISTS peak_alerts (
+    ts              TIMESTAMP,
+    day             TIMESTAMP,
+    scope           SYMBOL,
+    scope_id        SYMBOL,
+    level           SYMBOL,
+    status          SYMBOL,
+    max_kw          DOUBLE,
+    recent_kw       DOUBLE,
+    projected_kw    DOUBLE,
+    peak_kw         DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Runs of consecutive intervals in which an account's demand exceeded its
+-- `contract_demand`, written by the `contract_demand` job. `ts` is the start
+-- of the run; re-evaluation replaces a run, e.g. once it grew longer.
+CREATE TABLE IF NOT EXISTS contract_demand_exceedances (
+    ts              TIMESTAMP,
+    customer_id     SYMBOL,
+    end_ts          TIMESTAMP,
+    duration_mins   LONG,
+    intervals       LONG,
+    demand_unit     SYMBOL,
+    contract_demand DOUBLE,
+    max_demand      DOUBLE,
+    max_excess      DOUBLE,
+    max_excess_pct  DOUBLE,
+    excess_energy   DOUBLE,
+    evaluated_at    TIMESTAMP
+) TIMESTAMP(ts)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(ts, customer_id);
+
+-- Monthly contract demand exceedance per account, month to date.
+CREATE TABLE IF NOT EXISTS contract_demand_monthly (
+    month               TIMESTAMP,
+    customer_id         SYMBOL,
+    demand_unit         SYMBOL,
+    contract_demand     DOUBLE,
+    max_demand          DOUBLE,
+    max_excess          DOUBLE,
+    exceeded_intervals  LONG,
+    exceeded_mins       LONG,
+    exceedances         LONG,
+    excess_energy       DOUBLE,
+    evaluated_at        TIMESTAMP
+) TIMESTAMP(month)
+PARTITION BY YEAR
+WAL
+DEDUP UPSERT KEYS(month, customer_id);
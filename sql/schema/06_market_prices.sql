@@ -0,0 +1,11 @@
+-- Market price tables for the electric utility QuestDB project
+
+-- Nodal (LMP) prices per pricing node and market. A price applies from `ts`
+-- until the next one for the same node/market; cost queries `ASOF JOIN` to it.
+CREATE TABLE IF NOT EXISTS nodal_price (
+    ts              TIMESTAMP,
+    node_id         SYMBOL,
+    market          SYMBOL,     -- e.g. DA | RT
+    price_per_mwh   DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY DAY;
@@ -0,0 +1,64 @@
+-- Settlement snapshots for the electric utility QuestDB project
+
+-- Freezes of closed billing periods, written by the `settlement_freeze` job
+-- after the snapshot rows of a table were inserted. A period counts as
+-- settled for a table only once it has a row here; snapshot rows of an
+-- interrupted freeze carry a `frozen_at` that has none.
+CREATE TABLE IF NOT EXISTS settlement_periods (
+    frozen_at       TIMESTAMP,
+    table_name      SYMBOL,
+    period_start    TIMESTAMP,
+    period_end      TIMESTAMP,
+    rows            LONG
+) TIMESTAMP(frozen_at)
+PARTITION BY YEAR;
+
+-- Daily meter totals as used for settlement, snapshotted from `meter_usage`.
+-- Append-only: corrections to `meter_usage` after the freeze don't change them.
+CREATE TABLE IF NOT EXISTS settled_meter_usage_1d (
+    ts              TIMESTAMP,
+    meter_id        SYMBOL,
+    kwh             DOUBLE,
+    kvarh           DOUBLE,
+    max_kva_demand  DOUBLE,
+    samples         LONG,
+    period_start    TIMESTAMP,
+    frozen_at       TIMESTAMP
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Feeder energy balance as used for settlement, snapshotted from `feeder_energy_balance`.
+CREATE TABLE IF NOT EXISTS settled_feeder_energy_balance (
+    ts                  TIMESTAMP,
+    feeder_id           SYMBOL,
+    feeder_kwh_gen      DOUBLE,
+    feeder_kwh_demand   DOUBLE,
+    loss_kwh            DOUBLE,
+    loss_pct            DOUBLE,
+    meter_coverage_pct  DOUBLE,
+    complete            BOOLEAN,
+    period_start        TIMESTAMP,
+    frozen_at           TIMESTAMP
+) TIMESTAMP(ts)
+PARTITION BY MONTH;
+
+-- Billing-cycle calendar: the scheduled read dates of each cycle. A billing
+-- period runs from one read date of a cycle to the next. Rows with a
+-- `route_id` give a route its own schedule within the cycle; routes without
+-- any are read on the cycle's dates (rows without a `route_id`).
+CREATE TABLE IF NOT EXISTS billing_cycle_reads (
+    read_date       TIMESTAMP,
+    cycle_id        SYMBOL,
+    route_id        SYMBOL
+) TIMESTAMP(read_date)
+PARTITION BY YEAR;
+
+-- Assignment of premises to billing cycles and meter-reading routes,
+-- effective-dated like `meters`.
+CREATE TABLE IF NOT EXISTS premise_billing_cycles (
+    effective_from  TIMESTAMP,
+    premise_id      SYMBOL INDEX,
+    cycle_id        SYMBOL,
+    route_id        SYMBOL
+) TIMESTAMP(effective_from)
+PARTITION BY YEAR;
@@ -0,0 +1,39 @@
+-- Load forecasting tables for the electric utility QuestDB project
+
+-- Feeder load forecasts, written by the forecasting models: the energy
+-- `model` expected on `feeder_id` in the interval starting at `ts`, as issued
+-- at `issued_at`. Intervals line up with `feeder_energy_balance`. A model
+-- issuing several forecasts for an interval (e.g. day-ahead and hour-ahead)
+-- keeps one row per issue time.
+CREATE TABLE IF NOT EXISTS load_forecast (
+    ts              TIMESTAMP,
+    feeder_id       SYMBOL,
+    model           SYMBOL,
+    issued_at       TIMESTAMP,
+    kwh             DOUBLE
+) TIMESTAMP(ts)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(ts, feeder_id, model, issued_at);
+
+-- Forecast accuracy per day, feeder, model and horizon (whole hours between
+-- `issued_at` and the interval), written by the `forecast_accuracy` job from
+-- the complete intervals of `feeder_energy_balance`. mape_pct leaves out
+-- intervals without demand; bias_kwh is the mean and bias_pct the total of
+-- forecast minus actual (positive: over-forecast). Deduplicated on
+-- (day, feeder_id, model, horizon_hours).
+CREATE TABLE IF NOT EXISTS forecast_accuracy (
+    day             TIMESTAMP,
+    feeder_id       SYMBOL,
+    model           SYMBOL,
+    horizon_hours   INT,
+    samples         LONG,
+    forecast_kwh    DOUBLE,
+    actual_kwh      DOUBLE,
+    mape_pct        DOUBLE,
+    bias_kwh        DOUBLE,
+    bias_pct        DOUBLE
+) TIMESTAMP(day)
+PARTITION BY MONTH
+WAL
+DEDUP UPSERT KEYS(day, feeder_id, model, horizon_hours);
@@ -43,7 +43,11 @@ def aggregated_segment_load_sql(
     time_range: TimeRange,
     sample_by: str = "1h",
 ) -> str:
-    """SQL to aggregate kWh by customer segment over time."""
+    """SQL to aggregate kWh by customer segment over time.
+
+    `meters`/`customers` are effective-dated: each usage row is attributed to the
+    versions in effect at its timestamp (ASOF JOIN), excluding soft-deleted ones.
+    """
 
     segments_list = ", ".join(f"'{s}'" for s in segments)
 
@@ -53,14 +57,16 @@ SELECT
     c.segment,
     SUM(mu.kwh * COALESCE(msm.kwh_multiplier, 1.0)) AS total_kwh
 FROM meter_usage mu
-JOIN meters m ON mu.meter_id = m.meter_id
-JOIN customers c ON m.customer_id = c.customer_id
+ASOF JOIN meters m ON (meter_id)
+ASOF JOIN customers c ON (m.customer_id = c.customer_id)
 LEFT JOIN meter_scale_map msm
   ON msm.meter_id = mu.meter_id
  AND msm.from_ts <= mu.ts
  AND msm.to_ts   >  mu.ts
 WHERE mu.ts >= '{time_range.start}'
   AND mu.ts <  '{time_range.end}'
+  AND m.deleted = false
+  AND c.deleted = false
   AND c.segment IN ({segments_list})
 SAMPLE BY {sample_by} ALIGN TO CALENDAR
 GROUP BY segment, ts
@@ -25,3 +25,12 @@ def test_aggregated_segment_load_sql_includes_sample_by_and_segments() -> None:
     assert "SAMPLE BY 1h" in sql
     assert "c.segment IN ('res', 'c&i')" in sql
     assert "GROUP BY segment, ts" in sql
+
+
+def test_aggregated_segment_load_sql_uses_effective_dated_reference_tables() -> None:
+    tr = TimeRange(start="2024-01-01T00:00:00Z", end="2024-01-02T00:00:00Z")
+    sql = aggregated_segment_load_sql(segments=["res"], time_range=tr)
+
+    assert "ASOF JOIN meters m ON (meter_id)" in sql
+    assert "m.deleted = false" in sql
+    assert "c.deleted = false" in sql